@@ -2,8 +2,10 @@ use bytes::Bytes;
 use criterion::{criterion_group, criterion_main, Criterion};
 use http::{HeaderName, HeaderValue, StatusCode};
 use nanoid::nanoid;
-use pingap::config::LocationConf;
+use pingap::cache::CacheObject;
+use pingap::config::{LocationConf, PluginConf, PluginStep};
 use pingap::http_extra::{convert_headers, HttpResponse};
+use pingap::plugin::{get_builtin_proxy_plugins, parse_plugins, Plugin};
 use pingap::proxy::{Location, Parser};
 use pingap::state::{CompressionStat, State};
 use pingap::util::{self, get_super_ts};
@@ -148,6 +150,38 @@ fn bench_location_filter(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_location_route(c: &mut Criterion) {
+    let upstream_name = "charts";
+    let mut locations = vec![];
+    for i in 0..20 {
+        let path = match i % 3 {
+            0 => format!("/api/v{i}"),
+            1 => format!("~^/rest/v{i}/"),
+            _ => format!("=/static/v{i}"),
+        };
+        locations.push(
+            Location::new(
+                &format!("lo{i}"),
+                &LocationConf {
+                    upstream: Some(upstream_name.to_string()),
+                    host: Some(format!("host{}.com", i % 4)),
+                    path: Some(path),
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        );
+    }
+
+    c.bench_function("location route", |b| {
+        b.iter(|| {
+            let _ = locations
+                .iter()
+                .find(|lo| lo.matched("host2.com", "/rest/v19/users/me"));
+        })
+    });
+}
+
 fn bench_location_rewrite_path(c: &mut Criterion) {
     let upstream_name = "charts";
 
@@ -281,15 +315,86 @@ fn bench_logger_format(c: &mut Criterion) {
     });
 }
 
+fn bench_cache_object(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cache object");
+    let obj = CacheObject {
+        meta: (vec![1; 512], vec![2; 256]),
+        body: vec![3; 32 * 1024],
+    };
+    let buf: Vec<u8> = obj.clone().into();
+
+    group.bench_function("serialize", |b| {
+        b.iter(|| {
+            let _: Vec<u8> = obj.clone().into();
+        })
+    });
+    group.bench_function("deserialize", |b| {
+        b.iter(|| {
+            let _ = CacheObject::from(buf.clone());
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_plugin_dispatch(c: &mut Criterion) {
+    let (s, r) = crossbeam_channel::bounded(0);
+    get_logger_session(s);
+    let mut session = r.recv().unwrap().unwrap();
+    let mut confs = get_builtin_proxy_plugins();
+    confs.push((
+        "responseHeaders".to_string(),
+        toml::from_str::<PluginConf>(
+            r###"
+category = "response_headers"
+step = "response"
+add_headers = ["X-Service:pingap"]
+"###,
+        )
+        .unwrap(),
+    ));
+    let names: Vec<String> =
+        confs.iter().map(|(name, _)| name.clone()).collect();
+    let plugins = parse_plugins(confs).unwrap();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    c.bench_function("plugin dispatch", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let mut ctx = State::default();
+                for step in [
+                    PluginStep::EarlyRequest,
+                    PluginStep::Request,
+                    PluginStep::ProxyUpstream,
+                ] {
+                    for name in names.iter() {
+                        if let Some(plugin) = plugins.get(name) {
+                            let _ = plugin
+                                .handle_request(step, &mut session, &mut ctx)
+                                .await;
+                        }
+                    }
+                }
+            })
+        })
+    });
+}
+
 criterion_group!(
     benches,
     bench_insert_bytes_header,
     bench_insert_header_name,
     bench_get_response_header,
     bench_location_filter,
+    bench_location_route,
     bench_location_rewrite_path,
     bench_get_super_ts,
     bench_logger_format,
     bench_map,
+    bench_cache_object,
+    bench_plugin_dispatch,
 );
 criterion_main!(benches);
@@ -0,0 +1,282 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Load test for pingap, it spins up a mock upstream and a pingap process
+//! for each scenario, then measures the rps and latency of the proxy.
+//!
+//! cargo build --release
+//! cargo run --release --example load_test -- --duration=10s --concurrency=50
+
+use clap::Parser;
+use std::net::SocketAddr;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// The path of pingap binary
+    #[arg(long, default_value = "target/release/pingap")]
+    bin: String,
+    /// The duration of each scenario
+    #[arg(long, default_value = "10s")]
+    duration: humantime::Duration,
+    /// The count of concurrent clients
+    #[arg(long, default_value_t = 50)]
+    concurrency: usize,
+    /// The scenarios to run, e.g. proxy,cache,compression
+    #[arg(long, default_value = "proxy,cache,compression")]
+    scenarios: String,
+    /// The body size of mock upstream response
+    #[arg(long, default_value_t = 4096)]
+    body_size: usize,
+    /// The listen port of pingap
+    #[arg(long, default_value_t = 6180)]
+    port: u16,
+}
+
+struct Report {
+    scenario: String,
+    requests: usize,
+    failures: usize,
+    elapsed: Duration,
+    latencies: Vec<u64>,
+}
+
+impl Report {
+    fn percentile(&self, p: f64) -> u64 {
+        if self.latencies.is_empty() {
+            return 0;
+        }
+        let index = ((self.latencies.len() as f64) * p) as usize;
+        self.latencies[index.min(self.latencies.len() - 1)]
+    }
+    fn print(&self) {
+        let rps = self.requests as f64 / self.elapsed.as_secs_f64();
+        println!(
+            "{:<12} requests:{:<8} failures:{:<6} rps:{:<10.1} p50:{}us p90:{}us p99:{}us max:{}us",
+            self.scenario,
+            self.requests,
+            self.failures,
+            rps,
+            self.percentile(0.5),
+            self.percentile(0.9),
+            self.percentile(0.99),
+            self.latencies.last().cloned().unwrap_or_default(),
+        );
+    }
+}
+
+/// Mock upstream which responds the same body for every request.
+async fn run_mock_upstream(listener: TcpListener, body_size: usize) {
+    let body = "a".repeat(body_size);
+    let resp = Arc::new(format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nCache-Control: public, max-age=60\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    ));
+    loop {
+        let Ok((mut stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let resp = resp.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0; 8192];
+            let mut data = vec![];
+            loop {
+                let Ok(size) = stream.read(&mut buf).await else {
+                    return;
+                };
+                if size == 0 {
+                    return;
+                }
+                data.extend_from_slice(&buf[..size]);
+                // only support request without body
+                while let Some(index) =
+                    data.windows(4).position(|item| item == b"\r\n\r\n")
+                {
+                    data.drain(..index + 4);
+                    if stream.write_all(resp.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn get_scenario_config(
+    scenario: &str,
+    upstream: &SocketAddr,
+    port: u16,
+) -> Option<String> {
+    let plugins = match scenario {
+        "proxy" => "[]",
+        "cache" => r#"["cache"]"#,
+        "compression" => r#"["pingap:compression"]"#,
+        _ => return None,
+    };
+    Some(format!(
+        r#"
+[basic]
+log_level = "error"
+pid_file = "/tmp/pingap_load_test.pid"
+upgrade_sock = "/tmp/pingap_load_test.sock"
+
+[upstreams.mock]
+addrs = ["{upstream}"]
+
+[locations.mock]
+upstream = "mock"
+plugins = {plugins}
+
+[servers.load_test]
+addr = "127.0.0.1:{port}"
+locations = ["mock"]
+
+[plugins.cache]
+category = "cache"
+"#
+    ))
+}
+
+async fn wait_for_listen(port: u16) -> bool {
+    for _ in 0..50 {
+        if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+fn start_pingap(bin: &str, conf: &str) -> std::io::Result<Child> {
+    Command::new(bin)
+        .arg("-c")
+        .arg(conf)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+}
+
+async fn run_scenario(
+    scenario: &str,
+    url: &str,
+    duration: Duration,
+    concurrency: usize,
+) -> Report {
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(concurrency)
+        .build()
+        .unwrap();
+    let results = Arc::new(Mutex::new((0, vec![])));
+    let start = Instant::now();
+    let mut handles = vec![];
+    for _ in 0..concurrency {
+        let client = client.clone();
+        let url = url.to_string();
+        let results = results.clone();
+        handles.push(tokio::spawn(async move {
+            let mut failures = 0;
+            let mut latencies = vec![];
+            while start.elapsed() < duration {
+                let now = Instant::now();
+                let ok = match client
+                    .get(&url)
+                    .header("Accept-Encoding", "gzip, br")
+                    .send()
+                    .await
+                {
+                    Ok(resp) => {
+                        resp.status().is_success() && resp.bytes().await.is_ok()
+                    },
+                    Err(_) => false,
+                };
+                if ok {
+                    latencies.push(now.elapsed().as_micros() as u64);
+                } else {
+                    failures += 1;
+                }
+            }
+            let mut results = results.lock().await;
+            results.0 += failures;
+            results.1.extend(latencies);
+        }));
+    }
+    for handle in handles {
+        let _ = handle.await;
+    }
+    let elapsed = start.elapsed();
+    let mut results = results.lock().await;
+    let mut latencies = std::mem::take(&mut results.1);
+    latencies.sort_unstable();
+    Report {
+        scenario: scenario.to_string(),
+        requests: latencies.len() + results.0,
+        failures: results.0,
+        elapsed,
+        latencies,
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = listener.local_addr().unwrap();
+        tokio::spawn(run_mock_upstream(listener, args.body_size));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let url = format!("http://127.0.0.1:{}/load-test", args.port);
+        for scenario in args.scenarios.split(',') {
+            let scenario = scenario.trim();
+            let Some(conf) =
+                get_scenario_config(scenario, &upstream, args.port)
+            else {
+                println!("{scenario} is not supported");
+                continue;
+            };
+            let file = dir.path().join(format!("{scenario}.toml"));
+            std::fs::write(&file, conf).unwrap();
+            let mut child =
+                match start_pingap(&args.bin, &file.to_string_lossy()) {
+                    Ok(child) => child,
+                    Err(e) => {
+                        println!("start pingap({}) fail, {e}", args.bin);
+                        return;
+                    },
+                };
+            if wait_for_listen(args.port).await {
+                let report = run_scenario(
+                    scenario,
+                    &url,
+                    args.duration.into(),
+                    args.concurrency,
+                )
+                .await;
+                report.print();
+            } else {
+                println!("{scenario}: pingap is not listening");
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    });
+}
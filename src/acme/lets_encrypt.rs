@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use super::{get_certificate_info, Certificate, Error, Result};
+use crate::cluster;
 use crate::http_extra::HttpResponse;
 use crate::service::{CommonServiceTask, ServiceTask};
 use crate::state::{restart_now, State};
@@ -80,6 +81,18 @@ impl ServiceTask for LetsEncryptService {
                 true
             };
         if should_renew_now {
+            // the certificate may be renewed by other node of cluster
+            if load_cluster_certificate(&self.certificate_file, domains).await {
+                info!(domains = domains.join(","), "use cert of cluster");
+                if let Err(e) = restart_now() {
+                    error!(
+                        error = e.to_string(),
+                        domains = domains.join(","),
+                        "restart fail"
+                    );
+                }
+                return None;
+            }
             info!(domains = domains.join(","), "renew cert from let's encrypt");
             match new_lets_encrypt(&self.certificate_file, domains).await {
                 Ok(()) => {
@@ -106,6 +119,44 @@ impl ServiceTask for LetsEncryptService {
     }
 }
 
+/// Loads the valid cert of the same domains from cluster,
+/// and writes it to the certificate file.
+async fn load_cluster_certificate(
+    certificate_file: &PathBuf,
+    domains: &[String],
+) -> bool {
+    if !cluster::is_cluster_enabled() {
+        return false;
+    }
+    let buf = match cluster::load_certificate(&domains.join(",")).await {
+        Ok(Some(buf)) => buf,
+        Ok(None) => return false,
+        Err(e) => {
+            error!(
+                error = e.to_string(),
+                domains = domains.join(","),
+                "load certificate from cluster fail"
+            );
+            return false;
+        },
+    };
+    let Ok(cert) = serde_json::from_slice::<Certificate>(&buf) else {
+        return false;
+    };
+    if !cert.valid() || domains.join(",") != cert.domains.join(",") {
+        return false;
+    }
+    if let Err(e) = fs::write(certificate_file, &buf).await {
+        error!(
+            error = e.to_string(),
+            domains = domains.join(","),
+            "write certificate of cluster fail"
+        );
+        return false;
+    }
+    true
+}
+
 /// Get the cert from file and convert it to cert struct.
 pub fn get_lets_encrypt_cert(path: &PathBuf) -> Result<Certificate> {
     if !path.exists() {
@@ -119,6 +170,12 @@ pub fn get_lets_encrypt_cert(path: &PathBuf) -> Result<Certificate> {
     Ok(cert)
 }
 
+/// Adds the token of http-01 challenge.
+pub async fn add_challenge_token(path: &str, value: &str) {
+    let mut map = get_lets_encrypt().lock().await;
+    map.insert(path.to_string(), value.to_string());
+}
+
 /// The proxy plugin for lets encrypt http-01.
 pub async fn handle_lets_encrypt(
     session: &mut Session,
@@ -220,8 +277,12 @@ async fn new_lets_encrypt(
             format!("/.well-known/acme-challenge/{}", challenge.token);
         info!(well_known_path, "let's encrypt well known path",);
 
-        let mut map = get_lets_encrypt().lock().await;
-        map.insert(well_known_path, key_auth.as_str().to_string());
+        add_challenge_token(&well_known_path, key_auth.as_str()).await;
+        // the challenge request may be sent to any node of cluster
+        cluster::publish(cluster::ClusterEvent::AcmeChallenge {
+            path: well_known_path,
+            value: key_auth.as_str().to_string(),
+        });
 
         challenges.push((identifier, &challenge.url));
     }
@@ -327,6 +388,17 @@ async fn new_lets_encrypt(
         certificate_file = format!("{certificate_file:?}"),
         "write certificate success"
     );
+    if cluster::is_cluster_enabled() {
+        if let Err(e) =
+            cluster::save_certificate(&domains.join(","), &buf).await
+        {
+            error!(
+                error = e.to_string(),
+                domains = domains.join(","),
+                "save certificate to cluster fail"
+            );
+        }
+    }
     webhook::send(webhook::SendNotificationParams {
         level: webhook::NotificationLevel::Info,
         category: webhook::NotificationCategory::LetsEncrypt,
@@ -105,7 +105,8 @@ mod lets_encrypt;
 mod validity_checker;
 
 pub use lets_encrypt::{
-    get_lets_encrypt_cert, handle_lets_encrypt, new_lets_encrypt_service,
+    add_challenge_token, get_lets_encrypt_cert, handle_lets_encrypt,
+    new_lets_encrypt_service,
 };
 pub use validity_checker::new_tls_validity_service;
 
@@ -0,0 +1,352 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::util;
+use ahash::{AHashMap, AHashSet};
+use async_trait::async_trait;
+use http::header::HeaderName;
+use http::HeaderMap;
+use nanoid::nanoid;
+use once_cell::sync::Lazy;
+use pingora::http::ResponseHeader;
+use std::sync::Mutex;
+use std::time::Duration;
+
+static HTTP_HEADER_NAME_X_ACCEL_EXPIRES: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_static("x-accel-expires"));
+static HTTP_HEADER_NAME_SURROGATE_CONTROL: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_static("surrogate-control"));
+static HTTP_HEADER_NAME_CACHE_TAG: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_static("cache-tag"));
+static HTTP_HEADER_NAME_SURROGATE_KEY: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_static("surrogate-key"));
+/// The header of prefetch request sent by pingap itself.
+pub static HTTP_HEADER_NAME_X_PINGAP_PREFETCH: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_static("x-pingap-prefetch"));
+// the token of prefetch request, it's generated for each process
+static PREFETCH_TOKEN: Lazy<String> = Lazy::new(|| nanoid!(32));
+
+// the max count of cache keys for each tag
+const MAX_TAG_KEYS: usize = 10_000;
+// the max count of cache keys which have tags
+const MAX_TAGGED_KEYS: usize = 100_000;
+
+#[derive(Default)]
+struct CacheTags {
+    // the cache keys of tag
+    tags: AHashMap<String, AHashSet<String>>,
+    // the tags and expired time(seconds) of cache key
+    keys: AHashMap<String, (Vec<String>, u64)>,
+}
+
+impl CacheTags {
+    fn remove_key(&mut self, key: &str) {
+        let Some((tags, _)) = self.keys.remove(key) else {
+            return;
+        };
+        for tag in tags.iter() {
+            if let Some(keys) = self.tags.get_mut(tag) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.tags.remove(tag);
+                }
+            }
+        }
+    }
+    fn remove_expired(&mut self, now: u64) {
+        let expired_keys: Vec<String> = self
+            .keys
+            .iter()
+            .filter(|(_, (_, expired_at))| *expired_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired_keys.iter() {
+            self.remove_key(key);
+        }
+    }
+    fn add(&mut self, key: &str, tags: &[String], expired_at: u64, now: u64) {
+        // the tags of key may be changed after the cache is refreshed
+        self.remove_key(key);
+        if self.keys.len() >= MAX_TAGGED_KEYS {
+            self.remove_expired(now);
+            if self.keys.len() >= MAX_TAGGED_KEYS {
+                return;
+            }
+        }
+        let mut added = vec![];
+        for tag in tags {
+            let keys = self.tags.entry(tag.to_string()).or_default();
+            if keys.len() < MAX_TAG_KEYS {
+                keys.insert(key.to_string());
+                added.push(tag.to_string());
+            }
+        }
+        if !added.is_empty() {
+            self.keys.insert(key.to_string(), (added, expired_at));
+        }
+    }
+    fn take(&mut self, tag: &str) -> Vec<String> {
+        let Some(keys) = self.tags.remove(tag) else {
+            return vec![];
+        };
+        for key in keys.iter() {
+            self.remove_key(key);
+        }
+        keys.into_iter().collect()
+    }
+}
+
+// the index is cleaned when the cache is purged or expired
+static CACHE_TAGS: Lazy<Mutex<CacheTags>> =
+    Lazy::new(|| Mutex::new(CacheTags::default()));
+
+fn get_header_str<'a>(
+    headers: &'a HeaderMap,
+    name: &HeaderName,
+) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim())
+}
+
+/// Gets the cache ttl(seconds) set by the upstream response,
+/// `X-Accel-Expires` has higher priority than `Surrogate-Control`.
+/// `Some(0)` means the response should not be cached,
+/// and `None` means the ttl is decided by `Cache-Control`.
+pub fn get_upstream_cache_ttl(headers: &HeaderMap, now: u64) -> Option<u64> {
+    if let Some(value) =
+        get_header_str(headers, &HTTP_HEADER_NAME_X_ACCEL_EXPIRES)
+    {
+        // `@` prefix means the unix timestamp
+        let ttl = if let Some(timestamp) = value.strip_prefix('@') {
+            timestamp
+                .parse::<u64>()
+                .ok()
+                .map(|timestamp| timestamp.saturating_sub(now))
+        } else {
+            value.parse::<u64>().ok()
+        };
+        if ttl.is_some() {
+            return ttl;
+        }
+    }
+    let value = get_header_str(headers, &HTTP_HEADER_NAME_SURROGATE_CONTROL)?;
+    let mut ttl = None;
+    for item in value.split(',') {
+        let item = item.trim().to_lowercase();
+        if item == "no-store" {
+            return Some(0);
+        }
+        if let Some(max_age) = item.strip_prefix("max-age=") {
+            ttl = max_age.trim_matches('"').parse::<u64>().ok();
+        }
+    }
+    ttl
+}
+
+/// Gets the cache tags of upstream response from `Cache-Tag` and `Surrogate-Key`,
+/// the tags are separated by comma or space.
+pub fn get_upstream_cache_tags(headers: &HeaderMap) -> Vec<String> {
+    let mut tags = vec![];
+    for name in [
+        &*HTTP_HEADER_NAME_CACHE_TAG,
+        &*HTTP_HEADER_NAME_SURROGATE_KEY,
+    ] {
+        for value in headers.get_all(name) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for tag in value.split([',', ' ']) {
+                let tag = tag.trim();
+                if !tag.is_empty() && !tags.iter().any(|item| item == tag) {
+                    tags.push(tag.to_string());
+                }
+            }
+        }
+    }
+    tags
+}
+
+/// Removes the cache control headers of upstream,
+/// they should not be sent to the client.
+pub fn remove_upstream_cache_headers(resp: &mut ResponseHeader) {
+    for name in [
+        &*HTTP_HEADER_NAME_X_ACCEL_EXPIRES,
+        &*HTTP_HEADER_NAME_SURROGATE_CONTROL,
+        &*HTTP_HEADER_NAME_CACHE_TAG,
+        &*HTTP_HEADER_NAME_SURROGATE_KEY,
+    ] {
+        let _ = resp.remove_header(name);
+    }
+}
+
+/// Gets the token of prefetch request.
+pub fn get_prefetch_token() -> &'static str {
+    PREFETCH_TOKEN.as_str()
+}
+
+/// Returns `true` if the request is the prefetch request of pingap.
+pub fn is_prefetch_request(headers: &HeaderMap) -> bool {
+    get_header_str(headers, &HTTP_HEADER_NAME_X_PINGAP_PREFETCH)
+        .map(|value| value == get_prefetch_token())
+        .unwrap_or_default()
+}
+
+/// Associates the cache key with the tags, which can be purged by tag.
+/// The key is removed from tags after it's expired(seconds).
+pub fn add_cache_tags(key: &str, tags: &[String], expired_at: u64) {
+    if tags.is_empty() {
+        return;
+    }
+    if let Ok(mut cache_tags) = CACHE_TAGS.lock() {
+        cache_tags.add(key, tags, expired_at, util::now().as_secs());
+    }
+}
+
+/// Removes the cache key from the tags, it's called after
+/// the cache is purged.
+pub fn remove_cache_tag_key(key: &str) {
+    if let Ok(mut cache_tags) = CACHE_TAGS.lock() {
+        cache_tags.remove_key(key);
+    }
+}
+
+/// Removes the expired cache keys from the tags.
+fn remove_expired_cache_tags() {
+    if let Ok(mut cache_tags) = CACHE_TAGS.lock() {
+        cache_tags.remove_expired(util::now().as_secs());
+    }
+}
+
+struct CacheTagsCleanupTask;
+
+#[async_trait]
+impl ServiceTask for CacheTagsCleanupTask {
+    async fn run(&self) -> Option<bool> {
+        remove_expired_cache_tags();
+        None
+    }
+    fn description(&self) -> String {
+        "Remove expired keys of cache tags".to_string()
+    }
+}
+
+/// Creates the task which removes the expired keys of cache tags
+/// every minute, the evicted cache is removed after it's expired.
+pub fn new_cache_tags_cleanup_task() -> CommonServiceTask {
+    CommonServiceTask::new(
+        "Cache tags cleanup",
+        Duration::from_secs(60),
+        CacheTagsCleanupTask,
+    )
+}
+
+/// Takes the cache keys of the tag, the tag is removed from index.
+pub fn take_cache_tag_keys(tag: &str) -> Vec<String> {
+    let Ok(mut cache_tags) = CACHE_TAGS.lock() else {
+        return vec![];
+    };
+    cache_tags.take(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_cache_tags, get_upstream_cache_tags, get_upstream_cache_ttl,
+        remove_upstream_cache_headers, take_cache_tag_keys, CacheTags,
+    };
+    use crate::util;
+    use http::{HeaderMap, HeaderValue};
+    use pingora::http::ResponseHeader;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_get_upstream_cache_ttl() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, get_upstream_cache_ttl(&headers, 1000));
+
+        headers.insert(
+            "Surrogate-Control",
+            HeaderValue::from_static("content=\"ESI/1.0\", max-age=300"),
+        );
+        assert_eq!(Some(300), get_upstream_cache_ttl(&headers, 1000));
+
+        headers.insert("X-Accel-Expires", HeaderValue::from_static("60"));
+        assert_eq!(Some(60), get_upstream_cache_ttl(&headers, 1000));
+
+        headers.insert("X-Accel-Expires", HeaderValue::from_static("@1600"));
+        assert_eq!(Some(600), get_upstream_cache_ttl(&headers, 1000));
+
+        headers.insert("X-Accel-Expires", HeaderValue::from_static("0"));
+        assert_eq!(Some(0), get_upstream_cache_ttl(&headers, 1000));
+
+        // invalid value is ignored
+        headers.insert("X-Accel-Expires", HeaderValue::from_static("abc"));
+        assert_eq!(Some(300), get_upstream_cache_ttl(&headers, 1000));
+
+        headers
+            .insert("Surrogate-Control", HeaderValue::from_static("no-store"));
+        assert_eq!(Some(0), get_upstream_cache_ttl(&headers, 1000));
+    }
+
+    #[test]
+    fn test_upstream_cache_tags() {
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("Cache-Tag", "user-1,product").unwrap();
+        resp.insert_header("Surrogate-Key", "product list").unwrap();
+        resp.insert_header("X-Accel-Expires", "60").unwrap();
+        resp.insert_header("Content-Type", "text/html").unwrap();
+        let tags = get_upstream_cache_tags(&resp.headers);
+        assert_eq!(r#"["user-1", "product", "list"]"#, format!("{tags:?}"));
+
+        remove_upstream_cache_headers(&mut resp);
+        assert_eq!(1, resp.headers.len());
+
+        let expired_at = util::now().as_secs() + 60;
+        add_cache_tags("key1", &tags, expired_at);
+        add_cache_tags("key2", &["product".to_string()], expired_at);
+        add_cache_tags("key3", &["list".to_string()], expired_at);
+        let mut keys = take_cache_tag_keys("product");
+        keys.sort();
+        assert_eq!(r#"["key1", "key2"]"#, format!("{keys:?}"));
+        assert_eq!(true, take_cache_tag_keys("product").is_empty());
+        // the purged key is removed from other tags
+        assert_eq!(r#"["key3"]"#, format!("{:?}", take_cache_tag_keys("list")));
+    }
+
+    #[test]
+    fn test_cache_tags_cleanup() {
+        let mut cache_tags = CacheTags::default();
+        let tags = vec!["user-1".to_string(), "product".to_string()];
+        cache_tags.add("key1", &tags, 100, 10);
+        cache_tags.add("key2", &["product".to_string()], 200, 10);
+        assert_eq!(2, cache_tags.keys.len());
+        assert_eq!(2, cache_tags.tags.len());
+
+        // the tags of key are replaced
+        cache_tags.add("key1", &["list".to_string()], 100, 10);
+        assert_eq!(2, cache_tags.tags.len());
+        assert_eq!(false, cache_tags.tags.contains_key("user-1"));
+
+        cache_tags.remove_key("key2");
+        assert_eq!(1, cache_tags.keys.len());
+        assert_eq!(false, cache_tags.tags.contains_key("product"));
+
+        cache_tags.remove_expired(100);
+        assert_eq!(true, cache_tags.keys.is_empty());
+        assert_eq!(true, cache_tags.tags.is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use ahash::AHashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+// the max count of cache entries in the index
+const MAX_ENTRIES: usize = 100_000;
+
+/// The inspection info of cache entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CacheEntry {
+    // the hash of cache key
+    pub hash: String,
+    // the primary key of cache, it's the prefix with url
+    pub key: String,
+    // the body size of cache
+    pub size: usize,
+    // the hit count since the entry is stored
+    pub hits: u64,
+    // the fresh until timestamp(seconds)
+    pub fresh_until: u64,
+    // the remaining ttl(seconds), 0 means the entry is expired
+    pub ttl: u64,
+    // the variance key of cache
+    pub variance: Option<String>,
+    // the request of cache, it's used to prefetch the entry
+    pub request: Option<CacheRequest>,
+}
+
+/// The request info to fetch the cache entry again.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CacheRequest {
+    // the scheme of request, http or https
+    pub scheme: String,
+    // the host of request
+    pub host: String,
+    // the local address of server which handles the request
+    pub addr: String,
+    // the path and query of request
+    pub path: String,
+    // the request headers of cache key, the format is `name:value`
+    pub headers: Vec<String>,
+}
+
+impl CacheEntry {
+    fn with_ttl(mut self) -> Self {
+        self.ttl = self.fresh_until.saturating_sub(util::now().as_secs());
+        self
+    }
+}
+
+// the entries are only recorded in memory, they are lost after restart
+// unless the snapshot of cache is enabled
+static CACHE_ENTRIES: Lazy<Mutex<AHashMap<String, CacheEntry>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+/// Records the cacheable response of the key, the hit count is reset.
+pub fn add_cache_entry(
+    hash: &str,
+    key: &str,
+    fresh_until: u64,
+    variance: Option<String>,
+    request: Option<CacheRequest>,
+) {
+    let Ok(mut entries) = CACHE_ENTRIES.lock() else {
+        return;
+    };
+    if let Some(entry) = entries.get_mut(hash) {
+        // revalidated entry keeps its size and hits
+        entry.fresh_until = fresh_until;
+        entry.variance = variance;
+        if request.is_some() {
+            entry.request = request;
+        }
+        return;
+    }
+    if entries.len() >= MAX_ENTRIES {
+        return;
+    }
+    entries.insert(
+        hash.to_string(),
+        CacheEntry {
+            hash: hash.to_string(),
+            key: key.to_string(),
+            fresh_until,
+            variance,
+            request,
+            ..Default::default()
+        },
+    );
+}
+
+/// Sets the body size of cache entry after it's stored.
+pub fn set_cache_entry_size(hash: &str, size: usize) {
+    if let Ok(mut entries) = CACHE_ENTRIES.lock() {
+        if let Some(entry) = entries.get_mut(hash) {
+            entry.size = size;
+            entry.hits = 0;
+        }
+    }
+}
+
+/// Increases the hit count of cache entry.
+pub fn incr_cache_entry_hits(hash: &str) {
+    if let Ok(mut entries) = CACHE_ENTRIES.lock() {
+        if let Some(entry) = entries.get_mut(hash) {
+            entry.hits += 1;
+        }
+    }
+}
+
+/// Removes the cache entry from index.
+pub fn remove_cache_entry(hash: &str) {
+    if let Ok(mut entries) = CACHE_ENTRIES.lock() {
+        entries.remove(hash);
+    }
+}
+
+/// Gets the cache entry of index.
+pub fn get_cache_entry(hash: &str) -> Option<CacheEntry> {
+    CACHE_ENTRIES
+        .lock()
+        .ok()
+        .and_then(|entries| entries.get(hash).cloned())
+        .map(|entry| entry.with_ttl())
+}
+
+/// Gets all the fresh cache entries of index, they are saved to snapshot.
+pub fn get_fresh_cache_entries() -> Vec<CacheEntry> {
+    let Ok(entries) = CACHE_ENTRIES.lock() else {
+        return vec![];
+    };
+    let now = util::now().as_secs();
+    entries
+        .values()
+        .filter(|entry| entry.fresh_until > now)
+        .cloned()
+        .collect()
+}
+
+/// Gets the hot entries which are close to expiry, the hit count is
+/// not less than `min_hits` and the remaining ttl is less than `before`(seconds).
+pub fn get_prefetch_cache_entries(
+    min_hits: u64,
+    before: u64,
+) -> Vec<CacheEntry> {
+    let Ok(entries) = CACHE_ENTRIES.lock() else {
+        return vec![];
+    };
+    let now = util::now().as_secs();
+    entries
+        .values()
+        .filter(|entry| {
+            entry.request.is_some()
+                && entry.hits >= min_hits
+                && entry.fresh_until > now
+                && entry.fresh_until - now <= before
+        })
+        .cloned()
+        .collect()
+}
+
+/// Restores the cache entry from snapshot, the existing entry is kept.
+pub fn restore_cache_entry(entry: CacheEntry) {
+    let Ok(mut entries) = CACHE_ENTRIES.lock() else {
+        return;
+    };
+    if entries.len() >= MAX_ENTRIES || entries.contains_key(&entry.hash) {
+        return;
+    }
+    entries.insert(entry.hash.clone(), entry);
+}
+
+/// Lists the biggest(sort by `size`) or hottest(sort by `hits`) entries.
+pub fn list_cache_entries(sort: &str, limit: usize) -> Vec<CacheEntry> {
+    let Ok(entries) = CACHE_ENTRIES.lock() else {
+        return vec![];
+    };
+    let mut list: Vec<&CacheEntry> = entries.values().collect();
+    if sort == "size" {
+        list.sort_by(|a, b| b.size.cmp(&a.size));
+    } else {
+        list.sort_by(|a, b| b.hits.cmp(&a.hits));
+    }
+    list.into_iter()
+        .take(limit)
+        .map(|entry| entry.clone().with_ttl())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_cache_entry, get_cache_entry, get_prefetch_cache_entries,
+        incr_cache_entry_hits, list_cache_entries, remove_cache_entry,
+        set_cache_entry_size, CacheRequest,
+    };
+    use crate::util;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_cache_entry() {
+        let now = util::now().as_secs();
+        add_cache_entry("entry-a", "charts:/a", now + 60, None, None);
+        add_cache_entry("entry-b", "charts:/b", now + 60, None, None);
+        set_cache_entry_size("entry-a", 1024);
+        set_cache_entry_size("entry-b", 10);
+        incr_cache_entry_hits("entry-b");
+        incr_cache_entry_hits("entry-b");
+
+        let entry = get_cache_entry("entry-a").unwrap();
+        assert_eq!("charts:/a", entry.key);
+        assert_eq!(1024, entry.size);
+        assert_eq!(true, entry.ttl > 50);
+
+        // revalidated entry keeps the hits
+        add_cache_entry("entry-b", "charts:/b", now - 1, None, None);
+        let entry = get_cache_entry("entry-b").unwrap();
+        assert_eq!(2, entry.hits);
+        assert_eq!(0, entry.ttl);
+
+        let keys: Vec<String> = list_cache_entries("size", 100)
+            .into_iter()
+            .filter(|item| item.hash.starts_with("entry-"))
+            .map(|item| item.hash)
+            .collect();
+        assert_eq!(r#"["entry-a", "entry-b"]"#, format!("{keys:?}"));
+        let keys: Vec<String> = list_cache_entries("hits", 100)
+            .into_iter()
+            .filter(|item| item.hash.starts_with("entry-"))
+            .map(|item| item.hash)
+            .collect();
+        assert_eq!(r#"["entry-b", "entry-a"]"#, format!("{keys:?}"));
+
+        remove_cache_entry("entry-a");
+        assert_eq!(true, get_cache_entry("entry-a").is_none());
+    }
+
+    #[test]
+    fn test_prefetch_cache_entries() {
+        let now = util::now().as_secs();
+        let request = CacheRequest {
+            scheme: "http".to_string(),
+            host: "pingap.io".to_string(),
+            addr: "127.0.0.1:6188".to_string(),
+            path: "/prefetch".to_string(),
+            headers: vec![],
+        };
+        add_cache_entry(
+            "prefetch-a",
+            "/prefetch",
+            now + 5,
+            None,
+            Some(request.clone()),
+        );
+        add_cache_entry(
+            "prefetch-b",
+            "/prefetch",
+            now + 300,
+            None,
+            Some(request),
+        );
+        for _ in 0..3 {
+            incr_cache_entry_hits("prefetch-a");
+            incr_cache_entry_hits("prefetch-b");
+        }
+        let hashes: Vec<String> = get_prefetch_cache_entries(3, 10)
+            .into_iter()
+            .filter(|item| item.hash.starts_with("prefetch-"))
+            .map(|item| item.hash)
+            .collect();
+        assert_eq!(r#"["prefetch-a"]"#, format!("{hashes:?}"));
+        assert_eq!(
+            true,
+            get_prefetch_cache_entries(4, 10)
+                .iter()
+                .all(|item| !item.hash.starts_with("prefetch-"))
+        );
+    }
+}
@@ -12,15 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::http_cache::{CacheObject, HttpCacheStorage};
+use super::http_cache::{
+    get_wegith, BinaryMeta, CacheObject, CompleteHit, HttpCacheStorage,
+};
 use super::{Error, Result};
 use crate::util;
 use async_trait::async_trait;
+use bytes::Bytes;
+use pingora::cache::key::CacheKey;
+use pingora::cache::storage::{HandleHit, HitHandler};
+use pingora::cache::trace::SpanHandle;
+use pingora::cache::Storage;
+use std::any::Any;
+use std::io::SeekFrom;
 use std::path::Path;
 use tinyufo::TinyUfo;
 use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tracing::info;
 
+// the chunk size of reading body from file
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+// only the object less than this size will be cached in memory
+const MAX_MEMORY_OBJECT_SIZE: usize = 512 * 1024;
+
 pub struct FileCache {
     directory: String,
     cache: TinyUfo<String, CacheObject>,
@@ -41,12 +56,21 @@ pub fn new_file_cache(dir: &str) -> Result<FileCache> {
     })
 }
 
+impl FileCache {
+    /// Gets the cache object from tinyufo, the removed object is ignored.
+    fn get_memory_object(&self, key: &str) -> Option<CacheObject> {
+        self.cache
+            .get(&key.to_string())
+            .filter(|obj| !obj.meta.0.is_empty())
+    }
+}
+
 #[async_trait]
 impl HttpCacheStorage for FileCache {
     /// Get cache object from tinyufo,
     /// if not exists, then get from the file.
     async fn get(&self, key: &str) -> Option<CacheObject> {
-        if let Some(obj) = self.cache.get(&key.to_string()) {
+        if let Some(obj) = self.get_memory_object(key) {
             return Some(obj);
         }
         let file = Path::new(&self.directory).join(key);
@@ -59,14 +83,17 @@ impl HttpCacheStorage for FileCache {
             Some(CacheObject::from(buf))
         }
     }
-    /// Put cache object to tinyufo and file.
+    /// Put cache object to tinyufo and file,
+    /// the large object is only saved to file.
     async fn put(
         &self,
         key: String,
         data: CacheObject,
         weight: u16,
     ) -> Result<()> {
-        self.cache.put(key.clone(), data.clone(), weight);
+        if data.body.len() <= MAX_MEMORY_OBJECT_SIZE {
+            self.cache.put(key.clone(), data.clone(), weight);
+        }
         let buf: Vec<u8> = data.into();
         let file = Path::new(&self.directory).join(key);
         fs::write(file, buf)
@@ -74,14 +101,205 @@ impl HttpCacheStorage for FileCache {
             .map_err(|e| Error::Io { source: e })?;
         Ok(())
     }
-    /// Remove cache object from file, tinyufo doesn't support remove now.
+    /// Remove cache object from file, tinyufo doesn't support remove,
+    /// so the object in memory is replaced with an empty one(without meta).
     async fn remove(&self, key: &str) -> Result<Option<CacheObject>> {
-        // TODO remove from tinyufo
+        let obj = self.get_memory_object(key);
+        if obj.is_some() {
+            self.cache.put(key.to_string(), CacheObject::default(), 1);
+        }
         let file = Path::new(&self.directory).join(key);
         fs::remove_file(file)
             .await
             .map_err(|e| Error::Io { source: e })?;
-        Ok(None)
+        Ok(Some(obj.unwrap_or_default()))
+    }
+    /// The cache objects are saved to file.
+    fn persistent(&self) -> bool {
+        true
+    }
+    /// Lookup cache object from tinyufo,
+    /// if not exists, then only read the meta from file and stream the body.
+    async fn lookup(&self, key: &str) -> Option<(BinaryMeta, HitHandler)> {
+        if let Some(obj) = self.get_memory_object(key) {
+            return Some((obj.meta, Box::new(CompleteHit::new(obj.body))));
+        }
+        let file = Path::new(&self.directory).join(key);
+        let (meta, hit) = FileHit::open(&file).await.ok()?;
+        Some((meta, Box::new(hit)))
+    }
+    /// Update the meta of cache object, the body of file is copied
+    /// to a new file with the new meta, so it will not be loaded into memory.
+    async fn update_meta(&self, key: &str, meta: BinaryMeta) -> Result<bool> {
+        let file = Path::new(&self.directory).join(key);
+        let Ok((_, mut hit)) = FileHit::open(&file).await else {
+            return Ok(false);
+        };
+        if let Some(mut obj) = self.get_memory_object(key) {
+            obj.meta = meta.clone();
+            let weight = get_wegith(obj.body.len());
+            self.cache.put(key.to_string(), obj, weight);
+        }
+        let tmp_file = Path::new(&self.directory).join(format!("{key}.tmp"));
+        let mut f = fs::File::create(&tmp_file)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        let mut buf = Vec::with_capacity(8 + meta.0.len() + meta.1.len());
+        buf.extend((meta.0.len() as u32).to_be_bytes());
+        buf.extend((meta.1.len() as u32).to_be_bytes());
+        buf.extend(meta.0);
+        buf.extend(meta.1);
+        f.write_all(&buf)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        // the file is positioned at the start of body after open
+        tokio::io::copy(&mut hit.file, &mut f)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        f.flush().await.map_err(|e| Error::Io { source: e })?;
+        fs::rename(tmp_file, file)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        Ok(true)
+    }
+}
+
+/// Hit handler which reads the body from file chunk by chunk,
+/// so the large object will not be loaded into memory.
+pub struct FileHit {
+    file: fs::File,
+    body_offset: u64,
+    body_size: usize,
+    range_start: usize,
+    range_end: usize,
+    position: usize,
+    need_seek: bool,
+}
+
+impl FileHit {
+    /// Open the cache file, read the meta and position at the start of body.
+    async fn open(file: &Path) -> Result<(BinaryMeta, Self)> {
+        let mut f = fs::File::open(file)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        let file_size = f
+            .metadata()
+            .await
+            .map_err(|e| Error::Io { source: e })?
+            .len() as usize;
+        let mut size_buf = [0; 8];
+        f.read_exact(&mut size_buf)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        let meta0_size =
+            u32::from_be_bytes(size_buf[0..4].try_into().unwrap()) as usize;
+        let meta1_size =
+            u32::from_be_bytes(size_buf[4..8].try_into().unwrap()) as usize;
+        let body_offset = 8 + meta0_size + meta1_size;
+        if body_offset > file_size {
+            return Err(Error::Invalid {
+                message: format!(
+                    "cache file is invalid, meta size {} > file size {file_size}",
+                    meta0_size + meta1_size
+                ),
+            });
+        }
+        let mut meta0 = vec![0; meta0_size];
+        f.read_exact(&mut meta0)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        let mut meta1 = vec![0; meta1_size];
+        f.read_exact(&mut meta1)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        let body_size = file_size - body_offset;
+        Ok((
+            (meta0, meta1),
+            Self {
+                file: f,
+                body_offset: body_offset as u64,
+                body_size,
+                range_start: 0,
+                range_end: body_size,
+                position: 0,
+                need_seek: false,
+            },
+        ))
+    }
+    async fn read_chunk(&mut self) -> Result<Option<Bytes>> {
+        if self.need_seek {
+            self.file
+                .seek(SeekFrom::Start(
+                    self.body_offset + self.range_start as u64,
+                ))
+                .await
+                .map_err(|e| Error::Io { source: e })?;
+            self.position = self.range_start;
+            self.need_seek = false;
+        }
+        if self.position >= self.range_end {
+            return Ok(None);
+        }
+        let size =
+            std::cmp::min(READ_CHUNK_SIZE, self.range_end - self.position);
+        let mut buf = vec![0; size];
+        self.file
+            .read_exact(&mut buf)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        self.position += size;
+        Ok(Some(Bytes::from(buf)))
+    }
+    fn seek(&mut self, start: usize, end: Option<usize>) -> Result<()> {
+        if start >= self.body_size {
+            return Err(Error::Invalid {
+                message: format!(
+                    "seek start out of range {start} >= {}",
+                    self.body_size
+                ),
+            });
+        }
+        self.range_start = start;
+        self.range_end = if let Some(end) = end {
+            std::cmp::min(self.body_size, end)
+        } else {
+            self.body_size
+        };
+        self.need_seek = true;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HandleHit for FileHit {
+    async fn read_body(&mut self) -> pingora::Result<Option<Bytes>> {
+        let data = self.read_chunk().await?;
+        Ok(data)
+    }
+    async fn finish(
+        self: Box<Self>,
+        _storage: &'static (dyn Storage + Sync),
+        _key: &CacheKey,
+        _trace: &SpanHandle,
+    ) -> pingora::Result<()> {
+        Ok(())
+    }
+
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    fn seek(
+        &mut self,
+        start: usize,
+        end: Option<usize>,
+    ) -> pingora::Result<()> {
+        self.seek(start, end)?;
+        Ok(())
+    }
+
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
     }
 }
 
@@ -116,5 +334,72 @@ mod tests {
         cache.remove(&key).await.unwrap();
         let result = cache.get(&key).await;
         assert_eq!(true, result.is_none());
+
+        // the object in memory is removed too
+        cache.put(key.clone(), obj.clone(), 1).await.unwrap();
+        assert_eq!(true, cache.remove(&key).await.unwrap().is_some());
+        assert_eq!(true, cache.get(&key).await.is_none());
+        assert_eq!(true, cache.lookup(&key).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_cache_lookup() {
+        let dir = TempDir::new().unwrap();
+        let dir = dir.into_path().to_string_lossy().to_string();
+        let cache = new_file_cache(&dir).unwrap();
+        let key = "key".to_string();
+        let body: Vec<u8> = (0..200 * 1024).map(|i| (i % 255) as u8).collect();
+        let obj = CacheObject {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: body.clone(),
+        };
+        cache.put(key.clone(), obj.clone(), 1).await.unwrap();
+
+        // empty tinyufo, stream from file
+        let cache = new_file_cache(&dir).unwrap();
+        let (meta, mut hit) = cache.lookup(&key).await.unwrap();
+        assert_eq!(obj.meta, meta);
+        let mut data = vec![];
+        let mut count = 0;
+        while let Some(chunk) = hit.read_body().await.unwrap() {
+            count += 1;
+            data.extend(chunk);
+        }
+        assert_eq!(4, count);
+        assert_eq!(body, data);
+
+        hit.seek(10, Some(100)).unwrap();
+        let chunk = hit.read_body().await.unwrap().unwrap();
+        assert_eq!(&body[10..100], chunk.as_ref());
+        assert_eq!(true, hit.read_body().await.unwrap().is_none());
+
+        assert_eq!(true, hit.seek(body.len(), None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_cache_update_meta() {
+        let dir = TempDir::new().unwrap();
+        let dir = dir.into_path().to_string_lossy().to_string();
+        let cache = new_file_cache(&dir).unwrap();
+        let key = "key".to_string();
+        let meta = (b"Hi".to_vec(), b"Pingap".to_vec());
+        assert_eq!(false, cache.update_meta(&key, meta.clone()).await.unwrap());
+
+        let body: Vec<u8> = (0..200 * 1024).map(|i| (i % 255) as u8).collect();
+        let obj = CacheObject {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: body.clone(),
+        };
+        cache.put(key.clone(), obj, 1).await.unwrap();
+        assert_eq!(true, cache.update_meta(&key, meta.clone()).await.unwrap());
+        let result = cache.get(&key).await.unwrap();
+        assert_eq!(meta, result.meta);
+        assert_eq!(body, result.body);
+
+        // empty tinyufo, get from file
+        let cache = new_file_cache(&dir).unwrap();
+        let result = cache.get(&key).await.unwrap();
+        assert_eq!(meta, result.meta);
+        assert_eq!(body, result.body);
     }
 }
@@ -12,7 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::entry::{
+    get_cache_entry, incr_cache_entry_hits, remove_cache_entry,
+    set_cache_entry_size, CacheEntry,
+};
 use super::{Error, Result};
+use crate::util;
 use async_trait::async_trait;
 use bytes::BufMut;
 use bytes::Bytes;
@@ -27,7 +32,7 @@ use pingora::cache::{
 use std::any::Any;
 use std::sync::Arc;
 
-type BinaryMeta = (Vec<u8>, Vec<u8>);
+pub(crate) type BinaryMeta = (Vec<u8>, Vec<u8>);
 
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct CacheObject {
@@ -91,12 +96,90 @@ pub trait HttpCacheStorage: Sync + Send {
     async fn remove(&self, _key: &str) -> Result<Option<CacheObject>> {
         Ok(None)
     }
+    /// Returns `true` if the cache objects are persisted by the storage,
+    /// so only the index of entries is saved to snapshot.
+    fn persistent(&self) -> bool {
+        false
+    }
+    /// Lookup the cache object, return the meta and a hit handler for reading body.
+    /// The default implementation loads the whole object into memory,
+    /// storage backed by disk should stream the body instead.
+    async fn lookup(&self, key: &str) -> Option<(BinaryMeta, HitHandler)> {
+        let obj = self.get(key).await?;
+        // the removed object without meta
+        if obj.meta.0.is_empty() {
+            return None;
+        }
+        Some((obj.meta, Box::new(CompleteHit::new(obj.body))))
+    }
+    /// Update the meta of cache object and keep the body,
+    /// it's used when the upstream revalidates the object with 304.
+    /// Returns `false` if the object is not found.
+    async fn update_meta(&self, key: &str, meta: BinaryMeta) -> Result<bool> {
+        let Some(mut obj) =
+            self.get(key).await.filter(|obj| !obj.meta.0.is_empty())
+        else {
+            return Ok(false);
+        };
+        obj.meta = meta;
+        let size = obj.body.len();
+        self.put(key.to_string(), obj, get_wegith(size)).await?;
+        Ok(true)
+    }
 }
 
 pub struct HttpCache {
     pub(crate) cached: Arc<dyn HttpCacheStorage>,
 }
 
+impl HttpCache {
+    /// Purges the cache objects of the tag, returns the count of removed objects.
+    pub async fn purge_tag(&self, tag: &str) -> usize {
+        let mut count = 0;
+        for key in super::control::take_cache_tag_keys(tag) {
+            remove_cache_entry(&key);
+            if let Ok(Some(_)) = self.cached.remove(&key).await {
+                count += 1;
+            }
+        }
+        count
+    }
+    /// Removes the cache object of the hash key,
+    /// returns `true` if the object is removed.
+    pub async fn remove(&self, hash: &str) -> bool {
+        remove_cache_entry(hash);
+        super::control::remove_cache_tag_key(hash);
+        matches!(self.cached.remove(hash).await, Ok(Some(_)))
+    }
+    /// Gets the inspection info of the cache object, the meta is read from
+    /// storage and the hit count is from the index of cache entries.
+    pub async fn get_entry(&self, hash: &str, key: &str) -> Option<CacheEntry> {
+        let (meta, _) = self.cached.lookup(hash).await?;
+        let meta = CacheMeta::deserialize(&meta.0, &meta.1).ok()?;
+        let mut entry = get_cache_entry(hash).unwrap_or_else(|| CacheEntry {
+            hash: hash.to_string(),
+            key: key.to_string(),
+            ..Default::default()
+        });
+        if entry.size == 0 {
+            entry.size = meta
+                .headers()
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or_default();
+        }
+        entry.fresh_until = meta
+            .fresh_until()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        entry.ttl = entry.fresh_until.saturating_sub(util::now().as_secs());
+        entry.variance = meta.variance().map(hex::encode);
+        Some(entry)
+    }
+}
+
 pub struct CompleteHit {
     body: Vec<u8>,
     done: bool,
@@ -105,6 +188,15 @@ pub struct CompleteHit {
 }
 
 impl CompleteHit {
+    pub fn new(body: Vec<u8>) -> Self {
+        let size = body.len();
+        Self {
+            body,
+            done: false,
+            range_start: 0,
+            range_end: size,
+        }
+    }
     fn get(&mut self) -> Option<Bytes> {
         if self.done {
             None
@@ -189,13 +281,15 @@ impl HandleMiss for ObjectMissHandler {
 
     async fn finish(self: Box<Self>) -> pingora::Result<usize> {
         let size = self.body.len(); // FIXME: this just body size, also track meta size
+        let body = self.body.into();
+        set_cache_entry_size(&self.key, size);
         let _ = self
             .cache
             .put(
                 self.key.clone(),
                 CacheObject {
                     meta: self.meta,
-                    body: self.body.to_vec(),
+                    body,
                 },
                 get_wegith(size),
             )
@@ -205,7 +299,7 @@ impl HandleMiss for ObjectMissHandler {
     }
 }
 
-fn get_wegith(size: usize) -> u16 {
+pub(crate) fn get_wegith(size: usize) -> u16 {
     if size < 50 * 1024 {
         return 4;
     }
@@ -223,16 +317,10 @@ impl Storage for HttpCache {
         _trace: &SpanHandle,
     ) -> pingora::Result<Option<(CacheMeta, HitHandler)>> {
         let hash = key.combined();
-        if let Some(obj) = self.cached.get(&hash).await {
-            let meta = CacheMeta::deserialize(&obj.meta.0, &obj.meta.1)?;
-            let size = obj.body.len();
-            let hit_handler = CompleteHit {
-                body: obj.body,
-                done: false,
-                range_start: 0,
-                range_end: size,
-            };
-            Ok(Some((meta, Box::new(hit_handler))))
+        if let Some((meta, hit_handler)) = self.cached.lookup(&hash).await {
+            let meta = CacheMeta::deserialize(&meta.0, &meta.1)?;
+            incr_cache_entry_hits(&hash);
+            Ok(Some((meta, hit_handler)))
         } else {
             Ok(None)
         }
@@ -277,6 +365,8 @@ impl Storage for HttpCache {
         // This usually purges the primary key because, without a lookup,
         // the variance key is usually empty
         let hash = key.combined();
+        remove_cache_entry(&hash);
+        super::control::remove_cache_tag_key(&hash);
         let cache_removed = if let Ok(result) = self.cached.remove(&hash).await
         {
             result.is_some()
@@ -293,10 +383,8 @@ impl Storage for HttpCache {
         _trace: &SpanHandle,
     ) -> pingora::Result<bool> {
         let hash = key.combined();
-        if let Some(mut obj) = self.cached.get(&hash).await {
-            obj.meta = meta.serialize()?;
-            let size = obj.body.len();
-            let _ = self.cached.put(hash, obj, get_wegith(size)).await?;
+        // only the meta is replaced, the body is not downloaded again
+        if self.cached.update_meta(&hash, meta.serialize()?).await? {
             Ok(true)
         } else {
             Err(Error::Invalid {
@@ -317,7 +405,9 @@ impl Storage for HttpCache {
 
 #[cfg(test)]
 mod tests {
-    use super::{CompleteHit, HttpCacheStorage, ObjectMissHandler};
+    use super::{
+        CacheObject, CompleteHit, HttpCacheStorage, ObjectMissHandler,
+    };
     use crate::cache::tiny::new_tiny_ufo_cache;
     use bytes::{Bytes, BytesMut};
     use pingora::cache::storage::{HitHandler, MissHandler};
@@ -367,4 +457,38 @@ mod tests {
         let data = cache.get(key).await.unwrap();
         assert_eq!("Hello World!", std::str::from_utf8(&data.body).unwrap());
     }
+
+    #[tokio::test]
+    async fn test_update_meta() {
+        let key = "key";
+        let cache = new_tiny_ufo_cache(10, 10);
+        assert_eq!(
+            false,
+            cache
+                .update_meta(key, (b"Hi".to_vec(), b"Pingap".to_vec()))
+                .await
+                .unwrap()
+        );
+        cache
+            .put(
+                key.to_string(),
+                CacheObject {
+                    meta: (b"Hello".to_vec(), b"World".to_vec()),
+                    body: b"Hello World!".to_vec(),
+                },
+                1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            true,
+            cache
+                .update_meta(key, (b"Hi".to_vec(), b"Pingap".to_vec()))
+                .await
+                .unwrap()
+        );
+        let data = cache.get(key).await.unwrap();
+        assert_eq!((b"Hi".to_vec(), b"Pingap".to_vec()), data.meta);
+        assert_eq!(b"Hello World!".to_vec(), data.body);
+    }
 }
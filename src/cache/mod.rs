@@ -16,8 +16,11 @@ use crate::util;
 use snafu::Snafu;
 use std::sync::Arc;
 
+mod control;
+mod entry;
 mod file;
 mod http_cache;
+mod snapshot;
 mod tiny;
 
 #[derive(Debug, Snafu)]
@@ -46,4 +49,13 @@ pub fn new_file_cache(dir: &str) -> Result<HttpCache> {
     })
 }
 
-pub use http_cache::HttpCache;
+pub use control::{
+    add_cache_tags, get_prefetch_token, get_upstream_cache_tags,
+    get_upstream_cache_ttl, is_prefetch_request, new_cache_tags_cleanup_task,
+    remove_upstream_cache_headers, HTTP_HEADER_NAME_X_PINGAP_PREFETCH,
+};
+pub use entry::{
+    add_cache_entry, get_prefetch_cache_entries, list_cache_entries,
+    CacheEntry, CacheRequest,
+};
+pub use http_cache::{CacheObject, HttpCache};
@@ -0,0 +1,169 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::entry::{get_fresh_cache_entries, restore_cache_entry, CacheEntry};
+use super::http_cache::{get_wegith, CacheObject, HttpCache};
+use super::{Error, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use std::path::Path;
+use tokio::fs;
+
+// the magic header of snapshot file
+const SNAPSHOT_MAGIC: &[u8] = b"PINGAPCS";
+
+/// Appends the data with its length(u32) to buffer.
+fn put_data(buf: &mut BytesMut, data: &[u8]) {
+    buf.put_u32(data.len() as u32);
+    buf.extend_from_slice(data);
+}
+
+/// Gets the data with its length(u32) from buffer.
+fn get_data(buf: &mut &[u8]) -> Result<Vec<u8>> {
+    if buf.remaining() < 4 {
+        return Err(Error::Invalid {
+            message: "snapshot is truncated".to_string(),
+        });
+    }
+    let size = buf.get_u32() as usize;
+    if buf.remaining() < size {
+        return Err(Error::Invalid {
+            message: "snapshot is truncated".to_string(),
+        });
+    }
+    let data = buf[..size].to_vec();
+    buf.advance(size);
+    Ok(data)
+}
+
+impl HttpCache {
+    /// Saves the snapshot of fresh cache entries to file,
+    /// the objects are saved too if the storage is not persistent(memory),
+    /// returns the count of saved entries.
+    pub async fn save_snapshot(&self, file: &str) -> Result<usize> {
+        let with_object = !self.cached.persistent();
+        let mut buf = BytesMut::with_capacity(1024 * 1024);
+        buf.extend_from_slice(SNAPSHOT_MAGIC);
+        let mut count = 0;
+        for entry in get_fresh_cache_entries() {
+            let object: Vec<u8> = if with_object {
+                let Some(obj) = self
+                    .cached
+                    .get(&entry.hash)
+                    .await
+                    .filter(|obj| !obj.meta.0.is_empty())
+                else {
+                    continue;
+                };
+                obj.into()
+            } else {
+                vec![]
+            };
+            let value =
+                serde_json::to_vec(&entry).map_err(|e| Error::Invalid {
+                    message: e.to_string(),
+                })?;
+            put_data(&mut buf, &value);
+            put_data(&mut buf, &object);
+            count += 1;
+        }
+        // write to temp file and rename, avoid reading a partial snapshot
+        let tmp_file = format!("{file}.tmp");
+        fs::write(&tmp_file, buf)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        fs::rename(&tmp_file, file)
+            .await
+            .map_err(|e| Error::Io { source: e })?;
+        Ok(count)
+    }
+    /// Restores the cache entries from snapshot file,
+    /// the existing objects are not replaced,
+    /// returns the count of restored entries.
+    pub async fn restore_snapshot(&self, file: &str) -> Result<usize> {
+        if !Path::new(file).exists() {
+            return Ok(0);
+        }
+        let data = fs::read(file).await.map_err(|e| Error::Io { source: e })?;
+        if !data.starts_with(SNAPSHOT_MAGIC) {
+            return Err(Error::Invalid {
+                message: "snapshot is invalid".to_string(),
+            });
+        }
+        let mut buf = &data[SNAPSHOT_MAGIC.len()..];
+        let mut count = 0;
+        while buf.has_remaining() {
+            let value = get_data(&mut buf)?;
+            let object = get_data(&mut buf)?;
+            let entry: CacheEntry =
+                serde_json::from_slice(&value).map_err(|e| Error::Invalid {
+                    message: e.to_string(),
+                })?;
+            if !object.is_empty() {
+                if self.cached.get(&entry.hash).await.is_some() {
+                    continue;
+                }
+                let obj = CacheObject::from(object);
+                let weight = get_wegith(obj.body.len());
+                self.cached.put(entry.hash.clone(), obj, weight).await?;
+            }
+            restore_cache_entry(entry);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::entry::{add_cache_entry, get_cache_entry};
+    use crate::cache::http_cache::CacheObject;
+    use crate::cache::new_tiny_ufo_cache;
+    use crate::util;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_cache_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("cache.snapshot");
+        let file = file.to_string_lossy().to_string();
+
+        let cache = new_tiny_ufo_cache(1024 * 1024);
+        assert_eq!(0, cache.restore_snapshot(&file).await.unwrap());
+
+        let obj = CacheObject {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: b"Hello World!".to_vec(),
+        };
+        let hash = "snapshot-entry";
+        cache
+            .cached
+            .put(hash.to_string(), obj.clone(), 1)
+            .await
+            .unwrap();
+        add_cache_entry(
+            hash,
+            "charts:/a",
+            util::now().as_secs() + 60,
+            None,
+            None,
+        );
+        assert_eq!(true, cache.save_snapshot(&file).await.unwrap() >= 1);
+
+        let cache = new_tiny_ufo_cache(1024 * 1024);
+        assert_eq!(true, cache.restore_snapshot(&file).await.unwrap() >= 1);
+        assert_eq!(obj, cache.cached.get(hash).await.unwrap());
+        assert_eq!("charts:/a", get_cache_entry(hash).unwrap().key);
+    }
+}
@@ -50,7 +50,16 @@ impl HttpCacheStorage for TinyUfoCache {
         self.cache.put(key, data, weight);
         Ok(())
     }
-    // TODO remove
+    /// Tinyufo doesn't support remove,
+    /// so replace the object with an empty one(without meta).
+    async fn remove(&self, key: &str) -> Result<Option<CacheObject>> {
+        let key = key.to_string();
+        let obj = self.cache.get(&key).filter(|obj| !obj.meta.0.is_empty());
+        if obj.is_some() {
+            self.cache.put(key, CacheObject::default(), 1);
+        }
+        Ok(obj)
+    }
 }
 
 #[cfg(test)]
@@ -71,5 +80,10 @@ mod tests {
         cache.put(key.clone(), obj.clone(), 1).await.unwrap();
         let result = cache.get(&key).await.unwrap();
         assert_eq!(obj, result);
+
+        let result = cache.remove(&key).await.unwrap();
+        assert_eq!(Some(obj), result);
+        assert_eq!(true, cache.lookup(&key).await.is_none());
+        assert_eq!(true, cache.remove(&key).await.unwrap().is_none());
     }
 }
@@ -0,0 +1,533 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config::EtcdStorage;
+use crate::sink::{self, SecurityEvent};
+use crate::state::get_hostname;
+use crate::util;
+use ahash::AHashMap;
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use etcd_client::{Client, EventType, GetOptions, PutOptions, WatchOptions};
+use once_cell::sync::{Lazy, OnceCell};
+use pingora::proxy::Session;
+use pingora::server::ShutdownWatch;
+use pingora::services::background::BackgroundService;
+use pingora_limits::rate::Rate;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::interval;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Config error {source}"))]
+    Config { source: crate::config::Error },
+    #[snafu(display("Etcd error {source}"))]
+    Etcd { source: etcd_client::Error },
+    #[snafu(display("Json error {source}"))]
+    SerdeJson { source: serde_json::Error },
+    #[snafu(display("Cluster is not enabled"))]
+    Disabled,
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+// the ttl of node, the node is removed if it's not alive
+const NODE_TTL: i64 = 30;
+// the ttl of event, the event is only for the nodes online
+const EVENT_TTL: i64 = 30;
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The event shared between pingap instances of cluster.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "category", rename_all = "snake_case")]
+pub enum ClusterEvent {
+    // purge the cache of tag
+    PurgeCacheTag {
+        tag: String,
+    },
+    // ban the ip until the expired time(seconds)
+    Ban {
+        ip: String,
+        expired_at: u64,
+    },
+    Unban {
+        ip: String,
+    },
+    // the rate counts of limit plugin
+    RateObserve {
+        key: String,
+        counts: Vec<(String, isize)>,
+    },
+    // the http-01 challenge of acme, it may be requested to any node
+    AcmeChallenge {
+        path: String,
+        value: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ClusterMessage {
+    node: String,
+    events: Vec<ClusterEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClusterNode {
+    pub name: String,
+    pub version: String,
+    pub started_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BanInfo {
+    pub ip: String,
+    pub expired_at: u64,
+}
+
+static CLUSTER_STORAGE: OnceCell<EtcdStorage> = OnceCell::new();
+static PENDING_EVENTS: Lazy<Mutex<Vec<ClusterEvent>>> =
+    Lazy::new(|| Mutex::new(vec![]));
+// the rate counts of limit plugin observed by current node,
+// they are sent to other nodes in batch
+static PENDING_RATE_COUNTS: Lazy<
+    Mutex<AHashMap<String, AHashMap<String, isize>>>,
+> = Lazy::new(|| Mutex::new(AHashMap::new()));
+static SHARED_RATES: Lazy<Mutex<AHashMap<String, Arc<Rate>>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+static BANS: Lazy<Mutex<AHashMap<String, u64>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+static HAS_BANS: AtomicBool = AtomicBool::new(false);
+static TRUSTED_PROXIES: Lazy<ArcSwap<util::IpRules>> =
+    Lazy::new(|| ArcSwap::from_pointee(util::IpRules::default()));
+
+/// Returns `true` if the cluster mode is enabled.
+pub fn is_cluster_enabled() -> bool {
+    CLUSTER_STORAGE.get().is_some()
+}
+
+/// Publishes the event to other nodes of cluster,
+/// it's ignored if the cluster mode is disabled.
+pub fn publish(event: ClusterEvent) {
+    if !is_cluster_enabled() {
+        return;
+    }
+    if let Ok(mut events) = PENDING_EVENTS.lock() {
+        events.push(event);
+    }
+}
+
+/// Gets the rate of limit plugin by key, it's created if not exists.
+/// The counts of it are shared in cluster.
+pub fn get_shared_rate(key: &str, interval: Duration) -> Arc<Rate> {
+    let Ok(mut rates) = SHARED_RATES.lock() else {
+        return Arc::new(Rate::new(interval));
+    };
+    rates
+        .entry(key.to_string())
+        .or_insert_with(|| Arc::new(Rate::new(interval)))
+        .clone()
+}
+
+/// Records the rate count observed by current node.
+pub fn observe_shared_rate(key: &str, item: &str, count: isize) {
+    if !is_cluster_enabled() {
+        return;
+    }
+    if let Ok(mut rate_counts) = PENDING_RATE_COUNTS.lock() {
+        *rate_counts
+            .entry(key.to_string())
+            .or_default()
+            .entry(item.to_string())
+            .or_default() += count;
+    }
+}
+
+fn add_ban(ip: &str, expired_at: u64) {
+    if let Ok(mut bans) = BANS.lock() {
+        bans.insert(ip.to_string(), expired_at);
+        HAS_BANS.store(true, Ordering::Relaxed);
+    }
+}
+
+fn remove_ban(ip: &str) {
+    if let Ok(mut bans) = BANS.lock() {
+        bans.remove(ip);
+        HAS_BANS.store(!bans.is_empty(), Ordering::Relaxed);
+    }
+}
+
+/// Sets the trusted proxies, the `X-Forwarded-For` set by them is used
+/// to get the client ip of ban.
+pub fn set_trusted_proxies(values: &[String]) {
+    TRUSTED_PROXIES.store(Arc::new(util::IpRules::new(values)));
+}
+
+/// Gets the client ip to ban or check the ban, it can't be forged by
+/// the `X-Forwarded-For` of untrusted client.
+pub fn get_ban_ip(session: &Session) -> String {
+    util::get_trusted_client_ip(session, &TRUSTED_PROXIES.load())
+}
+
+/// Bans the ip for a while, the ban is shared in cluster.
+pub fn ban_ip(ip: &str, ttl: Duration) {
+    let expired_at = util::now().as_secs() + ttl.as_secs();
+    add_ban(ip, expired_at);
+    sink::send_security_event(SecurityEvent::Ban {
+        ip: ip.to_string(),
+        expired_at,
+    });
+    publish(ClusterEvent::Ban {
+        ip: ip.to_string(),
+        expired_at,
+    });
+}
+
+/// Unbans the ip, the unban is shared in cluster.
+pub fn unban_ip(ip: &str) {
+    remove_ban(ip);
+    publish(ClusterEvent::Unban { ip: ip.to_string() });
+}
+
+/// Returns `true` if there is any ban, it's used to avoid locking.
+pub fn has_bans() -> bool {
+    HAS_BANS.load(Ordering::Relaxed)
+}
+
+/// Returns `true` if the ip is banned and not expired.
+pub fn is_banned(ip: &str) -> bool {
+    if !has_bans() {
+        return false;
+    }
+    let Ok(mut bans) = BANS.lock() else {
+        return false;
+    };
+    match bans.get(ip) {
+        Some(expired_at) if *expired_at > util::now().as_secs() => true,
+        Some(_) => {
+            bans.remove(ip);
+            HAS_BANS.store(!bans.is_empty(), Ordering::Relaxed);
+            false
+        },
+        None => false,
+    }
+}
+
+/// Gets the ban list which is not expired.
+pub fn get_bans() -> Vec<BanInfo> {
+    let now = util::now().as_secs();
+    let Ok(mut bans) = BANS.lock() else {
+        return vec![];
+    };
+    bans.retain(|_, expired_at| *expired_at > now);
+    HAS_BANS.store(!bans.is_empty(), Ordering::Relaxed);
+    bans.iter()
+        .map(|(ip, expired_at)| BanInfo {
+            ip: ip.to_string(),
+            expired_at: *expired_at,
+        })
+        .collect()
+}
+
+/// Takes the pending events of current node as a message.
+fn take_message(node: &str) -> Option<ClusterMessage> {
+    let mut events = PENDING_EVENTS
+        .lock()
+        .map(|mut events| std::mem::take(&mut *events))
+        .unwrap_or_default();
+    let rate_counts = PENDING_RATE_COUNTS
+        .lock()
+        .map(|mut rate_counts| std::mem::take(&mut *rate_counts))
+        .unwrap_or_default();
+    for (key, counts) in rate_counts {
+        events.push(ClusterEvent::RateObserve {
+            key,
+            counts: counts.into_iter().collect(),
+        });
+    }
+    if events.is_empty() {
+        return None;
+    }
+    Some(ClusterMessage {
+        node: node.to_string(),
+        events,
+    })
+}
+
+/// Applies the event of other node to current node.
+async fn apply_event(event: ClusterEvent) {
+    match event {
+        ClusterEvent::PurgeCacheTag { tag } => {
+            let count = crate::plugin::purge_cache_tag(&tag).await;
+            info!(tag, count, "purge cache tag from cluster");
+        },
+        ClusterEvent::Ban { ip, expired_at } => add_ban(&ip, expired_at),
+        ClusterEvent::Unban { ip } => remove_ban(&ip),
+        ClusterEvent::RateObserve { key, counts } => {
+            let Some(rate) = SHARED_RATES
+                .lock()
+                .ok()
+                .and_then(|rates| rates.get(&key).cloned())
+            else {
+                return;
+            };
+            for (item, count) in counts {
+                rate.observe(&item, count);
+            }
+        },
+        ClusterEvent::AcmeChallenge { path, value } => {
+            crate::acme::add_challenge_token(&path, &value).await;
+        },
+    }
+}
+
+async fn handle_message(node: &str, buf: &[u8]) {
+    let message: ClusterMessage = match serde_json::from_slice(buf) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!(error = e.to_string(), "invalid cluster message");
+            return;
+        },
+    };
+    // the message is sent by current node
+    if message.node == node {
+        return;
+    }
+    for event in message.events {
+        apply_event(event).await;
+    }
+}
+
+fn get_storage() -> Result<&'static EtcdStorage> {
+    CLUSTER_STORAGE.get().ok_or(Error::Disabled)
+}
+
+async fn connect() -> Result<Client> {
+    get_storage()?.connect().await.context(ConfigSnafu)
+}
+
+/// Gets the alive nodes of cluster.
+pub async fn get_cluster_nodes() -> Result<Vec<ClusterNode>> {
+    let prefix = format!("{}/nodes/", get_storage()?.path());
+    let mut client = connect().await?;
+    let kvs = client
+        .get(prefix, Some(GetOptions::new().with_prefix()))
+        .await
+        .context(EtcdSnafu)?
+        .take_kvs();
+    let mut nodes = vec![];
+    for kv in kvs {
+        nodes.push(serde_json::from_slice(kv.value()).context(SerdeJsonSnafu)?);
+    }
+    Ok(nodes)
+}
+
+/// Saves the certificate to cluster, other nodes can use it
+/// instead of applying a new one.
+pub async fn save_certificate(name: &str, data: &[u8]) -> Result<()> {
+    let key = format!("{}/certificates/{name}", get_storage()?.path());
+    let mut client = connect().await?;
+    client.put(key, data, None).await.context(EtcdSnafu)?;
+    Ok(())
+}
+
+/// Loads the certificate from cluster.
+pub async fn load_certificate(name: &str) -> Result<Option<Vec<u8>>> {
+    let key = format!("{}/certificates/{name}", get_storage()?.path());
+    let mut client = connect().await?;
+    let kvs = client.get(key, None).await.context(EtcdSnafu)?.take_kvs();
+    Ok(kvs.first().map(|kv| kv.value().to_vec()))
+}
+
+pub struct ClusterService {
+    node: ClusterNode,
+}
+
+/// Creates the cluster service, the nodes share state by etcd.
+/// Connection url: etcd://host1:port1,host2:port2/pingap-cluster?timeout=10s
+pub fn new_cluster_service(url: &str) -> Result<ClusterService> {
+    let storage = EtcdStorage::new(url).context(ConfigSnafu)?;
+    if CLUSTER_STORAGE.set(storage).is_err() {
+        warn!("cluster service is initialized");
+    }
+    Ok(ClusterService {
+        node: ClusterNode {
+            name: get_hostname(),
+            version: util::get_pkg_version().to_string(),
+            started_at: util::now().as_secs(),
+        },
+    })
+}
+
+impl ClusterService {
+    async fn publish_message(
+        &self,
+        client: &mut Client,
+        prefix: &str,
+        message: &ClusterMessage,
+    ) -> Result<()> {
+        let buf = serde_json::to_vec(message).context(SerdeJsonSnafu)?;
+        let lease = client
+            .lease_grant(EVENT_TTL, None)
+            .await
+            .context(EtcdSnafu)?
+            .id();
+        let key = format!("{prefix}/events/{}", uuid::Uuid::now_v7());
+        client
+            .put(key, buf, Some(PutOptions::new().with_lease(lease)))
+            .await
+            .context(EtcdSnafu)?;
+        Ok(())
+    }
+    /// Registers the node and watches the events of other nodes
+    /// until the connection fails or shutdown.
+    async fn run(&self, shutdown: &mut ShutdownWatch) -> Result<()> {
+        let prefix = get_storage()?.path().to_string();
+        let mut client = connect().await?;
+        let lease = client
+            .lease_grant(NODE_TTL, None)
+            .await
+            .context(EtcdSnafu)?
+            .id();
+        let buf = serde_json::to_vec(&self.node).context(SerdeJsonSnafu)?;
+        client
+            .put(
+                format!("{prefix}/nodes/{}", self.node.name),
+                buf,
+                Some(PutOptions::new().with_lease(lease)),
+            )
+            .await
+            .context(EtcdSnafu)?;
+        let (mut keeper, _) =
+            client.lease_keep_alive(lease).await.context(EtcdSnafu)?;
+        let (_watcher, mut stream) = client
+            .watch(
+                format!("{prefix}/events/"),
+                Some(WatchOptions::new().with_prefix()),
+            )
+            .await
+            .context(EtcdSnafu)?;
+        info!(node = self.node.name, "join the cluster");
+
+        let mut sync = interval(SYNC_INTERVAL);
+        let mut keepalive = interval(KEEPALIVE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    // the node is removed from cluster immediately
+                    let _ = client.lease_revoke(lease).await;
+                    return Ok(());
+                }
+                _ = keepalive.tick() => {
+                    keeper.keep_alive().await.context(EtcdSnafu)?;
+                }
+                _ = sync.tick() => {
+                    if let Some(message) = take_message(&self.node.name) {
+                        self.publish_message(&mut client, &prefix, &message)
+                            .await?;
+                    }
+                }
+                resp = stream.message() => {
+                    let Some(resp) = resp.context(EtcdSnafu)? else {
+                        return Ok(());
+                    };
+                    for event in resp.events() {
+                        if event.event_type() != EventType::Put {
+                            continue;
+                        }
+                        if let Some(kv) = event.kv() {
+                            handle_message(&self.node.name, kv.value()).await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BackgroundService for ClusterService {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        loop {
+            if let Err(e) = self.run(&mut shutdown).await {
+                error!(error = e.to_string(), "cluster service fail");
+            }
+            if *shutdown.borrow() {
+                break;
+            }
+            // reconnect after a while
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    break;
+                }
+                _ = tokio::time::sleep(RETRY_INTERVAL) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_ban, get_bans, is_banned, remove_ban, take_message, ClusterEvent,
+        ClusterMessage,
+    };
+    use crate::util;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_cluster_event() {
+        let message = ClusterMessage {
+            node: "pingap".to_string(),
+            events: vec![
+                ClusterEvent::PurgeCacheTag {
+                    tag: "product".to_string(),
+                },
+                ClusterEvent::RateObserve {
+                    key: "limit".to_string(),
+                    counts: vec![("1.1.1.1".to_string(), 2)],
+                },
+            ],
+        };
+        let buf = serde_json::to_string(&message).unwrap();
+        assert_eq!(
+            r#"{"node":"pingap","events":[{"category":"purge_cache_tag","tag":"product"},{"category":"rate_observe","key":"limit","counts":[["1.1.1.1",2]]}]}"#,
+            buf
+        );
+        let value: ClusterMessage = serde_json::from_str(&buf).unwrap();
+        assert_eq!(message.events, value.events);
+
+        // cluster is not enabled
+        assert_eq!(true, take_message("pingap").is_none());
+    }
+
+    #[test]
+    fn test_ban() {
+        let now = util::now().as_secs();
+        add_ban("1.1.1.1", now + 60);
+        add_ban("2.2.2.2", now - 1);
+        assert_eq!(true, is_banned("1.1.1.1"));
+        assert_eq!(false, is_banned("2.2.2.2"));
+        assert_eq!(false, is_banned("3.3.3.3"));
+        assert_eq!(1, get_bans().len());
+        remove_ban("1.1.1.1");
+        assert_eq!(false, is_banned("1.1.1.1"));
+    }
+}
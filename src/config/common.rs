@@ -12,9 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::template::{collapse_includes, expand_includes, ConfigTemplate};
 use super::{Error, Result};
-use crate::plugin::parse_plugins;
-use crate::proxy::{is_dns_discovery, Parser};
+use crate::discovery::{DnsFailurePolicy, RegistryParams};
+use crate::plugin::{get_builtin_proxy_plugins, parse_plugins};
+use crate::proxy::{
+    is_dns_discovery, is_valid_header_filter, is_xds_discovery,
+    validate_clickhouse, validate_latency_buckets, validate_metering,
+    validate_metrics_path_templates, Parser,
+};
+use crate::service::{validate_cron, validate_metrics_push};
+use crate::sink::validate_sink;
+use crate::state::validate_sandbox;
 use crate::util;
 use arc_swap::ArcSwap;
 use base64::{engine::general_purpose::STANDARD, Engine};
@@ -33,14 +42,24 @@ use std::{collections::HashMap, str::FromStr};
 use strum::EnumString;
 use toml::{map::Map, Value};
 use url::Url;
+use utoipa::ToSchema;
 
 pub const CATEGORY_CERTIFICATE: &str = "certificate";
 pub const CATEGORY_UPSTREAM: &str = "upstream";
 pub const CATEGORY_LOCATION: &str = "location";
 pub const CATEGORY_SERVER: &str = "server";
 pub const CATEGORY_PLUGIN: &str = "plugin";
+pub const CATEGORY_SCHEDULE: &str = "schedule";
 pub const CATEGORY_BASIC: &str = "basic";
 
+// the max priority of location, the request of it can use
+// all reserved concurrency of upstream
+pub const MAX_LOCATION_PRIORITY: u8 = 9;
+
+// the max size of request body buffered for retry, it's the size of
+// pingora retry buffer, the larger body is truncated by pingora
+pub const MAX_REQUEST_BUFFER_SIZE: u64 = 64 * 1024;
+
 #[derive(PartialEq, Debug, Default, Clone, EnumString, strum::Display)]
 #[strum(serialize_all = "snake_case")]
 pub enum PluginCategory {
@@ -60,11 +79,18 @@ pub enum PluginCategory {
     Redirect,
     Ping,
     ResponseHeaders,
+    SecurityHeaders,
+    BotDetection,
+    RequestSign,
     RefererRestriction,
     Csrf,
     Cors,
     OwaspCrsPlugin,
     WirefilterPlugin,
+    Coalesce,
+    Honeypot,
+    CookieFilter,
+    AbTest,
 }
 
 impl Serialize for PluginCategory {
@@ -123,7 +149,31 @@ impl<'de> Deserialize<'de> for PluginStep {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Clone, Serialize)]
+/// Reads the pem of certificate, the value can be pem,
+/// the path of pem file or the base64 of pem.
+fn read_certificate_pem(value: &str) -> Result<Vec<u8>> {
+    if util::is_pem(value) {
+        return Ok(value.as_bytes().to_vec());
+    }
+    if util::is_pem_file(value) {
+        let file = util::resolve_path(value);
+        return std::fs::read(&file).map_err(|e| Error::Io { source: e, file });
+    }
+    STANDARD
+        .decode(value)
+        .map_err(|e| Error::Base64Decode { source: e })
+}
+
+/// Returns `false` if the private key doesn't match the certificate,
+/// `None` is returned if any of them is invalid.
+fn is_certificate_key_matched(cert: &str, key: &str) -> Option<bool> {
+    let cert = X509::from_pem(&read_certificate_pem(cert).ok()?).ok()?;
+    let key =
+        PKey::private_key_from_pem(&read_certificate_pem(key).ok()?).ok()?;
+    Some(cert.public_key().ok()?.public_eq(&key))
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Serialize, ToSchema)]
 pub struct CertificateConf {
     pub domains: Option<String>,
     pub tls_cert: Option<String>,
@@ -138,13 +188,7 @@ impl CertificateConf {
     /// Validate the options of certificate config.
     pub fn validate(&self) -> Result<()> {
         if let Some(value) = &self.tls_key {
-            let buf = if util::is_pem(value) {
-                value.as_bytes().to_vec()
-            } else {
-                STANDARD
-                    .decode(value)
-                    .map_err(|e| Error::Base64Decode { source: e })?
-            };
+            let buf = read_certificate_pem(value)?;
 
             let _ = PKey::private_key_from_pem(&buf).map_err(|e| {
                 Error::Invalid {
@@ -153,25 +197,13 @@ impl CertificateConf {
             })?;
         }
         if let Some(value) = &self.tls_cert {
-            let buf = if util::is_pem(value) {
-                value.as_bytes().to_vec()
-            } else {
-                STANDARD
-                    .decode(value)
-                    .map_err(|e| Error::Base64Decode { source: e })?
-            };
+            let buf = read_certificate_pem(value)?;
             let _ = X509::from_pem(&buf).map_err(|e| Error::Invalid {
                 message: e.to_string(),
             })?;
         }
         if let Some(value) = &self.tls_chain {
-            let buf = if util::is_pem(value) {
-                value.as_bytes().to_vec()
-            } else {
-                STANDARD
-                    .decode(value)
-                    .map_err(|e| Error::Base64Decode { source: e })?
-            };
+            let buf = read_certificate_pem(value)?;
             let _ = X509::from_pem(&buf).map_err(|e| Error::Invalid {
                 message: e.to_string(),
             })?;
@@ -180,70 +212,261 @@ impl CertificateConf {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Clone, Serialize)]
+#[derive(Debug, Default, Deserialize, Clone, Serialize, ToSchema)]
 pub struct UpstreamConf {
     pub addrs: Vec<String>,
     pub discovery: Option<String>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub update_frequency: Option<Duration>,
     pub algo: Option<String>,
     pub sni: Option<String>,
     pub verify_cert: Option<bool>,
     pub health_check: Option<String>,
     pub ipv4_only: Option<bool>,
+    pub ipv6_only: Option<bool>,
+    pub prefer_ipv6: Option<bool>,
+    pub dns_ttl_refresh: Option<bool>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub dns_min_ttl: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub dns_max_ttl: Option<Duration>,
+    // the policy of dns discovery when the resolution fails,
+    // it should be keep, fail or static
+    pub dns_failure_policy: Option<String>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub dns_max_stale: Option<Duration>,
+    pub dns_fallback_addrs: Option<Vec<String>>,
+    pub lazy_resolve: Option<bool>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub resolve_valid: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub resolve_negative_valid: Option<Duration>,
     pub enable_tracer: Option<bool>,
     pub alpn: Option<String>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub connection_timeout: Option<Duration>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub total_connection_timeout: Option<Duration>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub read_timeout: Option<Duration>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub idle_timeout: Option<Duration>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub write_timeout: Option<Duration>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub tcp_idle: Option<Duration>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub tcp_interval: Option<Duration>,
     pub tcp_probe_count: Option<usize>,
+    #[schema(value_type = Option<String>)]
     pub tcp_recv_buf: Option<ByteSize>,
     pub tcp_fast_open: Option<bool>,
+    pub keepalive_pool_size: Option<usize>,
+    pub max_requests_per_connection: Option<u32>,
+    pub max_h2_streams: Option<usize>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub slow_start: Option<Duration>,
+    // the bucket of s3 compatible origin
+    pub s3_bucket: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_prefix: Option<String>,
+    pub s3_path_style: Option<bool>,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_access_key: Option<String>,
+    pub s3_session_token: Option<String>,
+    // the backends are fastcgi servers(e.g. php-fpm)
+    pub fastcgi: Option<bool>,
+    pub fastcgi_root: Option<String>,
+    pub fastcgi_index: Option<String>,
+    pub fastcgi_params: Option<Vec<String>>,
+    pub fastcgi_keepalive: Option<bool>,
+    // the protocol of python application server, uwsgi or scgi
+    pub wsgi_protocol: Option<String>,
+    pub wsgi_script_name: Option<String>,
+    pub wsgi_params: Option<Vec<String>>,
+    // the green upstream of blue green deployment, the requests are sent
+    // to it after it's switched to green by admin api
+    pub green_upstream: Option<String>,
+    // the min healthy backends of target upstream to switch, default 1
+    pub blue_green_min_healthy: Option<usize>,
+    // the max concurrent requests of upstream, and the part reserved
+    // for the requests of high priority locations
+    pub max_concurrency: Option<u32>,
+    pub reserved_concurrency: Option<u32>,
+    // the namespace of tenant, empty means it's global
+    pub namespace: Option<String>,
     pub remark: Option<String>,
 }
 impl UpstreamConf {
     /// Validate the options of upstream config.
     /// 1. The address list can't be empty, and can be converted to socket addr.
     /// 2. The health check url can be parsed to Url if it exists.
+    /// 3. The alpn should be h1, h2 or h2h1, and max h2 streams can't be 0.
+    /// 4. The region of s3 origin should be set with its credentials.
+    /// 5. The root of fastcgi should be set, and it can't be s3 origin.
+    /// 6. The wsgi protocol should be uwsgi or scgi, and it can't be used
+    ///    with fastcgi or s3 origin.
+    /// 7. The reserved concurrency can't be greater than max concurrency.
     pub fn validate(&self, name: &str) -> Result<()> {
         if self.addrs.is_empty() {
             return Err(Error::Invalid {
                 message: "upstream addrs is empty".to_string(),
             });
         }
-        // validate upstream addr
-        if !is_dns_discovery(&self.discovery.clone().unwrap_or_default()) {
+        // validate upstream addr, the addrs of xds are cluster names,
+        // and the hosts of lazy resolve are resolved at request time
+        let discovery = self.discovery.clone().unwrap_or_default();
+        if !is_dns_discovery(&discovery)
+            && !is_xds_discovery(&discovery)
+            && !self.lazy_resolve.unwrap_or_default()
+        {
             for addr in self.addrs.iter() {
                 let arr: Vec<_> = addr.split(' ').collect();
-                let mut addr = arr[0].to_string();
-                if !addr.contains(':') {
-                    addr = format!("{addr}:80");
-                }
+                let (host, port) = util::split_host_port(arr[0]);
+                let addr = util::join_host_port(host, port.unwrap_or("80"));
                 let _ = addr.to_socket_addrs().map_err(|e| Error::Io {
                     source: e,
                     file: format!("{}(upstream:{name})", arr[0]),
                 })?;
             }
         }
+        let ip_options = [self.ipv4_only, self.ipv6_only, self.prefer_ipv6];
+        if ip_options
+            .iter()
+            .filter(|item| item.unwrap_or_default())
+            .count()
+            > 1
+        {
+            return Err(Error::Invalid {
+                message: format!(
+                    "ipv4_only, ipv6_only and prefer_ipv6 are exclusive(upstream:{name})"
+                ),
+            });
+        }
+        if let (Some(min), Some(max)) = (self.dns_min_ttl, self.dns_max_ttl) {
+            if min > max {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "dns min ttl should be less than max ttl(upstream:{name})"
+                    ),
+                });
+            }
+        }
+        if let Some(policy) = &self.dns_failure_policy {
+            let _ = DnsFailurePolicy::new(
+                policy,
+                self.dns_max_stale,
+                &self.dns_fallback_addrs.clone().unwrap_or_default(),
+            )
+            .map_err(|e| Error::Invalid {
+                message: format!("{e}(upstream:{name})"),
+            })?;
+        }
+        if self.lazy_resolve.unwrap_or_default()
+            && self.discovery.as_ref().is_some_and(|item| !item.is_empty())
+        {
+            return Err(Error::Invalid {
+                message: format!(
+                    "lazy resolve can't be used with discovery(upstream:{name})"
+                ),
+            });
+        }
+        if let Some(alpn) = &self.alpn {
+            if !["H1", "H2", "H2H1"].contains(&alpn.to_uppercase().as_str()) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "alpn should be h1, h2 or h2h1(upstream:{name})"
+                    ),
+                });
+            }
+        }
+        if self.max_h2_streams == Some(0) {
+            return Err(Error::Invalid {
+                message: format!(
+                    "max h2 streams should be greater than 0(upstream:{name})"
+                ),
+            });
+        }
+        let is_empty = |value: &Option<String>| {
+            value.as_ref().map_or(true, String::is_empty)
+        };
+        if !is_empty(&self.s3_bucket)
+            && !is_empty(&self.s3_access_key_id)
+            && is_empty(&self.s3_region)
+        {
+            return Err(Error::Invalid {
+                message: format!("s3 region should be set(upstream:{name})"),
+            });
+        }
+        if self.fastcgi.unwrap_or_default() {
+            if is_empty(&self.fastcgi_root) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "fastcgi root should be set(upstream:{name})"
+                    ),
+                });
+            }
+            if !is_empty(&self.s3_bucket) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "fastcgi can't be used with s3 origin(upstream:{name})"
+                    ),
+                });
+            }
+        }
+        if let Some(protocol) = &self.wsgi_protocol {
+            if !["uwsgi", "scgi"].contains(&protocol.to_lowercase().as_str()) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "wsgi protocol should be uwsgi or scgi(upstream:{name})"
+                    ),
+                });
+            }
+            if self.fastcgi.unwrap_or_default() || !is_empty(&self.s3_bucket) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "wsgi can't be used with fastcgi or s3 origin(upstream:{name})"
+                    ),
+                });
+            }
+        }
+        if let Some(reserved) = self.reserved_concurrency {
+            if reserved > self.max_concurrency.unwrap_or_default() {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "reserved concurrency can't be greater than max concurrency(upstream:{name})"
+                    ),
+                });
+            }
+        }
         // validate health check
         let health_check = self.health_check.clone().unwrap_or_default();
         if !health_check.is_empty() {
@@ -257,7 +480,7 @@ impl UpstreamConf {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Clone, Serialize)]
+#[derive(Debug, Default, Deserialize, Clone, Serialize, ToSchema)]
 pub struct LocationConf {
     pub upstream: Option<String>,
     pub path: Option<String>,
@@ -267,7 +490,59 @@ pub struct LocationConf {
     pub rewrite: Option<String>,
     pub weight: Option<u16>,
     pub plugins: Option<Vec<String>>,
+    // the params of plugin are overridden for this location
+    #[schema(value_type = Option<HashMap<String, Object>>)]
+    pub plugin_overrides: Option<HashMap<String, PluginConf>>,
+    #[schema(value_type = Option<String>)]
     pub client_max_body_size: Option<ByteSize>,
+    pub debug_journal: Option<bool>,
+    pub debug_journal_ips: Option<Vec<String>>,
+    pub internal_only: Option<bool>,
+    pub internal_ips: Option<Vec<String>>,
+    pub auth_bypass_paths: Option<Vec<String>>,
+    pub internal_redirect: Option<bool>,
+    pub sendfile_root: Option<String>,
+    pub low_priority: Option<bool>,
+    // the priority of location to use the reserved concurrency of upstream
+    pub priority: Option<u8>,
+    pub fallback_upstreams: Option<Vec<String>>,
+    pub fallback_statuses: Option<Vec<u16>>,
+    pub proxy_request_buffering: Option<bool>,
+    #[schema(value_type = Option<String>)]
+    pub proxy_request_buffer_size: Option<ByteSize>,
+    pub proxy_retries: Option<u8>,
+    // the grace period before the upstream request of non-idempotent
+    // method is cancelled when the client aborts
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub client_abort_grace: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub cache_not_found: Option<Duration>,
+    pub always_online: Option<bool>,
+    pub upstream_down_fallback: Option<String>,
+    pub access_log: Option<String>,
+    pub access_log_file: Option<String>,
+    // the timeouts of upstream peer, they override the upstream's
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub proxy_connect_timeout: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub proxy_read_timeout: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub proxy_write_timeout: Option<Duration>,
+    // the headers of upstream response to remove or to keep only
+    pub proxy_hide_headers: Option<Vec<String>>,
+    pub proxy_allow_headers: Option<Vec<String>>,
+    // the namespace of tenant, empty means it's global
+    pub namespace: Option<String>,
     pub remark: Option<String>,
 }
 
@@ -310,14 +585,79 @@ impl LocationConf {
                 ),
             });
         }
+        for upstream in self.fallback_upstreams.iter().flatten() {
+            if !upstream_names.contains(upstream) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "fallback upstream({upstream}) is not found(location:{name})"
+                    ),
+                });
+            }
+        }
+        if self.priority.unwrap_or_default() > MAX_LOCATION_PRIORITY {
+            return Err(Error::Invalid {
+                message: format!(
+                    "priority should be between 0 and {MAX_LOCATION_PRIORITY}(location:{name})"
+                ),
+            });
+        }
+        if self.proxy_request_buffer_size.unwrap_or_default().as_u64()
+            > MAX_REQUEST_BUFFER_SIZE
+        {
+            return Err(Error::Invalid {
+                message: format!(
+                    "proxy request buffer size should be less than or equal to {}KB(location:{name})",
+                    MAX_REQUEST_BUFFER_SIZE / 1024
+                ),
+            });
+        }
         validate(&self.proxy_add_headers)?;
         validate(&self.proxy_set_headers)?;
+        for value in self
+            .proxy_hide_headers
+            .iter()
+            .chain(self.proxy_allow_headers.iter())
+            .flatten()
+        {
+            if !is_valid_header_filter(value.trim()) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "response header filter({value}) is invalid(location:{name})"
+                    ),
+                });
+            }
+        }
 
         if let Some(value) = &self.rewrite {
             let arr: Vec<&str> = value.split(' ').collect();
             let _ =
                 Regex::new(arr[0]).map_err(|e| Error::Regex { source: e })?;
         }
+        for path in self.auth_bypass_paths.iter().flatten() {
+            if let Some(value) = path.trim().strip_prefix('~') {
+                let _ = Regex::new(value.trim())
+                    .map_err(|e| Error::Regex { source: e })?;
+            }
+        }
+        let plugins = self.plugins.clone().unwrap_or_default();
+        for (index, plugin) in plugins.iter().enumerate() {
+            if plugins[..index].contains(plugin) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "plugin({plugin}) is duplicated(location:{name})"
+                    ),
+                });
+            }
+        }
+        for plugin in self.plugin_overrides.iter().flat_map(|v| v.keys()) {
+            if !plugins.contains(plugin) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "override plugin({plugin}) is not used(location:{name})"
+                    ),
+                });
+            }
+        }
 
         Ok(())
     }
@@ -349,7 +689,7 @@ impl LocationConf {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Clone, Serialize)]
+#[derive(Debug, Default, Deserialize, Clone, Serialize, ToSchema)]
 pub struct ServerConf {
     pub addr: String,
     pub access_log: Option<String>,
@@ -367,22 +707,115 @@ pub struct ServerConf {
     pub enabled_h2: Option<bool>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub tcp_idle: Option<Duration>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub tcp_interval: Option<Duration>,
     pub tcp_probe_count: Option<usize>,
     pub tcp_fastopen: Option<usize>,
+    pub overload_max_processing: Option<i32>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub overload_max_event_loop_delay: Option<Duration>,
+    pub overload_low_priority_only: Option<bool>,
+    pub https_redirect: Option<bool>,
+    pub normalize_path: Option<bool>,
+    pub normalize_host: Option<bool>,
+    pub strict_request: Option<bool>,
+    pub max_header_count: Option<usize>,
+    #[schema(value_type = Option<String>)]
+    pub max_header_size: Option<ByteSize>,
+    // the handling of request which doesn't match any location,
+    // e.g. `redirect:https://example.com` or `location:catchAll`
+    pub not_found: Option<String>,
+    // the defaults of locations, they are inherited if the location
+    // doesn't set the value, the plugin of same category is overridden
+    pub location_plugins: Option<Vec<String>>,
+    #[schema(value_type = Option<String>)]
+    pub location_client_max_body_size: Option<ByteSize>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub location_proxy_connect_timeout: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub location_proxy_read_timeout: Option<Duration>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub location_proxy_write_timeout: Option<Duration>,
+    // the service registry to generate locations,
+    // e.g. consul://127.0.0.1:8500?prefix=pingap
+    pub registry: Option<String>,
+    // the namespace of tenant, empty means it's global
+    pub namespace: Option<String>,
     pub remark: Option<String>,
 }
 
+/// The handling of request which doesn't match any location.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum NotFoundAction {
+    // respond 404 with the error template
+    #[default]
+    Respond,
+    // redirect to the default host, the path and query are kept
+    Redirect(String),
+    // fall through to the catch-all location
+    Location(String),
+}
+
+impl FromStr for NotFoundAction {
+    type Err = Error;
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim();
+        if value.is_empty() || value == "404" {
+            return Ok(Self::Respond);
+        }
+        match value.split_once(':') {
+            Some(("redirect", url)) => {
+                let url = url.trim().trim_end_matches('/');
+                Url::parse(url).map_err(|e| Error::Invalid {
+                    message: format!("redirect url({url}) is invalid, {e}"),
+                })?;
+                Ok(Self::Redirect(url.to_string()))
+            },
+            Some(("location", name)) if !name.trim().is_empty() => {
+                Ok(Self::Location(name.trim().to_string()))
+            },
+            _ => Err(Error::Invalid {
+                message: format!(
+                    "not found({value}) should be 404, redirect:url or location:name"
+                ),
+            }),
+        }
+    }
+}
+
 impl ServerConf {
+    /// Returns `true` if the defaults of locations are the same.
+    fn has_same_location_defaults(&self, other: &ServerConf) -> bool {
+        self.location_plugins == other.location_plugins
+            && self.location_client_max_body_size
+                == other.location_client_max_body_size
+            && self.location_proxy_connect_timeout
+                == other.location_proxy_connect_timeout
+            && self.location_proxy_read_timeout
+                == other.location_proxy_read_timeout
+            && self.location_proxy_write_timeout
+                == other.location_proxy_write_timeout
+    }
     /// Validate the options of server config.
     /// 1. Parse listen addr to socket addr.
     /// 2. Check the locations are exists.
     /// 3. Parse tls key to `Pkey` success.
     /// 4. Parse tls cert to `X509` success.
     /// 5. Parse access log layout success.
+    /// 6. Parse registry url success.
+    /// 7. Check the not found action is valid.
     fn validate(&self, name: &str, location_names: &[String]) -> Result<()> {
         for addr in self.addr.split(',') {
             let _ = addr.to_socket_addrs().map_err(|e| Error::Io {
@@ -401,14 +834,19 @@ impl ServerConf {
                 }
             }
         }
+        if self.https_redirect.unwrap_or_default()
+            && self.tls_cert.is_none()
+            && self.lets_encrypt.is_none()
+            && !self.global_certificates.unwrap_or_default()
+        {
+            return Err(Error::Invalid {
+                message: format!(
+                    "https redirect is only for tls server(server:{name})"
+                ),
+            });
+        }
         if let Some(value) = &self.tls_key {
-            let buf = if util::is_pem(value) {
-                value.as_bytes().to_vec()
-            } else {
-                STANDARD
-                    .decode(value)
-                    .map_err(|e| Error::Base64Decode { source: e })?
-            };
+            let buf = read_certificate_pem(value)?;
 
             let _ = PKey::private_key_from_pem(&buf).map_err(|e| {
                 Error::Invalid {
@@ -417,13 +855,7 @@ impl ServerConf {
             })?;
         }
         if let Some(value) = &self.tls_cert {
-            let buf = if util::is_pem(value) {
-                value.as_bytes().to_vec()
-            } else {
-                STANDARD
-                    .decode(value)
-                    .map_err(|e| Error::Base64Decode { source: e })?
-            };
+            let buf = read_certificate_pem(value)?;
             let _ = X509::from_pem(&buf).map_err(|e| Error::Invalid {
                 message: e.to_string(),
             })?;
@@ -436,27 +868,59 @@ impl ServerConf {
                 });
             }
         }
+        if let Some(registry) = &self.registry {
+            RegistryParams::try_from(registry.as_str()).map_err(|e| {
+                Error::Invalid {
+                    message: format!("{e}(server:{name})"),
+                }
+            })?;
+        }
+        if let Some(not_found) = &self.not_found {
+            let action = NotFoundAction::from_str(not_found).map_err(|e| {
+                Error::Invalid {
+                    message: format!("{e}(server:{name})"),
+                }
+            })?;
+            if let NotFoundAction::Location(location) = action {
+                if !location_names.contains(&location) {
+                    return Err(Error::Invalid {
+                        message: format!(
+                            "not found location({location}) is not found(server:{name})"
+                        ),
+                    });
+                }
+            }
+        }
 
         Ok(())
     }
 }
-#[derive(Debug, Default, Deserialize, Clone, Serialize)]
+#[derive(Debug, Default, Deserialize, Clone, Serialize, ToSchema)]
 pub struct BasicConf {
     pub name: Option<String>,
     pub error_template: Option<String>,
+    pub error_template_dir: Option<String>,
     pub pid_file: Option<String>,
     pub upgrade_sock: Option<String>,
     pub user: Option<String>,
     pub group: Option<String>,
+    // the sandbox of filesystem access, only landlock is supported
+    pub sandbox: Option<String>,
+    // the paths which are allowed to read and write in sandbox
+    pub sandbox_paths: Option<Vec<String>>,
     pub threads: Option<usize>,
     pub work_stealing: Option<bool>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub grace_period: Option<Duration>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub graceful_shutdown_timeout: Option<Duration>,
     pub upstream_keepalive_pool_size: Option<usize>,
+    // the hosts of critical upstreams should be resolved at startup
+    pub critical_upstreams: Option<Vec<String>>,
     pub webhook: Option<String>,
     pub webhook_type: Option<String>,
     pub webhook_notifications: Option<Vec<String>>,
@@ -464,22 +928,221 @@ pub struct BasicConf {
     pub log_buffered_lines: Option<usize>,
     pub log_format_json: Option<bool>,
     pub sentry: Option<String>,
+    pub sentry_sample_rate: Option<f64>,
+    pub sentry_burst_threshold: Option<u64>,
+    pub sentry_scrub_headers: Option<Vec<String>>,
     pub pyroscope: Option<String>,
+    pub cluster: Option<String>,
+    // the proxies whose X-Forwarded-For is trusted to get the
+    // client ip of ban
+    pub trusted_proxies: Option<Vec<String>>,
+    pub xds_server: Option<String>,
+    pub xds_node_id: Option<String>,
+    pub xds_node_cluster: Option<String>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
     pub auto_restart_check_interval: Option<Duration>,
     pub cache_directory: Option<String>,
+    #[schema(value_type = Option<String>)]
     pub cache_max_size: Option<ByteSize>,
+    pub cache_snapshot: Option<String>,
+    pub cache_prefetch_hits: Option<u64>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub cache_prefetch_before: Option<Duration>,
+    pub cache_prefetch_concurrency: Option<usize>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub slow_request_threshold: Option<Duration>,
+    pub slow_request_log: Option<String>,
+    pub connection_log: Option<String>,
+    pub traffic_record: Option<String>,
+    pub traffic_record_sample_rate: Option<f64>,
+    #[schema(value_type = Option<String>)]
+    pub traffic_record_body_limit: Option<ByteSize>,
+    pub metrics_push: Option<String>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub metrics_push_interval: Option<Duration>,
+    pub metrics_push_labels: Option<Vec<String>>,
+    pub metrics_path_templates: Option<Vec<String>>,
+    pub metrics_max_series: Option<usize>,
+    pub metrics_latency_buckets: Option<Vec<f64>>,
+    pub metrics_exemplars: Option<bool>,
+    pub metering: Option<String>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub metering_interval: Option<Duration>,
+    pub metering_consumers: Option<Vec<String>>,
+    pub sink: Option<String>,
+    pub sink_batch_size: Option<usize>,
+    pub clickhouse: Option<String>,
+    pub clickhouse_columns: Option<Vec<String>>,
+    pub clickhouse_batch_size: Option<usize>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub clickhouse_flush_interval: Option<Duration>,
+    pub clickhouse_spool: Option<String>,
+    pub anomaly_detection: Option<bool>,
+    pub anomaly_sensitivity: Option<f64>,
+}
+
+impl BasicConf {
+    /// Validate the options of basic config.
+    fn validate(&self) -> Result<()> {
+        if let Some(url) = &self.metrics_push {
+            validate_metrics_push(
+                url,
+                &self.metrics_push_labels.clone().unwrap_or_default(),
+            )
+            .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        }
+        if let Some(templates) = &self.metrics_path_templates {
+            validate_metrics_path_templates(templates).map_err(|e| {
+                Error::Invalid {
+                    message: e.to_string(),
+                }
+            })?;
+        }
+        if let Some(buckets) = &self.metrics_latency_buckets {
+            validate_latency_buckets(buckets).map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        }
+        if let Some(target) = &self.metering {
+            validate_metering(target).map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        }
+        if let Some(url) = &self.sink {
+            validate_sink(url).map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        }
+        if let Some(url) = &self.clickhouse {
+            validate_clickhouse(
+                url,
+                &self.clickhouse_columns.clone().unwrap_or_default(),
+            )
+            .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        }
+        if let Some(sample_rate) = self.traffic_record_sample_rate {
+            if !(0.0..=1.0).contains(&sample_rate) {
+                return Err(Error::Invalid {
+                    message:
+                        "traffic record sample rate should be between 0 and 1"
+                            .to_string(),
+                });
+            }
+        }
+        if let Some(sandbox) = &self.sandbox {
+            validate_sandbox(sandbox).map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        }
+        if let Some(sensitivity) = self.anomaly_sensitivity {
+            if sensitivity <= 0.0 {
+                return Err(Error::Invalid {
+                    message: "anomaly sensitivity should be greater than 0"
+                        .to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Serialize, ToSchema)]
+pub struct ScheduleConf {
+    pub cron: String,
+    pub category: String,
+    pub name: Option<String>,
+    pub values: Option<Vec<String>>,
+    pub remark: Option<String>,
+}
+
+impl ScheduleConf {
+    /// Gets the values which override the fields of config,
+    /// each value is a toml key value pair, e.g. `upstream = "maintenance"`.
+    pub fn get_values(&self) -> Result<Map<String, Value>> {
+        let values = self.values.clone().unwrap_or_default().join("\n");
+        toml::from_str(&values).map_err(|e| Error::De { source: e })
+    }
+    /// Validate the options of schedule config.
+    fn validate(&self, name: &str) -> Result<()> {
+        validate_cron(&self.cron).map_err(|e| Error::Invalid {
+            message: format!("{e}(schedule:{name})"),
+        })?;
+        if ![
+            CATEGORY_BASIC,
+            CATEGORY_SERVER,
+            CATEGORY_LOCATION,
+            CATEGORY_UPSTREAM,
+            CATEGORY_PLUGIN,
+        ]
+        .contains(&self.category.as_str())
+        {
+            return Err(Error::Invalid {
+                message: format!(
+                    "category({}) is not supported(schedule:{name})",
+                    self.category
+                ),
+            });
+        }
+        if self.get_values()?.is_empty() {
+            return Err(Error::Invalid {
+                message: format!("values are required(schedule:{name})"),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Overrides the fields of config by values.
+fn override_config<T>(conf: &T, values: &Map<String, Value>) -> Result<T>
+where
+    T: Serialize + serde::de::DeserializeOwned,
+{
+    let mut value =
+        Value::try_from(conf).map_err(|e| Error::Ser { source: e })?;
+    if let Some(table) = value.as_table_mut() {
+        for (key, value) in values.iter() {
+            table.insert(key.to_string(), value.clone());
+        }
+    }
+    value.try_into().map_err(|e| Error::De { source: e })
+}
+
+// the basic config is loaded as table to expand the snippets, and it's
+// saved as struct to keep the order of fields if snippets are not used
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(untagged)]
+enum TomlBasic {
+    Table(Map<String, Value>),
+    Conf(Box<BasicConf>),
 }
 
 #[derive(Deserialize, Debug, Serialize)]
 struct TomlConfig {
-    basic: Option<BasicConf>,
+    basic: Option<TomlBasic>,
     servers: Option<Map<String, Value>>,
     upstreams: Option<Map<String, Value>>,
     locations: Option<Map<String, Value>>,
     plugins: Option<Map<String, Value>>,
     certificates: Option<Map<String, Value>>,
+    schedules: Option<Map<String, Value>>,
+    // the reusable snippets which are included by other configs
+    snippets: Option<Map<String, Value>>,
 }
 
 fn format_toml(value: &Value) -> String {
@@ -492,22 +1155,81 @@ fn format_toml(value: &Value) -> String {
 
 pub type PluginConf = Map<String, Value>;
 
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+// the plugin extends the params of other plugin
+const PLUGIN_EXTENDS: &str = "extends";
+
+/// Gets the name of plugin which is overridden by location.
+pub fn get_override_plugin_name(plugin: &str, location: &str) -> String {
+    format!("{plugin}@{location}")
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, ToSchema)]
 pub struct PingapConf {
     pub basic: BasicConf,
     pub upstreams: HashMap<String, UpstreamConf>,
     pub locations: HashMap<String, LocationConf>,
     pub servers: HashMap<String, ServerConf>,
+    #[schema(value_type = HashMap<String, Object>)]
     pub plugins: HashMap<String, PluginConf>,
     pub certificates: HashMap<String, CertificateConf>,
+    pub schedules: HashMap<String, ScheduleConf>,
+    // the reusable snippets and anchors of configs
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    #[schema(value_type = HashMap<String, Object>)]
+    pub snippets: Map<String, Value>,
+    // the snippets and anchors used by configs, the key is
+    // `{category}:{name}`, they are written back when saving
+    #[serde(skip)]
+    pub templates: HashMap<String, ConfigTemplate>,
 }
 
 impl PingapConf {
-    pub fn get_toml(&self, category: &str) -> Result<(String, String)> {
+    /// Converts the config to toml struct, the values of snippets are
+    /// collapsed to `include` and anchors.
+    fn to_toml_config(&self) -> Result<TomlConfig> {
         let ping_conf = toml::to_string_pretty(self)
             .map_err(|e| Error::Ser { source: e })?;
         let mut data: TomlConfig =
             toml::from_str(&ping_conf).map_err(|e| Error::De { source: e })?;
+        let collapse = |target: String, value: &mut Map<String, Value>| {
+            if let Some(template) = self.templates.get(&target) {
+                collapse_includes(&self.snippets, template, value);
+            }
+        };
+        match data.basic.as_mut() {
+            Some(TomlBasic::Table(basic))
+                if self.templates.contains_key(CATEGORY_BASIC) =>
+            {
+                collapse(CATEGORY_BASIC.to_string(), basic);
+            },
+            Some(basic) => {
+                *basic = TomlBasic::Conf(Box::new(self.basic.clone()));
+            },
+            None => {},
+        }
+        for (category, values) in [
+            (CATEGORY_UPSTREAM, data.upstreams.as_mut()),
+            (CATEGORY_LOCATION, data.locations.as_mut()),
+            (CATEGORY_SERVER, data.servers.as_mut()),
+            (CATEGORY_PLUGIN, data.plugins.as_mut()),
+            (CATEGORY_CERTIFICATE, data.certificates.as_mut()),
+            (CATEGORY_SCHEDULE, data.schedules.as_mut()),
+        ] {
+            for (name, value) in values.into_iter().flatten() {
+                if let Some(value) = value.as_table_mut() {
+                    collapse(format!("{category}:{name}"), value);
+                }
+            }
+        }
+        Ok(data)
+    }
+    /// Converts the config to toml, the `include` and anchors are kept.
+    pub fn to_toml(&self) -> Result<String> {
+        let data = self.to_toml_config()?;
+        toml::to_string_pretty(&data).map_err(|e| Error::Ser { source: e })
+    }
+    pub fn get_toml(&self, category: &str) -> Result<(String, String)> {
+        let mut data = self.to_toml_config()?;
         let result = match category {
             CATEGORY_SERVER => {
                 let mut m = Map::new();
@@ -559,12 +1281,23 @@ impl PingapConf {
                     .map_err(|e| Error::Ser { source: e })?;
                 ("/certificates.toml".to_string(), value)
             },
+            CATEGORY_SCHEDULE => {
+                let mut m = Map::new();
+                let _ = m.insert(
+                    "schedules".to_string(),
+                    toml::Value::Table(data.schedules.unwrap_or_default()),
+                );
+                let value = toml::to_string_pretty(&m)
+                    .map_err(|e| Error::Ser { source: e })?;
+                ("/schedules.toml".to_string(), value)
+            },
             _ => {
                 data.servers = None;
                 data.locations = None;
                 data.upstreams = None;
                 data.plugins = None;
                 data.certificates = None;
+                data.schedules = None;
                 let value = toml::to_string_pretty(&data)
                     .map_err(|e| Error::Ser { source: e })?;
                 ("/basic.toml".to_string(), value)
@@ -585,40 +1318,82 @@ impl TryFrom<&[u8]> for PingapConf {
         .map_err(|e| Error::De { source: e })?;
 
         let mut conf = PingapConf {
-            basic: data.basic.unwrap_or_default(),
+            snippets: data.snippets.unwrap_or_default(),
             ..Default::default()
         };
-        for (name, value) in data.upstreams.unwrap_or_default() {
-            let upstream: UpstreamConf =
-                toml::from_str(format_toml(&value).as_str())
+        let snippets = conf.snippets.clone();
+        let mut templates = HashMap::new();
+        let mut expand_toml = |target: String, value: &Value| {
+            let (value, template) = expand_includes(&snippets, value, &target)?;
+            if template != ConfigTemplate::default() {
+                templates.insert(target, template);
+            }
+            Ok::<String, Error>(format_toml(&value))
+        };
+        match data.basic {
+            Some(TomlBasic::Table(basic)) => {
+                let basic = expand_toml(
+                    CATEGORY_BASIC.to_string(),
+                    &Value::Table(basic),
+                )?;
+                conf.basic = toml::from_str(basic.as_str())
                     .map_err(|e| Error::De { source: e })?;
+            },
+            Some(TomlBasic::Conf(basic)) => {
+                conf.basic = *basic;
+            },
+            None => {},
+        }
+        for (name, value) in data.upstreams.unwrap_or_default() {
+            let upstream: UpstreamConf = toml::from_str(
+                expand_toml(format!("{CATEGORY_UPSTREAM}:{name}"), &value)?
+                    .as_str(),
+            )
+            .map_err(|e| Error::De { source: e })?;
             conf.upstreams.insert(name, upstream);
         }
         for (name, value) in data.locations.unwrap_or_default() {
-            let location: LocationConf =
-                toml::from_str(format_toml(&value).as_str())
-                    .map_err(|e| Error::De { source: e })?;
+            let location: LocationConf = toml::from_str(
+                expand_toml(format!("{CATEGORY_LOCATION}:{name}"), &value)?
+                    .as_str(),
+            )
+            .map_err(|e| Error::De { source: e })?;
             conf.locations.insert(name, location);
         }
         for (name, value) in data.servers.unwrap_or_default() {
-            let server: ServerConf =
-                toml::from_str(format_toml(&value).as_str())
-                    .map_err(|e| Error::De { source: e })?;
+            let server: ServerConf = toml::from_str(
+                expand_toml(format!("{CATEGORY_SERVER}:{name}"), &value)?
+                    .as_str(),
+            )
+            .map_err(|e| Error::De { source: e })?;
             conf.servers.insert(name, server);
         }
         for (name, value) in data.plugins.unwrap_or_default() {
-            let plugin: PluginConf =
-                toml::from_str(format_toml(&value).as_str())
-                    .map_err(|e| Error::De { source: e })?;
+            let plugin: PluginConf = toml::from_str(
+                expand_toml(format!("{CATEGORY_PLUGIN}:{name}"), &value)?
+                    .as_str(),
+            )
+            .map_err(|e| Error::De { source: e })?;
             conf.plugins.insert(name, plugin);
         }
 
         for (name, value) in data.certificates.unwrap_or_default() {
-            let certificate: CertificateConf =
-                toml::from_str(format_toml(&value).as_str())
-                    .map_err(|e| Error::De { source: e })?;
+            let certificate: CertificateConf = toml::from_str(
+                expand_toml(format!("{CATEGORY_CERTIFICATE}:{name}"), &value)?
+                    .as_str(),
+            )
+            .map_err(|e| Error::De { source: e })?;
             conf.certificates.insert(name, certificate);
         }
+        for (name, value) in data.schedules.unwrap_or_default() {
+            let schedule: ScheduleConf = toml::from_str(
+                expand_toml(format!("{CATEGORY_SCHEDULE}:{name}"), &value)?
+                    .as_str(),
+            )
+            .map_err(|e| Error::De { source: e })?;
+            conf.schedules.insert(name, schedule);
+        }
+        conf.templates = templates;
 
         Ok(conf)
     }
@@ -634,11 +1409,13 @@ struct Description {
 impl PingapConf {
     /// Validate the options of pinggap config.
     pub fn validate(&self) -> Result<()> {
+        self.basic.validate()?;
         let mut upstream_names = vec![];
         for (name, upstream) in self.upstreams.iter() {
             upstream.validate(name)?;
             upstream_names.push(name.to_string());
         }
+        self.validate_blue_green()?;
         let mut location_names = vec![];
         for (name, location) in self.locations.iter() {
             location.validate(name, &upstream_names)?;
@@ -656,18 +1433,398 @@ impl PingapConf {
             }
             server.validate(name, &location_names)?;
         }
-        for (name, plugin) in self.plugins.iter() {
-            parse_plugins(vec![(name.to_string(), plugin.clone())]).map_err(
-                |e| Error::Invalid {
+        self.validate_namespaces()?;
+        // the location is shared by servers, so it can only inherit
+        // the same defaults from them
+        for name in location_names.iter() {
+            let mut servers = self.servers.iter().filter(|(_, server)| {
+                server.locations.iter().flatten().any(|item| item == name)
+            });
+            let Some((first_name, first)) = servers.next() else {
+                continue;
+            };
+            if let Some((other_name, _)) = servers
+                .find(|(_, server)| !first.has_same_location_defaults(server))
+            {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "location({name}) is used by servers({first_name}, {other_name}) with different location defaults"
+                    ),
+                });
+            }
+        }
+        for (name, plugin) in self.resolve_plugins()? {
+            parse_plugins(vec![(name, plugin)]).map_err(|e| {
+                Error::Invalid {
                     message: e.to_string(),
-                },
-            )?;
+                }
+            })?;
         }
         for (_, certificate) in self.certificates.iter() {
             certificate.validate()?;
         }
+        for (name, schedule) in self.schedules.iter() {
+            schedule.validate(name)?;
+            // the target of schedule should exist and the values
+            // should be valid for it
+            self.clone().apply_schedule(schedule).map_err(|e| {
+                Error::Invalid {
+                    message: format!("{e}(schedule:{name})"),
+                }
+            })?;
+        }
         Ok(())
     }
+    /// Validates the green upstream of blue green deployment, it should
+    /// exist and can't be another blue green upstream.
+    fn validate_blue_green(&self) -> Result<()> {
+        for (name, upstream) in self.upstreams.iter() {
+            let Some(green) = &upstream.green_upstream else {
+                continue;
+            };
+            let Some(green_upstream) = self.upstreams.get(green) else {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "green upstream({green}) is not found(upstream:{name})"
+                    ),
+                });
+            };
+            if green == name || green_upstream.green_upstream.is_some() {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "green upstream({green}) can't be blue green upstream(upstream:{name})"
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+    /// Validates the references between namespaces, the config of
+    /// namespace can only use the config of same namespace or global.
+    fn validate_namespaces(&self) -> Result<()> {
+        let check = |category: &str,
+                     name: &str,
+                     namespace: &Option<String>,
+                     ref_category: &str,
+                     ref_name: &str|
+         -> Result<()> {
+            let namespace = namespace.clone().unwrap_or_default();
+            if namespace.is_empty() {
+                return Ok(());
+            }
+            let ref_namespace = self
+                .get_namespace(ref_category, ref_name)
+                .unwrap_or_default();
+            if ref_namespace.is_empty() || ref_namespace == namespace {
+                return Ok(());
+            }
+            Err(Error::Invalid {
+                message: format!(
+                    "{category}({name}) of namespace({namespace}) can't use {ref_category}({ref_name}) of namespace({ref_namespace})"
+                ),
+            })
+        };
+        for (name, location) in self.locations.iter() {
+            for upstream in location
+                .upstream
+                .iter()
+                .chain(location.fallback_upstreams.iter().flatten())
+            {
+                check(
+                    CATEGORY_LOCATION,
+                    name,
+                    &location.namespace,
+                    CATEGORY_UPSTREAM,
+                    upstream,
+                )?;
+            }
+        }
+        for (name, server) in self.servers.iter() {
+            for location in server.locations.iter().flatten() {
+                check(
+                    CATEGORY_SERVER,
+                    name,
+                    &server.namespace,
+                    CATEGORY_LOCATION,
+                    location,
+                )?;
+            }
+        }
+        Ok(())
+    }
+    /// Gets the namespace of config, `None` means the config is not found,
+    /// and empty value means it's global.
+    pub fn get_namespace(&self, category: &str, name: &str) -> Option<String> {
+        let namespace = match category {
+            CATEGORY_UPSTREAM => &self.upstreams.get(name)?.namespace,
+            CATEGORY_LOCATION => &self.locations.get(name)?.namespace,
+            CATEGORY_SERVER => &self.servers.get(name)?.namespace,
+            _ => return Some("".to_string()),
+        };
+        Some(namespace.clone().unwrap_or_default())
+    }
+    /// Gets the config of namespace, only the servers, locations and
+    /// upstreams of the namespace are kept.
+    pub fn filter_namespace(&self, namespace: &str) -> PingapConf {
+        let is_matched = |value: &Option<String>| {
+            value.as_deref().unwrap_or_default() == namespace
+        };
+        PingapConf {
+            upstreams: self
+                .upstreams
+                .iter()
+                .filter(|(_, item)| is_matched(&item.namespace))
+                .map(|(name, item)| (name.to_string(), item.clone()))
+                .collect(),
+            locations: self
+                .locations
+                .iter()
+                .filter(|(_, item)| is_matched(&item.namespace))
+                .map(|(name, item)| (name.to_string(), item.clone()))
+                .collect(),
+            servers: self
+                .servers
+                .iter()
+                .filter(|(_, item)| is_matched(&item.namespace))
+                .map(|(name, item)| (name.to_string(), item.clone()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+    /// Gets the warnings of config, they don't block the config
+    /// but may be mistakes:
+    /// 1. The upstream is not used by any location.
+    /// 2. The location is not used by any server.
+    /// 3. The locations of server have the same host, path and weight.
+    /// 4. The step of plugin is invalid, it falls back to request step.
+    /// 5. The tls cert and key of server or certificate don't match.
+    pub fn get_warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        for (name, _) in self.upstreams.iter() {
+            let used = self.locations.values().any(|location| {
+                location.upstream.as_ref() == Some(name)
+                    || location
+                        .fallback_upstreams
+                        .iter()
+                        .flatten()
+                        .any(|item| item == name)
+            });
+            if !used {
+                warnings.push(format!(
+                    "upstream({name}) is not used by any location"
+                ));
+            }
+        }
+        for (name, _) in self.locations.iter() {
+            let used = self.servers.values().any(|server| {
+                server.locations.iter().flatten().any(|item| item == name)
+            });
+            if !used {
+                warnings.push(format!(
+                    "location({name}) is not used by any server"
+                ));
+            }
+        }
+        for (server_name, server) in self.servers.iter() {
+            let locations = server.locations.clone().unwrap_or_default();
+            for (index, name) in locations.iter().enumerate() {
+                let Some(location) = self.locations.get(name) else {
+                    continue;
+                };
+                for other_name in locations[index + 1..].iter() {
+                    let Some(other) = self.locations.get(other_name) else {
+                        continue;
+                    };
+                    if location.host.clone().unwrap_or_default()
+                        == other.host.clone().unwrap_or_default()
+                        && location.path.clone().unwrap_or_default()
+                            == other.path.clone().unwrap_or_default()
+                        && location.get_weight() == other.get_weight()
+                    {
+                        warnings.push(format!(
+                            "location({name}) and location({other_name}) have the same host, path and weight(server:{server_name})"
+                        ));
+                    }
+                }
+            }
+        }
+        for (name, plugin) in self.resolve_plugins().unwrap_or_default() {
+            let step = plugin
+                .get("step")
+                .and_then(|value| value.as_str())
+                .unwrap_or_default();
+            if !step.is_empty() && PluginStep::from_str(step).is_err() {
+                warnings.push(format!(
+                    "step({step}) of plugin({name}) is invalid, request step is used"
+                ));
+            }
+        }
+        let tls_pairs = self
+            .servers
+            .iter()
+            .map(|(name, item)| {
+                (format!("server({name})"), &item.tls_cert, &item.tls_key)
+            })
+            .chain(self.certificates.iter().map(|(name, item)| {
+                (
+                    format!("certificate({name})"),
+                    &item.tls_cert,
+                    &item.tls_key,
+                )
+            }));
+        for (name, cert, key) in tls_pairs {
+            let (Some(cert), Some(key)) = (cert, key) else {
+                continue;
+            };
+            if is_certificate_key_matched(cert, key) == Some(false) {
+                warnings
+                    .push(format!("tls cert and key of {name} don't match"));
+            }
+        }
+        warnings.sort();
+        warnings
+    }
+    /// Resolves the plugin config which extends other plugin,
+    /// the params of `extends` plugin are used as default values.
+    fn resolve_plugin(&self, name: &str) -> Result<PluginConf> {
+        let mut chain = vec![name.to_string()];
+        let mut conf = PluginConf::new();
+        let mut current = name.to_string();
+        loop {
+            let Some(plugin) = self.plugins.get(&current) else {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "extends plugin({current}) is not found(plugin:{name})"
+                    ),
+                });
+            };
+            for (key, value) in plugin.iter() {
+                if !conf.contains_key(key) {
+                    conf.insert(key.to_string(), value.clone());
+                }
+            }
+            let Some(extends) =
+                plugin.get(PLUGIN_EXTENDS).and_then(|v| v.as_str())
+            else {
+                break;
+            };
+            if chain.iter().any(|item| item == extends) {
+                chain.push(extends.to_string());
+                return Err(Error::Invalid {
+                    message: format!(
+                        "plugin extends is cyclic: {}",
+                        chain.join(" -> ")
+                    ),
+                });
+            }
+            chain.push(extends.to_string());
+            current = extends.to_string();
+        }
+        conf.remove(PLUGIN_EXTENDS);
+        Ok(conf)
+    }
+    /// Resolves all plugins of config, includes the plugins extend
+    /// other plugin and the plugins overridden by locations, the name of
+    /// overridden plugin is `{plugin}@{location}`.
+    /// Gets the category of plugin, including the builtin plugins.
+    fn get_plugin_category(&self, name: &str) -> Option<String> {
+        let plugin = if self.plugins.contains_key(name) {
+            self.resolve_plugin(name).ok()?
+        } else {
+            get_builtin_proxy_plugins()
+                .into_iter()
+                .find(|(item, _)| item == name)?
+                .1
+        };
+        plugin
+            .get("category")
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    }
+    /// Gets the configs of locations with the defaults of servers inherited,
+    /// the value of location overrides the default one, and the default
+    /// plugin is skipped if the location has the plugin of same category.
+    pub fn get_inherited_locations(&self) -> HashMap<String, LocationConf> {
+        let mut locations = self.locations.clone();
+        for server in self.servers.values() {
+            for name in server.locations.iter().flatten() {
+                let Some(location) = locations.get_mut(name) else {
+                    continue;
+                };
+                if location.client_max_body_size.is_none() {
+                    location.client_max_body_size =
+                        server.location_client_max_body_size;
+                }
+                if location.proxy_connect_timeout.is_none() {
+                    location.proxy_connect_timeout =
+                        server.location_proxy_connect_timeout;
+                }
+                if location.proxy_read_timeout.is_none() {
+                    location.proxy_read_timeout =
+                        server.location_proxy_read_timeout;
+                }
+                if location.proxy_write_timeout.is_none() {
+                    location.proxy_write_timeout =
+                        server.location_proxy_write_timeout;
+                }
+                let Some(default_plugins) = &server.location_plugins else {
+                    continue;
+                };
+                let plugins = location.plugins.clone().unwrap_or_default();
+                let categories: Vec<String> = plugins
+                    .iter()
+                    .filter_map(|plugin| self.get_plugin_category(plugin))
+                    .collect();
+                let mut inherited = vec![];
+                for plugin in default_plugins.iter() {
+                    let overridden = plugins.contains(plugin)
+                        || self.get_plugin_category(plugin).is_some_and(
+                            |category| categories.contains(&category),
+                        );
+                    if !overridden {
+                        inherited.push(plugin.to_string());
+                    }
+                }
+                if !inherited.is_empty() {
+                    inherited.extend(plugins);
+                    location.plugins = Some(inherited);
+                }
+            }
+        }
+        locations
+    }
+    pub fn resolve_plugins(&self) -> Result<Vec<(String, PluginConf)>> {
+        let mut plugins = HashMap::new();
+        for name in self.plugins.keys() {
+            plugins.insert(name.to_string(), self.resolve_plugin(name)?);
+        }
+        let mut result = vec![];
+        for (location, conf) in self.locations.iter() {
+            for (name, params) in conf.plugin_overrides.iter().flatten() {
+                let Some(plugin) = plugins.get(name) else {
+                    return Err(Error::Invalid {
+                        message: format!(
+                            "override plugin({name}) is not found(location:{location})"
+                        ),
+                    });
+                };
+                let mut plugin = plugin.clone();
+                for (key, value) in params.iter() {
+                    if key == "category" && plugin.get(key) != Some(value) {
+                        return Err(Error::Invalid {
+                            message: format!(
+                                "category of plugin can not be overridden(location:{location})"
+                            ),
+                        });
+                    }
+                    plugin.insert(key.to_string(), value.clone());
+                }
+                result.push((get_override_plugin_name(name, location), plugin));
+            }
+        }
+        result.extend(plugins);
+        Ok(result)
+    }
     /// Generate the content hash of config.
     pub fn hash(&self) -> Result<String> {
         let mut lines = vec![];
@@ -731,10 +1888,53 @@ impl PingapConf {
             CATEGORY_CERTIFICATE => {
                 self.certificates.remove(name);
             },
+            CATEGORY_SCHEDULE => {
+                self.schedules.remove(name);
+            },
             _ => {},
         };
         Ok(())
     }
+    /// Applies the values of schedule to its target config.
+    pub fn apply_schedule(&mut self, schedule: &ScheduleConf) -> Result<()> {
+        let values = schedule.get_values()?;
+        let category = &schedule.category;
+        let name = schedule.name.clone().unwrap_or_default();
+        let not_found = || Error::Invalid {
+            message: format!("{category}({name}) is not found"),
+        };
+        match category.as_str() {
+            CATEGORY_BASIC => {
+                self.basic = override_config(&self.basic, &values)?;
+            },
+            CATEGORY_SERVER => {
+                let server =
+                    self.servers.get_mut(&name).ok_or_else(not_found)?;
+                *server = override_config(server, &values)?;
+            },
+            CATEGORY_LOCATION => {
+                let location =
+                    self.locations.get_mut(&name).ok_or_else(not_found)?;
+                *location = override_config(location, &values)?;
+            },
+            CATEGORY_UPSTREAM => {
+                let upstream =
+                    self.upstreams.get_mut(&name).ok_or_else(not_found)?;
+                *upstream = override_config(upstream, &values)?;
+            },
+            CATEGORY_PLUGIN => {
+                let plugin =
+                    self.plugins.get_mut(&name).ok_or_else(not_found)?;
+                plugin.extend(values);
+            },
+            _ => {
+                return Err(Error::Invalid {
+                    message: format!("category({category}) is not supported"),
+                });
+            },
+        };
+        Ok(())
+    }
     fn descriptions(&self) -> Vec<Description> {
         let mut value = self.clone();
         let mut descriptions = vec![];
@@ -773,11 +1973,19 @@ impl PingapConf {
                 data: toml::to_string_pretty(data).unwrap_or_default(),
             });
         }
+        for (name, data) in value.schedules.iter() {
+            descriptions.push(Description {
+                category: CATEGORY_SCHEDULE.to_string(),
+                name: format!("schedule:{name}"),
+                data: toml::to_string_pretty(data).unwrap_or_default(),
+            });
+        }
         value.servers = HashMap::new();
         value.locations = HashMap::new();
         value.upstreams = HashMap::new();
         value.plugins = HashMap::new();
         value.certificates = HashMap::new();
+        value.schedules = HashMap::new();
         descriptions.push(Description {
             category: CATEGORY_BASIC.to_string(),
             name: CATEGORY_BASIC.to_string(),
@@ -921,11 +2129,16 @@ mod tests {
         BasicConf,
     };
     use super::{
-        LocationConf, PingapConf, PluginCategory, ServerConf, UpstreamConf,
-        CATEGORY_LOCATION, CATEGORY_PLUGIN, CATEGORY_SERVER, CATEGORY_UPSTREAM,
+        LocationConf, NotFoundAction, PingapConf, PluginCategory, PluginConf,
+        ScheduleConf, ServerConf, UpstreamConf, CATEGORY_LOCATION,
+        CATEGORY_PLUGIN, CATEGORY_SERVER, CATEGORY_UPSTREAM,
     };
+    use bytesize::ByteSize;
     use pretty_assertions::assert_eq;
     use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use toml::Value;
 
     #[test]
     fn test_app_name() {
@@ -998,6 +2211,170 @@ mod tests {
         conf.health_check = Some("http://github.com/".to_string());
         let result = conf.validate("test");
         assert_eq!(true, result.is_ok());
+
+        conf.addrs = vec!["::1".to_string(), "[::1]:8080".to_string()];
+        let result = conf.validate("test");
+        assert_eq!(true, result.is_ok());
+
+        conf.ipv4_only = Some(true);
+        conf.prefer_ipv6 = Some(true);
+        let result = conf.validate("test");
+        assert_eq!(true, result.is_err());
+        assert_eq!(
+            "Invalid error ipv4_only, ipv6_only and prefer_ipv6 are exclusive(upstream:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.ipv4_only = None;
+        conf.ipv6_only = None;
+        conf.prefer_ipv6 = None;
+        conf.lazy_resolve = Some(true);
+        conf.discovery = Some("dns".to_string());
+        let result = conf.validate("test");
+        assert_eq!(
+            "Invalid error lazy resolve can't be used with discovery(upstream:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.lazy_resolve = None;
+        conf.dns_failure_policy = Some("static".to_string());
+        let result = conf.validate("test");
+        assert_eq!(
+            "Invalid error fallback addrs of static policy is empty(upstream:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.dns_failure_policy = Some("keep".to_string());
+        conf.dns_max_stale = Some(Duration::from_secs(300));
+        let result = conf.validate("test");
+        assert_eq!(true, result.is_ok());
+
+        conf.dns_failure_policy = None;
+        conf.dns_max_stale = None;
+        conf.discovery = None;
+        conf.alpn = Some("h3".to_string());
+        let result = conf.validate("test");
+        assert_eq!(
+            "Invalid error alpn should be h1, h2 or h2h1(upstream:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.alpn = Some("h2h1".to_string());
+        conf.max_h2_streams = Some(0);
+        let result = conf.validate("test");
+        assert_eq!(
+            "Invalid error max h2 streams should be greater than 0(upstream:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.max_h2_streams = Some(100);
+        let result = conf.validate("test");
+        assert_eq!(true, result.is_ok());
+
+        conf.wsgi_protocol = Some("ajp".to_string());
+        let result = conf.validate("test");
+        assert_eq!(
+            "Invalid error wsgi protocol should be uwsgi or scgi(upstream:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.wsgi_protocol = Some("scgi".to_string());
+        conf.fastcgi = Some(true);
+        conf.fastcgi_root = Some("/var/www/html".to_string());
+        let result = conf.validate("test");
+        assert_eq!(
+            "Invalid error wsgi can't be used with fastcgi or s3 origin(upstream:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.fastcgi = None;
+        let result = conf.validate("test");
+        assert_eq!(true, result.is_ok());
+
+        conf.reserved_concurrency = Some(10);
+        let result = conf.validate("test");
+        assert_eq!(
+            "Invalid error reserved concurrency can't be greater than max concurrency(upstream:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.max_concurrency = Some(100);
+        let result = conf.validate("test");
+        assert_eq!(true, result.is_ok());
+    }
+
+    #[test]
+    fn test_basic_conf() {
+        let mut conf = BasicConf {
+            metrics_push: Some("statsd://127.0.0.1:8125".to_string()),
+            metrics_push_labels: Some(vec!["env=prod".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(true, conf.validate().is_ok());
+
+        conf.metrics_push_labels = Some(vec!["env".to_string()]);
+        assert_eq!(
+            "Invalid error Invalid error label(env) is invalid",
+            conf.validate().expect_err("").to_string()
+        );
+
+        conf.metrics_push_labels = None;
+        conf.metrics_path_templates = Some(vec!["users/:id".to_string()]);
+        assert_eq!(
+            "Invalid error Invalid error path template(users/:id) should start with /",
+            conf.validate().expect_err("").to_string()
+        );
+
+        conf.metrics_path_templates = None;
+        conf.metrics_latency_buckets = Some(vec![100.0, 10.0]);
+        assert_eq!(
+            "Invalid error Invalid error latency buckets should be positive and increasing",
+            conf.validate().expect_err("").to_string()
+        );
+
+        conf.metrics_latency_buckets = None;
+        conf.metrics_push = Some("tcp://127.0.0.1:8125".to_string());
+        assert_eq!(
+            "Invalid error Invalid error metrics push scheme(tcp) is not supported",
+            conf.validate().expect_err("").to_string()
+        );
+
+        conf.metrics_push = None;
+        conf.metering = Some("kafka://127.0.0.1:9092".to_string());
+        assert_eq!(
+            "Invalid error Invalid error metering scheme(kafka) is not supported",
+            conf.validate().expect_err("").to_string()
+        );
+
+        conf.metering = None;
+        conf.sink = Some("kafka://127.0.0.1:9092".to_string());
+        assert_eq!(
+            "Invalid error Invalid error sink scheme(kafka) is not supported",
+            conf.validate().expect_err("").to_string()
+        );
+
+        conf.sink = None;
+        conf.clickhouse = Some("http://127.0.0.1:8123/logs.access".to_string());
+        conf.clickhouse_columns = Some(vec!["status".to_string()]);
+        assert_eq!(
+            "Invalid error Invalid error column(status) should be name:format",
+            conf.validate().expect_err("").to_string()
+        );
+
+        conf.clickhouse = None;
+        conf.clickhouse_columns = None;
+        conf.traffic_record_sample_rate = Some(1.5);
+        assert_eq!(
+            "Invalid error traffic record sample rate should be between 0 and 1",
+            conf.validate().expect_err("").to_string()
+        );
+
+        conf.traffic_record_sample_rate = None;
+        conf.sandbox = Some("seccomp".to_string());
+        assert_eq!(
+            "Invalid error sandbox(seccomp) is not supported",
+            conf.validate().expect_err("").to_string()
+        );
     }
 
     #[test]
@@ -1034,6 +2411,28 @@ mod tests {
         let result = conf.validate("lo", &upstream_names);
         assert_eq!(true, result.is_ok());
 
+        conf.priority = Some(10);
+        let result = conf.validate("lo", &upstream_names);
+        assert_eq!(
+            "Invalid error priority should be between 0 and 9(location:lo)",
+            result.expect_err("").to_string()
+        );
+
+        conf.priority = Some(9);
+        let result = conf.validate("lo", &upstream_names);
+        assert_eq!(true, result.is_ok());
+
+        conf.proxy_request_buffer_size = Some(ByteSize::mb(1));
+        let result = conf.validate("lo", &upstream_names);
+        assert_eq!(
+            "Invalid error proxy request buffer size should be less than or equal to 64KB(location:lo)",
+            result.expect_err("").to_string()
+        );
+
+        conf.proxy_request_buffer_size = Some(ByteSize::kb(16));
+        let result = conf.validate("lo", &upstream_names);
+        assert_eq!(true, result.is_ok());
+
         conf.rewrite = Some(r"foo(bar".to_string());
         let result = conf.validate("lo", &upstream_names);
         assert_eq!(true, result.is_err());
@@ -1048,6 +2447,87 @@ mod tests {
         conf.rewrite = Some(r"^/api /".to_string());
         let result = conf.validate("lo", &upstream_names);
         assert_eq!(true, result.is_ok());
+
+        conf.plugins = Some(vec!["stats".to_string(), "stats".to_string()]);
+        let result = conf.validate("lo", &upstream_names);
+        assert_eq!(
+            "Invalid error plugin(stats) is duplicated(location:lo)",
+            result.expect_err("").to_string()
+        );
+
+        conf.plugins = Some(vec!["stats".to_string()]);
+        conf.plugin_overrides =
+            Some(HashMap::from([("limit".to_string(), PluginConf::new())]));
+        let result = conf.validate("lo", &upstream_names);
+        assert_eq!(
+            "Invalid error override plugin(limit) is not used(location:lo)",
+            result.expect_err("").to_string()
+        );
+    }
+
+    #[test]
+    fn test_resolve_plugins() {
+        let mut conf = PingapConf::try_from(
+            r###"
+[plugins.baseLimit]
+category = "limit"
+type = "rate"
+tag = "ip"
+max = 10
+interval = "1s"
+
+[plugins.apiLimit]
+extends = "baseLimit"
+max = 100
+
+[locations.lo]
+plugins = ["apiLimit"]
+
+[locations.lo.plugin_overrides.apiLimit]
+max = 20
+"###
+            .as_bytes(),
+        )
+        .unwrap();
+        let plugins: HashMap<_, _> =
+            conf.resolve_plugins().unwrap().into_iter().collect();
+        assert_eq!(3, plugins.len());
+        let api_limit = plugins.get("apiLimit").unwrap();
+        assert_eq!("limit", api_limit["category"].as_str().unwrap());
+        assert_eq!(100, api_limit["max"].as_integer().unwrap());
+        assert_eq!("ip", api_limit["tag"].as_str().unwrap());
+        assert_eq!(true, api_limit.get("extends").is_none());
+        let lo_limit = plugins.get("apiLimit@lo").unwrap();
+        assert_eq!(20, lo_limit["max"].as_integer().unwrap());
+        assert_eq!("1s", lo_limit["interval"].as_str().unwrap());
+
+        conf.plugins
+            .get_mut("baseLimit")
+            .unwrap()
+            .insert("extends".to_string(), Value::String("apiLimit".into()));
+        assert_eq!(
+            true,
+            conf.resolve_plugins()
+                .expect_err("")
+                .to_string()
+                .contains("plugin extends is cyclic")
+        );
+
+        conf.plugins.get_mut("baseLimit").unwrap().remove("extends");
+        conf.locations
+            .get_mut("lo")
+            .unwrap()
+            .plugin_overrides
+            .as_mut()
+            .unwrap()
+            .insert(
+                "apiLimit".to_string(),
+                toml::from_str(r#"category = "stats""#).unwrap(),
+            );
+        assert_eq!(
+            "Invalid error category of plugin can not be overridden(location:lo)",
+            conf.resolve_plugins().expect_err("").to_string()
+        );
     }
 
     #[test]
@@ -1096,6 +2576,38 @@ mod tests {
         );
 
         conf.locations = Some(vec!["lo".to_string()]);
+        conf.https_redirect = Some(true);
+        let result = conf.validate("test", &location_names);
+        assert_eq!(true, result.is_err());
+        assert_eq!(
+            "Invalid error https redirect is only for tls server(server:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.https_redirect = None;
+        conf.registry = Some("zookeeper://127.0.0.1:2181".to_string());
+        let result = conf.validate("test", &location_names);
+        assert_eq!(
+            "Invalid error registry zookeeper is not supported, only consul is supported(server:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.registry = None;
+        conf.not_found = Some("location:lo1".to_string());
+        let result = conf.validate("test", &location_names);
+        assert_eq!(
+            "Invalid error not found location(lo1) is not found(server:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.not_found = Some("rewrite:/".to_string());
+        let result = conf.validate("test", &location_names);
+        assert_eq!(
+            "Invalid error Invalid error not found(rewrite:/) should be 404, redirect:url or location:name(server:test)",
+            result.expect_err("").to_string()
+        );
+
+        conf.not_found = None;
         conf.tls_key = Some("ab".to_string());
         let result = conf.validate("test", &location_names);
         assert_eq!(true, result.is_err());
@@ -1103,6 +2615,326 @@ mod tests {
             "Base64 decode error Invalid padding",
             result.expect_err("").to_string()
         );
+
+        conf.tls_key = Some("/tmp/pingap-not-exists.key".to_string());
+        let result = conf.validate("test", &location_names);
+        assert_eq!(
+            "Io error No such file or directory (os error 2), /tmp/pingap-not-exists.key",
+            result.expect_err("").to_string()
+        );
+    }
+
+    #[test]
+    fn test_not_found_action() {
+        assert_eq!(NotFoundAction::Respond, "".parse().unwrap());
+        assert_eq!(NotFoundAction::Respond, "404".parse().unwrap());
+        assert_eq!(
+            NotFoundAction::Redirect("https://pingap.io".to_string()),
+            "redirect:https://pingap.io/".parse().unwrap()
+        );
+        assert_eq!(
+            NotFoundAction::Location("catchAll".to_string()),
+            "location:catchAll".parse().unwrap()
+        );
+        assert_eq!(
+            true,
+            "redirect:pingap.io".parse::<NotFoundAction>().is_err()
+        );
+        assert_eq!(true, "location:".parse::<NotFoundAction>().is_err());
+    }
+
+    #[test]
+    fn test_pingap_conf_warnings() {
+        let mut conf = PingapConf::default();
+        conf.upstreams.insert(
+            "charts".to_string(),
+            UpstreamConf {
+                addrs: vec!["127.0.0.1:3000".to_string()],
+                ..Default::default()
+            },
+        );
+        conf.upstreams.insert(
+            "unused".to_string(),
+            UpstreamConf {
+                addrs: vec!["127.0.0.1:3001".to_string()],
+                ..Default::default()
+            },
+        );
+        for name in ["lo", "lo2", "orphan"] {
+            conf.locations.insert(
+                name.to_string(),
+                LocationConf {
+                    upstream: Some("charts".to_string()),
+                    path: Some("/api".to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        conf.servers.insert(
+            "test".to_string(),
+            ServerConf {
+                addr: "127.0.0.1:6188".to_string(),
+                locations: Some(vec!["lo".to_string(), "lo2".to_string()]),
+                ..Default::default()
+            },
+        );
+        conf.plugins.insert(
+            "stats".to_string(),
+            toml::from_str::<PluginConf>(
+                r###"
+category = "stats"
+path = "/stats"
+step = "upstream"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            r#"["location(lo) and location(lo2) have the same host, path and weight(server:test)", "location(orphan) is not used by any server", "step(upstream) of plugin(stats) is invalid, request step is used", "upstream(unused) is not used by any location"]"#,
+            format!("{:?}", conf.get_warnings())
+        );
+    }
+
+    #[test]
+    fn test_pingap_conf_namespace() {
+        let mut conf = PingapConf::default();
+        for (name, namespace) in [("global", None), ("charts", Some("team-a"))]
+        {
+            conf.upstreams.insert(
+                name.to_string(),
+                UpstreamConf {
+                    addrs: vec!["127.0.0.1:3000".to_string()],
+                    namespace: namespace.map(|item| item.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        conf.locations.insert(
+            "lo".to_string(),
+            LocationConf {
+                upstream: Some("charts".to_string()),
+                fallback_upstreams: Some(vec!["global".to_string()]),
+                namespace: Some("team-a".to_string()),
+                ..Default::default()
+            },
+        );
+        conf.servers.insert(
+            "test".to_string(),
+            ServerConf {
+                addr: "127.0.0.1:6188".to_string(),
+                locations: Some(vec!["lo".to_string()]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(true, conf.validate().is_ok());
+        assert_eq!(
+            Some("team-a".to_string()),
+            conf.get_namespace(CATEGORY_LOCATION, "lo")
+        );
+        assert_eq!(
+            Some("".to_string()),
+            conf.get_namespace(CATEGORY_SERVER, "test")
+        );
+        assert_eq!(None, conf.get_namespace(CATEGORY_UPSTREAM, "none"));
+
+        let team_a = conf.filter_namespace("team-a");
+        assert_eq!(
+            vec!["charts".to_string()],
+            team_a.upstreams.keys().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(1, team_a.locations.len());
+        assert_eq!(true, team_a.servers.is_empty());
+
+        conf.servers.get_mut("test").unwrap().namespace =
+            Some("team-b".to_string());
+        assert_eq!(
+            "Invalid error server(test) of namespace(team-b) can't use location(lo) of namespace(team-a)",
+            conf.validate().err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_pingap_conf_blue_green() {
+        let mut conf = PingapConf::default();
+        for name in ["charts-blue", "charts-green"] {
+            conf.upstreams.insert(
+                name.to_string(),
+                UpstreamConf {
+                    addrs: vec!["127.0.0.1:3000".to_string()],
+                    ..Default::default()
+                },
+            );
+        }
+        conf.upstreams
+            .get_mut("charts-blue")
+            .unwrap()
+            .green_upstream = Some("charts-canary".to_string());
+        assert_eq!(
+            "Invalid error green upstream(charts-canary) is not found(upstream:charts-blue)",
+            conf.validate().err().unwrap().to_string()
+        );
+
+        conf.upstreams
+            .get_mut("charts-blue")
+            .unwrap()
+            .green_upstream = Some("charts-blue".to_string());
+        assert_eq!(
+            "Invalid error green upstream(charts-blue) can't be blue green upstream(upstream:charts-blue)",
+            conf.validate().err().unwrap().to_string()
+        );
+
+        conf.upstreams
+            .get_mut("charts-blue")
+            .unwrap()
+            .green_upstream = Some("charts-green".to_string());
+        assert_eq!(true, conf.validate().is_ok());
+    }
+
+    #[test]
+    fn test_pingap_conf_schedule() {
+        let mut conf = PingapConf::default();
+        conf.upstreams.insert(
+            "charts".to_string(),
+            UpstreamConf {
+                addrs: vec!["127.0.0.1:3000".to_string()],
+                ..Default::default()
+            },
+        );
+        let mut schedule = ScheduleConf {
+            cron: "0 2 * *".to_string(),
+            category: CATEGORY_UPSTREAM.to_string(),
+            name: Some("charts".to_string()),
+            values: Some(vec![
+                r#"addrs = ["127.0.0.1:3000 10", "127.0.0.1:3001 1"]"#
+                    .to_string(),
+            ]),
+            ..Default::default()
+        };
+        conf.schedules
+            .insert("weights".to_string(), schedule.clone());
+        assert_eq!(
+            "Invalid error Invalid error cron(0 2 * *) should have five fields(schedule:weights)",
+            conf.validate().err().unwrap().to_string()
+        );
+
+        schedule.cron = "0 9-18 * * 1-5".to_string();
+        schedule.name = Some("diving".to_string());
+        conf.schedules
+            .insert("weights".to_string(), schedule.clone());
+        assert_eq!(
+            "Invalid error Invalid error upstream(diving) is not found(schedule:weights)",
+            conf.validate().err().unwrap().to_string()
+        );
+
+        schedule.name = Some("charts".to_string());
+        conf.schedules
+            .insert("weights".to_string(), schedule.clone());
+        assert_eq!(true, conf.validate().is_ok());
+
+        conf.apply_schedule(&schedule).unwrap();
+        assert_eq!(
+            r#"["127.0.0.1:3000 10", "127.0.0.1:3001 1"]"#,
+            format!("{:?}", conf.upstreams.get("charts").unwrap().addrs)
+        );
+    }
+
+    #[test]
+    fn test_inherited_locations() {
+        let mut conf = PingapConf::default();
+        conf.upstreams.insert(
+            "charts".to_string(),
+            UpstreamConf {
+                addrs: vec!["127.0.0.1:3000".to_string()],
+                ..Default::default()
+            },
+        );
+        conf.plugins.insert(
+            "stats".to_string(),
+            toml::from_str::<PluginConf>(
+                r###"
+category = "stats"
+path = "/stats"
+"###,
+            )
+            .unwrap(),
+        );
+        conf.plugins.insert(
+            "apiStats".to_string(),
+            toml::from_str::<PluginConf>(
+                r###"
+category = "stats"
+path = "/api/stats"
+"###,
+            )
+            .unwrap(),
+        );
+        conf.locations.insert(
+            "lo".to_string(),
+            LocationConf {
+                upstream: Some("charts".to_string()),
+                ..Default::default()
+            },
+        );
+        conf.locations.insert(
+            "api".to_string(),
+            LocationConf {
+                upstream: Some("charts".to_string()),
+                path: Some("/api".to_string()),
+                plugins: Some(vec!["apiStats".to_string()]),
+                client_max_body_size: Some(ByteSize::mb(1)),
+                ..Default::default()
+            },
+        );
+        conf.servers.insert(
+            "test".to_string(),
+            ServerConf {
+                addr: "127.0.0.1:6188".to_string(),
+                locations: Some(vec!["lo".to_string(), "api".to_string()]),
+                location_plugins: Some(vec![
+                    "pingap:compression".to_string(),
+                    "stats".to_string(),
+                ]),
+                location_client_max_body_size: Some(ByteSize::mb(10)),
+                location_proxy_read_timeout: Some(Duration::from_secs(30)),
+                ..Default::default()
+            },
+        );
+        conf.validate().unwrap();
+
+        let locations = conf.get_inherited_locations();
+        let lo = locations.get("lo").unwrap();
+        assert_eq!(
+            r#"Some(["pingap:compression", "stats"])"#,
+            format!("{:?}", lo.plugins)
+        );
+        assert_eq!(Some(ByteSize::mb(10)), lo.client_max_body_size);
+        assert_eq!(Some(Duration::from_secs(30)), lo.proxy_read_timeout);
+        assert_eq!(None, lo.proxy_connect_timeout);
+
+        // the plugin of same category and the value of location
+        // override the defaults
+        let api = locations.get("api").unwrap();
+        assert_eq!(
+            r#"Some(["pingap:compression", "apiStats"])"#,
+            format!("{:?}", api.plugins)
+        );
+        assert_eq!(Some(ByteSize::mb(1)), api.client_max_body_size);
+
+        conf.servers.insert(
+            "other".to_string(),
+            ServerConf {
+                addr: "127.0.0.1:6189".to_string(),
+                locations: Some(vec!["lo".to_string()]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            true,
+            conf.validate()
+                .unwrap_err()
+                .to_string()
+                .contains("with different location defaults")
+        );
     }
 
     #[test]
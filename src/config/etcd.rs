@@ -15,7 +15,7 @@
 use super::PingapConf;
 use super::{ConfigStorage, Error, Result};
 use async_trait::async_trait;
-use etcd_client::{Client, ConnectOptions, GetOptions};
+use etcd_client::{Client, ConnectOptions, GetOptions, Txn, TxnOp};
 use humantime::parse_duration;
 use substring::Substring;
 
@@ -79,8 +79,12 @@ impl EtcdStorage {
             path,
         })
     }
+    /// Returns the key prefix of etcd storage.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
     /// Connect to etcd server.
-    async fn connect(&self) -> Result<Client> {
+    pub async fn connect(&self) -> Result<Client> {
         Client::connect(&self.addrs, Some(self.options.clone()))
             .await
             .map_err(|e| Error::Etcd { source: e })
@@ -122,6 +126,25 @@ impl ConfigStorage for EtcdStorage {
             .map_err(|e| Error::Etcd { source: e })?;
         Ok(())
     }
+    /// Save config of categories to etcd in one transaction.
+    async fn save_configs(
+        &self,
+        conf: &PingapConf,
+        categories: &[String],
+    ) -> Result<()> {
+        conf.validate()?;
+        let mut ops = vec![];
+        for category in categories.iter() {
+            let (path, toml_value) = conf.get_toml(category)?;
+            let key = format!("{}{path}", self.path);
+            ops.push(TxnOp::put(key, toml_value, None));
+        }
+        let mut c = self.connect().await?;
+        c.txn(Txn::new().and_then(ops))
+            .await
+            .map_err(|e| Error::Etcd { source: e })?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
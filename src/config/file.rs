@@ -94,8 +94,7 @@ impl ConfigStorage for FileStorage {
         let filepath = self.path.clone();
         conf.validate()?;
         if Path::new(&filepath).is_file() {
-            let ping_conf = toml::to_string_pretty(&conf)
-                .map_err(|e| Error::Ser { source: e })?;
+            let ping_conf = conf.to_toml()?;
             return fs::write(&filepath, ping_conf).await.map_err(|e| {
                 Error::Io {
                     source: e,
@@ -113,6 +112,53 @@ impl ConfigStorage for FileStorage {
                 file: filepath,
             })
     }
+    /// Save config of categories to file, the files are written to
+    /// temp files first and then renamed.
+    async fn save_configs(
+        &self,
+        conf: &PingapConf,
+        categories: &[String],
+    ) -> Result<()> {
+        let filepath = self.path.clone();
+        conf.validate()?;
+        let mut files = vec![];
+        if Path::new(&filepath).is_file() {
+            let ping_conf = conf.to_toml()?;
+            files.push((filepath, ping_conf));
+        } else {
+            for category in categories.iter() {
+                let (path, toml_value) = conf.get_toml(category)?;
+                files.push((format!("{filepath}{path}"), toml_value));
+            }
+        }
+        write_files(files).await
+    }
+}
+
+/// Writes the files as a whole, nothing is changed if any temp
+/// file fails to be written.
+async fn write_files(files: Vec<(String, String)>) -> Result<()> {
+    let mut temp_files = vec![];
+    for (file, data) in files {
+        // the temp file doesn't end with `.toml`, so it won't be loaded
+        let temp_file = format!("{file}.tmp");
+        if let Err(e) = fs::write(&temp_file, data).await {
+            for (temp_file, _) in temp_files.iter() {
+                let _ = fs::remove_file(temp_file).await;
+            }
+            return Err(Error::Io {
+                source: e,
+                file: temp_file,
+            });
+        }
+        temp_files.push((temp_file, file));
+    }
+    for (temp_file, file) in temp_files {
+        fs::rename(&temp_file, &file)
+            .await
+            .map_err(|e| Error::Io { source: e, file })?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -150,5 +196,120 @@ mod tests {
 
         let current_conf = storage.load_config(false).await.unwrap();
         assert_eq!(current_conf.hash().unwrap(), conf.hash().unwrap());
+
+        let path = format!("/tmp/{}", nanoid!(16));
+        tokio::fs::create_dir(&path).await.unwrap();
+        let storage = FileStorage::new(&path).unwrap();
+        storage
+            .save_configs(
+                &conf,
+                &[
+                    CATEGORY_BASIC.to_string(),
+                    CATEGORY_UPSTREAM.to_string(),
+                    CATEGORY_LOCATION.to_string(),
+                    CATEGORY_PLUGIN.to_string(),
+                    CATEGORY_SERVER.to_string(),
+                ],
+            )
+            .await
+            .unwrap();
+        let current_conf = storage.load_config(false).await.unwrap();
+        assert_eq!(current_conf.hash().unwrap(), conf.hash().unwrap());
+        let mut entries = tokio::fs::read_dir(&path).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            assert_eq!(
+                false,
+                entry.file_name().to_string_lossy().ends_with(".tmp")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_storage_snippets() {
+        let toml_data = r###"
+[basic]
+include = "common_basic"
+name = "pingap"
+
+[snippets]
+security_headers = ["X-Frame-Options:DENY"]
+
+[snippets.common_basic]
+log_level = "info"
+
+[snippets.timeout]
+proxy_read_timeout = "10s"
+proxy_set_headers = ["X-Service:pingap"]
+
+[upstreams.charts]
+addrs = ["127.0.0.1:5000"]
+
+[locations.lo]
+include = "timeout"
+upstream = "charts"
+proxy_add_headers = "*security_headers"
+"###;
+        let mut conf = PingapConf::try_from(toml_data.as_bytes()).unwrap();
+        assert_eq!("info", conf.basic.log_level.clone().unwrap());
+        let lo = conf.locations.get("lo").unwrap();
+        assert_eq!(10, lo.proxy_read_timeout.unwrap().as_secs());
+        assert_eq!(
+            vec!["X-Frame-Options:DENY".to_string()],
+            lo.proxy_add_headers.clone().unwrap()
+        );
+
+        let path = format!("/tmp/{}", nanoid!(16));
+        tokio::fs::create_dir(&path).await.unwrap();
+        let storage = FileStorage::new(&path).unwrap();
+        storage
+            .save_configs(
+                &conf,
+                &[
+                    CATEGORY_BASIC.to_string(),
+                    CATEGORY_UPSTREAM.to_string(),
+                    CATEGORY_LOCATION.to_string(),
+                ],
+            )
+            .await
+            .unwrap();
+        // the include and anchor are written back
+        let data = tokio::fs::read_to_string(format!("{path}/locations.toml"))
+            .await
+            .unwrap();
+        assert_eq!(true, data.contains(r#"include = ["timeout"]"#));
+        assert_eq!(
+            true,
+            data.contains(r#"proxy_add_headers = "*security_headers""#)
+        );
+        assert_eq!(false, data.contains("proxy_read_timeout"));
+
+        let current_conf = storage.load_config(false).await.unwrap();
+        assert_eq!(current_conf.hash().unwrap(), conf.hash().unwrap());
+        assert_eq!(conf.snippets, current_conf.snippets);
+        assert_eq!(conf.templates, current_conf.templates);
+
+        // the location uses the changed snippet
+        conf.snippets
+            .get_mut("timeout")
+            .and_then(|value| value.as_table_mut())
+            .unwrap()
+            .insert(
+                "proxy_read_timeout".to_string(),
+                toml::Value::String("20s".to_string()),
+            );
+        storage.save_config(&conf, CATEGORY_BASIC).await.unwrap();
+        let current_conf = storage.load_config(false).await.unwrap();
+        let lo = current_conf.locations.get("lo").unwrap();
+        assert_eq!(20, lo.proxy_read_timeout.unwrap().as_secs());
+
+        // the single config file
+        let file = format!("/tmp/{}.toml", nanoid!(16));
+        tokio::fs::write(&file, toml_data).await.unwrap();
+        let storage = FileStorage::new(&file).unwrap();
+        let conf = storage.load_config(false).await.unwrap();
+        storage.save_config(&conf, CATEGORY_LOCATION).await.unwrap();
+        let current_conf = storage.load_config(false).await.unwrap();
+        assert_eq!(current_conf.hash().unwrap(), conf.hash().unwrap());
+        assert_eq!(conf.templates, current_conf.templates);
     }
 }
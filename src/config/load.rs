@@ -31,6 +31,21 @@ pub async fn save_config(
     }
 }
 
+/// Save the config of categories to path as a whole.
+///
+/// Validate the config once before save.
+pub async fn save_configs(
+    path: &str,
+    conf: &PingapConf,
+    categories: &[String],
+) -> Result<()> {
+    if path.starts_with(ETCD_PROTOCOL) {
+        EtcdStorage::new(path)?.save_configs(conf, categories).await
+    } else {
+        FileStorage::new(path)?.save_configs(conf, categories).await
+    }
+}
+
 /// Load the config from path.
 pub async fn load_config(path: &str, admin: bool) -> Result<PingapConf> {
     if path.starts_with(ETCD_PROTOCOL) {
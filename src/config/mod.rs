@@ -15,10 +15,13 @@
 use async_trait::async_trait;
 use snafu::Snafu;
 
+mod bundle;
 mod common;
 mod etcd;
 mod file;
 mod load;
+mod preflight;
+mod template;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -67,9 +70,21 @@ pub trait ConfigStorage {
         conf: &PingapConf,
         category: &str,
     ) -> Result<()>;
+    /// Saves the config of categories as a whole, the config is validated
+    /// once and nothing is saved if any category fails.
+    async fn save_configs(
+        &self,
+        conf: &PingapConf,
+        categories: &[String],
+    ) -> Result<()>;
 }
 
+pub use bundle::{
+    export_bundle, import_bundle, strip_secrets, ConfigBundle, SECRETS_ENCRYPT,
+    SECRETS_PLAIN, SECRETS_STRIP,
+};
 pub use common::*;
 pub use etcd::{EtcdStorage, ETCD_PROTOCOL};
 pub use file::FileStorage;
-pub use load::{load_config, save_config};
+pub use load::{load_config, save_config, save_configs};
+pub use preflight::{run_preflight, PreflightParams, PreflightReport};
@@ -0,0 +1,270 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::PingapConf;
+use crate::util;
+use serde::Serialize;
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::Path;
+
+// the exit codes follow sysexits.h, so the operators can tell
+// what's wrong from the exit status
+const EXIT_CODE_CONFIG: i32 = 78;
+const EXIT_CODE_LISTENER: i32 = 69;
+const EXIT_CODE_CERTIFICATE: i32 = 65;
+const EXIT_CODE_CACHE_DIRECTORY: i32 = 73;
+const EXIT_CODE_DNS: i32 = 68;
+
+/// The category of preflight check.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreflightCategory {
+    Config,
+    Listener,
+    Certificate,
+    CacheDirectory,
+    Dns,
+}
+
+impl PreflightCategory {
+    fn exit_code(&self) -> i32 {
+        match self {
+            PreflightCategory::Config => EXIT_CODE_CONFIG,
+            PreflightCategory::Listener => EXIT_CODE_LISTENER,
+            PreflightCategory::Certificate => EXIT_CODE_CERTIFICATE,
+            PreflightCategory::CacheDirectory => EXIT_CODE_CACHE_DIRECTORY,
+            PreflightCategory::Dns => EXIT_CODE_DNS,
+        }
+    }
+    // the specific failure is preferred, e.g. the invalid certificate
+    // also makes the config invalid
+    fn priority(&self) -> usize {
+        match self {
+            PreflightCategory::Listener => 0,
+            PreflightCategory::Certificate => 1,
+            PreflightCategory::CacheDirectory => 2,
+            PreflightCategory::Dns => 3,
+            PreflightCategory::Config => 4,
+        }
+    }
+}
+
+/// The result of one preflight check.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PreflightCheck {
+    pub category: PreflightCategory,
+    pub target: String,
+    pub success: bool,
+    pub message: Option<String>,
+}
+
+/// The report of preflight checks, the exit code is zero if all
+/// checks are passed.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct PreflightReport {
+    pub success: bool,
+    pub exit_code: i32,
+    pub checks: Vec<PreflightCheck>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PreflightParams {
+    // the listeners are held by the old process when upgrading,
+    // so they can't be checked
+    pub check_listeners: bool,
+}
+
+impl PreflightReport {
+    fn new(checks: Vec<PreflightCheck>) -> Self {
+        let exit_code = checks
+            .iter()
+            .filter(|item| !item.success)
+            .min_by_key(|item| item.category.priority())
+            .map(|item| item.category.exit_code())
+            .unwrap_or_default();
+        Self {
+            success: exit_code == 0,
+            exit_code,
+            checks,
+        }
+    }
+    /// Creates the report of config which fails to load.
+    pub fn new_config_fail(target: &str, message: &str) -> Self {
+        Self::new(vec![new_check(
+            PreflightCategory::Config,
+            target,
+            Err(message.to_string()),
+        )])
+    }
+    /// Gets the failed checks.
+    pub fn failures(&self) -> Vec<&PreflightCheck> {
+        self.checks.iter().filter(|item| !item.success).collect()
+    }
+}
+
+fn new_check(
+    category: PreflightCategory,
+    target: &str,
+    result: Result<(), String>,
+) -> PreflightCheck {
+    PreflightCheck {
+        category,
+        target: target.to_string(),
+        success: result.is_ok(),
+        message: result.err(),
+    }
+}
+
+fn check_listener(addr: &str) -> Result<(), String> {
+    TcpListener::bind(addr)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn check_cache_directory(dir: &str) -> Result<(), String> {
+    let dir = util::resolve_path(dir);
+    let path = Path::new(&dir);
+    std::fs::create_dir_all(path).map_err(|e| e.to_string())?;
+    let file = path.join(".pingap_preflight");
+    std::fs::write(&file, b"pingap").map_err(|e| e.to_string())?;
+    std::fs::remove_file(&file).map_err(|e| e.to_string())
+}
+
+fn check_dns(conf: &PingapConf, name: &str) -> Result<(), String> {
+    let Some(upstream) = conf.upstreams.get(name) else {
+        return Err("upstream is not found".to_string());
+    };
+    for addr in upstream.addrs.iter() {
+        let addr = addr.split(' ').next().unwrap_or_default();
+        let (host, port) = util::split_host_port(addr);
+        let addr = util::join_host_port(host, port.unwrap_or("80"));
+        let count = addr
+            .to_socket_addrs()
+            .map_err(|e| format!("{host}: {e}"))?
+            .count();
+        if count == 0 {
+            return Err(format!("{host}: no address is resolved"));
+        }
+    }
+    Ok(())
+}
+
+/// Runs the preflight checks at startup, it verifies the listeners can be
+/// bound, the config is valid, the certificates can be decoded, the cache
+/// directory is writable and the critical upstreams can be resolved.
+pub fn run_preflight(
+    conf: &PingapConf,
+    params: &PreflightParams,
+) -> PreflightReport {
+    let mut checks = vec![];
+    if params.check_listeners {
+        let mut names: Vec<_> = conf.servers.keys().collect();
+        names.sort();
+        for name in names {
+            let Some(server) = conf.servers.get(name) else {
+                continue;
+            };
+            for addr in server.addr.split(',') {
+                let addr = addr.trim();
+                checks.push(new_check(
+                    PreflightCategory::Listener,
+                    &format!("{addr}(server:{name})"),
+                    check_listener(addr),
+                ));
+            }
+        }
+    }
+    checks.push(new_check(
+        PreflightCategory::Config,
+        "config",
+        conf.validate().map_err(|e| e.to_string()),
+    ));
+    let mut names: Vec<_> = conf.certificates.keys().collect();
+    names.sort();
+    for name in names {
+        let Some(certificate) = conf.certificates.get(name) else {
+            continue;
+        };
+        checks.push(new_check(
+            PreflightCategory::Certificate,
+            name,
+            certificate.validate().map_err(|e| e.to_string()),
+        ));
+    }
+    if let Some(dir) = &conf.basic.cache_directory {
+        checks.push(new_check(
+            PreflightCategory::CacheDirectory,
+            dir,
+            check_cache_directory(dir),
+        ));
+    }
+    for name in conf.basic.critical_upstreams.iter().flatten() {
+        checks.push(new_check(
+            PreflightCategory::Dns,
+            name,
+            check_dns(conf, name),
+        ));
+    }
+    PreflightReport::new(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        run_preflight, PreflightCategory, PreflightParams, PreflightReport,
+    };
+    use crate::config::{CertificateConf, PingapConf, UpstreamConf};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_run_preflight() {
+        let mut conf = PingapConf::default();
+        conf.upstreams.insert(
+            "charts".to_string(),
+            UpstreamConf {
+                addrs: vec!["127.0.0.1:5000".to_string()],
+                ..Default::default()
+            },
+        );
+        conf.basic.critical_upstreams =
+            Some(vec!["charts".to_string(), "not-exists".to_string()]);
+        let dir = tempfile::TempDir::new().unwrap();
+        conf.basic.cache_directory =
+            Some(dir.path().join("cache").to_string_lossy().to_string());
+
+        let report = run_preflight(&conf, &PreflightParams::default());
+        let failures = report.failures();
+        assert_eq!(1, failures.len());
+        assert_eq!(PreflightCategory::Dns, failures[0].category);
+        assert_eq!("not-exists", failures[0].target);
+        assert_eq!(68, report.exit_code);
+        assert_eq!(false, report.success);
+
+        // the certificate failure is preferred than the config failure
+        conf.basic.critical_upstreams = None;
+        conf.certificates.insert(
+            "pingap".to_string(),
+            CertificateConf {
+                tls_cert: Some("abc".to_string()),
+                ..Default::default()
+            },
+        );
+        let report = run_preflight(&conf, &PreflightParams::default());
+        assert_eq!(2, report.failures().len());
+        assert_eq!(65, report.exit_code);
+
+        let report = PreflightReport::new_config_fail("pingap.toml", "fail");
+        assert_eq!(78, report.exit_code);
+    }
+}
@@ -0,0 +1,304 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Error, Result};
+use toml::{map::Map, Value};
+
+// the key of config which includes the snippets
+const INCLUDE_KEY: &str = "include";
+// the prefix of value which references the snippet as anchor
+const ANCHOR_PREFIX: char = '*';
+
+/// The snippets and anchors used by the config, they are kept to be
+/// written back when the config is saved.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigTemplate {
+    // the names of included snippets
+    pub includes: Vec<String>,
+    // the keys of values and the names of anchors referenced by them
+    pub anchors: Vec<(String, String)>,
+}
+
+/// Gets the names of snippets, the include can be a snippet name
+/// or the list of snippet names.
+fn get_include_names(
+    table: &Map<String, Value>,
+) -> Result<Vec<String>, String> {
+    let Some(value) = table.get(INCLUDE_KEY) else {
+        return Ok(vec![]);
+    };
+    let names = match value {
+        Value::String(name) => Some(vec![name.clone()]),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(|name| name.to_string()))
+            .collect(),
+        _ => None,
+    };
+    names.ok_or_else(|| {
+        "include should be string or array of string".to_string()
+    })
+}
+
+fn expand_table(
+    snippets: &Map<String, Value>,
+    table: &Map<String, Value>,
+    chain: &mut Vec<String>,
+) -> Result<Map<String, Value>, String> {
+    let mut result = Map::new();
+    for name in get_include_names(table)? {
+        if chain.contains(&name) {
+            chain.push(name);
+            return Err(format!("include is cyclic: {}", chain.join(" -> ")));
+        }
+        let Some(snippet) = snippets.get(&name).and_then(|v| v.as_table())
+        else {
+            return Err(format!("snippet({name}) is not found"));
+        };
+        chain.push(name);
+        result.extend(expand_table(snippets, snippet, chain)?);
+        chain.pop();
+    }
+    // the values of config override the values of snippets
+    for (key, value) in table.iter() {
+        if key != INCLUDE_KEY {
+            result.insert(key.clone(), value.clone());
+        }
+    }
+    Ok(result)
+}
+
+/// Replaces the values which reference the anchors(e.g. `"*headers"`)
+/// with the values of snippets, the value is kept if the snippet is
+/// not found.
+fn expand_anchors(
+    snippets: &Map<String, Value>,
+    table: &mut Map<String, Value>,
+) -> Vec<(String, String)> {
+    let mut anchors = vec![];
+    for (key, value) in table.iter_mut() {
+        let Some(name) = value
+            .as_str()
+            .and_then(|value| value.strip_prefix(ANCHOR_PREFIX))
+            .map(|name| name.to_string())
+        else {
+            continue;
+        };
+        if let Some(anchor) = snippets.get(&name) {
+            *value = anchor.clone();
+            anchors.push((key.clone(), name));
+        }
+    }
+    anchors
+}
+
+/// Expands the `include` of config with the snippets, the snippets are
+/// merged in order and can include other snippets, the cyclic include
+/// is not allowed. Then the values which reference the anchors are
+/// expanded. The target is the category and name of config.
+pub fn expand_includes(
+    snippets: &Map<String, Value>,
+    value: &Value,
+    target: &str,
+) -> Result<(Value, ConfigTemplate)> {
+    let Some(table) = value.as_table() else {
+        return Ok((value.clone(), ConfigTemplate::default()));
+    };
+    let to_error = |message: String| Error::Invalid {
+        message: format!("{message}({target})"),
+    };
+    let includes = get_include_names(table).map_err(to_error)?;
+    let mut table =
+        expand_table(snippets, table, &mut vec![]).map_err(to_error)?;
+    let anchors = expand_anchors(snippets, &mut table);
+    Ok((Value::Table(table), ConfigTemplate { includes, anchors }))
+}
+
+/// Collapses the values which are the same as the anchors and the
+/// included snippets, it's the reverse of `expand_includes`, so the
+/// config keeps using the snippets after it's saved.
+pub fn collapse_includes(
+    snippets: &Map<String, Value>,
+    template: &ConfigTemplate,
+    table: &mut Map<String, Value>,
+) {
+    for (key, name) in template.anchors.iter() {
+        if table
+            .get(key)
+            .is_some_and(|value| Some(value) == snippets.get(name))
+        {
+            table.insert(
+                key.clone(),
+                Value::String(format!("{ANCHOR_PREFIX}{name}")),
+            );
+        }
+    }
+    if template.includes.is_empty() {
+        return;
+    }
+    let include = Value::Array(
+        template
+            .includes
+            .iter()
+            .map(|name| Value::String(name.clone()))
+            .collect(),
+    );
+    let mut included = Map::new();
+    included.insert(INCLUDE_KEY.to_string(), include.clone());
+    // the values are kept if the snippets are changed or removed
+    let Ok(base) = expand_table(snippets, &included, &mut vec![]) else {
+        return;
+    };
+    let keys: Vec<String> = table
+        .iter()
+        .filter(|(key, value)| base.get(key.as_str()) == Some(value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in keys.iter() {
+        table.remove(key);
+    }
+    table.insert(INCLUDE_KEY.to_string(), include);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collapse_includes, expand_includes, ConfigTemplate};
+    use pretty_assertions::assert_eq;
+    use toml::{map::Map, Value};
+
+    #[test]
+    fn test_expand_includes() {
+        let snippets: Map<String, Value> = toml::from_str(
+            r###"
+[common_headers]
+proxy_add_headers = ["X-Frame-Options:DENY"]
+proxy_set_headers = ["X-Service:pingap"]
+
+[timeout]
+include = "common_headers"
+proxy_read_timeout = "10s"
+proxy_set_headers = ["X-Service:charts"]
+
+[cyclic1]
+include = "cyclic2"
+
+[cyclic2]
+include = ["timeout", "cyclic1"]
+"###,
+        )
+        .unwrap();
+
+        let value: Value = toml::from_str(
+            r###"
+include = ["timeout"]
+upstream = "charts"
+proxy_read_timeout = "30s"
+"###,
+        )
+        .unwrap();
+        let expected: Value = toml::from_str(
+            r###"
+proxy_add_headers = ["X-Frame-Options:DENY"]
+proxy_set_headers = ["X-Service:charts"]
+proxy_read_timeout = "30s"
+upstream = "charts"
+"###,
+        )
+        .unwrap();
+        let (result, template) =
+            expand_includes(&snippets, &value, "location:lo").unwrap();
+        assert_eq!(expected, result);
+        assert_eq!(
+            ConfigTemplate {
+                includes: vec!["timeout".to_string()],
+                anchors: vec![],
+            },
+            template
+        );
+
+        // the values of snippets are collapsed to include
+        let mut table = result.as_table().unwrap().clone();
+        collapse_includes(&snippets, &template, &mut table);
+        let expected: Value = toml::from_str(
+            r###"
+include = ["timeout"]
+proxy_read_timeout = "30s"
+proxy_set_headers = ["X-Service:charts"]
+upstream = "charts"
+"###,
+        )
+        .unwrap();
+        assert_eq!(expected, Value::Table(table));
+
+        let value: Value = toml::from_str(r#"include = "cyclic1""#).unwrap();
+        assert_eq!(
+            "Invalid error include is cyclic: cyclic1 -> cyclic2 -> cyclic1(location:lo)",
+            expand_includes(&snippets, &value, "location:lo").unwrap_err().to_string()
+        );
+
+        let value: Value = toml::from_str(r#"include = "abc""#).unwrap();
+        assert_eq!(
+            "Invalid error snippet(abc) is not found(location:lo)",
+            expand_includes(&snippets, &value, "location:lo")
+                .unwrap_err()
+                .to_string()
+        );
+
+        let value: Value = toml::from_str("include = 1").unwrap();
+        assert_eq!(
+            "Invalid error include should be string or array of string(location:lo)",
+            expand_includes(&snippets, &value, "location:lo").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_expand_anchors() {
+        let snippets: Map<String, Value> = toml::from_str(
+            r###"
+security_headers = ["X-Frame-Options:DENY"]
+"###,
+        )
+        .unwrap();
+        let value: Value = toml::from_str(
+            r###"
+upstream = "charts"
+proxy_add_headers = "*security_headers"
+host = "*pingap.io"
+"###,
+        )
+        .unwrap();
+        let (result, template) =
+            expand_includes(&snippets, &value, "location:lo").unwrap();
+        let expected: Value = toml::from_str(
+            r###"
+upstream = "charts"
+proxy_add_headers = ["X-Frame-Options:DENY"]
+host = "*pingap.io"
+"###,
+        )
+        .unwrap();
+        assert_eq!(expected, result);
+        assert_eq!(
+            vec![(
+                "proxy_add_headers".to_string(),
+                "security_headers".to_string()
+            )],
+            template.anchors
+        );
+
+        let mut table = result.as_table().unwrap().clone();
+        collapse_includes(&snippets, &template, &mut table);
+        assert_eq!(value, Value::Table(table));
+    }
+}
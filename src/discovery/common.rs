@@ -12,7 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{format_addrs, Error, Result};
+use super::{format_addrs, AddrFamily, Error, Result};
+use crate::util;
 use pingora::lb::discovery;
 use pingora::lb::{Backend, Backends};
 use pingora::protocols::l4::socket::SocketAddr;
@@ -24,24 +25,26 @@ use std::net::ToSocketAddrs;
 pub fn new_common_discover_backends(
     addrs: &[String],
     tls: bool,
-    ipv4_only: bool,
+    family: AddrFamily,
 ) -> Result<Backends> {
     let mut upstreams = BTreeSet::new();
     let mut backends = vec![];
     let addrs = format_addrs(addrs, tls);
     for (ip, port, weight) in addrs.iter() {
-        let addr = format!("{ip}:{port}");
+        let addr = util::join_host_port(ip, port);
         // resolve to socket addr
         for item in addr.to_socket_addrs().map_err(|e| Error::Io {
             source: e,
             content: format!("{addr} to socket addr fail"),
         })? {
-            if ipv4_only && !item.is_ipv4() {
+            if !family.accept(&item.ip()) {
                 continue;
             }
             let backend = Backend {
                 addr: SocketAddr::Inet(item),
-                weight: weight.to_owned(),
+                // the backend of zero weight is drained by upstream,
+                // and the weight of load balancer is at least one
+                weight: (*weight).max(1),
             };
             backends.push(backend)
         }
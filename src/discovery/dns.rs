@@ -14,27 +14,266 @@
 
 use crate::webhook;
 
-use super::{format_addrs, Addr, Error, Result};
+use super::{format_addrs, Addr, AddrFamily, Error, Result};
+use crate::util;
+use ahash::AHashMap;
 use async_trait::async_trait;
 use hickory_resolver::lookup_ip::LookupIp;
 use hickory_resolver::{AsyncResolver, Resolver};
+use once_cell::sync::Lazy;
 use pingora::lb::discovery::ServiceDiscovery;
 use pingora::lb::{Backend, Backends};
 use pingora::protocols::l4::socket::SocketAddr;
 use std::collections::{BTreeSet, HashMap};
 use std::net::ToSocketAddrs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
-use tracing::{debug, error};
+use tracing::{debug, error, info, warn};
+
+/// The policy of dns discovery when the resolution fails.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum DnsFailurePolicy {
+    // the discover cycle fails, the backends of load balancer are not updated
+    #[default]
+    Error,
+    // the last known good backends are used until they are stale,
+    // the stale backends are removed
+    Keep(Option<Duration>),
+    // all backends are removed, the requests of upstream fail
+    Fail,
+    // the static addresses are used as backends
+    Static(Vec<String>),
+}
+
+impl DnsFailurePolicy {
+    /// Creates the failure policy of dns discovery, the policy should be
+    /// `keep`, `fail` or `static`, empty means returning error.
+    pub fn new(
+        policy: &str,
+        max_stale: Option<Duration>,
+        fallback_addrs: &[String],
+    ) -> Result<Self> {
+        let policy = match policy {
+            "" => DnsFailurePolicy::Error,
+            "keep" => DnsFailurePolicy::Keep(max_stale),
+            "fail" => DnsFailurePolicy::Fail,
+            "static" => {
+                if fallback_addrs.is_empty() {
+                    return Err(Error::Invalid {
+                        message: "fallback addrs of static policy is empty"
+                            .to_string(),
+                    });
+                }
+                DnsFailurePolicy::Static(fallback_addrs.to_vec())
+            },
+            _ => {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "dns failure policy({policy}) should be keep, fail or static"
+                    ),
+                });
+            },
+        };
+        Ok(policy)
+    }
+    fn name(&self) -> &'static str {
+        match self {
+            DnsFailurePolicy::Error => "error",
+            DnsFailurePolicy::Keep(_) => "keep",
+            DnsFailurePolicy::Fail => "fail",
+            DnsFailurePolicy::Static(_) => "static",
+        }
+    }
+}
+
+/// The resolve stats of dns discovery.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DnsResolveStats {
+    pub success: u64,
+    pub failure: u64,
+    // the count of discover cycles which use the cached backends
+    pub cache_hit: u64,
+    // the count of failures which are handled by the failure policy
+    pub fallback: u64,
+}
+
+static DNS_RESOLVE_STATS: Lazy<Mutex<AHashMap<String, DnsResolveStats>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+fn update_resolve_stats(name: &str, update: impl FnOnce(&mut DnsResolveStats)) {
+    if let Ok(mut stats) = DNS_RESOLVE_STATS.lock() {
+        update(stats.entry(name.to_string()).or_default());
+    }
+}
+
+/// Gets the resolve stats of upstreams which use dns discovery.
+pub fn get_dns_resolve_stats() -> Vec<(String, DnsResolveStats)> {
+    let Ok(stats) = DNS_RESOLVE_STATS.lock() else {
+        return vec![];
+    };
+    let mut items: Vec<_> = stats
+        .iter()
+        .map(|(name, item)| (name.clone(), item.clone()))
+        .collect();
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    items
+}
+
+#[derive(Default)]
+struct DnsState {
+    // the backends of last discovery
+    backends: Option<BTreeSet<Backend>>,
+    // the backends should be resolved again after expired
+    expired_at: Option<Instant>,
+    // the time of last successful resolution
+    resolved_at: Option<Instant>,
+}
 
 struct Dns {
-    ipv4_only: bool,
+    name: String,
+    family: AddrFamily,
     hosts: Vec<Addr>,
+    // the min and max ttl of dns record,
+    // the backends are refreshed after the ttl if it's set
+    ttl: Option<(Duration, Duration)>,
+    failure_policy: DnsFailurePolicy,
+    // the backends of static failure policy
+    fallback_backends: BTreeSet<Backend>,
+    state: Mutex<DnsState>,
+}
+
+/// Clamp the ttl of dns record between min and max value.
+fn clamp_ttl(ttl: Duration, min: Duration, max: Duration) -> Duration {
+    ttl.max(min).min(max)
+}
+
+/// Diff the addresses of backends, returns the added and removed addresses.
+fn diff_backends(
+    previous: &BTreeSet<Backend>,
+    current: &BTreeSet<Backend>,
+) -> (Vec<String>, Vec<String>) {
+    let added = current
+        .difference(previous)
+        .map(|item| item.addr.to_string())
+        .collect();
+    let removed = previous
+        .difference(current)
+        .map(|item| item.addr.to_string())
+        .collect();
+    (added, removed)
+}
+
+/// Converts the static addresses to backends, the host should be ip.
+fn new_static_backends(
+    addrs: &[String],
+    tls: bool,
+) -> Result<BTreeSet<Backend>> {
+    let mut backends = BTreeSet::new();
+    for (host, port, weight) in format_addrs(addrs, tls) {
+        let addr = util::join_host_port(&host, &port);
+        let socket_addr =
+            addr.parse::<std::net::SocketAddr>().map_err(|_| {
+                Error::Invalid {
+                    message: format!("fallback addr({addr}) should be ip"),
+                }
+            })?;
+        backends.insert(Backend {
+            addr: SocketAddr::Inet(socket_addr),
+            weight: weight.max(1),
+        });
+    }
+    Ok(backends)
 }
 
 impl Dns {
-    fn new(addrs: &[String], tls: bool, ipv4_only: bool) -> Result<Self> {
+    fn new(
+        name: &str,
+        addrs: &[String],
+        tls: bool,
+        family: AddrFamily,
+        ttl: Option<(Duration, Duration)>,
+        failure_policy: DnsFailurePolicy,
+    ) -> Result<Self> {
         let hosts = format_addrs(addrs, tls);
-        Ok(Self { hosts, ipv4_only })
+        let fallback_backends = match &failure_policy {
+            DnsFailurePolicy::Static(addrs) => new_static_backends(addrs, tls)?,
+            _ => BTreeSet::new(),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            hosts,
+            family,
+            ttl,
+            failure_policy,
+            fallback_backends,
+            state: Mutex::new(DnsState::default()),
+        })
+    }
+    /// Get the cached backends if they are not expired.
+    fn get_cached_backends(&self) -> Option<BTreeSet<Backend>> {
+        self.ttl?;
+        let state = self.state.lock().ok()?;
+        if state.expired_at? <= Instant::now() {
+            return None;
+        }
+        state.backends.clone()
+    }
+    /// Update the backends of state, the changes of addresses are
+    /// reported via log and webhook.
+    fn update_state(&self, backends: &BTreeSet<Backend>, ttl: Duration) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if let Some(previous) = &state.backends {
+            let (added, removed) = diff_backends(previous, backends);
+            if !added.is_empty() || !removed.is_empty() {
+                let hosts = format!("{:?}", self.hosts);
+                info!(
+                    hosts,
+                    added = added.join(","),
+                    removed = removed.join(","),
+                    "dns discover backends are changed"
+                );
+                webhook::send(webhook::SendNotificationParams {
+                    category:
+                        webhook::NotificationCategory::ServiceDiscoverChange,
+                    level: webhook::NotificationLevel::Info,
+                    msg: format!(
+                        "{hosts}, added: {added:?}, removed: {removed:?}"
+                    ),
+                });
+            }
+        }
+        state.backends = Some(backends.clone());
+        state.resolved_at = Some(Instant::now());
+        state.expired_at = self
+            .ttl
+            .map(|(min, max)| Instant::now() + clamp_ttl(ttl, min, max));
+    }
+    /// Gets the backends by the failure policy when the resolution fails,
+    /// none means the error should be returned.
+    fn get_fallback_backends(&self, now: Instant) -> Option<BTreeSet<Backend>> {
+        match &self.failure_policy {
+            DnsFailurePolicy::Error => None,
+            DnsFailurePolicy::Keep(max_stale) => {
+                let state = self.state.lock().ok()?;
+                let backends = state.backends.clone()?;
+                let is_stale = match (max_stale, state.resolved_at) {
+                    (Some(max_stale), Some(resolved_at)) => {
+                        resolved_at + *max_stale <= now
+                    },
+                    _ => false,
+                };
+                if is_stale {
+                    Some(BTreeSet::new())
+                } else {
+                    Some(backends)
+                }
+            },
+            DnsFailurePolicy::Fail => Some(BTreeSet::new()),
+            DnsFailurePolicy::Static(_) => Some(self.fallback_backends.clone()),
+        }
     }
     fn lookup_ip(&self) -> Result<Vec<LookupIp>> {
         let mut ip_list = vec![];
@@ -63,9 +302,9 @@ impl Dns {
         }
         Ok(ip_list)
     }
-    async fn run_discover(
-        &self,
-    ) -> Result<(BTreeSet<Backend>, HashMap<u64, bool>)> {
+    /// Resolve the hosts to backends, returns the backends and
+    /// the min ttl of dns records.
+    async fn run_discover(&self) -> Result<(BTreeSet<Backend>, Duration)> {
         let tokio_runtime = Handle::try_current().is_ok();
         let mut upstreams = BTreeSet::new();
         let mut backends = vec![];
@@ -78,19 +317,27 @@ impl Dns {
         } else {
             self.lookup_ip()?
         };
+        let now = Instant::now();
+        let ttl = lookup_ip_list
+            .iter()
+            .map(|item| item.valid_until().saturating_duration_since(now))
+            .min()
+            .unwrap_or_default();
         for (index, (_, port, weight)) in self.hosts.iter().enumerate() {
             let lookup_ip =
                 lookup_ip_list.get(index).ok_or(Error::Invalid {
                     message: "lookup ip fail".to_string(),
                 })?;
             for item in lookup_ip.iter() {
-                if self.ipv4_only && !item.is_ipv4() {
+                if !self.family.accept(&item) {
                     continue;
                 }
-                let mut addr = item.to_string();
-                if !port.is_empty() {
-                    addr += &format!(":{port}");
-                }
+                // the ipv6 address should be bracketed with port
+                let addr = if port.is_empty() {
+                    item.to_string()
+                } else {
+                    util::join_host_port(&item.to_string(), port)
+                };
                 for socket_addr in
                     addr.to_socket_addrs().map_err(|e| Error::Io {
                         source: e,
@@ -99,15 +346,14 @@ impl Dns {
                 {
                     backends.push(Backend {
                         addr: SocketAddr::Inet(socket_addr),
-                        weight: weight.to_owned(),
+                        // zero weight is invalid for consistent hashing
+                        weight: (*weight).max(1),
                     });
                 }
             }
         }
         upstreams.extend(backends);
-        // no readiness
-        let health = HashMap::new();
-        Ok((upstreams, health))
+        Ok((upstreams, ttl))
     }
 }
 
@@ -116,8 +362,18 @@ impl ServiceDiscovery for Dns {
     async fn discover(
         &self,
     ) -> pingora::Result<(BTreeSet<Backend>, HashMap<u64, bool>)> {
+        // no readiness
+        let health = HashMap::new();
+        if let Some(backends) = self.get_cached_backends() {
+            update_resolve_stats(&self.name, |stats| stats.cache_hit += 1);
+            return Ok((backends, health));
+        }
         match self.run_discover().await {
-            Ok(data) => return Ok(data),
+            Ok((backends, ttl)) => {
+                update_resolve_stats(&self.name, |stats| stats.success += 1);
+                self.update_state(&backends, ttl);
+                return Ok((backends, health));
+            },
             Err(e) => {
                 error!(
                     error = e.to_string(),
@@ -130,6 +386,22 @@ impl ServiceDiscovery for Dns {
                     level: webhook::NotificationLevel::Warn,
                     msg: format!("{:?}, error: {e}", self.hosts),
                 });
+                let fallback = self.get_fallback_backends(Instant::now());
+                update_resolve_stats(&self.name, |stats| {
+                    stats.failure += 1;
+                    if fallback.is_some() {
+                        stats.fallback += 1;
+                    }
+                });
+                if let Some(backends) = fallback {
+                    warn!(
+                        policy = self.failure_policy.name(),
+                        count = backends.len(),
+                        hosts = format!("{:?}", self.hosts),
+                        "dns discover uses backends of failure policy"
+                    );
+                    return Ok((backends, health));
+                }
                 return Err(e.into());
             },
         }
@@ -138,28 +410,152 @@ impl ServiceDiscovery for Dns {
 
 /// Create a dns discovery, scheduled execution execute DNS resolve,
 /// and update the latest IP address list.
+/// If the ttl(min, max) is set, the IP address list is kept until
+/// the ttl of dns record(clamped by min and max) is expired.
+/// The backends of failure policy are used if the resolution fails.
 pub fn new_dns_discover_backends(
+    name: &str,
     addrs: &[String],
     tls: bool,
-    ipv4_only: bool,
+    family: AddrFamily,
+    ttl: Option<(Duration, Duration)>,
+    failure_policy: DnsFailurePolicy,
 ) -> Result<Backends> {
-    let dns = Dns::new(addrs, tls, ipv4_only)?;
+    let dns = Dns::new(name, addrs, tls, family, ttl, failure_policy)?;
     let backends = Backends::new(Box::new(dns));
     Ok(backends)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Dns;
+    use super::{clamp_ttl, diff_backends, AddrFamily, Dns, DnsFailurePolicy};
+    use pingora::lb::Backend;
     use pretty_assertions::assert_eq;
+    use std::collections::BTreeSet;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_clamp_ttl() {
+        let min = Duration::from_secs(5);
+        let max = Duration::from_secs(300);
+        assert_eq!(min, clamp_ttl(Duration::from_secs(1), min, max));
+        assert_eq!(
+            Duration::from_secs(60),
+            clamp_ttl(Duration::from_secs(60), min, max)
+        );
+        assert_eq!(max, clamp_ttl(Duration::from_secs(3600), min, max));
+    }
+
+    #[test]
+    fn test_diff_backends() {
+        let previous = BTreeSet::from([
+            Backend::new("192.168.1.1:80").unwrap(),
+            Backend::new("192.168.1.2:80").unwrap(),
+        ]);
+        let current = BTreeSet::from([
+            Backend::new("192.168.1.2:80").unwrap(),
+            Backend::new("192.168.1.3:80").unwrap(),
+        ]);
+        let (added, removed) = diff_backends(&previous, &current);
+        assert_eq!(vec!["192.168.1.3:80".to_string()], added);
+        assert_eq!(vec!["192.168.1.1:80".to_string()], removed);
+    }
+
+    #[test]
+    fn test_dns_failure_policy() {
+        assert_eq!(
+            DnsFailurePolicy::Error,
+            DnsFailurePolicy::new("", None, &[]).unwrap()
+        );
+        assert_eq!(
+            "dns failure policy(abc) should be keep, fail or static",
+            DnsFailurePolicy::new("abc", None, &[])
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "fallback addrs of static policy is empty",
+            DnsFailurePolicy::new("static", None, &[])
+                .unwrap_err()
+                .to_string()
+        );
+
+        let new_dns = |policy: DnsFailurePolicy| {
+            Dns::new(
+                "dns-failure",
+                &["pingap.internal:3000".to_string()],
+                false,
+                AddrFamily::All,
+                None,
+                policy,
+            )
+        };
+        let backends = BTreeSet::from([Backend::new("10.0.0.1:3000").unwrap()]);
+        let now = Instant::now();
+
+        let dns = new_dns(DnsFailurePolicy::Error).unwrap();
+        dns.update_state(&backends, Duration::from_secs(60));
+        assert_eq!(None, dns.get_fallback_backends(now));
+
+        let dns =
+            new_dns(DnsFailurePolicy::Keep(Some(Duration::from_secs(300))))
+                .unwrap();
+        // no last known good backends
+        assert_eq!(None, dns.get_fallback_backends(now));
+        dns.update_state(&backends, Duration::from_secs(60));
+        assert_eq!(
+            Some(backends.clone()),
+            dns.get_fallback_backends(Instant::now())
+        );
+        assert_eq!(
+            Some(BTreeSet::new()),
+            dns.get_fallback_backends(
+                Instant::now() + Duration::from_secs(301)
+            )
+        );
+
+        let dns = new_dns(DnsFailurePolicy::Fail).unwrap();
+        dns.update_state(&backends, Duration::from_secs(60));
+        assert_eq!(Some(BTreeSet::new()), dns.get_fallback_backends(now));
+
+        let dns = new_dns(DnsFailurePolicy::Static(vec![
+            "10.0.0.2:3000 10".to_string()
+        ]))
+        .unwrap();
+        let fallback = dns.get_fallback_backends(now).unwrap();
+        assert_eq!(1, fallback.len());
+        let backend = fallback.first().unwrap();
+        assert_eq!("10.0.0.2:3000", backend.addr.to_string());
+        assert_eq!(10, backend.weight);
+
+        assert_eq!(
+            "fallback addr(pingap.io:80) should be ip",
+            new_dns(DnsFailurePolicy::Static(vec!["pingap.io".to_string()]))
+                .err()
+                .unwrap()
+                .to_string()
+        );
+    }
 
     #[tokio::test]
     async fn test_dns_discover() {
-        let dns = Dns::new(&["github.com".to_string()], true, true).unwrap();
+        let dns = Dns::new(
+            "dns-discover",
+            &["github.com".to_string()],
+            true,
+            AddrFamily::Ipv4Only,
+            Some((Duration::from_secs(5), Duration::from_secs(300))),
+            DnsFailurePolicy::Error,
+        )
+        .unwrap();
         let ip_list = dns.tokio_lookup_ip().await.unwrap();
         assert_eq!(true, !ip_list.is_empty());
 
         let (backends, _) = dns.run_discover().await.unwrap();
         assert_eq!(true, !backends.is_empty());
+
+        assert_eq!(true, dns.get_cached_backends().is_none());
+        dns.update_state(&backends, Duration::from_secs(60));
+        assert_eq!(Some(backends), dns.get_cached_backends());
     }
 }
@@ -0,0 +1,248 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{format_addrs, Addr, AddrFamily, Error, Result};
+use ahash::AHashMap;
+use hickory_resolver::AsyncResolver;
+use pingora::lb::Backend;
+use pingora::protocols::l4::socket::SocketAddr;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, error};
+
+// the min valid duration of positive cache
+const MIN_VALID: Duration = Duration::from_secs(1);
+
+struct ResolveCache {
+    // the empty list means the host failed to resolve
+    ips: Vec<IpAddr>,
+    expired_at: Instant,
+}
+
+/// The resolver of upstream hosts, the host is resolved lazily when
+/// the connection is created, and the result is cached in process.
+pub struct LazyResolver {
+    hosts: Vec<Addr>,
+    // the index of hosts, the host appears by its weight
+    slots: Vec<usize>,
+    family: AddrFamily,
+    // the valid duration of positive cache, the ttl of record is used if not set
+    valid: Option<Duration>,
+    // the valid duration of negative cache
+    negative_valid: Duration,
+    cache: Mutex<AHashMap<String, ResolveCache>>,
+    index: AtomicUsize,
+}
+
+impl LazyResolver {
+    pub fn new(
+        addrs: &[String],
+        tls: bool,
+        family: AddrFamily,
+        valid: Option<Duration>,
+        negative_valid: Duration,
+    ) -> Self {
+        let hosts = format_addrs(addrs, tls);
+        let mut slots = vec![];
+        for (index, (_, _, weight)) in hosts.iter().enumerate() {
+            for _ in 0..(*weight).max(1) {
+                slots.push(index);
+            }
+        }
+        Self {
+            hosts,
+            slots,
+            family,
+            valid,
+            negative_valid,
+            cache: Mutex::new(AHashMap::new()),
+            index: AtomicUsize::new(0),
+        }
+    }
+    /// Gets the cached ips of host, `None` means the cache is missing or expired.
+    fn get_cached_ips(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let cache = self.cache.lock().ok()?;
+        let item = cache.get(host)?;
+        if item.expired_at <= Instant::now() {
+            return None;
+        }
+        Some(item.ips.clone())
+    }
+    fn set_cached_ips(&self, host: &str, ips: Vec<IpAddr>, ttl: Duration) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(
+                host.to_string(),
+                ResolveCache {
+                    ips,
+                    expired_at: Instant::now() + ttl,
+                },
+            );
+        }
+    }
+    async fn lookup(&self, host: &str) -> Result<(Vec<IpAddr>, Duration)> {
+        let resolver = AsyncResolver::tokio_from_system_conf()
+            .map_err(|e| Error::Resolve { source: e })?;
+        let lookup_ip = resolver
+            .lookup_ip(host)
+            .await
+            .map_err(|e| Error::Resolve { source: e })?;
+        let ttl = lookup_ip
+            .valid_until()
+            .saturating_duration_since(Instant::now());
+        let ips: Vec<IpAddr> = lookup_ip
+            .iter()
+            .filter(|ip| self.family.accept(ip))
+            .collect();
+        if ips.is_empty() {
+            return Err(Error::Invalid {
+                message: format!("{host} has no address of {:?}", self.family),
+            });
+        }
+        Ok((ips, ttl))
+    }
+    /// Resolves the host to ips, the result is cached by the valid duration,
+    /// and the failure is cached by the negative valid duration.
+    async fn resolve(&self, host: &str) -> Vec<IpAddr> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return vec![ip];
+        }
+        if let Some(ips) = self.get_cached_ips(host) {
+            return ips;
+        }
+        match self.lookup(host).await {
+            Ok((ips, ttl)) => {
+                let ttl = self.valid.unwrap_or(ttl).max(MIN_VALID);
+                debug!(host, ips = format!("{ips:?}"), "lazy resolve success");
+                self.set_cached_ips(host, ips.clone(), ttl);
+                ips
+            },
+            Err(e) => {
+                error!(error = e.to_string(), host, "lazy resolve fail");
+                self.set_cached_ips(host, vec![], self.negative_valid);
+                vec![]
+            },
+        }
+    }
+    /// Selects the backend by weighted round robin of hosts,
+    /// the excluded addresses(failed to connect) are skipped.
+    pub async fn select(&self, excluded: Option<&[String]>) -> Option<Backend> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let index = self.index.fetch_add(1, Ordering::Relaxed);
+        for i in 0..self.slots.len() {
+            let (host, port, weight) =
+                &self.hosts[self.slots[(index + i) % self.slots.len()]];
+            let Ok(port) = port.parse::<u16>() else {
+                continue;
+            };
+            let mut ips = self.resolve(host).await;
+            if ips.is_empty() {
+                continue;
+            }
+            ips.rotate_left(index % ips.len());
+            // the ipv6 addresses are tried first
+            if self.family == AddrFamily::PreferIpv6 {
+                ips.sort_by_key(|ip| !ip.is_ipv6());
+            }
+            for ip in ips {
+                let addr = std::net::SocketAddr::new(ip, port);
+                if excluded
+                    .is_some_and(|addrs| addrs.contains(&addr.to_string()))
+                {
+                    continue;
+                }
+                return Some(Backend {
+                    addr: SocketAddr::Inet(addr),
+                    weight: *weight,
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddrFamily, LazyResolver};
+    use pretty_assertions::assert_eq;
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_lazy_resolver() {
+        let resolver = LazyResolver::new(
+            &["127.0.0.1:3000 2".to_string(), "[::1]:3001".to_string()],
+            false,
+            AddrFamily::All,
+            None,
+            Duration::from_secs(5),
+        );
+        assert_eq!(vec![0, 0, 1], resolver.slots);
+
+        let mut addrs = vec![];
+        for _ in 0..3 {
+            let backend = resolver.select(None).await.unwrap();
+            addrs.push(backend.addr.to_string());
+        }
+        addrs.sort();
+        assert_eq!(
+            vec![
+                "127.0.0.1:3000".to_string(),
+                "127.0.0.1:3000".to_string(),
+                "[::1]:3001".to_string(),
+            ],
+            addrs
+        );
+
+        // the failed addresses are skipped
+        let excluded = vec!["127.0.0.1:3000".to_string()];
+        for _ in 0..3 {
+            let backend = resolver.select(Some(&excluded)).await.unwrap();
+            assert_eq!("[::1]:3001", backend.addr.to_string());
+        }
+        let excluded =
+            vec!["127.0.0.1:3000".to_string(), "[::1]:3001".to_string()];
+        assert_eq!(true, resolver.select(Some(&excluded)).await.is_none());
+    }
+
+    #[test]
+    fn test_resolve_cache() {
+        let resolver = LazyResolver::new(
+            &["pingap.io".to_string()],
+            false,
+            AddrFamily::All,
+            None,
+            Duration::from_secs(5),
+        );
+        assert_eq!(true, resolver.get_cached_ips("pingap.io").is_none());
+
+        let ips: Vec<IpAddr> = vec!["1.1.1.1".parse().unwrap()];
+        resolver.set_cached_ips(
+            "pingap.io",
+            ips.clone(),
+            Duration::from_secs(60),
+        );
+        assert_eq!(Some(ips), resolver.get_cached_ips("pingap.io"));
+
+        // negative cache
+        resolver.set_cached_ips("pingap.io", vec![], Duration::from_secs(60));
+        assert_eq!(Some(vec![]), resolver.get_cached_ips("pingap.io"));
+
+        resolver.set_cached_ips("pingap.io", vec![], Duration::from_secs(0));
+        assert_eq!(true, resolver.get_cached_ips("pingap.io").is_none());
+    }
+}
@@ -14,6 +14,7 @@
 
 use hickory_resolver::error::ResolveError;
 use snafu::Snafu;
+use std::net::IpAddr;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -49,23 +50,130 @@ pub(crate) fn format_addrs(addrs: &[String], tls: bool) -> Vec<Addr> {
         };
         // split ip and port
         // the port will use default value if none
-        if let Some((host, port)) = arr[0].split_once(':') {
-            new_addrs.push((host.to_string(), port.to_string(), weight));
-        } else {
-            let port = if tls {
+        let (host, port) = util::split_host_port(arr[0]);
+        let port = port.map(|port| port.to_string()).unwrap_or_else(|| {
+            if tls {
                 "443".to_string()
             } else {
                 "80".to_string()
-            };
-            new_addrs.push((arr[0].to_string(), port, weight));
-        }
+            }
+        });
+        new_addrs.push((host.to_string(), port, weight));
     }
     new_addrs
 }
 
+/// The address family of discovered backends.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AddrFamily {
+    #[default]
+    All,
+    Ipv4Only,
+    Ipv6Only,
+    // all addresses are kept, the ipv6 addresses are preferred
+    // and the ipv4 addresses are used for fallback
+    PreferIpv6,
+}
+
+impl AddrFamily {
+    pub fn new(ipv4_only: bool, ipv6_only: bool, prefer_ipv6: bool) -> Self {
+        if ipv4_only {
+            AddrFamily::Ipv4Only
+        } else if ipv6_only {
+            AddrFamily::Ipv6Only
+        } else if prefer_ipv6 {
+            AddrFamily::PreferIpv6
+        } else {
+            AddrFamily::All
+        }
+    }
+    /// Whether the ip address is accepted by the address family.
+    pub fn accept(&self, ip: &IpAddr) -> bool {
+        match self {
+            AddrFamily::Ipv4Only => ip.is_ipv4(),
+            AddrFamily::Ipv6Only => ip.is_ipv6(),
+            _ => true,
+        }
+    }
+}
+
 mod common;
 mod dns;
+mod lazy;
+mod registry;
+#[cfg(feature = "xds")]
+mod xds;
 pub use common::new_common_discover_backends;
-pub use dns::new_dns_discover_backends;
+pub use dns::{
+    get_dns_resolve_stats, new_dns_discover_backends, DnsFailurePolicy,
+    DnsResolveStats,
+};
+pub use lazy::LazyResolver;
+pub use registry::{
+    merge_registry_config, new_registry_service, RegistryParams,
+};
+#[cfg(feature = "xds")]
+pub use xds::{
+    new_xds_discover_backends, new_xds_service, XdsService, XdsServiceParams,
+};
+
+/// The xds discovery is not supported without `xds` feature.
+#[cfg(not(feature = "xds"))]
+pub fn new_xds_discover_backends(
+    _addrs: &[String],
+) -> Result<pingora::lb::Backends> {
+    Err(Error::Invalid {
+        message: "xds discovery is not supported, build with xds feature"
+            .to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_addrs, AddrFamily};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_addrs() {
+        let addrs = format_addrs(
+            &[
+                "127.0.0.1:3000 10".to_string(),
+                "pingap.io".to_string(),
+                "[::1]:3001".to_string(),
+                "::1".to_string(),
+            ],
+            true,
+        );
+        assert_eq!(
+            vec![
+                ("127.0.0.1".to_string(), "3000".to_string(), 10),
+                ("pingap.io".to_string(), "443".to_string(), 1),
+                ("::1".to_string(), "3001".to_string(), 1),
+                ("::1".to_string(), "443".to_string(), 1),
+            ],
+            addrs
+        );
+    }
+
+    #[test]
+    fn test_addr_family() {
+        let ipv4 = "127.0.0.1".parse().unwrap();
+        let ipv6 = "::1".parse().unwrap();
+        let family = AddrFamily::new(true, false, false);
+        assert_eq!(AddrFamily::Ipv4Only, family);
+        assert_eq!(true, family.accept(&ipv4));
+        assert_eq!(false, family.accept(&ipv6));
+
+        let family = AddrFamily::new(false, true, false);
+        assert_eq!(AddrFamily::Ipv6Only, family);
+        assert_eq!(false, family.accept(&ipv4));
+        assert_eq!(true, family.accept(&ipv6));
+
+        let family = AddrFamily::new(false, false, true);
+        assert_eq!(AddrFamily::PreferIpv6, family);
+        assert_eq!(true, family.accept(&ipv4));
+        assert_eq!(true, family.accept(&ipv6));
+    }
+}
 
 use crate::util;
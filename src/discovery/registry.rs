@@ -0,0 +1,480 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Error, Result};
+use crate::config::{
+    get_current_config, LocationConf, PingapConf, UpstreamConf,
+};
+use crate::proxy;
+use crate::service::{CommonServiceTask, ServiceTask};
+use ahash::AHashMap;
+use async_trait::async_trait;
+use humantime::parse_duration;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{error, info};
+use url::Url;
+
+const CONSUL_REGISTRY: &str = "consul";
+const DEFAULT_TAG_PREFIX: &str = "pingap";
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// the name prefix of generated upstream and location
+const REGISTRY_NAME_PREFIX: &str = "registry-";
+
+/// The params of service registry, the format of url is
+/// `consul://127.0.0.1:8500?prefix=pingap&interval=10s&token=xxx`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryParams {
+    // the http address of registry api
+    pub addr: String,
+    // the tag prefix of service, e.g. `pingap.host=api.example.com`
+    pub prefix: String,
+    pub interval: Duration,
+    // the acl token of consul
+    pub token: Option<String>,
+}
+
+impl TryFrom<&str> for RegistryParams {
+    type Error = Error;
+    fn try_from(value: &str) -> Result<Self> {
+        let info = Url::parse(value).map_err(|e| Error::Invalid {
+            message: format!("{value} is invalid, {e}"),
+        })?;
+        if info.scheme() != CONSUL_REGISTRY {
+            return Err(Error::Invalid {
+                message: format!(
+                    "registry {} is not supported, only consul is supported",
+                    info.scheme()
+                ),
+            });
+        }
+        let host = info.host_str().unwrap_or_default();
+        if host.is_empty() {
+            return Err(Error::Invalid {
+                message: format!("host of registry {value} is empty"),
+            });
+        }
+        let mut params = Self {
+            addr: format!("http://{host}:{}", info.port().unwrap_or(8500)),
+            prefix: DEFAULT_TAG_PREFIX.to_string(),
+            interval: DEFAULT_INTERVAL,
+            token: None,
+        };
+        for (key, value) in info.query_pairs() {
+            match key.as_ref() {
+                "prefix" => params.prefix = value.to_string(),
+                "token" => params.token = Some(value.to_string()),
+                "interval" => {
+                    params.interval =
+                        parse_duration(&value).map_err(|e| Error::Invalid {
+                            message: e.to_string(),
+                        })?;
+                },
+                _ => {},
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// The service of registry which is exposed by pingap,
+/// the location and upstream are generated from it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegistryService {
+    pub name: String,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub weight: Option<u16>,
+    pub addrs: Vec<String>,
+}
+
+/// Parses the location params from the tags of service, only the service
+/// with `{prefix}.host` or `{prefix}.path` tag is exposed.
+fn parse_service_tags(
+    name: &str,
+    prefix: &str,
+    tags: &[String],
+) -> Option<RegistryService> {
+    let mut service = RegistryService {
+        name: name.to_string(),
+        ..Default::default()
+    };
+    for tag in tags.iter() {
+        let Some((key, value)) = tag.split_once('=') else {
+            continue;
+        };
+        let Some(key) = key
+            .trim()
+            .strip_prefix(prefix)
+            .and_then(|key| key.strip_prefix('.'))
+        else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key {
+            "host" => service.host = Some(value),
+            "path" => service.path = Some(value),
+            "weight" => service.weight = value.parse::<u16>().ok(),
+            _ => {},
+        }
+    }
+    if service.host.is_none() && service.path.is_none() {
+        return None;
+    }
+    Some(service)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulNode {
+    address: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulService {
+    address: String,
+    port: u16,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulServiceEntry {
+    node: ConsulNode,
+    service: ConsulService,
+}
+
+impl ConsulServiceEntry {
+    /// Gets the address of service instance, the node address is used
+    /// if the service address is empty.
+    fn addr(&self) -> String {
+        let host = if self.service.address.is_empty() {
+            &self.node.address
+        } else {
+            &self.service.address
+        };
+        if host.contains(':') {
+            format!("[{host}]:{}", self.service.port)
+        } else {
+            format!("{host}:{}", self.service.port)
+        }
+    }
+}
+
+async fn get_consul<T: for<'de> Deserialize<'de>>(
+    client: &reqwest::Client,
+    params: &RegistryParams,
+    path: &str,
+) -> Result<T> {
+    let mut req = client
+        .get(format!("{}{path}", params.addr))
+        .timeout(REQUEST_TIMEOUT);
+    if let Some(token) = &params.token {
+        req = req.header("X-Consul-Token", token);
+    }
+    let new_error = |e: reqwest::Error| Error::Invalid {
+        message: format!("consul request fail, {e}"),
+    };
+    req.send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(new_error)?
+        .json::<T>()
+        .await
+        .map_err(new_error)
+}
+
+/// Fetches the exposed services of consul, the service without
+/// passing instance is ignored.
+async fn fetch_consul_services(
+    params: &RegistryParams,
+) -> Result<Vec<RegistryService>> {
+    let client = reqwest::Client::new();
+    let catalog: HashMap<String, Vec<String>> =
+        get_consul(&client, params, "/v1/catalog/services").await?;
+    let mut services = vec![];
+    for (name, tags) in catalog.iter() {
+        let Some(mut service) = parse_service_tags(name, &params.prefix, tags)
+        else {
+            continue;
+        };
+        let entries: Vec<ConsulServiceEntry> = get_consul(
+            &client,
+            params,
+            &format!(
+                "/v1/health/service/{}?passing=true",
+                urlencoding::encode(name)
+            ),
+        )
+        .await?;
+        service.addrs = entries.iter().map(|entry| entry.addr()).collect();
+        service.addrs.sort();
+        if service.addrs.is_empty() {
+            continue;
+        }
+        services.push(service);
+    }
+    services.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(services)
+}
+
+// the services of registry, key is the server name
+static REGISTRY_SERVICES: Lazy<Mutex<AHashMap<String, Vec<RegistryService>>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+/// Merges the locations and upstreams generated from registry into config,
+/// the config with the same name isn't overridden.
+fn merge_services(
+    conf: &mut PingapConf,
+    server: &str,
+    services: &[RegistryService],
+) {
+    let Some(server_conf) = conf.servers.get_mut(server) else {
+        return;
+    };
+    let mut names = vec![];
+    for service in services.iter() {
+        let name = format!("{REGISTRY_NAME_PREFIX}{}", service.name);
+        if conf.upstreams.contains_key(&name)
+            || conf.locations.contains_key(&name)
+        {
+            continue;
+        }
+        let upstream = UpstreamConf {
+            addrs: service.addrs.clone(),
+            remark: Some(format!("generated from registry({server})")),
+            ..Default::default()
+        };
+        let location = LocationConf {
+            upstream: Some(name.clone()),
+            host: service.host.clone(),
+            path: service.path.clone(),
+            weight: service.weight,
+            remark: Some(format!("generated from registry({server})")),
+            ..Default::default()
+        };
+        conf.upstreams.insert(name.clone(), upstream);
+        conf.locations.insert(name.clone(), location);
+        names.push(name);
+    }
+    server_conf
+        .locations
+        .get_or_insert_with(Vec::new)
+        .extend(names);
+}
+
+/// Returns the config with the locations and upstreams generated from
+/// registry, they are kept in memory and not saved to config storage.
+pub fn merge_registry_config(conf: &PingapConf) -> PingapConf {
+    let mut conf = conf.clone();
+    let Ok(registry_services) = REGISTRY_SERVICES.lock() else {
+        return conf;
+    };
+    for (server, services) in registry_services.iter() {
+        merge_services(&mut conf, server, services);
+    }
+    conf
+}
+
+/// Reloads the upstreams and locations with the services of registry.
+fn reload_registry_config() -> std::result::Result<(), String> {
+    let conf = merge_registry_config(&get_current_config());
+    proxy::try_init_upstreams(&conf.upstreams).map_err(|e| e.to_string())?;
+    proxy::try_init_locations(&conf.get_inherited_locations())
+        .map_err(|e| e.to_string())?;
+    proxy::try_init_server_locations(&conf.servers, &conf.locations)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+struct RegistryTask {
+    server: String,
+    params: RegistryParams,
+}
+
+#[async_trait]
+impl ServiceTask for RegistryTask {
+    async fn run(&self) -> Option<bool> {
+        let services = match fetch_consul_services(&self.params).await {
+            Ok(services) => services,
+            Err(e) => {
+                error!(
+                    server = self.server,
+                    error = e.to_string(),
+                    "fetch registry services fail"
+                );
+                return None;
+            },
+        };
+        {
+            let Ok(mut registry_services) = REGISTRY_SERVICES.lock() else {
+                return None;
+            };
+            if registry_services.get(&self.server) == Some(&services) {
+                return None;
+            }
+            registry_services.insert(self.server.clone(), services.clone());
+        }
+        let names: Vec<String> =
+            services.iter().map(|item| item.name.clone()).collect();
+        match reload_registry_config() {
+            Ok(()) => {
+                info!(
+                    server = self.server,
+                    services = names.join(","),
+                    "reload registry services success"
+                );
+            },
+            Err(e) => {
+                error!(
+                    server = self.server,
+                    error = e,
+                    "reload registry services fail"
+                );
+            },
+        }
+        None
+    }
+    fn description(&self) -> String {
+        format!(
+            "generate locations of server({}) from registry({})",
+            self.server, self.params.addr
+        )
+    }
+}
+
+/// Creates the service to generate the locations of server
+/// from the services of registry.
+pub fn new_registry_service(
+    server: &str,
+    url: &str,
+) -> Result<CommonServiceTask> {
+    let params = RegistryParams::try_from(url)?;
+    Ok(CommonServiceTask::new(
+        "Registry",
+        params.interval,
+        RegistryTask {
+            server: server.to_string(),
+            params,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        merge_services, parse_service_tags, ConsulServiceEntry, RegistryParams,
+        RegistryService,
+    };
+    use crate::config::{LocationConf, PingapConf, ServerConf};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn test_registry_params() {
+        let params = RegistryParams::try_from(
+            "consul://127.0.0.1?prefix=edge&interval=30s&token=abc",
+        )
+        .unwrap();
+        assert_eq!("http://127.0.0.1:8500", params.addr);
+        assert_eq!("edge", params.prefix);
+        assert_eq!(Duration::from_secs(30), params.interval);
+        assert_eq!(Some("abc".to_string()), params.token);
+
+        assert_eq!(
+            "registry etcd is not supported, only consul is supported",
+            RegistryParams::try_from("etcd://127.0.0.1:2379")
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_service_tags() {
+        assert_eq!(
+            None,
+            parse_service_tags("web", "pingap", &["primary".to_string()])
+        );
+        let service = parse_service_tags(
+            "web",
+            "pingap",
+            &[
+                "pingap.host=api.example.com".to_string(),
+                "pingap.path=/api".to_string(),
+                "pingap.weight=1024".to_string(),
+                "pingapx.path=/x".to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(Some("api.example.com".to_string()), service.host);
+        assert_eq!(Some("/api".to_string()), service.path);
+        assert_eq!(Some(1024), service.weight);
+
+        let entry: ConsulServiceEntry = serde_json::from_str(
+            r#"{"Node":{"Address":"10.0.0.1"},"Service":{"Address":"","Port":3000}}"#,
+        )
+        .unwrap();
+        assert_eq!("10.0.0.1:3000", entry.addr());
+    }
+
+    #[test]
+    fn test_merge_services() {
+        let mut conf = PingapConf::default();
+        conf.servers.insert(
+            "edge".to_string(),
+            ServerConf {
+                locations: Some(vec!["registry-admin".to_string()]),
+                ..Default::default()
+            },
+        );
+        conf.locations
+            .insert("registry-admin".to_string(), LocationConf::default());
+        let services = vec![
+            RegistryService {
+                name: "web".to_string(),
+                host: Some("web.example.com".to_string()),
+                addrs: vec!["10.0.0.1:3000".to_string()],
+                ..Default::default()
+            },
+            RegistryService {
+                name: "admin".to_string(),
+                path: Some("/admin".to_string()),
+                addrs: vec!["10.0.0.2:3000".to_string()],
+                ..Default::default()
+            },
+        ];
+        merge_services(&mut conf, "edge", &services);
+        assert_eq!(
+            Some(vec![
+                "registry-admin".to_string(),
+                "registry-web".to_string()
+            ]),
+            conf.servers["edge"].locations
+        );
+        assert_eq!(
+            vec!["10.0.0.1:3000".to_string()],
+            conf.upstreams["registry-web"].addrs
+        );
+        assert_eq!(
+            Some("registry-web".to_string()),
+            conf.locations["registry-web"].upstream
+        );
+        // the location of config isn't overridden
+        assert_eq!(None, conf.locations["registry-admin"].upstream);
+    }
+}
@@ -0,0 +1,518 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Error, Result};
+use crate::webhook;
+use ahash::AHashMap;
+use async_trait::async_trait;
+use envoy_types::pb::envoy::config::cluster::v3::Cluster;
+use envoy_types::pb::envoy::config::core::v3::{
+    address, socket_address, HealthStatus, Node,
+};
+use envoy_types::pb::envoy::config::endpoint::v3::{
+    lb_endpoint, ClusterLoadAssignment,
+};
+use envoy_types::pb::envoy::service::discovery::v3::{
+    aggregated_discovery_service_client::AggregatedDiscoveryServiceClient,
+    DiscoveryRequest, DiscoveryResponse,
+};
+use envoy_types::pb::google::rpc::Status;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use once_cell::sync::Lazy;
+use pingora::lb::discovery::ServiceDiscovery;
+use pingora::lb::{Backend, Backends};
+use pingora::protocols::l4::socket::SocketAddr;
+use pingora::server::ShutdownWatch;
+use pingora::services::background::BackgroundService;
+use prost::Message;
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use tokio::time::interval;
+use tracing::{debug, error, info, warn};
+
+const CLUSTER_TYPE_URL: &str =
+    "type.googleapis.com/envoy.config.cluster.v3.Cluster";
+const ENDPOINT_TYPE_URL: &str =
+    "type.googleapis.com/envoy.config.endpoint.v3.ClusterLoadAssignment";
+const WATCH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+// the code of invalid argument
+const GRPC_INVALID_ARGUMENT: i32 = 3;
+
+// the endpoints of cluster from eds
+static XDS_ENDPOINTS: Lazy<RwLock<AHashMap<String, BTreeSet<Backend>>>> =
+    Lazy::new(|| RwLock::new(AHashMap::new()));
+// the clusters watched by upstreams
+static XDS_WATCHED_CLUSTERS: Lazy<Mutex<BTreeSet<String>>> =
+    Lazy::new(|| Mutex::new(BTreeSet::new()));
+static XDS_WATCHED_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn watch_clusters(clusters: &[String]) {
+    let Ok(mut watched) = XDS_WATCHED_CLUSTERS.lock() else {
+        return;
+    };
+    let mut changed = false;
+    for cluster in clusters {
+        changed = watched.insert(cluster.to_string()) || changed;
+    }
+    if changed {
+        XDS_WATCHED_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn get_watched_clusters() -> Vec<String> {
+    XDS_WATCHED_CLUSTERS
+        .lock()
+        .map(|watched| watched.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Converts the endpoints of cluster load assignment to backends,
+/// the unhealthy, draining and timeout endpoints are ignored.
+fn to_backends(assignment: &ClusterLoadAssignment) -> BTreeSet<Backend> {
+    let mut backends = BTreeSet::new();
+    for locality in assignment.endpoints.iter() {
+        for lb_endpoint in locality.lb_endpoints.iter() {
+            if [
+                HealthStatus::Unhealthy,
+                HealthStatus::Draining,
+                HealthStatus::Timeout,
+            ]
+            .map(|status| status as i32)
+            .contains(&lb_endpoint.health_status)
+            {
+                continue;
+            }
+            let Some(lb_endpoint::HostIdentifier::Endpoint(endpoint)) =
+                &lb_endpoint.host_identifier
+            else {
+                continue;
+            };
+            let Some(address::Address::SocketAddress(socket_address)) =
+                endpoint
+                    .address
+                    .as_ref()
+                    .and_then(|item| item.address.as_ref())
+            else {
+                continue;
+            };
+            let Some(socket_address::PortSpecifier::PortValue(port)) =
+                socket_address.port_specifier
+            else {
+                continue;
+            };
+            // only ip address is supported
+            let Ok(ip) = socket_address.address.parse::<IpAddr>() else {
+                warn!(
+                    cluster = assignment.cluster_name,
+                    address = socket_address.address,
+                    "xds endpoint address is not ip"
+                );
+                continue;
+            };
+            let weight = lb_endpoint.load_balancing_weight.unwrap_or(1).max(1);
+            backends.insert(Backend {
+                addr: SocketAddr::Inet(std::net::SocketAddr::new(
+                    ip,
+                    port as u16,
+                )),
+                weight: weight as usize,
+            });
+        }
+    }
+    backends
+}
+
+struct Xds {
+    clusters: Vec<String>,
+}
+
+#[async_trait]
+impl ServiceDiscovery for Xds {
+    async fn discover(
+        &self,
+    ) -> pingora::Result<(BTreeSet<Backend>, HashMap<u64, bool>)> {
+        let mut upstreams = BTreeSet::new();
+        if let Ok(endpoints) = XDS_ENDPOINTS.read() {
+            for cluster in self.clusters.iter() {
+                if let Some(backends) = endpoints.get(cluster) {
+                    upstreams.extend(backends.iter().cloned());
+                }
+            }
+        }
+        debug!(
+            clusters = format!("{:?}", self.clusters),
+            count = upstreams.len(),
+            "xds discover"
+        );
+        // no readiness
+        Ok((upstreams, HashMap::new()))
+    }
+}
+
+/// Create a xds discovery, the addrs are the cluster names of xds,
+/// and the endpoints are pushed by the xds management server.
+pub fn new_xds_discover_backends(addrs: &[String]) -> Result<Backends> {
+    let clusters: Vec<String> = addrs
+        .iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect();
+    if clusters.is_empty() {
+        return Err(Error::Invalid {
+            message: "xds cluster is empty".to_string(),
+        });
+    }
+    watch_clusters(&clusters);
+    Ok(Backends::new(Box::new(Xds { clusters })))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct XdsServiceParams {
+    // the address of xds management server, e.g. http://istiod:15010
+    pub server: String,
+    pub node_id: String,
+    pub node_cluster: String,
+}
+
+pub struct XdsService {
+    params: XdsServiceParams,
+}
+
+/// Create a xds service, it subscribes the clusters and endpoints
+/// from xds management server by aggregated discovery service.
+pub fn new_xds_service(params: XdsServiceParams) -> XdsService {
+    XdsService { params }
+}
+
+impl XdsService {
+    fn new_request(
+        &self,
+        type_url: &str,
+        resource_names: Vec<String>,
+        version_info: &str,
+        response_nonce: &str,
+        error_detail: Option<Status>,
+    ) -> DiscoveryRequest {
+        DiscoveryRequest {
+            version_info: version_info.to_string(),
+            node: Some(Node {
+                id: self.params.node_id.clone(),
+                cluster: self.params.node_cluster.clone(),
+                ..Default::default()
+            }),
+            resource_names,
+            type_url: type_url.to_string(),
+            response_nonce: response_nonce.to_string(),
+            error_detail,
+            ..Default::default()
+        }
+    }
+    fn handle_clusters(&self, resp: &DiscoveryResponse) -> Result<()> {
+        let mut names = vec![];
+        for resource in resp.resources.iter() {
+            let cluster =
+                Cluster::decode(&resource.value[..]).map_err(|e| {
+                    Error::Invalid {
+                        message: e.to_string(),
+                    }
+                })?;
+            names.push(cluster.name);
+        }
+        for cluster in get_watched_clusters() {
+            if !names.contains(&cluster) {
+                warn!(cluster, "xds cluster is not found");
+            }
+        }
+        info!(count = names.len(), "xds clusters are updated");
+        Ok(())
+    }
+    fn handle_endpoints(&self, resp: &DiscoveryResponse) -> Result<()> {
+        let mut assignments = vec![];
+        for resource in resp.resources.iter() {
+            let assignment = ClusterLoadAssignment::decode(&resource.value[..])
+                .map_err(|e| Error::Invalid {
+                    message: e.to_string(),
+                })?;
+            assignments.push(assignment);
+        }
+        let Ok(mut endpoints) = XDS_ENDPOINTS.write() else {
+            return Ok(());
+        };
+        for assignment in assignments.iter() {
+            let backends = to_backends(assignment);
+            info!(
+                cluster = assignment.cluster_name,
+                count = backends.len(),
+                "xds endpoints are updated"
+            );
+            endpoints.insert(assignment.cluster_name.clone(), backends);
+        }
+        Ok(())
+    }
+    fn subscribe_endpoints(
+        &self,
+        sender: &UnboundedSender<DiscoveryRequest>,
+        versions: &AHashMap<String, (String, String)>,
+    ) -> Result<()> {
+        let (version_info, nonce) =
+            versions.get(ENDPOINT_TYPE_URL).cloned().unwrap_or_default();
+        sender
+            .unbounded_send(self.new_request(
+                ENDPOINT_TYPE_URL,
+                get_watched_clusters(),
+                &version_info,
+                &nonce,
+                None,
+            ))
+            .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })
+    }
+    /// Subscribes the resources of xds until the stream is closed or shutdown.
+    async fn run(&self, shutdown: &mut ShutdownWatch) -> Result<()> {
+        let mut client = AggregatedDiscoveryServiceClient::connect(
+            self.params.server.clone(),
+        )
+        .await
+        .map_err(|e| Error::Invalid {
+            message: e.to_string(),
+        })?;
+        let (sender, receiver) = unbounded();
+        // the accepted (version, nonce) of each resource type
+        let mut versions: AHashMap<String, (String, String)> = AHashMap::new();
+        sender
+            .unbounded_send(self.new_request(
+                CLUSTER_TYPE_URL,
+                vec![],
+                "",
+                "",
+                None,
+            ))
+            .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        let mut watched_version = XDS_WATCHED_VERSION.load(Ordering::Relaxed);
+        self.subscribe_endpoints(&sender, &versions)?;
+
+        let mut stream = client
+            .stream_aggregated_resources(receiver)
+            .await
+            .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?
+            .into_inner();
+        info!(server = self.params.server, "xds stream is connected");
+
+        let mut check_interval = interval(WATCH_CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    return Ok(());
+                }
+                _ = check_interval.tick() => {
+                    // the upstreams of new config may watch other clusters
+                    let version = XDS_WATCHED_VERSION.load(Ordering::Relaxed);
+                    if version != watched_version {
+                        watched_version = version;
+                        self.subscribe_endpoints(&sender, &versions)?;
+                    }
+                }
+                resp = stream.message() => {
+                    let Some(resp) = resp.map_err(|e| Error::Invalid {
+                        message: e.to_string(),
+                    })?
+                    else {
+                        return Ok(());
+                    };
+                    let result = match resp.type_url.as_str() {
+                        CLUSTER_TYPE_URL => self.handle_clusters(&resp),
+                        ENDPOINT_TYPE_URL => self.handle_endpoints(&resp),
+                        _ => Ok(()),
+                    };
+                    let (mut version_info, _) = versions
+                        .get(&resp.type_url)
+                        .cloned()
+                        .unwrap_or_default();
+                    // ack with the new version, or nack with the previous version
+                    let error_detail = match result {
+                        Ok(()) => {
+                            version_info.clone_from(&resp.version_info);
+                            None
+                        },
+                        Err(e) => {
+                            error!(
+                                error = e.to_string(),
+                                type_url = resp.type_url,
+                                "xds response is invalid"
+                            );
+                            Some(Status {
+                                code: GRPC_INVALID_ARGUMENT,
+                                message: e.to_string(),
+                                ..Default::default()
+                            })
+                        },
+                    };
+                    versions.insert(
+                        resp.type_url.clone(),
+                        (version_info.clone(), resp.nonce.clone()),
+                    );
+                    let resource_names = if resp.type_url == ENDPOINT_TYPE_URL {
+                        get_watched_clusters()
+                    } else {
+                        vec![]
+                    };
+                    sender
+                        .unbounded_send(self.new_request(
+                            &resp.type_url,
+                            resource_names,
+                            &version_info,
+                            &resp.nonce,
+                            error_detail,
+                        ))
+                        .map_err(|e| Error::Invalid {
+                            message: e.to_string(),
+                        })?;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BackgroundService for XdsService {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        info!(
+            server = self.params.server,
+            node_id = self.params.node_id,
+            "xds service is running"
+        );
+        loop {
+            if let Err(e) = self.run(&mut shutdown).await {
+                error!(
+                    error = e.to_string(),
+                    server = self.params.server,
+                    "xds stream fail"
+                );
+                webhook::send(webhook::SendNotificationParams {
+                    category:
+                        webhook::NotificationCategory::ServiceDiscoverFail,
+                    level: webhook::NotificationLevel::Warn,
+                    msg: format!("xds {}, error: {e}", self.params.server),
+                });
+            }
+            if *shutdown.borrow() {
+                break;
+            }
+            // reconnect after a while
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    break;
+                }
+                _ = tokio::time::sleep(RETRY_INTERVAL) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_watched_clusters, new_xds_discover_backends, to_backends,
+        XDS_ENDPOINTS,
+    };
+    use envoy_types::pb::envoy::config::core::v3::{
+        address, socket_address, Address, HealthStatus, SocketAddress,
+    };
+    use envoy_types::pb::envoy::config::endpoint::v3::{
+        lb_endpoint, ClusterLoadAssignment, Endpoint, LbEndpoint,
+        LocalityLbEndpoints,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn new_lb_endpoint(
+        ip: &str,
+        port: u32,
+        health_status: HealthStatus,
+    ) -> LbEndpoint {
+        LbEndpoint {
+            host_identifier: Some(lb_endpoint::HostIdentifier::Endpoint(
+                Endpoint {
+                    address: Some(Address {
+                        address: Some(address::Address::SocketAddress(
+                            SocketAddress {
+                                address: ip.to_string(),
+                                port_specifier: Some(
+                                    socket_address::PortSpecifier::PortValue(
+                                        port,
+                                    ),
+                                ),
+                                ..Default::default()
+                            },
+                        )),
+                    }),
+                    ..Default::default()
+                },
+            )),
+            health_status: health_status as i32,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xds_discover() {
+        let assignment = ClusterLoadAssignment {
+            cluster_name: "outbound|80||httpbin".to_string(),
+            endpoints: vec![LocalityLbEndpoints {
+                lb_endpoints: vec![
+                    new_lb_endpoint("10.0.0.1", 8080, HealthStatus::Healthy),
+                    new_lb_endpoint("10.0.0.2", 8080, HealthStatus::Unknown),
+                    new_lb_endpoint("10.0.0.3", 8080, HealthStatus::Draining),
+                    new_lb_endpoint("httpbin", 8080, HealthStatus::Healthy),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let backends = to_backends(&assignment);
+        assert_eq!(
+            "10.0.0.1:8080,10.0.0.2:8080",
+            backends
+                .iter()
+                .map(|item| item.addr.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+
+        XDS_ENDPOINTS
+            .write()
+            .unwrap()
+            .insert(assignment.cluster_name.clone(), backends);
+        let backends =
+            new_xds_discover_backends(&["outbound|80||httpbin".to_string()])
+                .unwrap();
+        assert_eq!(
+            true,
+            get_watched_clusters()
+                .contains(&"outbound|80||httpbin".to_string())
+        );
+        backends.update().await.unwrap();
+        assert_eq!(2, backends.get_backend().len());
+
+        assert_eq!(true, new_xds_discover_backends(&[]).is_err());
+    }
+}
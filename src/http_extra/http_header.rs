@@ -12,8 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::state::{get_hostname, State};
-use crate::util;
+use super::variable::{format_variables, has_variables};
+use crate::state::State;
 use bytes::BytesMut;
 use http::header;
 use http::{HeaderName, HeaderValue};
@@ -23,10 +23,6 @@ use snafu::{ResultExt, Snafu};
 use std::str::FromStr;
 
 pub const HOST_NAME_TAG: &[u8] = b"$hostname";
-const REMOTE_ADDR_TAG: &[u8] = b"$remote_addr";
-const PROXY_ADD_FORWARDED_TAG: &[u8] = b"$proxy_add_x_forwarded_for";
-const HTTP_ORIGIN_TAG: &[u8] = b"$http_origin";
-const UPSTREAM_ADDR_TAG: &[u8] = b"$upstream_addr";
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -59,6 +55,12 @@ pub fn convert_header(value: &str) -> Result<Option<HttpHeader>> {
     }
 }
 
+/// Converts the dynamic header value for the request, the value supports:
+/// 1. `:xxx`, the value of context, e.g. `:upstream_addr`
+/// 2. `$xxx` or `${xxx}`, the variables of request, e.g. `$remote_addr`,
+///    and it can be mixed with static text, e.g. `${scheme}://$host`
+///
+/// Returns `None` if the converted value is empty.
 #[inline]
 pub fn convert_header_value(
     value: &HeaderValue,
@@ -66,60 +68,36 @@ pub fn convert_header_value(
     ctx: &State,
 ) -> Option<HeaderValue> {
     let buf = value.as_bytes();
-    match buf {
-        HOST_NAME_TAG => {
-            return HeaderValue::from_str(&get_hostname()).ok();
-        },
-        REMOTE_ADDR_TAG => {
-            if let Some(remote_addr) = &ctx.remote_addr {
-                return HeaderValue::from_str(remote_addr).ok();
-            }
-        },
-        UPSTREAM_ADDR_TAG => {
-            if !ctx.upstream_address.is_empty() {
-                return HeaderValue::from_str(&ctx.upstream_address).ok();
-            }
-        },
-        PROXY_ADD_FORWARDED_TAG => {
-            if let Some(remote_addr) = &ctx.remote_addr {
-                let value = if let Some(value) = session
-                    .get_header(util::HTTP_HEADER_X_FORWARDED_FOR.clone())
-                {
-                    format!(
-                        "{}, {}",
-                        value.to_str().unwrap_or_default(),
-                        remote_addr
-                    )
-                } else {
-                    remote_addr.to_string()
-                };
-                return HeaderValue::from_str(&value).ok();
-            }
-        },
-        HTTP_ORIGIN_TAG => {
-            return session.get_header("origin").cloned();
-        },
-        _ => {
-            if buf.starts_with(b"$") {
-                if let Ok(value) = std::env::var(
-                    std::str::from_utf8(&buf[1..buf.len()]).unwrap_or_default(),
-                ) {
-                    return HeaderValue::from_str(&value).ok();
-                }
-            } else if buf.starts_with(b":") {
-                let mut value = BytesMut::with_capacity(20);
-                value = ctx.append_value(
-                    value,
-                    std::str::from_utf8(&buf[1..buf.len()]).unwrap_or_default(),
-                );
-                if !value.is_empty() {
-                    return HeaderValue::from_bytes(&value).ok();
-                }
-            }
-        },
-    };
-    // not match return none
-    None
+    if buf.starts_with(b":") {
+        let mut value = BytesMut::with_capacity(20);
+        value = ctx.append_value(
+            value,
+            std::str::from_utf8(&buf[1..buf.len()]).unwrap_or_default(),
+        );
+        if !value.is_empty() {
+            return HeaderValue::from_bytes(&value).ok();
+        }
+        return None;
+    }
+    let value = value.to_str().ok()?;
+    if !has_variables(value) {
+        return None;
+    }
+    let value = format_variables(value, session.req_header(), ctx);
+    if value.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&value).ok()
+}
+
+/// Returns `true` if the header value is a tag(`:xxx`) or contains
+/// variables(`$xxx`), which should be converted by `convert_header_value`
+/// for each request.
+#[inline]
+pub fn is_dynamic_header_value(value: &HeaderValue) -> bool {
+    let buf = value.as_bytes();
+    buf.starts_with(b":")
+        || value.to_str().map(has_variables).unwrap_or_default()
 }
 
 /// Convert string slice to http headers.
@@ -178,13 +156,36 @@ pub static HTTP_HEADER_TRANSFER_CHUNKED: Lazy<HttpHeader> = Lazy::new(|| {
 pub static HTTP_HEADER_NAME_X_REQUEST_ID: Lazy<HeaderName> =
     Lazy::new(|| HeaderName::from_str("X-Request-Id").unwrap());
 
+pub static HTTP_HEADER_NAME_X_CACHE_STATUS: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_str("X-Cache-Status").unwrap());
+
+pub static HTTP_HEADER_NAME_X_CACHE_LOOKUP: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_str("X-Cache-Lookup").unwrap());
+
+pub static HTTP_HEADER_NAME_X_CACHE_LOCK: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_str("X-Cache-Lock").unwrap());
+
+pub static HTTP_HEADER_NAME_X_PINGAP_DEBUG: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_str("X-Pingap-Debug").unwrap());
+
+pub static HTTP_HEADER_NAME_X_PINGAP_JOURNAL: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_str("X-Pingap-Journal").unwrap());
+
+pub static HTTP_HEADER_NAME_X_ACCEL_REDIRECT: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_str("X-Accel-Redirect").unwrap());
+
+pub static HTTP_HEADER_NAME_X_SENDFILE: Lazy<HeaderName> =
+    Lazy::new(|| HeaderName::from_str("X-Sendfile").unwrap());
+
 #[cfg(test)]
 mod tests {
     use crate::state::State;
 
     use super::{
-        convert_header_value, convert_headers, HTTP_HEADER_CONTENT_HTML,
-        HTTP_HEADER_CONTENT_JSON, HTTP_HEADER_NAME_X_REQUEST_ID,
+        convert_header_value, convert_headers, is_dynamic_header_value,
+        HTTP_HEADER_CONTENT_HTML, HTTP_HEADER_CONTENT_JSON,
+        HTTP_HEADER_NAME_X_CACHE_LOCK, HTTP_HEADER_NAME_X_CACHE_LOOKUP,
+        HTTP_HEADER_NAME_X_CACHE_STATUS, HTTP_HEADER_NAME_X_REQUEST_ID,
         HTTP_HEADER_NO_CACHE, HTTP_HEADER_NO_STORE,
         HTTP_HEADER_TRANSFER_CHUNKED, HTTP_HEADER_WWW_AUTHENTICATE,
     };
@@ -334,6 +335,22 @@ mod tests {
             &State::default(),
         );
         assert_eq!(false, value.is_some());
+
+        let headers = ["Host: github.com"].join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let value = convert_header_value(
+            &HeaderValue::from_str("${scheme}://$host$uri").unwrap(),
+            &session,
+            &State::default(),
+        );
+        assert_eq!(
+            "http://github.com/vicanso/pingap",
+            value.unwrap().to_str().unwrap()
+        );
     }
 
     #[test]
@@ -396,5 +413,30 @@ mod tests {
             "x-request-id",
             format!("{}", HTTP_HEADER_NAME_X_REQUEST_ID.to_string(),)
         );
+        assert_eq!("x-cache-status", HTTP_HEADER_NAME_X_CACHE_STATUS.as_str());
+        assert_eq!("x-cache-lookup", HTTP_HEADER_NAME_X_CACHE_LOOKUP.as_str());
+        assert_eq!("x-cache-lock", HTTP_HEADER_NAME_X_CACHE_LOCK.as_str());
+    }
+
+    #[test]
+    fn test_is_dynamic_header_value() {
+        assert_eq!(
+            true,
+            is_dynamic_header_value(&HeaderValue::from_static("$hostname"))
+        );
+        assert_eq!(
+            true,
+            is_dynamic_header_value(&HeaderValue::from_static(":location"))
+        );
+        assert_eq!(
+            true,
+            is_dynamic_header_value(&HeaderValue::from_static(
+                "${scheme}://$host"
+            ))
+        );
+        assert_eq!(
+            false,
+            is_dynamic_header_value(&HeaderValue::from_static("pingap"))
+        );
     }
 }
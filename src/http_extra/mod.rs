@@ -14,6 +14,8 @@
 
 mod http_header;
 mod http_response;
+mod variable;
 
 pub use http_header::*;
 pub use http_response::*;
+pub use variable::*;
@@ -0,0 +1,264 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::state::{get_hostname, State};
+use crate::util;
+use bytes::BytesMut;
+use pingora::http::RequestHeader;
+
+/// Gets the value of variable(without `$`) for the request, the variables
+/// are resolved in order:
+/// 1. the built-in variables, e.g. `host`, `remote_addr` and `request_id`
+/// 2. `http_xxx`, `cookie_xxx` and `arg_xxx` of request
+/// 3. the values of context, e.g. `upstream_addr`, `upstream_response_time`
+/// 4. the variables set by plugins, e.g. `jwt_subject`, `geoip_country`
+/// 5. the environment variables
+pub fn get_variable_value(
+    name: &str,
+    req_header: &RequestHeader,
+    ctx: &State,
+) -> Option<String> {
+    let value = match name {
+        "host" => util::get_host(req_header).map(|v| v.to_string()),
+        "hostname" => Some(get_hostname()),
+        "remote_addr" => ctx.remote_addr.clone(),
+        "client_ip" => ctx.client_ip.clone().or_else(|| {
+            get_forwarded_ip(req_header).or_else(|| ctx.remote_addr.clone())
+        }),
+        "request_id" => ctx.request_id.clone(),
+        "request_method" => Some(req_header.method.to_string()),
+        "request_uri" => Some(req_header.uri.to_string()),
+        "uri" => Some(req_header.uri.path().to_string()),
+        "args" => req_header.uri.query().map(|v| v.to_string()),
+        "scheme" => {
+            let scheme = if ctx.tls_version.is_some() {
+                "https"
+            } else {
+                "http"
+            };
+            Some(scheme.to_string())
+        },
+        "status" => ctx.status.map(|v| v.as_str().to_string()),
+        "proxy_add_x_forwarded_for" => {
+            ctx.remote_addr.as_ref().map(|remote_addr| {
+                if let Some(value) = util::get_req_header_value(
+                    req_header,
+                    util::HTTP_HEADER_X_FORWARDED_FOR.as_str(),
+                ) {
+                    format!("{value}, {remote_addr}")
+                } else {
+                    remote_addr.to_string()
+                }
+            })
+        },
+        _ => None,
+    };
+    if value.is_some() {
+        return value;
+    }
+    if let Some(key) = name.strip_prefix("http_") {
+        return util::get_req_header_value(req_header, &key.replace('_', "-"))
+            .map(|v| v.to_string());
+    }
+    if let Some(key) = name.strip_prefix("cookie_") {
+        return util::get_cookie_value(req_header, key).map(|v| v.to_string());
+    }
+    if let Some(key) = name.strip_prefix("arg_") {
+        return util::get_query_value(req_header, key).map(|v| v.to_string());
+    }
+    let buf = ctx.append_value(BytesMut::new(), name);
+    if !buf.is_empty() {
+        return Some(String::from_utf8_lossy(&buf).to_string());
+    }
+    std::env::var(name).ok()
+}
+
+fn get_forwarded_ip(req_header: &RequestHeader) -> Option<String> {
+    if let Some(value) = util::get_req_header_value(
+        req_header,
+        util::HTTP_HEADER_X_FORWARDED_FOR.as_str(),
+    ) {
+        if let Some(ip) = value.split(',').next() {
+            return Some(ip.trim().to_string());
+        }
+    }
+    util::get_req_header_value(req_header, util::HTTP_HEADER_X_REAL_IP.as_str())
+        .map(|v| v.to_string())
+}
+
+/// Parses the variable name from the start of value(after `$`),
+/// supports `name` and `{name}`, returns the name and its length.
+fn parse_variable_name(value: &str) -> Option<(&str, usize)> {
+    if let Some(rest) = value.strip_prefix('{') {
+        let end = rest.find('}')?;
+        let name = &rest[..end];
+        if name.is_empty() {
+            return None;
+        }
+        return Some((name, end + 2));
+    }
+    let end = value
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(value.len());
+    let name = &value[..end];
+    // `$1` is the capture group of regexp
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((name, end))
+}
+
+/// Replaces the variables of value by the resolve function,
+/// the variable will be kept if the function returns `None`.
+pub fn replace_variables<F>(value: &str, resolve: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        let Some((name, size)) = parse_variable_name(rest) else {
+            result.push('$');
+            continue;
+        };
+        if let Some(value) = resolve(name) {
+            result.push_str(&value);
+        } else {
+            result.push('$');
+            result.push_str(&rest[..size]);
+        }
+        rest = &rest[size..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Returns `true` if the value contains variables(`$name` or `${name}`).
+#[inline]
+pub fn has_variables(value: &str) -> bool {
+    value
+        .match_indices('$')
+        .any(|(index, _)| parse_variable_name(&value[index + 1..]).is_some())
+}
+
+/// Formats the value with the variables of request,
+/// the unknown variable will be replaced as empty string.
+pub fn format_variables(
+    value: &str,
+    req_header: &RequestHeader,
+    ctx: &State,
+) -> String {
+    replace_variables(value, |name| {
+        Some(get_variable_value(name, req_header, ctx).unwrap_or_default())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        format_variables, get_variable_value, has_variables, replace_variables,
+    };
+    use crate::state::State;
+    use http::StatusCode;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_replace_variables() {
+        assert_eq!(true, has_variables("$host"));
+        assert_eq!(true, has_variables("/api/${uri}"));
+        assert_eq!(false, has_variables("/$1"));
+        assert_eq!(false, has_variables("$"));
+        assert_eq!(false, has_variables("pingap"));
+
+        let resolve = |name: &str| {
+            if name == "host" {
+                Some("pingap.io".to_string())
+            } else {
+                None
+            }
+        };
+        assert_eq!(
+            "https://pingap.io/$1?${unknown}$",
+            replace_variables("https://${host}/$1?${unknown}$", resolve)
+        );
+        assert_eq!("pingap.io-$id", replace_variables("$host-$id", resolve));
+    }
+
+    #[tokio::test]
+    async fn test_get_variable_value() {
+        let headers = [
+            "Host: github.com",
+            "X-Forwarded-For: 1.1.1.1, 2.2.2.2",
+            "Cookie: uid=ZHGG",
+            "X-Custom: pingap",
+        ]
+        .join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let req_header = session.req_header();
+
+        let mut ctx = State {
+            remote_addr: Some("10.1.1.1".to_string()),
+            request_id: Some("123".to_string()),
+            status: Some(StatusCode::OK),
+            upstream_address: "10.1.1.2:8001".to_string(),
+            ..Default::default()
+        };
+        ctx.add_variable("geoip_country", "CN");
+
+        let get = |name: &str| {
+            get_variable_value(name, req_header, &ctx).unwrap_or_default()
+        };
+        assert_eq!("github.com", get("host"));
+        assert_eq!("10.1.1.1", get("remote_addr"));
+        assert_eq!("1.1.1.1", get("client_ip"));
+        assert_eq!("123", get("request_id"));
+        assert_eq!("GET", get("request_method"));
+        assert_eq!("/vicanso/pingap?size=1", get("request_uri"));
+        assert_eq!("/vicanso/pingap", get("uri"));
+        assert_eq!("size=1", get("args"));
+        assert_eq!("http", get("scheme"));
+        assert_eq!("200", get("status"));
+        assert_eq!(
+            "1.1.1.1, 2.2.2.2, 10.1.1.1",
+            get("proxy_add_x_forwarded_for")
+        );
+        assert_eq!("pingap", get("http_x_custom"));
+        assert_eq!("ZHGG", get("cookie_uid"));
+        assert_eq!("1", get("arg_size"));
+        assert_eq!("10.1.1.2:8001", get("upstream_addr"));
+        assert_eq!("CN", get("geoip_country"));
+        assert_eq!(
+            true,
+            get_variable_value("not_exists_variable", req_header, &ctx)
+                .is_none()
+        );
+
+        assert_eq!(
+            "github.com:CN:",
+            format_variables(
+                "$host:${geoip_country}:$not_exists_variable",
+                req_header,
+                &ctx
+            )
+        );
+    }
+}
@@ -14,6 +14,7 @@
 
 pub mod acme;
 pub mod cache;
+pub mod cluster;
 pub mod config;
 pub mod discovery;
 pub mod http_extra;
@@ -22,6 +23,7 @@ pub mod logger;
 pub mod plugin;
 pub mod proxy;
 pub mod service;
+pub mod sink;
 pub mod state;
 pub mod util;
 pub mod webhook;
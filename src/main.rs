@@ -14,23 +14,31 @@
 
 use crate::acme::{new_lets_encrypt_service, new_tls_validity_service};
 use crate::config::ETCD_PROTOCOL;
-use crate::service::new_auto_restart_service;
-use clap::Parser;
+use crate::service::{
+    new_anomaly_detection_service, new_auto_restart_service,
+    new_cache_prefetch_service, new_listen_fds_service,
+    new_metrics_push_service, new_schedule_service, new_systemd_notify_service,
+    CachePrefetchParams, DEFAULT_ANOMALY_SENSITIVITY,
+};
+use clap::{Parser, Subcommand};
 use config::{PingapConf, PluginConf};
 use crossbeam_channel::Sender;
 use pingora::server;
 use pingora::server::configuration::Opt;
 use pingora::services::background::background_service;
-use proxy::{new_upstream_health_check_task, Server, ServerConf};
+use proxy::{
+    new_upstream_health_check_task, ClickHouseParams, Server, ServerConf,
+};
 use state::get_start_time;
 use std::error::Error;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 mod acme;
 mod cache;
+mod cluster;
 mod config;
 mod discovery;
 mod http_extra;
@@ -41,6 +49,7 @@ mod proxy;
 #[cfg(feature = "pyro")]
 mod pyro;
 mod service;
+mod sink;
 mod state;
 mod util;
 mod webhook;
@@ -88,6 +97,34 @@ struct Args {
     /// Whether this server should try to auto reload configuration
     #[arg(long)]
     autoreload: bool,
+    /// Replay the traffic record file and exit
+    #[arg(long)]
+    replay: Option<String>,
+    /// The target of replay, it's the url or the name of upstream
+    #[arg(long)]
+    replay_target: Option<String>,
+    /// The requests per second of replay
+    #[arg(long)]
+    replay_rate: Option<u32>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Upgrade the running server to the new binary without downtime
+    ///
+    /// The config is validated by the new binary, and the listeners are
+    /// transferred to the new process. The old process drains after the
+    /// new one is ready, otherwise it's rolled back to the current binary.
+    Upgrade {
+        /// The new binary, the current binary is used if not set
+        #[arg(long)]
+        binary: Option<String>,
+        /// The timeout of waiting for the new process to be ready
+        #[arg(long, default_value = "30s")]
+        ready_timeout: humantime::Duration,
+    },
 }
 
 fn new_server_conf(
@@ -103,6 +140,12 @@ fn new_server_conf(
         daemon: args.daemon,
         ..Default::default()
     };
+    // the privileges are dropped by pingap after the listeners are bound,
+    // rather than switching the user before binding in daemon mode
+    if state::is_privilege_drop_enabled(basic_conf.user.as_deref()) {
+        server_conf.user = None;
+        server_conf.group = None;
+    }
     if let Some(value) = basic_conf.grace_period {
         server_conf.grace_period_seconds = Some(value.as_secs());
     }
@@ -130,6 +173,147 @@ fn new_server_conf(
     server_conf
 }
 
+/// Gets the paths which are allowed to read and write in sandbox,
+/// they are the directories of config, log, cache, pid file and
+/// upgrade sock, and the paths of `sandbox_paths`.
+fn get_sandbox_paths(
+    args: &Args,
+    conf: &PingapConf,
+    server_conf: &server::configuration::ServerConf,
+) -> Vec<String> {
+    let get_dir = |value: &str| {
+        let path = Path::new(value);
+        if path.is_dir() {
+            return Some(value.to_string());
+        }
+        path.parent()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .filter(|dir| !dir.is_empty())
+    };
+    let mut paths = vec![];
+    if !args.conf.starts_with(ETCD_PROTOCOL) {
+        paths.extend(get_dir(&util::resolve_path(&args.conf)));
+    }
+    if let Some(log) = &args.log {
+        paths.extend(get_dir(&util::resolve_path(log)));
+    }
+    if let Some(dir) = &conf.basic.cache_directory {
+        paths.push(util::resolve_path(dir));
+    }
+    paths.extend(get_dir(&server_conf.pid_file));
+    paths.extend(get_dir(&server_conf.upgrade_sock));
+    // the certificate of acme is written after it's renewed
+    for cert in conf.certificates.values() {
+        if let Some(file) = &cert.certificate_file {
+            paths.extend(get_dir(&util::resolve_path(file)));
+        }
+    }
+    for path in conf.basic.sandbox_paths.iter().flatten() {
+        paths.push(util::resolve_path(path));
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Gets the paths which are only allowed to read in sandbox, they are
+/// the files of tls certificates, the directories of static files,
+/// error templates and sendfile root.
+fn get_sandbox_readable_paths(
+    conf: &PingapConf,
+    plugin_confs: &[(String, PluginConf)],
+) -> Vec<String> {
+    let get_file = |value: &Option<String>| {
+        value
+            .as_deref()
+            .filter(|value| !util::is_pem(value) && util::is_pem_file(value))
+            .map(util::resolve_path)
+    };
+    let mut paths = vec![];
+    for cert in conf.certificates.values() {
+        paths.extend(get_file(&cert.tls_cert));
+        paths.extend(get_file(&cert.tls_key));
+        paths.extend(get_file(&cert.tls_chain));
+    }
+    for server in conf.servers.values() {
+        paths.extend(get_file(&server.tls_cert));
+        paths.extend(get_file(&server.tls_key));
+    }
+    if let Some(dir) = &conf.basic.error_template_dir {
+        paths.push(util::resolve_path(dir));
+    }
+    for location in conf.locations.values() {
+        if let Some(dir) = &location.sendfile_root {
+            paths.push(util::resolve_path(dir));
+        }
+    }
+    let directory = config::PluginCategory::Directory.to_string();
+    for (_, plugin) in plugin_confs.iter() {
+        let category = plugin.get("category").and_then(|value| value.as_str());
+        if category != Some(directory.as_str()) {
+            continue;
+        }
+        let dir = plugin.get("path").and_then(|value| value.as_str());
+        paths.extend(dir.map(util::resolve_path));
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+fn get_conf_path(args: &Args) -> String {
+    if args.conf.starts_with(ETCD_PROTOCOL) {
+        args.conf.clone()
+    } else {
+        util::resolve_path(&args.conf)
+    }
+}
+
+/// Gets the args of the new process which runs in the background,
+/// the config and upgrade flag are excluded.
+fn get_daemon_args(args: &Args) -> Vec<String> {
+    let mut new_args = vec!["-d".to_string()];
+    if let Some(log) = &args.log {
+        new_args.push(format!("--log={log}"));
+    }
+    if let Some(admin) = &args.admin {
+        new_args.push(format!("--admin={admin}"));
+    }
+    if args.autorestart {
+        new_args.push("--autorestart".to_string());
+    }
+    new_args
+}
+
+fn run_upgrade(
+    args: &Args,
+    binary: Option<String>,
+    ready_timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let (s, r) = crossbeam_channel::bounded(0);
+    get_config(args.conf.clone(), false, s);
+    let conf = r.recv()??;
+    let pid_file = conf
+        .basic
+        .pid_file
+        .clone()
+        .unwrap_or_else(|| format!("/tmp/{}.pid", util::get_pkg_name()));
+    let rollback_binary = std::env::current_exe()?;
+    let binary = binary
+        .map(|value| Path::new(&util::resolve_path(&value)).to_path_buf())
+        .unwrap_or_else(|| rollback_binary.clone());
+    let pid = state::upgrade_binary(&state::UpgradeParams {
+        binary,
+        rollback_binary,
+        conf: get_conf_path(args),
+        args: get_daemon_args(args),
+        pid_file,
+        ready_timeout,
+    })?;
+    println!("upgrade success, pid: {pid}");
+    Ok(())
+}
+
 fn get_config(
     conf: String,
     admin: bool,
@@ -214,16 +398,91 @@ fn run_admin_node(args: Args) -> Result<(), Box<dyn Error>> {
     my_server.run_forever();
 }
 
+fn run_replay(
+    args: &Args,
+    file: &str,
+    conf: &PingapConf,
+) -> Result<(), Box<dyn Error>> {
+    let target = args.replay_target.clone().unwrap_or_default();
+    let replay_target = if target.contains("://") {
+        proxy::ReplayTarget::from_url(&target)
+    } else {
+        let upstream = conf.upstreams.get(&target).ok_or_else(|| {
+            config::Error::Invalid {
+                message: format!("replay target({target}) is not found"),
+            }
+        })?;
+        proxy::ReplayTarget::from_upstream_conf(upstream)?
+    };
+    let params = proxy::ReplayParams {
+        file: file.to_string(),
+        target,
+        rate: args.replay_rate,
+        ..Default::default()
+    };
+    let status = tokio::runtime::Runtime::new()?
+        .block_on(proxy::replay_traffic(params, replay_target));
+    println!("{}", serde_json::to_string_pretty(&status)?);
+    Ok(())
+}
+
+/// Prints the report of preflight checks, and exits with its exit code
+/// if any check fails.
+fn handle_preflight_report(report: &config::PreflightReport, print: bool) {
+    if print || !report.success {
+        // use println because the log may not be initialized
+        println!(
+            "{}",
+            serde_json::to_string_pretty(report).unwrap_or_default()
+        );
+    }
+    if report.success {
+        info!(count = report.checks.len(), "preflight checks pass");
+        return;
+    }
+    for check in report.failures() {
+        error!(
+            category = format!("{:?}", check.category),
+            name = check.target.as_str(),
+            error = check.message.clone().unwrap_or_default(),
+            "preflight check fail"
+        );
+    }
+    std::process::exit(report.exit_code);
+}
+
 fn run() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
     if args.cp && args.admin.is_some() {
         return run_admin_node(args);
     }
+    if let Some(Command::Upgrade {
+        binary,
+        ready_timeout,
+    }) = args.command.clone()
+    {
+        return run_upgrade(&args, binary, ready_timeout.into());
+    }
+
+    // the environment of socket activation is read before any thread starts
+    let _ = state::get_activated_listeners();
 
     let (s, r) = crossbeam_channel::bounded(0);
     get_config(args.conf.clone(), args.admin.is_some(), s);
-    let conf = r.recv()??;
+    let conf = match r.recv()? {
+        Ok(conf) => conf,
+        Err(e) => {
+            handle_preflight_report(
+                &config::PreflightReport::new_config_fail(
+                    &args.conf,
+                    &e.to_string(),
+                ),
+                true,
+            );
+            return Err(e.into());
+        },
+    };
     logger::logger_try_init(logger::LoggerParams {
         capacity: conf.basic.log_buffered_lines.unwrap_or_default(),
         file: args.log.clone().unwrap_or_default(),
@@ -234,7 +493,27 @@ fn run() -> Result<(), Box<dyn Error>> {
     // since the cache will be initialized in validate function
     // so set the current conf first
     config::set_current_config(&conf);
-    conf.validate()?;
+    // the listeners are held by the running server when testing
+    // or upgrading, and they are not used by replay
+    // the listeners of systemd socket activation are bound already
+    let check_listeners = !args.test
+        && !args.upgrade
+        && args.replay.is_none()
+        && state::get_activated_listeners().is_empty();
+    handle_preflight_report(
+        &config::run_preflight(
+            &conf,
+            &config::PreflightParams { check_listeners },
+        ),
+        args.test,
+    );
+    let warnings = conf.get_warnings();
+    if !warnings.is_empty() {
+        for warning in warnings.iter() {
+            warn!(warning, "config warning");
+        }
+        warn!(count = warnings.len(), "config has warnings");
+    }
     let basic_conf = &conf.basic;
     config::set_app_name(&basic_conf.name.clone().unwrap_or_default());
 
@@ -250,10 +529,67 @@ fn run() -> Result<(), Box<dyn Error>> {
         info!("Validate config success");
         return Ok(());
     }
+    if let Some(file) = &args.replay {
+        return run_replay(&args, file, &conf);
+    }
 
     let auto_restart_check_interval = basic_conf
         .auto_restart_check_interval
         .map_or(Duration::from_secs(90), |item| item);
+    let slow_request_threshold = basic_conf.slow_request_threshold;
+    let slow_request_log = basic_conf.slow_request_log.clone();
+    let connection_log = basic_conf.connection_log.clone();
+    let traffic_record = basic_conf.traffic_record.clone();
+    let traffic_record_sample_rate =
+        basic_conf.traffic_record_sample_rate.unwrap_or(0.01);
+    let traffic_record_body_limit = basic_conf
+        .traffic_record_body_limit
+        .map(|value| value.as_u64() as usize);
+    let metrics_push = basic_conf.metrics_push.clone();
+    let metrics_push_interval = basic_conf
+        .metrics_push_interval
+        .unwrap_or(Duration::from_secs(10));
+    let metrics_push_labels =
+        basic_conf.metrics_push_labels.clone().unwrap_or_default();
+    let metrics_path_templates = basic_conf
+        .metrics_path_templates
+        .clone()
+        .unwrap_or_default();
+    let metrics_max_series = basic_conf.metrics_max_series;
+    let metrics_latency_buckets = basic_conf
+        .metrics_latency_buckets
+        .clone()
+        .unwrap_or_default();
+    let metrics_exemplars = basic_conf.metrics_exemplars.unwrap_or_default();
+    let metering = basic_conf.metering.clone();
+    let metering_interval = basic_conf
+        .metering_interval
+        .unwrap_or(Duration::from_secs(60));
+    let metering_consumers =
+        basic_conf.metering_consumers.clone().unwrap_or_default();
+    let sink = basic_conf.sink.clone();
+    let sink_batch_size = basic_conf.sink_batch_size;
+    let clickhouse =
+        basic_conf.clickhouse.clone().map(|url| ClickHouseParams {
+            url,
+            columns: basic_conf.clickhouse_columns.clone().unwrap_or_default(),
+            batch_size: basic_conf.clickhouse_batch_size,
+            interval: basic_conf
+                .clickhouse_flush_interval
+                .unwrap_or(Duration::from_secs(5)),
+            spool: basic_conf.clickhouse_spool.clone(),
+        });
+    let anomaly_detection = basic_conf.anomaly_detection.unwrap_or_default();
+    let anomaly_sensitivity = basic_conf
+        .anomaly_sensitivity
+        .unwrap_or(DEFAULT_ANOMALY_SENSITIVITY);
+    let cache_snapshot = basic_conf.cache_snapshot.clone();
+    let cache_prefetch_hits = basic_conf.cache_prefetch_hits;
+    let cache_prefetch_before = basic_conf
+        .cache_prefetch_before
+        .unwrap_or(Duration::from_secs(10));
+    let cache_prefetch_concurrency =
+        basic_conf.cache_prefetch_concurrency.unwrap_or(4);
 
     #[cfg(feature = "perf")]
     info!("Enable feature perf");
@@ -268,34 +604,28 @@ fn run() -> Result<(), Box<dyn Error>> {
         if let Ok(env) = std::env::var("RUST_LOG") {
             cmd.log_level = env;
         }
-        let conf_path = if args.conf.starts_with(ETCD_PROTOCOL) {
-            args.conf.clone()
-        } else {
-            util::resolve_path(&args.conf)
-        };
-
-        let mut new_args = vec![
-            format!("-c={conf_path}"),
-            "-d".to_string(),
-            "-u".to_string(),
-        ];
-        if let Some(log) = &args.log {
-            new_args.push(format!("--log={log}"));
-        }
-        if let Some(admin) = &args.admin {
-            new_args.push(format!("--admin={admin}"));
-        }
-        if args.autorestart {
-            new_args.push("--autorestart".to_string());
-        }
+        let mut new_args = vec![format!("-c={}", get_conf_path(&args))];
+        new_args.extend(get_daemon_args(&args));
+        new_args.push("-u".to_string());
         cmd.args = new_args;
         state::set_restart_process_command(cmd);
     }
 
     proxy::try_init_upstreams(&conf.upstreams)?;
-    proxy::try_init_locations(&conf.locations)?;
+    proxy::try_init_locations(&conf.get_inherited_locations())?;
     proxy::try_init_server_locations(&conf.servers, &conf.locations)?;
     let certificates = conf.certificates.clone();
+    // the locations of server are generated from the services of registry
+    let registries: Vec<(String, String)> = conf
+        .servers
+        .iter()
+        .filter_map(|(name, server)| {
+            server
+                .registry
+                .as_ref()
+                .map(|registry| (name.to_string(), registry.to_string()))
+        })
+        .collect();
 
     let opt = Opt {
         upgrade: args.upgrade,
@@ -307,6 +637,18 @@ fn run() -> Result<(), Box<dyn Error>> {
     let mut my_server = server::Server::new(Some(opt))?;
     my_server.configuration = Arc::new(new_server_conf(&args, &conf));
     my_server.sentry.clone_from(&basic_conf.sentry);
+    if basic_conf.sentry.is_some() {
+        proxy::init_error_tracking(proxy::ErrorTrackingParams {
+            sample_rate: basic_conf.sentry_sample_rate.unwrap_or(1.0),
+            burst_threshold: basic_conf
+                .sentry_burst_threshold
+                .unwrap_or_default(),
+            scrub_headers: basic_conf
+                .sentry_scrub_headers
+                .clone()
+                .unwrap_or_default(),
+        });
+    }
     my_server.bootstrap();
 
     #[cfg(feature = "pyro")]
@@ -316,13 +658,53 @@ fn run() -> Result<(), Box<dyn Error>> {
             pyro::new_agent_service(url),
         ));
     }
+    #[cfg(feature = "xds")]
+    if let Some(server) = &conf.basic.xds_server {
+        my_server.add_service(background_service(
+            "Xds",
+            discovery::new_xds_service(discovery::XdsServiceParams {
+                server: server.to_string(),
+                node_id: conf
+                    .basic
+                    .xds_node_id
+                    .clone()
+                    .unwrap_or_else(state::get_hostname),
+                node_cluster: conf
+                    .basic
+                    .xds_node_cluster
+                    .clone()
+                    .unwrap_or_else(|| "pingap".to_string()),
+            }),
+        ));
+    }
+    cluster::set_trusted_proxies(
+        conf.basic.trusted_proxies.as_deref().unwrap_or_default(),
+    );
+    if let Some(url) = &conf.basic.cluster {
+        match cluster::new_cluster_service(url) {
+            Ok(service) => {
+                my_server.add_service(background_service("Cluster", service));
+            },
+            Err(e) => {
+                error!(error = e.to_string(), "new cluster service fail");
+            },
+        }
+    }
 
-    let mut plugin_confs: Vec<(String, PluginConf)> = conf
-        .plugins
-        .iter()
-        .map(|(name, value)| (name.to_string(), value.clone()))
-        .collect();
+    // the config is validated, so the plugins can be resolved
+    let mut plugin_confs: Vec<(String, PluginConf)> = conf.resolve_plugins()?;
 
+    // the privileges are dropped and the sandbox is applied
+    // after all services are added
+    let drop_privileges =
+        state::is_privilege_drop_enabled(basic_conf.user.as_deref());
+    let user = basic_conf.user.clone().unwrap_or_default();
+    let group = basic_conf.group.clone();
+    let sandbox = basic_conf.sandbox.clone();
+    let sandbox_paths =
+        get_sandbox_paths(&args, &conf, &my_server.configuration);
+    let sandbox_readable_paths =
+        get_sandbox_readable_paths(&conf, &plugin_confs);
     let mut server_conf_list: Vec<ServerConf> = conf.into();
     if let Some(addr) = args.admin {
         let (server_conf, name, proxy_plugin_info) = parse_admin_plugin(&addr);
@@ -376,10 +758,21 @@ fn run() -> Result<(), Box<dyn Error>> {
     let mut certificate_info_list =
         proxy::try_init_certificates(&certificates)?;
 
+    let https_redirect_port = server_conf_list
+        .iter()
+        .find(|item| item.https_redirect)
+        .and_then(|item| item.get_port());
+    if exits_80_server && https_redirect_port.is_some() {
+        warn!("server listen 80 exists, https redirect server is ignored");
+    }
+    let auto_server_name = "lets encrypt".to_string();
     // no server listen 80 and lets encrypt domains is not empty
-    if !exits_80_server && enabled_lets_encrypt {
+    // or https redirect is enabled
+    if !exits_80_server
+        && (enabled_lets_encrypt || https_redirect_port.is_some())
+    {
         server_conf_list.push(ServerConf {
-            name: "lets encrypt".to_string(),
+            name: auto_server_name.clone(),
             addr: "0.0.0.0:80".to_string(),
             ..Default::default()
         });
@@ -392,12 +785,33 @@ fn run() -> Result<(), Box<dyn Error>> {
         if enabled_lets_encrypt && listen_80_port {
             ps.enable_lets_encrypt();
         }
+        if !exits_80_server && name == auto_server_name {
+            if let Some(port) = https_redirect_port {
+                ps.enable_https_redirect(port);
+            }
+        }
+        // the listeners are held by the old process when upgrading
+        if drop_privileges && !args.upgrade {
+            state::prebind_listeners(&server_conf.addr)?;
+        }
         let services = ps.run(&my_server.configuration)?;
-        my_server.add_service(services.lb);
+        // the listeners of systemd socket activation or bound before
+        // dropping privileges are used if matched
+        my_server.add_boxed_service(new_listen_fds_service(
+            services.lb,
+            &server_conf.addr,
+        ));
         if let Some(tls_cert_info) = services.tls_cert_info {
             certificate_info_list.push((name, tls_cert_info));
         }
     }
+    // the certificates of pem files are reloaded without restart
+    if proxy::has_watched_certificates() {
+        my_server.add_service(background_service(
+            "CertificateReload",
+            proxy::new_certificate_reload_service(),
+        ));
+    }
 
     if args.autorestart || args.autoreload {
         let only_hot_reload = !args.autorestart;
@@ -408,6 +822,11 @@ fn run() -> Result<(), Box<dyn Error>> {
                 only_hot_reload,
             ),
         ));
+        // the config changes of schedules are applied by auto reload
+        my_server.add_service(background_service(
+            "Schedule",
+            new_schedule_service(),
+        ));
     }
 
     if !certificate_info_list.is_empty() {
@@ -420,6 +839,146 @@ fn run() -> Result<(), Box<dyn Error>> {
         "UpstreamHc",
         new_upstream_health_check_task(Duration::from_secs(10)),
     ));
+    // the keys of cache tags are removed after they are expired
+    my_server.add_service(background_service(
+        "CacheTagsCleanup",
+        cache::new_cache_tags_cleanup_task(),
+    ));
+    for (name, registry) in registries.iter() {
+        match discovery::new_registry_service(name, registry) {
+            Ok(service) => {
+                my_server.add_service(background_service("Registry", service));
+            },
+            Err(e) => {
+                error!(
+                    error = e.to_string(),
+                    name, registry, "new registry service fail"
+                );
+            },
+        }
+    }
+    if let Some(threshold) = slow_request_threshold {
+        if let Some(file) = &slow_request_log {
+            if let Err(e) = proxy::init_slow_request_log(file) {
+                error!(
+                    error = e.to_string(),
+                    file, "init slow request log fail"
+                );
+            }
+        }
+        my_server.add_service(background_service(
+            "SlowRequestWatchdog",
+            proxy::new_slow_request_watchdog_task(threshold),
+        ));
+    }
+    if let Some(file) = &connection_log {
+        match proxy::init_connection_log(file) {
+            Ok(()) => {
+                my_server.add_service(background_service(
+                    "ConnectionLog",
+                    proxy::new_connection_log_task(),
+                ));
+            },
+            Err(e) => {
+                error!(error = e.to_string(), file, "init connection log fail");
+            },
+        }
+    }
+    if let Some(file) = &traffic_record {
+        if let Err(e) = proxy::init_traffic_record(
+            file,
+            traffic_record_sample_rate,
+            traffic_record_body_limit,
+        ) {
+            error!(error = e.to_string(), file, "init traffic record fail");
+        }
+    }
+    // the request metrics and latency histograms are exposed by
+    // stats plugin and metrics push
+    if let Err(e) =
+        proxy::init_request_metrics(&metrics_path_templates, metrics_max_series)
+    {
+        error!(error = e.to_string(), "init request metrics fail");
+    }
+    if let Err(e) = proxy::init_latency_histograms(
+        &metrics_latency_buckets,
+        metrics_exemplars,
+    ) {
+        error!(error = e.to_string(), "init latency histograms fail");
+    }
+    if let Some(url) = &metrics_push {
+        match new_metrics_push_service(
+            url,
+            metrics_push_interval,
+            &metrics_push_labels,
+        ) {
+            Ok(service) => {
+                my_server
+                    .add_service(background_service("MetricsPush", service));
+            },
+            Err(e) => {
+                error!(error = e.to_string(), url, "new metrics push fail");
+            },
+        }
+    }
+    if let Some(target) = &metering {
+        match proxy::new_metering_service(
+            target,
+            metering_interval,
+            &metering_consumers,
+        ) {
+            Ok(service) => {
+                my_server.add_service(background_service("Metering", service));
+            },
+            Err(e) => {
+                error!(error = e.to_string(), target, "new metering fail");
+            },
+        }
+    }
+    if let Some(url) = &sink {
+        match sink::new_sink_service(url, sink_batch_size) {
+            Ok(service) => {
+                my_server.add_service(background_service("Sink", service));
+            },
+            Err(e) => {
+                error!(error = e.to_string(), url, "new sink fail");
+            },
+        }
+    }
+    if let Some(params) = clickhouse {
+        // the url is not logged as it may contain the password
+        match proxy::new_clickhouse_service(params) {
+            Ok(service) => {
+                my_server
+                    .add_service(background_service("ClickHouse", service));
+            },
+            Err(e) => {
+                error!(error = e.to_string(), "new clickhouse fail");
+            },
+        }
+    }
+    if anomaly_detection {
+        my_server.add_service(background_service(
+            "AnomalyDetection",
+            new_anomaly_detection_service(anomaly_sensitivity),
+        ));
+    }
+    if let Some(file) = &cache_snapshot {
+        my_server.add_service(background_service(
+            "CacheSnapshot",
+            plugin::new_cache_snapshot_service(file),
+        ));
+    }
+    if let Some(min_hits) = cache_prefetch_hits {
+        my_server.add_service(background_service(
+            "CachePrefetch",
+            new_cache_prefetch_service(CachePrefetchParams {
+                min_hits,
+                before: cache_prefetch_before,
+                concurrency: cache_prefetch_concurrency,
+            }),
+        ));
+    }
 
     if let Some(plugins) = plugin::get_plugins() {
         for (name, plugin) in plugins {
@@ -438,6 +997,18 @@ fn run() -> Result<(), Box<dyn Error>> {
         ));
     }
 
+    my_server.add_service(background_service(
+        "SystemdNotify",
+        new_systemd_notify_service(),
+    ));
+
+    if drop_privileges {
+        state::drop_privileges(&user, group.as_deref())?;
+    }
+    if let Some(sandbox) = &sandbox {
+        state::apply_sandbox(sandbox, &sandbox_paths, &sandbox_readable_paths)?;
+    }
+
     info!("Server is running");
     let _ = get_start_time();
 
@@ -449,5 +1020,6 @@ fn main() {
     if let Err(e) = run() {
         println!("{e}");
         error!(error = e.to_string());
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,380 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    get_step_conf, get_str_conf, get_str_slice_conf, Error, Plugin, Result,
+};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::http_extra::HttpResponse;
+use crate::state::State;
+use crate::util;
+use async_trait::async_trait;
+use http::HeaderName;
+use pingora::http::RequestHeader;
+use pingora::proxy::Session;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::debug;
+
+/// The key of client for bucketing, the client ip is used
+/// if the cookie or header doesn't exist.
+#[derive(Debug, Clone, PartialEq)]
+enum BucketKey {
+    Ip,
+    Cookie(String),
+    Header(String),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    name: String,
+    weight: u32,
+    // the upstream of bucket, the location's upstream is used if empty
+    upstream: String,
+    exposures: AtomicU64,
+}
+
+pub struct AbTest {
+    plugin_step: PluginStep,
+    experiment: String,
+    salt: String,
+    key: BucketKey,
+    buckets: Vec<Bucket>,
+    total_weight: u32,
+    // the header of bucket for upstream
+    header: HeaderName,
+    // the variable of bucket, it can be used in access log
+    variable: String,
+}
+
+/// The runtime state of ab test, it's the exposures of buckets.
+#[derive(Serialize, Debug)]
+struct AbTestState {
+    experiment: String,
+    exposures: HashMap<String, u64>,
+}
+
+impl TryFrom<&PluginConf> for AbTest {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let new_error = |message: String| Error::Invalid {
+            category: PluginCategory::AbTest.to_string(),
+            message,
+        };
+        let experiment = get_str_conf(value, "experiment");
+        if experiment.is_empty() {
+            return Err(new_error("experiment can't be empty".to_string()));
+        }
+        let key = get_str_conf(value, "key");
+        let key = match key.split_once(':') {
+            _ if key.is_empty() || key == "ip" => BucketKey::Ip,
+            Some(("cookie", name)) => BucketKey::Cookie(name.to_string()),
+            Some(("header", name)) => BucketKey::Header(name.to_string()),
+            _ => {
+                return Err(new_error(format!(
+                    "key({key}) should be ip, cookie:name or header:name"
+                )));
+            },
+        };
+        let mut buckets = vec![];
+        for item in get_str_slice_conf(value, "buckets").iter() {
+            let arr: Vec<&str> = item.split(':').map(|v| v.trim()).collect();
+            let weight = arr
+                .get(1)
+                .and_then(|weight| weight.parse::<u32>().ok())
+                .filter(|weight| *weight > 0 && !arr[0].is_empty());
+            let Some(weight) = weight else {
+                return Err(new_error(format!(
+                    "bucket({item}) should be name:weight or name:weight:upstream"
+                )));
+            };
+            buckets.push(Bucket {
+                name: arr[0].to_string(),
+                weight,
+                upstream: arr.get(2).unwrap_or(&"").to_string(),
+                exposures: AtomicU64::new(0),
+            });
+        }
+        if buckets.len() < 2 {
+            return Err(new_error(
+                "at least two buckets should be set".to_string(),
+            ));
+        }
+        let mut salt = get_str_conf(value, "salt");
+        if salt.is_empty() {
+            salt.clone_from(&experiment);
+        }
+        let mut header = get_str_conf(value, "header");
+        if header.is_empty() {
+            header = "X-Ab-Bucket".to_string();
+        }
+        let params = Self {
+            plugin_step: get_step_conf(value),
+            variable: format!("ab_{experiment}"),
+            experiment,
+            salt,
+            key,
+            total_weight: buckets.iter().map(|item| item.weight).sum(),
+            buckets,
+            header: HeaderName::from_str(&header)
+                .map_err(|e| new_error(e.to_string()))?,
+        };
+        if ![PluginStep::Request, PluginStep::ProxyUpstream]
+            .contains(&params.plugin_step)
+        {
+            return Err(new_error(
+                "Ab test plugin should be executed at request or proxy upstream step".to_string(),
+            ));
+        }
+        Ok(params)
+    }
+}
+
+impl AbTest {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new ab test plugin");
+        Self::try_from(params)
+    }
+    /// Gets the bucket of the client key, the same key with the same salt
+    /// is always assigned to the same bucket.
+    fn get_bucket(&self, key: &str) -> &Bucket {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(b":");
+        hasher.update(key.as_bytes());
+        let mut value = hasher.finalize() % self.total_weight;
+        for bucket in self.buckets.iter() {
+            if value < bucket.weight {
+                return bucket;
+            }
+            value -= bucket.weight;
+        }
+        // the value is always less than total weight
+        &self.buckets[self.buckets.len() - 1]
+    }
+    fn get_key(&self, session: &Session, ctx: &mut State) -> String {
+        let req_header = session.req_header();
+        let value = match &self.key {
+            BucketKey::Ip => None,
+            BucketKey::Cookie(name) => util::get_cookie_value(req_header, name),
+            BucketKey::Header(name) => {
+                util::get_req_header_value(req_header, name)
+            },
+        };
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            return value.to_string();
+        }
+        if let Some(ip) = &ctx.client_ip {
+            return ip.to_string();
+        }
+        let ip = util::get_client_ip(session);
+        ctx.client_ip = Some(ip.clone());
+        ip
+    }
+}
+
+#[async_trait]
+impl Plugin for AbTest {
+    #[inline]
+    fn step(&self) -> String {
+        self.plugin_step.to_string()
+    }
+    #[inline]
+    fn category(&self) -> PluginCategory {
+        PluginCategory::AbTest
+    }
+    fn runtime_state(&self, _key: &str) -> Option<serde_json::Value> {
+        serde_json::to_value(AbTestState {
+            experiment: self.experiment.clone(),
+            exposures: self
+                .buckets
+                .iter()
+                .map(|item| {
+                    (item.name.clone(), item.exposures.load(Ordering::Relaxed))
+                })
+                .collect(),
+        })
+        .ok()
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        let key = self.get_key(session, ctx);
+        let bucket = self.get_bucket(&key);
+        bucket.exposures.fetch_add(1, Ordering::Relaxed);
+        ctx.add_variable(&self.variable, &bucket.name);
+        ctx.add_journal(|| {
+            format!("ab_test:{}:{}", self.experiment, bucket.name)
+        });
+        // the upstream of bucket replaces the first tier upstream of location
+        if !bucket.upstream.is_empty()
+            && ctx.upstream_name.is_none()
+            && ctx.upstream_tier == 0
+        {
+            ctx.upstream_name = Some(bucket.upstream.clone());
+        }
+        Ok(None)
+    }
+    #[inline]
+    async fn handle_upstream_request(
+        &self,
+        step: PluginStep,
+        _session: &mut Session,
+        ctx: &mut State,
+        upstream_request: &mut RequestHeader,
+    ) -> pingora::Result<()> {
+        if step != PluginStep::ProxyUpstream {
+            return Ok(());
+        }
+        if let Some(bucket) = ctx.get_variable(&self.variable) {
+            upstream_request.insert_header(self.header.clone(), bucket)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AbTest, BucketKey};
+    use crate::config::{PluginConf, PluginStep};
+    use crate::plugin::Plugin;
+    use crate::state::State;
+    use pingora::http::RequestHeader;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_ab_test_params() {
+        let params = AbTest::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+experiment = "checkout"
+key = "cookie:uid"
+buckets = ["control:90", "variant:10:checkout-v2"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("request", params.step());
+        assert_eq!("checkout", params.salt);
+        assert_eq!(BucketKey::Cookie("uid".to_string()), params.key);
+        assert_eq!(100, params.total_weight);
+        assert_eq!("checkout-v2", params.buckets[1].upstream);
+        assert_eq!("x-ab-bucket", params.header.as_str());
+
+        let result = AbTest::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+experiment = "checkout"
+buckets = ["control:90", "variant"]
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin ab_test invalid, message: bucket(variant) should be name:weight or name:weight:upstream",
+            result.err().unwrap().to_string()
+        );
+
+        let result = AbTest::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+experiment = "checkout"
+buckets = ["control:90"]
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin ab_test invalid, message: at least two buckets should be set",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ab_test() {
+        let ab_test = AbTest::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+experiment = "checkout"
+key = "cookie:uid"
+buckets = ["control:50", "variant:50:checkout-v2"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        // the same key is always assigned to the same bucket
+        let bucket = ab_test.get_bucket("user-1").name.clone();
+        for _ in 0..10 {
+            assert_eq!(bucket, ab_test.get_bucket("user-1").name);
+        }
+        let variants = (0..1000)
+            .filter(|i| {
+                ab_test.get_bucket(&format!("user-{i}")).name == "variant"
+            })
+            .count();
+        assert_eq!(true, variants > 400 && variants < 600);
+
+        let headers = ["Cookie: uid=user-1"].join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        let result = ab_test
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        assert_eq!(Some(bucket.as_str()), ctx.get_variable("ab_checkout"));
+        assert_eq!(
+            bucket == "variant",
+            ctx.upstream_name == Some("checkout-v2".to_string())
+        );
+
+        let mut upstream_request =
+            RequestHeader::build("GET", b"/vicanso/pingap", None).unwrap();
+        ab_test
+            .handle_upstream_request(
+                PluginStep::ProxyUpstream,
+                &mut session,
+                &mut ctx,
+                &mut upstream_request,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            bucket,
+            upstream_request
+                .headers
+                .get("X-Ab-Bucket")
+                .unwrap()
+                .to_str()
+                .unwrap()
+        );
+    }
+}
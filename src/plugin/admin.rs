@@ -12,23 +12,41 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::cache::{get_cache_entry, purge_cache_tag, remove_cache_entry};
 use super::{
-    get_int_conf, get_step_conf, get_str_conf, get_str_slice_conf, Error,
-    Plugin, Result,
+    get_bool_conf, get_int_conf, get_plugins, get_step_conf, get_str_conf,
+    get_str_slice_conf, Error, Plugin, Result,
 };
+use crate::cache::list_cache_entries;
+use crate::cluster;
 use crate::config::{
-    self, save_config, BasicConf, CertificateConf, LocationConf,
-    PluginCategory, PluginConf, PluginStep, ServerConf, UpstreamConf,
-    CATEGORY_CERTIFICATE,
+    self, save_config, save_configs, BasicConf, CertificateConf, LocationConf,
+    PluginCategory, PluginConf, PluginStep, ScheduleConf, ServerConf,
+    UpstreamConf, CATEGORY_CERTIFICATE, CATEGORY_SCHEDULE,
 };
 use crate::config::{
-    PingapConf, CATEGORY_LOCATION, CATEGORY_PLUGIN, CATEGORY_SERVER,
-    CATEGORY_UPSTREAM,
+    export_bundle, import_bundle, strip_secrets, ConfigBundle, PingapConf,
+    CATEGORY_LOCATION, CATEGORY_PLUGIN, CATEGORY_SERVER, CATEGORY_UPSTREAM,
+    SECRETS_PLAIN, SECRETS_STRIP,
+};
+use crate::http_extra::{
+    HttpResponse, HTTP_HEADER_CONTENT_JSON, HTTP_HEADER_NO_STORE,
+    HTTP_HEADER_WWW_AUTHENTICATE,
 };
-use crate::http_extra::{HttpResponse, HTTP_HEADER_WWW_AUTHENTICATE};
 use crate::limit::TtlLruLimit;
+use crate::proxy::{
+    drain_backend, get_capture_har, get_capture_status,
+    get_config_apply_report, get_health_history, get_replay_status,
+    get_time_series, get_topology, get_upstream, get_upstreams_stats,
+    pause_upstream, restore_backend, resume_upstream, start_capture,
+    start_replay, stop_capture, stop_replay, switch_upstream, CaptureParams,
+    ReplayParams, UpstreamColor, MAX_TIME_SERIES_MINUTES,
+};
 use crate::state::get_start_time;
-use crate::state::{restart_now, State};
+use crate::state::{
+    is_profiling_available, is_profiling_enabled, restart_now,
+    set_profiling_enabled, State,
+};
 use crate::util::{self, get_pkg_version};
 use async_trait::async_trait;
 use base64::{engine::general_purpose::STANDARD, Engine};
@@ -38,15 +56,26 @@ use bytesize::ByteSize;
 use hex::encode;
 use http::Method;
 use http::{header, HeaderValue, StatusCode};
+use humantime::parse_duration;
 use memory_stats::memory_stats;
 use pingora::http::RequestHeader;
 use pingora::proxy::Session;
 use rust_embed::EmbeddedFile;
 use rust_embed::RustEmbed;
 use serde::{Deserialize, Serialize};
+use session::{
+    is_csrf_token_matched, SessionParams, SessionStore, ValidSession,
+    HTTP_HEADER_NAME_CSRF_TOKEN, SESSION_COOKIE_NAME,
+};
+use std::collections::HashMap;
 use std::time::Duration;
 use substring::Substring;
+use tokio::sync::Mutex;
 use tracing::{debug, error};
+use utoipa::ToSchema;
+
+mod openapi;
+mod session;
 
 #[derive(RustEmbed)]
 #[folder = "dist/"]
@@ -91,19 +120,360 @@ impl From<EmbeddedStaticFile> for HttpResponse {
     }
 }
 
+// the passphrase for encrypting or decrypting the secrets of config bundle
+const HTTP_HEADER_NAME_PASSPHRASE: &str = "X-Pingap-Passphrase";
+
+/// The scope of tenant token, the tenant can only access the config
+/// of its namespace.
+#[derive(Debug, Clone, PartialEq)]
+struct TenantScope {
+    namespace: String,
+    writable: bool,
+}
+
+// the fields which can't be changed by tenant, they can access the
+// files, the internal addresses or the registry of host
+static TENANT_DENIED_FIELDS: [(&str, &[&str]); 3] = [
+    (
+        CATEGORY_UPSTREAM,
+        &[
+            "addrs",
+            "discovery",
+            "dns_fallback_addrs",
+            "fastcgi_root",
+            "fastcgi_params",
+            "wsgi_script_name",
+            "wsgi_params",
+        ],
+    ),
+    (
+        CATEGORY_LOCATION,
+        &[
+            "sendfile_root",
+            "upstream_down_fallback",
+            "access_log_file",
+            "plugin_overrides",
+        ],
+    ),
+    (
+        CATEGORY_SERVER,
+        &[
+            "addr",
+            "tls_cert",
+            "tls_key",
+            "certificate_file",
+            "registry",
+        ],
+    ),
+];
+
+/// Gets the config as json value, it's used to compare the fields.
+fn get_config_value(
+    conf: &PingapConf,
+    category: &str,
+    name: &str,
+) -> Option<serde_json::Value> {
+    let value = match category {
+        CATEGORY_UPSTREAM => serde_json::to_value(conf.upstreams.get(name)?),
+        CATEGORY_LOCATION => serde_json::to_value(conf.locations.get(name)?),
+        CATEGORY_SERVER => serde_json::to_value(conf.servers.get(name)?),
+        _ => return None,
+    };
+    value.ok()
+}
+
+/// Gets the configs referenced by the config, e.g. the upstreams
+/// of location and the locations of server.
+fn get_config_references(
+    conf: &PingapConf,
+    category: &str,
+    name: &str,
+) -> Vec<(&'static str, String)> {
+    match category {
+        CATEGORY_UPSTREAM => conf
+            .upstreams
+            .get(name)
+            .and_then(|item| item.green_upstream.clone())
+            .map(|item| vec![(CATEGORY_UPSTREAM, item)])
+            .unwrap_or_default(),
+        CATEGORY_LOCATION => conf
+            .locations
+            .get(name)
+            .map(|item| {
+                item.upstream
+                    .iter()
+                    .chain(item.fallback_upstreams.iter().flatten())
+                    .map(|upstream| (CATEGORY_UPSTREAM, upstream.clone()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        CATEGORY_SERVER => conf
+            .servers
+            .get(name)
+            .map(|item| {
+                item.locations
+                    .iter()
+                    .flatten()
+                    .map(|location| (CATEGORY_LOCATION, location.clone()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+/// The scope of admin request.
+#[derive(Debug, Clone, PartialEq)]
+enum AdminScope {
+    All,
+    Tenant(TenantScope),
+    // the observer can only access the get endpoints,
+    // and the secrets of config are stripped
+    Observer,
+}
+
+impl AdminScope {
+    /// Checks whether the config can be changed by the scope,
+    /// the tenant can only change the servers, locations and upstreams
+    /// of its namespace.
+    fn check_writable(
+        &self,
+        conf: &PingapConf,
+        category: &str,
+        name: &str,
+    ) -> pingora::Result<()> {
+        if self == &AdminScope::Observer {
+            return Err(util::new_internal_error(
+                403,
+                "Observer is read only".to_string(),
+            ));
+        }
+        let AdminScope::Tenant(tenant) = self else {
+            return Ok(());
+        };
+        if !tenant.writable {
+            return Err(util::new_internal_error(
+                403,
+                format!("Namespace({}) is read only", tenant.namespace),
+            ));
+        }
+        if ![CATEGORY_UPSTREAM, CATEGORY_LOCATION, CATEGORY_SERVER]
+            .contains(&category)
+        {
+            return Err(util::new_internal_error(
+                403,
+                format!("{category} can't be changed by tenant"),
+            ));
+        }
+        if let Some(namespace) = conf.get_namespace(category, name) {
+            if namespace != tenant.namespace {
+                return Err(util::new_internal_error(
+                    403,
+                    format!(
+                        "{category}({name}) is not in namespace({})",
+                        tenant.namespace
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+    /// Sets the namespace of the changed config to the tenant's one,
+    /// it fails if the config is set to other namespace.
+    fn apply_namespace(
+        &self,
+        conf: &mut PingapConf,
+        category: &str,
+        name: &str,
+    ) -> pingora::Result<()> {
+        let AdminScope::Tenant(tenant) = self else {
+            return Ok(());
+        };
+        let namespace = match category {
+            CATEGORY_UPSTREAM => {
+                conf.upstreams.get_mut(name).map(|item| &mut item.namespace)
+            },
+            CATEGORY_LOCATION => {
+                conf.locations.get_mut(name).map(|item| &mut item.namespace)
+            },
+            CATEGORY_SERVER => {
+                conf.servers.get_mut(name).map(|item| &mut item.namespace)
+            },
+            _ => None,
+        };
+        let Some(namespace) = namespace else {
+            return Ok(());
+        };
+        if namespace
+            .as_ref()
+            .is_some_and(|value| value != &tenant.namespace)
+        {
+            return Err(util::new_internal_error(
+                403,
+                format!("Namespace should be {}", tenant.namespace),
+            ));
+        }
+        namespace.replace(tenant.namespace.clone());
+        Ok(())
+    }
+    /// Checks the config changed by tenant, the denied fields can't be
+    /// changed, and it can only reference the configs of its namespace.
+    fn check_changes(
+        &self,
+        current: &PingapConf,
+        conf: &PingapConf,
+        category: &str,
+        name: &str,
+    ) -> pingora::Result<()> {
+        let AdminScope::Tenant(tenant) = self else {
+            return Ok(());
+        };
+        let fields = TENANT_DENIED_FIELDS
+            .iter()
+            .find(|(item, _)| *item == category)
+            .map(|(_, fields)| *fields)
+            .unwrap_or_default();
+        let current_value = get_config_value(current, category, name);
+        let value = get_config_value(conf, category, name);
+        let get_field = |value: &Option<serde_json::Value>, field: &str| {
+            value
+                .as_ref()
+                .and_then(|value| value.get(field))
+                .filter(|value| !value.is_null())
+                .cloned()
+        };
+        for field in fields.iter() {
+            if get_field(&current_value, field) != get_field(&value, field) {
+                return Err(util::new_internal_error(
+                    403,
+                    format!(
+                        "{category}({name}).{field} can't be changed by tenant"
+                    ),
+                ));
+            }
+        }
+        for (ref_category, ref_name) in
+            get_config_references(conf, category, name)
+        {
+            // the config which is not found is checked by validation
+            let Some(namespace) = conf.get_namespace(ref_category, &ref_name)
+            else {
+                continue;
+            };
+            if namespace != tenant.namespace {
+                return Err(util::new_internal_error(
+                    403,
+                    format!(
+                        "{ref_category}({ref_name}) is not in namespace({})",
+                        tenant.namespace
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the tenant token, the format is `namespace:read|write:token`.
+fn parse_tenant_token(value: &str) -> Result<(Vec<u8>, TenantScope)> {
+    let arr: Vec<&str> = value.splitn(3, ':').collect();
+    let writable = match arr.get(1) {
+        Some(&"write") => true,
+        Some(&"read") => false,
+        _ => {
+            return Err(Error::Invalid {
+                category: PluginCategory::Admin.to_string(),
+                message: "Tenant token should be namespace:read|write:token"
+                    .to_string(),
+            })
+        },
+    };
+    let token = arr.get(2).cloned().unwrap_or_default();
+    if arr[0].is_empty() || token.is_empty() {
+        return Err(Error::Invalid {
+            category: PluginCategory::Admin.to_string(),
+            message: "Namespace and token of tenant can't be empty".to_string(),
+        });
+    }
+    Ok((
+        format!("Bearer {token}").as_bytes().to_vec(),
+        TenantScope {
+            namespace: arr[0].to_string(),
+            writable,
+        },
+    ))
+}
+
 pub struct AdminServe {
     pub path: String,
     pub authorizations: Vec<Vec<u8>>,
+    // the tokens of tenants, the key is the authorization value
+    tenant_tokens: Vec<(Vec<u8>, TenantScope)>,
+    // the tokens of observers, the key is the authorization value
+    observer_tokens: Vec<Vec<u8>>,
+    // all the requests are treated as observer
+    read_only: bool,
     pub plugin_step: PluginStep,
     ip_fail_limit: TtlLruLimit,
+    // the staged config, it's saved only when applied
+    staged: Mutex<Option<StagedConfig>>,
+    // the login sessions, the basic auth is only used for login if it's set
+    sessions: Option<SessionStore>,
+}
+
+#[derive(Clone)]
+struct StagedConfig {
+    // the hash of config which the changes are staged on
+    base_hash: String,
+    conf: PingapConf,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct StagedDiff {
+    staged: bool,
+    outdated: bool,
+    category_list: Vec<String>,
+    diff: Vec<String>,
+    // the warnings of staged config, e.g. unused upstreams
+    warnings: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ApplyResult {
+    // the config is saved
+    changed: bool,
+    created: bool,
+    diff: Vec<String>,
+    // the warnings of config, e.g. unused upstreams
+    warnings: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 struct ErrorResponse {
     message: String,
 }
 
-#[derive(Serialize, Deserialize)]
+/// The params of login, the account and password are for the
+/// authorizations, and the token is for the tenant tokens.
+#[derive(Default, Deserialize, ToSchema)]
+struct LoginParams {
+    account: Option<String>,
+    password: Option<String>,
+    token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct SessionInfo {
+    // the session management is enabled
+    enabled: bool,
+    // the csrf token should be set by header `X-Pingap-Csrf-Token`
+    // for the mutating requests
+    csrf_token: String,
+    // the max lifetime of session
+    expired_at: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 struct BasicInfo {
     start_time: u64,
     version: String,
@@ -112,12 +482,88 @@ struct BasicInfo {
     config_hash: String,
 }
 
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ProfilingInfo {
+    available: bool,
+    enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct PurgeResult {
+    count: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PluginRuntimeState {
+    category: String,
+    #[schema(value_type = Object)]
+    state: serde_json::Value,
+}
+
+/// The runtime state of plugins and the banned ips.
+#[derive(Serialize, ToSchema)]
+struct PluginsState {
+    plugins: HashMap<String, PluginRuntimeState>,
+    bans: Vec<cluster::BanInfo>,
+}
+
+/// Gets the runtime state of plugins, only the plugins which support
+/// inspection are returned, filtered by the name if it's not empty.
+fn get_plugins_state(name: &str, key: &str) -> PluginsState {
+    let plugins = get_plugins()
+        .map(|plugins| {
+            plugins
+                .iter()
+                .filter(|(plugin_name, _)| {
+                    name.is_empty() || plugin_name.as_str() == name
+                })
+                .filter_map(|(plugin_name, plugin)| {
+                    let state = plugin.runtime_state(key)?;
+                    Some((
+                        plugin_name.to_string(),
+                        PluginRuntimeState {
+                            category: plugin.category().to_string(),
+                            state,
+                        },
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    PluginsState {
+        plugins,
+        bans: cluster::get_bans(),
+    }
+}
+
 #[derive(Debug)]
 struct AdminServeParams {
     path: String,
     step: PluginStep,
     authorizations: Vec<Vec<u8>>,
+    tenant_tokens: Vec<(Vec<u8>, TenantScope)>,
+    observer_tokens: Vec<Vec<u8>>,
+    read_only: bool,
     ip_fail_limit: i64,
+    session: Option<SessionParams>,
+}
+
+/// Parses the duration of session config, the default value is used
+/// if it's empty.
+fn get_session_duration_conf(
+    value: &PluginConf,
+    key: &str,
+    default_value: Duration,
+) -> Result<u64> {
+    let value = get_str_conf(value, key);
+    if value.is_empty() {
+        return Ok(default_value.as_secs());
+    }
+    let value = parse_duration(&value).map_err(|e| Error::Invalid {
+        category: PluginCategory::Admin.to_string(),
+        message: format!("{key} is invalid, {e}"),
+    })?;
+    Ok(value.as_secs().max(1))
 }
 
 impl TryFrom<&PluginConf> for AdminServeParams {
@@ -134,15 +580,77 @@ impl TryFrom<&PluginConf> for AdminServeParams {
             })?;
             authorizations.push(format!("Basic {item}").as_bytes().to_vec());
         }
+        let mut tenant_tokens = vec![];
+        for item in get_str_slice_conf(value, "tenant_tokens").iter() {
+            if item.is_empty() {
+                continue;
+            }
+            tenant_tokens.push(parse_tenant_token(item)?);
+        }
+        // the admin is open to all if there is no authorization
+        if !tenant_tokens.is_empty() && authorizations.is_empty() {
+            return Err(Error::Invalid {
+                category: PluginCategory::Admin.to_string(),
+                message: "Authorizations should be set for tenant tokens"
+                    .to_string(),
+            });
+        }
+        let observer_tokens: Vec<Vec<u8>> =
+            get_str_slice_conf(value, "observer_tokens")
+                .iter()
+                .filter(|item| !item.is_empty())
+                .map(|item| format!("Bearer {item}").as_bytes().to_vec())
+                .collect();
+        if !observer_tokens.is_empty() && authorizations.is_empty() {
+            return Err(Error::Invalid {
+                category: PluginCategory::Admin.to_string(),
+                message: "Authorizations should be set for observer tokens"
+                    .to_string(),
+            });
+        }
         let mut ip_fail_limit = get_int_conf(value, "ip_fail_limit");
         if ip_fail_limit <= 0 {
             ip_fail_limit = 10;
         }
+        // the session management is enabled if the ttl is set
+        let session = if get_str_conf(value, "session_ttl").is_empty() {
+            None
+        } else {
+            if authorizations.is_empty() {
+                return Err(Error::Invalid {
+                    category: PluginCategory::Admin.to_string(),
+                    message: "Authorizations should be set for session"
+                        .to_string(),
+                });
+            }
+            Some(SessionParams {
+                ttl: get_session_duration_conf(
+                    value,
+                    "session_ttl",
+                    Duration::from_secs(3600),
+                )?,
+                idle_timeout: get_session_duration_conf(
+                    value,
+                    "session_idle_timeout",
+                    Duration::from_secs(15 * 60),
+                )?,
+                rotate_interval: get_session_duration_conf(
+                    value,
+                    "session_rotate_interval",
+                    Duration::from_secs(5 * 60),
+                )?,
+                ip_pinning: get_bool_conf(value, "session_ip_pinning"),
+            })
+        };
         let params = Self {
             step: get_step_conf(value),
             path: get_str_conf(value, "path"),
             ip_fail_limit,
             authorizations,
+            tenant_tokens,
+            observer_tokens,
+            read_only: get_bool_conf(value, "read_only"),
+            session,
         };
         if ![PluginStep::Request, PluginStep::ProxyUpstream]
             .contains(&params.step)
@@ -166,23 +674,166 @@ impl AdminServe {
             path: params.path,
             plugin_step: params.step,
             authorizations: params.authorizations,
+            tenant_tokens: params.tenant_tokens,
+            observer_tokens: params.observer_tokens,
+            read_only: params.read_only,
             ip_fail_limit: TtlLruLimit::new(
                 512,
                 Duration::from_secs(5 * 60),
                 params.ip_fail_limit as usize,
             ),
+            staged: Mutex::new(None),
+            sessions: params.session.map(SessionStore::new),
         })
     }
-    fn auth_validate(&self, req_header: &RequestHeader) -> bool {
+    /// Gets the scope of admin authorizations, it's observer
+    /// if the admin is read only.
+    fn get_all_scope(&self) -> AdminScope {
+        if self.read_only {
+            AdminScope::Observer
+        } else {
+            AdminScope::All
+        }
+    }
+    /// Gets the scope of authorization value, `None` means
+    /// the value is not matched.
+    fn get_scope(&self, value: &[u8]) -> Option<AdminScope> {
+        if value.is_empty() {
+            return None;
+        }
+        if self.authorizations.iter().any(|item| item == value) {
+            return Some(self.get_all_scope());
+        }
+        if self.observer_tokens.iter().any(|item| item == value) {
+            return Some(AdminScope::Observer);
+        }
+        self.tenant_tokens
+            .iter()
+            .find(|(token, _)| token == value)
+            .map(|(_, tenant)| AdminScope::Tenant(tenant.clone()))
+    }
+    /// Validates the authorization of request and returns its scope,
+    /// `None` means the request is unauthorized.
+    fn auth_validate(&self, req_header: &RequestHeader) -> Option<AdminScope> {
         if self.authorizations.is_empty() {
-            return true;
+            return Some(self.get_all_scope());
         }
         let value = util::get_req_header_value(req_header, "Authorization")
             .unwrap_or_default();
-        if value.is_empty() {
-            return false;
+        self.get_scope(value.as_bytes())
+    }
+    /// Logs in by the account and password(or the tenant token),
+    /// a new session is created and its id is set to the cookie.
+    async fn login(
+        &self,
+        store: &SessionStore,
+        session: &mut Session,
+        ip: &str,
+        secure: bool,
+    ) -> pingora::Result<HttpResponse> {
+        let buf = read_request_body(session).await?;
+        let params = if buf.is_empty() {
+            LoginParams::default()
+        } else {
+            serde_json::from_slice::<LoginParams>(&buf)
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?
+        };
+        let value = if let Some(token) = params.token {
+            format!("Bearer {token}")
+        } else if let Some(account) = params.account {
+            let password = params.password.unwrap_or_default();
+            format!(
+                "Basic {}",
+                STANDARD.encode(format!("{account}:{password}"))
+            )
+        } else {
+            util::get_req_header_value(session.req_header(), "Authorization")
+                .unwrap_or_default()
+                .to_string()
+        };
+        let Some(scope) = self.get_scope(value.as_bytes()) else {
+            self.ip_fail_limit.inc(ip).await;
+            return Err(util::new_internal_error(
+                401,
+                "Account or password is invalid".to_string(),
+            ));
+        };
+        let now = util::now().as_secs();
+        let (id, csrf_token) = store.create(scope, ip, now);
+        let mut resp = HttpResponse::try_from_json(&SessionInfo {
+            enabled: true,
+            csrf_token,
+            expired_at: now + store.params.ttl,
+        })?;
+        resp.headers = Some(vec![
+            HTTP_HEADER_NO_STORE.clone(),
+            new_set_cookie(&store.new_cookie(&self.path, &id, secure))?,
+        ]);
+        Ok(resp)
+    }
+    /// Validates the session of request, the csrf token is required
+    /// for the mutating requests. The bearer token of tenant is also
+    /// allowed, it's not sent by browser automatically.
+    async fn session_validate(
+        &self,
+        store: &SessionStore,
+        req_header: &RequestHeader,
+        ip: &str,
+        secure: bool,
+    ) -> std::result::Result<(AdminScope, Option<ValidSession>), HttpResponse>
+    {
+        let authorization =
+            util::get_req_header_value(req_header, "Authorization")
+                .unwrap_or_default();
+        if authorization.starts_with("Bearer ") {
+            let scope = self
+                .get_scope(authorization.as_bytes())
+                .filter(|scope| scope != &AdminScope::All);
+            if let Some(scope) = scope {
+                return Ok((scope, None));
+            }
+            self.ip_fail_limit.inc(ip).await;
+            return Err(new_session_error(
+                StatusCode::UNAUTHORIZED,
+                "Token is invalid",
+                None,
+            ));
+        }
+        let id = util::get_cookie_value(req_header, SESSION_COOKIE_NAME)
+            .unwrap_or_default();
+        if id.is_empty() {
+            return Err(new_session_error(
+                StatusCode::UNAUTHORIZED,
+                "Session is required, please login first",
+                None,
+            ));
+        }
+        let valid = store.validate(id, ip, util::now().as_secs()).map_err(
+            |message| {
+                new_session_error(
+                    StatusCode::UNAUTHORIZED,
+                    message,
+                    Some(store.new_cookie(&self.path, "", secure)),
+                )
+            },
+        )?;
+        if ![Method::GET, Method::HEAD, Method::OPTIONS]
+            .contains(&req_header.method)
+        {
+            let csrf_token = util::get_req_header_value(
+                req_header,
+                HTTP_HEADER_NAME_CSRF_TOKEN,
+            )
+            .unwrap_or_default();
+            if !is_csrf_token_matched(&valid.csrf_token, csrf_token) {
+                return Err(new_session_error(
+                    StatusCode::FORBIDDEN,
+                    "Csrf token is empty or invalid",
+                    None,
+                ));
+            }
         }
-        self.authorizations.contains(&value.as_bytes().to_vec())
+        Ok((valid.scope.clone(), Some(valid)))
     }
     async fn load_config(&self) -> pingora::Result<PingapConf> {
         let conf = config::load_config(&config::get_config_path(), true)
@@ -199,11 +850,21 @@ impl AdminServe {
     }
     async fn get_config(
         &self,
+        scope: &AdminScope,
         category: &str,
     ) -> pingora::Result<HttpResponse> {
-        let conf = self.load_config().await?;
+        let mut conf = self.load_config().await?;
+        // the tenant can only read the config of its namespace
+        if let AdminScope::Tenant(tenant) = scope {
+            conf = conf.filter_namespace(&tenant.namespace);
+        }
+        if scope == &AdminScope::Observer {
+            strip_secrets(&mut conf)
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+        }
         if category == "toml" {
-            let data = toml::to_string_pretty(&conf)
+            let data = conf
+                .to_toml()
                 .map_err(|e| util::new_internal_error(400, e.to_string()))?;
             return Ok(HttpResponse {
                 status: StatusCode::OK,
@@ -219,6 +880,7 @@ impl AdminServe {
             CATEGORY_CERTIFICATE => {
                 HttpResponse::try_from_json(&conf.certificates)?
             },
+            CATEGORY_SCHEDULE => HttpResponse::try_from_json(&conf.schedules)?,
             _ => HttpResponse::try_from_json(&conf)?,
         };
         Ok(resp)
@@ -226,10 +888,12 @@ impl AdminServe {
 
     async fn remove_config(
         &self,
+        scope: &AdminScope,
         category: &str,
         name: &str,
     ) -> pingora::Result<HttpResponse> {
         let mut conf = self.load_config().await?;
+        scope.check_writable(&conf, category, name)?;
         conf.remove(category, name).map_err(|e| {
             error!(error = e.to_string(), "validate config fail");
             util::new_internal_error(400, e.to_string())
@@ -245,88 +909,698 @@ impl AdminServe {
     async fn update_config(
         &self,
         session: &mut Session,
+        scope: &AdminScope,
+        category: &str,
+        name: &str,
+    ) -> pingora::Result<HttpResponse> {
+        let buf = read_request_body(session).await?;
+        let current = self.load_config().await?;
+        scope.check_writable(&current, category, name)?;
+        let mut conf = current.clone();
+        update_config_by_body(&mut conf, category, name, &buf)?;
+        scope.apply_namespace(&mut conf, category, name)?;
+        scope.check_changes(&current, &conf, category, name)?;
+        validate_config(&conf)?;
+        save_config(&config::get_config_path(), &conf, category)
+            .await
+            .map_err(|e| {
+                error!(error = e.to_string(), "save config fail");
+                util::new_internal_error(400, e.to_string())
+            })?;
+        Ok(HttpResponse::no_content())
+    }
+    /// Applies the desired state of config, it's created or replaced
+    /// by the body, and nothing is saved if the config is not changed.
+    async fn put_config(
+        &self,
+        session: &mut Session,
+        scope: &AdminScope,
         category: &str,
         name: &str,
     ) -> pingora::Result<HttpResponse> {
-        let mut buf = BytesMut::with_capacity(4096);
-        while let Some(value) = session.read_request_body().await? {
-            buf.put(value.as_ref());
+        let buf = read_request_body(session).await?;
+        let current = self.load_config().await?;
+        scope.check_writable(&current, category, name)?;
+        let mut conf = current.clone();
+        update_config_by_body(&mut conf, category, name, &buf)?;
+        scope.apply_namespace(&mut conf, category, name)?;
+        scope.check_changes(&current, &conf, category, name)?;
+        let (_, diff) = current.diff(&conf);
+        if diff.is_empty() {
+            return HttpResponse::try_from_json(&ApplyResult {
+                changed: false,
+                created: false,
+                diff,
+                warnings: conf.get_warnings(),
+            });
         }
-        let key = name.to_string();
-        let mut conf = self.load_config().await?;
-        match category {
-            CATEGORY_UPSTREAM => {
-                let upstream: UpstreamConf = serde_json::from_slice(&buf)
-                    .map_err(|e| {
-                        error!(
-                            error = e.to_string(),
-                            "descrialize upstream fail"
-                        );
-                        util::new_internal_error(400, e.to_string())
-                    })?;
-                conf.upstreams.insert(key, upstream);
-            },
-            CATEGORY_LOCATION => {
-                let location: LocationConf = serde_json::from_slice(&buf)
-                    .map_err(|e| {
-                        error!(
-                            error = e.to_string(),
-                            "descrialize location fail"
-                        );
-                        util::new_internal_error(400, e.to_string())
-                    })?;
-                conf.locations.insert(key, location);
-            },
-            CATEGORY_SERVER => {
-                let server: ServerConf =
-                    serde_json::from_slice(&buf).map_err(|e| {
-                        error!(
-                            error = e.to_string(),
-                            "descrialize server fail"
-                        );
-                        util::new_internal_error(400, e.to_string())
-                    })?;
-                conf.servers.insert(key, server);
-            },
-            CATEGORY_PLUGIN => {
-                let plugin: PluginConf =
-                    serde_json::from_slice(&buf).map_err(|e| {
-                        error!(
-                            error = e.to_string(),
-                            "descrialize plugin fail"
-                        );
-                        util::new_internal_error(400, e.to_string())
-                    })?;
-                conf.plugins.insert(key, plugin);
-            },
-            CATEGORY_CERTIFICATE => {
-                let certificate: CertificateConf = serde_json::from_slice(&buf)
-                    .map_err(|e| {
-                        error!(
-                            error = e.to_string(),
-                            "descrialize certificate fail"
-                        );
-                        util::new_internal_error(400, e.to_string())
-                    })?;
-                conf.certificates.insert(key, certificate);
-            },
-            _ => {
-                let basic_conf: BasicConf = serde_json::from_slice(&buf)
-                    .map_err(|e| {
-                        error!(error = e.to_string(), "descrialize basic fail");
-                        util::new_internal_error(400, e.to_string())
-                    })?;
-                conf.basic = basic_conf;
-            },
-        };
+        let warnings = validate_config(&conf)?;
         save_config(&config::get_config_path(), &conf, category)
             .await
             .map_err(|e| {
                 error!(error = e.to_string(), "save config fail");
                 util::new_internal_error(400, e.to_string())
             })?;
+        HttpResponse::try_from_json(&ApplyResult {
+            changed: true,
+            created: diff.iter().any(|item| item.starts_with("++")),
+            diff,
+            warnings,
+        })
+    }
+    /// Gets the staged config, it will be created from the current config
+    /// if there is no staged config.
+    async fn get_staged_config(&self) -> pingora::Result<StagedConfig> {
+        if let Some(staged) = self.staged.lock().await.clone() {
+            return Ok(staged);
+        }
+        let conf = self.load_config().await?;
+        let base_hash = conf
+            .hash()
+            .map_err(|e| util::new_internal_error(500, e.to_string()))?;
+        Ok(StagedConfig { base_hash, conf })
+    }
+    /// Stages the change of config, the whole config is validated,
+    /// but it will not be saved until applied.
+    async fn stage_config(
+        &self,
+        session: &mut Session,
+        method: &Method,
+        category: &str,
+        name: &str,
+    ) -> pingora::Result<HttpResponse> {
+        let buf = if *method == Method::POST {
+            read_request_body(session).await?
+        } else {
+            BytesMut::new()
+        };
+        let mut staged = self.get_staged_config().await?;
+        if *method == Method::DELETE {
+            staged.conf.remove(category, name).map_err(|e| {
+                error!(error = e.to_string(), "remove staged config fail");
+                util::new_internal_error(400, e.to_string())
+            })?;
+        } else {
+            update_config_by_body(&mut staged.conf, category, name, &buf)?;
+        }
+        validate_config(&staged.conf)?;
+        self.staged.lock().await.replace(staged);
+        Ok(HttpResponse::no_content())
+    }
+    /// Gets the diff between the current config and staged config.
+    async fn diff_staged_config(
+        &self,
+        scope: &AdminScope,
+    ) -> pingora::Result<HttpResponse> {
+        let current = self.load_config().await?;
+        let staged = self.get_staged_config().await?;
+        let is_staged = self.staged.lock().await.is_some();
+        HttpResponse::try_from_json(&new_staged_diff(
+            current, staged, is_staged, scope,
+        )?)
+    }
+    /// Applies the staged config, it will fail if the config has been
+    /// modified after the changes were staged.
+    async fn apply_staged_config(&self) -> pingora::Result<HttpResponse> {
+        let mut staged_guard = self.staged.lock().await;
+        let Some(staged) = staged_guard.as_ref() else {
+            return Ok(HttpResponse::bad_request(
+                "No staged config to apply".into(),
+            ));
+        };
+        let current = self.load_config().await?;
+        if current.hash().unwrap_or_default() != staged.base_hash {
+            return Ok(HttpResponse {
+                status: StatusCode::CONFLICT,
+                body: Bytes::from_static(
+                    b"Config has been modified after staged, please discard and stage again",
+                ),
+                ..Default::default()
+            });
+        }
+        validate_config(&staged.conf)?;
+        let (mut category_list, _) = current.diff(&staged.conf);
+        category_list.sort();
+        category_list.dedup();
+        // the categories are saved as a whole, so the config isn't
+        // partially applied
+        save_configs(&config::get_config_path(), &staged.conf, &category_list)
+            .await
+            .map_err(|e| {
+                error!(error = e.to_string(), "save staged config fail");
+                util::new_internal_error(400, e.to_string())
+            })?;
+        staged_guard.take();
         Ok(HttpResponse::no_content())
     }
+    /// Exports the whole config as bundle, the secrets are plain(default),
+    /// stripped or encrypted by the passphrase of request header.
+    async fn export_config_bundle(
+        &self,
+        session: &Session,
+        scope: &AdminScope,
+    ) -> pingora::Result<HttpResponse> {
+        let req_header = session.req_header();
+        let mut secrets = util::get_query_value(req_header, "secrets")
+            .unwrap_or(SECRETS_PLAIN);
+        // the secrets are always stripped for observer
+        if scope == &AdminScope::Observer {
+            secrets = SECRETS_STRIP;
+        }
+        let passphrase =
+            util::get_req_header_value(req_header, HTTP_HEADER_NAME_PASSPHRASE)
+                .unwrap_or_default();
+        let conf = self.load_config().await?;
+        let bundle =
+            export_bundle(&conf, secrets, passphrase).map_err(|e| {
+                error!(error = e.to_string(), "export config bundle fail");
+                util::new_internal_error(400, e.to_string())
+            })?;
+        HttpResponse::try_from_json(&bundle)
+    }
+    /// Imports the config bundle and applies it as a whole, nothing is
+    /// saved if any config is invalid or it's a dry run.
+    async fn import_config_bundle(
+        &self,
+        session: &mut Session,
+    ) -> pingora::Result<HttpResponse> {
+        let req_header = session.req_header();
+        let dry_run =
+            util::get_query_value(req_header, "dry_run") == Some("true");
+        let passphrase =
+            util::get_req_header_value(req_header, HTTP_HEADER_NAME_PASSPHRASE)
+                .unwrap_or_default()
+                .to_string();
+        let buf = read_request_body(session).await?;
+        let bundle: ConfigBundle =
+            serde_json::from_slice(&buf).map_err(|e| {
+                error!(error = e.to_string(), "descrialize bundle fail");
+                util::new_internal_error(400, e.to_string())
+            })?;
+        let current = self.load_config().await?;
+        let conf =
+            import_bundle(&bundle, &current, &passphrase).map_err(|e| {
+                error!(error = e.to_string(), "import config bundle fail");
+                util::new_internal_error(400, e.to_string())
+            })?;
+        let warnings = validate_config(&conf)?;
+        let (mut category_list, diff) = current.diff(&conf);
+        let created = diff.iter().any(|item| item.starts_with("++"));
+        if dry_run || diff.is_empty() {
+            return HttpResponse::try_from_json(&ApplyResult {
+                changed: false,
+                created,
+                diff,
+                warnings,
+            });
+        }
+        category_list.sort();
+        category_list.dedup();
+        save_configs(&config::get_config_path(), &conf, &category_list)
+            .await
+            .map_err(|e| {
+                error!(error = e.to_string(), "save config bundle fail");
+                util::new_internal_error(400, e.to_string())
+            })?;
+        HttpResponse::try_from_json(&ApplyResult {
+            changed: true,
+            created,
+            diff,
+            warnings,
+        })
+    }
+}
+
+async fn read_request_body(session: &mut Session) -> pingora::Result<BytesMut> {
+    let mut buf = BytesMut::with_capacity(4096);
+    while let Some(value) = session.read_request_body().await? {
+        buf.put(value.as_ref());
+    }
+    Ok(buf)
+}
+
+#[derive(Deserialize, ToSchema)]
+struct BanParams {
+    ip: String,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>, example = "1h")]
+    ttl: Option<Duration>,
+}
+
+/// Handles the shared state of cluster, e.g. nodes and ban list.
+async fn handle_cluster(
+    session: &mut Session,
+    method: &Method,
+    params: &[&str],
+) -> pingora::Result<HttpResponse> {
+    if !cluster::is_cluster_enabled() {
+        return Err(util::new_internal_error(
+            400,
+            "Cluster is not enabled".to_string(),
+        ));
+    }
+    let category = params.get(2).cloned().unwrap_or_default();
+    match (method, category) {
+        (&Method::GET, "nodes") => {
+            let nodes = cluster::get_cluster_nodes()
+                .await
+                .map_err(|e| util::new_internal_error(500, e.to_string()))?;
+            HttpResponse::try_from_json(&nodes)
+        },
+        (&Method::GET, "bans") => {
+            HttpResponse::try_from_json(&cluster::get_bans())
+        },
+        (&Method::POST, "bans") => {
+            let buf = read_request_body(session).await?;
+            let params: BanParams = serde_json::from_slice(&buf)
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+            cluster::ban_ip(
+                &params.ip,
+                params.ttl.unwrap_or(Duration::from_secs(3600)),
+            );
+            Ok(HttpResponse::no_content())
+        },
+        (&Method::DELETE, "bans") if params.len() >= 4 => {
+            cluster::unban_ip(params[3]);
+            Ok(HttpResponse::no_content())
+        },
+        _ => Err(pingora::Error::new_str("Url is invalid")),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct PauseParams {
+    // the target of pause: discovery, health_check or all(default)
+    target: Option<String>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>, example = "30m")]
+    duration: Option<Duration>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DrainParams {
+    // the address of backend, e.g. 192.168.1.1:3000
+    addr: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SwitchParams {
+    // the active color of blue green upstream, blue or green
+    color: UpstreamColor,
+}
+
+/// Handles the pause and resume of upstream discovery and health check,
+/// the drain and restore of upstream backend, and the switch of
+/// blue green upstream.
+async fn handle_upstream(
+    session: &mut Session,
+    method: &Method,
+    params: &[&str],
+) -> pingora::Result<HttpResponse> {
+    let name = params.get(2).cloned().unwrap_or_default();
+    let action = params.get(3).cloned().unwrap_or_default();
+    if get_upstream(name).is_none() {
+        return Err(util::new_internal_error(
+            400,
+            format!("Upstream({name}) is not found"),
+        ));
+    }
+    if action == "health-history" {
+        if *method != Method::GET {
+            return Err(pingora::Error::new_str("Url is invalid"));
+        }
+        return HttpResponse::try_from_json(&get_health_history(name));
+    }
+    let buf = read_request_body(session).await?;
+    if action == "switch" {
+        if *method != Method::POST {
+            return Err(pingora::Error::new_str("Url is invalid"));
+        }
+        let switch_params: SwitchParams = serde_json::from_slice(&buf)
+            .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+        switch_upstream(name, switch_params.color)
+            .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+        return Ok(HttpResponse::no_content());
+    }
+    if matches!(action, "drain" | "restore") {
+        let drain_params: DrainParams = serde_json::from_slice(&buf)
+            .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+        return match (method, action) {
+            (&Method::POST, "drain") => {
+                let drain =
+                    drain_backend(name, &drain_params.addr).map_err(|e| {
+                        util::new_internal_error(400, e.to_string())
+                    })?;
+                HttpResponse::try_from_json(&drain)
+            },
+            (&Method::POST, "restore") => {
+                restore_backend(name, &drain_params.addr).map_err(|e| {
+                    util::new_internal_error(400, e.to_string())
+                })?;
+                Ok(HttpResponse::no_content())
+            },
+            _ => Err(pingora::Error::new_str("Url is invalid")),
+        };
+    }
+    let pause_params: PauseParams = if buf.is_empty() {
+        PauseParams {
+            target: None,
+            duration: None,
+        }
+    } else {
+        serde_json::from_slice(&buf)
+            .map_err(|e| util::new_internal_error(400, e.to_string()))?
+    };
+    let target = pause_params.target.unwrap_or_default();
+    let (discovery, health_check) = match target.as_str() {
+        "discovery" => (true, false),
+        "health_check" => (false, true),
+        "" | "all" => (true, true),
+        _ => {
+            return Err(util::new_internal_error(
+                400,
+                format!("Pause target({target}) is invalid"),
+            ))
+        },
+    };
+    match (method, action) {
+        (&Method::POST, "pause") => {
+            let pause = pause_upstream(
+                name,
+                discovery,
+                health_check,
+                pause_params.duration.unwrap_or(Duration::from_secs(3600)),
+            )
+            .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+            HttpResponse::try_from_json(&pause)
+        },
+        (&Method::POST, "resume") => {
+            resume_upstream(name, discovery, health_check)
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+            Ok(HttpResponse::no_content())
+        },
+        _ => Err(pingora::Error::new_str("Url is invalid")),
+    }
+}
+
+/// Handles the inspection of cache entries, the entry is specified by
+/// the `url` and `prefix`(namespace and headers value of cache plugin) query,
+/// the biggest or hottest entries are listed if no url is specified.
+async fn handle_cache(
+    session: &Session,
+    method: &Method,
+) -> pingora::Result<HttpResponse> {
+    let req_header = session.req_header();
+    let get_query = |name: &str| -> String {
+        util::get_query_value(req_header, name)
+            .and_then(|value| urlencoding::decode(value).ok())
+            .map(|value| value.to_string())
+            .unwrap_or_default()
+    };
+    let url = get_query("url");
+    let prefix = get_query("prefix");
+    match method {
+        &Method::GET if url.is_empty() => {
+            let sort = get_query("sort");
+            let limit = get_query("limit").parse::<usize>().unwrap_or(20);
+            HttpResponse::try_from_json(&list_cache_entries(&sort, limit))
+        },
+        &Method::GET => {
+            if let Some(entry) = get_cache_entry(&prefix, &url).await {
+                HttpResponse::try_from_json(&entry)
+            } else {
+                HttpResponse::try_from_json_status(
+                    &ErrorResponse {
+                        message: format!("Cache of {prefix}{url} is not found"),
+                    },
+                    StatusCode::NOT_FOUND,
+                )
+            }
+        },
+        &Method::DELETE if !url.is_empty() => {
+            let count = if remove_cache_entry(&prefix, &url).await {
+                1
+            } else {
+                0
+            };
+            HttpResponse::try_from_json(&PurgeResult { count })
+        },
+        _ => Err(pingora::Error::new_str("Url is invalid")),
+    }
+}
+
+/// Handles the request capture, the captured requests can be downloaded as har.
+async fn handle_capture(
+    session: &mut Session,
+    method: &Method,
+    category: &str,
+) -> pingora::Result<HttpResponse> {
+    match (method, category) {
+        (&Method::POST, "") => {
+            let buf = read_request_body(session).await?;
+            let params: CaptureParams = serde_json::from_slice(&buf)
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+            if params.count == 0 {
+                return Err(util::new_internal_error(
+                    400,
+                    "Count of capture should be greater than 0".to_string(),
+                ));
+            }
+            start_capture(params);
+            HttpResponse::try_from_json(&get_capture_status())
+        },
+        (&Method::DELETE, "") => {
+            stop_capture();
+            Ok(HttpResponse::no_content())
+        },
+        (&Method::GET, "har") => {
+            let buf = get_capture_har()
+                .map_err(|e| util::new_internal_error(500, e.to_string()))?;
+            Ok(HttpResponse {
+                status: StatusCode::OK,
+                body: buf.into(),
+                headers: Some(vec![
+                    HTTP_HEADER_CONTENT_JSON.clone(),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        HeaderValue::from_static(
+                            r#"attachment; filename="pingap.har""#,
+                        ),
+                    ),
+                ]),
+                ..Default::default()
+            })
+        },
+        (&Method::GET, "") => {
+            HttpResponse::try_from_json(&get_capture_status())
+        },
+        _ => Err(pingora::Error::new_str("Url is invalid")),
+    }
+}
+
+/// Handles the traffic replay, the recorded requests are replayed
+/// against the target in background.
+async fn handle_replay(
+    session: &mut Session,
+    method: &Method,
+) -> pingora::Result<HttpResponse> {
+    match *method {
+        Method::POST => {
+            let buf = read_request_body(session).await?;
+            let params: ReplayParams = serde_json::from_slice(&buf)
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+            if params.file.is_empty() || params.target.is_empty() {
+                return Err(util::new_internal_error(
+                    400,
+                    "File and target of replay should be set".to_string(),
+                ));
+            }
+            start_replay(params)
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+            HttpResponse::try_from_json(&get_replay_status())
+        },
+        Method::DELETE => {
+            stop_replay();
+            Ok(HttpResponse::no_content())
+        },
+        Method::GET => HttpResponse::try_from_json(&get_replay_status()),
+        _ => Err(pingora::Error::new_str("Url is invalid")),
+    }
+}
+
+/// Validates the whole config, including the references of config,
+/// plugin params and certificates, returns the warnings of config.
+fn validate_config(conf: &PingapConf) -> pingora::Result<Vec<String>> {
+    conf.validate().map_err(|e| {
+        error!(error = e.to_string(), "validate config fail");
+        util::new_internal_error(400, e.to_string())
+    })?;
+    Ok(conf.get_warnings())
+}
+
+/// Updates the config of category by the json body.
+/// Creates the diff of staged config, the secrets of both configs are
+/// stripped for observer, so they are not leaked by the diff.
+fn new_staged_diff(
+    mut current: PingapConf,
+    mut staged: StagedConfig,
+    is_staged: bool,
+    scope: &AdminScope,
+) -> pingora::Result<StagedDiff> {
+    let outdated = current.hash().unwrap_or_default() != staged.base_hash;
+    if scope == &AdminScope::Observer {
+        strip_secrets(&mut current)
+            .and_then(|_| strip_secrets(&mut staged.conf))
+            .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+    }
+    let (category_list, diff) = current.diff(&staged.conf);
+    Ok(StagedDiff {
+        staged: is_staged,
+        outdated,
+        category_list,
+        diff,
+        warnings: staged.conf.get_warnings(),
+    })
+}
+
+fn update_config_by_body(
+    conf: &mut PingapConf,
+    category: &str,
+    name: &str,
+    buf: &[u8],
+) -> pingora::Result<()> {
+    let key = name.to_string();
+    match category {
+        CATEGORY_UPSTREAM => {
+            let upstream: UpstreamConf =
+                serde_json::from_slice(buf).map_err(|e| {
+                    error!(error = e.to_string(), "descrialize upstream fail");
+                    util::new_internal_error(400, e.to_string())
+                })?;
+            conf.upstreams.insert(key, upstream);
+        },
+        CATEGORY_LOCATION => {
+            let location: LocationConf =
+                serde_json::from_slice(buf).map_err(|e| {
+                    error!(error = e.to_string(), "descrialize location fail");
+                    util::new_internal_error(400, e.to_string())
+                })?;
+            conf.locations.insert(key, location);
+        },
+        CATEGORY_SERVER => {
+            let server: ServerConf =
+                serde_json::from_slice(buf).map_err(|e| {
+                    error!(error = e.to_string(), "descrialize server fail");
+                    util::new_internal_error(400, e.to_string())
+                })?;
+            conf.servers.insert(key, server);
+        },
+        CATEGORY_PLUGIN => {
+            let plugin: PluginConf =
+                serde_json::from_slice(buf).map_err(|e| {
+                    error!(error = e.to_string(), "descrialize plugin fail");
+                    util::new_internal_error(400, e.to_string())
+                })?;
+            conf.plugins.insert(key, plugin);
+        },
+        CATEGORY_CERTIFICATE => {
+            let certificate: CertificateConf = serde_json::from_slice(buf)
+                .map_err(|e| {
+                    error!(
+                        error = e.to_string(),
+                        "descrialize certificate fail"
+                    );
+                    util::new_internal_error(400, e.to_string())
+                })?;
+            conf.certificates.insert(key, certificate);
+        },
+        CATEGORY_SCHEDULE => {
+            let schedule: ScheduleConf =
+                serde_json::from_slice(buf).map_err(|e| {
+                    error!(error = e.to_string(), "descrialize schedule fail");
+                    util::new_internal_error(400, e.to_string())
+                })?;
+            conf.schedules.insert(key, schedule);
+        },
+        _ => {
+            let basic_conf: BasicConf =
+                serde_json::from_slice(buf).map_err(|e| {
+                    error!(error = e.to_string(), "descrialize basic fail");
+                    util::new_internal_error(400, e.to_string())
+                })?;
+            conf.basic = basic_conf;
+        },
+    };
+    Ok(())
+}
+
+/// Returns `true` if the path can be accessed by tenant.
+fn is_tenant_allowed_path(path: &str) -> bool {
+    if path.starts_with("/configs")
+        || ["/basic", "/session", "/logout"].contains(&path)
+    {
+        return true;
+    }
+    is_static_file(path)
+}
+
+/// Returns `true` if the request can be accessed by observer, only the
+/// get endpoints are allowed except the har of captured requests,
+/// which may contain the authorizations.
+fn is_observer_allowed(method: &Method, path: &str) -> bool {
+    if path == "/logout" {
+        return true;
+    }
+    [Method::GET, Method::HEAD, Method::OPTIONS].contains(method)
+        && path != "/capture/har"
+}
+
+/// Returns `true` if the path is the static file of admin page,
+/// they are public for the login page when session is enabled.
+fn is_static_file(path: &str) -> bool {
+    path == "/" || AdminAsset::get(path.substring(1, path.len())).is_some()
+}
+
+fn get_static_file(path: &str) -> HttpResponse {
+    let mut file = path.substring(1, path.len());
+    if file.is_empty() {
+        file = "index.html";
+    }
+    EmbeddedStaticFile(
+        AdminAsset::get(file),
+        Duration::from_secs(365 * 24 * 3600),
+    )
+    .into()
+}
+
+fn new_set_cookie(
+    value: &str,
+) -> pingora::Result<(header::HeaderName, HeaderValue)> {
+    let value = HeaderValue::from_str(value)
+        .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+    Ok((header::SET_COOKIE, value))
+}
+
+/// Creates the error response of session, the cookie is cleared
+/// if the session is invalid.
+fn new_session_error(
+    status: StatusCode,
+    message: &str,
+    clear_cookie: Option<String>,
+) -> HttpResponse {
+    let mut resp = HttpResponse::try_from_json_status(
+        &ErrorResponse {
+            message: message.to_string(),
+        },
+        status,
+    )
+    .unwrap_or(HttpResponse::unknown_error("Json serde fail".into()));
+    if let Some(Ok(value)) = clear_cookie.map(|value| new_set_cookie(&value)) {
+        resp.headers = Some(vec![value]);
+    }
+    resp
 }
 
 fn get_method_path(session: &Session) -> (Method, String) {
@@ -350,7 +1624,7 @@ impl Plugin for AdminServe {
         &self,
         step: PluginStep,
         session: &mut Session,
-        _ctx: &mut State,
+        ctx: &mut State,
     ) -> pingora::Result<Option<HttpResponse>> {
         if self.plugin_step != step {
             return Ok(None);
@@ -367,14 +1641,6 @@ impl Plugin for AdminServe {
             return Ok(None);
         }
         let header = session.req_header_mut();
-        if !self.auth_validate(header) {
-            self.ip_fail_limit.inc(&ip).await;
-            return Ok(Some(HttpResponse {
-                status: StatusCode::UNAUTHORIZED,
-                headers: Some(vec![HTTP_HEADER_WWW_AUTHENTICATE.clone()]),
-                ..Default::default()
-            }));
-        }
         let path = header.uri.path();
         let mut new_path =
             path.substring(self.path.len(), path.len()).to_string();
@@ -388,43 +1654,242 @@ impl Plugin for AdminServe {
 
         let (method, mut path) = get_method_path(session);
         let api_prefix = "/api";
-        if path.starts_with(api_prefix) {
+        let is_api = path.starts_with(api_prefix);
+        if is_api {
             path = path.substring(api_prefix.len(), path.len()).to_string();
         }
+        // the session cookie is set as secure for https request
+        let secure = ctx.tls_version.is_some();
+        let (scope, valid_session) = if let Some(store) = &self.sessions {
+            if path == "/login" && method == Method::POST {
+                let resp = self
+                    .login(store, session, &ip, secure)
+                    .await
+                    .unwrap_or_else(|err| {
+                        let status = match err.etype() {
+                            pingora::ErrorType::HTTPStatus(code) => {
+                                StatusCode::from_u16(*code)
+                                    .unwrap_or(StatusCode::BAD_REQUEST)
+                            },
+                            _ => StatusCode::BAD_REQUEST,
+                        };
+                        new_session_error(status, &err.to_string(), None)
+                    });
+                return Ok(Some(resp));
+            }
+            if !is_api && method == Method::GET && is_static_file(&path) {
+                return Ok(Some(get_static_file(&path)));
+            }
+            match self
+                .session_validate(store, session.req_header(), &ip, secure)
+                .await
+            {
+                Ok(result) => result,
+                Err(resp) => return Ok(Some(resp)),
+            }
+        } else {
+            let Some(scope) = self.auth_validate(session.req_header()) else {
+                self.ip_fail_limit.inc(&ip).await;
+                return Ok(Some(HttpResponse {
+                    status: StatusCode::UNAUTHORIZED,
+                    headers: Some(vec![HTTP_HEADER_WWW_AUTHENTICATE.clone()]),
+                    ..Default::default()
+                }));
+            };
+            (scope, None)
+        };
         let params: Vec<&str> = path.split('/').collect();
         let mut category = "";
         if params.len() >= 3 {
             category = params[2];
         }
-        let resp = if path.starts_with("/configs") {
+        // the tenant can only access the configs and the static files
+        if matches!(scope, AdminScope::Tenant(_))
+            && !is_tenant_allowed_path(&path)
+        {
+            return Ok(Some(HttpResponse {
+                status: StatusCode::FORBIDDEN,
+                body: Bytes::from_static(b"Forbidden, tenant has no access"),
+                ..Default::default()
+            }));
+        }
+        if scope == AdminScope::Observer && !is_observer_allowed(&method, &path)
+        {
+            return Ok(Some(HttpResponse {
+                status: StatusCode::FORBIDDEN,
+                body: Bytes::from_static(b"Forbidden, observer is read only"),
+                ..Default::default()
+            }));
+        }
+        let mut rotated_id = valid_session
+            .as_ref()
+            .and_then(|item| item.rotated_id.clone());
+        let mut resp = if path.starts_with("/configs") {
             match method {
                 Method::POST => {
                     if params.len() < 4 {
                         Err(pingora::Error::new_str("Url is invalid(no name)"))
                     } else {
-                        self.update_config(session, category, params[3]).await
+                        self.update_config(session, &scope, category, params[3])
+                            .await
+                    }
+                },
+                Method::PUT => {
+                    if params.len() < 4 {
+                        Err(pingora::Error::new_str("Url is invalid(no name)"))
+                    } else {
+                        self.put_config(session, &scope, category, params[3])
+                            .await
+                    }
+                },
+                Method::DELETE => {
+                    if params.len() < 4 {
+                        Err(pingora::Error::new_str("Url is invalid(no name)"))
+                    } else {
+                        self.remove_config(&scope, category, params[3]).await
                     }
                 },
-                Method::DELETE => {
-                    if params.len() < 4 {
-                        Err(pingora::Error::new_str("Url is invalid(no name)"))
-                    } else {
-                        self.remove_config(category, params[3]).await
-                    }
+                _ => self.get_config(&scope, category).await,
+            }
+            .unwrap_or_else(|err| {
+                // the forbidden error of tenant is responded with its status
+                let status = match err.etype() {
+                    pingora::ErrorType::HTTPStatus(code) => {
+                        StatusCode::from_u16(*code)
+                            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+                    },
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                HttpResponse::try_from_json_status(
+                    &ErrorResponse {
+                        message: err.to_string(),
+                    },
+                    status,
+                )
+                .unwrap_or(HttpResponse::unknown_error(
+                    "Json serde fail".into(),
+                ))
+            })
+        } else if path.starts_with("/stages") {
+            match (&method, category) {
+                (&Method::GET, "diff") => self.diff_staged_config(&scope).await,
+                (&Method::POST, "apply") => self.apply_staged_config().await,
+                (&Method::DELETE, "") => {
+                    self.staged.lock().await.take();
+                    Ok(HttpResponse::no_content())
+                },
+                (&Method::POST | &Method::DELETE, _) if params.len() >= 4 => {
+                    self.stage_config(session, &method, category, params[3])
+                        .await
+                },
+                _ => Err(pingora::Error::new_str("Url is invalid(no name)")),
+            }
+            .unwrap_or_else(|err| {
+                HttpResponse::try_from_json_status(
+                    &ErrorResponse {
+                        message: err.to_string(),
+                    },
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .unwrap_or(HttpResponse::unknown_error(
+                    "Json serde fail".into(),
+                ))
+            })
+        } else if path.starts_with("/bundle") {
+            match (&method, category) {
+                (&Method::GET, "export") => {
+                    self.export_config_bundle(session, &scope).await
+                },
+                (&Method::POST, "import") => {
+                    self.import_config_bundle(session).await
                 },
-                _ => self.get_config(category).await,
+                _ => Err(pingora::Error::new_str("Url is invalid")),
             }
             .unwrap_or_else(|err| {
                 HttpResponse::try_from_json_status(
                     &ErrorResponse {
                         message: err.to_string(),
                     },
-                    StatusCode::INTERNAL_SERVER_ERROR,
+                    StatusCode::BAD_REQUEST,
+                )
+                .unwrap_or(HttpResponse::unknown_error(
+                    "Json serde fail".into(),
+                ))
+            })
+        } else if path.starts_with("/cluster") {
+            handle_cluster(session, &method, &params)
+                .await
+                .unwrap_or_else(|err| {
+                    HttpResponse::try_from_json_status(
+                        &ErrorResponse {
+                            message: err.to_string(),
+                        },
+                        StatusCode::BAD_REQUEST,
+                    )
+                    .unwrap_or(
+                        HttpResponse::unknown_error("Json serde fail".into()),
+                    )
+                })
+        } else if path.starts_with("/capture") {
+            handle_capture(session, &method, category)
+                .await
+                .unwrap_or_else(|err| {
+                    HttpResponse::try_from_json_status(
+                        &ErrorResponse {
+                            message: err.to_string(),
+                        },
+                        StatusCode::BAD_REQUEST,
+                    )
+                    .unwrap_or(
+                        HttpResponse::unknown_error("Json serde fail".into()),
+                    )
+                })
+        } else if path == "/replay" {
+            handle_replay(session, &method).await.unwrap_or_else(|err| {
+                HttpResponse::try_from_json_status(
+                    &ErrorResponse {
+                        message: err.to_string(),
+                    },
+                    StatusCode::BAD_REQUEST,
                 )
                 .unwrap_or(HttpResponse::unknown_error(
                     "Json serde fail".into(),
                 ))
             })
+        } else if path == "/session" {
+            let info = valid_session
+                .as_ref()
+                .map(|item| SessionInfo {
+                    enabled: true,
+                    csrf_token: item.csrf_token.clone(),
+                    expired_at: item.expired_at,
+                })
+                .unwrap_or(SessionInfo {
+                    enabled: self.sessions.is_some(),
+                    csrf_token: "".to_string(),
+                    expired_at: 0,
+                });
+            HttpResponse::try_from_json(&info).unwrap_or(
+                HttpResponse::unknown_error("Json serde fail".into()),
+            )
+        } else if path == "/logout" && method == Method::POST {
+            let mut resp = HttpResponse::no_content();
+            if let Some(store) = &self.sessions {
+                let current = util::get_cookie_value(
+                    session.req_header(),
+                    SESSION_COOKIE_NAME,
+                );
+                let rotated = rotated_id.take();
+                for id in [current, rotated.as_deref()].into_iter().flatten() {
+                    store.remove(id);
+                }
+                if let Ok(value) =
+                    new_set_cookie(&store.new_cookie(&self.path, "", secure))
+                {
+                    resp.headers = Some(vec![value]);
+                }
+            }
+            resp
         } else if path == "/basic" {
             let mut memory = "".to_string();
             if let Some(value) = memory_stats() {
@@ -445,6 +1910,102 @@ impl Plugin for AdminServe {
                 memory,
             })
             .unwrap_or(HttpResponse::unknown_error("Json serde fail".into()))
+        } else if path == "/topology" {
+            HttpResponse::try_from_json(&get_topology(
+                &config::get_current_config(),
+            ))
+            .unwrap_or(HttpResponse::unknown_error("Json serde fail".into()))
+        } else if path == "/locations/time-series" {
+            let req_header = session.req_header();
+            let location = util::get_query_value(req_header, "location")
+                .unwrap_or_default();
+            let minutes = util::get_query_value(req_header, "minutes")
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(MAX_TIME_SERIES_MINUTES);
+            HttpResponse::try_from_json(&get_time_series(location, minutes))
+                .unwrap_or(HttpResponse::unknown_error(
+                    "Json serde fail".into(),
+                ))
+        } else if path == "/plugins/states" {
+            let req_header = session.req_header();
+            let name =
+                util::get_query_value(req_header, "name").unwrap_or_default();
+            let key = util::get_query_value(req_header, "key")
+                .and_then(|value| urlencoding::decode(value).ok())
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+            HttpResponse::try_from_json(&get_plugins_state(name, &key))
+                .unwrap_or(HttpResponse::unknown_error(
+                    "Json serde fail".into(),
+                ))
+        } else if path == "/upstreams/stats" {
+            HttpResponse::try_from_json(&get_upstreams_stats()).unwrap_or(
+                HttpResponse::unknown_error("Json serde fail".into()),
+            )
+        } else if path.starts_with("/upstreams/") {
+            handle_upstream(session, &method, &params)
+                .await
+                .unwrap_or_else(|err| {
+                    HttpResponse::try_from_json_status(
+                        &ErrorResponse {
+                            message: err.to_string(),
+                        },
+                        StatusCode::BAD_REQUEST,
+                    )
+                    .unwrap_or(
+                        HttpResponse::unknown_error("Json serde fail".into()),
+                    )
+                })
+        } else if path == "/cache/entries" {
+            handle_cache(session, &method).await.unwrap_or_else(|err| {
+                HttpResponse::try_from_json_status(
+                    &ErrorResponse {
+                        message: err.to_string(),
+                    },
+                    StatusCode::BAD_REQUEST,
+                )
+                .unwrap_or(HttpResponse::unknown_error(
+                    "Json serde fail".into(),
+                ))
+            })
+        } else if path.starts_with("/cache/tags/") && method == Method::DELETE {
+            let tag = path.substring("/cache/tags/".len(), path.len());
+            let count = purge_cache_tag(tag).await;
+            cluster::publish(cluster::ClusterEvent::PurgeCacheTag {
+                tag: tag.to_string(),
+            });
+            HttpResponse::try_from_json(&PurgeResult { count }).unwrap_or(
+                HttpResponse::unknown_error("Json serde fail".into()),
+            )
+        } else if path.starts_with("/profiling") {
+            let enabled = match (&method, path.as_str()) {
+                (&Method::POST, "/profiling/enable") => Some(true),
+                (&Method::POST, "/profiling/disable") => Some(false),
+                _ => None,
+            };
+            if enabled
+                .map(|enabled| !set_profiling_enabled(enabled))
+                .unwrap_or_default()
+            {
+                HttpResponse::bad_request("Profiling is not available".into())
+            } else {
+                HttpResponse::try_from_json(&ProfilingInfo {
+                    available: is_profiling_available(),
+                    enabled: is_profiling_enabled(),
+                })
+                .unwrap_or(HttpResponse::unknown_error(
+                    "Json serde fail".into(),
+                ))
+            }
+        } else if path == "/openapi.json" {
+            HttpResponse::try_from_json(&openapi::get_openapi()).unwrap_or(
+                HttpResponse::unknown_error("Json serde fail".into()),
+            )
+        } else if path == "/config-apply" {
+            // the report of the latest applying at runtime
+            HttpResponse::try_from_json(&get_config_apply_report()).unwrap_or(
+                HttpResponse::unknown_error("Json serde fail".into()),
+            )
         } else if path == "/restart" && method == Method::POST {
             if let Err(e) = restart_now() {
                 error!("Restart fail: {e}");
@@ -453,16 +2014,16 @@ impl Plugin for AdminServe {
                 HttpResponse::no_content()
             }
         } else {
-            let mut file = path.substring(1, path.len());
-            if file.is_empty() {
-                file = "index.html";
-            }
-            EmbeddedStaticFile(
-                AdminAsset::get(file),
-                Duration::from_secs(365 * 24 * 3600),
-            )
-            .into()
+            get_static_file(&path)
         };
+        // set the rotated session id to cookie
+        if let (Some(store), Some(id)) = (&self.sessions, rotated_id) {
+            if let Ok(value) =
+                new_set_cookie(&store.new_cookie(&self.path, &id, secure))
+            {
+                resp.headers.get_or_insert_with(Vec::new).push(value);
+            }
+        }
         Ok(Some(resp))
     }
 }
@@ -470,12 +2031,16 @@ impl Plugin for AdminServe {
 #[cfg(test)]
 mod tests {
     use super::{
-        get_method_path, AdminAsset, AdminServe, AdminServeParams,
-        EmbeddedStaticFile,
+        get_method_path, is_observer_allowed, new_staged_diff,
+        update_config_by_body, AdminAsset, AdminScope, AdminServe,
+        AdminServeParams, EmbeddedStaticFile, SessionParams, StagedConfig,
+        TenantScope,
     };
     use crate::plugin::Plugin;
     use crate::{
-        config::set_config_path, config::PluginConf, http_extra::HttpResponse,
+        config::set_config_path, config::PingapConf, config::PluginConf,
+        config::CATEGORY_LOCATION, config::CATEGORY_PLUGIN,
+        config::CATEGORY_UPSTREAM, http_extra::HttpResponse,
     };
     use http::Method;
     use pingora::http::RequestHeader;
@@ -548,6 +2113,330 @@ mod tests {
             "Plugin admin invalid, message: Admin serve plugin should be executed at request or proxy upstream step",
             result.err().unwrap().to_string()
         );
+
+        let params = AdminServeParams::try_from(
+            &toml::from_str::<PluginConf>(
+                r#"
+    category = "admin"
+    path = "/"
+    authorizations = [
+        "YWRtaW46MTIzMTIz",
+    ]
+    tenant_tokens = [
+        "team-a:write:token-a",
+        "team-b:read:token:b",
+    ]
+    "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(2, params.tenant_tokens.len());
+        assert_eq!(b"Bearer token-a".to_vec(), params.tenant_tokens[0].0);
+        assert_eq!(
+            TenantScope {
+                namespace: "team-a".to_string(),
+                writable: true,
+            },
+            params.tenant_tokens[0].1
+        );
+        assert_eq!(b"Bearer token:b".to_vec(), params.tenant_tokens[1].0);
+        assert_eq!(false, params.tenant_tokens[1].1.writable);
+
+        let result = AdminServeParams::try_from(
+            &toml::from_str::<PluginConf>(
+                r#"
+    category = "admin"
+    path = "/"
+    tenant_tokens = [
+        "team-a:write:token-a",
+    ]
+    "#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin admin invalid, message: Authorizations should be set for tenant tokens",
+            result.err().unwrap().to_string()
+        );
+
+        let result = AdminServeParams::try_from(
+            &toml::from_str::<PluginConf>(
+                r#"
+    category = "admin"
+    path = "/"
+    authorizations = [
+        "YWRtaW46MTIzMTIz",
+    ]
+    tenant_tokens = [
+        "team-a:admin:token-a",
+    ]
+    "#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin admin invalid, message: Tenant token should be namespace:read|write:token",
+            result.err().unwrap().to_string()
+        );
+
+        let params = AdminServeParams::try_from(
+            &toml::from_str::<PluginConf>(
+                r#"
+    category = "admin"
+    path = "/"
+    authorizations = [
+        "YWRtaW46MTIzMTIz",
+    ]
+    session_ttl = "2h"
+    session_ip_pinning = true
+    "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            Some(SessionParams {
+                ttl: 7200,
+                idle_timeout: 900,
+                rotate_interval: 300,
+                ip_pinning: true,
+            }),
+            params.session
+        );
+
+        let result = AdminServeParams::try_from(
+            &toml::from_str::<PluginConf>(
+                r#"
+    category = "admin"
+    path = "/"
+    session_ttl = "2h"
+    "#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin admin invalid, message: Authorizations should be set for session",
+            result.err().unwrap().to_string()
+        );
+
+        let params = AdminServeParams::try_from(
+            &toml::from_str::<PluginConf>(
+                r#"
+    category = "admin"
+    path = "/"
+    authorizations = [
+        "YWRtaW46MTIzMTIz",
+    ]
+    observer_tokens = ["dashboard"]
+    read_only = true
+    "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(vec![b"Bearer dashboard".to_vec()], params.observer_tokens);
+        assert_eq!(true, params.read_only);
+
+        let result = AdminServeParams::try_from(
+            &toml::from_str::<PluginConf>(
+                r#"
+    category = "admin"
+    path = "/"
+    observer_tokens = ["dashboard"]
+    "#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin admin invalid, message: Authorizations should be set for observer tokens",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_observer_allowed() {
+        assert_eq!(true, is_observer_allowed(&Method::GET, "/configs"));
+        assert_eq!(true, is_observer_allowed(&Method::GET, "/upstreams/stats"));
+        assert_eq!(true, is_observer_allowed(&Method::POST, "/logout"));
+        assert_eq!(false, is_observer_allowed(&Method::POST, "/configs/a"));
+        assert_eq!(false, is_observer_allowed(&Method::DELETE, "/stages"));
+        assert_eq!(false, is_observer_allowed(&Method::GET, "/capture/har"));
+    }
+
+    #[test]
+    fn test_tenant_check_changes() {
+        let scope = AdminScope::Tenant(TenantScope {
+            namespace: "team-a".to_string(),
+            writable: true,
+        });
+        let mut current = PingapConf::default();
+        update_config_by_body(
+            &mut current,
+            CATEGORY_UPSTREAM,
+            "global",
+            br#"{"addrs": ["127.0.0.1:3000"]}"#,
+        )
+        .unwrap();
+        update_config_by_body(
+            &mut current,
+            CATEGORY_UPSTREAM,
+            "team-a-upstream",
+            br#"{"addrs": ["127.0.0.1:3001"], "namespace": "team-a"}"#,
+        )
+        .unwrap();
+        update_config_by_body(
+            &mut current,
+            CATEGORY_LOCATION,
+            "team-a-location",
+            br#"{"upstream": "team-a-upstream", "namespace": "team-a"}"#,
+        )
+        .unwrap();
+
+        // change the allowed field
+        let mut conf = current.clone();
+        update_config_by_body(
+            &mut conf,
+            CATEGORY_LOCATION,
+            "team-a-location",
+            br#"{"upstream": "team-a-upstream", "path": "/api", "namespace": "team-a"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            true,
+            scope
+                .check_changes(
+                    &current,
+                    &conf,
+                    CATEGORY_LOCATION,
+                    "team-a-location"
+                )
+                .is_ok()
+        );
+
+        // set sendfile root
+        let mut conf = current.clone();
+        update_config_by_body(
+            &mut conf,
+            CATEGORY_LOCATION,
+            "team-a-location",
+            br#"{"upstream": "team-a-upstream", "sendfile_root": "/", "namespace": "team-a"}"#,
+        )
+        .unwrap();
+        let err = scope
+            .check_changes(
+                &current,
+                &conf,
+                CATEGORY_LOCATION,
+                "team-a-location",
+            )
+            .unwrap_err();
+        assert_eq!(&pingora::ErrorType::HTTPStatus(403), err.etype());
+        assert_eq!(
+            true,
+            err.to_string()
+                .contains("location(team-a-location).sendfile_root can't be changed by tenant")
+        );
+        // admin can set sendfile root
+        assert_eq!(
+            true,
+            AdminScope::All
+                .check_changes(
+                    &current,
+                    &conf,
+                    CATEGORY_LOCATION,
+                    "team-a-location"
+                )
+                .is_ok()
+        );
+
+        // change the address of upstream
+        let mut conf = current.clone();
+        update_config_by_body(
+            &mut conf,
+            CATEGORY_UPSTREAM,
+            "team-a-upstream",
+            br#"{"addrs": ["10.0.0.1:22"], "namespace": "team-a"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            true,
+            scope
+                .check_changes(
+                    &current,
+                    &conf,
+                    CATEGORY_UPSTREAM,
+                    "team-a-upstream"
+                )
+                .unwrap_err()
+                .to_string()
+                .contains("upstream(team-a-upstream).addrs can't be changed by tenant")
+        );
+
+        // reference the global upstream
+        let mut conf = current.clone();
+        update_config_by_body(
+            &mut conf,
+            CATEGORY_LOCATION,
+            "team-a-location",
+            br#"{"upstream": "global", "namespace": "team-a"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            true,
+            scope
+                .check_changes(
+                    &current,
+                    &conf,
+                    CATEGORY_LOCATION,
+                    "team-a-location"
+                )
+                .unwrap_err()
+                .to_string()
+                .contains("upstream(global) is not in namespace(team-a)")
+        );
+    }
+
+    #[test]
+    fn test_observer_staged_diff() {
+        let mut current = PingapConf::default();
+        update_config_by_body(
+            &mut current,
+            CATEGORY_PLUGIN,
+            "auth",
+            br#"{"category": "basic_auth", "authorizations": ["current-secret"]}"#,
+        )
+        .unwrap();
+        let mut conf = current.clone();
+        update_config_by_body(
+            &mut conf,
+            CATEGORY_PLUGIN,
+            "auth",
+            br#"{"category": "basic_auth", "authorizations": ["staged-secret"], "hide_credentials": true}"#,
+        )
+        .unwrap();
+        let staged = StagedConfig {
+            base_hash: current.hash().unwrap(),
+            conf,
+        };
+
+        let result = new_staged_diff(
+            current.clone(),
+            staged.clone(),
+            true,
+            &AdminScope::Observer,
+        )
+        .unwrap();
+        assert_eq!(false, result.outdated);
+        assert_eq!(vec!["plugin".to_string()], result.category_list);
+        let diff = result.diff.join("\n");
+        assert_eq!(true, diff.contains("hide_credentials"));
+        assert_eq!(false, diff.contains("current-secret"));
+        assert_eq!(false, diff.contains("staged-secret"));
+
+        let result =
+            new_staged_diff(current, staged, true, &AdminScope::All).unwrap();
+        assert_eq!(true, result.diff.join("\n").contains("staged-secret"));
     }
 
     #[test]
@@ -743,5 +2632,114 @@ path = "/stats"
 "#,
             std::string::String::from_utf8_lossy(resp.body.as_ref())
         );
+
+        // stage the upstream change
+        let body = br#"{
+            "addrs": ["127.0.0.1:5001"]
+        }"#;
+        let headers = [format!("Content-Length: {}", body.len())].join("\r\n");
+        let input_header = format!("POST / HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new()
+            .read(input_header.as_bytes())
+            .read(body)
+            .build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let resp = serve
+            .stage_config(&mut session, &Method::POST, "upstream", "charts")
+            .await
+            .unwrap();
+        assert_eq!(204, resp.status.as_u16());
+        // the staged config is not saved
+        let conf = serve.load_config().await.unwrap();
+        assert_eq!("F0F60723", conf.hash().unwrap());
+
+        let resp = serve.diff_staged_config().await.unwrap();
+        let diff: serde_json::Value =
+            serde_json::from_slice(resp.body.as_ref()).unwrap();
+        assert_eq!(true, diff["staged"].as_bool().unwrap());
+        assert_eq!(false, diff["outdated"].as_bool().unwrap());
+        assert_eq!(r#"["upstream"]"#, diff["category_list"].to_string());
+
+        // the location refers to an unknown upstream
+        let body = br#"{
+            "upstream": "unknown"
+        }"#;
+        let headers = [format!("Content-Length: {}", body.len())].join("\r\n");
+        let input_header = format!("POST / HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new()
+            .read(input_header.as_bytes())
+            .read(body)
+            .build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let result = serve
+            .stage_config(&mut session, &Method::POST, "location", "lo")
+            .await;
+        assert_eq!(true, result.is_err());
+
+        // remove the used upstream is not allowed
+        let mock_io = Builder::new().read(b"DELETE / HTTP/1.1\r\n\r\n").build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let result = serve
+            .stage_config(&mut session, &Method::DELETE, "upstream", "charts")
+            .await;
+        assert_eq!(true, result.is_err());
+
+        serve.staged.lock().await.take();
+        let resp = serve.diff_staged_config().await.unwrap();
+        let diff: serde_json::Value =
+            serde_json::from_slice(resp.body.as_ref()).unwrap();
+        assert_eq!(false, diff["staged"].as_bool().unwrap());
+        assert_eq!("[]", diff["category_list"].to_string());
+
+        // put the same upstream is no-op
+        let put_upstream = |body: &'static [u8]| {
+            let headers =
+                [format!("Content-Length: {}", body.len())].join("\r\n");
+            let input_header = format!("PUT / HTTP/1.1\r\n{headers}\r\n\r\n");
+            let mock_io = Builder::new()
+                .read(input_header.as_bytes())
+                .read(body)
+                .build();
+            Session::new_h1(Box::new(mock_io))
+        };
+        let mut session = put_upstream(br#"{"addrs": ["127.0.0.1:5000"]}"#);
+        session.read_request().await.unwrap();
+        let resp = serve
+            .put_config(&mut session, "upstream", "charts")
+            .await
+            .unwrap();
+        let result: serde_json::Value =
+            serde_json::from_slice(resp.body.as_ref()).unwrap();
+        assert_eq!(false, result["changed"].as_bool().unwrap());
+        assert_eq!("[]", result["diff"].to_string());
+        assert_eq!(
+            "F0F60723",
+            serve.load_config().await.unwrap().hash().unwrap()
+        );
+
+        let mut session = put_upstream(br#"{"addrs": ["127.0.0.1:5001"]}"#);
+        session.read_request().await.unwrap();
+        let resp = serve
+            .put_config(&mut session, "upstream", "charts")
+            .await
+            .unwrap();
+        let result: serde_json::Value =
+            serde_json::from_slice(resp.body.as_ref()).unwrap();
+        assert_eq!(true, result["changed"].as_bool().unwrap());
+        assert_eq!(false, result["created"].as_bool().unwrap());
+        assert_eq!("upstream:charts", result["diff"][0].as_str().unwrap());
+
+        let mut session = put_upstream(br#"{"addrs": ["127.0.0.1:5001"]}"#);
+        session.read_request().await.unwrap();
+        let resp = serve
+            .put_config(&mut session, "upstream", "charts")
+            .await
+            .unwrap();
+        let result: serde_json::Value =
+            serde_json::from_slice(resp.body.as_ref()).unwrap();
+        assert_eq!(false, result["changed"].as_bool().unwrap());
     }
 }
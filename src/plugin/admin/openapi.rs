@@ -0,0 +1,776 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The functions of this module are only used to describe the admin api,
+// the requests are handled by the admin plugin.
+#![allow(dead_code)]
+
+use super::{
+    ApplyResult, BanParams, BasicInfo, DrainParams, ErrorResponse, LoginParams,
+    PauseParams, PluginRuntimeState, PluginsState, ProfilingInfo, PurgeResult,
+    SessionInfo, StagedDiff, SwitchParams,
+};
+use crate::cache::CacheEntry;
+use crate::cluster::{BanInfo, ClusterNode};
+use crate::config::{
+    BasicConf, CertificateConf, ConfigBundle, LocationConf, PingapConf,
+    ServerConf, UpstreamConf,
+};
+use crate::proxy::{
+    BackendDrain, BackendHealthHistory, BackendStats, CaptureParams,
+    CaptureStatus, ConfigApplyReport, HealthCheckRecord, ReplayMismatch,
+    ReplayParams, ReplayStatus, TimeSeriesPoint, Topology, TopologyEdge,
+    TopologyNode, UpstreamColor, UpstreamPause, UpstreamStats,
+};
+use crate::util::get_pkg_version;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use utoipa::{OpenApi, ToSchema};
+
+/// The params of plugin, the other fields are different
+/// for each category of plugin.
+#[derive(ToSchema)]
+struct PluginParams {
+    /// The category of plugin, e.g. `compression`, `cache` and `limit`
+    category: String,
+    /// The step of plugin, e.g. `request` and `proxy_upstream`
+    step: Option<String>,
+    remark: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/login",
+    tag = "session",
+    request_body = LoginParams,
+    responses(
+        (status = 200, description = "Login success, the session id is set to the cookie", body = SessionInfo),
+        (status = 401, description = "Account or password is invalid", body = ErrorResponse),
+    )
+)]
+fn login() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/session",
+    tag = "session",
+    responses(
+        (status = 200, description = "The session of request, the csrf token should be set by header X-Pingap-Csrf-Token for the mutating requests", body = SessionInfo),
+        (status = 401, description = "Session is expired or invalid", body = ErrorResponse),
+    )
+)]
+fn get_session() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/logout",
+    tag = "session",
+    responses((status = 204, description = "Logout success, the session is removed")),
+)]
+fn logout() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/configs/{category}",
+    tag = "config",
+    params(
+        ("category" = String, Path, description = "The category of config: upstream, location, server, plugin, certificate, toml, or empty for the whole config")
+    ),
+    responses(
+        (status = 200, description = "The config of category", body = PingapConf),
+        (status = 500, description = "Load config fail", body = ErrorResponse),
+    )
+)]
+fn get_config() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/configs/basic/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name is ignored for basic config")),
+    request_body = BasicConf,
+    responses(
+        (status = 204, description = "Update basic config success"),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn update_basic() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/configs/server/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name of server")),
+    request_body = ServerConf,
+    responses(
+        (status = 204, description = "Create or update server success"),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn update_server() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/configs/location/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name of location")),
+    request_body = LocationConf,
+    responses(
+        (status = 204, description = "Create or update location success"),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn update_location() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/configs/upstream/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name of upstream")),
+    request_body = UpstreamConf,
+    responses(
+        (status = 204, description = "Create or update upstream success"),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn update_upstream() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/configs/plugin/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name of plugin")),
+    request_body = PluginParams,
+    responses(
+        (status = 204, description = "Create or update plugin success"),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn update_plugin() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/configs/certificate/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name of certificate")),
+    request_body = CertificateConf,
+    responses(
+        (status = 204, description = "Create or update certificate success"),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn update_certificate() {}
+
+#[utoipa::path(
+    put,
+    path = "/api/configs/server/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name of server")),
+    request_body = ServerConf,
+    responses(
+        (status = 200, description = "The server is applied, it's not saved if nothing changes", body = ApplyResult),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn put_server() {}
+
+#[utoipa::path(
+    put,
+    path = "/api/configs/location/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name of location")),
+    request_body = LocationConf,
+    responses(
+        (status = 200, description = "The location is applied, it's not saved if nothing changes", body = ApplyResult),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn put_location() {}
+
+#[utoipa::path(
+    put,
+    path = "/api/configs/upstream/{name}",
+    tag = "config",
+    params(("name" = String, Path, description = "The name of upstream")),
+    request_body = UpstreamConf,
+    responses(
+        (status = 200, description = "The upstream is applied, it's not saved if nothing changes", body = ApplyResult),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn put_upstream() {}
+
+#[utoipa::path(
+    delete,
+    path = "/api/configs/{category}/{name}",
+    tag = "config",
+    params(
+        ("category" = String, Path, description = "The category of config"),
+        ("name" = String, Path, description = "The name of config"),
+    ),
+    responses(
+        (status = 204, description = "Remove config success"),
+        (status = 500, description = "The config is used by others", body = ErrorResponse),
+    )
+)]
+fn remove_config() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/stages/{category}/{name}",
+    tag = "stage",
+    params(
+        ("category" = String, Path, description = "The category of config"),
+        ("name" = String, Path, description = "The name of config"),
+    ),
+    request_body(content = String, description = "The json of config, the same as `/api/configs/{category}/{name}`"),
+    responses(
+        (status = 204, description = "Stage the config success"),
+        (status = 500, description = "Invalid config", body = ErrorResponse),
+    )
+)]
+fn stage_update_config() {}
+
+#[utoipa::path(
+    delete,
+    path = "/api/stages/{category}/{name}",
+    tag = "stage",
+    params(
+        ("category" = String, Path, description = "The category of config"),
+        ("name" = String, Path, description = "The name of config"),
+    ),
+    responses(
+        (status = 204, description = "Stage the removal of config success"),
+        (status = 500, description = "The config is used by others", body = ErrorResponse),
+    )
+)]
+fn stage_remove_config() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/stages/diff",
+    tag = "stage",
+    responses(
+        (status = 200, description = "The diff of staged config", body = StagedDiff),
+    )
+)]
+fn diff_staged_config() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/stages/apply",
+    tag = "stage",
+    responses(
+        (status = 204, description = "Apply the staged config success"),
+        (status = 409, description = "The config is changed after staged", body = ErrorResponse),
+    )
+)]
+fn apply_staged_config() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/bundle/export",
+    tag = "config",
+    params(
+        ("secrets" = Option<String>, Query, description = "The secrets of bundle are plain(default), strip or encrypt"),
+        ("X-Pingap-Passphrase" = Option<String>, Header, description = "The passphrase for encrypting the secrets"),
+    ),
+    responses(
+        (status = 200, description = "The bundle of the whole config", body = ConfigBundle),
+        (status = 400, description = "Export config bundle fail", body = ErrorResponse),
+    )
+)]
+fn export_config_bundle() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/bundle/import",
+    tag = "config",
+    params(
+        ("dry_run" = Option<bool>, Query, description = "Only validate the bundle and return the diff if it's true"),
+        ("X-Pingap-Passphrase" = Option<String>, Header, description = "The passphrase for decrypting the secrets"),
+    ),
+    request_body = ConfigBundle,
+    responses(
+        (status = 200, description = "The result of importing config bundle", body = ApplyResult),
+        (status = 400, description = "Invalid config bundle", body = ErrorResponse),
+    )
+)]
+fn import_config_bundle() {}
+
+#[utoipa::path(
+    delete,
+    path = "/api/stages",
+    tag = "stage",
+    responses((status = 204, description = "Discard the staged config")),
+)]
+fn discard_staged_config() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/cluster/nodes",
+    tag = "cluster",
+    responses(
+        (status = 200, description = "The nodes of cluster", body = [ClusterNode]),
+        (status = 400, description = "Cluster is not enabled", body = ErrorResponse),
+    )
+)]
+fn get_cluster_nodes() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/cluster/bans",
+    tag = "cluster",
+    responses(
+        (status = 200, description = "The banned ip list", body = [BanInfo]),
+        (status = 400, description = "Cluster is not enabled", body = ErrorResponse),
+    )
+)]
+fn get_bans() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/cluster/bans",
+    tag = "cluster",
+    request_body = BanParams,
+    responses(
+        (status = 204, description = "Ban the ip success"),
+        (status = 400, description = "Invalid params", body = ErrorResponse),
+    )
+)]
+fn ban_ip() {}
+
+#[utoipa::path(
+    delete,
+    path = "/api/cluster/bans/{ip}",
+    tag = "cluster",
+    params(("ip" = String, Path, description = "The banned ip")),
+    responses(
+        (status = 204, description = "Unban the ip success"),
+        (status = 400, description = "Cluster is not enabled", body = ErrorResponse),
+    )
+)]
+fn unban_ip() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/capture",
+    tag = "capture",
+    responses((status = 200, description = "The status of capture", body = CaptureStatus)),
+)]
+fn get_capture_status() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/capture",
+    tag = "capture",
+    request_body = CaptureParams,
+    responses(
+        (status = 200, description = "Start capture success", body = CaptureStatus),
+        (status = 400, description = "Invalid params", body = ErrorResponse),
+    )
+)]
+fn start_capture() {}
+
+#[utoipa::path(
+    delete,
+    path = "/api/capture",
+    tag = "capture",
+    responses((status = 204, description = "Stop capture success")),
+)]
+fn stop_capture() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/capture/har",
+    tag = "capture",
+    responses((status = 200, description = "The captured requests of har format", content_type = "application/json")),
+)]
+fn get_capture_har() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/replay",
+    tag = "replay",
+    responses((status = 200, description = "The status of running or last replay", body = ReplayStatus)),
+)]
+fn get_replay_status() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/replay",
+    tag = "replay",
+    request_body = ReplayParams,
+    responses(
+        (status = 200, description = "Start replay success", body = ReplayStatus),
+        (status = 400, description = "Invalid params or replay is running", body = ErrorResponse),
+    )
+)]
+fn start_replay() {}
+
+#[utoipa::path(
+    delete,
+    path = "/api/replay",
+    tag = "replay",
+    responses((status = 204, description = "Stop replay success")),
+)]
+fn stop_replay() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/basic",
+    tag = "system",
+    responses((status = 200, description = "The basic info of pingap", body = BasicInfo)),
+)]
+fn get_basic_info() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/upstreams/stats",
+    tag = "system",
+    responses((status = 200, description = "The stats of upstreams and their backends", body = HashMap<String, UpstreamStats>)),
+)]
+fn get_upstreams_stats() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/topology",
+    tag = "system",
+    responses((status = 200, description = "The routing topology of servers, locations, upstreams and backends", body = Topology)),
+)]
+fn get_topology() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/locations/time-series",
+    tag = "system",
+    params(
+        ("location" = Option<String>, Query, description = "The name of location, all locations are returned if it's empty"),
+        ("minutes" = Option<usize>, Query, description = "The last minutes of time series, default and max is 60"),
+    ),
+    responses((status = 200, description = "The time series of locations in minute, including rps, error rate, latency percentiles and cache hit ratio", body = HashMap<String, Vec<TimeSeriesPoint>>)),
+)]
+fn get_locations_time_series() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/plugins/states",
+    tag = "system",
+    params(
+        ("name" = Option<String>, Query, description = "The name of plugin, all plugins are returned if it's empty"),
+        ("key" = Option<String>, Query, description = "The key to inspect, e.g. the client ip of limit plugin"),
+    ),
+    responses((status = 200, description = "The runtime state of plugins(e.g. the counters and recent rejections of limit plugin) and the banned ips", body = PluginsState)),
+)]
+fn get_plugins_states() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/upstreams/{name}/pause",
+    tag = "system",
+    params(("name" = String, Path, description = "The name of upstream")),
+    request_body = PauseParams,
+    responses(
+        (status = 200, description = "The paused state of upstream", body = UpstreamPause),
+        (status = 400, description = "Upstream is not found or invalid params", body = ErrorResponse),
+    )
+)]
+fn pause_upstream() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/upstreams/{name}/resume",
+    tag = "system",
+    params(("name" = String, Path, description = "The name of upstream")),
+    request_body = PauseParams,
+    responses(
+        (status = 204, description = "Resume the upstream success"),
+        (status = 400, description = "Upstream is not found or invalid params", body = ErrorResponse),
+    )
+)]
+fn resume_upstream() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/upstreams/{name}/drain",
+    tag = "system",
+    params(("name" = String, Path, description = "The name of upstream")),
+    request_body = DrainParams,
+    responses(
+        (status = 200, description = "The draining state of backend, it's the same as zero weight", body = BackendDrain),
+        (status = 400, description = "Upstream or backend is not found", body = ErrorResponse),
+    )
+)]
+fn drain_backend() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/upstreams/{name}/restore",
+    tag = "system",
+    params(("name" = String, Path, description = "The name of upstream")),
+    request_body = DrainParams,
+    responses(
+        (status = 204, description = "Restore the drained backend success"),
+        (status = 400, description = "Upstream is not found or invalid params", body = ErrorResponse),
+    )
+)]
+fn restore_backend() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/upstreams/{name}/switch",
+    tag = "system",
+    params(("name" = String, Path, description = "The name of blue green upstream")),
+    request_body = SwitchParams,
+    responses(
+        (status = 204, description = "Switch the active color of upstream success"),
+        (status = 400, description = "Upstream is not blue green or the healthy backends of target are not enough", body = ErrorResponse),
+    )
+)]
+fn switch_upstream() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/upstreams/{name}/health-history",
+    tag = "system",
+    params(("name" = String, Path, description = "The name of upstream")),
+    responses(
+        (status = 200, description = "The recent health check results of backends", body = HashMap<String, BackendHealthHistory>),
+        (status = 400, description = "Upstream is not found", body = ErrorResponse),
+    )
+)]
+fn get_upstream_health_history() {}
+
+#[utoipa::path(
+    delete,
+    path = "/api/cache/tags/{tag}",
+    tag = "system",
+    params(("tag" = String, Path, description = "The tag of cache")),
+    responses((status = 200, description = "The count of purged cache", body = PurgeResult)),
+)]
+fn purge_cache_tag() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/cache/entries",
+    tag = "system",
+    params(
+        ("url" = Option<String>, Query, description = "The url of cache, the biggest or hottest entries are listed if it's empty"),
+        ("prefix" = Option<String>, Query, description = "The prefix of cache key, it's the namespace and headers value of cache plugin"),
+        ("sort" = Option<String>, Query, description = "Sort the entries by size or hits(default)"),
+        ("limit" = Option<usize>, Query, description = "The max count of entries, default is 20"),
+    ),
+    responses(
+        (status = 200, description = "The cache entry of url or the list of entries", body = CacheEntry),
+        (status = 404, description = "Cache of url is not found", body = ErrorResponse),
+    )
+)]
+fn get_cache_entries() {}
+
+#[utoipa::path(
+    delete,
+    path = "/api/cache/entries",
+    tag = "system",
+    params(
+        ("url" = String, Query, description = "The url of cache"),
+        ("prefix" = Option<String>, Query, description = "The prefix of cache key, it's the namespace and headers value of cache plugin"),
+    ),
+    responses((status = 200, description = "The count of purged cache", body = PurgeResult)),
+)]
+fn remove_cache_entry() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/profiling",
+    tag = "system",
+    responses((status = 200, description = "The status of profiling", body = ProfilingInfo)),
+)]
+fn get_profiling() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/profiling/{action}",
+    tag = "system",
+    params(("action" = String, Path, description = "enable or disable")),
+    responses(
+        (status = 200, description = "The status of profiling", body = ProfilingInfo),
+        (status = 400, description = "Profiling is not available"),
+    )
+)]
+fn toggle_profiling() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/config-apply",
+    tag = "system",
+    responses((status = 200, description = "The report of the latest applying config at runtime, it's null if no config has been applied", body = Option<ConfigApplyReport>)),
+)]
+fn get_config_apply_report() {}
+
+#[utoipa::path(
+    post,
+    path = "/api/restart",
+    tag = "system",
+    responses(
+        (status = 204, description = "Restart pingap success"),
+        (status = 400, description = "Restart fail"),
+    )
+)]
+fn restart() {}
+
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "system",
+    responses((status = 200, description = "The openapi document of admin api")),
+)]
+fn get_openapi_json() {}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Pingap admin api",
+        description = "The api of admin plugin, the paths are relative to the path of admin plugin"
+    ),
+    paths(
+        login,
+        get_session,
+        logout,
+        get_config,
+        update_basic,
+        update_server,
+        update_location,
+        update_upstream,
+        update_plugin,
+        update_certificate,
+        put_server,
+        put_location,
+        put_upstream,
+        remove_config,
+        stage_update_config,
+        stage_remove_config,
+        diff_staged_config,
+        apply_staged_config,
+        export_config_bundle,
+        import_config_bundle,
+        discard_staged_config,
+        get_cluster_nodes,
+        get_bans,
+        ban_ip,
+        unban_ip,
+        get_capture_status,
+        start_capture,
+        stop_capture,
+        get_capture_har,
+        get_replay_status,
+        start_replay,
+        stop_replay,
+        get_basic_info,
+        get_upstreams_stats,
+        get_topology,
+        get_locations_time_series,
+        get_plugins_states,
+        pause_upstream,
+        resume_upstream,
+        drain_backend,
+        restore_backend,
+        switch_upstream,
+        get_upstream_health_history,
+        purge_cache_tag,
+        get_cache_entries,
+        remove_cache_entry,
+        get_profiling,
+        toggle_profiling,
+        get_config_apply_report,
+        restart,
+        get_openapi_json,
+    ),
+    components(schemas(
+        PingapConf,
+        BasicConf,
+        ServerConf,
+        LocationConf,
+        UpstreamConf,
+        CertificateConf,
+        PluginParams,
+        ErrorResponse,
+        LoginParams,
+        SessionInfo,
+        ApplyResult,
+        StagedDiff,
+        ConfigBundle,
+        ClusterNode,
+        BanInfo,
+        BanParams,
+        CaptureParams,
+        CaptureStatus,
+        ReplayParams,
+        ReplayStatus,
+        ReplayMismatch,
+        BasicInfo,
+        UpstreamStats,
+        BackendStats,
+        BackendDrain,
+        UpstreamPause,
+        UpstreamColor,
+        BackendHealthHistory,
+        HealthCheckRecord,
+        Topology,
+        TopologyNode,
+        TopologyEdge,
+        TimeSeriesPoint,
+        PluginsState,
+        PluginRuntimeState,
+        PauseParams,
+        DrainParams,
+        SwitchParams,
+        PurgeResult,
+        CacheEntry,
+        ProfilingInfo,
+        ConfigApplyReport,
+    ))
+)]
+struct ApiDoc;
+
+static OPENAPI: Lazy<utoipa::openapi::OpenApi> = Lazy::new(|| {
+    let mut doc = ApiDoc::openapi();
+    doc.info.version = get_pkg_version().to_string();
+    doc
+});
+
+/// Gets the openapi document of admin api.
+pub fn get_openapi() -> &'static utoipa::openapi::OpenApi {
+    &OPENAPI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_openapi;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_get_openapi() {
+        let doc = get_openapi();
+        assert_eq!("Pingap admin api", doc.info.title);
+        let value = serde_json::to_value(doc).unwrap();
+        assert_eq!(
+            true,
+            value["paths"]["/api/configs/upstream/{name}"]["post"].is_object()
+        );
+        assert_eq!(
+            true,
+            value["components"]["schemas"]["UpstreamConf"].is_object()
+        );
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::AdminScope;
+use ahash::AHashMap;
+use cookie::{Cookie, SameSite};
+use nanoid::nanoid;
+use std::sync::Mutex;
+
+pub(super) const SESSION_COOKIE_NAME: &str = "pingap_admin_session";
+// the csrf token should be set by this header for the mutating requests
+pub(super) const HTTP_HEADER_NAME_CSRF_TOKEN: &str = "X-Pingap-Csrf-Token";
+// the max count of sessions, the least active one is removed if exceeded
+const MAX_SESSIONS: usize = 1024;
+// the rotated session is still valid in the grace period,
+// so the concurrent requests with the old cookie are not rejected
+const ROTATION_GRACE_SECONDS: u64 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct SessionParams {
+    // the max lifetime(seconds) of session
+    pub ttl: u64,
+    // the session is expired if it's not active in the timeout(seconds)
+    pub idle_timeout: u64,
+    // the session id is rotated at the interval(seconds)
+    pub rotate_interval: u64,
+    // the session can only be used by the ip which logs in
+    pub ip_pinning: bool,
+}
+
+#[derive(Debug, Clone)]
+struct AdminSession {
+    scope: AdminScope,
+    ip: String,
+    csrf_token: String,
+    created_at: u64,
+    last_active_at: u64,
+    rotated_at: u64,
+    // the session is replaced by a new one, it's valid until the time
+    retired_until: Option<u64>,
+}
+
+/// The valid session of request.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct ValidSession {
+    pub scope: AdminScope,
+    pub csrf_token: String,
+    pub expired_at: u64,
+    // the new session id if the session is rotated
+    pub rotated_id: Option<String>,
+}
+
+pub(super) struct SessionStore {
+    pub params: SessionParams,
+    sessions: Mutex<AHashMap<String, AdminSession>>,
+}
+
+impl AdminSession {
+    fn is_expired(&self, params: &SessionParams, now: u64) -> bool {
+        self.created_at + params.ttl <= now
+            || self.last_active_at + params.idle_timeout <= now
+            || self.retired_until.is_some_and(|value| value <= now)
+    }
+}
+
+impl SessionStore {
+    pub fn new(params: SessionParams) -> Self {
+        Self {
+            params,
+            sessions: Mutex::new(AHashMap::new()),
+        }
+    }
+    /// Creates a new session for the scope, it returns the session id
+    /// and the csrf token.
+    pub fn create(
+        &self,
+        scope: AdminScope,
+        ip: &str,
+        now: u64,
+    ) -> (String, String) {
+        let id = nanoid!(32);
+        let csrf_token = nanoid!(32);
+        let Ok(mut sessions) = self.sessions.lock() else {
+            return (id, csrf_token);
+        };
+        sessions.retain(|_, item| !item.is_expired(&self.params, now));
+        if sessions.len() >= MAX_SESSIONS {
+            let least_active = sessions
+                .iter()
+                .min_by_key(|(_, item)| item.last_active_at)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = least_active {
+                sessions.remove(&key);
+            }
+        }
+        sessions.insert(
+            id.clone(),
+            AdminSession {
+                scope,
+                ip: ip.to_string(),
+                csrf_token: csrf_token.clone(),
+                created_at: now,
+                last_active_at: now,
+                rotated_at: now,
+                retired_until: None,
+            },
+        );
+        (id, csrf_token)
+    }
+    /// Validates the session and refreshes its active time,
+    /// the session is rotated if it reaches the rotate interval.
+    pub fn validate(
+        &self,
+        id: &str,
+        ip: &str,
+        now: u64,
+    ) -> Result<ValidSession, &'static str> {
+        let Ok(mut sessions) = self.sessions.lock() else {
+            return Err("Session is unavailable");
+        };
+        let Some(session) = sessions.get_mut(id) else {
+            return Err("Session is invalid");
+        };
+        if session.is_expired(&self.params, now) {
+            sessions.remove(id);
+            return Err("Session is expired");
+        }
+        if self.params.ip_pinning && session.ip != ip {
+            return Err("Session is not allowed for the ip");
+        }
+        session.last_active_at = now;
+        let mut result = ValidSession {
+            scope: session.scope.clone(),
+            csrf_token: session.csrf_token.clone(),
+            expired_at: session.created_at + self.params.ttl,
+            rotated_id: None,
+        };
+        if session.retired_until.is_none()
+            && session.rotated_at + self.params.rotate_interval <= now
+        {
+            session.retired_until = Some(now + ROTATION_GRACE_SECONDS);
+            let mut rotated = session.clone();
+            rotated.rotated_at = now;
+            rotated.retired_until = None;
+            let new_id = nanoid!(32);
+            sessions.insert(new_id.clone(), rotated);
+            result.rotated_id = Some(new_id);
+        }
+        Ok(result)
+    }
+    /// Removes the session, the rotated sessions of it are kept
+    /// until they are expired.
+    pub fn remove(&self, id: &str) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(id);
+        }
+    }
+    /// Creates the cookie of session, the empty id clears the cookie.
+    pub fn new_cookie(&self, path: &str, id: &str, secure: bool) -> String {
+        let max_age = if id.is_empty() {
+            0
+        } else {
+            self.params.ttl as i64
+        };
+        let path = if path.is_empty() { "/" } else { path };
+        Cookie::build((SESSION_COOKIE_NAME, id))
+            .path(path)
+            .max_age(cookie::time::Duration::seconds(max_age))
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .secure(secure)
+            .build()
+            .to_string()
+    }
+}
+
+/// Compares the csrf token in constant time.
+pub(super) fn is_csrf_token_matched(expected: &str, value: &str) -> bool {
+    if expected.is_empty() || expected.len() != value.len() {
+        return false;
+    }
+    expected
+        .bytes()
+        .zip(value.bytes())
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_csrf_token_matched, SessionParams, SessionStore};
+    use crate::plugin::admin::AdminScope;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_session_store() {
+        let store = SessionStore::new(SessionParams {
+            ttl: 3600,
+            idle_timeout: 600,
+            rotate_interval: 300,
+            ip_pinning: true,
+        });
+        let now = 1_700_000_000;
+        let (id, csrf_token) = store.create(AdminScope::All, "1.1.1.1", now);
+        assert_eq!(32, id.len());
+
+        let result = store.validate(&id, "1.1.1.1", now + 10).unwrap();
+        assert_eq!(AdminScope::All, result.scope);
+        assert_eq!(csrf_token, result.csrf_token);
+        assert_eq!(now + 3600, result.expired_at);
+        assert_eq!(None, result.rotated_id);
+
+        // ip pinning
+        assert_eq!(
+            "Session is not allowed for the ip",
+            store.validate(&id, "2.2.2.2", now + 10).unwrap_err()
+        );
+
+        // rotation, the old session is valid in the grace period
+        let result = store.validate(&id, "1.1.1.1", now + 300).unwrap();
+        let new_id = result.rotated_id.unwrap();
+        assert_eq!(csrf_token, result.csrf_token);
+        assert_eq!(true, store.validate(&id, "1.1.1.1", now + 320).is_ok());
+        assert_eq!(
+            "Session is expired",
+            store.validate(&id, "1.1.1.1", now + 330).unwrap_err()
+        );
+        assert_eq!(true, store.validate(&new_id, "1.1.1.1", now + 330).is_ok());
+
+        // idle timeout
+        assert_eq!(
+            "Session is expired",
+            store.validate(&new_id, "1.1.1.1", now + 930).unwrap_err()
+        );
+        assert_eq!(
+            "Session is invalid",
+            store.validate(&new_id, "1.1.1.1", now + 930).unwrap_err()
+        );
+
+        // logout
+        let (id, _) = store.create(AdminScope::All, "1.1.1.1", now);
+        store.remove(&id);
+        assert_eq!(
+            "Session is invalid",
+            store.validate(&id, "1.1.1.1", now).unwrap_err()
+        );
+
+        assert_eq!(
+            "pingap_admin_session=abc; HttpOnly; SameSite=Strict; Secure; Path=/pingap; Max-Age=3600",
+            store.new_cookie("/pingap", "abc", true)
+        );
+        assert_eq!(
+            "pingap_admin_session=; HttpOnly; SameSite=Strict; Path=/; Max-Age=0",
+            store.new_cookie("", "", false)
+        );
+    }
+
+    #[test]
+    fn test_csrf_token_matched() {
+        assert_eq!(true, is_csrf_token_matched("abc", "abc"));
+        assert_eq!(false, is_csrf_token_matched("abc", "abd"));
+        assert_eq!(false, is_csrf_token_matched("abc", "ab"));
+        assert_eq!(false, is_csrf_token_matched("", ""));
+    }
+}
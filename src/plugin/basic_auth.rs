@@ -26,6 +26,17 @@ use http::StatusCode;
 use pingora::proxy::Session;
 use tracing::debug;
 
+// the variable name of basic auth user in context
+const BASIC_AUTH_USER_VARIABLE: &str = "basic_auth_user";
+
+/// Gets the user from basic authorization, e.g. `Basic base64(user:pass)`.
+fn get_basic_auth_user(value: &[u8]) -> Option<String> {
+    let value = value.strip_prefix(b"Basic ")?;
+    let buf = STANDARD.decode(value).ok()?;
+    let value = std::string::String::from_utf8(buf).ok()?;
+    value.split_once(':').map(|(user, _)| user.to_string())
+}
+
 pub struct BasicAuth {
     plugin_step: PluginStep,
     authorizations: Vec<Vec<u8>>,
@@ -115,7 +126,7 @@ impl Plugin for BasicAuth {
         &self,
         step: PluginStep,
         session: &mut Session,
-        _ctx: &mut State,
+        ctx: &mut State,
     ) -> pingora::Result<Option<HttpResponse>> {
         if step != self.plugin_step {
             return Ok(None);
@@ -127,6 +138,10 @@ impl Plugin for BasicAuth {
         if !self.authorizations.contains(&value.to_vec()) {
             return Ok(Some(self.unauthorized_resp.clone()));
         }
+        // the user can be used by other plugins, e.g. limit by user
+        if let Some(user) = get_basic_auth_user(value) {
+            ctx.add_variable(BASIC_AUTH_USER_VARIABLE, &user);
+        }
         if self.hide_credentials {
             session
                 .req_header_mut()
@@ -227,15 +242,13 @@ authorizations = [
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
+        let mut ctx = State::default();
         let result = auth
-            .handle_request(
-                PluginStep::Request,
-                &mut session,
-                &mut State::default(),
-            )
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
             .await
             .unwrap();
         assert_eq!(true, result.is_none());
+        assert_eq!("admin", ctx.get_variable("basic_auth_user").unwrap());
 
         // auth fail
         let headers = ["Authorization: Basic YWRtaW46MTIzMTIa"].join("\r\n");
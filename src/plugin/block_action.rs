@@ -0,0 +1,291 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{get_int_conf, get_str_conf, Error, Result};
+use crate::config::{PluginCategory, PluginConf};
+use crate::http_extra::HttpResponse;
+use crate::sink::{self, SecurityEvent};
+use crate::state::State;
+use crate::util;
+use bytes::Bytes;
+use http::{header, StatusCode};
+use humantime::parse_duration;
+use once_cell::sync::Lazy;
+use pingora::proxy::Session;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+// the status of dropped request in log, the same as nginx
+const DROP_STATUS: u16 = 444;
+const DEFAULT_TARPIT_DURATION: Duration = Duration::from_secs(30);
+const DEFAULT_TARPIT_MAX: u32 = 100;
+const TARPIT_INTERVAL: Duration = Duration::from_secs(1);
+
+// the processing tarpit requests of all plugins
+static TARPIT_PROCESSING: AtomicU32 = AtomicU32::new(0);
+
+// the response is sent by block action, so it's ignored by location
+static IGNORE_RESPONSE: Lazy<HttpResponse> = Lazy::new(|| HttpResponse {
+    status: StatusCode::from_u16(999).unwrap(),
+    ..Default::default()
+});
+
+/// The action for blocked request of waf, acl and limit plugins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum BlockAction {
+    // respond the deny response of plugin
+    #[default]
+    Deny,
+    // close the connection without response
+    Drop,
+    // respond extremely slowly to waste the resources of scanner
+    Tarpit,
+}
+
+/// Handles the blocked request by the block action of plugin.
+#[derive(Debug, Clone)]
+pub struct Blocker {
+    pub action: BlockAction,
+    // the category of plugin, it's used for security event
+    category: String,
+    // the duration of tarpit response, one byte is sent every second
+    tarpit_duration: Duration,
+    // the max processing tarpit requests, the request is dropped if exceeded
+    tarpit_max: u32,
+}
+
+impl Default for Blocker {
+    fn default() -> Self {
+        Self {
+            action: BlockAction::Deny,
+            category: String::new(),
+            tarpit_duration: DEFAULT_TARPIT_DURATION,
+            tarpit_max: DEFAULT_TARPIT_MAX,
+        }
+    }
+}
+
+struct TarpitGuard;
+
+impl Drop for TarpitGuard {
+    fn drop(&mut self) {
+        TARPIT_PROCESSING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Gets the count of processing tarpit requests.
+pub fn get_tarpit_processing() -> u32 {
+    TARPIT_PROCESSING.load(Ordering::Relaxed)
+}
+
+impl Blocker {
+    /// Creates the blocker from `block_action`, `tarpit_duration`
+    /// and `tarpit_max` of plugin config.
+    pub fn new(category: PluginCategory, value: &PluginConf) -> Result<Self> {
+        let new_error = |message: String| Error::Invalid {
+            category: category.to_string(),
+            message,
+        };
+        let action = match get_str_conf(value, "block_action").as_str() {
+            "" | "deny" => BlockAction::Deny,
+            "drop" => BlockAction::Drop,
+            "tarpit" => BlockAction::Tarpit,
+            action => {
+                return Err(new_error(format!(
+                    "block action({action}) should be deny, drop or tarpit"
+                )));
+            },
+        };
+        let tarpit_duration = get_str_conf(value, "tarpit_duration");
+        let tarpit_duration = if tarpit_duration.is_empty() {
+            DEFAULT_TARPIT_DURATION
+        } else {
+            parse_duration(&tarpit_duration)
+                .map_err(|e| new_error(e.to_string()))?
+        };
+        let tarpit_max = get_int_conf(value, "tarpit_max");
+        Ok(Self {
+            action,
+            category: category.to_string(),
+            tarpit_duration,
+            tarpit_max: if tarpit_max > 0 {
+                tarpit_max as u32
+            } else {
+                DEFAULT_TARPIT_MAX
+            },
+        })
+    }
+    /// Handles the blocked request, the deny response is returned for
+    /// deny action, otherwise the request is handled here and the ignored
+    /// response is returned.
+    pub async fn block(
+        &self,
+        session: &mut Session,
+        ctx: &mut State,
+        resp: HttpResponse,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if sink::is_sink_enabled() {
+            let ip = ctx
+                .client_ip
+                .clone()
+                .unwrap_or_else(|| util::get_client_ip(session));
+            let req_header = session.req_header();
+            sink::send_security_event(SecurityEvent::Block {
+                plugin: self.category.clone(),
+                action: self.action.to_string(),
+                ip,
+                method: req_header.method.to_string(),
+                path: req_header.uri.path().to_string(),
+            });
+        }
+        match self.action {
+            BlockAction::Deny => Ok(Some(resp)),
+            BlockAction::Drop => {
+                drop_request(session, ctx);
+                Ok(Some(IGNORE_RESPONSE.clone()))
+            },
+            BlockAction::Tarpit => {
+                let acquired = TARPIT_PROCESSING
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                        (v < self.tarpit_max).then_some(v + 1)
+                    })
+                    .is_ok();
+                // drop the request to protect itself from too many tarpits
+                if !acquired {
+                    drop_request(session, ctx);
+                    return Ok(Some(IGNORE_RESPONSE.clone()));
+                }
+                let _guard = TarpitGuard;
+                self.tarpit(session, ctx, resp).await?;
+                Ok(Some(IGNORE_RESPONSE.clone()))
+            },
+        }
+    }
+    /// Sends the response header, and then one byte of body every second
+    /// until the tarpit duration is reached or the client is closed.
+    async fn tarpit(
+        &self,
+        session: &mut Session,
+        ctx: &mut State,
+        resp: HttpResponse,
+    ) -> pingora::Result<()> {
+        ctx.add_journal(|| "block:tarpit".to_string());
+        ctx.status = Some(resp.status);
+        session.set_keepalive(None);
+        let mut header = resp.get_response_header()?;
+        // the body is sent until the connection is closed
+        header.remove_header(&header::CONTENT_LENGTH);
+        session
+            .write_response_header(Box::new(header), false)
+            .await?;
+        let count = self.tarpit_duration.as_secs().max(1);
+        for _ in 0..count {
+            tokio::time::sleep(TARPIT_INTERVAL).await;
+            if session
+                .write_response_body(Some(Bytes::from_static(b" ")), false)
+                .await
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
+        let _ = session.write_response_body(Some(Bytes::new()), true).await;
+        Ok(())
+    }
+}
+
+/// Closes the connection of request without response.
+fn drop_request(session: &mut Session, ctx: &mut State) {
+    ctx.add_journal(|| "block:drop".to_string());
+    ctx.status = StatusCode::from_u16(DROP_STATUS).ok();
+    session.set_keepalive(None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockAction, Blocker};
+    use crate::config::{PluginCategory, PluginConf};
+    use crate::http_extra::HttpResponse;
+    use crate::state::State;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_blocker_params() {
+        let blocker =
+            Blocker::new(PluginCategory::IpRestriction, &PluginConf::new())
+                .unwrap();
+        assert_eq!(BlockAction::Deny, blocker.action);
+
+        let blocker = Blocker::new(
+            PluginCategory::IpRestriction,
+            &toml::from_str::<PluginConf>(
+                r###"
+block_action = "tarpit"
+tarpit_duration = "1m"
+tarpit_max = 10
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(BlockAction::Tarpit, blocker.action);
+        assert_eq!(Duration::from_secs(60), blocker.tarpit_duration);
+        assert_eq!(10, blocker.tarpit_max);
+
+        let result = Blocker::new(
+            PluginCategory::IpRestriction,
+            &toml::from_str::<PluginConf>(
+                r###"
+block_action = "reject"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin ip_restriction invalid, message: block action(reject) should be deny, drop or tarpit",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocker_drop() {
+        let blocker = Blocker::new(
+            PluginCategory::IpRestriction,
+            &toml::from_str::<PluginConf>(
+                r###"
+block_action = "drop"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let input_header =
+            "GET /vicanso/pingap HTTP/1.1\r\nHost: github.com\r\n\r\n";
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        let resp = blocker
+            .block(&mut session, &mut ctx, HttpResponse::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(999, resp.status.as_u16());
+        assert_eq!(Some(444), ctx.status.map(|status| status.as_u16()));
+    }
+}
@@ -0,0 +1,466 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::block_action::Blocker;
+use super::{
+    get_int_conf, get_step_conf, get_str_conf, get_str_slice_conf, Error,
+    Plugin, Result,
+};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::http_extra::HttpResponse;
+use crate::state::State;
+use crate::util;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{header, HeaderName, StatusCode};
+use humantime::parse_duration;
+use pingora::http::RequestHeader;
+use pingora::proxy::Session;
+use pingora_limits::rate::Rate;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::debug;
+
+// the variable name of bot score in context
+const BOT_SCORE_VARIABLE: &str = "bot_score";
+
+const DEFAULT_BOT_USER_AGENTS: [&str; 10] = [
+    "curl",
+    "wget",
+    "python-requests",
+    "python-urllib",
+    "go-http-client",
+    "scrapy",
+    "libwww-perl",
+    "headlesschrome",
+    "phantomjs",
+    "java/",
+];
+
+#[derive(PartialEq, Debug)]
+enum BotAction {
+    // only tag the request by header and variable
+    Tag,
+    // rate limit the suspected request by client ip
+    Limit,
+    Block,
+}
+
+pub struct BotDetection {
+    plugin_step: PluginStep,
+    action: BotAction,
+    threshold: i64,
+    user_agents: Vec<String>,
+    blocked_fingerprints: Vec<String>,
+    allowed_fingerprints: Vec<String>,
+    tag_header: Option<HeaderName>,
+    max: isize,
+    rate: Rate,
+    forbidden_resp: HttpResponse,
+    blocker: Blocker,
+}
+
+impl TryFrom<&PluginConf> for BotDetection {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let step = get_step_conf(value);
+        let new_error = |message: String| Error::Invalid {
+            category: PluginCategory::BotDetection.to_string(),
+            message,
+        };
+
+        let action = match get_str_conf(value, "action").as_str() {
+            "limit" => BotAction::Limit,
+            "block" => BotAction::Block,
+            _ => BotAction::Tag,
+        };
+        let mut threshold = get_int_conf(value, "threshold");
+        if threshold <= 0 {
+            threshold = 60;
+        }
+        let mut user_agents: Vec<String> =
+            get_str_slice_conf(value, "user_agents")
+                .iter()
+                .map(|item| item.to_lowercase())
+                .collect();
+        if user_agents.is_empty() {
+            user_agents = DEFAULT_BOT_USER_AGENTS
+                .iter()
+                .map(|item| item.to_string())
+                .collect();
+        }
+        let mut tag_header = get_str_conf(value, "tag_header");
+        if tag_header.is_empty() {
+            tag_header = "X-Bot-Score".to_string();
+        }
+        let tag_header = if tag_header == "-" {
+            None
+        } else {
+            Some(
+                HeaderName::from_str(&tag_header)
+                    .map_err(|e| new_error(e.to_string()))?,
+            )
+        };
+        let interval = get_str_conf(value, "interval");
+        let interval = if !interval.is_empty() {
+            parse_duration(&interval).map_err(|e| new_error(e.to_string()))?
+        } else {
+            Duration::from_secs(60)
+        };
+        let mut max = get_int_conf(value, "max");
+        if max <= 0 {
+            max = 10;
+        }
+        let mut message = get_str_conf(value, "message");
+        if message.is_empty() {
+            message = "Request is forbidden".to_string();
+        }
+
+        let params = Self {
+            plugin_step: step,
+            action,
+            threshold,
+            user_agents,
+            blocked_fingerprints: get_str_slice_conf(
+                value,
+                "blocked_fingerprints",
+            ),
+            allowed_fingerprints: get_str_slice_conf(
+                value,
+                "allowed_fingerprints",
+            ),
+            tag_header,
+            max: max as isize,
+            rate: Rate::new(interval),
+            forbidden_resp: HttpResponse {
+                status: StatusCode::FORBIDDEN,
+                body: Bytes::from(message),
+                ..Default::default()
+            },
+            blocker: Blocker::new(PluginCategory::BotDetection, value)?,
+        };
+        if ![PluginStep::Request, PluginStep::ProxyUpstream]
+            .contains(&params.plugin_step)
+        {
+            return Err(new_error(
+                "Bot detection plugin should be executed at request or proxy upstream step".to_string(),
+            ));
+        }
+        Ok(params)
+    }
+}
+
+impl BotDetection {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new bot detection plugin");
+        Self::try_from(params)
+    }
+    /// Gets the score of request, the higher score means
+    /// the request is more likely from a bot.
+    fn get_score(
+        &self,
+        req_header: &RequestHeader,
+        fingerprint: Option<&str>,
+    ) -> i64 {
+        if let Some(fingerprint) = fingerprint {
+            if self
+                .allowed_fingerprints
+                .iter()
+                .any(|item| item == fingerprint)
+            {
+                return 0;
+            }
+            if self
+                .blocked_fingerprints
+                .iter()
+                .any(|item| item == fingerprint)
+            {
+                return 100;
+            }
+        }
+        let headers = &req_header.headers;
+        let user_agent = headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_lowercase();
+        if user_agent.is_empty() {
+            return 80;
+        }
+        let mut score = 0;
+        if self
+            .user_agents
+            .iter()
+            .any(|item| user_agent.contains(item.as_str()))
+        {
+            score += 60;
+        }
+        if !headers.contains_key(header::ACCEPT) {
+            score += 10;
+        }
+        if !headers.contains_key(header::ACCEPT_LANGUAGE) {
+            score += 10;
+        }
+        // the browser always sends host as the first header of http1
+        if user_agent.starts_with("mozilla/") {
+            let first = headers.keys().next();
+            if req_header.version < http::Version::HTTP_2
+                && first != Some(&header::HOST)
+            {
+                score += 20;
+            }
+            if !headers.contains_key(header::ACCEPT_ENCODING) {
+                score += 20;
+            }
+        }
+        score.min(100)
+    }
+}
+
+#[async_trait]
+impl Plugin for BotDetection {
+    #[inline]
+    fn step(&self) -> String {
+        self.plugin_step.to_string()
+    }
+    #[inline]
+    fn category(&self) -> PluginCategory {
+        PluginCategory::BotDetection
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        let score = self
+            .get_score(session.req_header(), ctx.tls_fingerprint.as_deref());
+        ctx.add_variable(BOT_SCORE_VARIABLE, &score.to_string());
+        if score < self.threshold {
+            return Ok(None);
+        }
+        match self.action {
+            BotAction::Block => {
+                return self
+                    .blocker
+                    .block(session, ctx, self.forbidden_resp.clone())
+                    .await;
+            },
+            BotAction::Limit => {
+                let client_ip = util::get_client_ip(session);
+                let value = self.rate.observe(&client_ip, 1);
+                ctx.client_ip = Some(client_ip);
+                if value > self.max {
+                    return Ok(Some(HttpResponse {
+                        status: StatusCode::TOO_MANY_REQUESTS,
+                        body: Bytes::from_static(b"Too many requests"),
+                        ..Default::default()
+                    }));
+                }
+            },
+            BotAction::Tag => {},
+        }
+        if let Some(name) = &self.tag_header {
+            let _ = session
+                .req_header_mut()
+                .insert_header(name.clone(), score.to_string());
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BotAction, BotDetection};
+    use crate::state::State;
+    use crate::{config::PluginConf, config::PluginStep, plugin::Plugin};
+    use http::StatusCode;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use tokio_test::io::Builder;
+
+    async fn new_session(headers: &[&str]) -> Session {
+        let headers = headers.join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        session
+    }
+
+    #[test]
+    fn test_bot_detection_params() {
+        let params = BotDetection::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+action = "block"
+user_agents = ["Curl"]
+blocked_fingerprints = ["b32309a26951912be7dba376398abc3b"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("request", params.plugin_step.to_string());
+        assert_eq!(BotAction::Block, params.action);
+        assert_eq!(60, params.threshold);
+        assert_eq!(vec!["curl".to_string()], params.user_agents);
+        assert_eq!("x-bot-score", params.tag_header.unwrap().to_string());
+
+        let result = BotDetection::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+step = "response"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin bot_detection invalid, message: Bot detection plugin should be executed at request or proxy upstream step",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bot_detection_score() {
+        let detection = BotDetection::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+blocked_fingerprints = ["b32309a26951912be7dba376398abc3b"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let session = new_session(&[
+            "Host: github.com",
+            "User-Agent: Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7)",
+            "Accept: text/html",
+            "Accept-Language: en-US",
+            "Accept-Encoding: gzip, br",
+        ])
+        .await;
+        assert_eq!(0, detection.get_score(session.req_header(), None));
+        assert_eq!(
+            100,
+            detection.get_score(
+                session.req_header(),
+                Some("b32309a26951912be7dba376398abc3b")
+            )
+        );
+
+        let session = new_session(&[
+            "User-Agent: Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7)",
+            "Host: github.com",
+            "Accept: text/html",
+            "Accept-Language: en-US",
+        ])
+        .await;
+        assert_eq!(40, detection.get_score(session.req_header(), None));
+
+        let session =
+            new_session(&["Host: github.com", "User-Agent: curl/8.4.0"]).await;
+        assert_eq!(80, detection.get_score(session.req_header(), None));
+
+        let session = new_session(&["Host: github.com"]).await;
+        assert_eq!(80, detection.get_score(session.req_header(), None));
+    }
+
+    #[tokio::test]
+    async fn test_bot_detection() {
+        let detection = BotDetection::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+action = "block"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("bot_detection", detection.category().to_string());
+        assert_eq!("request", detection.step().to_string());
+
+        let mut session =
+            new_session(&["Host: github.com", "User-Agent: curl/8.4.0"]).await;
+        let mut ctx = State::default();
+        let result = detection
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.unwrap().status);
+        assert_eq!("80", ctx.get_variable("bot_score").unwrap());
+
+        let detection = BotDetection::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+action = "tag"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut session =
+            new_session(&["Host: github.com", "User-Agent: curl/8.4.0"]).await;
+        let result = detection
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        assert_eq!(
+            "80",
+            session.req_header().headers.get("X-Bot-Score").unwrap()
+        );
+
+        let detection = BotDetection::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+action = "limit"
+max = 1
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut session =
+            new_session(&["Host: github.com", "User-Agent: curl/8.4.0"]).await;
+        let result = detection
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        let result = detection
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, result.unwrap().status);
+    }
+}
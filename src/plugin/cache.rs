@@ -15,30 +15,39 @@
 use super::{
     get_step_conf, get_str_conf, get_str_slice_conf, Error, Plugin, Result,
 };
-use crate::cache::{new_file_cache, new_tiny_ufo_cache, HttpCache};
+use crate::cache::{
+    self, new_file_cache, new_tiny_ufo_cache, CacheEntry, HttpCache,
+};
 use crate::config::{
     get_current_config, PluginCategory, PluginConf, PluginStep,
 };
 use crate::http_extra::HttpResponse;
 use crate::state::State;
+use crate::util;
 use async_trait::async_trait;
 use bytes::{BufMut, BytesMut};
 use bytesize::ByteSize;
-use http::Method;
+use http::{HeaderName, Method};
 use humantime::parse_duration;
 use once_cell::sync::{Lazy, OnceCell};
 use pingora::cache::eviction::simple_lru::Manager;
 use pingora::cache::eviction::EvictionManager;
+use pingora::cache::key::CacheHashKey;
 use pingora::cache::lock::CacheLock;
 use pingora::cache::predictor::{CacheablePredictor, Predictor};
-use pingora::cache::Storage;
+use pingora::cache::{CacheKey, Storage};
 use pingora::proxy::Session;
+use pingora::server::ShutdownWatch;
+use pingora::services::background::BackgroundService;
 use std::str::FromStr;
-use std::time::Duration;
-use tracing::debug;
+use std::time::{Duration, SystemTime};
+use tokio::time::interval;
+use tracing::{debug, error, info};
 
 static CACHE_BACKEND: OnceCell<HttpCache> = OnceCell::new();
 static PREDICTOR: Lazy<Predictor<32>> = Lazy::new(|| Predictor::new(128, None));
+// the times of checking the update of snapshot after startup
+const SNAPSHOT_CHECK_TIMES: u32 = 60;
 // meomory limit size
 const MAX_MEMORY_SIZE: usize = 100 * 1024 * 1024;
 static EVICTION_MANAGER: Lazy<Manager> = Lazy::new(|| {
@@ -68,6 +77,83 @@ pub struct Cache {
     max_ttl: Option<Duration>,
     namespace: Option<String>,
     headers: Option<Vec<String>>,
+    // the client ips whose `Cache-Control` directive is honoured
+    bypass_ips: util::IpRules,
+    // the secret header(name and value) to bypass the cache
+    bypass_header: Option<(HeaderName, String)>,
+    // the query name to bypass(or refresh with value `refresh`) the cache
+    bypass_query: Option<String>,
+    // the caching mode of range request
+    range: RangeMode,
+}
+
+/// The caching mode of range request.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum RangeMode {
+    // the partial response is not cached,
+    // the range of cache hit is served from the full object
+    #[default]
+    Default,
+    // fetch the full object from upstream without range on cache miss
+    Fill,
+    // cache the partial response, the range is part of cache key
+    Partial,
+}
+
+/// Gets the normalized value of single range request, e.g. `bytes=0-1023`,
+/// returns `None` if the range is invalid or multiple ranges.
+fn get_single_range(value: &str) -> Option<String> {
+    let value = value.trim().to_lowercase();
+    let spec = value.strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let parse = |value: &str| -> Option<Option<u64>> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Some(None);
+        }
+        value.parse::<u64>().ok().map(Some)
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    match (start, end) {
+        (None, None) => None,
+        (Some(start), Some(end)) if start > end => None,
+        _ => Some(format!(
+            "bytes={}-{}",
+            start.map(|v| v.to_string()).unwrap_or_default(),
+            end.map(|v| v.to_string()).unwrap_or_default()
+        )),
+    }
+}
+
+/// The cache directive of a single request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CacheDirective {
+    // skip the cache, neither lookup nor store
+    Bypass,
+    // treat the cached object as expired and fetch it from upstream again
+    Refresh,
+}
+
+/// Gets the cache directive from `Cache-Control` or `Pragma` of request,
+/// `no-store` means bypass, `no-cache` or `max-age=0` means refresh.
+fn get_cache_control_directive(session: &Session) -> Option<CacheDirective> {
+    let req_header = session.req_header();
+    let value = util::get_req_header_value(req_header, "Cache-Control")
+        .or_else(|| util::get_req_header_value(req_header, "Pragma"))?;
+    let mut directive = None;
+    for item in value.split(',') {
+        let item = item.trim().to_lowercase();
+        if item == "no-store" {
+            return Some(CacheDirective::Bypass);
+        }
+        if item == "no-cache" || item == "max-age=0" {
+            directive = Some(CacheDirective::Refresh);
+        }
+    }
+    directive
 }
 
 impl TryFrom<&PluginConf> for Cache {
@@ -133,6 +219,38 @@ impl TryFrom<&PluginConf> for Cache {
         } else {
             Some(headers)
         };
+        let bypass_header = get_str_conf(value, "bypass_header");
+        let bypass_header =
+            if let Some((name, value)) = bypass_header.split_once(':') {
+                let name = HeaderName::from_str(name.trim()).map_err(|e| {
+                    Error::Invalid {
+                        category: PluginCategory::Cache.to_string(),
+                        message: e.to_string(),
+                    }
+                })?;
+                Some((name, value.trim().to_string()))
+            } else {
+                None
+            };
+        let bypass_query = get_str_conf(value, "bypass_query");
+        let bypass_query = if bypass_query.is_empty() {
+            None
+        } else {
+            Some(bypass_query)
+        };
+        let range = match get_str_conf(value, "range").to_lowercase().as_str() {
+            "" => RangeMode::Default,
+            "fill" => RangeMode::Fill,
+            "partial" => RangeMode::Partial,
+            range => {
+                return Err(Error::Invalid {
+                    category: PluginCategory::Cache.to_string(),
+                    message: format!(
+                        "range mode should be fill or partial, {range}"
+                    ),
+                });
+            },
+        };
         let params = Self {
             storage: cache,
             plugin_step: step,
@@ -143,6 +261,13 @@ impl TryFrom<&PluginConf> for Cache {
             max_file_size: max_file_size.as_u64() as usize,
             namespace,
             headers,
+            bypass_ips: util::IpRules::new(&get_str_slice_conf(
+                value,
+                "bypass_ips",
+            )),
+            bypass_header,
+            bypass_query,
+            range,
         };
         if params.plugin_step != PluginStep::Request {
             return Err(Error::Invalid {
@@ -155,11 +280,163 @@ impl TryFrom<&PluginConf> for Cache {
     }
 }
 
+/// Purges the cache objects of the tag, which is set by
+/// the `Cache-Tag` or `Surrogate-Key` header of upstream response.
+pub async fn purge_cache_tag(tag: &str) -> usize {
+    if let Some(cache) = CACHE_BACKEND.get() {
+        cache.purge_tag(tag).await
+    } else {
+        0
+    }
+}
+
+/// The service restores the snapshot of cache on startup
+/// and saves it on shutdown, so the cache is warm after graceful upgrade.
+pub struct CacheSnapshotService {
+    file: String,
+}
+
+/// Creates a service of cache snapshot, the file is the path of snapshot.
+pub fn new_cache_snapshot_service(file: &str) -> CacheSnapshotService {
+    CacheSnapshotService {
+        file: util::resolve_path(file),
+    }
+}
+
+impl CacheSnapshotService {
+    async fn restore(&self, cache: &HttpCache) {
+        match cache.restore_snapshot(&self.file).await {
+            Ok(count) => {
+                info!(file = self.file, count, "restore cache snapshot")
+            },
+            Err(e) => error!(
+                error = e.to_string(),
+                file = self.file,
+                "restore cache snapshot fail"
+            ),
+        }
+    }
+    fn get_modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.file)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+}
+
+#[async_trait]
+impl BackgroundService for CacheSnapshotService {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        // the cache backend is initialized by cache plugin
+        let Some(cache) = CACHE_BACKEND.get() else {
+            return;
+        };
+        self.restore(cache).await;
+        // the old process of graceful upgrade saves the snapshot
+        // after the new process is started, so check the update for a while
+        let mut modified = self.get_modified();
+        let mut checks = 0;
+        let mut period = interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    break;
+                }
+                _ = period.tick(), if checks < SNAPSHOT_CHECK_TIMES => {
+                    checks += 1;
+                    let current = self.get_modified();
+                    if current > modified {
+                        modified = current;
+                        self.restore(cache).await;
+                    }
+                }
+            }
+        }
+        match cache.save_snapshot(&self.file).await {
+            Ok(count) => {
+                info!(file = self.file, count, "save cache snapshot")
+            },
+            Err(e) => error!(
+                error = e.to_string(),
+                file = self.file,
+                "save cache snapshot fail"
+            ),
+        }
+    }
+}
+
+/// Gets the hash of cache key, it's the same as the cache key of proxy,
+/// the prefix is the namespace and headers value set by cache plugin.
+fn get_cache_hash(prefix: &str, url: &str) -> String {
+    CacheKey::new(prefix.to_string(), url.to_string(), "".to_string())
+        .combined()
+}
+
+/// Gets the inspection info of the cache object of url.
+pub async fn get_cache_entry(prefix: &str, url: &str) -> Option<CacheEntry> {
+    let cache = CACHE_BACKEND.get()?;
+    cache
+        .get_entry(&get_cache_hash(prefix, url), &format!("{prefix}{url}"))
+        .await
+}
+
+/// Removes the cache object of url, returns `true` if it's removed.
+pub async fn remove_cache_entry(prefix: &str, url: &str) -> bool {
+    if let Some(cache) = CACHE_BACKEND.get() {
+        cache.remove(&get_cache_hash(prefix, url)).await
+    } else {
+        false
+    }
+}
+
 impl Cache {
     pub fn new(params: &PluginConf) -> Result<Self> {
         debug!(params = params.to_string(), "new http cache plugin");
         Self::try_from(params)
     }
+    /// Gets the cache directive of request, the query is checked first,
+    /// then the `Cache-Control` of request is only honoured if the client ip
+    /// is allow-listed or the secret header matches.
+    fn get_cache_directive(
+        &self,
+        session: &Session,
+        ctx: &mut State,
+    ) -> Option<CacheDirective> {
+        // the prefetch request of hot entry is always refreshed
+        if cache::is_prefetch_request(&session.req_header().headers) {
+            return Some(CacheDirective::Refresh);
+        }
+        if let Some(name) = &self.bypass_query {
+            if let Some(value) =
+                util::get_query_value(session.req_header(), name)
+            {
+                if value == "refresh" {
+                    return Some(CacheDirective::Refresh);
+                }
+                return Some(CacheDirective::Bypass);
+            }
+        }
+        let header_matched =
+            self.bypass_header.as_ref().is_some_and(|(name, value)| {
+                session.get_header_bytes(name) == value.as_bytes()
+            });
+        let ip_matched = if self.bypass_ips.is_empty() {
+            false
+        } else {
+            let ip = ctx
+                .client_ip
+                .get_or_insert_with(|| util::get_client_ip(session));
+            self.bypass_ips.is_match(ip).unwrap_or_default()
+        };
+        if !header_matched && !ip_matched {
+            return None;
+        }
+        let directive = get_cache_control_directive(session);
+        // the secret header means bypass if no directive is set
+        if directive.is_none() && header_matched {
+            return Some(CacheDirective::Bypass);
+        }
+        directive
+    }
 }
 
 #[async_trait]
@@ -185,6 +462,39 @@ impl Plugin for Cache {
         if ![Method::GET, Method::HEAD].contains(&session.req_header().method) {
             return Ok(None);
         }
+        match self.get_cache_directive(session, ctx) {
+            Some(CacheDirective::Bypass) => {
+                debug!("Cache is bypassed by request directive");
+                ctx.cache_bypass = true;
+                return Ok(None);
+            },
+            Some(CacheDirective::Refresh) => {
+                debug!("Cache is refreshed by request directive");
+                ctx.cache_refresh = true;
+            },
+            None => {},
+        }
+        let mut partial_range = None;
+        if let Some(range) =
+            util::get_req_header_value(session.req_header(), "Range")
+        {
+            match self.range {
+                RangeMode::Fill => ctx.cache_range_fill = true,
+                RangeMode::Partial => {
+                    // multiple ranges are not cached
+                    let Some(range) = get_single_range(range) else {
+                        return Ok(None);
+                    };
+                    ctx.cache_partial = true;
+                    partial_range = Some(range);
+                },
+                RangeMode::Default => {},
+            }
+        }
+        // the token of prefetch request should not be sent to upstream
+        let _ = session
+            .req_header_mut()
+            .remove_header(&*cache::HTTP_HEADER_NAME_X_PINGAP_PREFETCH);
         ctx.cache_max_ttl = self.max_ttl;
         let eviction = if self.eviction {
             None
@@ -216,13 +526,28 @@ impl Plugin for Cache {
             keys.put(&b":"[..]);
         }
         if let Some(headers) = &self.headers {
+            let mut key_headers = vec![];
             for key in headers.iter() {
                 let buf = session.get_header_bytes(key);
                 if !buf.is_empty() {
                     keys.put(buf);
                     keys.put(&b":"[..]);
+                    key_headers.push(format!(
+                        "{key}:{}",
+                        std::str::from_utf8(buf).unwrap_or_default()
+                    ));
                 }
             }
+            if !key_headers.is_empty() {
+                ctx.cache_key_headers = Some(key_headers);
+            }
+        }
+        if let Some(range) = partial_range {
+            keys.put(range.as_bytes());
+            keys.put(&b":"[..]);
+            ctx.cache_key_headers
+                .get_or_insert_with(Vec::new)
+                .push(format!("Range:{range}"));
         }
         if !keys.is_empty() {
             let prefix =
@@ -237,11 +562,13 @@ impl Plugin for Cache {
 
 #[cfg(test)]
 mod tests {
-    use super::Cache;
+    use super::{get_single_range, Cache, RangeMode};
     use crate::config::{PluginConf, PluginStep};
     use crate::plugin::Plugin;
     use crate::state::State;
     use pingora::proxy::Session;
+    use pingora::server::ShutdownWatch;
+    use pingora::services::background::BackgroundService;
     use pretty_assertions::assert_eq;
     use tokio_test::io::Builder;
 
@@ -256,6 +583,7 @@ lock = "2s"
 max_file_size = "100kb"
 predictor = true
 max_ttl = "1m"
+range = "partial"
 "###,
             )
             .unwrap(),
@@ -270,6 +598,40 @@ max_ttl = "1m"
         assert_eq!(100 * 1000, params.max_file_size);
         assert_eq!(60, params.max_ttl.unwrap().as_secs());
         assert_eq!(true, params.predictor);
+        assert_eq!(RangeMode::Partial, params.range);
+
+        let result = Cache::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+range = "slice"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin cache invalid, message: range mode should be fill or partial, slice",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_get_single_range() {
+        assert_eq!(
+            "bytes=0-1023",
+            get_single_range("bytes=0-1023").unwrap_or_default()
+        );
+        assert_eq!(
+            "bytes=100-",
+            get_single_range(" Bytes=0100- ").unwrap_or_default()
+        );
+        assert_eq!(
+            "bytes=-500",
+            get_single_range("bytes=-500").unwrap_or_default()
+        );
+        assert_eq!(true, get_single_range("bytes=0-1,5-10").is_none());
+        assert_eq!(true, get_single_range("bytes=10-1").is_none());
+        assert_eq!(true, get_single_range("bytes=-").is_none());
+        assert_eq!(true, get_single_range("items=0-1").is_none());
     }
     #[tokio::test]
     async fn test_cache() {
@@ -307,4 +669,144 @@ max_ttl = "1m"
         assert_eq!(true, session.cache.enabled());
         assert_eq!(100 * 1000, cache.max_file_size);
     }
+
+    #[tokio::test]
+    async fn test_cache_directive() {
+        let cache = Cache::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+bypass_ips = ["10.0.0.0/8"]
+bypass_header = "X-Cache-Bypass:pingap"
+bypass_query = "nocache"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let new_session = |input: &str| {
+            let input_header = format!("{input}\r\n\r\n");
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            Session::new_h1(Box::new(mock_io))
+        };
+
+        // bypass by query
+        let mut session = new_session("GET /vicanso/pingap?nocache=1 HTTP/1.1");
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        cache
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.cache_bypass);
+        assert_eq!(false, session.cache.enabled());
+
+        // refresh by query
+        let mut session =
+            new_session("GET /vicanso/pingap?nocache=refresh HTTP/1.1");
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        cache
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.cache_refresh);
+        assert_eq!(true, session.cache.enabled());
+
+        // the directive of not allowed ip is ignored
+        let mut session = new_session(
+            "GET /vicanso/pingap HTTP/1.1\r\nX-Real-Ip: 192.168.1.1\r\nCache-Control: no-cache",
+        );
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        cache
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(false, ctx.cache_refresh);
+        assert_eq!(false, ctx.cache_bypass);
+        assert_eq!(true, session.cache.enabled());
+
+        // refresh by the directive of allowed ip
+        let mut session = new_session(
+            "GET /vicanso/pingap HTTP/1.1\r\nX-Real-Ip: 10.1.1.1\r\nCache-Control: no-cache",
+        );
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        cache
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.cache_refresh);
+
+        // bypass by secret header
+        let mut session = new_session(
+            "GET /vicanso/pingap HTTP/1.1\r\nX-Cache-Bypass: pingap",
+        );
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        cache
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.cache_bypass);
+        assert_eq!(false, session.cache.enabled());
+    }
+
+    #[tokio::test]
+    async fn test_cache_range() {
+        let new_cache = |range: &str| {
+            Cache::try_from(
+                &toml::from_str::<PluginConf>(&format!(r#"range = "{range}""#))
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        let new_session = |range: &str| {
+            let input_header = format!(
+                "GET /vicanso/pingap HTTP/1.1\r\nRange: {range}\r\n\r\n"
+            );
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            Session::new_h1(Box::new(mock_io))
+        };
+
+        let cache = new_cache("partial");
+        let mut session = new_session("bytes=0-1023");
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        cache
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.cache_partial);
+        assert_eq!("bytes=0-1023:", ctx.cache_prefix.unwrap_or_default());
+        assert_eq!(
+            r#"Some(["Range:bytes=0-1023"])"#,
+            format!("{:?}", ctx.cache_key_headers)
+        );
+        assert_eq!(true, session.cache.enabled());
+
+        // multiple ranges are not cached
+        let mut session = new_session("bytes=0-1,5-10");
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        cache
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(false, session.cache.enabled());
+
+        let cache = new_cache("fill");
+        let mut session = new_session("bytes=0-1023");
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        cache
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.cache_range_fill);
+        assert_eq!(false, ctx.cache_partial);
+        assert_eq!(true, ctx.cache_prefix.is_none());
+        assert_eq!(true, session.cache.enabled());
+    }
 }
@@ -0,0 +1,568 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    get_int_conf, get_step_conf, get_str_conf, get_str_slice_conf, Error,
+    Plugin, Result,
+};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::http_extra::{HttpHeader, HttpResponse};
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use bytesize::ByteSize;
+use http::{header, HeaderName, Method, StatusCode};
+use humantime::parse_duration;
+use once_cell::sync::Lazy;
+use pingora::http::ResponseHeader;
+use pingora::proxy::Session;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::debug;
+
+// the coalesced response, `None` means the response can't be shared
+// and the waiting requests should be sent to upstream by themselves
+type CoalescedResult = Option<Option<Arc<CoalescedResponse>>>;
+
+#[derive(Debug, Clone)]
+struct CoalescedResponse {
+    status: StatusCode,
+    headers: Vec<HttpHeader>,
+    body: Bytes,
+}
+
+impl From<&CoalescedResponse> for HttpResponse {
+    fn from(value: &CoalescedResponse) -> Self {
+        HttpResponse {
+            status: value.status,
+            headers: Some(value.headers.clone()),
+            body: value.body.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+// the in-flight requests, key is the coalescing key of request
+static IN_FLIGHT_CALLS: Lazy<
+    Mutex<AHashMap<String, (u64, watch::Receiver<CoalescedResult>)>>,
+> = Lazy::new(|| Mutex::new(AHashMap::new()));
+static CALL_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The leader request of coalescing, it's sent to upstream and
+/// its response is fanned out to the waiting requests.
+/// The waiting requests are released if it's dropped before finished.
+pub struct CoalescingLeader {
+    id: u64,
+    key: String,
+    max_body_size: usize,
+    sender: watch::Sender<CoalescedResult>,
+    response: Option<(StatusCode, Vec<HttpHeader>)>,
+    body: BytesMut,
+}
+
+impl CoalescingLeader {
+    fn finish(&self, result: Option<Arc<CoalescedResponse>>) {
+        self.sender.send_replace(Some(result));
+        if let Ok(mut calls) = IN_FLIGHT_CALLS.lock() {
+            if calls.get(&self.key).is_some_and(|(id, _)| *id == self.id) {
+                calls.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl Drop for CoalescingLeader {
+    fn drop(&mut self) {
+        if self.sender.borrow().is_none() {
+            self.finish(None);
+        }
+    }
+}
+
+enum Coalescing {
+    Leader(CoalescingLeader),
+    Follower(watch::Receiver<CoalescedResult>),
+}
+
+fn join_call(key: &str, max_body_size: usize) -> Coalescing {
+    let Ok(mut calls) = IN_FLIGHT_CALLS.lock() else {
+        let (sender, _) = watch::channel(None);
+        return Coalescing::Leader(CoalescingLeader {
+            id: 0,
+            key: key.to_string(),
+            max_body_size,
+            sender,
+            response: None,
+            body: BytesMut::new(),
+        });
+    };
+    if let Some((_, receiver)) = calls.get(key) {
+        return Coalescing::Follower(receiver.clone());
+    }
+    let id = CALL_ID.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = watch::channel(None);
+    calls.insert(key.to_string(), (id, receiver));
+    Coalescing::Leader(CoalescingLeader {
+        id,
+        key: key.to_string(),
+        max_body_size,
+        sender,
+        response: None,
+        body: BytesMut::new(),
+    })
+}
+
+/// Wait for the response of leader, returns `None` if the leader fails
+/// or the response can't be shared.
+async fn wait_call(
+    mut receiver: watch::Receiver<CoalescedResult>,
+) -> Option<Arc<CoalescedResponse>> {
+    loop {
+        let result = receiver.borrow_and_update().clone();
+        if let Some(result) = result {
+            return result;
+        }
+        if receiver.changed().await.is_err() {
+            return None;
+        }
+    }
+}
+
+pub struct Coalesce {
+    plugin_step: PluginStep,
+    vary_headers: Vec<HeaderName>,
+    timeout: Duration,
+    max_body_size: usize,
+}
+
+impl TryFrom<&PluginConf> for Coalesce {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let new_error = |message: String| Error::Invalid {
+            category: PluginCategory::Coalesce.to_string(),
+            message,
+        };
+        let step = get_step_conf(value);
+        let mut vary_headers = vec![];
+        let mut headers = get_str_slice_conf(value, "vary_headers");
+        if headers.is_empty() {
+            headers = vec![
+                "accept-encoding".to_string(),
+                "authorization".to_string(),
+                "cookie".to_string(),
+            ];
+        }
+        for item in headers.iter() {
+            vary_headers.push(
+                HeaderName::from_str(item)
+                    .map_err(|e| new_error(e.to_string()))?,
+            );
+        }
+        let timeout = get_str_conf(value, "timeout");
+        let timeout = if !timeout.is_empty() {
+            parse_duration(&timeout).map_err(|e| new_error(e.to_string()))?
+        } else {
+            Duration::from_secs(10)
+        };
+        let max_body_size = get_str_conf(value, "max_body_size");
+        let max_body_size = if !max_body_size.is_empty() {
+            ByteSize::from_str(&max_body_size)
+                .map_err(|e| new_error(e.to_string()))?
+                .as_u64() as usize
+        } else {
+            let size = get_int_conf(value, "max_body_size");
+            if size > 0 {
+                size as usize
+            } else {
+                1024 * 1024
+            }
+        };
+        Ok(Self {
+            plugin_step: step,
+            vary_headers,
+            timeout,
+            max_body_size,
+        })
+    }
+}
+
+impl Coalesce {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new coalesce plugin");
+        let coalesce = Self::try_from(params)?;
+        if coalesce.plugin_step != PluginStep::Request {
+            return Err(Error::Invalid {
+                category: PluginCategory::Coalesce.to_string(),
+                message: "Coalesce plugin should be executed at request step"
+                    .to_string(),
+            });
+        }
+        Ok(coalesce)
+    }
+    /// Get the coalescing key of request, it's the method, host, uri
+    /// and the values of vary headers.
+    fn get_key(&self, session: &Session) -> String {
+        let req_header = session.req_header();
+        // the host of http/2 request is the authority of uri
+        let mut key = format!(
+            "{}:{}:{}",
+            req_header.method,
+            util::get_host(req_header).unwrap_or_default(),
+            req_header.uri
+        );
+        for name in self.vary_headers.iter() {
+            key.push(':');
+            if let Some(value) = req_header.headers.get(name) {
+                key.push_str(value.to_str().unwrap_or_default());
+            }
+        }
+        key
+    }
+}
+
+#[async_trait]
+impl Plugin for Coalesce {
+    #[inline]
+    fn step(&self) -> String {
+        self.plugin_step.to_string()
+    }
+    #[inline]
+    fn category(&self) -> PluginCategory {
+        PluginCategory::Coalesce
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        let req_header = session.req_header();
+        let method = &req_header.method;
+        if method != Method::GET && method != Method::HEAD {
+            return Ok(None);
+        }
+        // the response of range or conditional request(206 or 304)
+        // can't be shared with the other requests
+        if [
+            header::RANGE,
+            header::IF_RANGE,
+            header::IF_NONE_MATCH,
+            header::IF_MODIFIED_SINCE,
+        ]
+        .iter()
+        .any(|name| req_header.headers.contains_key(name))
+        {
+            return Ok(None);
+        }
+        let key = self.get_key(session);
+        match join_call(&key, self.max_body_size) {
+            Coalescing::Leader(leader) => {
+                ctx.coalescing = Some(Box::new(leader));
+            },
+            Coalescing::Follower(receiver) => {
+                if let Ok(Some(resp)) =
+                    tokio::time::timeout(self.timeout, wait_call(receiver))
+                        .await
+                {
+                    ctx.add_journal(|| "coalesced".to_string());
+                    return Ok(Some(resp.as_ref().into()));
+                }
+                // the request is sent to upstream if the leader fails
+                // or timeout
+                ctx.add_journal(|| "coalesce:fallback".to_string());
+            },
+        }
+        Ok(None)
+    }
+    async fn handle_response(
+        &self,
+        step: PluginStep,
+        _session: &mut Session,
+        ctx: &mut State,
+        upstream_response: &mut ResponseHeader,
+    ) -> pingora::Result<Option<Bytes>> {
+        if step != PluginStep::Response {
+            return Ok(None);
+        }
+        let Some(leader) = ctx.coalescing.as_mut() else {
+            return Ok(None);
+        };
+        // only the successful response without cookie can be shared,
+        // the waiting requests are sent to upstream by themselves
+        if upstream_response.status != StatusCode::OK
+            || upstream_response.headers.contains_key(header::SET_COOKIE)
+        {
+            ctx.coalescing = None;
+            return Ok(None);
+        }
+        let headers = upstream_response
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                ![
+                    header::CONTENT_LENGTH,
+                    header::TRANSFER_ENCODING,
+                    header::CONNECTION,
+                ]
+                .contains(name)
+            })
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        leader.response = Some((upstream_response.status, headers));
+        Ok(None)
+    }
+    fn handle_response_body(
+        &self,
+        step: PluginStep,
+        _session: &mut Session,
+        ctx: &mut State,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+    ) -> pingora::Result<()> {
+        if step != PluginStep::Response {
+            return Ok(());
+        }
+        let Some(leader) = ctx.coalescing.as_mut() else {
+            return Ok(());
+        };
+        if let Some(data) = body {
+            // the response is too large to be shared
+            if leader.body.len() + data.len() > leader.max_body_size {
+                ctx.coalescing = None;
+                return Ok(());
+            }
+            leader.body.extend_from_slice(data);
+        }
+        if end_of_stream {
+            if let Some((status, headers)) = leader.response.take() {
+                let resp = CoalescedResponse {
+                    status,
+                    headers,
+                    body: leader.body.split().freeze(),
+                };
+                leader.finish(Some(Arc::new(resp)));
+            }
+            ctx.coalescing = None;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        join_call, wait_call, Coalesce, CoalescedResponse, Coalescing,
+    };
+    use crate::config::{PluginConf, PluginStep};
+    use crate::plugin::Plugin;
+    use crate::state::State;
+    use bytes::Bytes;
+    use http::StatusCode;
+    use pingora::http::ResponseHeader;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_coalesce_params() {
+        let coalesce = Coalesce::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+vary_headers = ["accept-encoding"]
+timeout = "3s"
+max_body_size = "64kb"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("request", coalesce.step());
+        assert_eq!("coalesce", coalesce.category().to_string());
+        assert_eq!(
+            r#"["accept-encoding"]"#,
+            format!("{:?}", coalesce.vary_headers)
+        );
+        assert_eq!(Duration::from_secs(3), coalesce.timeout);
+        assert_eq!(64 * 1000, coalesce.max_body_size);
+
+        let result = Coalesce::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+step = "response"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin coalesce invalid, message: Coalesce plugin should be executed at request step",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_join_call() {
+        let Coalescing::Leader(leader) = join_call("test-join-call", 1024)
+        else {
+            panic!("first call should be leader");
+        };
+        let Coalescing::Follower(receiver) = join_call("test-join-call", 1024)
+        else {
+            panic!("second call should be follower");
+        };
+        leader.finish(Some(Arc::new(CoalescedResponse {
+            status: StatusCode::OK,
+            headers: vec![],
+            body: Bytes::from_static(b"pingap"),
+        })));
+        let resp = wait_call(receiver).await.unwrap();
+        assert_eq!(b"pingap", resp.body.as_ref());
+
+        // the waiting requests are released if leader is dropped
+        let Coalescing::Leader(leader) = join_call("test-join-call", 1024)
+        else {
+            panic!("call should be leader after finished");
+        };
+        let Coalescing::Follower(receiver) = join_call("test-join-call", 1024)
+        else {
+            panic!("second call should be follower");
+        };
+        drop(leader);
+        assert_eq!(true, wait_call(receiver).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let coalesce = Coalesce::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+timeout = "1s"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let new_session = || async {
+            let headers = ["Host: pingap.io"].join("\r\n");
+            let input_header =
+                format!("GET /search?q=pingap HTTP/1.1\r\n{headers}\r\n\r\n");
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            let mut session = Session::new_h1(Box::new(mock_io));
+            session.read_request().await.unwrap();
+            session
+        };
+
+        let mut session = new_session().await;
+        let mut leader_ctx = State::default();
+        let result = coalesce
+            .handle_request(PluginStep::Request, &mut session, &mut leader_ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        assert_eq!(true, leader_ctx.coalescing.is_some());
+
+        let mut upstream_response = ResponseHeader::build(200, None).unwrap();
+        upstream_response
+            .insert_header("Content-Type", "application/json")
+            .unwrap();
+        coalesce
+            .handle_response(
+                PluginStep::Response,
+                &mut session,
+                &mut leader_ctx,
+                &mut upstream_response,
+            )
+            .await
+            .unwrap();
+
+        let follower = async {
+            let mut session = new_session().await;
+            coalesce
+                .handle_request(
+                    PluginStep::Request,
+                    &mut session,
+                    &mut State::default(),
+                )
+                .await
+                .unwrap()
+        };
+        let leader = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let mut body = Some(Bytes::from_static(b"[]"));
+            coalesce
+                .handle_response_body(
+                    PluginStep::Response,
+                    &mut session,
+                    &mut leader_ctx,
+                    &mut body,
+                    true,
+                )
+                .unwrap();
+        };
+        let (resp, _) = tokio::join!(follower, leader);
+        let resp = resp.unwrap();
+        assert_eq!(200, resp.status.as_u16());
+        assert_eq!(b"[]", resp.body.as_ref());
+        assert_eq!(
+            r#"Some([("content-type", "application/json")])"#,
+            format!("{:?}", resp.headers)
+        );
+        assert_eq!(true, leader_ctx.coalescing.is_none());
+
+        // the range request isn't coalesced
+        let headers = ["Host: pingap.io", "Range: bytes=0-1"].join("\r\n");
+        let input_header =
+            format!("GET /search?q=pingap HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        coalesce
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.coalescing.is_none());
+
+        // the error response isn't shared
+        let mut session = new_session().await;
+        let mut ctx = State::default();
+        coalesce
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.coalescing.is_some());
+        let mut upstream_response = ResponseHeader::build(502, None).unwrap();
+        coalesce
+            .handle_response(
+                PluginStep::Response,
+                &mut session,
+                &mut ctx,
+                &mut upstream_response,
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, ctx.coalescing.is_none());
+    }
+}
@@ -0,0 +1,345 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Error, Plugin, Result};
+use crate::config::{PluginCategory, PluginStep};
+use crate::http_extra::HttpResponse;
+use crate::state::State;
+use crate::util;
+use async_trait::async_trait;
+use bytes::Bytes;
+use pingora::http::{RequestHeader, ResponseHeader};
+use pingora::proxy::Session;
+
+#[derive(Debug, PartialEq)]
+enum Field {
+    Path,
+    Method,
+    Host,
+    Header(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum Operator {
+    // the value exists, it's only for header
+    Exists,
+    Equal,
+    NotEqual,
+    Prefix,
+}
+
+#[derive(Debug)]
+struct Clause {
+    field: Field,
+    operator: Operator,
+    // the alternative values split by `|`
+    values: Vec<String>,
+}
+
+/// Condition of plugin, the clauses are joined by `&&`, e.g.
+/// `path ^= /api && method == GET|POST && header[X-Debug] == 1`.
+#[derive(Debug)]
+pub struct PluginCondition {
+    clauses: Vec<Clause>,
+}
+
+impl PluginCondition {
+    pub fn new(value: &str) -> Result<Self> {
+        let new_error = |message: String| Error::Invalid {
+            category: "condition".to_string(),
+            message,
+        };
+        let mut clauses = vec![];
+        for item in value.split("&&") {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            let (field, operator, value) =
+                if let Some((field, value)) = item.split_once("^=") {
+                    (field, Operator::Prefix, value)
+                } else if let Some((field, value)) = item.split_once("!=") {
+                    (field, Operator::NotEqual, value)
+                } else if let Some((field, value)) = item.split_once("==") {
+                    (field, Operator::Equal, value)
+                } else {
+                    (item, Operator::Exists, "")
+                };
+            let field = match field.trim() {
+                "path" => Field::Path,
+                "method" => Field::Method,
+                "host" => Field::Host,
+                value => {
+                    let Some(name) = value
+                        .strip_prefix("header[")
+                        .and_then(|value| value.strip_suffix(']'))
+                    else {
+                        return Err(new_error(format!(
+                            "{value} is not supported"
+                        )));
+                    };
+                    Field::Header(name.trim().to_lowercase())
+                },
+            };
+            if operator == Operator::Exists
+                && !matches!(field, Field::Header(_))
+            {
+                return Err(new_error(format!("{item} is invalid")));
+            }
+            let values = value
+                .split('|')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect::<Vec<_>>();
+            if operator != Operator::Exists && values.is_empty() {
+                return Err(new_error(format!("{item} value is empty")));
+            }
+            clauses.push(Clause {
+                field,
+                operator,
+                values,
+            });
+        }
+        Ok(Self { clauses })
+    }
+    /// Returns `true` if the request matches all clauses.
+    pub fn matched(&self, req_header: &RequestHeader) -> bool {
+        self.clauses.iter().all(|clause| {
+            let value = match &clause.field {
+                Field::Path => Some(req_header.uri.path()),
+                Field::Method => Some(req_header.method.as_str()),
+                Field::Host => util::get_host(req_header),
+                Field::Header(name) => req_header
+                    .headers
+                    .get(name)
+                    .and_then(|value| value.to_str().ok()),
+            };
+            let Some(value) = value else {
+                return clause.operator == Operator::NotEqual;
+            };
+            let mut values = clause.values.iter();
+            match clause.operator {
+                Operator::Exists => true,
+                Operator::Equal => values.any(|item| item == value),
+                Operator::NotEqual => values.all(|item| item != value),
+                Operator::Prefix => {
+                    values.any(|item| value.starts_with(item.as_str()))
+                },
+            }
+        })
+    }
+}
+
+/// The plugin with priority and condition,
+/// it's only executed when the request matches the condition.
+pub struct ConditionalPlugin {
+    pub(crate) priority: i64,
+    pub(crate) condition: Option<PluginCondition>,
+    pub(crate) plugin: Box<dyn Plugin>,
+}
+
+impl ConditionalPlugin {
+    #[inline]
+    fn matched(&self, session: &Session) -> bool {
+        if let Some(condition) = &self.condition {
+            return condition.matched(session.req_header());
+        }
+        true
+    }
+}
+
+#[async_trait]
+impl Plugin for ConditionalPlugin {
+    #[inline]
+    fn step(&self) -> String {
+        self.plugin.step()
+    }
+    #[inline]
+    fn category(&self) -> PluginCategory {
+        self.plugin.category()
+    }
+    #[inline]
+    fn priority(&self) -> i64 {
+        self.priority
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if !self.matched(session) {
+            return Ok(None);
+        }
+        self.plugin.handle_request(step, session, ctx).await
+    }
+    #[inline]
+    async fn handle_response(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+        upstream_response: &mut ResponseHeader,
+    ) -> pingora::Result<Option<Bytes>> {
+        if !self.matched(session) {
+            return Ok(None);
+        }
+        self.plugin
+            .handle_response(step, session, ctx, upstream_response)
+            .await
+    }
+    #[inline]
+    fn handle_response_body(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+    ) -> pingora::Result<()> {
+        if !self.matched(session) {
+            return Ok(());
+        }
+        self.plugin.handle_response_body(
+            step,
+            session,
+            ctx,
+            body,
+            end_of_stream,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConditionalPlugin, PluginCondition};
+    use crate::config::{PluginCategory, PluginStep};
+    use crate::plugin::Plugin;
+    use crate::state::State;
+    use bytes::Bytes;
+    use pingora::http::RequestHeader;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_plugin_condition() {
+        let condition = PluginCondition::new(
+            "path ^= /api && method == GET|POST && header[X-Debug]",
+        )
+        .unwrap();
+        assert_eq!(3, condition.clauses.len());
+
+        let mut req_header =
+            RequestHeader::build("GET", b"/api/users", None).unwrap();
+        assert_eq!(false, condition.matched(&req_header));
+        req_header.insert_header("X-Debug", "1").unwrap();
+        assert_eq!(true, condition.matched(&req_header));
+
+        let req_header =
+            RequestHeader::build("DELETE", b"/api/users", None).unwrap();
+        assert_eq!(false, condition.matched(&req_header));
+
+        let condition =
+            PluginCondition::new("host != pingap.io && header[X-Debug] != 1")
+                .unwrap();
+        let mut req_header =
+            RequestHeader::build("GET", b"/api/users", None).unwrap();
+        assert_eq!(true, condition.matched(&req_header));
+        req_header.insert_header("Host", "pingap.io").unwrap();
+        assert_eq!(false, condition.matched(&req_header));
+
+        assert_eq!(
+            "Plugin condition invalid, message: query is not supported",
+            PluginCondition::new("query == 1")
+                .err()
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "Plugin condition invalid, message: path is invalid",
+            PluginCondition::new("path").err().unwrap().to_string()
+        );
+        assert_eq!(
+            "Plugin condition invalid, message: method == value is empty",
+            PluginCondition::new("method ==").err().unwrap().to_string()
+        );
+    }
+
+    struct UppercaseBody {}
+
+    impl Plugin for UppercaseBody {
+        fn step(&self) -> String {
+            PluginStep::Response.to_string()
+        }
+        fn category(&self) -> PluginCategory {
+            PluginCategory::Mock
+        }
+        fn handle_response_body(
+            &self,
+            _step: PluginStep,
+            _session: &mut Session,
+            _ctx: &mut State,
+            body: &mut Option<Bytes>,
+            _end_of_stream: bool,
+        ) -> pingora::Result<()> {
+            if let Some(data) = body {
+                *body = Some(Bytes::from(data.to_ascii_uppercase()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conditional_plugin_response_body() {
+        let plugin = ConditionalPlugin {
+            priority: 0,
+            condition: Some(PluginCondition::new("method == GET").unwrap()),
+            plugin: Box::new(UppercaseBody {}),
+        };
+
+        let input_header = "GET /vicanso/pingap HTTP/1.1\r\n\r\n";
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let mut body = Some(Bytes::from_static(b"pingap"));
+        plugin
+            .handle_response_body(
+                PluginStep::Response,
+                &mut session,
+                &mut State::default(),
+                &mut body,
+                true,
+            )
+            .unwrap();
+        assert_eq!(b"PINGAP", body.unwrap().as_ref());
+
+        let input_header = "POST /vicanso/pingap HTTP/1.1\r\n\r\n";
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let mut body = Some(Bytes::from_static(b"pingap"));
+        plugin
+            .handle_response_body(
+                PluginStep::Response,
+                &mut session,
+                &mut State::default(),
+                &mut body,
+                true,
+            )
+            .unwrap();
+        assert_eq!(b"pingap", body.unwrap().as_ref());
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{get_str_conf, get_str_slice_conf, Error, Plugin, Result};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::state::State;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::header;
+use pingora::http::{RequestHeader, ResponseHeader};
+use pingora::proxy::Session;
+use tracing::debug;
+
+/// The names of cookie, the name ends with `*` matches by prefix.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct CookieNames {
+    names: Vec<String>,
+    prefixes: Vec<String>,
+}
+
+impl CookieNames {
+    fn new(values: &[String]) -> Self {
+        let mut cookie_names = Self::default();
+        for value in values.iter() {
+            if let Some(prefix) = value.strip_suffix('*') {
+                cookie_names.prefixes.push(prefix.to_string());
+            } else {
+                cookie_names.names.push(value.to_string());
+            }
+        }
+        cookie_names
+    }
+    #[inline]
+    fn is_match(&self, name: &str) -> bool {
+        self.names.iter().any(|item| item == name)
+            || self.prefixes.iter().any(|item| name.starts_with(item))
+    }
+}
+
+pub struct CookieFilter {
+    plugin_step: PluginStep,
+    // the cookies are not sent to upstream
+    drop_cookies: CookieNames,
+    // only the allowed cookies are sent to upstream
+    allow_cookies: Option<CookieNames>,
+    // the cookies are renamed as (client name, upstream name)
+    rename_cookies: Vec<(String, String)>,
+    // only the cookies with the prefix are sent to upstream
+    // without the prefix, and the prefix is added to the set cookies
+    scope_prefix: String,
+}
+
+impl TryFrom<&PluginConf> for CookieFilter {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let new_error = |message: String| Error::Invalid {
+            category: PluginCategory::CookieFilter.to_string(),
+            message,
+        };
+        let mut rename_cookies = vec![];
+        for item in get_str_slice_conf(value, "rename_cookies").iter() {
+            let Some((name, upstream_name)) = item
+                .split_once(':')
+                .map(|(name, upstream_name)| {
+                    (name.trim(), upstream_name.trim())
+                })
+                .filter(|(name, upstream_name)| {
+                    !name.is_empty() && !upstream_name.is_empty()
+                })
+            else {
+                return Err(new_error(format!(
+                    "rename cookie({item}) should be name:upstream_name"
+                )));
+            };
+            rename_cookies.push((name.to_string(), upstream_name.to_string()));
+        }
+        let allow_cookies = get_str_slice_conf(value, "allow_cookies");
+        let params = Self {
+            plugin_step: PluginStep::ProxyUpstream,
+            drop_cookies: CookieNames::new(&get_str_slice_conf(
+                value,
+                "drop_cookies",
+            )),
+            allow_cookies: if allow_cookies.is_empty() {
+                None
+            } else {
+                Some(CookieNames::new(&allow_cookies))
+            },
+            rename_cookies,
+            scope_prefix: get_str_conf(value, "scope_prefix"),
+        };
+        if params.drop_cookies == CookieNames::default()
+            && params.allow_cookies.is_none()
+            && params.rename_cookies.is_empty()
+            && params.scope_prefix.is_empty()
+        {
+            return Err(new_error(
+                "drop, allow, rename cookies or scope prefix should be set"
+                    .to_string(),
+            ));
+        }
+        Ok(params)
+    }
+}
+
+impl CookieFilter {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new cookie filter plugin");
+        Self::try_from(params)
+    }
+    /// Gets the cookie name for upstream, `None` means the cookie
+    /// is not sent to upstream.
+    fn get_upstream_name<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        let name = if self.scope_prefix.is_empty() {
+            name
+        } else {
+            name.strip_prefix(&self.scope_prefix)?
+        };
+        if self.drop_cookies.is_match(name) {
+            return None;
+        }
+        if let Some(allow_cookies) = &self.allow_cookies {
+            if !allow_cookies.is_match(name) {
+                return None;
+            }
+        }
+        let name = self
+            .rename_cookies
+            .iter()
+            .find(|(client_name, _)| client_name == name)
+            .map(|(_, upstream_name)| upstream_name.as_str())
+            .unwrap_or(name);
+        Some(name)
+    }
+    /// Gets the cookie name for client from the name of upstream,
+    /// the renamed cookie is restored and the scope prefix is added.
+    fn get_client_name(&self, name: &str) -> String {
+        let name = self
+            .rename_cookies
+            .iter()
+            .find(|(_, upstream_name)| upstream_name == name)
+            .map(|(client_name, _)| client_name.as_str())
+            .unwrap_or(name);
+        format!("{}{name}", self.scope_prefix)
+    }
+    /// Filters the cookies of upstream request, it returns the count
+    /// of dropped cookies.
+    fn filter_cookies(&self, req: &mut RequestHeader) -> usize {
+        let mut dropped = 0;
+        let mut cookies = vec![];
+        // the cookie may be split into multiple headers in http2
+        for value in req.headers.get_all(header::COOKIE).iter() {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for item in value.split(';') {
+                let item = item.trim();
+                if item.is_empty() {
+                    continue;
+                }
+                let (name, value) = item.split_once('=').unwrap_or((item, ""));
+                if let Some(name) = self.get_upstream_name(name) {
+                    cookies.push(format!("{name}={value}"));
+                } else {
+                    dropped += 1;
+                }
+            }
+        }
+        req.remove_header(&header::COOKIE);
+        if !cookies.is_empty() {
+            // the cookies are valid header value as they are from header
+            let _ = req.insert_header(header::COOKIE, cookies.join("; "));
+        }
+        dropped
+    }
+    /// Converts the names of set cookies to the names for client.
+    fn convert_set_cookies(&self, resp: &mut ResponseHeader) {
+        let set_cookies: Vec<String> = resp
+            .headers
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(|value| {
+                let (name, value) =
+                    value.split_once('=').unwrap_or((value, ""));
+                format!("{}={value}", self.get_client_name(name.trim()))
+            })
+            .collect();
+        if set_cookies.is_empty() {
+            return;
+        }
+        resp.remove_header(&header::SET_COOKIE);
+        for value in set_cookies {
+            let _ = resp.append_header(header::SET_COOKIE, value);
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for CookieFilter {
+    #[inline]
+    fn step(&self) -> String {
+        self.plugin_step.to_string()
+    }
+    #[inline]
+    fn category(&self) -> PluginCategory {
+        PluginCategory::CookieFilter
+    }
+    #[inline]
+    async fn handle_upstream_request(
+        &self,
+        step: PluginStep,
+        _session: &mut Session,
+        ctx: &mut State,
+        upstream_request: &mut RequestHeader,
+    ) -> pingora::Result<()> {
+        if step != self.plugin_step {
+            return Ok(());
+        }
+        let dropped = self.filter_cookies(upstream_request);
+        if dropped > 0 {
+            ctx.add_journal(|| format!("cookie_filter:dropped:{dropped}"));
+        }
+        Ok(())
+    }
+    #[inline]
+    async fn handle_response(
+        &self,
+        step: PluginStep,
+        _session: &mut Session,
+        _ctx: &mut State,
+        upstream_response: &mut ResponseHeader,
+    ) -> pingora::Result<Option<Bytes>> {
+        // the names of set cookies are restored for client
+        if step != PluginStep::Response
+            || (self.rename_cookies.is_empty() && self.scope_prefix.is_empty())
+        {
+            return Ok(None);
+        }
+        self.convert_set_cookies(upstream_response);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CookieFilter;
+    use crate::config::PluginConf;
+    use http::header;
+    use pingora::http::{RequestHeader, ResponseHeader};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_cookie_filter_params() {
+        let params = CookieFilter::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+drop_cookies = ["_ga*", "_gid"]
+rename_cookies = ["app_session:session"]
+scope_prefix = "shop_"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("proxy_upstream", params.plugin_step.to_string());
+        assert_eq!(vec!["_gid".to_string()], params.drop_cookies.names);
+        assert_eq!(vec!["_ga".to_string()], params.drop_cookies.prefixes);
+        assert_eq!(
+            vec![("app_session".to_string(), "session".to_string())],
+            params.rename_cookies
+        );
+
+        let result = CookieFilter::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+rename_cookies = ["app_session"]
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin cookie_filter invalid, message: rename cookie(app_session) should be name:upstream_name",
+            result.err().unwrap().to_string()
+        );
+
+        let result = CookieFilter::try_from(&PluginConf::new());
+        assert_eq!(
+            "Plugin cookie_filter invalid, message: drop, allow, rename cookies or scope prefix should be set",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_cookie_filter() {
+        let cookie_filter = CookieFilter::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+drop_cookies = ["_ga*"]
+rename_cookies = ["app_session:session"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        req.insert_header(
+            header::COOKIE,
+            "_ga=GA1.2.3; _ga_ABC=GS1; app_session=123; lang=en",
+        )
+        .unwrap();
+        assert_eq!(2, cookie_filter.filter_cookies(&mut req));
+        assert_eq!(
+            "session=123; lang=en",
+            req.headers.get(header::COOKIE).unwrap().to_str().unwrap()
+        );
+
+        let cookie_filter = CookieFilter::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+allow_cookies = ["session"]
+scope_prefix = "shop_"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        req.insert_header(
+            header::COOKIE,
+            "shop_session=123; shop_lang=en; blog_session=456",
+        )
+        .unwrap();
+        assert_eq!(2, cookie_filter.filter_cookies(&mut req));
+        assert_eq!(
+            "session=123",
+            req.headers.get(header::COOKIE).unwrap().to_str().unwrap()
+        );
+
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        req.insert_header(header::COOKIE, "blog_session=456")
+            .unwrap();
+        assert_eq!(1, cookie_filter.filter_cookies(&mut req));
+        assert_eq!(true, req.headers.get(header::COOKIE).is_none());
+
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.append_header(header::SET_COOKIE, "session=789; Path=/")
+            .unwrap();
+        cookie_filter.convert_set_cookies(&mut resp);
+        assert_eq!(
+            "shop_session=789; Path=/",
+            resp.headers
+                .get(header::SET_COOKIE)
+                .unwrap()
+                .to_str()
+                .unwrap()
+        );
+    }
+}
@@ -228,6 +228,34 @@ impl Directory {
     }
 }
 
+/// Sends the file to client, it's used for `X-Sendfile` of upstream response.
+pub async fn send_file(
+    session: &mut Session,
+    ctx: &mut State,
+    file: &PathBuf,
+) -> pingora::Result<()> {
+    let (meta, mut f) = match get_data(file).await {
+        Ok(value) => value,
+        Err(err) => {
+            let resp = if err.kind() == std::io::ErrorKind::NotFound {
+                HttpResponse::not_found("Not Found".into())
+            } else {
+                HttpResponse::unknown_error("Get file data fail".into())
+            };
+            ctx.status = Some(resp.status);
+            resp.send(session).await?;
+            return Ok(());
+        },
+    };
+    let (_, _, headers) =
+        get_cacheable_and_headers_from_meta(file, &meta, &None);
+    let mut resp = HttpChunkResponse::new(&mut f);
+    resp.headers = Some(headers);
+    ctx.status = Some(StatusCode::OK);
+    resp.send(session).await?;
+    Ok(())
+}
+
 static IGNORE_RESPONSE: Lazy<HttpResponse> = Lazy::new(|| HttpResponse {
     status: StatusCode::from_u16(999).unwrap(),
     ..Default::default()
@@ -0,0 +1,306 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::block_action::Blocker;
+use super::{
+    get_step_conf, get_str_conf, get_str_slice_conf, Error, Plugin, Result,
+};
+use crate::cluster;
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::http_extra::HttpResponse;
+use crate::state::State;
+use crate::util;
+use crate::webhook;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{header, StatusCode};
+use humantime::parse_duration;
+use pingora::proxy::Session;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+// the notification interval(seconds) if the client is not banned
+const NOTIFICATION_INTERVAL: u64 = 60;
+
+pub struct Honeypot {
+    plugin_step: PluginStep,
+    // the decoy paths which are matched exactly
+    paths: Vec<String>,
+    // the decoy paths which are matched by prefix, e.g. `/phpmyadmin*`
+    prefix_paths: Vec<String>,
+    // the ban duration of client ip, zero means not to ban
+    ban_ttl: Duration,
+    hits: AtomicU64,
+    last_notified_at: AtomicU64,
+    decoy_resp: HttpResponse,
+    blocker: Blocker,
+}
+
+/// The runtime state of honeypot.
+#[derive(Serialize, Debug)]
+struct HoneypotState {
+    hits: u64,
+}
+
+impl TryFrom<&PluginConf> for Honeypot {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let step = get_step_conf(value);
+        let mut paths = vec![];
+        let mut prefix_paths = vec![];
+        for item in get_str_slice_conf(value, "paths").iter() {
+            if let Some(prefix) = item.strip_suffix('*') {
+                prefix_paths.push(prefix.to_string());
+            } else {
+                paths.push(item.to_string());
+            }
+        }
+        let ban_ttl = get_str_conf(value, "ban_ttl");
+        let ban_ttl = if ban_ttl.is_empty() {
+            Duration::from_secs(3600)
+        } else {
+            parse_duration(&ban_ttl).map_err(|e| Error::Invalid {
+                category: PluginCategory::Honeypot.to_string(),
+                message: e.to_string(),
+            })?
+        };
+        let mut message = get_str_conf(value, "message");
+        if message.is_empty() {
+            message = "Not Found".to_string();
+        }
+        let params = Self {
+            plugin_step: step,
+            paths,
+            prefix_paths,
+            ban_ttl,
+            hits: AtomicU64::new(0),
+            last_notified_at: AtomicU64::new(0),
+            // respond as a normal not found page to avoid exposing the trap
+            decoy_resp: HttpResponse {
+                status: StatusCode::NOT_FOUND,
+                body: Bytes::from(message),
+                ..Default::default()
+            },
+            blocker: Blocker::new(PluginCategory::Honeypot, value)?,
+        };
+        if params.paths.is_empty() && params.prefix_paths.is_empty() {
+            return Err(Error::Invalid {
+                category: PluginCategory::Honeypot.to_string(),
+                message: "Honeypot paths are not allowed empty".to_string(),
+            });
+        }
+        if ![PluginStep::Request, PluginStep::ProxyUpstream]
+            .contains(&params.plugin_step)
+        {
+            return Err(Error::Invalid {
+                category: PluginCategory::Honeypot.to_string(),
+                message: "Honeypot plugin should be executed at request or proxy upstream step".to_string(),
+            });
+        }
+        Ok(params)
+    }
+}
+
+impl Honeypot {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new honeypot plugin");
+        Self::try_from(params)
+    }
+    #[inline]
+    fn is_decoy(&self, path: &str) -> bool {
+        self.paths.iter().any(|item| item == path)
+            || self.prefix_paths.iter().any(|item| path.starts_with(item))
+    }
+    /// Returns `true` if the notification should be sent, the banned client
+    /// is rejected by server, so it's notified only once,
+    /// otherwise the notification is sent at most once per minute.
+    fn should_notify(&self) -> bool {
+        if !self.ban_ttl.is_zero() {
+            return true;
+        }
+        let now = util::now().as_secs();
+        let last_notified_at = self.last_notified_at.load(Ordering::Relaxed);
+        now.saturating_sub(last_notified_at) >= NOTIFICATION_INTERVAL
+            && self
+                .last_notified_at
+                .compare_exchange(
+                    last_notified_at,
+                    now,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+    }
+}
+
+#[async_trait]
+impl Plugin for Honeypot {
+    #[inline]
+    fn step(&self) -> String {
+        self.plugin_step.to_string()
+    }
+    #[inline]
+    fn category(&self) -> PluginCategory {
+        PluginCategory::Honeypot
+    }
+    fn runtime_state(&self, _key: &str) -> Option<serde_json::Value> {
+        serde_json::to_value(HoneypotState {
+            hits: self.hits.load(Ordering::Relaxed),
+        })
+        .ok()
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        let path = session.req_header().uri.path().to_string();
+        if !self.is_decoy(&path) {
+            return Ok(None);
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        // the ip of ban can't be forged by the request headers,
+        // otherwise the ip of victim may be banned
+        let ip = cluster::get_ban_ip(session);
+        ctx.add_journal(|| "honeypot:hit".to_string());
+        if !self.ban_ttl.is_zero() && !ip.is_empty() {
+            cluster::ban_ip(&ip, self.ban_ttl);
+        }
+        if self.should_notify() {
+            let user_agent = session
+                .get_header(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            warn!(ip, path, user_agent, "honeypot is hit");
+            webhook::send(webhook::SendNotificationParams {
+                category: webhook::NotificationCategory::Honeypot,
+                level: webhook::NotificationLevel::Error,
+                msg: format!(
+                    "honeypot {path} is hit by {ip}, user agent: {user_agent}"
+                ),
+            });
+        }
+        self.blocker
+            .block(session, ctx, self.decoy_resp.clone())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Honeypot;
+    use crate::cluster;
+    use crate::config::{PluginConf, PluginStep};
+    use crate::plugin::Plugin;
+    use crate::state::State;
+    use http::StatusCode;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_honeypot_params() {
+        let params = Honeypot::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+paths = ["/wp-login.php", "/phpmyadmin*"]
+ban_ttl = "10m"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("request", params.step());
+        assert_eq!(vec!["/wp-login.php".to_string()], params.paths);
+        assert_eq!(vec!["/phpmyadmin".to_string()], params.prefix_paths);
+        assert_eq!(Duration::from_secs(600), params.ban_ttl);
+        assert_eq!(true, params.is_decoy("/phpmyadmin/index.php"));
+        assert_eq!(false, params.is_decoy("/wp-login"));
+
+        let result = Honeypot::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+ban_ttl = "10m"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin honeypot invalid, message: Honeypot paths are not allowed empty",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_honeypot() {
+        let honeypot = Honeypot::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+paths = ["/wp-login.php"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let headers = ["X-Forwarded-For: 10.1.1.200"].join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let result = honeypot
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        assert_eq!(false, cluster::is_banned("10.1.1.200"));
+
+        let input_header =
+            format!("GET /wp-login.php HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let result = honeypot
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, result.unwrap().status);
+        // the forged X-Forwarded-For doesn't change the banned ip
+        assert_eq!(false, cluster::is_banned("10.1.1.200"));
+        assert_eq!(
+            false,
+            cluster::get_bans()
+                .iter()
+                .any(|item| item.ip == "10.1.1.200")
+        );
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::block_action::Blocker;
 use super::{
     get_step_conf, get_str_conf, get_str_slice_conf, Error, Plugin, Result,
 };
@@ -22,18 +23,15 @@ use crate::util;
 use async_trait::async_trait;
 use bytes::Bytes;
 use http::StatusCode;
-use ipnet::IpNet;
 use pingora::proxy::Session;
-use std::net::IpAddr;
-use std::str::FromStr;
 use tracing::debug;
 
 pub struct IpRestriction {
     plugin_step: PluginStep,
-    ip_net_list: Vec<IpNet>,
-    ip_list: Vec<String>,
+    ip_rules: util::IpRules,
     restriction_category: String,
     forbidden_resp: HttpResponse,
+    blocker: Blocker,
 }
 
 impl TryFrom<&PluginConf> for IpRestriction {
@@ -41,29 +39,22 @@ impl TryFrom<&PluginConf> for IpRestriction {
     fn try_from(value: &PluginConf) -> Result<Self> {
         let step = get_step_conf(value);
 
-        let mut ip_net_list = vec![];
-        let mut ip_list = vec![];
-        for item in get_str_slice_conf(value, "ip_list") {
-            if let Ok(value) = IpNet::from_str(&item) {
-                ip_net_list.push(value);
-            } else {
-                ip_list.push(item);
-            }
-        }
+        let ip_rules =
+            util::IpRules::new(&get_str_slice_conf(value, "ip_list"));
         let mut message = get_str_conf(value, "message");
         if message.is_empty() {
             message = "Request is forbidden".to_string();
         }
         let params = Self {
             plugin_step: step,
-            ip_list,
-            ip_net_list,
+            ip_rules,
             restriction_category: get_str_conf(value, "type"),
             forbidden_resp: HttpResponse {
                 status: StatusCode::FORBIDDEN,
                 body: Bytes::from(message),
                 ..Default::default()
             },
+            blocker: Blocker::new(PluginCategory::IpRestriction, value)?,
         };
         if ![PluginStep::Request, PluginStep::ProxyUpstream]
             .contains(&params.plugin_step)
@@ -112,19 +103,13 @@ impl Plugin for IpRestriction {
             ip
         };
 
-        let found = if self.ip_list.contains(&ip) {
-            true
-        } else {
-            match ip.parse::<IpAddr>() {
-                Ok(addr) => {
-                    self.ip_net_list.iter().any(|item| item.contains(&addr))
-                },
-                Err(e) => {
-                    return Ok(Some(HttpResponse::bad_request(
-                        e.to_string().into(),
-                    )));
-                },
-            }
+        let found = match self.ip_rules.is_match(&ip) {
+            Ok(matched) => matched,
+            Err(e) => {
+                return Ok(Some(HttpResponse::bad_request(
+                    e.to_string().into(),
+                )));
+            },
         };
         // deny ip
         let allow = if self.restriction_category == "deny" {
@@ -133,7 +118,10 @@ impl Plugin for IpRestriction {
             found
         };
         if !allow {
-            return Ok(Some(self.forbidden_resp.clone()));
+            return self
+                .blocker
+                .block(session, ctx, self.forbidden_resp.clone())
+                .await;
         }
         return Ok(None);
     }
@@ -167,10 +155,11 @@ type = "deny"
         )
         .unwrap();
         assert_eq!("request", params.plugin_step.to_string());
-        assert_eq!("192.168.1.1,10.1.1.1", params.ip_list.join(","));
+        assert_eq!("192.168.1.1,10.1.1.1", params.ip_rules.ip_list.join(","));
         assert_eq!(
             "1.1.1.0/24,2.1.1.0/24",
             params
+                .ip_rules
                 .ip_net_list
                 .iter()
                 .map(|item| item.to_string())
@@ -24,9 +24,23 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use http::{HeaderName, StatusCode};
 use pingora::proxy::Session;
+use sha2::{Digest, Sha256};
 use std::str::FromStr;
 use tracing::{debug, error};
 
+// the id of key, it can be used in access log and metering
+const KEY_AUTH_ID_VARIABLE: &str = "key_auth_id";
+
+/// Gets the id of key, it's the prefix of sha256 hex,
+/// so the key is not leaked.
+fn get_key_id(key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    let mut id = hex::encode(hasher.finalize());
+    id.truncate(16);
+    id
+}
+
 pub struct KeyAuth {
     plugin_step: PluginStep,
     header: Option<HeaderName>,
@@ -123,7 +137,7 @@ impl Plugin for KeyAuth {
         &self,
         step: PluginStep,
         session: &mut Session,
-        _ctx: &mut State,
+        ctx: &mut State,
     ) -> pingora::Result<Option<HttpResponse>> {
         if step != self.plugin_step {
             return Ok(None);
@@ -144,6 +158,7 @@ impl Plugin for KeyAuth {
         if !self.keys.contains(&value.to_vec()) {
             return Ok(Some(self.unauthorized_resp.clone()));
         }
+        ctx.add_variable(KEY_AUTH_ID_VARIABLE, &get_key_id(value));
         if self.hide_credentials {
             if let Some(name) = &self.header {
                 session.req_header_mut().remove_header(name);
@@ -261,16 +276,17 @@ hide_credentials = true
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
         assert_eq!(false, session.get_header_bytes("X-User").is_empty());
+        let mut ctx = State::default();
         let result = auth
-            .handle_request(
-                PluginStep::Request,
-                &mut session,
-                &mut State::default(),
-            )
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
             .await
             .unwrap();
         assert_eq!(true, result.is_none());
         assert_eq!(true, session.get_header_bytes("X-User").is_empty());
+        assert_eq!(
+            "a665a45920422f9d",
+            ctx.get_variable("key_auth_id").unwrap()
+        );
 
         let headers = ["X-User: 12"].join("\r\n");
         let input_header =
@@ -12,35 +12,155 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{get_int_conf, get_step_conf, get_str_conf, Error, Plugin, Result};
+use super::block_action::Blocker;
+use super::{
+    get_bool_conf, get_int_conf, get_step_conf, get_str_conf,
+    get_str_slice_conf, Error, Plugin, Result,
+};
+use crate::cluster;
 use crate::config::{PluginCategory, PluginConf, PluginStep};
-use crate::http_extra::HttpResponse;
+use crate::http_extra::{HttpHeader, HttpResponse};
 use crate::state::State;
 use crate::util;
 use async_trait::async_trait;
-use http::StatusCode;
+use http::{header, HeaderName, HeaderValue, StatusCode};
 use humantime::parse_duration;
 use pingora::proxy::Session;
 use pingora_limits::inflight::Inflight;
 use pingora_limits::rate::Rate;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tracing::debug;
 
+// the reason of rejected request, it can be used by access log
+const LIMIT_REASON_VARIABLE: &str = "limit_reason";
+// the max count of recent rejections kept for inspection
+const MAX_RECENT_REJECTIONS: usize = 20;
+
 #[derive(PartialEq, Debug)]
 pub enum LimitTag {
     Ip,
     RequestHeader,
     Cookie,
     Query,
+    // the variable set by other plugins, e.g. jwt subject
+    Variable,
+    Method,
+    // the prefix of path, the key is the count of path segments
+    Path,
+}
+
+impl From<&str> for LimitTag {
+    fn from(value: &str) -> Self {
+        match value {
+            "cookie" => LimitTag::Cookie,
+            "header" => LimitTag::RequestHeader,
+            "query" => LimitTag::Query,
+            "variable" => LimitTag::Variable,
+            "method" => LimitTag::Method,
+            "path" => LimitTag::Path,
+            _ => LimitTag::Ip,
+        }
+    }
+}
+
+struct RateWindow {
+    rate: Arc<Rate>,
+    max: isize,
+    interval: Duration,
+    // the key of rate shared in cluster
+    cluster_key: Option<String>,
+    // the start(unix ms) of current window, the window of rate
+    // is reset by the first observation after the interval
+    started_at: AtomicU64,
+}
+
+impl RateWindow {
+    /// Observes the window and returns the start of current window.
+    fn observe_window(&self, now: u64) -> u64 {
+        let started_at = self.started_at.load(Ordering::Relaxed);
+        if now.saturating_sub(started_at) < self.interval.as_millis() as u64 {
+            return started_at;
+        }
+        self.started_at.store(now, Ordering::Relaxed);
+        now
+    }
+}
+
+/// Gets the seconds until the window is reset, it's at least one second.
+fn get_window_reset(started_at: u64, interval: Duration, now: u64) -> u64 {
+    let remaining = (started_at + interval.as_millis() as u64)
+        .saturating_sub(now)
+        .div_ceil(1000);
+    remaining.max(1)
+}
+
+struct Exceeded {
+    max: isize,
+    value: isize,
+    retry_after: u64,
+    // the seconds until the exceeded window is reset
+    reset: u64,
+    rate: bool,
+}
+
+/// The rejected request of limiter.
+#[derive(Serialize, Debug, Clone)]
+struct Rejection {
+    key: String,
+    max: isize,
+    value: isize,
+    // the unix timestamp(seconds) of rejection
+    rejected_at: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct RateWindowState {
+    max: isize,
+    interval: String,
+    cluster: bool,
+    // the current rate of the inspected key
+    value: Option<isize>,
+}
+
+/// The runtime state of limiter.
+#[derive(Serialize, Debug)]
+struct LimiterState {
+    inflight: bool,
+    max: isize,
+    rates: Vec<RateWindowState>,
+    rejected: u64,
+    recent_rejections: Vec<Rejection>,
 }
 
 pub struct Limiter {
     tag: LimitTag,
     max: isize,
     key: String,
+    // the key is composed of multiple parts, e.g. `ip` and `path:1`
+    keys: Vec<(LimitTag, String)>,
     inflight: Option<Inflight>,
-    rate: Option<Rate>,
+    // the rate windows are evaluated together, e.g. burst and sustained
+    rates: Vec<RateWindow>,
+    // the status of rejected response
+    status: StatusCode,
+    // the body template of rejected response,
+    // supports `{{max}}`, `{{value}}` and `{{retry_after}}`
+    message: String,
     plugin_step: PluginStep,
+    rejected: AtomicU64,
+    recent_rejections: Mutex<VecDeque<Rejection>>,
+    blocker: Blocker,
+}
+
+fn parse_duration_conf(value: &str) -> Result<Duration> {
+    parse_duration(value).map_err(|e| Error::Invalid {
+        category: PluginCategory::Limit.to_string(),
+        message: e.to_string(),
+    })
 }
 
 impl TryFrom<&PluginConf> for Limiter {
@@ -48,36 +168,103 @@ impl TryFrom<&PluginConf> for Limiter {
     fn try_from(value: &PluginConf) -> Result<Self> {
         let step = get_step_conf(value);
 
-        let tag = match get_str_conf(value, "tag").as_str() {
-            "cookie" => LimitTag::Cookie,
-            "header" => LimitTag::RequestHeader,
-            "query" => LimitTag::Query,
-            _ => LimitTag::Ip,
-        };
+        let tag = LimitTag::from(get_str_conf(value, "tag").as_str());
+        let keys = get_str_slice_conf(value, "keys")
+            .iter()
+            .map(|item| {
+                let (tag, key) = item.split_once(':').unwrap_or((item, ""));
+                (LimitTag::from(tag.trim()), key.trim().to_string())
+            })
+            .collect();
+        let max = get_int_conf(value, "max") as isize;
         let interval = get_str_conf(value, "interval");
         let interval = if !interval.is_empty() {
-            parse_duration(&interval).map_err(|e| Error::Invalid {
-                category: PluginCategory::Limit.to_string(),
-                message: e.to_string(),
-            })?
+            parse_duration_conf(&interval)?
         } else {
             Duration::from_secs(10)
         };
         let mut inflight = None;
-        let mut rate = None;
+        let mut rates = vec![];
         if get_str_conf(value, "type") == "inflight" {
             inflight = Some(Inflight::new());
         } else {
-            rate = Some(Rate::new(interval));
+            // the windows of rate, e.g. `10/1s` and `100/1m`
+            let mut windows = vec![];
+            for item in get_str_slice_conf(value, "rates").iter() {
+                let Some((max, interval)) = item.split_once('/') else {
+                    return Err(Error::Invalid {
+                        category: PluginCategory::Limit.to_string(),
+                        message: format!("rate({item}) is invalid"),
+                    });
+                };
+                let max = max.trim().parse::<isize>().map_err(|e| {
+                    Error::Invalid {
+                        category: PluginCategory::Limit.to_string(),
+                        message: e.to_string(),
+                    }
+                })?;
+                windows.push((max, parse_duration_conf(interval.trim())?));
+            }
+            if windows.is_empty() {
+                windows.push((max, interval));
+            }
+            // the config of plugin is the same in cluster,
+            // so the hash of it is used as the key
+            let cluster = get_bool_conf(value, "cluster");
+            let hash = crc32fast::hash(value.to_string().as_bytes());
+            for (index, (max, interval)) in windows.into_iter().enumerate() {
+                let (rate, cluster_key) = if cluster {
+                    let key = if index == 0 {
+                        format!("{hash:x}")
+                    } else {
+                        format!("{hash:x}-{index}")
+                    };
+                    (cluster::get_shared_rate(&key, interval), Some(key))
+                } else {
+                    (Arc::new(Rate::new(interval)), None)
+                };
+                rates.push(RateWindow {
+                    rate,
+                    max,
+                    interval,
+                    cluster_key,
+                    started_at: AtomicU64::new(util::now().as_millis() as u64),
+                });
+            }
+        }
+
+        let status = get_int_conf(value, "status");
+        let status = if status > 0 {
+            StatusCode::from_u16(status as u16).map_err(|e| Error::Invalid {
+                category: PluginCategory::Limit.to_string(),
+                message: e.to_string(),
+            })?
+        } else {
+            StatusCode::TOO_MANY_REQUESTS
+        };
+        if !status.is_client_error() && !status.is_server_error() {
+            return Err(Error::Invalid {
+                category: PluginCategory::Limit.to_string(),
+                message: format!(
+                    "status({}) should be 4xx or 5xx",
+                    status.as_u16()
+                ),
+            });
         }
 
         let params = Self {
             tag,
             key: get_str_conf(value, "key"),
-            max: get_int_conf(value, "max") as isize,
+            keys,
+            max,
             inflight,
-            rate,
+            rates,
+            status,
+            message: get_str_conf(value, "message"),
             plugin_step: step,
+            rejected: AtomicU64::new(0),
+            recent_rejections: Mutex::new(VecDeque::new()),
+            blocker: Blocker::new(PluginCategory::Limit, value)?,
         };
         if ![PluginStep::Request, PluginStep::ProxyUpstream]
             .contains(&params.plugin_step)
@@ -91,59 +278,200 @@ impl TryFrom<&PluginConf> for Limiter {
     }
 }
 
+/// Gets the prefix of path with the count of segments,
+/// the whole path is returned if the count is zero.
+fn get_path_prefix(path: &str, count: usize) -> String {
+    if count == 0 {
+        return path.to_string();
+    }
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|item| !item.is_empty())
+        .take(count)
+        .collect();
+    format!("/{}", segments.join("/"))
+}
+
 impl Limiter {
     pub fn new(params: &PluginConf) -> Result<Self> {
         debug!(params = params.to_string(), "new limit plugin");
         Self::try_from(params)
     }
-    /// Increment `key` by 1. If value gt max, an error will be return.
-    /// Otherwise returns a Guard. It may set the client ip to context.
-    pub fn incr(&self, session: &Session, ctx: &mut State) -> Result<()> {
-        let key = match self.tag {
-            LimitTag::Query => {
-                util::get_query_value(session.req_header(), &self.key)
-                    .unwrap_or_default()
-                    .to_string()
-            },
+    fn get_key_value(
+        &self,
+        tag: &LimitTag,
+        key: &str,
+        session: &Session,
+        ctx: &mut State,
+    ) -> String {
+        match tag {
+            LimitTag::Query => util::get_query_value(session.req_header(), key)
+                .unwrap_or_default()
+                .to_string(),
             LimitTag::RequestHeader => {
-                util::get_req_header_value(session.req_header(), &self.key)
+                util::get_req_header_value(session.req_header(), key)
                     .unwrap_or_default()
                     .to_string()
             },
             LimitTag::Cookie => {
-                util::get_cookie_value(session.req_header(), &self.key)
+                util::get_cookie_value(session.req_header(), key)
                     .unwrap_or_default()
                     .to_string()
             },
+            LimitTag::Variable => {
+                ctx.get_variable(key).unwrap_or_default().to_string()
+            },
+            LimitTag::Method => session.req_header().method.to_string(),
+            LimitTag::Path => get_path_prefix(
+                session.req_header().uri.path(),
+                key.parse::<usize>().unwrap_or_default(),
+            ),
             _ => {
                 let client_ip = util::get_client_ip(session);
                 ctx.client_ip = Some(client_ip.clone());
                 client_ip
             },
-        };
+        }
+    }
+    /// Gets the key of limit, the parts of composite key are joined by `:`,
+    /// and it will be empty if any part is empty.
+    fn get_key(&self, session: &Session, ctx: &mut State) -> String {
+        if self.keys.is_empty() {
+            return self.get_key_value(&self.tag, &self.key, session, ctx);
+        }
+        let mut values = Vec::with_capacity(self.keys.len());
+        for (tag, key) in self.keys.iter() {
+            let value = self.get_key_value(tag, key, session, ctx);
+            if value.is_empty() {
+                return "".to_string();
+            }
+            values.push(value);
+        }
+        values.join(":")
+    }
+    /// Observes the request and returns the exceeded limit,
+    /// the rejection is recorded for inspection.
+    fn observe(&self, session: &Session, ctx: &mut State) -> Option<Exceeded> {
+        let key = self.get_key(session, ctx);
         if key.is_empty() {
-            return Ok(());
+            return None;
         }
-        let value = if let Some(rate) = &self.rate {
-            rate.observe(&key, 1);
-            let value = rate.rate(&key);
-            value as isize
-        } else if let Some(inflight) = &self.inflight {
-            let (guard, value) = inflight.incr(&key, 1);
+        let exceeded = self.observe_key(&key, ctx)?;
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut rejections) = self.recent_rejections.lock() {
+            if rejections.len() >= MAX_RECENT_REJECTIONS {
+                rejections.pop_front();
+            }
+            rejections.push_back(Rejection {
+                key,
+                max: exceeded.max,
+                value: exceeded.value,
+                rejected_at: util::now().as_secs(),
+            });
+        }
+        Some(exceeded)
+    }
+    /// Observes the key of request and returns the exceeded limit,
+    /// all rate windows are observed even if one of them is exceeded.
+    fn observe_key(&self, key: &str, ctx: &mut State) -> Option<Exceeded> {
+        if let Some(inflight) = &self.inflight {
+            let (guard, value) = inflight.incr(key, 1);
             ctx.guard = Some(guard);
-            value
-        } else {
-            0
-        };
-        if value > self.max {
+            if value > self.max {
+                return Some(Exceeded {
+                    max: self.max,
+                    value,
+                    // the inflight request may be done soon
+                    retry_after: 1,
+                    reset: 1,
+                    rate: false,
+                });
+            }
+            return None;
+        }
+        let mut exceeded: Option<Exceeded> = None;
+        let now = util::now().as_millis() as u64;
+        for window in self.rates.iter() {
+            let started_at = window.observe_window(now);
+            window.rate.observe(&key, 1);
+            if let Some(cluster_key) = &window.cluster_key {
+                cluster::observe_shared_rate(cluster_key, key, 1);
+            }
+            let value = window.rate.rate(&key) as isize;
+            if value <= window.max {
+                continue;
+            }
+            // the rate is counted by fixed window,
+            // so the client should retry after the longest interval
+            let retry_after = window.interval.as_secs().max(1);
+            if exceeded
+                .as_ref()
+                .is_some_and(|item| item.retry_after >= retry_after)
+            {
+                continue;
+            }
+            exceeded = Some(Exceeded {
+                max: window.max,
+                value,
+                retry_after,
+                reset: get_window_reset(started_at, window.interval, now),
+                rate: true,
+            });
+        }
+        exceeded
+    }
+    /// Increment `key` by 1. If value gt max, an error will be return.
+    /// Otherwise returns a Guard. It may set the client ip to context.
+    pub fn incr(&self, session: &Session, ctx: &mut State) -> Result<()> {
+        if let Some(exceeded) = self.observe(session, ctx) {
             return Err(Error::Exceed {
                 category: PluginCategory::Limit.to_string(),
-                max: self.max,
-                value,
+                max: exceeded.max,
+                value: exceeded.value,
             });
         }
         Ok(())
     }
+    /// Creates the response of rejected request, the `Retry-After` and
+    /// `RateLimit-*` headers are set by the limit type.
+    fn new_limited_response(&self, exceeded: &Exceeded) -> HttpResponse {
+        let retry_after = exceeded.retry_after;
+        let body = if self.message.is_empty() {
+            Error::Exceed {
+                category: PluginCategory::Limit.to_string(),
+                max: exceeded.max,
+                value: exceeded.value,
+            }
+            .to_string()
+        } else {
+            self.message
+                .replace("{{max}}", &exceeded.max.to_string())
+                .replace("{{value}}", &exceeded.value.to_string())
+                .replace("{{retry_after}}", &retry_after.to_string())
+        };
+        let mut headers: Vec<HttpHeader> =
+            vec![(header::RETRY_AFTER, HeaderValue::from(retry_after))];
+        if exceeded.rate {
+            headers.push((
+                HeaderName::from_static("ratelimit-limit"),
+                HeaderValue::from(exceeded.max.max(0) as u64),
+            ));
+            headers.push((
+                HeaderName::from_static("ratelimit-remaining"),
+                HeaderValue::from(0),
+            ));
+            headers.push((
+                HeaderName::from_static("ratelimit-reset"),
+                HeaderValue::from(exceeded.reset),
+            ));
+        }
+        HttpResponse {
+            status: self.status,
+            body: body.into(),
+            headers: Some(headers),
+            ..Default::default()
+        }
+    }
 }
 #[async_trait]
 impl Plugin for Limiter {
@@ -155,6 +483,33 @@ impl Plugin for Limiter {
     fn category(&self) -> PluginCategory {
         PluginCategory::Limit
     }
+    fn runtime_state(&self, key: &str) -> Option<serde_json::Value> {
+        let rates = self
+            .rates
+            .iter()
+            .map(|window| RateWindowState {
+                max: window.max,
+                interval: humantime::format_duration(window.interval)
+                    .to_string(),
+                cluster: window.cluster_key.is_some(),
+                value: (!key.is_empty())
+                    .then(|| window.rate.rate(&key) as isize),
+            })
+            .collect();
+        let recent_rejections = self
+            .recent_rejections
+            .lock()
+            .map(|rejections| rejections.iter().cloned().collect())
+            .unwrap_or_default();
+        serde_json::to_value(LimiterState {
+            inflight: self.inflight.is_some(),
+            max: self.max,
+            rates,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            recent_rejections,
+        })
+        .ok()
+    }
     #[inline]
     async fn handle_request(
         &self,
@@ -165,12 +520,12 @@ impl Plugin for Limiter {
         if step != self.plugin_step {
             return Ok(None);
         }
-        if let Err(e) = self.incr(session, ctx) {
-            return Ok(Some(HttpResponse {
-                status: StatusCode::TOO_MANY_REQUESTS,
-                body: e.to_string().into(),
-                ..Default::default()
-            }));
+        if let Some(exceeded) = self.observe(session, ctx) {
+            let reason =
+                format!("exceed limit {}/{}", exceeded.value, exceeded.max);
+            ctx.add_variable(LIMIT_REASON_VARIABLE, &reason);
+            let resp = self.new_limited_response(&exceeded);
+            return self.blocker.block(session, ctx, resp).await;
         }
         Ok(None)
     }
@@ -178,13 +533,16 @@ impl Plugin for Limiter {
 
 #[cfg(test)]
 mod tests {
-    use super::{LimitTag, Limiter};
+    use super::{
+        get_path_prefix, get_window_reset, Exceeded, LimitTag, Limiter,
+    };
     use crate::{
         config::PluginConf, config::PluginStep, plugin::Plugin, state::State,
     };
     use http::StatusCode;
     use pingora::proxy::Session;
     use pretty_assertions::assert_eq;
+    use std::sync::atomic::Ordering;
     use std::time::Duration;
     use tokio_test::io::Builder;
 
@@ -242,6 +600,49 @@ max = 10
             "Plugin limit invalid, message: Limit plugin should be executed at request or proxy upstream step",
             result.err().unwrap().to_string()
         );
+
+        let params = Limiter::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+type = "inflight"
+max = 10
+status = 503
+message = "Too many requests, max: {{max}}, retry after {{retry_after}}s"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, params.status);
+        let resp = params.new_limited_response(&Exceeded {
+            max: 10,
+            value: 11,
+            retry_after: 1,
+            reset: 1,
+            rate: false,
+        });
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, resp.status);
+        assert_eq!(
+            "Too many requests, max: 10, retry after 1s",
+            std::string::String::from_utf8_lossy(&resp.body)
+        );
+
+        for status in [200, 302] {
+            let result = Limiter::try_from(
+                &toml::from_str::<PluginConf>(&format!(
+                    r###"
+type = "inflight"
+max = 10
+status = {status}
+"###
+                ))
+                .unwrap(),
+            );
+            assert_eq!(
+                format!("Plugin limit invalid, message: status({status}) should be 4xx or 5xx"),
+                result.err().unwrap().to_string()
+            );
+        }
     }
 
     #[tokio::test]
@@ -317,6 +718,30 @@ max = 10
         assert_eq!(true, ctx.guard.is_some());
     }
     #[tokio::test]
+    async fn test_new_variable_limiter() {
+        let limiter = Limiter::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+type = "inflight"
+tag = "variable"
+key = "jwt_subject"
+max = 10
+    "###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(LimitTag::Variable, limiter.tag);
+        let mut ctx = State {
+            ..Default::default()
+        };
+        ctx.add_variable("jwt_subject", "pingap");
+        let session = new_session().await;
+
+        limiter.incr(&session, &mut ctx).unwrap();
+        assert_eq!(true, ctx.guard.is_some());
+    }
+    #[tokio::test]
     async fn test_new_ip_limiter() {
         let limiter = Limiter::new(
             &toml::from_str::<PluginConf>(
@@ -337,6 +762,154 @@ max = 10
         limiter.incr(&session, &mut ctx).unwrap();
         assert_eq!(true, ctx.guard.is_some());
     }
+    #[test]
+    fn test_get_window_reset() {
+        let interval = Duration::from_secs(10);
+        assert_eq!(10, get_window_reset(1000, interval, 1000));
+        assert_eq!(3, get_window_reset(1000, interval, 8200));
+        assert_eq!(1, get_window_reset(1000, interval, 10_999));
+        assert_eq!(1, get_window_reset(1000, interval, 20_000));
+    }
+
+    #[test]
+    fn test_rate_limit_reset() {
+        let limiter = Limiter::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+type = "rate"
+rates = ["0/10s"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let window = &limiter.rates[0];
+        let started_at = window.started_at.load(Ordering::Relaxed);
+        assert_eq!(true, started_at > 0);
+        assert_eq!(started_at, window.observe_window(started_at + 8000));
+        // the window is reset by the observation after the interval
+        let started_at = started_at + 10_000;
+        assert_eq!(started_at, window.observe_window(started_at));
+
+        // the reset header is the remaining time of window
+        let now = started_at + 6500;
+        let resp = limiter.new_limited_response(&Exceeded {
+            max: 0,
+            value: 1,
+            retry_after: 10,
+            reset: get_window_reset(started_at, window.interval, now),
+            rate: true,
+        });
+        assert_eq!(
+            r#"Some([("retry-after", "10"), ("ratelimit-limit", "0"), ("ratelimit-remaining", "0"), ("ratelimit-reset", "4")])"#,
+            format!("{:?}", resp.headers)
+        );
+    }
+
+    #[test]
+    fn test_get_path_prefix() {
+        assert_eq!("/api/users", get_path_prefix("/api/users/1", 2));
+        assert_eq!("/api", get_path_prefix("/api", 2));
+        assert_eq!("/api/users/1", get_path_prefix("/api/users/1", 0));
+    }
+
+    #[tokio::test]
+    async fn test_composite_key_limiter() {
+        let limiter = Limiter::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+type = "inflight"
+keys = ["ip", "path:1", "method"]
+max = 10
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                (LimitTag::Ip, "".to_string()),
+                (LimitTag::Path, "1".to_string()),
+                (LimitTag::Method, "".to_string())
+            ],
+            limiter.keys
+        );
+        let mut ctx = State::default();
+        let session = new_session().await;
+        assert_eq!("1.1.1.1:/vicanso:GET", limiter.get_key(&session, &mut ctx));
+
+        // the key is empty if any part is empty
+        let limiter = Limiter::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+type = "inflight"
+keys = ["ip", "header:X-App"]
+max = 10
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("", limiter.get_key(&session, &mut ctx));
+    }
+
+    #[tokio::test]
+    async fn test_multiple_rate_limit() {
+        let limiter = Limiter::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+type = "rate"
+rates = ["100/1s", "0/2s"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(2, limiter.rates.len());
+        assert_eq!(100, limiter.rates[0].max);
+        assert_eq!(Duration::from_secs(2), limiter.rates[1].interval);
+
+        let mut session = new_session().await;
+        let mut ctx = State::default();
+        // the rate of current window is counted from next window
+        for _ in 0..2 {
+            limiter
+                .handle_request(PluginStep::Request, &mut session, &mut ctx)
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let result = limiter
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        let resp = result.unwrap();
+        assert_eq!(
+            r#"Some([("retry-after", "2"), ("ratelimit-limit", "0"), ("ratelimit-remaining", "0"), ("ratelimit-reset", "2")])"#,
+            format!("{:?}", resp.headers)
+        );
+
+        let state = limiter.runtime_state("").unwrap();
+        assert_eq!(1, state["rejected"]);
+        assert_eq!(1, state["recent_rejections"].as_array().unwrap().len());
+        assert_eq!("2s", state["rates"][1]["interval"]);
+        assert_eq!(true, state["rates"][1]["value"].is_null());
+
+        let result = Limiter::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+type = "rate"
+rates = ["100"]
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin limit invalid, message: rate(100) is invalid",
+            result.err().unwrap().to_string()
+        );
+    }
+
     #[tokio::test]
     async fn test_inflight_limit() {
         let limiter = Limiter::new(
@@ -366,7 +939,12 @@ max = 0
             .unwrap();
 
         assert_eq!(true, result.is_some());
-        assert_eq!(StatusCode::TOO_MANY_REQUESTS, result.unwrap().status);
+        let resp = result.unwrap();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, resp.status);
+        assert_eq!(
+            r#"Some([("retry-after", "1")])"#,
+            format!("{:?}", resp.headers)
+        );
 
         let limiter = Limiter::new(
             &toml::from_str::<PluginConf>(
@@ -432,16 +1010,24 @@ interval = "1s"
 
         tokio::time::sleep(Duration::from_secs(1)).await;
 
+        let mut ctx = State::default();
         let result = limiter
-            .handle_request(
-                PluginStep::Request,
-                &mut session,
-                &mut State::default(),
-            )
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
             .await
             .unwrap();
         assert_eq!(true, result.is_some());
-        assert_eq!(StatusCode::TOO_MANY_REQUESTS, result.unwrap().status);
+        let resp = result.unwrap();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, resp.status);
+        assert_eq!(
+            r#"Some([("retry-after", "1"), ("ratelimit-limit", "1"), ("ratelimit-remaining", "0"), ("ratelimit-reset", "1")])"#,
+            format!("{:?}", resp.headers)
+        );
+        assert_eq!(
+            true,
+            ctx.get_variable("limit_reason")
+                .unwrap()
+                .starts_with("exceed limit")
+        );
 
         tokio::time::sleep(Duration::from_secs(1)).await;
 
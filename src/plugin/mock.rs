@@ -14,18 +14,83 @@
 
 use super::{get_step_conf, get_str_conf, Error, Plugin, Result};
 use crate::config::{PluginCategory, PluginConf, PluginStep};
-use crate::http_extra::{convert_headers, HttpResponse};
-use crate::plugin::{get_int_conf, get_str_slice_conf};
+use crate::http_extra::{
+    convert_headers, format_variables, has_variables, HttpResponse,
+};
+use crate::plugin::{
+    get_bool_conf, get_float_conf, get_int_conf, get_str_slice_conf,
+};
 use crate::state::State;
 use async_trait::async_trait;
-use http::StatusCode;
+use bytes::Bytes;
+use http::{HeaderValue, StatusCode};
+use humantime::parse_duration;
 use pingora::proxy::Session;
+use pingora::tls::rand::rand_bytes;
+use std::time::Duration;
 use tracing::debug;
 
+/// The mock response of path, the data supports variables of request.
+#[derive(Debug, Clone)]
+struct MockRoute {
+    // empty means all paths, `*` suffix means prefix match
+    path: String,
+    status: StatusCode,
+    data: String,
+}
+
+impl MockRoute {
+    /// Parses the route from `path status data`, e.g.
+    /// `/users/* 200 {"id":"$arg_id"}`.
+    fn parse(value: &str) -> Option<Self> {
+        let mut arr = value.trim().splitn(3, ' ');
+        let path = arr.next()?.to_string();
+        let status = arr.next()?.parse::<u16>().ok()?;
+        Some(Self {
+            path,
+            status: StatusCode::from_u16(status).ok()?,
+            data: arr.next().unwrap_or_default().trim().to_string(),
+        })
+    }
+    fn matched(&self, path: &str) -> bool {
+        if self.path.is_empty() {
+            return true;
+        }
+        if let Some(prefix) = self.path.strip_suffix('*') {
+            return path.starts_with(prefix);
+        }
+        path == self.path
+    }
+}
+
+/// Returns a random value in `[0, 1)`.
+fn random_f64() -> f64 {
+    let mut buf = [0; 8];
+    if rand_bytes(&mut buf).is_err() {
+        return 0.0;
+    }
+    (u64::from_be_bytes(buf) >> 11) as f64 / (1u64 << 53) as f64
+}
+
 pub struct MockResponse {
     pub path: String,
     pub plugin_step: PluginStep,
     pub resp: HttpResponse,
+    // the responses of paths, they are matched before the default response
+    routes: Vec<MockRoute>,
+    // the response data of default, it supports variables
+    data: String,
+    // the default response is disabled if only routes are set
+    default_enabled: bool,
+    // the artificial latency before response
+    delay: Duration,
+    // the random latency in `[0, jitter)` added to delay
+    jitter: Duration,
+    // the probability(0-1) of error response
+    error_rate: f64,
+    error_resp: HttpResponse,
+    // only latency and error are injected, the request is proxied to upstream
+    passthrough: bool,
 }
 
 impl MockResponse {
@@ -39,6 +104,10 @@ impl MockResponse {
                 message: "Mock plugin should be executed at request or proxy upstream step".to_string(),
             });
         }
+        let new_error = |message: String| Error::Invalid {
+            category: PluginCategory::Mock.to_string(),
+            message,
+        };
 
         let path = get_str_conf(params, "path");
         let status = get_int_conf(params, "status") as u16;
@@ -47,7 +116,7 @@ impl MockResponse {
 
         let mut resp = HttpResponse {
             status: StatusCode::OK,
-            body: data.into(),
+            body: data.clone().into(),
             ..Default::default()
         };
         if status > 0 {
@@ -59,13 +128,105 @@ impl MockResponse {
                 resp.headers = Some(headers);
             }
         }
+        let mut routes = vec![];
+        for item in get_str_slice_conf(params, "routes").iter() {
+            let route = MockRoute::parse(item).ok_or_else(|| {
+                new_error(format!("Mock route({item}) is invalid"))
+            })?;
+            routes.push(route);
+        }
+        let get_duration = |key: &str| -> Result<Duration> {
+            let value = get_str_conf(params, key);
+            if value.is_empty() {
+                return Ok(Duration::ZERO);
+            }
+            parse_duration(&value).map_err(|e| new_error(e.to_string()))
+        };
+        let error_rate = get_float_conf(params, "error_rate");
+        if !(0.0..=1.0).contains(&error_rate) {
+            return Err(new_error(
+                "Mock error rate should be between 0 and 1".to_string(),
+            ));
+        }
+        let error_status = get_int_conf(params, "error_status") as u16;
+        let error_status = if error_status > 0 {
+            StatusCode::from_u16(error_status)
+                .unwrap_or(StatusCode::SERVICE_UNAVAILABLE)
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let mut error_data = get_str_conf(params, "error_data");
+        if error_data.is_empty() {
+            error_data = error_status
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_string();
+        }
+
+        let default_enabled = routes.is_empty()
+            || !path.is_empty()
+            || !data.is_empty()
+            || status > 0;
 
         Ok(MockResponse {
             resp,
             plugin_step: step,
             path,
+            routes,
+            data,
+            default_enabled,
+            delay: get_duration("delay")?,
+            jitter: get_duration("jitter")?,
+            error_rate,
+            error_resp: HttpResponse {
+                status: error_status,
+                body: error_data.into(),
+                ..Default::default()
+            },
+            passthrough: get_bool_conf(params, "passthrough"),
         })
     }
+    /// Gets the latency of request, it's the delay with random jitter.
+    fn get_latency(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.delay;
+        }
+        self.delay + self.jitter.mul_f64(random_f64())
+    }
+    /// Renders the response, the variables of data and header values
+    /// are replaced with the values of request.
+    fn render(
+        &self,
+        status: StatusCode,
+        data: &str,
+        session: &Session,
+        ctx: &State,
+    ) -> HttpResponse {
+        let req_header = session.req_header();
+        let mut resp = self.resp.clone();
+        resp.status = status;
+        resp.body = if has_variables(data) {
+            Bytes::from(format_variables(data, req_header, ctx))
+        } else {
+            Bytes::from(data.to_string())
+        };
+        if let Some(headers) = resp.headers.as_mut() {
+            for (_, value) in headers.iter_mut() {
+                let Ok(raw) = value.to_str() else {
+                    continue;
+                };
+                if !has_variables(raw) {
+                    continue;
+                }
+                if let Ok(formatted) = HeaderValue::from_str(&format_variables(
+                    raw, req_header, ctx,
+                )) {
+                    *value = formatted;
+                }
+            }
+        }
+        resp
+    }
 }
 
 #[async_trait]
@@ -79,21 +240,48 @@ impl Plugin for MockResponse {
         PluginCategory::Mock
     }
     #[inline]
-    /// Sends the mock data to client.
+    /// Sends the mock data to client, the latency and error are injected
+    /// before response.
     async fn handle_request(
         &self,
         step: PluginStep,
         session: &mut Session,
-        _ctx: &mut State,
+        ctx: &mut State,
     ) -> pingora::Result<Option<HttpResponse>> {
         if step != self.plugin_step {
             return Ok(None);
         }
-        if !self.path.is_empty() && session.req_header().uri.path() != self.path
-        {
+        let route = {
+            let path = session.req_header().uri.path();
+            let route = if self.passthrough {
+                None
+            } else {
+                self.routes.iter().find(|item| item.matched(path))
+            };
+            if route.is_none()
+                && ((!self.path.is_empty() && path != self.path)
+                    || (!self.passthrough && !self.default_enabled))
+            {
+                return Ok(None);
+            }
+            route
+        };
+        let latency = self.get_latency();
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        if self.error_rate > 0.0 && random_f64() < self.error_rate {
+            return Ok(Some(self.error_resp.clone()));
+        }
+        if self.passthrough {
             return Ok(None);
         }
-        Ok(Some(self.resp.clone()))
+        let resp = if let Some(route) = route {
+            self.render(route.status, &route.data, session, ctx)
+        } else {
+            self.render(self.resp.status, &self.data, session, ctx)
+        };
+        Ok(Some(resp))
     }
 }
 
@@ -106,6 +294,7 @@ mod tests {
     use http::StatusCode;
     use pingora::proxy::Session;
     use pretty_assertions::assert_eq;
+    use std::time::Duration;
     use tokio_test::io::Builder;
 
     #[test]
@@ -211,4 +400,143 @@ data = "{\"message\":\"Mock Service Unavailable\"}"
             .unwrap();
         assert_eq!(true, result.is_none());
     }
+
+    #[tokio::test]
+    async fn test_mock_routes() {
+        let result = MockResponse::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+routes = ["/users 200"]
+error_rate = 1.5
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin mock invalid, message: Mock error rate should be between 0 and 1",
+            result.err().unwrap().to_string()
+        );
+
+        let mock = MockResponse::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+headers = [
+    "Content-Type: application/json",
+    "X-Request-Method: $request_method",
+]
+routes = [
+    "/users/* 200 {\"id\":\"${arg_id}\",\"path\":\"$uri\"}",
+    "/health 204",
+]
+delay = "10ms"
+jitter = "5ms"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(2, mock.routes.len());
+        assert_eq!(false, mock.default_enabled);
+        let latency = mock.get_latency();
+        assert_eq!(true, latency >= Duration::from_millis(10));
+        assert_eq!(true, latency < Duration::from_millis(15));
+
+        let new_session = |uri: &str| {
+            let input_header = format!("GET {uri} HTTP/1.1\r\n\r\n");
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            Session::new_h1(Box::new(mock_io))
+        };
+        let mut session = new_session("/users/1?id=1");
+        session.read_request().await.unwrap();
+        let resp = mock
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(StatusCode::OK, resp.status);
+        assert_eq!(
+            Bytes::from_static(b"{\"id\":\"1\",\"path\":\"/users/1\"}"),
+            resp.body
+        );
+        assert_eq!(
+            r###"Some([("content-type", "application/json"), ("x-request-method", "GET")])"###,
+            format!("{:?}", resp.headers)
+        );
+
+        let mut session = new_session("/health");
+        session.read_request().await.unwrap();
+        let resp = mock
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, resp.status);
+
+        // the default response is disabled
+        let mut session = new_session("/orders");
+        session.read_request().await.unwrap();
+        let result = mock
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+
+        let mock = MockResponse::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+passthrough = true
+error_rate = 1
+error_status = 500
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut session = new_session("/orders");
+        session.read_request().await.unwrap();
+        let resp = mock
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, resp.status);
+        assert_eq!(Bytes::from_static(b"Internal Server Error"), resp.body);
+
+        let mock = MockResponse::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+passthrough = true
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut session = new_session("/orders");
+        session.read_request().await.unwrap();
+        let result = mock
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+    }
 }
@@ -18,32 +18,46 @@ use crate::state::State;
 use async_trait::async_trait;
 use bytes::Bytes;
 use once_cell::sync::OnceCell;
-use pingora::http::ResponseHeader;
+use pingora::http::{RequestHeader, ResponseHeader};
 use pingora::proxy::Session;
 use snafu::Snafu;
 use std::collections::HashMap;
 use std::str::FromStr;
 
+mod ab_test;
 mod admin;
 mod basic_auth;
+mod block_action;
+mod bot_detection;
 mod cache;
+mod coalesce;
 mod compression;
+mod condition;
+mod cookie_filter;
 mod cors;
 mod csrf;
 mod directory;
+mod honeypot;
 mod ip_restriction;
 mod jwt;
 mod key_auth;
 mod limit;
 mod mock;
 mod owasp_crs_plugin;
-mod wirefilter_plugin;
 mod ping;
 mod redirect;
 mod referer_restriction;
 mod request_id;
+mod request_sign;
 mod response_headers;
+mod security_headers;
 mod stats;
+mod wirefilter_plugin;
+
+pub(crate) use cache::{new_cache_snapshot_service, purge_cache_tag};
+pub use coalesce::CoalescingLeader;
+pub(crate) use directory::send_file;
+pub(crate) use request_sign::AwsSigV4;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -66,6 +80,11 @@ type Result<T, E = Error> = std::result::Result<T, E>;
 pub trait Plugin: Sync + Send {
     fn category(&self) -> PluginCategory;
     fn step(&self) -> String;
+    /// The priority of plugin, the plugin with higher priority
+    /// is executed first in location.
+    fn priority(&self) -> i64 {
+        0
+    }
     async fn handle_request(
         &self,
         _step: PluginStep,
@@ -83,6 +102,35 @@ pub trait Plugin: Sync + Send {
     ) -> pingora::Result<Option<Bytes>> {
         Ok(None)
     }
+    /// Handles the chunk of response body, it's called for each chunk,
+    /// the body can be modified or replaced.
+    fn handle_response_body(
+        &self,
+        _step: PluginStep,
+        _session: &mut Session,
+        _ctx: &mut State,
+        _body: &mut Option<Bytes>,
+        _end_of_stream: bool,
+    ) -> pingora::Result<()> {
+        Ok(())
+    }
+    /// Handles the request header before it's sent to upstream,
+    /// e.g. signs the request with the final headers.
+    async fn handle_upstream_request(
+        &self,
+        _step: PluginStep,
+        _session: &mut Session,
+        _ctx: &mut State,
+        _upstream_request: &mut RequestHeader,
+    ) -> pingora::Result<()> {
+        Ok(())
+    }
+    /// Returns the runtime state of plugin for inspection,
+    /// e.g. the counters of limiter, the `key` is used to inspect
+    /// the state of specified key(e.g. client ip).
+    fn runtime_state(&self, _key: &str) -> Option<serde_json::Value> {
+        None
+    }
 }
 
 pub fn get_builtin_proxy_plugins() -> Vec<(String, PluginConf)> {
@@ -130,6 +178,16 @@ remark = "Get stats of server"
                 r###"
 category = "request_id"
 remark = "Generate a request id for service"
+"###,
+            )
+            .unwrap(),
+        ),
+        (
+            "pingap:securityHeaders".to_string(),
+            toml::from_str::<PluginConf>(
+                r###"
+category = "security_headers"
+remark = "Security headers of basic preset"
 "###,
             )
             .unwrap(),
@@ -155,90 +213,85 @@ pub fn parse_plugins(confs: Vec<(String, PluginConf)>) -> Result<Plugins> {
             category.unwrap().as_str().unwrap_or_default(),
         )
         .unwrap_or_default();
-        match category {
-            PluginCategory::Limit => {
-                let l = limit::Limiter::new(conf)?;
-                plguins.insert(name, Box::new(l));
-            },
+        let plugin: Box<dyn Plugin> = match category {
+            PluginCategory::Limit => Box::new(limit::Limiter::new(conf)?),
             PluginCategory::Compression => {
-                let c = compression::Compression::new(conf)?;
-                plguins.insert(name, Box::new(c));
-            },
-            PluginCategory::Stats => {
-                let s = stats::Stats::new(conf)?;
-                plguins.insert(name, Box::new(s));
-            },
-            PluginCategory::Admin => {
-                let a = admin::AdminServe::new(conf)?;
-                plguins.insert(name, Box::new(a));
+                Box::new(compression::Compression::new(conf)?)
             },
+            PluginCategory::Stats => Box::new(stats::Stats::new(conf)?),
+            PluginCategory::Admin => Box::new(admin::AdminServe::new(conf)?),
             PluginCategory::Directory => {
-                let d = directory::Directory::new(conf)?;
-                plguins.insert(name, Box::new(d));
-            },
-            PluginCategory::Mock => {
-                let m = mock::MockResponse::new(conf)?;
-                plguins.insert(name, Box::new(m));
+                Box::new(directory::Directory::new(conf)?)
             },
+            PluginCategory::Mock => Box::new(mock::MockResponse::new(conf)?),
             PluginCategory::RequestId => {
-                let r = request_id::RequestId::new(conf)?;
-                plguins.insert(name, Box::new(r));
+                Box::new(request_id::RequestId::new(conf)?)
             },
             PluginCategory::IpRestriction => {
-                let l = ip_restriction::IpRestriction::new(conf)?;
-                plguins.insert(name, Box::new(l));
-            },
-            PluginCategory::KeyAuth => {
-                let k = key_auth::KeyAuth::new(conf)?;
-                plguins.insert(name, Box::new(k));
+                Box::new(ip_restriction::IpRestriction::new(conf)?)
             },
+            PluginCategory::KeyAuth => Box::new(key_auth::KeyAuth::new(conf)?),
             PluginCategory::BasicAuth => {
-                let b = basic_auth::BasicAuth::new(conf)?;
-                plguins.insert(name, Box::new(b));
+                Box::new(basic_auth::BasicAuth::new(conf)?)
             },
-            PluginCategory::Cache => {
-                let c = cache::Cache::new(conf)?;
-                plguins.insert(name, Box::new(c));
+            PluginCategory::BotDetection => {
+                Box::new(bot_detection::BotDetection::new(conf)?)
             },
+            PluginCategory::Cache => Box::new(cache::Cache::new(conf)?),
             PluginCategory::Redirect => {
-                let r = redirect::Redirect::new(conf)?;
-                plguins.insert(name, Box::new(r));
-            },
-            PluginCategory::Ping => {
-                let p = ping::Ping::new(conf)?;
-                plguins.insert(name, Box::new(p));
+                Box::new(redirect::Redirect::new(conf)?)
             },
+            PluginCategory::Ping => Box::new(ping::Ping::new(conf)?),
             PluginCategory::ResponseHeaders => {
-                let r = response_headers::ResponseHeaders::new(conf)?;
-                plguins.insert(name, Box::new(r));
+                Box::new(response_headers::ResponseHeaders::new(conf)?)
+            },
+            PluginCategory::RequestSign => {
+                Box::new(request_sign::RequestSign::new(conf)?)
+            },
+            PluginCategory::SecurityHeaders => {
+                Box::new(security_headers::SecurityHeaders::new(conf)?)
             },
             PluginCategory::RefererRestriction => {
-                let r = referer_restriction::RefererRestriction::new(conf)?;
-                plguins.insert(name, Box::new(r));
+                Box::new(referer_restriction::RefererRestriction::new(conf)?)
             },
-            PluginCategory::Csrf => {
-                let c = csrf::Csrf::new(conf)?;
-                plguins.insert(name, Box::new(c));
+            PluginCategory::Csrf => Box::new(csrf::Csrf::new(conf)?),
+            PluginCategory::Jwt => Box::new(jwt::JwtAuth::new(conf)?),
+            PluginCategory::Cors => Box::new(cors::Cors::new(conf)?),
+            PluginCategory::OwaspCrsPlugin => {
+                Box::new(owasp_crs_plugin::OwaspCrsPlugin::new(conf)?)
             },
-            PluginCategory::Jwt => {
-                let auth = jwt::JwtAuth::new(conf)?;
-                plguins.insert(name.clone(), Box::new(auth));
+            PluginCategory::Honeypot => {
+                Box::new(honeypot::Honeypot::new(conf)?)
             },
-            PluginCategory::Cors => {
-                let cors = cors::Cors::new(conf)?;
-                plguins.insert(name.clone(), Box::new(cors));
+            PluginCategory::CookieFilter => {
+                Box::new(cookie_filter::CookieFilter::new(conf)?)
             },
-            PluginCategory::OwaspCrsPlugin => {
-                let owasp_crs_plugin =
-                    owasp_crs_plugin::OwaspCrsPlugin::new(conf)?;
-                plguins.insert(name.clone(), Box::new(owasp_crs_plugin));
+            PluginCategory::AbTest => Box::new(ab_test::AbTest::new(conf)?),
+            PluginCategory::Coalesce => {
+                Box::new(coalesce::Coalesce::new(conf)?)
             },
             PluginCategory::WirefilterPlugin => {
-                let wirefilter_plugin =
-                wirefilter_plugin::WirefilterPlugin::new(conf)?;
-                plguins.insert(name.clone(), Box::new(wirefilter_plugin));
+                Box::new(wirefilter_plugin::WirefilterPlugin::new(conf)?)
             },
         };
+        let priority = get_int_conf(conf, "priority");
+        let condition = get_str_conf(conf, "condition");
+        let plugin: Box<dyn Plugin> = if priority != 0 || !condition.is_empty()
+        {
+            let condition = if condition.is_empty() {
+                None
+            } else {
+                Some(condition::PluginCondition::new(&condition)?)
+            };
+            Box::new(condition::ConditionalPlugin {
+                priority,
+                condition,
+                plugin,
+            })
+        } else {
+            plugin
+        };
+        plguins.insert(name, plugin);
     }
 
     Ok(plguins)
@@ -275,6 +328,17 @@ pub(crate) fn get_int_conf(value: &PluginConf, key: &str) -> i64 {
     }
 }
 
+pub(crate) fn get_float_conf(value: &PluginConf, key: &str) -> f64 {
+    if let Some(value) = value.get(key) {
+        value
+            .as_float()
+            .or_else(|| value.as_integer().map(|v| v as f64))
+            .unwrap_or_default()
+    } else {
+        0.0
+    }
+}
+
 pub(crate) fn get_bool_conf(value: &PluginConf, key: &str) -> bool {
     if let Some(value) = value.get(key) {
         value.as_bool().unwrap_or_default()
@@ -311,6 +375,30 @@ category = "mock"
 path = "/mock"
 status = 999
 data = "abc"
+"###,
+            )
+            .unwrap(),
+        ),
+        (
+            "test:mock_condition".to_string(),
+            toml::from_str::<PluginConf>(
+                r###"
+category = "mock"
+path = "/condition"
+status = 999
+data = "abc"
+priority = 10
+condition = "method == POST"
+"###,
+            )
+            .unwrap(),
+        ),
+        (
+            "test:basic_auth".to_string(),
+            toml::from_str::<PluginConf>(
+                r###"
+category = "basic_auth"
+authorizations = ["YWRtaW46MTIzMTIz"]
 "###,
             )
             .unwrap(),
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::block_action::Blocker;
 use super::{
     get_step_conf, get_str_conf, get_str_slice_conf, Error, Plugin, Result,
 };
@@ -31,6 +32,7 @@ pub struct RefererRestriction {
     prefix_referer_list: Vec<String>,
     restriction_category: String,
     forbidden_resp: HttpResponse,
+    blocker: Blocker,
 }
 
 impl TryFrom<&PluginConf> for RefererRestriction {
@@ -62,6 +64,7 @@ impl TryFrom<&PluginConf> for RefererRestriction {
                 body: Bytes::from(message),
                 ..Default::default()
             },
+            blocker: Blocker::new(PluginCategory::RefererRestriction, value)?,
         };
         if ![PluginStep::Request, PluginStep::ProxyUpstream]
             .contains(&params.plugin_step)
@@ -101,7 +104,7 @@ impl Plugin for RefererRestriction {
         &self,
         step: PluginStep,
         session: &mut Session,
-        _ctx: &mut State,
+        ctx: &mut State,
     ) -> pingora::Result<Option<HttpResponse>> {
         if step != self.plugin_step {
             return Ok(None);
@@ -129,7 +132,10 @@ impl Plugin for RefererRestriction {
             found
         };
         if !allow {
-            return Ok(Some(self.forbidden_resp.clone()));
+            return self
+                .blocker
+                .block(session, ctx, self.forbidden_resp.clone())
+                .await;
         }
         return Ok(None);
     }
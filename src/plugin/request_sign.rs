@@ -0,0 +1,529 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{get_str_conf, get_str_slice_conf, Error, Plugin, Result};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::state::State;
+use crate::util;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use http::header::HeaderName;
+use pingora::http::RequestHeader;
+use pingora::proxy::Session;
+use std::str::FromStr;
+use tracing::debug;
+
+const AWS_SIGV4_ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const AWS_UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+// the sha256 of empty payload
+const AWS_EMPTY_PAYLOAD_SHA256: &str =
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// The signer of aws signature version 4, it's also used by
+/// the s3 origin of upstream.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AwsSigV4 {
+    access_key_id: String,
+    secret_access_key: String,
+    // the session token of temporary credentials(e.g. assumed role)
+    session_token: String,
+    region: String,
+    service: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct HmacSign {
+    secret: String,
+    key_id: String,
+    header: HeaderName,
+    timestamp_header: HeaderName,
+    // the values of headers are appended to the string to sign
+    signed_headers: Vec<HeaderName>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SignAlgorithm {
+    AwsSigV4(AwsSigV4),
+    Hmac(HmacSign),
+}
+
+pub struct RequestSign {
+    plugin_step: PluginStep,
+    // the host of upstream request is set before signing
+    host: String,
+    algorithm: SignAlgorithm,
+}
+
+/// Encodes the value as the uri encoding of aws signature,
+/// only the unreserved characters are not encoded.
+fn aws_uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut result = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => result.push(b as char),
+            b'-' | b'_' | b'.' | b'~' => result.push(b as char),
+            b'/' if !encode_slash => result.push('/'),
+            _ => result.push_str(&format!("%{b:02X}")),
+        }
+    }
+    result
+}
+
+fn decode_uri_component(value: &str) -> String {
+    urlencoding::decode(value)
+        .map(|value| value.to_string())
+        .unwrap_or_else(|_| value.to_string())
+}
+
+/// Gets the canonical query of aws signature, the query parameters
+/// are encoded and sorted by name.
+fn get_aws_canonical_query(query: &str) -> String {
+    let mut params: Vec<(String, String)> = query
+        .split('&')
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (name, value) = item.split_once('=').unwrap_or((item, ""));
+            (
+                aws_uri_encode(&decode_uri_component(name), true),
+                aws_uri_encode(&decode_uri_component(value), true),
+            )
+        })
+        .collect();
+    params.sort();
+    params
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+impl AwsSigV4 {
+    pub(crate) fn new(
+        access_key_id: &str,
+        secret_access_key: &str,
+        session_token: &str,
+        region: &str,
+        service: &str,
+    ) -> Self {
+        Self {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            session_token: session_token.to_string(),
+            region: region.to_string(),
+            service: service.to_string(),
+        }
+    }
+    fn get_signing_key(&self, date: &str) -> [u8; 32] {
+        let key = format!("AWS4{}", self.secret_access_key);
+        let key = hmac_sha256::HMAC::mac(date.as_bytes(), key.as_bytes());
+        let key = hmac_sha256::HMAC::mac(self.region.as_bytes(), key);
+        let key = hmac_sha256::HMAC::mac(self.service.as_bytes(), key);
+        hmac_sha256::HMAC::mac(b"aws4_request", key)
+    }
+    /// Signs the request with aws signature version 4, the payload is
+    /// unsigned because the body is not read before the request is sent.
+    pub(crate) fn sign(
+        &self,
+        req: &mut RequestHeader,
+        now: DateTime<Utc>,
+    ) -> pingora::Result<()> {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let has_body = req.headers.contains_key(http::header::CONTENT_LENGTH)
+            || req.headers.contains_key(http::header::TRANSFER_ENCODING);
+        let payload_hash = if has_body {
+            AWS_UNSIGNED_PAYLOAD
+        } else {
+            AWS_EMPTY_PAYLOAD_SHA256
+        };
+        req.insert_header("x-amz-date", &amz_date)?;
+        req.insert_header("x-amz-content-sha256", payload_hash)?;
+        if !self.session_token.is_empty() {
+            req.insert_header("x-amz-security-token", &self.session_token)?;
+        }
+
+        let mut headers: Vec<(String, String)> = req
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.as_str();
+                name == "host" || name.starts_with("x-amz-")
+            })
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes())
+                        .trim()
+                        .to_string(),
+                )
+            })
+            .collect();
+        // the host of uri is used if the header is not set(http2)
+        if !headers.iter().any(|(name, _)| name == "host") {
+            if let Some(host) = req.uri.host() {
+                headers.push(("host".to_string(), host.to_string()));
+            }
+        }
+        headers.sort();
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        // the path of s3 is only encoded once
+        let path = req.uri.path();
+        let canonical_uri = if self.service == "s3" {
+            aws_uri_encode(&decode_uri_component(path), false)
+        } else {
+            aws_uri_encode(path, false)
+        };
+        let canonical_request = [
+            req.method.as_str(),
+            &canonical_uri,
+            &get_aws_canonical_query(req.uri.query().unwrap_or_default()),
+            &canonical_headers,
+            &signed_headers,
+            payload_hash,
+        ]
+        .join("\n");
+
+        let scope =
+            format!("{date}/{}/{}/aws4_request", self.region, self.service);
+        let string_to_sign = [
+            AWS_SIGV4_ALGORITHM,
+            &amz_date,
+            &scope,
+            &hex::encode(hmac_sha256::Hash::hash(canonical_request.as_bytes())),
+        ]
+        .join("\n");
+        let signature = hex::encode(hmac_sha256::HMAC::mac(
+            string_to_sign.as_bytes(),
+            self.get_signing_key(&date),
+        ));
+        req.insert_header(
+            http::header::AUTHORIZATION,
+            format!(
+                "{AWS_SIGV4_ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                self.access_key_id
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+impl HmacSign {
+    /// Signs the request with hmac-sha256, the string to sign is
+    /// `method\npath?query\ntimestamp` with the values of signed headers.
+    fn sign(
+        &self,
+        req: &mut RequestHeader,
+        now: DateTime<Utc>,
+    ) -> pingora::Result<()> {
+        let timestamp = now.timestamp().to_string();
+        req.insert_header(self.timestamp_header.clone(), &timestamp)?;
+        let path = req
+            .uri
+            .path_and_query()
+            .map(|item| item.as_str())
+            .unwrap_or("/");
+        let mut values =
+            vec![req.method.to_string(), path.to_string(), timestamp];
+        for name in self.signed_headers.iter() {
+            let value = req
+                .headers
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()))
+                .unwrap_or_default();
+            values.push(value.trim().to_string());
+        }
+        let signature = hex::encode(hmac_sha256::HMAC::mac(
+            values.join("\n").as_bytes(),
+            self.secret.as_bytes(),
+        ));
+        let value = if self.key_id.is_empty() {
+            signature
+        } else {
+            format!("{}:{signature}", self.key_id)
+        };
+        req.insert_header(self.header.clone(), value)?;
+        Ok(())
+    }
+}
+
+impl TryFrom<&PluginConf> for RequestSign {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let new_error = |message: String| Error::Invalid {
+            category: PluginCategory::RequestSign.to_string(),
+            message,
+        };
+        let get_conf_or_env = |key: &str, env: &str| {
+            let value = get_str_conf(value, key);
+            if value.is_empty() {
+                std::env::var(env).unwrap_or_default()
+            } else {
+                value
+            }
+        };
+        let parse_header_name = |value: &str| {
+            HeaderName::from_str(value).map_err(|e| new_error(e.to_string()))
+        };
+        let algorithm = match get_str_conf(value, "algorithm").as_str() {
+            "aws_sigv4" => {
+                // the credentials of environment are used if not set
+                let mut aws = AwsSigV4 {
+                    access_key_id: get_conf_or_env(
+                        "access_key_id",
+                        "AWS_ACCESS_KEY_ID",
+                    ),
+                    secret_access_key: get_conf_or_env(
+                        "secret_access_key",
+                        "AWS_SECRET_ACCESS_KEY",
+                    ),
+                    session_token: get_conf_or_env(
+                        "session_token",
+                        "AWS_SESSION_TOKEN",
+                    ),
+                    region: get_conf_or_env("region", "AWS_REGION"),
+                    service: get_str_conf(value, "service"),
+                };
+                if aws.access_key_id.is_empty()
+                    || aws.secret_access_key.is_empty()
+                {
+                    return Err(new_error(
+                        "access key id and secret access key can't be empty"
+                            .to_string(),
+                    ));
+                }
+                if aws.region.is_empty() {
+                    return Err(new_error("region can't be empty".to_string()));
+                }
+                if aws.service.is_empty() {
+                    aws.service = "s3".to_string();
+                }
+                SignAlgorithm::AwsSigV4(aws)
+            },
+            "" | "hmac" => {
+                let secret = get_str_conf(value, "secret");
+                if secret.is_empty() {
+                    return Err(new_error("secret can't be empty".to_string()));
+                }
+                let mut header = get_str_conf(value, "header");
+                if header.is_empty() {
+                    header = "X-Signature".to_string();
+                }
+                let mut timestamp_header =
+                    get_str_conf(value, "timestamp_header");
+                if timestamp_header.is_empty() {
+                    timestamp_header = "X-Timestamp".to_string();
+                }
+                let mut signed_headers = vec![];
+                for item in get_str_slice_conf(value, "signed_headers").iter() {
+                    signed_headers.push(parse_header_name(item)?);
+                }
+                SignAlgorithm::Hmac(HmacSign {
+                    secret,
+                    key_id: get_str_conf(value, "key_id"),
+                    header: parse_header_name(&header)?,
+                    timestamp_header: parse_header_name(&timestamp_header)?,
+                    signed_headers,
+                })
+            },
+            algorithm => {
+                return Err(new_error(format!(
+                    "algorithm {algorithm} is not supported"
+                )))
+            },
+        };
+
+        Ok(Self {
+            plugin_step: PluginStep::ProxyUpstream,
+            host: get_str_conf(value, "host"),
+            algorithm,
+        })
+    }
+}
+
+impl RequestSign {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new request sign plugin");
+        Self::try_from(params)
+    }
+    fn sign(
+        &self,
+        req: &mut RequestHeader,
+        now: DateTime<Utc>,
+    ) -> pingora::Result<()> {
+        if !self.host.is_empty() {
+            req.insert_header(http::header::HOST, &self.host)?;
+        }
+        match &self.algorithm {
+            SignAlgorithm::AwsSigV4(aws) => aws.sign(req, now),
+            SignAlgorithm::Hmac(hmac) => hmac.sign(req, now),
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for RequestSign {
+    #[inline]
+    fn step(&self) -> String {
+        self.plugin_step.to_string()
+    }
+    #[inline]
+    fn category(&self) -> PluginCategory {
+        PluginCategory::RequestSign
+    }
+    #[inline]
+    async fn handle_upstream_request(
+        &self,
+        step: PluginStep,
+        _session: &mut Session,
+        ctx: &mut State,
+        upstream_request: &mut RequestHeader,
+    ) -> pingora::Result<()> {
+        if step != self.plugin_step {
+            return Ok(());
+        }
+        let now = DateTime::from_timestamp(util::now().as_secs() as i64, 0)
+            .unwrap_or_default();
+        self.sign(upstream_request, now)?;
+        ctx.add_journal(|| "request_sign:signed".to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aws_uri_encode, get_aws_canonical_query, RequestSign};
+    use crate::config::PluginConf;
+    use crate::plugin::Plugin;
+    use chrono::DateTime;
+    use pingora::http::RequestHeader;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_aws_canonical_query() {
+        assert_eq!("a%2Fb", aws_uri_encode("a/b", true));
+        assert_eq!("/a/b%20c", aws_uri_encode("/a/b c", false));
+        assert_eq!(
+            "list-type=2&prefix=a%2Fb&x=",
+            get_aws_canonical_query("prefix=a/b&x&list-type=2")
+        );
+    }
+
+    #[test]
+    fn test_request_sign_params() {
+        let result = RequestSign::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+algorithm = "aws_sigv4"
+access_key_id = "AKIDEXAMPLE"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin request_sign invalid, message: access key id and secret access key can't be empty",
+            result.err().unwrap().to_string()
+        );
+
+        let result = RequestSign::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+algorithm = "md5"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin request_sign invalid, message: algorithm md5 is not supported",
+            result.err().unwrap().to_string()
+        );
+
+        let sign = RequestSign::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+secret = "123123"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("request_sign", sign.category().to_string());
+        assert_eq!("proxy_upstream", sign.step());
+    }
+
+    #[test]
+    fn test_aws_sigv4_sign() {
+        // the example of aws signature version 4 test suite(get-vanilla)
+        let sign = RequestSign::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+algorithm = "aws_sigv4"
+access_key_id = "AKIDEXAMPLE"
+secret_access_key = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY"
+region = "us-east-1"
+service = "service"
+host = "example.amazonaws.com"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        let now = DateTime::from_timestamp(1440938160, 0).unwrap();
+        sign.sign(&mut req, now).unwrap();
+        assert_eq!("20150830T123600Z", req.headers["x-amz-date"]);
+        assert_eq!(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature=726c5c4879a6b4ccbbd3b24edbd6b8826d34f87450fbbf4e85546fc7ba9c1642",
+            req.headers["authorization"]
+        );
+    }
+
+    #[test]
+    fn test_hmac_sign() {
+        let sign = RequestSign::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+secret = "123123"
+key_id = "pingap"
+signed_headers = ["Content-Type"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut req =
+            RequestHeader::build("POST", b"/api/users?id=1", None).unwrap();
+        req.insert_header("Content-Type", "application/json")
+            .unwrap();
+        let now = DateTime::from_timestamp(1700000000, 0).unwrap();
+        sign.sign(&mut req, now).unwrap();
+        assert_eq!("1700000000", req.headers["x-timestamp"]);
+        let signature = hex::encode(hmac_sha256::HMAC::mac(
+            b"POST\n/api/users?id=1\n1700000000\napplication/json",
+            b"123123",
+        ));
+        assert_eq!(
+            format!("pingap:{signature}"),
+            req.headers["x-signature"].to_str().unwrap()
+        );
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    get_bool_conf, get_str_conf, get_str_slice_conf, Error, Plugin, Result,
+};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::state::State;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::header::HeaderName;
+use http::HeaderValue;
+use pingora::http::ResponseHeader;
+use pingora::proxy::Session;
+use std::str::FromStr;
+use tracing::debug;
+
+// (config key, header name, basic preset, strict preset)
+const SECURITY_HEADER_PRESETS: [(&str, &str, &str, &str); 6] = [
+    (
+        "hsts",
+        "strict-transport-security",
+        "max-age=31536000; includeSubDomains",
+        "max-age=63072000; includeSubDomains; preload",
+    ),
+    (
+        "content_security_policy",
+        "content-security-policy",
+        "",
+        "default-src 'self'; frame-ancestors 'none'; object-src 'none'",
+    ),
+    ("x_frame_options", "x-frame-options", "SAMEORIGIN", "DENY"),
+    (
+        "x_content_type_options",
+        "x-content-type-options",
+        "nosniff",
+        "nosniff",
+    ),
+    (
+        "referrer_policy",
+        "referrer-policy",
+        "strict-origin-when-cross-origin",
+        "no-referrer",
+    ),
+    (
+        "permissions_policy",
+        "permissions-policy",
+        "",
+        "camera=(), microphone=(), geolocation=(), payment=()",
+    ),
+];
+
+pub struct SecurityHeaders {
+    plugin_step: PluginStep,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    // override the header if the upstream response has set it
+    override_headers: bool,
+    remove_headers: Vec<HeaderName>,
+}
+
+impl TryFrom<&PluginConf> for SecurityHeaders {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let new_error = |message: String| Error::Invalid {
+            category: PluginCategory::SecurityHeaders.to_string(),
+            message,
+        };
+        let strict = match get_str_conf(value, "preset").as_str() {
+            "" | "basic" => false,
+            "strict" => true,
+            preset => {
+                return Err(new_error(format!("preset {preset} is invalid")))
+            },
+        };
+        // the headers of preset can be disabled by name, e.g. `hsts`
+        let disabled_headers = get_str_slice_conf(value, "disabled_headers");
+
+        let mut headers = vec![];
+        for (key, name, basic, strict_value) in SECURITY_HEADER_PRESETS {
+            if disabled_headers.iter().any(|item| item == key) {
+                continue;
+            }
+            let mut header_value = get_str_conf(value, key);
+            if header_value.is_empty() {
+                header_value =
+                    if strict { strict_value } else { basic }.to_string();
+            }
+            if header_value.is_empty() {
+                continue;
+            }
+            let header_value = HeaderValue::from_str(&header_value)
+                .map_err(|e| new_error(e.to_string()))?;
+            headers.push((HeaderName::from_static(name), header_value));
+        }
+
+        let mut remove_headers = vec![];
+        for item in get_str_slice_conf(value, "remove_headers").iter() {
+            let item = HeaderName::from_str(item)
+                .map_err(|e| new_error(e.to_string()))?;
+            remove_headers.push(item);
+        }
+
+        Ok(Self {
+            plugin_step: PluginStep::Response,
+            headers,
+            override_headers: get_bool_conf(value, "override"),
+            remove_headers,
+        })
+    }
+}
+
+impl SecurityHeaders {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new security headers plugin");
+        Self::try_from(params)
+    }
+}
+
+#[async_trait]
+impl Plugin for SecurityHeaders {
+    #[inline]
+    fn step(&self) -> String {
+        self.plugin_step.to_string()
+    }
+    #[inline]
+    fn category(&self) -> PluginCategory {
+        PluginCategory::SecurityHeaders
+    }
+    #[inline]
+    async fn handle_response(
+        &self,
+        step: PluginStep,
+        _session: &mut Session,
+        _ctx: &mut State,
+        upstream_response: &mut ResponseHeader,
+    ) -> pingora::Result<Option<Bytes>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        // ingore error
+        for name in &self.remove_headers {
+            let _ = upstream_response.remove_header(name);
+        }
+        for (name, value) in &self.headers {
+            if !self.override_headers
+                && upstream_response.headers.contains_key(name)
+            {
+                continue;
+            }
+            let _ = upstream_response.insert_header(name, value);
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SecurityHeaders;
+    use crate::state::State;
+    use crate::{config::PluginConf, config::PluginStep, plugin::Plugin};
+    use pingora::http::ResponseHeader;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_security_headers_params() {
+        let params = SecurityHeaders::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+x_frame_options = "DENY"
+disabled_headers = ["hsts"]
+remove_headers = ["Server"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("response", params.plugin_step.to_string());
+        assert_eq!(
+            r#"[("x-frame-options", "DENY"), ("x-content-type-options", "nosniff"), ("referrer-policy", "strict-origin-when-cross-origin")]"#,
+            format!("{:?}", params.headers)
+        );
+        assert_eq!(r#"["server"]"#, format!("{:?}", params.remove_headers));
+
+        let params = SecurityHeaders::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+preset = "strict"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(6, params.headers.len());
+
+        let result = SecurityHeaders::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+preset = "none"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin security_headers invalid, message: preset none is invalid",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_security_headers() {
+        let security_headers = SecurityHeaders::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+remove_headers = ["Server"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("security_headers", security_headers.category().to_string());
+        assert_eq!("response", security_headers.step().to_string());
+
+        let headers = ["Accept-Encoding: gzip"].join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+
+        let mut upstream_response =
+            ResponseHeader::build_no_case(200, None).unwrap();
+        upstream_response.insert_header("Server", "nginx").unwrap();
+        upstream_response
+            .insert_header("X-Frame-Options", "DENY")
+            .unwrap();
+
+        security_headers
+            .handle_response(
+                PluginStep::Response,
+                &mut session,
+                &mut State::default(),
+                &mut upstream_response,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            r###"ResponseHeader { base: Parts { status: 200, version: HTTP/1.1, headers: {"x-frame-options": "DENY", "strict-transport-security": "max-age=31536000; includeSubDomains", "x-content-type-options": "nosniff", "referrer-policy": "strict-origin-when-cross-origin"} }, header_name_map: None, reason_phrase: None }"###,
+            format!("{upstream_response:?}")
+        );
+    }
+}
@@ -12,16 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{get_step_conf, get_str_conf, Error, Plugin, Result};
+use super::block_action::get_tarpit_processing;
+use super::{
+    get_step_conf, get_str_conf, get_str_slice_conf, Error, Plugin, Result,
+};
 use crate::config::{PluginCategory, PluginConf, PluginStep};
-use crate::http_extra::HttpResponse;
+use crate::http_extra::{HttpResponse, HTTP_HEADER_NO_STORE};
+use crate::proxy::{
+    get_event_loop_delay, get_locations_stats, get_rejected_requests,
+    get_shed_requests, get_stuck_requests, get_upstreams_stats, UpstreamStats,
+};
+use crate::service::get_prometheus_metrics;
 use crate::state::{get_hostname, get_start_time, State};
 use crate::util;
 use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
 use bytesize::ByteSize;
+use http::{HeaderValue, StatusCode};
 use memory_stats::memory_stats;
 use pingora::proxy::Session;
 use serde::Serialize;
+use std::collections::HashMap;
 use std::time::Duration;
 use tracing::debug;
 
@@ -39,10 +51,24 @@ struct ServerStats {
     version: String,
     start_time: u64,
     uptime: String,
+    upstreams: HashMap<String, UpstreamStats>,
+    stuck_requests: usize,
+    shed_requests: u64,
+    // the processing tarpit requests of blocked requests
+    tarpit_requests: u32,
+    rejected_requests: HashMap<String, u64>,
+    // the requests aborted by client of locations
+    client_aborted_requests: HashMap<String, u64>,
+    event_loop_delay: u64,
 }
 pub struct Stats {
     path: String,
     plugin_step: PluginStep,
+    // basic and bearer authorizations, e.g. `Basic MTIz` or `Bearer abc`
+    authorizations: Vec<Vec<u8>>,
+    // the ip allow list, it only checks the remote address of connection
+    ip_rules: util::IpRules,
+    unauthorized_resp: HttpResponse,
 }
 
 impl TryFrom<&PluginConf> for Stats {
@@ -50,9 +76,34 @@ impl TryFrom<&PluginConf> for Stats {
     fn try_from(value: &PluginConf) -> Result<Self> {
         let step = get_step_conf(value);
 
+        let mut authorizations = vec![];
+        for item in get_str_slice_conf(value, "authorizations").iter() {
+            let _ = STANDARD.decode(item).map_err(|e| Error::Base64Decode {
+                category: PluginCategory::Stats.to_string(),
+                source: e,
+            })?;
+            authorizations.push(format!("Basic {item}").as_bytes().to_vec());
+        }
+        for item in get_str_slice_conf(value, "bearer_tokens").iter() {
+            authorizations.push(format!("Bearer {item}").as_bytes().to_vec());
+        }
+
         let params = Self {
             plugin_step: step,
             path: get_str_conf(value, "path"),
+            authorizations,
+            ip_rules: util::IpRules::new(&get_str_slice_conf(value, "ip_list")),
+            unauthorized_resp: HttpResponse {
+                status: StatusCode::UNAUTHORIZED,
+                headers: Some(vec![(
+                    http::header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(
+                        r###"Basic realm="Pingap stats""###,
+                    ),
+                )]),
+                body: Bytes::from_static(b"Invalid authorization"),
+                ..Default::default()
+            },
         };
         if ![PluginStep::Request, PluginStep::ProxyUpstream]
             .contains(&params.plugin_step)
@@ -71,6 +122,36 @@ impl Stats {
         debug!(params = params.to_string(), "new stats plugin");
         Self::try_from(params)
     }
+    /// Checks whether the request is allowed to access stats,
+    /// returns the response of forbidden or unauthorized if not.
+    fn check_access(
+        &self,
+        session: &Session,
+        ctx: &State,
+    ) -> Option<HttpResponse> {
+        if !self.ip_rules.is_empty() {
+            // x-forwarded-for can be forged, so use the remote address
+            let remote_addr = ctx
+                .remote_addr
+                .clone()
+                .or_else(|| util::get_remote_addr(session))
+                .unwrap_or_default();
+            if !self.ip_rules.is_match(&remote_addr).unwrap_or_default() {
+                return Some(HttpResponse {
+                    status: StatusCode::FORBIDDEN,
+                    body: Bytes::from_static(b"Request is forbidden"),
+                    ..Default::default()
+                });
+            }
+        }
+        if !self.authorizations.is_empty() {
+            let value = session.get_header_bytes(http::header::AUTHORIZATION);
+            if !self.authorizations.iter().any(|item| item == value) {
+                return Some(self.unauthorized_resp.clone());
+            }
+        }
+        None
+    }
 }
 
 #[async_trait]
@@ -94,6 +175,28 @@ impl Plugin for Stats {
             return Ok(None);
         }
         if session.req_header().uri.path() == self.path {
+            if let Some(resp) = self.check_access(session, ctx) {
+                return Ok(Some(resp));
+            }
+            // prometheus scrapes with `params: { format: [prometheus] }`
+            if util::get_query_value(session.req_header(), "format")
+                == Some("prometheus")
+            {
+                return Ok(Some(HttpResponse {
+                    status: StatusCode::OK,
+                    body: get_prometheus_metrics().into(),
+                    headers: Some(vec![
+                        (
+                            http::header::CONTENT_TYPE,
+                            HeaderValue::from_static(
+                                "text/plain; version=0.0.4; charset=utf-8",
+                            ),
+                        ),
+                        HTTP_HEADER_NO_STORE.clone(),
+                    ]),
+                    ..Default::default()
+                }));
+            }
             let mut physical_mem = 0;
             if let Some(value) = memory_stats() {
                 physical_mem = value.physical_mem;
@@ -112,6 +215,19 @@ impl Plugin for Stats {
                 version: VERSION.to_string(),
                 start_time: get_start_time(),
                 uptime: uptime.to_string(),
+                upstreams: get_upstreams_stats(),
+                stuck_requests: get_stuck_requests(),
+                shed_requests: get_shed_requests(),
+                tarpit_requests: get_tarpit_processing(),
+                rejected_requests: get_rejected_requests(),
+                client_aborted_requests: get_locations_stats()
+                    .into_iter()
+                    .filter(|(_, (_, _, client_aborted))| *client_aborted > 0)
+                    .map(|(name, (_, _, client_aborted))| {
+                        (name, client_aborted)
+                    })
+                    .collect(),
+                event_loop_delay: get_event_loop_delay(),
             })
             .map_err(|e| util::new_internal_error(500, e.to_string()))?;
             return Ok(Some(resp));
@@ -125,6 +241,7 @@ mod tests {
     use super::Stats;
     use crate::state::State;
     use crate::{config::PluginConf, config::PluginStep, plugin::Plugin};
+    use http::StatusCode;
     use pingora::proxy::Session;
     use pretty_assertions::assert_eq;
     use tokio_test::io::Builder;
@@ -204,4 +321,118 @@ mod tests {
             .unwrap();
         assert_eq!(true, result.is_some());
     }
+
+    #[tokio::test]
+    async fn test_stats_access() {
+        let stats = Stats::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+            path = "/stats"
+            authorizations = ["MTIz"]
+            bearer_tokens = ["pingap"]
+            ip_list = ["127.0.0.1", "192.168.0.0/16"]
+        "###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(2, stats.authorizations.len());
+
+        let new_session = |authorization: &str| {
+            let input_header = format!(
+                "GET /stats HTTP/1.1\r\nAuthorization: {authorization}\r\n\r\n"
+            );
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            Session::new_h1(Box::new(mock_io))
+        };
+
+        // remote address is not allowed
+        let mut session = new_session("Bearer pingap");
+        session.read_request().await.unwrap();
+        let result = stats
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State {
+                    remote_addr: Some("10.0.0.1".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.unwrap().status);
+
+        // invalid authorization
+        let mut session = new_session("Bearer abc");
+        session.read_request().await.unwrap();
+        let result = stats
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State {
+                    remote_addr: Some("192.168.1.1".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, result.unwrap().status);
+
+        for authorization in ["Bearer pingap", "Basic MTIz"] {
+            let mut session = new_session(authorization);
+            session.read_request().await.unwrap();
+            let result = stats
+                .handle_request(
+                    PluginStep::Request,
+                    &mut session,
+                    &mut State {
+                        remote_addr: Some("127.0.0.1".to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!(StatusCode::OK, result.unwrap().status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stats_prometheus() {
+        let stats = Stats::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+            path = "/stats"
+        "###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let input_header = "GET /stats?format=prometheus HTTP/1.1\r\n\r\n";
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+
+        let resp = stats
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(StatusCode::OK, resp.status);
+        assert_eq!(
+            "text/plain; version=0.0.4; charset=utf-8",
+            resp.headers.unwrap()[0].1.to_str().unwrap()
+        );
+        let body = std::str::from_utf8(&resp.body).unwrap();
+        assert_eq!(
+            true,
+            body.contains(
+                "# TYPE pingap_requests_series_overflow_total counter\n"
+            )
+        );
+    }
 }
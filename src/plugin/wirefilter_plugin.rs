@@ -1,3 +1,4 @@
+use super::block_action::Blocker;
 use super::{get_step_conf, get_str_conf, get_str_slice_conf, Error, Plugin, Result};
 use crate::config::{PluginCategory, PluginConf, PluginStep};
 use crate::http_extra::HttpResponse;
@@ -8,13 +9,25 @@ use http::header::USER_AGENT;
 use http::StatusCode;
 use pingora::proxy::Session;
 use regex::Replacer;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tracing::{debug, info};
 use wirefilter::{ExecutionContext, Type, Scheme};
 
 pub struct WirefilterPlugin {
     plugin_step: PluginStep,
     restriction_expression_list: Vec<String>,
+    // the matched count of each expression
+    matched_counts: Vec<AtomicU64>,
     forbidden_resp: HttpResponse,
+    blocker: Blocker,
+}
+
+/// The runtime state of expression.
+#[derive(Serialize, Debug)]
+struct ExpressionState {
+    expression: String,
+    matched: u64,
 }
 
 impl TryFrom<&PluginConf> for WirefilterPlugin {
@@ -46,12 +59,14 @@ impl TryFrom<&PluginConf> for WirefilterPlugin {
 
         let params = Self {
             plugin_step: step,
+            matched_counts: exps.iter().map(|_| AtomicU64::new(0)).collect(),
             restriction_expression_list: exps,
             forbidden_resp: HttpResponse {
                 status: StatusCode::FORBIDDEN,
                 body: Bytes::from(message),
                 ..Default::default()
             },
+            blocker: Blocker::new(PluginCategory::WirefilterPlugin, value)?,
         };
         if ![PluginStep::Request, PluginStep::ProxyUpstream]
             .contains(&params.plugin_step)
@@ -102,12 +117,24 @@ impl Plugin for WirefilterPlugin {
     fn category(&self) -> PluginCategory {
         PluginCategory::WirefilterPlugin
     }
+    fn runtime_state(&self, _key: &str) -> Option<serde_json::Value> {
+        let expressions: Vec<ExpressionState> = self
+            .restriction_expression_list
+            .iter()
+            .zip(self.matched_counts.iter())
+            .map(|(expression, matched)| ExpressionState {
+                expression: expression.to_string(),
+                matched: matched.load(Ordering::Relaxed),
+            })
+            .collect();
+        serde_json::to_value(expressions).ok()
+    }
     #[inline]
     async fn handle_request(
         &self,
         step: PluginStep,
         session: &mut Session,
-        _ctx: &mut State,
+        ctx: &mut State,
     ) -> pingora::Result<Option<HttpResponse>> {
         if step != self.plugin_step {
             return Ok(None);
@@ -117,7 +144,7 @@ impl Plugin for WirefilterPlugin {
         let headers = &req_header.headers;
         let mut allow = true;
     
-        for item in self.restriction_expression_list.iter() {
+        for (index, item) in self.restriction_expression_list.iter().enumerate() {
             let expression = item.as_str();
             
             // Parse a Wireshark-like expression into an AST.
@@ -147,7 +174,10 @@ impl Plugin for WirefilterPlugin {
             let matche_filter = filter.execute(&ctx).unwrap();
             println!("Filter matches: {:?}", matche_filter); // false
             info!(matche_filter, "client request restricted if filter find expression restriction in request data ");
-            if matche_filter {allow = false}
+            if matche_filter {
+                allow = false;
+                self.matched_counts[index].fetch_add(1, Ordering::Relaxed);
+            }
         } 
 
         let mut message = String::from("");
@@ -175,7 +205,7 @@ impl Plugin for WirefilterPlugin {
 
         //let allow = false;
         if !allow {
-            return Ok(Some(forbidden_resp));
+            return self.blocker.block(session, ctx, forbidden_resp).await;
         }
         return Ok(None);
     }
@@ -0,0 +1,83 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use ahash::AHashMap;
+use crossbeam_channel::Sender;
+use once_cell::sync::Lazy;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+use tracing::{error, info};
+
+// the writers of access log files, the key is the resolved file path.
+// the writer is shared by locations and kept after config is reloaded
+static ACCESS_LOG_WRITERS: Lazy<Mutex<AHashMap<String, Sender<String>>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+/// Gets the writer of access log file, the file is opened once and
+/// the logs are written by a separate thread.
+pub fn get_access_log_writer(file: &str) -> std::io::Result<Sender<String>> {
+    let file = util::resolve_path(file);
+    let mut writers = ACCESS_LOG_WRITERS.lock().map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+    })?;
+    if let Some(sender) = writers.get(&file) {
+        return Ok(sender.clone());
+    }
+    let mut f = OpenOptions::new().create(true).append(true).open(&file)?;
+    let (sender, receiver) = crossbeam_channel::bounded::<String>(1024);
+    writers.insert(file.clone(), sender.clone());
+    info!(file, "init access log file");
+    std::thread::spawn(move || {
+        for line in receiver.iter() {
+            if let Err(e) = writeln!(f, "{line}") {
+                error!(error = e.to_string(), file, "write access log fail");
+            }
+        }
+    });
+    Ok(sender)
+}
+
+#[inline]
+pub fn write_access_log(sender: &Sender<String>, line: String) {
+    // drop the log if the channel is full
+    let _ = sender.try_send(line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_access_log_writer, write_access_log};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn test_access_log_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("access.log");
+        let file = file.to_string_lossy().to_string();
+        let writer = get_access_log_writer(&file).unwrap();
+        // the writer of same file is reused
+        let other = get_access_log_writer(&file).unwrap();
+        assert_eq!(true, writer.same_channel(&other));
+
+        write_access_log(&writer, "GET /ping 200".to_string());
+        write_access_log(&other, "GET /health 200".to_string());
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(
+            "GET /ping 200\nGET /health 200\n",
+            std::fs::read_to_string(&file).unwrap()
+        );
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use ahash::AHashMap;
+use bytes::{Bytes, BytesMut};
+use http::{header, Method, StatusCode};
+use once_cell::sync::Lazy;
+use pingora::http::{RequestHeader, ResponseHeader};
+use std::sync::Mutex;
+
+// the max size of response body kept for always online
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+// the max count of responses kept for always online
+const MAX_ENTRIES: usize = 1024;
+
+struct OnlineResponse {
+    header: ResponseHeader,
+    body: Bytes,
+    created_at: u64,
+}
+
+static ONLINE_RESPONSES: Lazy<Mutex<AHashMap<String, OnlineResponse>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+/// The record of successful response, it's kept as the last successful
+/// response of request after the whole body is received.
+pub struct AlwaysOnlineRecord {
+    key: String,
+    header: ResponseHeader,
+    body: BytesMut,
+    oversize: bool,
+}
+
+/// Gets the key of always online response, it's the location and
+/// the host, path and query of request.
+pub fn get_always_online_key(
+    location: &str,
+    req_header: &RequestHeader,
+) -> String {
+    let host = util::get_host(req_header).unwrap_or_default();
+    let uri = req_header
+        .uri
+        .path_and_query()
+        .map(|item| item.as_str())
+        .unwrap_or_else(|| req_header.uri.path());
+    format!("{location}:{host}{uri}")
+}
+
+/// Creates the record of response, only the `200` response of `GET`
+/// request without `Set-Cookie` and `Content-Encoding` is kept.
+pub fn new_always_online_record(
+    key: String,
+    req_header: &RequestHeader,
+    resp: &ResponseHeader,
+) -> Option<Box<AlwaysOnlineRecord>> {
+    if req_header.method != Method::GET
+        || resp.status != StatusCode::OK
+        || resp.headers.contains_key(header::SET_COOKIE)
+        || resp.headers.contains_key(header::CONTENT_ENCODING)
+    {
+        return None;
+    }
+    Some(Box::new(AlwaysOnlineRecord {
+        key,
+        header: resp.clone(),
+        body: BytesMut::new(),
+        oversize: false,
+    }))
+}
+
+impl AlwaysOnlineRecord {
+    /// Appends the data of response body, the record is dropped
+    /// if the body is larger than the limit.
+    pub fn add_body(&mut self, data: &[u8]) {
+        if self.oversize {
+            return;
+        }
+        if self.body.len() + data.len() > MAX_BODY_SIZE {
+            self.oversize = true;
+            self.body = BytesMut::new();
+            return;
+        }
+        self.body.extend_from_slice(data);
+    }
+    /// Keeps the response as the last successful response,
+    /// the oldest one is removed if there are too many responses.
+    pub fn finish(self) {
+        if self.oversize {
+            return;
+        }
+        let Ok(mut responses) = ONLINE_RESPONSES.lock() else {
+            return;
+        };
+        if responses.len() >= MAX_ENTRIES && !responses.contains_key(&self.key)
+        {
+            let oldest = responses
+                .iter()
+                .min_by_key(|(_, item)| item.created_at)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                responses.remove(&key);
+            }
+        }
+        let mut resp = self.header;
+        resp.remove_header(&header::TRANSFER_ENCODING);
+        let _ = resp
+            .insert_header(header::CONTENT_LENGTH, self.body.len().to_string());
+        responses.insert(
+            self.key,
+            OnlineResponse {
+                header: resp,
+                body: self.body.freeze(),
+                created_at: util::now().as_secs(),
+            },
+        );
+    }
+}
+
+/// Gets the last successful response of the key.
+pub fn get_always_online_response(
+    key: &str,
+) -> Option<(ResponseHeader, Bytes)> {
+    let responses = ONLINE_RESPONSES.lock().ok()?;
+    responses
+        .get(key)
+        .map(|item| (item.header.clone(), item.body.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_always_online_key, get_always_online_response,
+        new_always_online_record, MAX_BODY_SIZE,
+    };
+    use pingora::http::{RequestHeader, ResponseHeader};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_always_online() {
+        let mut req =
+            RequestHeader::build("GET", b"/api/users?page=1", None).unwrap();
+        req.insert_header("Host", "pingap.io").unwrap();
+        let key = get_always_online_key("lo", &req);
+        assert_eq!("lo:pingap.io/api/users?page=1", key);
+
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("Content-Type", "application/json")
+            .unwrap();
+
+        let mut record =
+            new_always_online_record(key.clone(), &req, &resp).unwrap();
+        record.add_body(b"{\"name\":");
+        record.add_body(b"\"pingap\"}");
+        record.finish();
+
+        let (header, body) = get_always_online_response(&key).unwrap();
+        assert_eq!(200, header.status.as_u16());
+        assert_eq!(
+            "17",
+            header
+                .headers
+                .get("Content-Length")
+                .unwrap()
+                .to_str()
+                .unwrap()
+        );
+        assert_eq!(b"{\"name\":\"pingap\"}".to_vec(), body.to_vec());
+
+        // the oversize response is not kept
+        let key = "lo:pingap.io/large".to_string();
+        let mut record =
+            new_always_online_record(key.clone(), &req, &resp).unwrap();
+        record.add_body(&vec![0; MAX_BODY_SIZE + 1]);
+        record.finish();
+        assert_eq!(true, get_always_online_response(&key).is_none());
+
+        // only the successful response is kept
+        let resp = ResponseHeader::build(500, None).unwrap();
+        assert_eq!(
+            true,
+            new_always_online_record(key.clone(), &req, &resp).is_none()
+        );
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("Set-Cookie", "uid=1").unwrap();
+        assert_eq!(
+            true,
+            new_always_online_record(key.clone(), &req, &resp).is_none()
+        );
+        let req = RequestHeader::build("POST", b"/api/users", None).unwrap();
+        let resp = ResponseHeader::build(200, None).unwrap();
+        assert_eq!(true, new_always_online_record(key, &req, &resp).is_none());
+    }
+}
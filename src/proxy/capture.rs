@@ -0,0 +1,538 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::state::State;
+use crate::util;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::BytesMut;
+use http::{header, HeaderMap, Version};
+use once_cell::sync::Lazy;
+use pingora::proxy::Session;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::info;
+use utoipa::ToSchema;
+
+// the default max size of captured body
+const DEFAULT_BODY_LIMIT: usize = 4 * 1024;
+const MAX_BODY_LIMIT: usize = 1024 * 1024;
+const MAX_CAPTURE_COUNT: usize = 1000;
+
+/// The params of capture, the requests matching all the filters are captured.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, ToSchema)]
+pub struct CaptureParams {
+    // the count of requests to capture
+    pub count: usize,
+    // the path prefix of request
+    pub path: Option<String>,
+    // the host of request
+    pub host: Option<String>,
+    // the client ip of request
+    pub client_ip: Option<String>,
+    // the max size of captured request and response body
+    pub body_limit: Option<usize>,
+}
+
+impl CaptureParams {
+    fn matched(&self, session: &Session) -> bool {
+        let header = session.req_header();
+        if let Some(path) = &self.path {
+            if !header.uri.path().starts_with(path.as_str()) {
+                return false;
+            }
+        }
+        if let Some(host) = &self.host {
+            if util::get_host(header).unwrap_or_default() != host {
+                return false;
+            }
+        }
+        if let Some(client_ip) = &self.client_ip {
+            if &util::get_client_ip(session) != client_ip {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, ToSchema)]
+pub struct CaptureStatus {
+    pub capturing: bool,
+    pub remaining: usize,
+    pub captured: usize,
+    pub params: Option<CaptureParams>,
+}
+
+#[derive(Default)]
+struct Capture {
+    params: Option<CaptureParams>,
+    remaining: usize,
+    entries: Vec<HarEntry>,
+}
+
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+static CAPTURE: Lazy<Mutex<Capture>> =
+    Lazy::new(|| Mutex::new(Capture::default()));
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct HarNameValue {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarPostData {
+    mime_type: String,
+    text: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    http_version: String,
+    headers: Vec<HarNameValue>,
+    query_string: Vec<HarNameValue>,
+    cookies: Vec<HarNameValue>,
+    headers_size: i64,
+    body_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<HarPostData>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarContent {
+    size: usize,
+    mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarResponse {
+    status: u16,
+    status_text: String,
+    http_version: String,
+    headers: Vec<HarNameValue>,
+    cookies: Vec<HarNameValue>,
+    content: HarContent,
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: usize,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct HarCache {}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct HarTimings {
+    send: i64,
+    wait: u64,
+    receive: i64,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarEntry {
+    started_date_time: String,
+    time: u64,
+    request: HarRequest,
+    response: HarResponse,
+    cache: HarCache,
+    timings: HarTimings,
+    #[serde(
+        rename = "serverIPAddress",
+        skip_serializing_if = "String::is_empty"
+    )]
+    server_ip_address: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    comment: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct HarCreator {
+    name: String,
+    version: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct HarLog {
+    version: String,
+    creator: HarCreator,
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct Har {
+    log: HarLog,
+}
+
+/// The record of captured request, the body is truncated by the limit.
+pub struct CaptureRecord {
+    started_date_time: String,
+    body_limit: usize,
+    request: HarRequest,
+    request_body: BytesMut,
+    response_body: BytesMut,
+    response_body_size: usize,
+}
+
+fn format_http_version(version: Version) -> String {
+    format!("{version:?}")
+}
+
+fn to_har_headers(headers: &HeaderMap) -> Vec<HarNameValue> {
+    headers
+        .iter()
+        .map(|(name, value)| HarNameValue {
+            name: name.to_string(),
+            value: String::from_utf8_lossy(value.as_bytes()).to_string(),
+        })
+        .collect()
+}
+
+fn get_mime_type(headers: &HeaderMap) -> String {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Converts the body to text, the body which is not utf8 is base64 encoded.
+/// The utf8 char may be split by the limit, so the incomplete tail is dropped.
+fn to_har_text(buf: &[u8]) -> (String, Option<String>) {
+    match std::str::from_utf8(buf) {
+        Ok(text) => (text.to_string(), None),
+        Err(e) if e.error_len().is_none() => (
+            String::from_utf8_lossy(&buf[..e.valid_up_to()]).to_string(),
+            None,
+        ),
+        Err(_) => (STANDARD.encode(buf), Some("base64".to_string())),
+    }
+}
+
+fn append_body(buf: &mut BytesMut, data: &[u8], limit: usize) {
+    let size = data.len().min(limit.saturating_sub(buf.len()));
+    if size > 0 {
+        buf.extend_from_slice(&data[..size]);
+    }
+}
+
+impl CaptureRecord {
+    fn new(session: &Session, body_limit: usize) -> Self {
+        let header = session.req_header();
+        let host = util::get_host(header).unwrap_or("localhost");
+        let scheme = if session
+            .digest()
+            .map(|digest| digest.ssl_digest.is_some())
+            .unwrap_or_default()
+        {
+            "https"
+        } else {
+            "http"
+        };
+        let query_string = header
+            .uri
+            .query()
+            .map(|query| {
+                url::form_urlencoded::parse(query.as_bytes())
+                    .map(|(name, value)| HarNameValue {
+                        name: name.to_string(),
+                        value: value.to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let path = header
+            .uri
+            .path_and_query()
+            .map(|item| item.as_str())
+            .unwrap_or("/");
+        CaptureRecord {
+            started_date_time: chrono::Local::now().to_rfc3339(),
+            body_limit,
+            request: HarRequest {
+                method: header.method.to_string(),
+                url: format!("{scheme}://{host}{path}"),
+                http_version: format_http_version(header.version),
+                headers: to_har_headers(&header.headers),
+                query_string,
+                headers_size: -1,
+                ..Default::default()
+            },
+            request_body: BytesMut::new(),
+            response_body: BytesMut::new(),
+            response_body_size: 0,
+        }
+    }
+    /// Appends the request body, the data over the limit is dropped.
+    pub fn add_request_body(&mut self, data: &[u8]) {
+        append_body(&mut self.request_body, data, self.body_limit);
+    }
+    /// Appends the response body, the data over the limit is dropped.
+    pub fn add_response_body(&mut self, data: &[u8]) {
+        self.response_body_size += data.len();
+        append_body(&mut self.response_body, data, self.body_limit);
+    }
+    fn into_har_entry(self, session: &Session, ctx: &State) -> HarEntry {
+        let mut request = self.request;
+        request.body_size = ctx.payload_size;
+        if !self.request_body.is_empty() {
+            request.post_data = Some(HarPostData {
+                mime_type: get_mime_type(&session.req_header().headers),
+                text: String::from_utf8_lossy(&self.request_body).to_string(),
+            });
+        }
+        let mut truncated = ctx.payload_size > self.request_body.len();
+        let mut response = HarResponse {
+            headers_size: -1,
+            ..Default::default()
+        };
+        if let Some(header) = session.response_written() {
+            let (text, encoding) = to_har_text(&self.response_body);
+            response.status = header.status.as_u16();
+            response.status_text = header
+                .status
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_string();
+            response.http_version = format_http_version(header.version);
+            response.headers = to_har_headers(&header.headers);
+            response.redirect_url = header
+                .headers
+                .get(header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            response.body_size = self.response_body_size;
+            response.content = HarContent {
+                size: self.response_body_size,
+                mime_type: get_mime_type(&header.headers),
+                text: Some(text),
+                encoding,
+            };
+            truncated =
+                truncated || self.response_body_size > self.response_body.len();
+        } else if let Some(status) = ctx.status {
+            response.status = status.as_u16();
+        }
+        let time =
+            (util::now().as_millis() as u64).saturating_sub(ctx.created_at);
+        let mut comment = ctx
+            .location
+            .as_ref()
+            .map(|location| format!("location:{}", location.name))
+            .unwrap_or_default();
+        if truncated {
+            if !comment.is_empty() {
+                comment.push(' ');
+            }
+            comment.push_str("body truncated");
+        }
+        HarEntry {
+            started_date_time: self.started_date_time,
+            time,
+            request,
+            response,
+            timings: HarTimings {
+                send: -1,
+                wait: ctx.upstream_response_time.unwrap_or(time),
+                receive: -1,
+            },
+            server_ip_address: ctx.upstream_address.clone(),
+            comment,
+            ..Default::default()
+        }
+    }
+}
+
+/// Starts a new capture, the previous captured entries are cleared.
+pub fn start_capture(mut params: CaptureParams) {
+    params.count = params.count.min(MAX_CAPTURE_COUNT);
+    params.body_limit = Some(
+        params
+            .body_limit
+            .unwrap_or(DEFAULT_BODY_LIMIT)
+            .min(MAX_BODY_LIMIT),
+    );
+    info!(
+        count = params.count,
+        path = ?params.path,
+        host = ?params.host,
+        client_ip = ?params.client_ip,
+        "start request capture"
+    );
+    if let Ok(mut capture) = CAPTURE.lock() {
+        *capture = Capture {
+            remaining: params.count,
+            params: Some(params),
+            entries: vec![],
+        };
+        CAPTURING.store(capture.remaining > 0, Ordering::Relaxed);
+    }
+}
+
+/// Stops the capture and clears the captured entries.
+pub fn stop_capture() {
+    CAPTURING.store(false, Ordering::Relaxed);
+    if let Ok(mut capture) = CAPTURE.lock() {
+        *capture = Capture::default();
+    }
+}
+
+/// Gets the status of capture.
+pub fn get_capture_status() -> CaptureStatus {
+    let Ok(capture) = CAPTURE.lock() else {
+        return CaptureStatus::default();
+    };
+    CaptureStatus {
+        capturing: CAPTURING.load(Ordering::Relaxed),
+        remaining: capture.remaining,
+        captured: capture.entries.len(),
+        params: capture.params.clone(),
+    }
+}
+
+/// Gets the captured entries as har json.
+pub fn get_capture_har() -> serde_json::Result<Vec<u8>> {
+    let entries = CAPTURE
+        .lock()
+        .map(|capture| capture.entries.clone())
+        .unwrap_or_default();
+    serde_json::to_vec(&Har {
+        log: HarLog {
+            version: "1.2".to_string(),
+            creator: HarCreator {
+                name: "pingap".to_string(),
+                version: util::get_pkg_version().to_string(),
+            },
+            entries,
+        },
+    })
+}
+
+/// Creates the capture record if the request matches the capture filters,
+/// the capture is disabled after the count of requests are captured.
+pub fn new_capture_record(session: &Session) -> Option<Box<CaptureRecord>> {
+    if !CAPTURING.load(Ordering::Relaxed) {
+        return None;
+    }
+    let mut capture = CAPTURE.lock().ok()?;
+    let params = capture.params.as_ref()?;
+    if capture.remaining == 0 || !params.matched(session) {
+        return None;
+    }
+    let body_limit = params.body_limit.unwrap_or(DEFAULT_BODY_LIMIT);
+    capture.remaining -= 1;
+    if capture.remaining == 0 {
+        CAPTURING.store(false, Ordering::Relaxed);
+        info!("request capture is done");
+    }
+    Some(Box::new(CaptureRecord::new(session, body_limit)))
+}
+
+/// Adds the captured request to the har entries.
+pub fn finish_capture_record(session: &Session, ctx: &mut State) {
+    let Some(record) = ctx.capture.take() else {
+        return;
+    };
+    let entry = record.into_har_entry(session, ctx);
+    if let Ok(mut capture) = CAPTURE.lock() {
+        // the capture has been stopped
+        if capture.params.is_some() {
+            capture.entries.push(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append_body, to_har_text, CaptureParams, CaptureRecord};
+    use bytes::BytesMut;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_to_har_text() {
+        assert_eq!(
+            r#"("pingap", None)"#,
+            format!("{:?}", to_har_text(b"pingap"))
+        );
+        // the incomplete utf8 char is dropped
+        let buf = "你好".as_bytes();
+        assert_eq!(r#"("你", None)"#, format!("{:?}", to_har_text(&buf[..4])));
+        assert_eq!(
+            r#"("/wAB", Some("base64"))"#,
+            format!("{:?}", to_har_text(&[0xff, 0, 1]))
+        );
+
+        let mut buf = BytesMut::new();
+        append_body(&mut buf, b"pingap", 4);
+        append_body(&mut buf, b"proxy", 4);
+        assert_eq!(b"ping", &buf[..]);
+    }
+
+    #[tokio::test]
+    async fn test_capture_record() {
+        let headers =
+            ["Host: github.com", "Content-Type: application/json"].join("\r\n");
+        let input_header = format!(
+            "POST /vicanso/pingap?size=1&name=a%20b HTTP/1.1\r\n{headers}\r\n\r\n"
+        );
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+
+        let params = CaptureParams {
+            count: 1,
+            path: Some("/vicanso".to_string()),
+            host: Some("github.com".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(true, params.matched(&session));
+        let params = CaptureParams {
+            count: 1,
+            host: Some("pingap.io".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(false, params.matched(&session));
+
+        let mut record = CaptureRecord::new(&session, 4);
+        record.add_request_body(b"{\"a\":1}");
+        record.add_response_body(b"pingap");
+        assert_eq!("POST", record.request.method);
+        assert_eq!(
+            "http://github.com/vicanso/pingap?size=1&name=a%20b",
+            record.request.url
+        );
+        assert_eq!("HTTP/1.1", record.request.http_version);
+        assert_eq!(
+            r#"[HarNameValue { name: "size", value: "1" }, HarNameValue { name: "name", value: "a b" }]"#,
+            format!("{:?}", record.request.query_string)
+        );
+        assert_eq!(b"{\"a\"", &record.request_body[..]);
+        assert_eq!(b"ping", &record.response_body[..]);
+        assert_eq!(6, record.response_body_size);
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::http_extra::{format_variables, has_variables};
+use crate::state::State;
+use crate::util;
+use pingora::http::ResponseHeader;
+use pingora::proxy::Session;
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+// the hop by hop headers of backend response are not forwarded
+const CGI_REMOVED_RESPONSE_HEADERS: [&str; 3] =
+    ["connection", "keep-alive", "transfer-encoding"];
+
+/// Parses the custom params of upstream, the format is `NAME value`.
+pub(super) fn parse_params(
+    values: &Option<Vec<String>>,
+) -> Vec<(String, String)> {
+    values
+        .clone()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            let (name, value) = item.trim().split_once(' ')?;
+            Some((name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Gets the common params of cgi(request, server, client and http
+/// headers), the script params are set by the protocol.
+pub(super) fn get_cgi_params(
+    session: &Session,
+    ctx: &State,
+) -> Vec<(String, String)> {
+    let req_header = session.req_header();
+    let path = req_header.uri.path();
+    let mut params = vec![
+        ("GATEWAY_INTERFACE", "CGI/1.1".to_string()),
+        (
+            "SERVER_SOFTWARE",
+            format!("pingap/{}", util::get_pkg_version()),
+        ),
+        ("REQUEST_METHOD", req_header.method.to_string()),
+        (
+            "REQUEST_URI",
+            req_header
+                .uri
+                .path_and_query()
+                .map(|item| item.to_string())
+                .unwrap_or_else(|| path.to_string()),
+        ),
+        (
+            "QUERY_STRING",
+            req_header.uri.query().unwrap_or_default().to_string(),
+        ),
+        ("SERVER_PROTOCOL", format!("{:?}", req_header.version)),
+        (
+            "SERVER_NAME",
+            util::get_host(req_header).unwrap_or_default().to_string(),
+        ),
+    ];
+    if let Some(addr) = session.client_addr().and_then(|addr| addr.as_inet()) {
+        params.push(("REMOTE_ADDR", addr.ip().to_string()));
+        params.push(("REMOTE_PORT", addr.port().to_string()));
+    }
+    if let Some(addr) = session.server_addr().and_then(|addr| addr.as_inet()) {
+        params.push(("SERVER_ADDR", addr.ip().to_string()));
+        params.push(("SERVER_PORT", addr.port().to_string()));
+    }
+    if ctx.tls_version.is_some() {
+        params.push(("HTTPS", "on".to_string()));
+    }
+    let mut params: Vec<(String, String)> = params
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    for (name, value) in req_header.headers.iter() {
+        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+        let name = if name == http::header::CONTENT_TYPE {
+            "CONTENT_TYPE".to_string()
+        } else if name == http::header::CONTENT_LENGTH {
+            "CONTENT_LENGTH".to_string()
+        } else if name.as_str() == "proxy" {
+            // the proxy header is ignored(httpoxy)
+            continue;
+        } else {
+            format!("HTTP_{}", name.as_str().to_uppercase().replace('-', "_"))
+        };
+        params.push((name, value));
+    }
+    params
+}
+
+/// Sets the param, the param with the same name is replaced.
+pub(super) fn set_param(
+    params: &mut Vec<(String, String)>,
+    name: &str,
+    value: String,
+) {
+    params.retain(|(item, _)| item != name);
+    params.push((name.to_string(), value));
+}
+
+/// Sets the custom params, the value supports variables and the
+/// default params with the same name are overridden.
+pub(super) fn set_custom_params(
+    params: &mut Vec<(String, String)>,
+    custom_params: &[(String, String)],
+    session: &Session,
+    ctx: &State,
+) {
+    for (name, value) in custom_params.iter() {
+        let value = if has_variables(value) {
+            format_variables(value, session.req_header(), ctx)
+        } else {
+            value.clone()
+        };
+        set_param(params, name, value);
+    }
+}
+
+/// Parses the headers of response, both the cgi headers(`Status: 200 OK`)
+/// and the http status line(`HTTP/1.1 200 OK`) are supported.
+/// Returns the response header and the size of header data,
+/// `None` means the header is not complete.
+pub(super) fn parse_cgi_headers(
+    data: &[u8],
+) -> Option<pingora::Result<(ResponseHeader, usize)>> {
+    let (end, size) = data
+        .windows(4)
+        .position(|item| item == b"\r\n\r\n")
+        .map(|index| (index, index + 4))
+        .or_else(|| {
+            data.windows(2)
+                .position(|item| item == b"\n\n")
+                .map(|index| (index, index + 2))
+        })?;
+    let mut status = None;
+    let mut headers = vec![];
+    for (index, line) in std::str::from_utf8(&data[..end])
+        .unwrap_or_default()
+        .lines()
+        .enumerate()
+    {
+        if index == 0 && line.starts_with("HTTP/") {
+            status = line
+                .split_whitespace()
+                .nth(1)
+                .and_then(|code| code.parse::<u16>().ok());
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.trim();
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("status") {
+            // the format is `404 Not Found`
+            status = value
+                .split_whitespace()
+                .next()
+                .and_then(|code| code.parse::<u16>().ok());
+        } else if !CGI_REMOVED_RESPONSE_HEADERS
+            .iter()
+            .any(|item| name.eq_ignore_ascii_case(item))
+        {
+            headers.push((name.to_string(), value.to_string()));
+        }
+    }
+    // the redirect response of cgi without status
+    let status = status.unwrap_or_else(|| {
+        if headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("location"))
+        {
+            302
+        } else {
+            200
+        }
+    });
+    Some(new_cgi_response(status, headers).map(|resp| (resp, size)))
+}
+
+fn new_cgi_response(
+    status: u16,
+    headers: Vec<(String, String)>,
+) -> pingora::Result<ResponseHeader> {
+    let mut resp = ResponseHeader::build(status, Some(headers.len()))?;
+    for (name, value) in headers {
+        resp.append_header(name, value)?;
+    }
+    Ok(resp)
+}
+
+/// Runs the io future with timeout, `None` means no timeout.
+pub(super) async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    message: &str,
+    fut: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    let Some(timeout) = timeout else {
+        return fut.await;
+    };
+    tokio::time::timeout(timeout, fut)
+        .await
+        .unwrap_or_else(|_| {
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, message))
+        })
+}
+
+/// Connects to the tcp address of backend.
+pub(super) async fn connect(
+    addr: &str,
+    timeout: Option<Duration>,
+) -> std::io::Result<TcpStream> {
+    with_timeout(timeout, "connect timeout", TcpStream::connect(addr)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cgi_headers, parse_params, set_param};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_cgi_params() {
+        let mut params = parse_params(&Some(vec![
+            "APP_ENV production".to_string(),
+            "INVALID".to_string(),
+        ]));
+        assert_eq!(
+            vec![("APP_ENV".to_string(), "production".to_string())],
+            params
+        );
+        set_param(&mut params, "APP_ENV", "test".to_string());
+        assert_eq!(vec![("APP_ENV".to_string(), "test".to_string())], params);
+    }
+
+    #[test]
+    fn test_parse_cgi_headers() {
+        assert_eq!(
+            true,
+            parse_cgi_headers(b"Content-Type: text/html").is_none()
+        );
+
+        let (resp, size) = parse_cgi_headers(
+            b"Status: 404 Not Found\r\nContent-Type: text/html\r\n\r\nnot found",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(404, resp.status.as_u16());
+        assert_eq!("text/html", resp.headers["content-type"]);
+        assert_eq!(50, size);
+
+        let (resp, size) = parse_cgi_headers(
+            b"Location: /login\nSet-Cookie: a=1\nSet-Cookie: b=2\n\n",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(302, resp.status.as_u16());
+        assert_eq!(2, resp.headers.get_all("set-cookie").iter().count());
+        assert_eq!(50, size);
+
+        let (resp, size) = parse_cgi_headers(
+            b"HTTP/1.1 201 Created\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(201, resp.status.as_u16());
+        assert_eq!(1, resp.headers.len());
+        assert_eq!("2", resp.headers["content-length"]);
+        assert_eq!(62, size);
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::location::{restore_locations, snapshot_locations};
+use super::server::{restore_server_locations, snapshot_server_locations};
+use super::upstream::{restore_upstreams, snapshot_upstreams};
+use super::{
+    try_init_locations, try_init_server_locations, try_init_upstreams,
+};
+use crate::config::{
+    PingapConf, CATEGORY_LOCATION, CATEGORY_PLUGIN, CATEGORY_SCHEDULE,
+    CATEGORY_SERVER, CATEGORY_UPSTREAM,
+};
+use crate::util;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+// the order of components which are applied at runtime,
+// the dependencies are applied before the components which use them
+const COMPONENT_ORDER: [&str; 4] = [
+    CATEGORY_UPSTREAM,
+    CATEGORY_LOCATION,
+    CATEGORY_PLUGIN,
+    CATEGORY_SERVER,
+];
+
+/// The plan of applying config at runtime.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigApplyPlan {
+    pub upstream: bool,
+    pub location: bool,
+    // only the locations of servers can be reloaded
+    pub server_location: bool,
+    // the schedules are read from current config when they run
+    pub schedule: bool,
+    // the components which can't be reloaded, e.g. the plugins are
+    // initialized once and the listeners of servers can't be changed
+    pub require_upgrade: Vec<String>,
+}
+
+/// The result of applying config, all reloaded components are rolled back
+/// if any component fails.
+#[derive(Debug, Default, Clone, Serialize, PartialEq, ToSchema)]
+pub struct ConfigApplyReport {
+    // the time(seconds) of applying
+    pub applied_at: u64,
+    pub reloaded: Vec<String>,
+    pub require_upgrade: Vec<String>,
+    pub failed: Option<String>,
+    pub error: Option<String>,
+    pub rolled_back: Vec<String>,
+}
+
+static CONFIG_APPLY_REPORT: Lazy<Mutex<Option<ConfigApplyReport>>> =
+    Lazy::new(|| Mutex::new(None));
+
+fn get_component_index(category: &str) -> usize {
+    COMPONENT_ORDER
+        .iter()
+        .position(|item| *item == category)
+        .unwrap_or(COMPONENT_ORDER.len())
+}
+
+impl ConfigApplyPlan {
+    /// Creates the plan from the diff of current and new config.
+    pub fn new(current: &PingapConf, conf: &PingapConf) -> Self {
+        let mut current = current.clone();
+        let mut plan = ConfigApplyPlan::default();
+        // the locations of servers are reloaded,
+        // so they are excluded from the diff of servers
+        for (name, server) in conf.servers.iter() {
+            if let Some(old) = current.servers.get_mut(name) {
+                if server.locations != old.locations {
+                    plan.server_location = true;
+                    old.locations.clone_from(&server.locations);
+                }
+            }
+        }
+        let (mut category_list, _) = current.diff(conf);
+        category_list.sort_by(|a, b| {
+            (get_component_index(a), a).cmp(&(get_component_index(b), b))
+        });
+        category_list.dedup();
+        for category in category_list {
+            match category.as_str() {
+                CATEGORY_UPSTREAM => plan.upstream = true,
+                CATEGORY_LOCATION => plan.location = true,
+                CATEGORY_SCHEDULE => plan.schedule = true,
+                _ => plan.require_upgrade.push(category),
+            };
+        }
+        plan
+    }
+    /// Returns true if there is nothing to apply.
+    pub fn is_empty(&self) -> bool {
+        !self.upstream
+            && !self.location
+            && !self.server_location
+            && !self.schedule
+            && self.require_upgrade.is_empty()
+    }
+}
+
+/// Applies the components in order of upstreams, locations and servers,
+/// the plugins are listed as requiring upgrade. If any component fails,
+/// the reloaded components are rolled back in reverse order.
+pub fn apply_config(
+    plan: &ConfigApplyPlan,
+    conf: &PingapConf,
+) -> ConfigApplyReport {
+    let mut report = ConfigApplyReport {
+        applied_at: util::now().as_secs(),
+        require_upgrade: plan.require_upgrade.clone(),
+        ..Default::default()
+    };
+    let upstreams = snapshot_upstreams();
+    let locations = snapshot_locations();
+    let server_locations = snapshot_server_locations();
+
+    let mut result = Ok(());
+    if plan.upstream {
+        result = try_init_upstreams(&conf.upstreams)
+            .map(|_| report.reloaded.push(CATEGORY_UPSTREAM.to_string()))
+            .map_err(|e| (CATEGORY_UPSTREAM, e.to_string()));
+    }
+    // the locations inherit the defaults of servers which use them
+    if result.is_ok() && (plan.location || plan.server_location) {
+        result = try_init_locations(&conf.get_inherited_locations())
+            .map(|_| report.reloaded.push(CATEGORY_LOCATION.to_string()))
+            .map_err(|e| (CATEGORY_LOCATION, e.to_string()));
+    }
+    if result.is_ok() && plan.server_location {
+        result = try_init_server_locations(&conf.servers, &conf.locations)
+            .map(|_| report.reloaded.push(CATEGORY_SERVER.to_string()))
+            .map_err(|e| (CATEGORY_SERVER, e.to_string()));
+    }
+    if result.is_ok() && plan.schedule {
+        report.reloaded.push(CATEGORY_SCHEDULE.to_string());
+    }
+
+    if let Err((category, message)) = result {
+        report.failed = Some(category.to_string());
+        report.error = Some(message);
+        for category in report.reloaded.iter().rev() {
+            match category.as_str() {
+                CATEGORY_UPSTREAM => restore_upstreams(upstreams.clone()),
+                CATEGORY_LOCATION => restore_locations(locations.clone()),
+                CATEGORY_SERVER => {
+                    restore_server_locations(server_locations.clone())
+                },
+                _ => {},
+            };
+            report.rolled_back.push(category.clone());
+        }
+        report.reloaded.clear();
+    }
+    report
+}
+
+/// Sets the report of the latest applying, returns true if the result
+/// is different from the previous one.
+pub fn set_config_apply_report(report: &ConfigApplyReport) -> bool {
+    let Ok(mut value) = CONFIG_APPLY_REPORT.lock() else {
+        return false;
+    };
+    let changed = value.as_ref().map_or(true, |previous| {
+        previous.reloaded != report.reloaded
+            || previous.require_upgrade != report.require_upgrade
+            || previous.failed != report.failed
+            || previous.error != report.error
+    });
+    *value = Some(report.clone());
+    changed
+}
+
+/// Gets the report of the latest applying.
+pub fn get_config_apply_report() -> Option<ConfigApplyReport> {
+    CONFIG_APPLY_REPORT.lock().ok()?.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_config, ConfigApplyPlan, ConfigApplyReport};
+    use crate::config::{LocationConf, PingapConf, ServerConf, UpstreamConf};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_config_apply_plan() {
+        let mut current = PingapConf::default();
+        current.servers.insert(
+            "test".to_string(),
+            ServerConf {
+                addr: "127.0.0.1:6188".to_string(),
+                ..Default::default()
+            },
+        );
+        let mut conf = current.clone();
+        assert_eq!(true, ConfigApplyPlan::new(&current, &conf).is_empty());
+
+        conf.upstreams.insert(
+            "charts".to_string(),
+            UpstreamConf {
+                addrs: vec!["127.0.0.1:5000".to_string()],
+                ..Default::default()
+            },
+        );
+        conf.locations.insert(
+            "lo".to_string(),
+            LocationConf {
+                upstream: Some("charts".to_string()),
+                ..Default::default()
+            },
+        );
+        if let Some(server) = conf.servers.get_mut("test") {
+            server.locations = Some(vec!["lo".to_string()]);
+        }
+        assert_eq!(
+            ConfigApplyPlan {
+                upstream: true,
+                location: true,
+                server_location: true,
+                schedule: false,
+                require_upgrade: vec![],
+            },
+            ConfigApplyPlan::new(&current, &conf)
+        );
+
+        if let Some(server) = conf.servers.get_mut("test") {
+            server.addr = "127.0.0.1:6189".to_string();
+        }
+        conf.plugins.insert(
+            "stats".to_string(),
+            toml::from_str(
+                r###"
+category = "stats"
+path = "/stats"
+"###,
+            )
+            .unwrap(),
+        );
+        let plan = ConfigApplyPlan::new(&current, &conf);
+        assert_eq!(
+            vec!["plugin".to_string(), "server".to_string()],
+            plan.require_upgrade
+        );
+    }
+
+    #[test]
+    fn test_apply_config_fail() {
+        let mut conf = PingapConf::default();
+        // the upstream with empty addrs fails to reload
+        conf.upstreams
+            .insert("charts".to_string(), UpstreamConf::default());
+        let plan = ConfigApplyPlan {
+            upstream: true,
+            location: true,
+            require_upgrade: vec!["basic".to_string()],
+            ..Default::default()
+        };
+        let report = apply_config(&plan, &conf);
+        assert_eq!(
+            ConfigApplyReport {
+                applied_at: report.applied_at,
+                reloaded: vec![],
+                require_upgrade: vec!["basic".to_string()],
+                failed: Some("upstream".to_string()),
+                error: report.error.clone(),
+                rolled_back: vec![],
+            },
+            report
+        );
+        assert_eq!(true, report.error.is_some());
+    }
+}
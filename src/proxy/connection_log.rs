@@ -0,0 +1,292 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use async_trait::async_trait;
+use bytes::BytesMut;
+use chrono::{Local, TimeZone};
+use once_cell::sync::{Lazy, OnceCell};
+use pingora::proxy::Session;
+use pingora::tls::ssl::NameType;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info};
+
+// the connection is regarded as closed if it is idle for a long time,
+// it's the same as the default keepalive timeout of http1
+const CONNECTION_IDLE_TIMEOUT: u64 = 60_000;
+
+#[derive(Debug, Default, Clone)]
+struct ConnectionRecord {
+    server: String,
+    client_addr: String,
+    // the established time of tcp connection
+    accepted_at: u64,
+    last_active_at: u64,
+    tls_version: Option<String>,
+    tls_cipher: Option<String>,
+    sni: Option<String>,
+    alpn: String,
+    bytes_in: usize,
+    bytes_out: usize,
+    requests: u64,
+}
+
+static CONNECTIONS: Lazy<Mutex<AHashMap<String, ConnectionRecord>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+static CONNECTION_LOG_SENDER: OnceCell<crossbeam_channel::Sender<String>> =
+    OnceCell::new();
+
+/// Initializes the connection log file, the logs are written
+/// by a separate thread and it's separate from the access log.
+pub fn init_connection_log(file: &str) -> std::io::Result<()> {
+    let file = util::resolve_path(file);
+    let mut f = OpenOptions::new().create(true).append(true).open(&file)?;
+    let (sender, receiver) = crossbeam_channel::bounded::<String>(1024);
+    if CONNECTION_LOG_SENDER.set(sender).is_err() {
+        return Ok(());
+    }
+    std::thread::spawn(move || {
+        for line in receiver.iter() {
+            if let Err(e) = writeln!(f, "{line}") {
+                error!(error = e.to_string(), "write connection log fail");
+            }
+        }
+    });
+    info!(file, "init connection log");
+    Ok(())
+}
+
+#[inline]
+fn is_connection_log_enabled() -> bool {
+    CONNECTION_LOG_SENDER.get().is_some()
+}
+
+/// Gets the sni and alpn of downstream tls connection,
+/// the ssl stream is only available for http1.
+fn get_sni_alpn(session: &Session) -> (Option<String>, String) {
+    if session.is_http2() {
+        return (None, "h2".to_string());
+    }
+    let Some(ssl) = session
+        .as_downstream()
+        .stream()
+        .and_then(|stream| stream.get_ssl())
+    else {
+        return (None, "http/1.1".to_string());
+    };
+    let sni = ssl.servername(NameType::HOST_NAME).map(|v| v.to_string());
+    let alpn = ssl
+        .selected_alpn_protocol()
+        .map(|v| String::from_utf8_lossy(v).to_string())
+        .unwrap_or_else(|| "http/1.1".to_string());
+    (sni, alpn)
+}
+
+/// Records the request to its downstream connection,
+/// the connection log is written after the connection is closed.
+pub fn record_connection(server: &str, session: &Session, ctx: &State) {
+    if !is_connection_log_enabled() {
+        return;
+    }
+    let now = util::now().as_millis() as u64;
+    // the established time of tcp connection is the same for all requests
+    let accepted_at = session
+        .digest()
+        .and_then(|digest| digest.timing_digest.first().cloned().flatten())
+        .map(|timing| {
+            timing
+                .established_ts
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64
+        })
+        .unwrap_or_else(|| now.saturating_sub(ctx.connection_time));
+    let client_addr = session
+        .client_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_default();
+    let key = format!("{server}/{client_addr}/{accepted_at}");
+    let Ok(mut connections) = CONNECTIONS.lock() else {
+        return;
+    };
+    let record = connections.entry(key).or_insert_with(|| {
+        let (sni, alpn) = get_sni_alpn(session);
+        ConnectionRecord {
+            server: server.to_string(),
+            client_addr,
+            accepted_at,
+            tls_version: ctx.tls_version.clone(),
+            tls_cipher: ctx.tls_cipher.clone(),
+            sni,
+            alpn,
+            ..Default::default()
+        }
+    });
+    record.last_active_at = now;
+    record.requests += 1;
+    record.bytes_in += ctx.payload_size;
+    record.bytes_out += session.body_bytes_sent();
+}
+
+fn format_connection_log(record: &ConnectionRecord) -> String {
+    let format_time = |ms: u64| {
+        Local
+            .timestamp_millis_opt(ms as i64)
+            .single()
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default()
+    };
+    let mut buf = BytesMut::with_capacity(256);
+    buf.extend(format_time(record.last_active_at).as_bytes());
+    buf.extend(b" server:");
+    buf.extend(record.server.as_bytes());
+    buf.extend(b" client:");
+    buf.extend(record.client_addr.as_bytes());
+    buf.extend(b" accepted_at:");
+    buf.extend(format_time(record.accepted_at).as_bytes());
+    buf.extend(b" duration:");
+    buf = util::format_duration(
+        buf,
+        record.last_active_at.saturating_sub(record.accepted_at),
+    );
+    buf.extend(b" requests:");
+    buf.extend(itoa::Buffer::new().format(record.requests).as_bytes());
+    buf.extend(b" bytes_in:");
+    buf.extend(itoa::Buffer::new().format(record.bytes_in).as_bytes());
+    buf.extend(b" bytes_out:");
+    buf.extend(itoa::Buffer::new().format(record.bytes_out).as_bytes());
+    buf.extend(b" alpn:");
+    buf.extend(record.alpn.as_bytes());
+    if let Some(tls_version) = &record.tls_version {
+        buf.extend(b" tls_version:");
+        buf.extend(tls_version.as_bytes());
+    }
+    if let Some(tls_cipher) = &record.tls_cipher {
+        buf.extend(b" tls_cipher:");
+        buf.extend(tls_cipher.as_bytes());
+    }
+    if let Some(sni) = &record.sni {
+        buf.extend(b" sni:");
+        buf.extend(sni.as_bytes());
+    }
+    String::from_utf8_lossy(&buf).to_string()
+}
+
+/// Flushes the connections which are idle more than the timeout,
+/// returns the count of flushed connections.
+fn flush_idle_connections(timeout: u64) -> usize {
+    let now = util::now().as_millis() as u64;
+    let mut closed = vec![];
+    if let Ok(mut connections) = CONNECTIONS.lock() {
+        connections.retain(|_, record| {
+            if now.saturating_sub(record.last_active_at) < timeout {
+                return true;
+            }
+            closed.push(format_connection_log(record));
+            false
+        });
+    }
+    let count = closed.len();
+    if let Some(sender) = CONNECTION_LOG_SENDER.get() {
+        for line in closed {
+            // drop the log if the channel is full
+            let _ = sender.try_send(line);
+        }
+    }
+    count
+}
+
+struct ConnectionLogTask {
+    timeout: u64,
+}
+
+#[async_trait]
+impl ServiceTask for ConnectionLogTask {
+    async fn run(&self) -> Option<bool> {
+        flush_idle_connections(self.timeout);
+        None
+    }
+    fn description(&self) -> String {
+        "Connection log flush".to_string()
+    }
+}
+
+/// Creates a task to write the log of closed connections.
+pub fn new_connection_log_task() -> CommonServiceTask {
+    CommonServiceTask::new(
+        "Connection log",
+        Duration::from_secs(10),
+        ConnectionLogTask {
+            timeout: CONNECTION_IDLE_TIMEOUT,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        flush_idle_connections, format_connection_log, ConnectionRecord,
+        CONNECTIONS,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_connection_log() {
+        let record = ConnectionRecord {
+            server: "test".to_string(),
+            client_addr: "127.0.0.1:6000".to_string(),
+            accepted_at: 1_700_000_000_000,
+            last_active_at: 1_700_000_001_500,
+            tls_version: Some("TLSv1.3".to_string()),
+            tls_cipher: Some("TLS_AES_256_GCM_SHA384".to_string()),
+            sni: Some("pingap.io".to_string()),
+            alpn: "http/1.1".to_string(),
+            bytes_in: 10,
+            bytes_out: 1024,
+            requests: 3,
+        };
+        let log = format_connection_log(&record);
+        assert_eq!(
+            true,
+            log.ends_with(" duration:1.5s requests:3 bytes_in:10 bytes_out:1024 alpn:http/1.1 tls_version:TLSv1.3 tls_cipher:TLS_AES_256_GCM_SHA384 sni:pingap.io")
+        );
+        assert_eq!(true, log.contains(" server:test client:127.0.0.1:6000 "));
+    }
+
+    #[test]
+    fn test_flush_idle_connections() {
+        CONNECTIONS.lock().unwrap().insert(
+            "test/127.0.0.1:6000/1".to_string(),
+            ConnectionRecord {
+                last_active_at: 1000,
+                ..Default::default()
+            },
+        );
+        assert_eq!(true, flush_idle_connections(60_000) >= 1);
+        assert_eq!(
+            true,
+            CONNECTIONS
+                .lock()
+                .unwrap()
+                .get("test/127.0.0.1:6000/1")
+                .is_none()
+        );
+    }
+}
@@ -12,11 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::tls_fingerprint::set_tls_fingerprint_callback;
 use crate::acme::{
     get_certificate_info, get_lets_encrypt_cert, CertificateInfo,
 };
 use crate::config::CertificateConf;
+use crate::service::{CommonServiceTask, ServiceTask};
 use crate::{util, webhook};
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use once_cell::sync::{Lazy, OnceCell};
 use pingora::listeners::TlsSettings;
@@ -27,6 +30,8 @@ use pingora::tls::x509::X509;
 use snafu::Snafu;
 use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use substring::Substring;
 use tracing::{debug, error, info};
 
@@ -63,6 +68,126 @@ static R11_CERTIFICATE: Lazy<Option<X509>> =
 
 static LETS_ENCRYPT: &str = "lets_encrypt";
 
+/// The certificate loaded from pem files, it's reloaded
+/// when the modified time of cert or key file is changed.
+#[derive(Clone)]
+struct WatchedCertificate {
+    key_file: String,
+    modified: Option<SystemTime>,
+    certificate: (X509, PKey<Private>),
+}
+
+// the watched certificates, the key is the path of cert file
+static WATCHED_CERTIFICATES: Lazy<
+    ArcSwap<HashMap<String, WatchedCertificate>>,
+> = Lazy::new(|| ArcSwap::from_pointee(HashMap::new()));
+
+/// Gets the latest modified time of cert and key file,
+/// the symbolic link is followed, e.g. the live files of certbot.
+fn get_modified(cert_file: &str, key_file: &str) -> Option<SystemTime> {
+    let cert = std::fs::metadata(cert_file).ok()?.modified().ok()?;
+    let key = std::fs::metadata(key_file).ok()?.modified().ok()?;
+    Some(cert.max(key))
+}
+
+/// Reads and parses the certificate from pem files,
+/// the key should match the certificate.
+fn read_certificate_files(
+    cert_file: &str,
+    key_file: &str,
+) -> Result<(X509, PKey<Private>)> {
+    let read = |file: &str| {
+        std::fs::read(file).map_err(|e| Error::Invalid {
+            message: format!("{e}, {file}"),
+        })
+    };
+    let cert =
+        X509::from_pem(&read(cert_file)?).map_err(|e| Error::Invalid {
+            message: e.to_string(),
+        })?;
+    let key = PKey::private_key_from_pem(&read(key_file)?).map_err(|e| {
+        Error::Invalid {
+            message: e.to_string(),
+        }
+    })?;
+    let matched = cert
+        .public_key()
+        .map(|public_key| public_key.public_eq(&key))
+        .unwrap_or_default();
+    if !matched {
+        return Err(Error::Invalid {
+            message: format!("key({key_file}) doesn't match the certificate"),
+        });
+    }
+    Ok((cert, key))
+}
+
+/// Reloads the watched certificates whose files are changed,
+/// the previous certificate is kept if the new one is invalid.
+/// It returns the cert files of reloaded certificates.
+fn reload_certificate_files() -> Vec<String> {
+    let mut reloaded = vec![];
+    let mut certificates = WATCHED_CERTIFICATES.load().as_ref().clone();
+    for (cert_file, item) in certificates.iter_mut() {
+        let modified = get_modified(cert_file, &item.key_file);
+        if modified.is_none() || modified == item.modified {
+            continue;
+        }
+        // not to reload the invalid files again until they are changed
+        item.modified = modified;
+        match read_certificate_files(cert_file, &item.key_file) {
+            Ok(certificate) => {
+                info!(cert_file, "reload certificate success");
+                item.certificate = certificate;
+                reloaded.push(cert_file.to_string());
+            },
+            Err(e) => {
+                error!(
+                    error = e.to_string(),
+                    cert_file, "reload certificate fail"
+                );
+                webhook::send(webhook::SendNotificationParams {
+                    category:
+                        webhook::NotificationCategory::ParseCertificateFail,
+                    level: webhook::NotificationLevel::Error,
+                    msg: format!("reload certificate({cert_file}) fail, {e}"),
+                });
+            },
+        };
+    }
+    WATCHED_CERTIFICATES.store(Arc::new(certificates));
+    reloaded
+}
+
+/// Returns `true` if any certificate is loaded from pem files.
+pub fn has_watched_certificates() -> bool {
+    !WATCHED_CERTIFICATES.load().is_empty()
+}
+
+struct CertificateReloadTask;
+
+#[async_trait]
+impl ServiceTask for CertificateReloadTask {
+    async fn run(&self) -> Option<bool> {
+        reload_certificate_files();
+        None
+    }
+    fn description(&self) -> String {
+        "Reload certificates of changed pem files".to_string()
+    }
+}
+
+/// Creates the service which checks the pem files of certificates
+/// every ten seconds, the changed certificates are used by new tls
+/// connections without restart.
+pub fn new_certificate_reload_service() -> CommonServiceTask {
+    CommonServiceTask::new(
+        "Certificate reload",
+        Duration::from_secs(10),
+        CertificateReloadTask,
+    )
+}
+
 fn parse_certificate(
     certificate_config: &CertificateConf,
 ) -> Result<(Vec<String>, DynamicCertificate, CertificateInfo)> {
@@ -120,10 +245,20 @@ fn parse_certificate(
     let key = PKey::private_key_from_pem(&key).map_err(|e| Error::Invalid {
         message: e.to_string(),
     })?;
-    let d = DynamicCertificate {
+    let mut d = DynamicCertificate {
         chain_certificate,
         certificate: Some((cert, key)),
+        cert_file: None,
     };
+    if category != LETS_ENCRYPT {
+        if let (Some(cert_file), Some(key_file)) =
+            (&certificate_config.tls_cert, &certificate_config.tls_key)
+        {
+            if util::is_pem_file(cert_file) && util::is_pem_file(key_file) {
+                d = d.watch_files(cert_file, key_file);
+            }
+        }
+    }
     Ok((domains, d, info))
 }
 
@@ -180,6 +315,8 @@ pub fn try_init_certificates(
 pub struct DynamicCertificate {
     chain_certificate: Option<X509>,
     certificate: Option<(X509, PKey<Private>)>,
+    // the cert file of watched certificate
+    cert_file: Option<String>,
 }
 
 pub struct TlsSettingParams {
@@ -196,6 +333,7 @@ impl DynamicCertificate {
         Self {
             chain_certificate: None,
             certificate: None,
+            cert_file: None,
         }
     }
     pub fn new_tls_settings(
@@ -212,6 +350,7 @@ impl DynamicCertificate {
         if params.enbaled_h2 {
             tls_settings.enable_h2();
         }
+        set_tls_fingerprint_callback(&mut tls_settings);
         if let Some(cipher_list) = &params.cipher_list {
             if let Err(e) = tls_settings.set_cipher_list(cipher_list) {
                 error!(error = e.to_string(), name, "set cipher list fail");
@@ -279,8 +418,39 @@ impl DynamicCertificate {
         Ok(Self {
             chain_certificate: None,
             certificate: Some((cert, key)),
+            cert_file: None,
         })
     }
+    /// Watches the pem files of certificate, the certificate is reloaded
+    /// by the reload service when any of the files is changed.
+    pub fn watch_files(mut self, cert_file: &str, key_file: &str) -> Self {
+        let cert_file = util::resolve_path(cert_file);
+        let key_file = util::resolve_path(key_file);
+        if let Some(certificate) = &self.certificate {
+            let item = WatchedCertificate {
+                modified: get_modified(&cert_file, &key_file),
+                key_file,
+                certificate: certificate.clone(),
+            };
+            WATCHED_CERTIFICATES.rcu(|certificates| {
+                let mut certificates = HashMap::clone(certificates);
+                certificates.insert(cert_file.clone(), item.clone());
+                certificates
+            });
+        }
+        self.cert_file = Some(cert_file);
+        self
+    }
+    /// Gets the certificate, the reloaded one is returned
+    /// if the certificate is watched.
+    fn get_certificate(&self) -> Option<(X509, PKey<Private>)> {
+        if let Some(cert_file) = &self.cert_file {
+            if let Some(item) = WATCHED_CERTIFICATES.load().get(cert_file) {
+                return Some(item.certificate.clone());
+            }
+        }
+        self.certificate.clone()
+    }
 }
 
 #[inline]
@@ -308,8 +478,8 @@ impl pingora::listeners::TlsAccept for DynamicCertificate {
     async fn certificate_callback(&self, ssl: &mut SslRef) {
         // TODO add more debug log
         debug!(ssl = format!("{ssl:?}"));
-        if let Some((cert, key)) = &self.certificate {
-            ssl_certificate(ssl, cert, key, &self.chain_certificate);
+        if let Some((cert, key)) = self.get_certificate() {
+            ssl_certificate(ssl, &cert, &key, &self.chain_certificate);
             return;
         }
         let server_name = ssl.servername(NameType::HOST_NAME);
@@ -341,15 +511,18 @@ impl pingora::listeners::TlsAccept for DynamicCertificate {
             error!(sni, ssl = format!("{ssl:?}"), "no match certificate");
             return;
         };
-        if let Some((cert, key)) = &d.certificate {
-            ssl_certificate(ssl, cert, key, &d.chain_certificate);
+        if let Some((cert, key)) = d.get_certificate() {
+            ssl_certificate(ssl, &cert, &key, &d.chain_certificate);
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_certificate, DynamicCertificate, TlsSettingParams};
+    use super::{
+        has_watched_certificates, parse_certificate, reload_certificate_files,
+        DynamicCertificate, TlsSettingParams, WATCHED_CERTIFICATES,
+    };
     use crate::{
         config::CertificateConf,
         proxy::{
@@ -487,4 +660,41 @@ aqcrKJfS+xaKWxXPiNlpBMG5
         assert_eq!(true, dynamic_certificate.certificate.is_some());
         assert_eq!(true, dynamic_certificate.chain_certificate.is_none());
     }
+
+    #[test]
+    fn test_reload_certificate_files() {
+        let (tls_cert, tls_key) = get_tls_pem();
+        let dir = tempfile::tempdir().unwrap();
+        let cert_file =
+            dir.path().join("cert.pem").to_string_lossy().to_string();
+        let key_file = dir.path().join("key.pem").to_string_lossy().to_string();
+        std::fs::write(&cert_file, &tls_cert).unwrap();
+        std::fs::write(&key_file, &tls_key).unwrap();
+        let mark_changed = || {
+            WATCHED_CERTIFICATES.rcu(|certificates| {
+                let mut certificates = HashMap::clone(certificates);
+                if let Some(item) = certificates.get_mut(&cert_file) {
+                    item.modified = None;
+                }
+                certificates
+            });
+        };
+
+        let dynamic_certificate =
+            DynamicCertificate::new(tls_cert.as_bytes(), tls_key.as_bytes())
+                .unwrap()
+                .watch_files(&cert_file, &key_file);
+        assert_eq!(true, has_watched_certificates());
+        assert_eq!(true, dynamic_certificate.get_certificate().is_some());
+        assert_eq!(false, reload_certificate_files().contains(&cert_file));
+
+        mark_changed();
+        assert_eq!(true, reload_certificate_files().contains(&cert_file));
+
+        // the previous certificate is kept if the key is invalid
+        std::fs::write(&key_file, "invalid key").unwrap();
+        mark_changed();
+        assert_eq!(false, reload_certificate_files().contains(&cert_file));
+        assert_eq!(true, dynamic_certificate.get_certificate().is_some());
+    }
 }
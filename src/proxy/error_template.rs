@@ -0,0 +1,254 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::http_extra::get_variable_value;
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use pingora::http::RequestHeader;
+use tracing::{error, info};
+
+static DEFAULT_JSON_TEMPLATE: &str = r###"{"status":{{status}},"message":"{{content}}","request_id":"{{request_id}}"}"###;
+
+/// The error templates of server, the template is selected by
+/// status code and the `Accept` header of request.
+#[derive(Debug, Default, Clone)]
+pub struct ErrorTemplates {
+    html: String,
+    json: String,
+    // the templates of status, key is the file name,
+    // e.g. `502.html`, `5xx.html` and `404.json`
+    files: AHashMap<String, String>,
+}
+
+impl ErrorTemplates {
+    /// Creates the error templates, the files of template directory
+    /// are loaded if it is set.
+    pub fn new(html: &str, dir: Option<&str>) -> Self {
+        let mut files = AHashMap::new();
+        if let Some(dir) = dir {
+            let dir = util::resolve_path(dir);
+            match std::fs::read_dir(&dir) {
+                Ok(entries) => {
+                    for entry in entries.flatten() {
+                        let path = entry.path();
+                        let Some(name) =
+                            path.file_name().and_then(|name| name.to_str())
+                        else {
+                            continue;
+                        };
+                        if !name.ends_with(".html") && !name.ends_with(".json")
+                        {
+                            continue;
+                        }
+                        match std::fs::read_to_string(&path) {
+                            Ok(content) => {
+                                files.insert(name.to_string(), content);
+                            },
+                            Err(e) => {
+                                error!(
+                                    error = e.to_string(),
+                                    file = name,
+                                    "read error template fail"
+                                );
+                            },
+                        }
+                    }
+                    info!(dir, count = files.len(), "load error templates");
+                },
+                Err(e) => {
+                    error!(
+                        error = e.to_string(),
+                        dir, "read error template directory fail"
+                    );
+                },
+            }
+        }
+        let json = files
+            .get("error.json")
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_JSON_TEMPLATE.to_string());
+        Self {
+            html: html.to_string(),
+            json,
+            files,
+        }
+    }
+    /// Gets the template of status, the order is `502.html`, `5xx.html`,
+    /// and then the default template.
+    pub fn get(&self, code: u16, json: bool) -> &str {
+        let ext = if json { "json" } else { "html" };
+        for name in [format!("{code}.{ext}"), format!("{}xx.{ext}", code / 100)]
+        {
+            if let Some(template) = self.files.get(&name) {
+                return template;
+            }
+        }
+        if json {
+            &self.json
+        } else {
+            &self.html
+        }
+    }
+}
+
+/// Returns `true` if the client prefers json response by `Accept` header.
+pub fn is_accept_json(req_header: &RequestHeader) -> bool {
+    let Some(accept) = util::get_req_header_value(req_header, "Accept") else {
+        return false;
+    };
+    let Some(json_index) = accept.find("application/json") else {
+        return false;
+    };
+    accept
+        .find("text/html")
+        .map(|html_index| json_index < html_index)
+        .unwrap_or(true)
+}
+
+/// Renders the error template, the `{{name}}` will be replaced by:
+/// `version`, `content`, `status`, `request_id`, `host`, `timestamp`,
+/// `upstream` and the other variables of request, e.g. `{{remote_addr}}`.
+pub fn render_error_template(
+    template: &str,
+    code: u16,
+    content: &str,
+    req_header: &RequestHeader,
+    ctx: &State,
+) -> String {
+    let json = template.trim_start().starts_with('{');
+    let mut result = String::with_capacity(template.len() + content.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = rest[start + 2..start + end].trim();
+        let value = match name {
+            "version" => util::get_pkg_version().to_string(),
+            "content" => content.to_string(),
+            "status" => code.to_string(),
+            "request_id" => ctx.request_id.clone().unwrap_or_default(),
+            "host" => {
+                util::get_host(req_header).unwrap_or_default().to_string()
+            },
+            "timestamp" => chrono::Local::now().to_rfc3339(),
+            "upstream" => ctx
+                .location
+                .as_ref()
+                .map(|location| location.upstream.clone())
+                .unwrap_or_default(),
+            _ => get_variable_value(name, req_header, ctx).unwrap_or_default(),
+        };
+        if json {
+            // escape the value of json string
+            let value = serde_json::to_string(&value).unwrap_or_default();
+            result.push_str(value.trim_matches('"'));
+        } else {
+            result.push_str(&value);
+        }
+        rest = &rest[start + end + 2..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Gets the content type of rendered error template.
+pub fn get_error_content_type(content: &str) -> &'static str {
+    if content.trim_start().starts_with('{') {
+        "application/json; charset=utf-8"
+    } else {
+        "text/html; charset=utf-8"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_error_content_type, is_accept_json, render_error_template,
+        ErrorTemplates,
+    };
+    use crate::state::State;
+    use pingora::http::RequestHeader;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_error_templates() {
+        let dir = tempfile::TempDir::with_prefix("pingap").unwrap();
+        std::fs::write(dir.path().join("502.html"), "bad gateway").unwrap();
+        std::fs::write(dir.path().join("5xx.html"), "server error").unwrap();
+        std::fs::write(dir.path().join("404.json"), r#"{"code":404}"#).unwrap();
+        let templates = ErrorTemplates::new(
+            "<html></html>",
+            Some(dir.path().to_string_lossy().as_ref()),
+        );
+        assert_eq!("bad gateway", templates.get(502, false));
+        assert_eq!("server error", templates.get(503, false));
+        assert_eq!("<html></html>", templates.get(400, false));
+        assert_eq!(r#"{"code":404}"#, templates.get(404, true));
+        assert_eq!(true, templates.get(500, true).starts_with("{\"status\""));
+    }
+
+    #[test]
+    fn test_is_accept_json() {
+        let mut req_header = RequestHeader::build("GET", b"/", None).unwrap();
+        assert_eq!(false, is_accept_json(&req_header));
+        req_header
+            .insert_header("Accept", "application/json, text/plain")
+            .unwrap();
+        assert_eq!(true, is_accept_json(&req_header));
+        req_header
+            .insert_header("Accept", "text/html,application/json;q=0.9")
+            .unwrap();
+        assert_eq!(false, is_accept_json(&req_header));
+    }
+
+    #[test]
+    fn test_render_error_template() {
+        let mut req_header = RequestHeader::build("GET", b"/", None).unwrap();
+        req_header.insert_header("Host", "pingap.io").unwrap();
+        let ctx = State {
+            request_id: Some("123".to_string()),
+            remote_addr: Some("10.1.1.1".to_string()),
+            ..Default::default()
+        };
+        let content = render_error_template(
+            "<p>{{status}} {{ content }} {{request_id}} {{host}} {{remote_addr}}</p>",
+            502,
+            "connect fail",
+            &req_header,
+            &ctx,
+        );
+        assert_eq!("<p>502 connect fail 123 pingap.io 10.1.1.1</p>", content);
+        assert_eq!(
+            "text/html; charset=utf-8",
+            get_error_content_type(&content)
+        );
+
+        let content = render_error_template(
+            r#"{"status":{{status}},"message":"{{content}}"}"#,
+            500,
+            r#"invalid "value""#,
+            &req_header,
+            &ctx,
+        );
+        assert_eq!(r#"{"status":500,"message":"invalid \"value\""}"#, content);
+        assert_eq!(
+            "application/json; charset=utf-8",
+            get_error_content_type(&content)
+        );
+    }
+}
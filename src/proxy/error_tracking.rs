@@ -0,0 +1,258 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::state::{get_hostname, State};
+use crate::util;
+use ahash::AHashMap;
+use once_cell::sync::{Lazy, OnceCell};
+use pingora::proxy::Session;
+use sentry::protocol::{Event, Level, Request};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::info;
+
+// the window of 5xx burst
+const BURST_WINDOW_SECS: u64 = 60;
+// the headers are always scrubbed
+const DEFAULT_SCRUB_HEADERS: [&str; 5] = [
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "proxy-authorization",
+    "x-api-key",
+];
+const SCRUBBED_VALUE: &str = "[Filtered]";
+
+#[derive(Debug, Default, Clone)]
+pub struct ErrorTrackingParams {
+    // the sample rate of 5xx events, 0.0 ~ 1.0
+    pub sample_rate: f64,
+    // the 5xx count of location in one minute to capture as a burst event,
+    // 0 means capture each 5xx event
+    pub burst_threshold: u64,
+    // the sensitive headers, they are scrubbed before sending
+    pub scrub_headers: Vec<String>,
+}
+
+struct ErrorTracking {
+    sample_rate: f64,
+    burst_threshold: u64,
+    scrub_headers: Vec<String>,
+}
+
+static ERROR_TRACKING: OnceCell<ErrorTracking> = OnceCell::new();
+static SAMPLED_COUNT: AtomicU64 = AtomicU64::new(0);
+// the (window, count) of 5xx for each location
+static BURST_COUNTS: Lazy<Mutex<AHashMap<String, (u64, u64)>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+impl ErrorTracking {
+    fn is_scrubbed(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        DEFAULT_SCRUB_HEADERS.contains(&name.as_str())
+            || self.scrub_headers.contains(&name)
+    }
+    /// Returns `true` if the event should be sent,
+    /// the count of sent events is proportional to the sample rate.
+    fn sampled(&self) -> bool {
+        if self.sample_rate >= 1.0 {
+            return true;
+        }
+        if self.sample_rate <= 0.0 {
+            return false;
+        }
+        let count = SAMPLED_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+        (count as f64 * self.sample_rate).floor()
+            > ((count - 1) as f64 * self.sample_rate).floor()
+    }
+    /// Adds the 5xx count of location, returns the count if it reaches
+    /// the burst threshold, the burst is only reported once in the window.
+    fn add_burst_count(&self, location: &str, now: u64) -> Option<u64> {
+        let window = now / BURST_WINDOW_SECS;
+        let mut counts = BURST_COUNTS.lock().ok()?;
+        let value = counts.entry(location.to_string()).or_insert((window, 0));
+        if value.0 != window {
+            *value = (window, 0);
+        }
+        value.1 += 1;
+        if value.1 == self.burst_threshold {
+            Some(value.1)
+        } else {
+            None
+        }
+    }
+}
+
+/// Initializes the error tracking of sentry, the request context is attached
+/// to the 5xx events and the sensitive headers are scrubbed.
+pub fn init_error_tracking(params: ErrorTrackingParams) {
+    let tracking = ERROR_TRACKING.get_or_init(|| ErrorTracking {
+        sample_rate: params.sample_rate,
+        burst_threshold: params.burst_threshold,
+        scrub_headers: params
+            .scrub_headers
+            .iter()
+            .map(|item| item.to_lowercase())
+            .collect(),
+    });
+    info!(
+        sample_rate = tracking.sample_rate,
+        burst_threshold = tracking.burst_threshold,
+        "init sentry error tracking"
+    );
+    sentry::configure_scope(|scope| {
+        scope.set_tag("hostname", get_hostname());
+        scope.set_tag("version", util::get_pkg_version());
+        // scrub the headers of all events, include the panic events
+        scope.add_event_processor(|mut event| {
+            if let Some(tracking) = ERROR_TRACKING.get() {
+                if let Some(request) = event.request.as_mut() {
+                    for (name, value) in request.headers.iter_mut() {
+                        if tracking.is_scrubbed(name) {
+                            *value = SCRUBBED_VALUE.to_string();
+                        }
+                    }
+                    if request.cookies.is_some() {
+                        request.cookies = Some(SCRUBBED_VALUE.to_string());
+                    }
+                }
+            }
+            Some(event)
+        });
+    });
+}
+
+/// Hashes the client ip, the raw ip is not sent to sentry.
+fn hash_client_ip(ip: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(ip.as_bytes());
+    hex::encode(hasher.finalize())[..16].to_string()
+}
+
+fn new_error_event(
+    tracking: &ErrorTracking,
+    session: &Session,
+    ctx: &State,
+    status: u16,
+    message: String,
+) -> Event<'static> {
+    let req_header = session.req_header();
+    let mut headers = BTreeMap::new();
+    for (name, value) in req_header.headers.iter() {
+        let value = if tracking.is_scrubbed(name.as_str()) {
+            SCRUBBED_VALUE.to_string()
+        } else {
+            value.to_str().unwrap_or_default().to_string()
+        };
+        headers.insert(name.to_string(), value);
+    }
+    let host = util::get_host(req_header).unwrap_or("localhost");
+    let url = format!("http://{host}{}", req_header.uri.path());
+    let mut event = Event {
+        message: Some(message),
+        level: Level::Error,
+        request: Some(Request {
+            url: url.parse().ok(),
+            method: Some(req_header.method.to_string()),
+            query_string: req_header.uri.query().map(|item| item.to_string()),
+            headers,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let location = ctx
+        .location
+        .as_ref()
+        .map(|item| item.name.clone())
+        .unwrap_or_default();
+    event.tags.insert("location".to_string(), location);
+    event
+        .tags
+        .insert("upstream".to_string(), ctx.upstream_address.clone());
+    event.tags.insert("status".to_string(), status.to_string());
+    if let Some(ip) = &ctx.client_ip {
+        event
+            .tags
+            .insert("client_ip_hash".to_string(), hash_client_ip(ip));
+    }
+    if let Some(id) = &ctx.request_id {
+        event.tags.insert("request_id".to_string(), id.clone());
+    }
+    event
+}
+
+/// Captures the 5xx response as sentry event,
+/// it's ignored if the error tracking is not initialized.
+pub fn capture_server_error(session: &Session, ctx: &State) {
+    let Some(tracking) = ERROR_TRACKING.get() else {
+        return;
+    };
+    let status = ctx.status.map(|item| item.as_u16()).unwrap_or_default();
+    if status < 500 {
+        return;
+    }
+    let message = if tracking.burst_threshold > 0 {
+        let location = ctx
+            .location
+            .as_ref()
+            .map(|item| item.name.as_str())
+            .unwrap_or_default();
+        let Some(count) =
+            tracking.add_burst_count(location, util::now().as_secs())
+        else {
+            return;
+        };
+        format!("5xx burst of location {location}, count:{count} in one minute")
+    } else {
+        format!("{status} {}", session.req_header().uri.path())
+    };
+    if !tracking.sampled() {
+        return;
+    }
+    sentry::capture_event(new_error_event(
+        tracking, session, ctx, status, message,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_client_ip, ErrorTracking};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_error_tracking() {
+        let tracking = ErrorTracking {
+            sample_rate: 0.25,
+            burst_threshold: 3,
+            scrub_headers: vec!["x-token".to_string()],
+        };
+        assert_eq!(true, tracking.is_scrubbed("Authorization"));
+        assert_eq!(true, tracking.is_scrubbed("X-Token"));
+        assert_eq!(false, tracking.is_scrubbed("Accept"));
+
+        let sampled = (0..100).filter(|_| tracking.sampled()).count();
+        assert_eq!(25, sampled);
+
+        assert_eq!(None, tracking.add_burst_count("lo", 60));
+        assert_eq!(None, tracking.add_burst_count("lo", 61));
+        assert_eq!(Some(3), tracking.add_burst_count("lo", 62));
+        assert_eq!(None, tracking.add_burst_count("lo", 63));
+        // new window
+        assert_eq!(None, tracking.add_burst_count("lo", 120));
+
+        assert_eq!("12ca17b49af22894", hash_client_ip("127.0.0.1"));
+    }
+}
@@ -0,0 +1,517 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::cgi::{
+    connect, get_cgi_params, parse_cgi_headers, parse_params,
+    set_custom_params, set_param, with_timeout,
+};
+use crate::config::UpstreamConf;
+use crate::http_extra::HTTP_HEADER_TRANSFER_CHUNKED;
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use bytes::{BufMut, Bytes, BytesMut};
+use http::StatusCode;
+use pingora::proxy::Session;
+use pingora::upstreams::peer::HttpPeer;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+const FCGI_VERSION: u8 = 1;
+const FCGI_BEGIN_REQUEST: u8 = 1;
+const FCGI_END_REQUEST: u8 = 3;
+const FCGI_PARAMS: u8 = 4;
+const FCGI_STDIN: u8 = 5;
+const FCGI_STDOUT: u8 = 6;
+const FCGI_STDERR: u8 = 7;
+const FCGI_RESPONDER: u16 = 1;
+const FCGI_KEEP_CONN: u8 = 1;
+// the request id is always 1, php-fpm doesn't support multiplexing
+const FCGI_REQUEST_ID: u16 = 1;
+const FCGI_MAX_CONTENT_LENGTH: usize = 65535;
+const FASTCGI_DEFAULT_INDEX: &str = "index.php";
+const FASTCGI_DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const FASTCGI_MAX_IDLE_CONNECTIONS: usize = 32;
+
+/// Appends the fastcgi record to buffer, the content should not be
+/// greater than the max content length.
+fn encode_record(buf: &mut BytesMut, record_type: u8, content: &[u8]) {
+    let padding = (8 - content.len() % 8) % 8;
+    buf.put_u8(FCGI_VERSION);
+    buf.put_u8(record_type);
+    buf.put_u16(FCGI_REQUEST_ID);
+    buf.put_u16(content.len() as u16);
+    buf.put_u8(padding as u8);
+    buf.put_u8(0);
+    buf.put_slice(content);
+    buf.put_bytes(0, padding);
+}
+
+/// Appends the records of stream type(params, stdin), the content is
+/// split by the max content length.
+fn encode_stream(buf: &mut BytesMut, record_type: u8, content: &[u8]) {
+    for chunk in content.chunks(FCGI_MAX_CONTENT_LENGTH) {
+        encode_record(buf, record_type, chunk);
+    }
+}
+
+fn encode_name_value_length(buf: &mut BytesMut, len: usize) {
+    if len < 128 {
+        buf.put_u8(len as u8);
+    } else {
+        buf.put_u32(len as u32 | 0x8000_0000);
+    }
+}
+
+/// Encodes the params as name-value pairs of fastcgi.
+fn encode_params(params: &[(String, String)]) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(1024);
+    for (name, value) in params {
+        encode_name_value_length(&mut buf, name.len());
+        encode_name_value_length(&mut buf, value.len());
+        buf.put_slice(name.as_bytes());
+        buf.put_slice(value.as_bytes());
+    }
+    buf
+}
+
+fn new_begin_request(keep_conn: bool) -> BytesMut {
+    let mut content = BytesMut::with_capacity(8);
+    content.put_u16(FCGI_RESPONDER);
+    content.put_u8(if keep_conn { FCGI_KEEP_CONN } else { 0 });
+    content.put_bytes(0, 5);
+    let mut buf = BytesMut::with_capacity(16);
+    encode_record(&mut buf, FCGI_BEGIN_REQUEST, &content);
+    buf
+}
+
+/// Reads a record of fastcgi, returns the type and content of record.
+async fn read_record(stream: &mut TcpStream) -> std::io::Result<(u8, Bytes)> {
+    let mut header = [0; 8];
+    stream.read_exact(&mut header).await?;
+    let content_length = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let padding_length = header[6] as usize;
+    let mut content = vec![0; content_length + padding_length];
+    stream.read_exact(&mut content).await?;
+    content.truncate(content_length);
+    Ok((header[1], Bytes::from(content)))
+}
+
+/// Normalizes the request path of script, the path is decoded and the
+/// empty or `.` segments are removed. The `..` segment is rejected,
+/// otherwise the script outside of root can be executed.
+fn normalize_script_path(path: &str) -> pingora::Result<String> {
+    let new_invalid_error = || {
+        util::new_internal_error(400, format!("script path({path}) is invalid"))
+    };
+    let decoded = urlencoding::decode(path).map_err(|_| new_invalid_error())?;
+    if decoded.contains(['\0', '\\']) {
+        return Err(new_invalid_error());
+    }
+    let mut segments = vec![];
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {},
+            ".." => return Err(new_invalid_error()),
+            _ => segments.push(segment),
+        }
+    }
+    let mut result = format!("/{}", segments.join("/"));
+    if !segments.is_empty() && decoded.ends_with('/') {
+        result.push('/');
+    }
+    Ok(result)
+}
+
+/// The script of request, `name` is the path of script and
+/// `path_info` is the rest of request path.
+#[derive(Debug, Default, PartialEq)]
+struct FastCgiScript {
+    name: String,
+    path_info: String,
+}
+
+struct IdleConnection {
+    stream: TcpStream,
+    idle_at: Instant,
+}
+
+/// The fastcgi upstream(e.g. php-fpm), the request is converted to
+/// fastcgi records and the response is sent to client directly.
+pub struct FastCgi {
+    // the document root of script
+    root: String,
+    index: String,
+    // the custom params, the value supports variables
+    params: Vec<(String, String)>,
+    keepalive: bool,
+    idle_timeout: Duration,
+    max_idle_connections: usize,
+    // the idle connections of backends, the key is the address
+    idle_connections: Mutex<AHashMap<String, Vec<IdleConnection>>>,
+}
+
+impl FastCgi {
+    /// Creates the fastcgi upstream if it's enabled.
+    pub fn new(conf: &UpstreamConf) -> Option<Self> {
+        if !conf.fastcgi.unwrap_or_default() {
+            return None;
+        }
+        let mut index = conf.fastcgi_index.clone().unwrap_or_default();
+        if index.is_empty() {
+            index = FASTCGI_DEFAULT_INDEX.to_string();
+        }
+        Some(Self {
+            root: conf
+                .fastcgi_root
+                .clone()
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
+            index: index.trim_start_matches('/').to_string(),
+            params: parse_params(&conf.fastcgi_params),
+            keepalive: conf.fastcgi_keepalive.unwrap_or_default(),
+            idle_timeout: conf
+                .idle_timeout
+                .unwrap_or(FASTCGI_DEFAULT_IDLE_TIMEOUT),
+            max_idle_connections: conf
+                .keepalive_pool_size
+                .unwrap_or(FASTCGI_MAX_IDLE_CONNECTIONS),
+            idle_connections: Mutex::new(AHashMap::new()),
+        })
+    }
+    /// Resolves the script of request path, the path after `.php` is
+    /// the path info, and the index script is used for other paths
+    /// (front controller).
+    fn resolve_script(&self, path: &str) -> pingora::Result<FastCgiScript> {
+        let path = normalize_script_path(path)?;
+        if let Some(index) = path.find(".php") {
+            let end = index + ".php".len();
+            let rest = &path[end..];
+            if rest.is_empty() || rest.starts_with('/') {
+                return Ok(FastCgiScript {
+                    name: path[..end].to_string(),
+                    path_info: rest.to_string(),
+                });
+            }
+        }
+        if path.ends_with('/') {
+            return Ok(FastCgiScript {
+                name: format!("{path}{}", self.index),
+                ..Default::default()
+            });
+        }
+        Ok(FastCgiScript {
+            name: format!("/{}", self.index),
+            path_info: path,
+        })
+    }
+    /// Gets the params of request, the custom params override the
+    /// default params with the same name.
+    fn get_params(
+        &self,
+        session: &Session,
+        ctx: &State,
+    ) -> pingora::Result<Vec<(String, String)>> {
+        let path = session.req_header().uri.path();
+        let script = self.resolve_script(path)?;
+        let mut params = get_cgi_params(session, ctx);
+        for (name, value) in [
+            ("DOCUMENT_ROOT", self.root.clone()),
+            ("DOCUMENT_URI", path.to_string()),
+            ("SCRIPT_FILENAME", format!("{}{}", self.root, script.name)),
+            ("SCRIPT_NAME", script.name),
+            ("PATH_INFO", script.path_info),
+        ] {
+            set_param(&mut params, name, value);
+        }
+        set_custom_params(&mut params, &self.params, session, ctx);
+        Ok(params)
+    }
+    /// Gets the idle connection of backend or connects to it.
+    async fn get_connection(
+        &self,
+        addr: &str,
+        timeout: Option<Duration>,
+    ) -> std::io::Result<(TcpStream, bool)> {
+        if self.keepalive {
+            while let Some(conn) = self
+                .idle_connections
+                .lock()
+                .ok()
+                .and_then(|mut conns| conns.get_mut(addr)?.pop())
+            {
+                if conn.idle_at.elapsed() > self.idle_timeout {
+                    continue;
+                }
+                // the connection is closed by backend if it's readable
+                let mut buf = [0; 1];
+                match conn.stream.try_read(&mut buf) {
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        return Ok((conn.stream, true));
+                    },
+                    _ => continue,
+                }
+            }
+        }
+        let stream = connect(addr, timeout).await?;
+        Ok((stream, false))
+    }
+    fn release_connection(&self, addr: &str, stream: TcpStream) {
+        if !self.keepalive {
+            return;
+        }
+        if let Ok(mut conns) = self.idle_connections.lock() {
+            let conns = conns.entry(addr.to_string()).or_default();
+            if conns.len() < self.max_idle_connections {
+                conns.push(IdleConnection {
+                    stream,
+                    idle_at: Instant::now(),
+                });
+            }
+        }
+    }
+    /// Proxies the request to fastcgi backend and sends the response to
+    /// client, the request body is streamed as stdin of fastcgi.
+    pub async fn proxy(
+        &self,
+        session: &mut Session,
+        ctx: &mut State,
+        peer: &HttpPeer,
+    ) -> pingora::Result<()> {
+        // the script is resolved before connecting to backend,
+        // so the invalid path is rejected directly
+        let params = self.get_params(session, ctx)?;
+        let addr = peer.address().to_string();
+        let new_upstream_error = |e: std::io::Error| {
+            util::new_internal_error(502, format!("fastcgi({addr}): {e}"))
+        };
+        let (mut stream, reused) = self
+            .get_connection(&addr, peer.options.connection_timeout)
+            .await
+            .map_err(new_upstream_error)?;
+        ctx.upstream_reused = reused;
+        ctx.upstream_address.clone_from(&addr);
+        ctx.upstream_connect_time =
+            util::get_latency(&ctx.upstream_connect_time);
+
+        let mut buf = new_begin_request(self.keepalive);
+        encode_stream(&mut buf, FCGI_PARAMS, &encode_params(&params));
+        encode_record(&mut buf, FCGI_PARAMS, b"");
+        stream.write_all(&buf).await.map_err(new_upstream_error)?;
+        while let Some(data) = session.read_request_body().await? {
+            ctx.payload_size += data.len();
+            if let Some(location) = &ctx.location {
+                location.client_body_size_limit(None, ctx)?;
+            }
+            let mut buf = BytesMut::with_capacity(data.len() + 16);
+            encode_stream(&mut buf, FCGI_STDIN, &data);
+            stream.write_all(&buf).await.map_err(new_upstream_error)?;
+        }
+        let mut buf = BytesMut::with_capacity(8);
+        encode_record(&mut buf, FCGI_STDIN, b"");
+        stream.write_all(&buf).await.map_err(new_upstream_error)?;
+
+        let read_timeout = peer.options.read_timeout;
+        let mut header_buf = BytesMut::new();
+        let mut header_sent = false;
+        loop {
+            let (record_type, content) = with_timeout(
+                read_timeout,
+                "read timeout",
+                read_record(&mut stream),
+            )
+            .await
+            .map_err(new_upstream_error)?;
+            match record_type {
+                FCGI_STDOUT if header_sent => {
+                    if !content.is_empty() {
+                        session
+                            .write_response_body(Some(content), false)
+                            .await?;
+                    }
+                },
+                FCGI_STDOUT => {
+                    header_buf.extend_from_slice(&content);
+                    let Some(result) = parse_cgi_headers(&header_buf) else {
+                        continue;
+                    };
+                    let (mut resp, size) = result?;
+                    if !resp.headers.contains_key(http::header::CONTENT_LENGTH)
+                    {
+                        let chunked = HTTP_HEADER_TRANSFER_CHUNKED.clone();
+                        resp.insert_header(chunked.0, chunked.1)?;
+                    }
+                    ctx.status = Some(resp.status);
+                    ctx.upstream_response_time =
+                        util::get_latency(&ctx.upstream_response_time);
+                    session
+                        .write_response_header(Box::new(resp), false)
+                        .await?;
+                    header_sent = true;
+                    let body = header_buf.split_off(size);
+                    if !body.is_empty() {
+                        session
+                            .write_response_body(Some(body.freeze()), false)
+                            .await?;
+                    }
+                },
+                FCGI_STDERR => {
+                    warn!(
+                        addr,
+                        message = String::from_utf8_lossy(&content).to_string(),
+                        "fastcgi stderr"
+                    );
+                },
+                FCGI_END_REQUEST => break,
+                _ => {
+                    debug!(addr, record_type, "unknown fastcgi record");
+                },
+            }
+        }
+        if !header_sent {
+            return Err(util::new_internal_error(
+                StatusCode::BAD_GATEWAY.as_u16(),
+                format!("fastcgi({addr}): response header is invalid"),
+            ));
+        }
+        session
+            .write_response_body(Some(Bytes::new()), true)
+            .await?;
+        session.finish_body().await?;
+        self.release_connection(&addr, stream);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_params, encode_record, new_begin_request, FastCgi,
+        FastCgiScript, FCGI_STDIN,
+    };
+    use crate::config::UpstreamConf;
+    use bytes::BytesMut;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_fastcgi_records() {
+        assert_eq!(
+            b"\x01\x01\x00\x01\x00\x08\x00\x00\x00\x01\x01\x00\x00\x00\x00\x00",
+            new_begin_request(true).as_ref()
+        );
+        let mut buf = BytesMut::new();
+        encode_record(&mut buf, FCGI_STDIN, b"abc");
+        assert_eq!(
+            b"\x01\x05\x00\x01\x00\x03\x05\x00abc\x00\x00\x00\x00\x00",
+            buf.as_ref()
+        );
+
+        let value = "a".repeat(200);
+        let buf = encode_params(&[
+            ("SCRIPT_NAME".to_string(), "/index.php".to_string()),
+            ("HTTP_X_DATA".to_string(), value.clone()),
+        ]);
+        assert_eq!(b"\x0b\x0aSCRIPT_NAME/index.php", &buf[..23]);
+        assert_eq!(b"\x0b\x80\x00\x00\xc8HTTP_X_DATA", &buf[23..39]);
+        assert_eq!(39 + 200, buf.len());
+    }
+
+    #[test]
+    fn test_fastcgi_resolve_script() {
+        assert_eq!(true, FastCgi::new(&UpstreamConf::default()).is_none());
+        let fastcgi = FastCgi::new(&UpstreamConf {
+            fastcgi: Some(true),
+            fastcgi_root: Some("/var/www/html/".to_string()),
+            fastcgi_params: Some(vec!["APP_ENV production".to_string()]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!("/var/www/html", fastcgi.root);
+        assert_eq!("index.php", fastcgi.index);
+        assert_eq!(
+            vec![("APP_ENV".to_string(), "production".to_string())],
+            fastcgi.params
+        );
+
+        assert_eq!(
+            FastCgiScript {
+                name: "/info.php".to_string(),
+                path_info: "".to_string(),
+            },
+            fastcgi.resolve_script("/info.php").unwrap()
+        );
+        assert_eq!(
+            FastCgiScript {
+                name: "/admin/index.php".to_string(),
+                path_info: "/users/1".to_string(),
+            },
+            fastcgi.resolve_script("/admin/index.php/users/1").unwrap()
+        );
+        assert_eq!(
+            FastCgiScript {
+                name: "/docs/index.php".to_string(),
+                path_info: "".to_string(),
+            },
+            fastcgi.resolve_script("/docs/").unwrap()
+        );
+        assert_eq!(
+            FastCgiScript {
+                name: "/index.php".to_string(),
+                path_info: "/users/1".to_string(),
+            },
+            fastcgi.resolve_script("/users/1").unwrap()
+        );
+        assert_eq!(
+            FastCgiScript {
+                name: "/index.php".to_string(),
+                path_info: "/app.phpx".to_string(),
+            },
+            fastcgi.resolve_script("/app.phpx").unwrap()
+        );
+        assert_eq!(
+            FastCgiScript {
+                name: "/admin/info.php".to_string(),
+                path_info: "".to_string(),
+            },
+            fastcgi.resolve_script("//admin/./info.php").unwrap()
+        );
+        assert_eq!(
+            FastCgiScript {
+                name: "/index.php".to_string(),
+                path_info: "".to_string(),
+            },
+            fastcgi.resolve_script("/").unwrap()
+        );
+
+        // the script outside of root is rejected
+        for path in [
+            "/../etc/passwd.php",
+            "/uploads/../../shell.php",
+            "/%2e%2e/%2e%2e/tmp/shell.php",
+            "/index.php/..",
+            "/shell.php%00.jpg",
+        ] {
+            let err = fastcgi.resolve_script(path).unwrap_err();
+            assert_eq!(
+                &pingora::ErrorType::HTTPStatus(400),
+                err.etype(),
+                "{path}"
+            );
+        }
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes, BytesMut};
+use pingora::connectors::http::Connector;
+use pingora::http::RequestHeader;
+use pingora::lb::health_check::HealthCheck;
+use pingora::lb::Backend;
+use pingora::protocols::http::client::HttpSession;
+use pingora::protocols::ALPN;
+use pingora::upstreams::peer::HttpPeer;
+use pingora::{Error, ErrorType, Result};
+
+const GRPC_HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+// the serving status of `grpc.health.v1.HealthCheckResponse`
+const SERVING_STATUS_SERVING: u64 = 1;
+const ERROR_GRPC_HEALTH_CHECK: ErrorType =
+    ErrorType::Custom("GrpcHealthCheckFail");
+
+/// Encodes the `grpc.health.v1.HealthCheckRequest` with grpc frame,
+/// the message only has the `service` field(1).
+fn encode_health_check_request(service: &str) -> Bytes {
+    let mut message = BytesMut::with_capacity(service.len() + 8);
+    if !service.is_empty() {
+        // field 1, wire type 2(length-delimited)
+        message.put_u8(0x0a);
+        let mut len = service.len();
+        while len >= 0x80 {
+            message.put_u8((len as u8 & 0x7f) | 0x80);
+            len >>= 7;
+        }
+        message.put_u8(len as u8);
+        message.put_slice(service.as_bytes());
+    }
+    let mut buf = BytesMut::with_capacity(message.len() + 5);
+    // uncompressed flag and length of message
+    buf.put_u8(0);
+    buf.put_u32(message.len() as u32);
+    buf.put_slice(&message);
+    buf.freeze()
+}
+
+fn read_varint(data: &[u8], index: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let b = *data.get(*index)?;
+        *index += 1;
+        value |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Parses the serving status of `grpc.health.v1.HealthCheckResponse`
+/// from the grpc frame, the default status is unknown(0).
+fn parse_serving_status(data: &[u8]) -> Option<u64> {
+    if data.len() < 5 || data[0] != 0 {
+        return None;
+    }
+    let len = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+    let message = data.get(5..5 + len as usize)?;
+    let mut status = 0;
+    let mut index = 0;
+    while index < message.len() {
+        let key = read_varint(message, &mut index)?;
+        match key & 0x07 {
+            0 => {
+                let value = read_varint(message, &mut index)?;
+                if key >> 3 == 1 {
+                    status = value;
+                }
+            },
+            2 => {
+                let len = read_varint(message, &mut index)? as usize;
+                index += len;
+            },
+            // the other wire types are not used by the response
+            _ => return None,
+        }
+    }
+    Some(status)
+}
+
+/// The health check of grpc health checking protocol, the backend is
+/// healthy only if the serving status of service is `SERVING`.
+pub struct GrpcHealthCheck {
+    // the service name, empty means the overall health of server
+    service: String,
+    host: String,
+    tls: bool,
+    pub consecutive_success: usize,
+    pub consecutive_failure: usize,
+    pub peer_template: HttpPeer,
+    connector: Connector,
+}
+
+impl GrpcHealthCheck {
+    pub fn new(host: &str, tls: bool, service: &str) -> Self {
+        let mut peer_template = HttpPeer::new("0.0.0.0:1", tls, host.into());
+        // grpc is only over http2, and it's h2c without tls
+        peer_template.options.alpn = ALPN::H2;
+        GrpcHealthCheck {
+            service: service.to_string(),
+            host: host.to_string(),
+            tls,
+            consecutive_success: 1,
+            consecutive_failure: 1,
+            peer_template,
+            connector: Connector::new(None),
+        }
+    }
+    fn new_request(&self, addr: &str) -> Result<RequestHeader> {
+        let scheme = if self.tls { "https" } else { "http" };
+        let authority = if self.host.is_empty() {
+            addr
+        } else {
+            self.host.as_str()
+        };
+        let uri = format!("{scheme}://{authority}{GRPC_HEALTH_CHECK_PATH}");
+        let mut req = RequestHeader::build("POST", uri.as_bytes(), None)?;
+        req.insert_header("Content-Type", "application/grpc")?;
+        req.insert_header("TE", "trailers")?;
+        Ok(req)
+    }
+}
+
+#[async_trait]
+impl HealthCheck for GrpcHealthCheck {
+    fn health_threshold(&self, success: bool) -> usize {
+        if success {
+            self.consecutive_success
+        } else {
+            self.consecutive_failure
+        }
+    }
+    async fn check(&self, target: &Backend) -> Result<()> {
+        let mut peer = self.peer_template.clone();
+        peer._address = target.addr.clone();
+        let (session, _) = self.connector.get_http_session(&peer).await?;
+        let HttpSession::H2(mut session) = session else {
+            return Error::e_explain(
+                ERROR_GRPC_HEALTH_CHECK,
+                "grpc health check requires http2",
+            );
+        };
+        if let Some(read_timeout) = peer.options.read_timeout {
+            session.read_timeout = Some(read_timeout);
+        }
+        let req = self.new_request(&target.addr.to_string())?;
+        session.write_request_header(Box::new(req), false)?;
+        session.write_request_body(
+            encode_health_check_request(&self.service),
+            true,
+        )?;
+        session.read_response_header().await?;
+        let status = session
+            .response_header()
+            .map(|resp| resp.status.as_u16())
+            .unwrap_or_default();
+        if status != 200 {
+            return Error::e_explain(
+                ERROR_GRPC_HEALTH_CHECK,
+                format!("grpc health check status: {status}"),
+            );
+        }
+        let mut body = BytesMut::new();
+        while let Some(data) = session.read_response_body().await? {
+            body.extend_from_slice(&data);
+        }
+        // the grpc error(e.g. unknown service) is returned without message
+        match parse_serving_status(&body) {
+            Some(SERVING_STATUS_SERVING) => Ok(()),
+            Some(serving_status) => Error::e_explain(
+                ERROR_GRPC_HEALTH_CHECK,
+                format!(
+                    "grpc service({}) is not serving, status: {serving_status}",
+                    self.service
+                ),
+            ),
+            None => Error::e_explain(
+                ERROR_GRPC_HEALTH_CHECK,
+                format!(
+                    "grpc service({}) health check response is invalid",
+                    self.service
+                ),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_health_check_request, parse_serving_status, GrpcHealthCheck,
+    };
+    use pingora::protocols::ALPN;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_encode_health_check_request() {
+        assert_eq!(
+            b"\x00\x00\x00\x00\x00",
+            encode_health_check_request("").as_ref()
+        );
+        assert_eq!(
+            b"\x00\x00\x00\x00\x0b\x0a\x09pingap.v1",
+            encode_health_check_request("pingap.v1").as_ref()
+        );
+        let service = "a".repeat(200);
+        let data = encode_health_check_request(&service);
+        // the length of message is 203(tag + 2 bytes varint + service)
+        assert_eq!(b"\x00\x00\x00\x00\xcb\x0a\xc8\x01", &data[..8]);
+    }
+
+    #[test]
+    fn test_parse_serving_status() {
+        // serving
+        assert_eq!(
+            Some(1),
+            parse_serving_status(b"\x00\x00\x00\x00\x02\x08\x01")
+        );
+        // not serving
+        assert_eq!(
+            Some(2),
+            parse_serving_status(b"\x00\x00\x00\x00\x02\x08\x02")
+        );
+        // the default status is unknown
+        assert_eq!(Some(0), parse_serving_status(b"\x00\x00\x00\x00\x00"));
+        assert_eq!(None, parse_serving_status(b""));
+        assert_eq!(None, parse_serving_status(b"\x00\x00\x00\x00\x02\x08"));
+    }
+
+    #[test]
+    fn test_grpc_health_check() {
+        let check = GrpcHealthCheck::new("pingap.io", false, "pingap.v1");
+        assert_eq!(ALPN::H2, check.peer_template.options.alpn);
+        let req = check.new_request("127.0.0.1:3000").unwrap();
+        assert_eq!(
+            "http://pingap.io/grpc.health.v1.Health/Check",
+            req.uri.to_string()
+        );
+
+        let check = GrpcHealthCheck::new("", true, "");
+        let req = check.new_request("127.0.0.1:3000").unwrap();
+        assert_eq!(
+            "https://127.0.0.1:3000/grpc.health.v1.Health/Check",
+            req.uri.to_string()
+        );
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use crate::webhook;
+use ahash::AHashMap;
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use pingora::lb::health_check::HealthCheck;
+use pingora::lb::Backend;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+// the count of health check records kept for each backend
+const HEALTH_HISTORY_SIZE: usize = 20;
+
+/// The result of one health check.
+#[derive(Debug, Clone, Serialize, PartialEq, ToSchema)]
+pub struct HealthCheckRecord {
+    // the check time(seconds)
+    pub checked_at: u64,
+    pub success: bool,
+    // the latency(ms) of health check
+    pub latency: u64,
+    pub error: Option<String>,
+    // the health state of backend is changed by this check
+    pub changed: bool,
+}
+
+/// The health history of backend.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BackendHealthHistory {
+    pub healthy: bool,
+    pub consecutive_failures: usize,
+    pub records: Vec<HealthCheckRecord>,
+}
+
+#[derive(Debug, Clone)]
+struct BackendHealth {
+    healthy: bool,
+    // the count of consecutive opposite results, it's the same as pingora
+    counter: usize,
+    consecutive_failures: usize,
+    records: VecDeque<HealthCheckRecord>,
+}
+
+impl Default for BackendHealth {
+    fn default() -> Self {
+        // the backend is healthy by default in pingora
+        Self {
+            healthy: true,
+            counter: 0,
+            consecutive_failures: 0,
+            records: VecDeque::new(),
+        }
+    }
+}
+
+/// The state change of backend, it's used for webhook notification.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+struct HealthChange {
+    upstream: String,
+    backend: String,
+    previous: &'static str,
+    current: &'static str,
+    consecutive_failures: usize,
+    last_error: Option<String>,
+    latency: u64,
+}
+
+type UpstreamHealthHistories =
+    AHashMap<String, AHashMap<String, BackendHealth>>;
+static HEALTH_HISTORIES: Lazy<Mutex<UpstreamHealthHistories>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+#[inline]
+fn format_health(healthy: bool) -> &'static str {
+    if healthy {
+        "healthy"
+    } else {
+        "unhealthy"
+    }
+}
+
+/// Observes the result of health check, the health state is flipped if
+/// the opposite results reach the threshold, it's the same as pingora.
+/// It returns the change if the state is flipped.
+fn observe_health(
+    upstream: &str,
+    backend: &str,
+    record: HealthCheckRecord,
+    flip_threshold: usize,
+) -> Option<HealthChange> {
+    let mut histories = HEALTH_HISTORIES.lock().ok()?;
+    let health = histories
+        .entry(upstream.to_string())
+        .or_default()
+        .entry(backend.to_string())
+        .or_default();
+    let mut record = record;
+    if record.success {
+        health.consecutive_failures = 0;
+    } else {
+        health.consecutive_failures += 1;
+    }
+    let mut change = None;
+    if health.healthy != record.success {
+        health.counter += 1;
+        if health.counter >= flip_threshold {
+            change = Some(HealthChange {
+                upstream: upstream.to_string(),
+                backend: backend.to_string(),
+                previous: format_health(health.healthy),
+                current: format_health(record.success),
+                consecutive_failures: health.consecutive_failures,
+                last_error: record.error.clone().or_else(|| {
+                    health
+                        .records
+                        .iter()
+                        .rev()
+                        .find_map(|item| item.error.clone())
+                }),
+                latency: record.latency,
+            });
+            health.healthy = record.success;
+            health.counter = 0;
+            record.changed = true;
+        }
+    } else {
+        health.counter = 0;
+    }
+    if health.records.len() >= HEALTH_HISTORY_SIZE {
+        health.records.pop_front();
+    }
+    health.records.push_back(record);
+    change
+}
+
+/// Resets the health state of upstream's backends, the backends are
+/// recreated as healthy when the upstream is updated, the records are kept.
+fn reset_health(upstream: &str) {
+    if let Ok(mut histories) = HEALTH_HISTORIES.lock() {
+        if let Some(backends) = histories.get_mut(upstream) {
+            for health in backends.values_mut() {
+                health.healthy = true;
+                health.counter = 0;
+            }
+        }
+    }
+}
+
+/// Gets the health histories of upstream's backends.
+pub fn get_health_history(
+    upstream: &str,
+) -> HashMap<String, BackendHealthHistory> {
+    let Ok(histories) = HEALTH_HISTORIES.lock() else {
+        return HashMap::new();
+    };
+    let Some(backends) = histories.get(upstream) else {
+        return HashMap::new();
+    };
+    backends
+        .iter()
+        .map(|(addr, health)| {
+            (
+                addr.to_string(),
+                BackendHealthHistory {
+                    healthy: health.healthy,
+                    consecutive_failures: health.consecutive_failures,
+                    records: health.records.iter().cloned().collect(),
+                },
+            )
+        })
+        .collect()
+}
+
+fn notify_health_change(change: &HealthChange) {
+    let last_error = change.last_error.clone().unwrap_or_default();
+    let msg = format!(
+        "upstream {} backend {} becomes {}(previous: {}), consecutive failures: {}, latency: {}ms, last error: {last_error}",
+        change.upstream,
+        change.backend,
+        change.current,
+        change.previous,
+        change.consecutive_failures,
+        change.latency,
+    );
+    let level = if change.current == "healthy" {
+        info!(msg, "backend becomes healthy");
+        webhook::NotificationLevel::Info
+    } else {
+        warn!(msg, "backend becomes unhealthy");
+        webhook::NotificationLevel::Warn
+    };
+    webhook::send_with_details(
+        webhook::SendNotificationParams {
+            category: webhook::NotificationCategory::BackendStatus,
+            level,
+            msg,
+        },
+        serde_json::to_value(change).ok(),
+    );
+}
+
+/// The health check records the results of inner health check,
+/// and notifies when the health state of backend is changed.
+pub struct ObservedHealthCheck {
+    upstream: String,
+    check: Box<dyn HealthCheck + Send + Sync + 'static>,
+}
+
+impl ObservedHealthCheck {
+    pub fn new(
+        upstream: &str,
+        check: Box<dyn HealthCheck + Send + Sync + 'static>,
+    ) -> Self {
+        reset_health(upstream);
+        Self {
+            upstream: upstream.to_string(),
+            check,
+        }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for ObservedHealthCheck {
+    fn health_threshold(&self, success: bool) -> usize {
+        self.check.health_threshold(success)
+    }
+    async fn check(&self, target: &Backend) -> pingora::Result<()> {
+        let start = Instant::now();
+        let result = self.check.check(target).await;
+        let record = HealthCheckRecord {
+            checked_at: util::now().as_secs(),
+            success: result.is_ok(),
+            latency: start.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(|e| e.to_string()),
+            changed: false,
+        };
+        let flip_threshold = self.health_threshold(record.success);
+        if let Some(change) = observe_health(
+            &self.upstream,
+            &target.addr.to_string(),
+            record,
+            flip_threshold,
+        ) {
+            notify_health_change(&change);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_health_history, observe_health, HealthCheckRecord};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_observe_health() {
+        let new_record = |success: bool| HealthCheckRecord {
+            checked_at: 1_700_000_000,
+            success,
+            latency: 5,
+            error: (!success).then(|| "Connection refused".to_string()),
+            changed: false,
+        };
+        let upstream = "health-history";
+        let backend = "127.0.0.1:3000";
+        assert_eq!(
+            true,
+            observe_health(upstream, backend, new_record(false), 2).is_none()
+        );
+        let change =
+            observe_health(upstream, backend, new_record(false), 2).unwrap();
+        assert_eq!("healthy", change.previous);
+        assert_eq!("unhealthy", change.current);
+        assert_eq!(2, change.consecutive_failures);
+        assert_eq!(Some("Connection refused".to_string()), change.last_error);
+
+        // the success result resets the counter
+        assert_eq!(
+            true,
+            observe_health(upstream, backend, new_record(true), 2).is_none()
+        );
+        assert_eq!(
+            true,
+            observe_health(upstream, backend, new_record(false), 2).is_none()
+        );
+        assert_eq!(
+            true,
+            observe_health(upstream, backend, new_record(true), 2).is_none()
+        );
+        let change =
+            observe_health(upstream, backend, new_record(true), 2).unwrap();
+        assert_eq!("healthy", change.current);
+        assert_eq!(0, change.consecutive_failures);
+
+        for _ in 0..30 {
+            observe_health(upstream, backend, new_record(true), 2);
+        }
+        let history = get_health_history(upstream);
+        let history = history.get(backend).unwrap();
+        assert_eq!(true, history.healthy);
+        assert_eq!(20, history.records.len());
+        assert_eq!(true, get_health_history("not-exists").is_empty());
+    }
+}
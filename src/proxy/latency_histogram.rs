@@ -0,0 +1,306 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use once_cell::sync::OnceCell;
+use pingora::proxy::Session;
+use snafu::Snafu;
+use std::sync::Mutex;
+
+// the default buckets(ms) of latency histogram
+const DEFAULT_LATENCY_BUCKETS: [f64; 11] = [
+    5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+const MAX_LATENCY_BUCKETS: usize = 50;
+const EMPTY_LOCATION: &str = "-";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid error {message}"))]
+    Invalid { message: String },
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The category of latency histogram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LatencyCategory {
+    // the latency of whole request
+    Request,
+    // the response time of upstream
+    Upstream,
+}
+
+/// The trace id of the latest observation in bucket, it's used to
+/// jump from the metrics to the trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    pub trace_id: String,
+    pub value: f64,
+    // the timestamp(ms) of observation
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Histogram {
+    // the count of each bucket(not cumulative), the last one is `+Inf`
+    counts: Vec<u64>,
+    exemplars: Vec<Option<Exemplar>>,
+    sum: f64,
+    count: u64,
+}
+
+/// The histogram of location, the counts of buckets are cumulative.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyHistogram {
+    pub category: LatencyCategory,
+    pub location: String,
+    // the upper bound and cumulative count of buckets,
+    // the last bound is `+Inf`
+    pub buckets: Vec<(f64, u64)>,
+    pub exemplars: Vec<Option<Exemplar>>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+struct LatencyHistograms {
+    buckets: Vec<f64>,
+    exemplars: bool,
+    histograms: Mutex<AHashMap<(LatencyCategory, String), Histogram>>,
+}
+
+static LATENCY_HISTOGRAMS: OnceCell<LatencyHistograms> = OnceCell::new();
+
+fn parse_latency_buckets(values: &[f64]) -> Result<Vec<f64>> {
+    if values.is_empty() {
+        return Ok(DEFAULT_LATENCY_BUCKETS.to_vec());
+    }
+    if values.len() > MAX_LATENCY_BUCKETS {
+        return Err(Error::Invalid {
+            message: format!(
+                "latency buckets should be at most {MAX_LATENCY_BUCKETS}"
+            ),
+        });
+    }
+    if values
+        .iter()
+        .any(|value| !value.is_finite() || *value <= 0.0)
+        || values.windows(2).any(|items| items[0] >= items[1])
+    {
+        return Err(Error::Invalid {
+            message: "latency buckets should be positive and increasing"
+                .to_string(),
+        });
+    }
+    Ok(values.to_vec())
+}
+
+/// Validates the buckets of latency histogram.
+pub fn validate_latency_buckets(values: &[f64]) -> Result<()> {
+    parse_latency_buckets(values)?;
+    Ok(())
+}
+
+/// Initializes the latency histograms with the buckets(ms),
+/// the trace id is attached as exemplar if enabled.
+pub fn init_latency_histograms(buckets: &[f64], exemplars: bool) -> Result<()> {
+    let buckets = parse_latency_buckets(buckets)?;
+    let _ = LATENCY_HISTOGRAMS.set(LatencyHistograms {
+        buckets,
+        exemplars,
+        histograms: Mutex::new(AHashMap::new()),
+    });
+    Ok(())
+}
+
+/// Gets the trace id from the `traceparent` header of w3c trace context,
+/// the request id is used if it's not set.
+fn get_trace_id<'a>(session: &'a Session, ctx: &'a State) -> Option<&'a str> {
+    let trace_id =
+        util::get_req_header_value(session.req_header(), "traceparent")
+            .and_then(|value| value.split('-').nth(1))
+            .filter(|value| value.len() == 32);
+    trace_id.or(ctx.request_id.as_deref())
+}
+
+impl LatencyHistograms {
+    fn observe(
+        &self,
+        category: LatencyCategory,
+        location: &str,
+        value: f64,
+        exemplar: Option<Exemplar>,
+    ) {
+        let Ok(mut histograms) = self.histograms.lock() else {
+            return;
+        };
+        let size = self.buckets.len() + 1;
+        let histogram = histograms
+            .entry((category, location.to_string()))
+            .or_insert_with(|| Histogram {
+                counts: vec![0; size],
+                exemplars: vec![None; size],
+                sum: 0.0,
+                count: 0,
+            });
+        let index = self
+            .buckets
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.buckets.len());
+        histogram.counts[index] += 1;
+        if exemplar.is_some() {
+            histogram.exemplars[index] = exemplar;
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+}
+
+/// Records the latency of request and the response time of upstream.
+pub fn record_latency_histogram(session: &Session, ctx: &State) {
+    let Some(histograms) = LATENCY_HISTOGRAMS.get() else {
+        return;
+    };
+    let location = ctx
+        .location
+        .as_ref()
+        .map(|item| item.name.as_str())
+        .unwrap_or(EMPTY_LOCATION);
+    let now = util::now().as_millis() as u64;
+    let trace_id = histograms
+        .exemplars
+        .then(|| get_trace_id(session, ctx))
+        .flatten();
+    let new_exemplar = |value: f64| {
+        trace_id.map(|trace_id| Exemplar {
+            trace_id: trace_id.to_string(),
+            value,
+            timestamp: now,
+        })
+    };
+    let latency = now.saturating_sub(ctx.created_at) as f64;
+    histograms.observe(
+        LatencyCategory::Request,
+        location,
+        latency,
+        new_exemplar(latency),
+    );
+    if let Some(value) = ctx.get_upstream_response_time() {
+        let value = value as f64;
+        histograms.observe(
+            LatencyCategory::Upstream,
+            location,
+            value,
+            new_exemplar(value),
+        );
+    }
+}
+
+/// Gets the latency histograms of locations.
+pub fn get_latency_histograms() -> Vec<LatencyHistogram> {
+    let Some(histograms) = LATENCY_HISTOGRAMS.get() else {
+        return vec![];
+    };
+    let Ok(items) = histograms.histograms.lock() else {
+        return vec![];
+    };
+    let mut result: Vec<LatencyHistogram> = items
+        .iter()
+        .map(|((category, location), histogram)| {
+            let mut total = 0;
+            let buckets = histograms
+                .buckets
+                .iter()
+                .chain([f64::INFINITY].iter())
+                .zip(histogram.counts.iter())
+                .map(|(bound, count)| {
+                    total += count;
+                    (*bound, total)
+                })
+                .collect();
+            LatencyHistogram {
+                category: *category,
+                location: location.clone(),
+                buckets,
+                exemplars: histogram.exemplars.clone(),
+                sum: histogram.sum,
+                count: histogram.count,
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| {
+        (a.category, &a.location).cmp(&(b.category, &b.location))
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_latency_buckets, Exemplar, LatencyCategory, LatencyHistograms,
+    };
+    use ahash::AHashMap;
+    use pretty_assertions::assert_eq;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_parse_latency_buckets() {
+        assert_eq!(11, parse_latency_buckets(&[]).unwrap().len());
+        assert_eq!(
+            vec![10.0, 100.0],
+            parse_latency_buckets(&[10.0, 100.0]).unwrap()
+        );
+        assert_eq!(
+            "Invalid error latency buckets should be positive and increasing",
+            parse_latency_buckets(&[100.0, 10.0])
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(true, parse_latency_buckets(&[0.0, 10.0]).is_err());
+    }
+
+    #[test]
+    fn test_observe_latency() {
+        let histograms = LatencyHistograms {
+            buckets: vec![10.0, 100.0],
+            exemplars: true,
+            histograms: Mutex::new(AHashMap::new()),
+        };
+        let exemplar = Exemplar {
+            trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+            value: 50.0,
+            timestamp: 1_700_000_000_000,
+        };
+        histograms.observe(LatencyCategory::Request, "lo", 5.0, None);
+        histograms.observe(
+            LatencyCategory::Request,
+            "lo",
+            50.0,
+            Some(exemplar.clone()),
+        );
+        histograms.observe(LatencyCategory::Request, "lo", 500.0, None);
+        histograms.observe(LatencyCategory::Request, "lo", 10.0, None);
+
+        let items = histograms.histograms.lock().unwrap();
+        let histogram = items
+            .get(&(LatencyCategory::Request, "lo".to_string()))
+            .unwrap();
+        assert_eq!(vec![2, 1, 1], histogram.counts);
+        assert_eq!(vec![None, Some(exemplar), None], histogram.exemplars);
+        assert_eq!(565.0, histogram.sum);
+        assert_eq!(4, histogram.count);
+    }
+}
@@ -12,23 +12,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::config::{LocationConf, PluginStep};
-use crate::http_extra::{convert_header_value, convert_headers, HttpHeader};
-use crate::plugin::get_plugins;
+use super::access_log::get_access_log_writer;
+use super::logger::Parser;
+use crate::config::{
+    get_override_plugin_name, LocationConf, PluginCategory, PluginStep,
+    MAX_REQUEST_BUFFER_SIZE,
+};
+use crate::http_extra::{
+    convert_header_value, convert_headers, get_variable_value, has_variables,
+    is_dynamic_header_value, replace_variables,
+    HTTP_HEADER_NAME_X_PINGAP_DEBUG, HTTP_HEADER_NAME_X_PINGAP_JOURNAL,
+};
+use crate::plugin::{get_plugins, Plugin};
 use crate::state::State;
 use crate::util;
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
 use bytes::Bytes;
-use once_cell::sync::Lazy;
+use http::{HeaderName, HeaderValue, Method};
+use once_cell::sync::{Lazy, OnceCell};
 use pingora::http::{RequestHeader, ResponseHeader};
 use pingora::proxy::Session;
+use pingora::upstreams::peer::HttpPeer;
 use regex::Regex;
 use snafu::{ResultExt, Snafu};
 use std::collections::HashMap;
 use std::fmt;
-use std::sync::atomic::{AtomicI32, AtomicU64};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use substring::Substring;
 use tracing::{debug, error};
 
@@ -57,6 +70,22 @@ enum PathSelector {
     EqualPath(EqualPath),
     Empty,
 }
+impl PathSelector {
+    #[inline]
+    fn matched(&self, path: &str) -> bool {
+        match self {
+            PathSelector::EqualPath(EqualPath { value }) => value == path,
+            PathSelector::RegexPath(RegexPath { value }) => {
+                value.is_match(path)
+            },
+            PathSelector::PrefixPath(PrefixPath { value }) => {
+                path.starts_with(value)
+            },
+            PathSelector::Empty => true,
+        }
+    }
+}
+
 fn new_path_selector(path: &str) -> Result<PathSelector> {
     let path = path.trim();
     if path.is_empty() {
@@ -85,19 +114,91 @@ fn new_path_selector(path: &str) -> Result<PathSelector> {
     Ok(se)
 }
 
+/// The proxy header is converted once when the location is created,
+/// only the dynamic value(`$xxx` or `:xxx`) is converted for each request.
+struct ProxyHeader {
+    name: HeaderName,
+    value: HeaderValue,
+    dynamic: bool,
+}
+
+impl fmt::Debug for ProxyHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({:?}, {:?})", self.name, self.value)
+    }
+}
+
 pub struct Location {
     pub name: String,
     path: String,
     path_selector: PathSelector,
     hosts: Vec<String>,
     reg_rewrite: Option<(Regex, String)>,
-    proxy_add_headers: Option<Vec<HttpHeader>>,
-    proxy_set_headers: Option<Vec<HttpHeader>>,
+    // the rewrite target contains variables of request
+    rewrite_variables: bool,
+    proxy_add_headers: Option<Vec<ProxyHeader>>,
+    proxy_set_headers: Option<Vec<ProxyHeader>>,
     plugins: Option<Vec<String>>,
+    // the plugins sorted by priority, it's initialized at first use
+    // because the plugins are initialized after locations
+    sorted_plugins: OnceCell<Vec<(String, &'static dyn Plugin)>>,
     pub accepted: AtomicU64,
     pub processing: AtomicI32,
+    // the requests aborted by client before the response is done
+    pub client_aborted: AtomicU64,
     pub upstream: String,
     client_max_body_size: usize,
+    debug_journal: bool,
+    debug_journal_ips: Option<util::IpRules>,
+    // only accessible for the allow-listed ips or internal redirect
+    internal_only: bool,
+    internal_ips: util::IpRules,
+    // the auth plugins are skipped for these paths
+    auth_bypass_paths: Vec<PathSelector>,
+    // honour the `X-Accel-Redirect` of upstream response
+    internal_redirect: bool,
+    // honour the `X-Sendfile` of upstream response,
+    // only the files in this directory can be sent
+    sendfile_root: Option<PathBuf>,
+    // the requests are shed first when the server is overloaded
+    pub low_priority: bool,
+    // the priority to use the reserved concurrency of upstream
+    pub priority: u8,
+    // the upstreams are used in order when the previous one fails
+    fallback_upstreams: Vec<String>,
+    // the response statuses of upstream which trigger the fallback
+    fallback_statuses: Vec<u16>,
+    // the request body is buffered, so the request can be retried
+    // after it has been sent to upstream
+    proxy_request_buffering: bool,
+    // the max size of request body which is buffered
+    proxy_request_buffer_size: usize,
+    // the max retries on other backends of upstream
+    proxy_retries: u8,
+    // the grace period before the upstream request of non-idempotent
+    // method is cancelled when the client aborts
+    client_abort_grace: Option<Duration>,
+    // the ttl of cache for 404 response
+    pub cache_not_found: Option<Duration>,
+    // the last successful response is served for GET request
+    // when the upstream is down
+    pub always_online: bool,
+    // the static content is served when the upstream is down
+    pub upstream_down_fallback: Option<String>,
+    // the access log of location is disabled, e.g. health check
+    pub access_log_disabled: bool,
+    // the access log format of location, the server's one is used if none
+    pub access_log: Option<Parser>,
+    // the access log is written to this file instead of application log
+    pub access_log_writer: Option<crossbeam_channel::Sender<String>>,
+    // the timeouts of upstream peer, they override the upstream's
+    proxy_connect_timeout: Option<Duration>,
+    proxy_read_timeout: Option<Duration>,
+    proxy_write_timeout: Option<Duration>,
+    // the headers of upstream response are removed
+    proxy_hide_headers: HeaderFilter,
+    // only the allowed headers of upstream response are kept
+    proxy_allow_headers: Option<HeaderFilter>,
 }
 
 impl fmt::Display for Location {
@@ -115,18 +216,134 @@ impl fmt::Display for Location {
 
 fn format_headers(
     values: &Option<Vec<String>>,
-) -> Result<Option<Vec<HttpHeader>>> {
+) -> Result<Option<Vec<ProxyHeader>>> {
     if let Some(header_values) = values {
         let arr =
             convert_headers(header_values).map_err(|err| Error::Invalid {
                 message: err.to_string(),
             })?;
-        Ok(Some(arr))
+        Ok(Some(
+            arr.into_iter()
+                .map(|(name, value)| ProxyHeader {
+                    dynamic: is_dynamic_header_value(&value),
+                    name,
+                    value,
+                })
+                .collect(),
+        ))
     } else {
         Ok(None)
     }
 }
 
+// the headers which expose the server details of upstream
+static SENSITIVE_HEADERS: &[&str] = &[
+    "server",
+    "x-powered-by",
+    "x-aspnet-version",
+    "x-aspnetmvc-version",
+    "x-runtime",
+    "x-debug-*",
+];
+// the hop-by-hop headers which should not be forwarded,
+// `connection` and `upgrade` are handled by proxy
+static HOP_BY_HOP_HEADERS: &[&str] = &[
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "te",
+    "trailer",
+];
+// the headers of body framing, they are always kept
+static FRAMING_HEADERS: &[&str] = &[
+    "content-length",
+    "content-type",
+    "content-encoding",
+    "transfer-encoding",
+];
+
+/// Returns `true` if the value is valid for response header filter,
+/// it's a header name, a prefix ends with `*` or the preset
+/// `@sensitive` and `@hop_by_hop`.
+pub fn is_valid_header_filter(value: &str) -> bool {
+    if value.starts_with('@') {
+        return ["@sensitive", "@hop_by_hop"].contains(&value);
+    }
+    let name = value.strip_suffix('*').unwrap_or(value);
+    !name.is_empty() && HeaderName::from_bytes(name.as_bytes()).is_ok()
+}
+
+/// The filter of upstream response headers.
+#[derive(Debug, Default)]
+struct HeaderFilter {
+    names: Vec<String>,
+    prefixes: Vec<String>,
+}
+
+impl HeaderFilter {
+    fn new(values: &[String]) -> Self {
+        let mut filter = Self::default();
+        for value in values.iter() {
+            let value = value.trim().to_lowercase();
+            let items = match value.as_str() {
+                "@sensitive" => SENSITIVE_HEADERS.to_vec(),
+                "@hop_by_hop" => HOP_BY_HOP_HEADERS.to_vec(),
+                value => vec![value],
+            };
+            for item in items {
+                if let Some(prefix) = item.strip_suffix('*') {
+                    filter.prefixes.push(prefix.to_string());
+                } else {
+                    filter.names.push(item.to_string());
+                }
+            }
+        }
+        filter
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.prefixes.is_empty()
+    }
+    #[inline]
+    fn is_match(&self, name: &str) -> bool {
+        self.names.iter().any(|item| item == name)
+            || self.prefixes.iter().any(|item| name.starts_with(item))
+    }
+}
+
+/// Returns `true` if the method is idempotent, the request of it can be
+/// sent to upstream more than once.
+fn is_idempotent(method: &Method) -> bool {
+    [
+        Method::GET,
+        Method::HEAD,
+        Method::PUT,
+        Method::DELETE,
+        Method::OPTIONS,
+        Method::TRACE,
+    ]
+    .contains(method)
+}
+
+/// Loads the content of upstream down fallback, the value starting with
+/// `/`, `~` or `.` is the file path, otherwise it's the inline content.
+fn load_fallback_content(value: &str) -> Option<String> {
+    if !value.starts_with(['/', '~', '.']) {
+        return Some(value.to_string());
+    }
+    let file = util::resolve_path(value);
+    match std::fs::read_to_string(&file) {
+        Ok(content) => Some(content),
+        Err(e) => {
+            error!(
+                error = e.to_string(),
+                file, "read upstream down fallback fail"
+            );
+            None
+        },
+    }
+}
+
 impl Location {
     /// Create a location from config.
     pub fn new(name: &str, conf: &LocationConf) -> Result<Location> {
@@ -137,10 +354,12 @@ impl Location {
         }
         let upstream = conf.upstream.clone().unwrap_or_default();
         let mut reg_rewrite = None;
+        let mut rewrite_variables = false;
         if let Some(value) = &conf.rewrite {
             let arr: Vec<&str> = value.split(' ').collect();
             let value = if arr.len() == 2 { arr[1] } else { "" };
             if let Ok(re) = Regex::new(arr[0]) {
+                rewrite_variables = has_variables(value);
                 reg_rewrite = Some((re, value.to_string()));
             }
         }
@@ -153,6 +372,49 @@ impl Location {
         }
 
         let path = conf.path.clone().unwrap_or_default();
+        let mut auth_bypass_paths = vec![];
+        for item in conf.auth_bypass_paths.iter().flatten() {
+            auth_bypass_paths.push(new_path_selector(item)?);
+        }
+
+        // the overridden plugin is initialized with another name
+        let plugins = conf.plugins.clone().map(|plugins| {
+            plugins
+                .into_iter()
+                .map(|plugin| {
+                    if conf.plugin_overrides.as_ref().is_some_and(|overrides| {
+                        overrides.contains_key(&plugin)
+                    }) {
+                        get_override_plugin_name(&plugin, name)
+                    } else {
+                        plugin
+                    }
+                })
+                .collect()
+        });
+
+        let access_log = conf.access_log.clone().unwrap_or_default();
+        let access_log_disabled = access_log == "off";
+        let access_log_writer = if let Some(file) = &conf.access_log_file {
+            Some(get_access_log_writer(file).map_err(|e| Error::Invalid {
+                message: format!("open access log file fail, {e}"),
+            })?)
+        } else {
+            None
+        };
+
+        let proxy_allow_headers =
+            conf.proxy_allow_headers.as_ref().map(|values| {
+                let mut values = values.clone();
+                // the headers are handled by pingap, so they should be kept
+                if conf.internal_redirect.unwrap_or_default() {
+                    values.push("x-accel-redirect".to_string());
+                }
+                if conf.sendfile_root.is_some() {
+                    values.push("x-sendfile".to_string());
+                }
+                HeaderFilter::new(&values)
+            });
 
         let location = Location {
             name: name.to_string(),
@@ -161,15 +423,76 @@ impl Location {
             hosts,
             upstream,
             reg_rewrite,
-            plugins: conf.plugins.clone(),
+            rewrite_variables,
+            plugins,
+            sorted_plugins: OnceCell::new(),
             accepted: AtomicU64::new(0),
             processing: AtomicI32::new(0),
+            client_aborted: AtomicU64::new(0),
             proxy_add_headers: format_headers(&conf.proxy_add_headers)?,
             proxy_set_headers: format_headers(&conf.proxy_set_headers)?,
             client_max_body_size: conf
                 .client_max_body_size
                 .unwrap_or_default()
                 .as_u64() as usize,
+            debug_journal: conf.debug_journal.unwrap_or_default(),
+            debug_journal_ips: conf
+                .debug_journal_ips
+                .as_ref()
+                .map(|ips| util::IpRules::new(ips)),
+            internal_only: conf.internal_only.unwrap_or_default(),
+            internal_ips: util::IpRules::new(
+                &conf.internal_ips.clone().unwrap_or_default(),
+            ),
+            auth_bypass_paths,
+            internal_redirect: conf.internal_redirect.unwrap_or_default(),
+            sendfile_root: conf
+                .sendfile_root
+                .as_ref()
+                .map(|value| PathBuf::from(util::resolve_path(value))),
+            low_priority: conf.low_priority.unwrap_or_default(),
+            priority: conf.priority.unwrap_or_default(),
+            fallback_upstreams: conf
+                .fallback_upstreams
+                .clone()
+                .unwrap_or_default(),
+            fallback_statuses: conf
+                .fallback_statuses
+                .clone()
+                .unwrap_or_default(),
+            proxy_request_buffering: conf
+                .proxy_request_buffering
+                .unwrap_or_default(),
+            proxy_request_buffer_size: conf
+                .proxy_request_buffer_size
+                .map(|value| value.as_u64())
+                .unwrap_or(MAX_REQUEST_BUFFER_SIZE)
+                .min(MAX_REQUEST_BUFFER_SIZE)
+                as usize,
+            proxy_retries: conf.proxy_retries.unwrap_or_default(),
+            client_abort_grace: conf
+                .client_abort_grace
+                .filter(|grace| !grace.is_zero()),
+            cache_not_found: conf.cache_not_found,
+            always_online: conf.always_online.unwrap_or_default(),
+            upstream_down_fallback: conf
+                .upstream_down_fallback
+                .as_ref()
+                .and_then(|value| load_fallback_content(value)),
+            access_log: if access_log.is_empty() || access_log_disabled {
+                None
+            } else {
+                Some(Parser::from(access_log.as_str()))
+            },
+            access_log_disabled,
+            access_log_writer,
+            proxy_connect_timeout: conf.proxy_connect_timeout,
+            proxy_read_timeout: conf.proxy_read_timeout,
+            proxy_write_timeout: conf.proxy_write_timeout,
+            proxy_hide_headers: HeaderFilter::new(
+                &conf.proxy_hide_headers.clone().unwrap_or_default(),
+            ),
+            proxy_allow_headers,
         };
         debug!(location = location.to_string(), "create a new location");
 
@@ -178,20 +501,8 @@ impl Location {
     /// Return `true` if the host and path match location.
     #[inline]
     pub fn matched(&self, host: &str, path: &str) -> bool {
-        if !self.path.is_empty() {
-            let matched = match &self.path_selector {
-                PathSelector::EqualPath(EqualPath { value }) => value == path,
-                PathSelector::RegexPath(RegexPath { value }) => {
-                    value.is_match(path)
-                },
-                PathSelector::PrefixPath(PrefixPath { value }) => {
-                    path.starts_with(value)
-                },
-                PathSelector::Empty => true,
-            };
-            if !matched {
-                return false;
-            }
+        if !self.path.is_empty() && !self.path_selector.matched(path) {
+            return false;
         }
 
         if self.hosts.is_empty() {
@@ -200,6 +511,165 @@ impl Location {
 
         self.hosts.iter().any(|item| item == host)
     }
+    /// Returns `true` if the debug journal should be recorded,
+    /// it's enabled for all requests of the location, or the request
+    /// has `X-Pingap-Debug` header and comes from the allow-listed ips.
+    #[inline]
+    pub fn is_debug_journal(
+        &self,
+        header: &RequestHeader,
+        remote_addr: Option<&str>,
+    ) -> bool {
+        if self.debug_journal {
+            return true;
+        }
+        let Some(ips) = &self.debug_journal_ips else {
+            return false;
+        };
+        if !header
+            .headers
+            .contains_key(&*HTTP_HEADER_NAME_X_PINGAP_DEBUG)
+        {
+            return false;
+        }
+        // only trust the remote addr, the forwarded ip can be forged
+        remote_addr
+            .map(|addr| ips.is_match(addr).unwrap_or_default())
+            .unwrap_or_default()
+    }
+    /// Returns `true` if the location is accessible for the request,
+    /// the internal-only location is only accessible for the allow-listed ips.
+    #[inline]
+    pub fn is_accessible(&self, remote_addr: Option<&str>) -> bool {
+        if !self.internal_only {
+            return true;
+        }
+        // only trust the remote addr, the forwarded ip can be forged
+        remote_addr
+            .map(|addr| self.internal_ips.is_match(addr).unwrap_or_default())
+            .unwrap_or_default()
+    }
+    /// Returns `true` if the auth plugins should be skipped for the path.
+    #[inline]
+    pub fn is_auth_bypass(&self, path: &str) -> bool {
+        self.auth_bypass_paths
+            .iter()
+            .any(|selector| selector.matched(path))
+    }
+    /// Gets the upstream name of the tier, the tier `0` is the primary upstream
+    /// and the others are the fallback upstreams.
+    #[inline]
+    pub fn get_upstream_name(&self, tier: usize) -> Option<&str> {
+        if tier == 0 {
+            return Some(&self.upstream);
+        }
+        self.fallback_upstreams
+            .get(tier - 1)
+            .map(|item| item.as_str())
+    }
+    /// Returns `true` if the response status of upstream should fallback
+    /// to the next tier upstream.
+    #[inline]
+    pub fn is_fallback_status(&self, tier: usize, status: u16) -> bool {
+        tier < self.fallback_upstreams.len()
+            && self.fallback_statuses.contains(&status)
+    }
+    /// Returns `true` if the request body of the size is buffered,
+    /// so it can be sent again when the request is retried.
+    #[inline]
+    pub fn is_request_body_buffered(&self, size: usize) -> bool {
+        self.proxy_request_buffering && size <= self.proxy_request_buffer_size
+    }
+    /// Returns `true` if the request can be retried on another backend,
+    /// only the idempotent request is retried.
+    #[inline]
+    pub fn can_retry(&self, method: &Method, retries: u8) -> bool {
+        retries < self.proxy_retries && is_idempotent(method)
+    }
+    /// Gets the grace period before the upstream request is cancelled
+    /// when the client aborts, the idempotent request is cancelled
+    /// immediately.
+    #[inline]
+    pub fn get_client_abort_grace(&self, method: &Method) -> Option<Duration> {
+        if is_idempotent(method) {
+            return None;
+        }
+        self.client_abort_grace
+    }
+    /// Returns `true` if the `X-Accel-Redirect` of upstream response is enabled.
+    #[inline]
+    pub fn is_internal_redirect(&self) -> bool {
+        self.internal_redirect
+    }
+    /// Gets the file path of `X-Sendfile`, returns `None` if sendfile
+    /// is disabled or the file is not in the sendfile root.
+    pub fn get_sendfile_path(&self, value: &str) -> Option<PathBuf> {
+        let root = self.sendfile_root.as_ref()?;
+        let file = Path::new(value);
+        if file
+            .components()
+            .any(|item| item == std::path::Component::ParentDir)
+        {
+            return None;
+        }
+        if file.is_absolute() {
+            return file.starts_with(root).then(|| file.to_path_buf());
+        }
+        Some(root.join(file))
+    }
+    /// Gets the real path of sendfile, the symlinks are resolved and
+    /// returns `None` if the real path is not in the sendfile root.
+    pub async fn get_sendfile_real_path(&self, file: &Path) -> Option<PathBuf> {
+        let root = self.sendfile_root.as_ref()?;
+        let root = tokio::fs::canonicalize(root).await.ok()?;
+        let file = tokio::fs::canonicalize(file).await.ok()?;
+        file.starts_with(&root).then_some(file)
+    }
+    /// Filters the headers of upstream response, the hidden headers are
+    /// removed, and only the allowed headers are kept if the allow-list
+    /// is set, the headers of body framing are always kept.
+    pub fn filter_response_headers(&self, header: &mut ResponseHeader) {
+        if self.proxy_hide_headers.is_empty()
+            && self.proxy_allow_headers.is_none()
+        {
+            return;
+        }
+        let names: Vec<HeaderName> = header
+            .headers
+            .keys()
+            .filter(|name| {
+                let name = name.as_str();
+                if FRAMING_HEADERS.contains(&name) {
+                    return false;
+                }
+                if self.proxy_hide_headers.is_match(name) {
+                    return true;
+                }
+                self.proxy_allow_headers
+                    .as_ref()
+                    .map(|filter| !filter.is_match(name))
+                    .unwrap_or_default()
+            })
+            .cloned()
+            .collect();
+        for name in names.iter() {
+            header.remove_header(name);
+        }
+    }
+    /// Sets the timeouts of upstream peer, the timeouts of location
+    /// override the upstream's.
+    #[inline]
+    pub fn set_peer_timeouts(&self, peer: &mut HttpPeer) {
+        if let Some(timeout) = self.proxy_connect_timeout {
+            peer.options.connection_timeout = Some(timeout);
+        }
+        if let Some(timeout) = self.proxy_read_timeout {
+            peer.options.read_timeout = Some(timeout);
+        }
+        if let Some(timeout) = self.proxy_write_timeout {
+            peer.options.write_timeout = Some(timeout);
+        }
+    }
     /// Sets the maximum allowed size of the client request body.
     /// If the size in a request exceeds the configured value, the 413 (Request Entity Too Large) error
     /// is returned to the client.
@@ -232,11 +702,29 @@ impl Location {
     }
     /// Rewrite the path by the rule and returns true.
     /// If the rule is not exists, returns false.
+    /// The target supports the variables of request, e.g. `/$host/$1`,
+    /// and the capture group of regexp has higher priority.
     #[inline]
-    pub fn rewrite(&self, header: &mut RequestHeader) -> bool {
+    pub fn rewrite(&self, header: &mut RequestHeader, ctx: &State) -> bool {
         if let Some((re, value)) = &self.reg_rewrite {
+            let value = if self.rewrite_variables {
+                replace_variables(value, |name| {
+                    if re.capture_names().flatten().any(|item| item == name) {
+                        return None;
+                    }
+                    // the value is escaped, so the `$` of value isn't
+                    // expanded as capture group by regex replacement
+                    Some(
+                        get_variable_value(name, header, ctx)
+                            .unwrap_or_default()
+                            .replace('$', "$$"),
+                    )
+                })
+            } else {
+                value.to_string()
+            };
             let path = header.uri.path();
-            let mut new_path = re.replace(path, value).to_string();
+            let mut new_path = re.replace(path, value.as_str()).to_string();
             if path == new_path {
                 return false;
             }
@@ -266,28 +754,51 @@ impl Location {
         header: &mut RequestHeader,
     ) {
         if let Some(arr) = &self.proxy_set_headers {
-            for (k, v) in arr {
-                if let Some(v) = convert_header_value(v, session, ctx) {
-                    // v validate for HeaderValue, so always no error
-                    let _ = header.insert_header(k, v);
+            for item in arr {
+                let value = if item.dynamic {
+                    convert_header_value(&item.value, session, ctx)
+                        .unwrap_or_else(|| item.value.clone())
                 } else {
-                    // v validate for HeaderValue, so always no error
-                    let _ = header.insert_header(k, v);
-                }
+                    item.value.clone()
+                };
+                // value validate for HeaderValue, so always no error
+                let _ = header.insert_header(item.name.clone(), value);
             }
         }
         if let Some(arr) = &self.proxy_add_headers {
-            for (k, v) in arr {
-                if let Some(v) = convert_header_value(v, session, ctx) {
-                    // v validate for HeaderValue, so always no error
-                    let _ = header.append_header(k, v);
+            for item in arr {
+                let value = if item.dynamic {
+                    convert_header_value(&item.value, session, ctx)
+                        .unwrap_or_else(|| item.value.clone())
                 } else {
-                    // v validate for HeaderValue, so always no error
-                    let _ = header.append_header(k, v);
-                }
+                    item.value.clone()
+                };
+                // value validate for HeaderValue, so always no error
+                let _ = header.append_header(item.name.clone(), value);
             }
         }
     }
+    /// Gets the plugins of location sorted by priority,
+    /// the config order is kept for the same priority.
+    fn get_sorted_plugins(&self) -> Option<&[(String, &'static dyn Plugin)]> {
+        let plugins = self.plugins.as_ref()?;
+        let global_plugins = get_plugins()?;
+        let sorted_plugins = self.sorted_plugins.get_or_init(|| {
+            let mut sorted_plugins: Vec<_> = plugins
+                .iter()
+                .filter_map(|name| {
+                    global_plugins
+                        .get(name)
+                        .map(|plugin| (name.clone(), plugin.as_ref()))
+                })
+                .collect();
+            sorted_plugins.sort_by_key(|(_, plugin)| {
+                std::cmp::Reverse(plugin.priority())
+            });
+            sorted_plugins
+        });
+        Some(sorted_plugins)
+    }
     #[inline]
     pub async fn handle_request_plugin(
         &self,
@@ -295,26 +806,51 @@ impl Location {
         session: &mut Session,
         ctx: &mut State,
     ) -> pingora::Result<bool> {
-        let Some(plugins) = self.plugins.as_ref() else {
-            return Ok(false);
-        };
-        let Some(global_plugins) = get_plugins() else {
+        let Some(plugins) = self.get_sorted_plugins() else {
             return Ok(false);
         };
 
-        for name in plugins.iter() {
-            if let Some(plugin) = global_plugins.get(name) {
-                debug!(name, step = step.to_string(), "handle request plugin");
-                let result = plugin.handle_request(step, session, ctx).await?;
-                if let Some(resp) = result {
-                    // ingore http response status >= 900
-                    if resp.status.as_u16() < 900 {
-                        ctx.status = Some(resp.status);
-                        resp.send(session).await?;
+        for (name, plugin) in plugins.iter() {
+            if !self.auth_bypass_paths.is_empty()
+                && matches!(
+                    plugin.category(),
+                    PluginCategory::BasicAuth
+                        | PluginCategory::KeyAuth
+                        | PluginCategory::Jwt
+                )
+                && self.is_auth_bypass(session.req_header().uri.path())
+            {
+                ctx.add_journal(|| format!("plugin:{name}({step}) bypass"));
+                continue;
+            }
+            debug!(name, step = step.to_string(), "handle request plugin");
+            let result = plugin.handle_request(step, session, ctx).await;
+            if let Err(e) = &result {
+                ctx.add_journal(|| format!("plugin:{name}({step}) error:{e}"));
+            }
+            if let Some(mut resp) = result? {
+                ctx.add_journal(|| {
+                    format!(
+                        "plugin:{name}({step}) response:{}",
+                        resp.status.as_u16()
+                    )
+                });
+                // ingore http response status >= 900
+                if resp.status.as_u16() < 900 {
+                    ctx.status = Some(resp.status);
+                    if let Some(journal) = ctx.get_journal() {
+                        if let Ok(value) = HeaderValue::from_str(&journal) {
+                            resp.headers.get_or_insert_with(Vec::new).push((
+                                HTTP_HEADER_NAME_X_PINGAP_JOURNAL.clone(),
+                                value,
+                            ));
+                        }
                     }
-                    return Ok(true);
+                    resp.send(session).await?;
                 }
+                return Ok(true);
             }
+            ctx.add_journal(|| format!("plugin:{name}({step}) pass"));
         }
         Ok(false)
     }
@@ -326,25 +862,67 @@ impl Location {
         ctx: &mut State,
         upstream_response: &mut ResponseHeader,
     ) -> pingora::Result<Option<Bytes>> {
-        let Some(plugins) = self.plugins.as_ref() else {
-            return Ok(None);
-        };
-        let Some(global_plugins) = get_plugins() else {
+        let Some(plugins) = self.get_sorted_plugins() else {
             return Ok(None);
         };
-        for name in plugins.iter() {
-            if let Some(plugin) = global_plugins.get(name) {
-                debug!(name, step = step.to_string(), "handle response plugin");
-                let data = plugin
-                    .handle_response(step, session, ctx, upstream_response)
-                    .await?;
-                if data.is_some() {
-                    return Ok(data);
-                }
+        for (name, plugin) in plugins.iter() {
+            debug!(name, step = step.to_string(), "handle response plugin");
+            let data = plugin
+                .handle_response(step, session, ctx, upstream_response)
+                .await?;
+            ctx.add_journal(|| format!("plugin:{name}({step}) done"));
+            if data.is_some() {
+                return Ok(data);
             }
         }
         Ok(None)
     }
+    #[inline]
+    pub async fn handle_upstream_request_plugin(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+        upstream_request: &mut RequestHeader,
+    ) -> pingora::Result<()> {
+        let Some(plugins) = self.get_sorted_plugins() else {
+            return Ok(());
+        };
+        for (name, plugin) in plugins.iter() {
+            debug!(
+                name,
+                step = step.to_string(),
+                "handle upstream request plugin"
+            );
+            plugin
+                .handle_upstream_request(step, session, ctx, upstream_request)
+                .await?;
+        }
+        Ok(())
+    }
+    #[inline]
+    pub fn handle_response_body_plugin(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+    ) -> pingora::Result<()> {
+        let Some(plugins) = self.get_sorted_plugins() else {
+            return Ok(());
+        };
+        for (_, plugin) in plugins.iter() {
+            plugin.handle_response_body(
+                step,
+                session,
+                ctx,
+                body,
+                end_of_stream,
+            )?;
+        }
+        Ok(())
+    }
 }
 
 type Locations = AHashMap<String, Arc<Location>>;
@@ -358,6 +936,24 @@ pub fn get_location(name: &str) -> Option<Arc<Location>> {
     LOCATION_MAP.load().get(name).cloned()
 }
 
+/// Gets the accepted, processing and client aborted count of all locations.
+pub fn get_locations_stats() -> HashMap<String, (u64, i32, u64)> {
+    LOCATION_MAP
+        .load()
+        .iter()
+        .map(|(name, lo)| {
+            (
+                name.to_string(),
+                (
+                    lo.accepted.load(Ordering::Relaxed),
+                    lo.processing.load(Ordering::Relaxed),
+                    lo.client_aborted.load(Ordering::Relaxed),
+                ),
+            )
+        })
+        .collect()
+}
+
 pub fn try_init_locations(confs: &HashMap<String, LocationConf>) -> Result<()> {
     let mut locations = AHashMap::new();
     for (name, conf) in confs.iter() {
@@ -368,9 +964,22 @@ pub fn try_init_locations(confs: &HashMap<String, LocationConf>) -> Result<()> {
     Ok(())
 }
 
+/// Gets the snapshot of locations, it's used to roll back the reloading.
+pub(super) fn snapshot_locations() -> Arc<Locations> {
+    LOCATION_MAP.load_full()
+}
+
+/// Restores the locations from snapshot.
+pub(super) fn restore_locations(snapshot: Arc<Locations>) {
+    LOCATION_MAP.store(snapshot);
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{format_headers, new_path_selector, Location, PathSelector};
+    use super::{
+        format_headers, is_valid_header_filter, load_fallback_content,
+        new_path_selector, Location, PathSelector,
+    };
     use crate::config::{LocationConf, PluginStep};
     use crate::plugin::initialize_test_plugins;
     use crate::state::State;
@@ -378,7 +987,9 @@ mod tests {
     use http::Method;
     use pingora::http::{RequestHeader, ResponseHeader};
     use pingora::proxy::Session;
+    use pingora::upstreams::peer::HttpPeer;
     use pretty_assertions::assert_eq;
+    use std::time::Duration;
     use tokio_test::io::Builder;
 
     #[test]
@@ -510,13 +1121,47 @@ mod tests {
         .unwrap();
         let mut req_header =
             RequestHeader::build("GET", b"/users/me?abc=1", None).unwrap();
-        assert_eq!(true, lo.rewrite(&mut req_header));
+        assert_eq!(true, lo.rewrite(&mut req_header, &State::default()));
         assert_eq!("/me?abc=1", req_header.uri.to_string());
 
         let mut req_header =
             RequestHeader::build("GET", b"/api/me?abc=1", None).unwrap();
-        assert_eq!(false, lo.rewrite(&mut req_header));
+        assert_eq!(false, lo.rewrite(&mut req_header, &State::default()));
         assert_eq!("/api/me?abc=1", req_header.uri.to_string());
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some(upstream_name.to_string()),
+                rewrite: Some(
+                    "^/users/(?P<name>.*)$ /$request_method/${name}/$1"
+                        .to_string(),
+                ),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut req_header =
+            RequestHeader::build("GET", b"/users/me?abc=1", None).unwrap();
+        assert_eq!(true, lo.rewrite(&mut req_header, &State::default()));
+        assert_eq!("/GET/me/me?abc=1", req_header.uri.to_string());
+
+        // the capture group of variable value isn't expanded
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some(upstream_name.to_string()),
+                rewrite: Some(
+                    "^/users/(?P<name>.*)$ /$arg_id/${name}".to_string(),
+                ),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut req_header =
+            RequestHeader::build("GET", b"/users/me?id=$1$name", None).unwrap();
+        assert_eq!(true, lo.rewrite(&mut req_header, &State::default()));
+        assert_eq!("/$1$name/me?id=$1$name", req_header.uri.to_string());
     }
 
     #[tokio::test]
@@ -614,6 +1259,472 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_debug_journal() {
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                debug_journal_ips: Some(vec!["10.0.0.0/8".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut req_header =
+            RequestHeader::build("GET", b"/users/v1/me", None).unwrap();
+        assert_eq!(false, lo.is_debug_journal(&req_header, Some("10.1.1.1")));
+
+        req_header.insert_header("X-Pingap-Debug", "1").unwrap();
+        assert_eq!(true, lo.is_debug_journal(&req_header, Some("10.1.1.1")));
+        assert_eq!(
+            false,
+            lo.is_debug_journal(&req_header, Some("192.168.1.1"))
+        );
+        assert_eq!(false, lo.is_debug_journal(&req_header, None));
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                debug_journal: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let req_header =
+            RequestHeader::build("GET", b"/users/v1/me", None).unwrap();
+        assert_eq!(true, lo.is_debug_journal(&req_header, None));
+    }
+
+    #[tokio::test]
+    async fn test_plugin_priority_condition() {
+        initialize_test_plugins();
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                plugins: Some(vec![
+                    "test:mock".to_string(),
+                    "test:mock_condition".to_string(),
+                ]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            "test:mock_condition,test:mock",
+            lo.get_sorted_plugins()
+                .unwrap()
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+
+        for (method, expected) in [("GET", false), ("POST", true)] {
+            let input_header = format!("{method} /condition HTTP/1.1\r\n\r\n");
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            let mut session = Session::new_h1(Box::new(mock_io));
+            session.read_request().await.unwrap();
+            let result = lo
+                .handle_request_plugin(
+                    PluginStep::Request,
+                    &mut session,
+                    &mut State::default(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(expected, result);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_internal_only_auth_bypass() {
+        initialize_test_plugins();
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                plugins: Some(vec!["test:basic_auth".to_string()]),
+                internal_only: Some(true),
+                internal_ips: Some(vec!["10.0.0.0/8".to_string()]),
+                auth_bypass_paths: Some(vec![
+                    "=/healthz".to_string(),
+                    "~^/public/".to_string(),
+                ]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, lo.is_accessible(Some("10.1.1.1")));
+        assert_eq!(false, lo.is_accessible(Some("192.168.1.1")));
+        assert_eq!(false, lo.is_accessible(None));
+
+        assert_eq!(true, lo.is_auth_bypass("/healthz"));
+        assert_eq!(true, lo.is_auth_bypass("/public/logo.png"));
+        assert_eq!(false, lo.is_auth_bypass("/healthz/detail"));
+
+        // the basic auth plugin is skipped
+        let input_header = "GET /healthz HTTP/1.1\r\n\r\n";
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let result = lo
+            .handle_request_plugin(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(false, result);
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, lo.is_accessible(None));
+        assert_eq!(false, lo.is_auth_bypass("/healthz"));
+    }
+
+    #[test]
+    fn test_upstream_fallback() {
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                fallback_upstreams: Some(vec![
+                    "backup".to_string(),
+                    "maintenance".to_string(),
+                ]),
+                fallback_statuses: Some(vec![502, 503]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(Some("charts"), lo.get_upstream_name(0));
+        assert_eq!(Some("backup"), lo.get_upstream_name(1));
+        assert_eq!(Some("maintenance"), lo.get_upstream_name(2));
+        assert_eq!(None, lo.get_upstream_name(3));
+
+        assert_eq!(true, lo.is_fallback_status(0, 502));
+        assert_eq!(false, lo.is_fallback_status(0, 500));
+        assert_eq!(true, lo.is_fallback_status(1, 503));
+        // the last tier has no fallback
+        assert_eq!(false, lo.is_fallback_status(2, 503));
+    }
+
+    #[test]
+    fn test_location_can_retry() {
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                proxy_request_buffering: Some(true),
+                proxy_retries: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, lo.is_request_body_buffered(64 * 1024));
+        assert_eq!(false, lo.is_request_body_buffered(64 * 1024 + 1));
+        assert_eq!(true, lo.can_retry(&Method::GET, 0));
+        assert_eq!(true, lo.can_retry(&Method::PUT, 1));
+        assert_eq!(false, lo.can_retry(&Method::GET, 2));
+        // the non-idempotent request is not retried
+        assert_eq!(false, lo.can_retry(&Method::POST, 0));
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                proxy_request_buffering: Some(true),
+                proxy_request_buffer_size: Some(ByteSize::kib(16)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, lo.is_request_body_buffered(16 * 1024));
+        assert_eq!(false, lo.is_request_body_buffered(16 * 1024 + 1));
+    }
+
+    #[test]
+    fn test_location_client_abort_grace() {
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                client_abort_grace: Some(std::time::Duration::from_secs(5)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            Some(std::time::Duration::from_secs(5)),
+            lo.get_client_abort_grace(&Method::POST)
+        );
+        assert_eq!(
+            Some(std::time::Duration::from_secs(5)),
+            lo.get_client_abort_grace(&Method::PATCH)
+        );
+        // the idempotent request is cancelled immediately
+        assert_eq!(None, lo.get_client_abort_grace(&Method::GET));
+        assert_eq!(None, lo.get_client_abort_grace(&Method::PUT));
+    }
+
+    #[test]
+    fn test_internal_redirect_sendfile() {
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                internal_redirect: Some(true),
+                sendfile_root: Some("/opt/files".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, lo.is_internal_redirect());
+        assert_eq!(
+            "/opt/files/a/b.zip",
+            lo.get_sendfile_path("/opt/files/a/b.zip")
+                .unwrap()
+                .to_string_lossy()
+        );
+        assert_eq!(
+            "/opt/files/a/b.zip",
+            lo.get_sendfile_path("a/b.zip").unwrap().to_string_lossy()
+        );
+        assert_eq!(true, lo.get_sendfile_path("/etc/passwd").is_none());
+        assert_eq!(
+            true,
+            lo.get_sendfile_path("/opt/files/../../etc/passwd")
+                .is_none()
+        );
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(false, lo.is_internal_redirect());
+        assert_eq!(true, lo.get_sendfile_path("/opt/files/a.zip").is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sendfile_real_path() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("a.txt"), b"a").unwrap();
+        std::fs::write(outside.path().join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("link.txt"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("dir"))
+            .unwrap();
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                sendfile_root: Some(root.path().to_string_lossy().to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let file = lo.get_sendfile_path("a.txt").unwrap();
+        assert_eq!(
+            std::fs::canonicalize(root.path().join("a.txt")).unwrap(),
+            lo.get_sendfile_real_path(&file).await.unwrap()
+        );
+        // the symlink to the file outside of root is rejected
+        let file = lo.get_sendfile_path("link.txt").unwrap();
+        assert_eq!(true, lo.get_sendfile_real_path(&file).await.is_none());
+        let file = lo.get_sendfile_path("dir/secret.txt").unwrap();
+        assert_eq!(true, lo.get_sendfile_real_path(&file).await.is_none());
+        // the file is not found
+        let file = lo.get_sendfile_path("b.txt").unwrap();
+        assert_eq!(true, lo.get_sendfile_real_path(&file).await.is_none());
+    }
+
+    #[test]
+    fn test_filter_response_headers() {
+        assert_eq!(true, is_valid_header_filter("X-Debug-*"));
+        assert_eq!(true, is_valid_header_filter("@sensitive"));
+        assert_eq!(false, is_valid_header_filter("@unknown"));
+        assert_eq!(false, is_valid_header_filter("*"));
+        assert_eq!(false, is_valid_header_filter("X Debug"));
+
+        let new_response_header = || {
+            let mut header = ResponseHeader::build(200, None).unwrap();
+            for (name, value) in [
+                ("Server", "nginx"),
+                ("X-Powered-By", "PHP"),
+                ("X-Debug-Token", "abc"),
+                ("X-Accel-Redirect", "/files/a.zip"),
+                ("Content-Type", "text/html"),
+                ("Cache-Control", "no-cache"),
+                ("X-Trace", "123"),
+            ] {
+                header.insert_header(name, value).unwrap();
+            }
+            header
+        };
+        let get_names = |header: &ResponseHeader| {
+            let mut names: Vec<String> =
+                header.headers.keys().map(|name| name.to_string()).collect();
+            names.sort();
+            names.join(",")
+        };
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                proxy_hide_headers: Some(vec![
+                    "@sensitive".to_string(),
+                    "X-Trace".to_string(),
+                ]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut header = new_response_header();
+        lo.filter_response_headers(&mut header);
+        assert_eq!(
+            "cache-control,content-type,x-accel-redirect",
+            get_names(&header)
+        );
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                internal_redirect: Some(true),
+                proxy_allow_headers: Some(vec!["Cache-Control".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut header = new_response_header();
+        lo.filter_response_headers(&mut header);
+        assert_eq!(
+            "cache-control,content-type,x-accel-redirect",
+            get_names(&header)
+        );
+    }
+
+    #[test]
+    fn test_upstream_down_fallback() {
+        assert_eq!(
+            "<p>Maintenance</p>",
+            load_fallback_content("<p>Maintenance</p>").unwrap()
+        );
+        assert_eq!(
+            true,
+            load_fallback_content("/tmp/pingap-not-exists.html").is_none()
+        );
+
+        let file = std::env::temp_dir().join("pingap-fallback.html");
+        std::fs::write(&file, "<p>Offline</p>").unwrap();
+        assert_eq!(
+            "<p>Offline</p>",
+            load_fallback_content(&file.to_string_lossy()).unwrap()
+        );
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                cache_not_found: Some(std::time::Duration::from_secs(60)),
+                always_online: Some(true),
+                upstream_down_fallback: Some(
+                    r#"{"message":"offline"}"#.to_string(),
+                ),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(60, lo.cache_not_found.unwrap().as_secs());
+        assert_eq!(true, lo.always_online);
+        assert_eq!(
+            r#"{"message":"offline"}"#,
+            lo.upstream_down_fallback.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_access_log_override() {
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(false, lo.access_log_disabled);
+        assert_eq!(true, lo.access_log.is_none());
+        assert_eq!(true, lo.access_log_writer.is_none());
+
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                access_log: Some("off".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, lo.access_log_disabled);
+        assert_eq!(true, lo.access_log.is_none());
+
+        let file = std::env::temp_dir().join("pingap-location-access.log");
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                access_log: Some("tiny".to_string()),
+                access_log_file: Some(file.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(false, lo.access_log_disabled);
+        assert_eq!(true, lo.access_log.is_some());
+        assert_eq!(true, lo.access_log_writer.is_some());
+    }
+
+    #[test]
+    fn test_set_peer_timeouts() {
+        let lo = Location::new(
+            "lo",
+            &LocationConf {
+                upstream: Some("charts".to_string()),
+                proxy_read_timeout: Some(Duration::from_secs(30)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut peer = HttpPeer::new("127.0.0.1:3000", false, "".to_string());
+        peer.options.connection_timeout = Some(Duration::from_secs(3));
+        lo.set_peer_timeouts(&mut peer);
+        assert_eq!(
+            Some(Duration::from_secs(3)),
+            peer.options.connection_timeout
+        );
+        assert_eq!(Some(Duration::from_secs(30)), peer.options.read_timeout);
+        assert_eq!(None, peer.options.write_timeout);
+    }
+
     #[tokio::test]
     async fn test_exec_proxy_plugins() {
         initialize_test_plugins();
@@ -12,14 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::http_extra::HOST_NAME_TAG;
+use crate::http_extra::{get_variable_value, HOST_NAME_TAG};
 use crate::state::{get_hostname, State};
 use crate::util;
 use crate::util::{format_byte_size, format_duration};
 use bytes::BytesMut;
+use chrono::format::{Item, StrftimeItems};
+use once_cell::sync::Lazy;
 use pingora::http::ResponseHeader;
 use pingora::proxy::Session;
 use regex::Regex;
+use std::cell::RefCell;
+use std::fmt::Write;
 use substring::Substring;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -51,6 +55,7 @@ pub enum TagCategory {
     PayloadSize,
     PayloadSizeHuman,
     RequestId,
+    Variable,
 }
 
 #[derive(Debug, Clone)]
@@ -94,10 +99,16 @@ fn format_extra_tag(key: &str) -> Option<Tag> {
                     category: TagCategory::Fill,
                     data: Some(get_hostname()),
                 })
-            } else {
+            } else if let Ok(env) = std::env::var(value) {
                 Some(Tag {
                     category: TagCategory::Fill,
-                    data: Some(std::env::var(value).unwrap_or_default()),
+                    data: Some(env),
+                })
+            } else {
+                // the variable of request, it's resolved at request time
+                Some(Tag {
+                    category: TagCategory::Variable,
+                    data: Some(value.to_string()),
                 })
             }
         },
@@ -245,6 +256,16 @@ impl From<&str> for Parser {
     }
 }
 
+// same as the output of `to_rfc3339`, parsed once and reused for each log
+static RFC3339_ITEMS: Lazy<Vec<Item<'static>>> =
+    Lazy::new(|| StrftimeItems::new("%Y-%m-%dT%H:%M:%S%.f%:z").collect());
+
+thread_local! {
+    // the buffer of access log, reused by the requests of the same thread
+    static LOG_BUFFER: RefCell<BytesMut> =
+        RefCell::new(BytesMut::with_capacity(1024));
+}
+
 fn get_resp_header_value<'a>(
     resp_header: &'a ResponseHeader,
     key: &str,
@@ -257,7 +278,21 @@ fn get_resp_header_value<'a>(
 
 impl Parser {
     pub fn format(&self, session: &Session, ctx: &State) -> String {
-        let mut buf = BytesMut::with_capacity(1024);
+        LOG_BUFFER.with(|cell| {
+            let mut buf = std::mem::take(&mut *cell.borrow_mut());
+            buf.clear();
+            buf = self.append_log(buf, session, ctx);
+            let log = std::str::from_utf8(&buf).unwrap_or_default().to_string();
+            *cell.borrow_mut() = buf;
+            log
+        })
+    }
+    fn append_log(
+        &self,
+        mut buf: BytesMut,
+        session: &Session,
+        ctx: &State,
+    ) -> BytesMut {
         let req_header = session.req_header();
         for tag in self.tags.iter() {
             match tag.category {
@@ -322,10 +357,20 @@ impl Parser {
                     buf.extend(value);
                 },
                 TagCategory::When => {
-                    buf.extend(chrono::Local::now().to_rfc3339().as_bytes());
+                    let _ = write!(
+                        buf,
+                        "{}",
+                        chrono::Local::now()
+                            .format_with_items(RFC3339_ITEMS.iter())
+                    );
                 },
                 TagCategory::WhenUtcIso => {
-                    buf.extend(chrono::Utc::now().to_rfc3339().as_bytes());
+                    let _ = write!(
+                        buf,
+                        "{}",
+                        chrono::Utc::now()
+                            .format_with_items(RFC3339_ITEMS.iter())
+                    );
                 },
                 TagCategory::WhenUnix => {
                     buf.extend(
@@ -403,10 +448,19 @@ impl Parser {
                         buf = ctx.append_value(buf, key.as_str());
                     }
                 },
+                TagCategory::Variable => {
+                    if let Some(key) = &tag.data {
+                        if let Some(value) =
+                            get_variable_value(key, req_header, ctx)
+                        {
+                            buf.extend(value.as_bytes());
+                        }
+                    }
+                },
             };
         }
 
-        std::string::String::from_utf8(buf.into()).unwrap_or_default()
+        buf
     }
 }
 
@@ -444,6 +498,10 @@ mod tests {
         let env = format_extra_tag("{$HOME}").unwrap();
         assert_eq!(TagCategory::Fill, env.category);
         assert_eq!(false, env.data.unwrap().is_empty());
+
+        let variable = format_extra_tag("{$upstream_response_time}").unwrap();
+        assert_eq!(TagCategory::Variable, variable.category);
+        assert_eq!("upstream_response_time", variable.data.unwrap());
     }
     #[test]
     fn test_parse_format() {
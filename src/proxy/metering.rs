@@ -0,0 +1,384 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use pingora::proxy::Session;
+use serde::Serialize;
+use snafu::Snafu;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, error};
+
+// the max count of (location, consumer) in a window,
+// the usages of the other consumers are merged into `_other`
+const METERING_MAX_KEYS: usize = 10_000;
+const OTHER_CONSUMER: &str = "_other";
+const UNKNOWN_CONSUMER: &str = "-";
+// the variables of consumer set by the auth plugins
+const DEFAULT_CONSUMERS: [&str; 3] =
+    ["jwt_subject", "basic_auth_user", "key_auth_id"];
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid error {message}"))]
+    Invalid { message: String },
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum MeteringTarget {
+    // append the usage records to the file as json lines
+    File(String),
+    // post the usage records as json lines
+    Http(String),
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Usage {
+    requests: u64,
+    errors: u64,
+    request_bytes: u64,
+    response_bytes: u64,
+}
+
+/// The usage record of a consumer for the location in a window.
+#[derive(Debug, Serialize)]
+struct UsageRecord {
+    window_start: u64,
+    window_end: u64,
+    location: String,
+    consumer: String,
+    requests: u64,
+    errors: u64,
+    request_bytes: u64,
+    response_bytes: u64,
+}
+
+struct Metering {
+    consumers: Vec<String>,
+    // the start time(seconds) of current window
+    window_start: u64,
+    usages: AHashMap<(String, String), Usage>,
+}
+
+static METERING: OnceCell<Mutex<Metering>> = OnceCell::new();
+
+/// Parses the metering target, `http(s)://` posts the records to the url,
+/// otherwise the records are appended to the file.
+fn parse_metering_target(value: &str) -> Result<MeteringTarget> {
+    if value.starts_with("http://") || value.starts_with("https://") {
+        return Ok(MeteringTarget::Http(value.to_string()));
+    }
+    if let Some((scheme, _)) = value.split_once("://") {
+        return Err(Error::Invalid {
+            message: format!("metering scheme({scheme}) is not supported"),
+        });
+    }
+    if value.is_empty() {
+        return Err(Error::Invalid {
+            message: "metering target can't be empty".to_string(),
+        });
+    }
+    Ok(MeteringTarget::File(util::resolve_path(value)))
+}
+
+/// Validates the target of metering.
+pub fn validate_metering(value: &str) -> Result<()> {
+    parse_metering_target(value)?;
+    Ok(())
+}
+
+fn get_consumer<'a>(consumers: &[String], ctx: &'a State) -> &'a str {
+    consumers
+        .iter()
+        .find_map(|name| ctx.get_variable(name))
+        .filter(|value| !value.is_empty())
+        .unwrap_or(UNKNOWN_CONSUMER)
+}
+
+fn add_usage(
+    metering: &mut Metering,
+    location: &str,
+    consumer: &str,
+    usage: &Usage,
+) {
+    let mut key = (location.to_string(), consumer.to_string());
+    if !metering.usages.contains_key(&key)
+        && metering.usages.len() >= METERING_MAX_KEYS
+    {
+        key.1 = OTHER_CONSUMER.to_string();
+    }
+    let value = metering.usages.entry(key).or_default();
+    value.requests += usage.requests;
+    value.errors += usage.errors;
+    value.request_bytes += usage.request_bytes;
+    value.response_bytes += usage.response_bytes;
+}
+
+/// Records the usage of request, the consumer is the first
+/// exists variable of the metering consumers.
+pub fn record_usage(
+    location: &str,
+    session: &Session,
+    ctx: &State,
+    failed: bool,
+) {
+    let Some(metering) = METERING.get() else {
+        return;
+    };
+    let Ok(mut metering) = metering.lock() else {
+        return;
+    };
+    let consumer = get_consumer(&metering.consumers, ctx).to_string();
+    add_usage(
+        &mut metering,
+        location,
+        &consumer,
+        &Usage {
+            requests: 1,
+            errors: failed as u64,
+            request_bytes: ctx.payload_size as u64,
+            response_bytes: session.body_bytes_sent() as u64,
+        },
+    );
+}
+
+/// Takes the usage records of current window and starts a new window.
+fn take_usage_records(now: u64) -> Vec<UsageRecord> {
+    let Some(metering) = METERING.get() else {
+        return vec![];
+    };
+    let Ok(mut metering) = metering.lock() else {
+        return vec![];
+    };
+    let window_start = std::mem::replace(&mut metering.window_start, now);
+    let mut records: Vec<UsageRecord> = std::mem::take(&mut metering.usages)
+        .into_iter()
+        .map(|((location, consumer), usage)| UsageRecord {
+            window_start,
+            window_end: now,
+            location,
+            consumer,
+            requests: usage.requests,
+            errors: usage.errors,
+            request_bytes: usage.request_bytes,
+            response_bytes: usage.response_bytes,
+        })
+        .collect();
+    records.sort_by(|a, b| {
+        (&a.location, &a.consumer).cmp(&(&b.location, &b.consumer))
+    });
+    records
+}
+
+fn format_usage_records(records: &[UsageRecord]) -> String {
+    let mut data = String::new();
+    for record in records.iter() {
+        if let Ok(line) = serde_json::to_string(record) {
+            data.push_str(&line);
+            data.push('\n');
+        }
+    }
+    data
+}
+
+struct MeteringTask {
+    target: MeteringTarget,
+}
+
+impl MeteringTask {
+    async fn export(&self, data: String) -> Result<()> {
+        match &self.target {
+            MeteringTarget::File(file) => {
+                let mut f = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(file)
+                    .map_err(|e| Error::Invalid {
+                        message: e.to_string(),
+                    })?;
+                f.write_all(data.as_bytes()).map_err(|e| Error::Invalid {
+                    message: e.to_string(),
+                })?;
+            },
+            MeteringTarget::Http(url) => {
+                let resp = reqwest::Client::new()
+                    .post(url)
+                    .header("Content-Type", "application/x-ndjson")
+                    .body(data)
+                    .timeout(Duration::from_secs(10))
+                    .send()
+                    .await
+                    .map_err(|e| Error::Invalid {
+                        message: e.to_string(),
+                    })?;
+                if resp.status().as_u16() >= 400 {
+                    return Err(Error::Invalid {
+                        message: format!("metering status: {}", resp.status()),
+                    });
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ServiceTask for MeteringTask {
+    async fn run(&self) -> Option<bool> {
+        let records = take_usage_records(util::now().as_secs());
+        if records.is_empty() {
+            return None;
+        }
+        let count = records.len();
+        match self.export(format_usage_records(&records)).await {
+            Ok(()) => debug!(count, "export usage records success"),
+            // the records of the window are dropped if export fails
+            Err(e) => {
+                error!(
+                    error = e.to_string(),
+                    count, "export usage records fail"
+                )
+            },
+        }
+        None
+    }
+    fn description(&self) -> String {
+        format!("{:?}", self.target)
+    }
+}
+
+/// Creates a service to export the usage of consumers at the interval,
+/// the usages are aggregated by location and consumer in each window.
+pub fn new_metering_service(
+    target: &str,
+    interval: Duration,
+    consumers: &[String],
+) -> Result<CommonServiceTask> {
+    let target = parse_metering_target(target)?;
+    let consumers = if consumers.is_empty() {
+        DEFAULT_CONSUMERS
+            .iter()
+            .map(|item| item.to_string())
+            .collect()
+    } else {
+        consumers.to_vec()
+    };
+    let _ = METERING.set(Mutex::new(Metering {
+        consumers,
+        window_start: util::now().as_secs(),
+        usages: AHashMap::new(),
+    }));
+    Ok(CommonServiceTask::new(
+        "Metering",
+        interval.max(Duration::from_secs(1)),
+        MeteringTask { target },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_usage, format_usage_records, get_consumer, parse_metering_target,
+        Metering, MeteringTarget, Usage, UsageRecord, METERING_MAX_KEYS,
+    };
+    use crate::state::State;
+    use ahash::AHashMap;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_metering_target() {
+        assert_eq!(
+            MeteringTarget::Http("http://127.0.0.1:8082/usages".to_string()),
+            parse_metering_target("http://127.0.0.1:8082/usages").unwrap()
+        );
+        assert_eq!(
+            MeteringTarget::File("/var/log/pingap/usage.log".to_string()),
+            parse_metering_target("/var/log/pingap/usage.log").unwrap()
+        );
+        assert_eq!(
+            "Invalid error metering scheme(kafka) is not supported",
+            parse_metering_target("kafka://127.0.0.1:9092/usages")
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_metering_usage() {
+        let consumers =
+            vec!["jwt_subject".to_string(), "key_auth_id".to_string()];
+        let mut ctx = State::default();
+        assert_eq!("-", get_consumer(&consumers, &ctx));
+        ctx.add_variable("key_auth_id", "8d969eef");
+        assert_eq!("8d969eef", get_consumer(&consumers, &ctx));
+        ctx.add_variable("jwt_subject", "pingap");
+        assert_eq!("pingap", get_consumer(&consumers, &ctx));
+
+        let mut metering = Metering {
+            consumers,
+            window_start: 0,
+            usages: AHashMap::new(),
+        };
+        let usage = Usage {
+            requests: 1,
+            errors: 1,
+            request_bytes: 10,
+            response_bytes: 100,
+        };
+        add_usage(&mut metering, "lo", "pingap", &usage);
+        add_usage(&mut metering, "lo", "pingap", &usage);
+        assert_eq!(
+            Usage {
+                requests: 2,
+                errors: 2,
+                request_bytes: 20,
+                response_bytes: 200,
+            },
+            metering.usages[&("lo".to_string(), "pingap".to_string())]
+        );
+        for i in 0..METERING_MAX_KEYS {
+            add_usage(&mut metering, "lo", &i.to_string(), &usage);
+        }
+        assert_eq!(METERING_MAX_KEYS + 1, metering.usages.len());
+        assert_eq!(
+            true,
+            metering
+                .usages
+                .contains_key(&("lo".to_string(), "_other".to_string()))
+        );
+
+        assert_eq!(
+            r#"{"window_start":1700000000,"window_end":1700000060,"location":"lo","consumer":"pingap","requests":2,"errors":0,"request_bytes":20,"response_bytes":200}
+"#,
+            format_usage_records(&[UsageRecord {
+                window_start: 1_700_000_000,
+                window_end: 1_700_000_060,
+                location: "lo".to_string(),
+                consumer: "pingap".to_string(),
+                requests: 2,
+                errors: 0,
+                request_bytes: 20,
+                response_bytes: 200,
+            }])
+        );
+    }
+}
@@ -12,22 +12,96 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod access_log;
+mod always_online;
+mod capture;
+mod cgi;
+mod clickhouse_log;
+mod config_apply;
+mod connection_log;
 mod dynamic_certificate;
+mod error_template;
+mod error_tracking;
+mod fastcgi;
+mod grpc_health_check;
+mod health_history;
+mod latency_histogram;
 mod location;
 mod logger;
+mod metering;
+mod normalize;
+mod overload;
+mod request_guard;
+mod request_metrics;
+mod s3_origin;
 mod server;
 mod server_conf;
+mod slow_request;
+mod time_series;
+mod tls_fingerprint;
+mod topology;
+mod traffic_replay;
 mod upstream;
+mod wsgi;
 
 // for bench
 #[allow(unused_imports)]
 pub use location::Location;
 
-pub use dynamic_certificate::try_init_certificates;
-pub use location::try_init_locations;
+pub use always_online::AlwaysOnlineRecord;
+pub use capture::{
+    get_capture_har, get_capture_status, start_capture, stop_capture,
+    CaptureParams, CaptureRecord, CaptureStatus,
+};
+pub use clickhouse_log::{
+    new_clickhouse_service, validate_clickhouse, ClickHouseParams,
+};
+pub use config_apply::{
+    apply_config, get_config_apply_report, set_config_apply_report,
+    ConfigApplyPlan, ConfigApplyReport,
+};
+pub use connection_log::{init_connection_log, new_connection_log_task};
+pub use dynamic_certificate::{
+    has_watched_certificates, new_certificate_reload_service,
+    try_init_certificates,
+};
+pub use error_tracking::{init_error_tracking, ErrorTrackingParams};
+pub use health_history::{
+    get_health_history, BackendHealthHistory, HealthCheckRecord,
+};
+pub use latency_histogram::{
+    get_latency_histograms, init_latency_histograms, validate_latency_buckets,
+    Exemplar, LatencyCategory,
+};
+pub use location::{
+    get_locations_stats, is_valid_header_filter, try_init_locations,
+};
 pub use logger::Parser;
+pub use metering::{new_metering_service, validate_metering};
+pub use overload::{get_event_loop_delay, get_shed_requests};
+pub use request_guard::get_rejected_requests;
+pub use request_metrics::{
+    get_request_metrics, get_request_metrics_overflow, init_request_metrics,
+    validate_metrics_path_templates, RequestSeries,
+};
 pub use server::*;
 pub use server_conf::ServerConf;
+pub use slow_request::{
+    get_stuck_requests, init_slow_request_log, new_slow_request_watchdog_task,
+};
+pub use time_series::{
+    get_time_series, TimeSeriesPoint, MAX_TIME_SERIES_MINUTES,
+};
+pub use topology::{get_topology, Topology, TopologyEdge, TopologyNode};
+pub use traffic_replay::{
+    get_replay_status, init_traffic_record, replay_traffic, start_replay,
+    stop_replay, ReplayMismatch, ReplayParams, ReplayStatus, ReplayTarget,
+    TrafficRecord,
+};
 pub use upstream::{
-    is_dns_discovery, new_upstream_health_check_task, try_init_upstreams,
+    drain_backend, get_upstream, get_upstreams_stats, is_dns_discovery,
+    is_xds_discovery, new_upstream_health_check_task, pause_upstream,
+    restore_backend, resume_upstream, switch_upstream, try_init_upstreams,
+    BackendDrain, BackendStats, UpstreamColor, UpstreamConcurrencyGuard,
+    UpstreamPause, UpstreamStats,
 };
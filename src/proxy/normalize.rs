@@ -0,0 +1,296 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use http::{header, Uri};
+use pingora::http::RequestHeader;
+
+/// Checks whether the byte is unreserved character of rfc3986,
+/// the percent-encoded unreserved character is decoded.
+#[inline]
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+#[inline]
+fn from_hex(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes the percent-encoded unreserved characters and uppercases the
+/// hex digits of others, the reserved characters(e.g. `%2F`) keep encoded
+/// because decoding them changes the meaning of path.
+fn decode_percent(path: &str) -> Result<String, String> {
+    let bytes = path.as_bytes();
+    let mut buf = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let b = bytes[index];
+        if b.is_ascii_control() || b == b'\\' {
+            return Err(format!("invalid character {b:#04x} of path"));
+        }
+        if b != b'%' {
+            buf.push(b);
+            index += 1;
+            continue;
+        }
+        let value = bytes
+            .get(index + 1)
+            .and_then(|&h| from_hex(h))
+            .zip(bytes.get(index + 2).and_then(|&l| from_hex(l)))
+            .map(|(h, l)| (h << 4) | l)
+            .ok_or_else(|| "invalid percent-encoding of path".to_string())?;
+        if value == 0 {
+            return Err("percent-encoded null of path".to_string());
+        }
+        if is_unreserved(value) {
+            buf.push(value);
+        } else {
+            buf.extend_from_slice(format!("%{value:02X}").as_bytes());
+        }
+        index += 3;
+    }
+    String::from_utf8(buf).map_err(|e| e.to_string())
+}
+
+/// Normalizes the path of request, it decodes the percent-encoded unreserved
+/// characters, collapses the duplicate slashes and resolves the dot segments.
+/// Returns `None` if the path is not changed, and error if the path is
+/// malformed, e.g. invalid percent-encoding or traversal above root.
+pub fn normalize_path(path: &str) -> Result<Option<String>, String> {
+    // asterisk-form of OPTIONS request
+    if path == "*" {
+        return Ok(None);
+    }
+    if !path.starts_with('/') {
+        return Err("path should start with /".to_string());
+    }
+    let decoded = decode_percent(path)?;
+    let mut segments: Vec<&str> = vec![];
+    let mut trailing_slash = false;
+    for segment in decoded.split('/').skip(1) {
+        trailing_slash = false;
+        match segment {
+            "" | "." => trailing_slash = true,
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err("path traversal above root".to_string());
+                }
+                trailing_slash = true;
+            },
+            _ => segments.push(segment),
+        }
+    }
+    let mut normalized = String::with_capacity(decoded.len());
+    for segment in segments.iter() {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if trailing_slash || normalized.is_empty() {
+        normalized.push('/');
+    }
+    if normalized == path {
+        return Ok(None);
+    }
+    Ok(Some(normalized))
+}
+
+/// Lowercases the host of authority, the userinfo is kept.
+fn lowercase_authority(authority: &str) -> Option<String> {
+    let (userinfo, host) = match authority.rsplit_once('@') {
+        Some((userinfo, host)) => (Some(userinfo), host),
+        None => (None, authority),
+    };
+    if !host.bytes().any(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+    let host = host.to_lowercase();
+    Some(if let Some(userinfo) = userinfo {
+        format!("{userinfo}@{host}")
+    } else {
+        host
+    })
+}
+
+/// Normalizes the request before routing, returns the normalized path if
+/// it's changed, and error message if the request target is malformed.
+/// The host of http/2 request is the authority of uri, so it's normalized
+/// with the `Host` header, and the scheme and authority are kept.
+pub fn normalize_request(
+    header: &mut RequestHeader,
+    path_enabled: bool,
+    host_enabled: bool,
+) -> Result<Option<String>, String> {
+    let mut authority = None;
+    if host_enabled {
+        if let Some(host) = util::get_req_header_value(header, "Host") {
+            if host.bytes().any(|b| b.is_ascii_uppercase()) {
+                let host = host.to_lowercase();
+                let _ = header.insert_header(header::HOST, host);
+            }
+        }
+        authority = header
+            .uri
+            .authority()
+            .and_then(|value| lowercase_authority(value.as_str()));
+    }
+    let path = if path_enabled {
+        normalize_path(header.uri.path())?
+    } else {
+        None
+    };
+    if path.is_none() && authority.is_none() {
+        return Ok(None);
+    }
+    let path_and_query = match (&path, header.uri.query()) {
+        (Some(path), Some(query)) => format!("{path}?{query}"),
+        (Some(path), None) => path.clone(),
+        (None, _) => header
+            .uri
+            .path_and_query()
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| "/".to_string()),
+    };
+    let mut builder = Uri::builder();
+    if let Some(scheme) = header.uri.scheme() {
+        builder = builder.scheme(scheme.clone());
+    }
+    if let Some(authority) = authority
+        .or_else(|| header.uri.authority().map(|value| value.to_string()))
+    {
+        builder = builder.authority(authority);
+    }
+    let uri = builder
+        .path_and_query(path_and_query)
+        .build()
+        .map_err(|e| e.to_string())?;
+    header.set_uri(uri);
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_path, normalize_request};
+    use crate::util;
+    use pingora::http::RequestHeader;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(None, normalize_path("/api/users").unwrap());
+        assert_eq!(None, normalize_path("/api/users/").unwrap());
+        assert_eq!(None, normalize_path("/").unwrap());
+        assert_eq!(None, normalize_path("*").unwrap());
+        assert_eq!(
+            "/api/users",
+            normalize_path("//api///users").unwrap().unwrap()
+        );
+        assert_eq!(
+            "/api/users/",
+            normalize_path("/api/./users/.").unwrap().unwrap()
+        );
+        assert_eq!(
+            "/api/users",
+            normalize_path("/api/admin/../users").unwrap().unwrap()
+        );
+        assert_eq!(
+            "/api/users",
+            normalize_path("/api/%2e%2e/api/%75sers").unwrap().unwrap()
+        );
+        // the reserved characters keep encoded
+        assert_eq!(
+            "/api/a%2Fb",
+            normalize_path("/api/a%2fb").unwrap().unwrap()
+        );
+        assert_eq!(None, normalize_path("/api/a%2Fb").unwrap());
+
+        assert_eq!(
+            "path traversal above root",
+            normalize_path("/api/../../etc/passwd").unwrap_err()
+        );
+        assert_eq!(
+            "path traversal above root",
+            normalize_path("/%2e%2e/etc/passwd").unwrap_err()
+        );
+        assert_eq!(
+            "invalid percent-encoding of path",
+            normalize_path("/api/%zz").unwrap_err()
+        );
+        assert_eq!(
+            "invalid percent-encoding of path",
+            normalize_path("/api/%2").unwrap_err()
+        );
+        assert_eq!(
+            "percent-encoded null of path",
+            normalize_path("/api/%00").unwrap_err()
+        );
+        assert_eq!(
+            "invalid character 0x5c of path",
+            normalize_path("/api\\users").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_normalize_request() {
+        let mut header =
+            RequestHeader::build("GET", b"//api/./users?id=1", None).unwrap();
+        header.insert_header("Host", "Pingap.IO").unwrap();
+        let path = normalize_request(&mut header, true, true).unwrap();
+        assert_eq!("/api/users", path.unwrap());
+        assert_eq!("/api/users?id=1", header.uri.to_string());
+        assert_eq!(
+            "pingap.io",
+            header.headers.get("Host").unwrap().to_str().unwrap()
+        );
+
+        let mut header =
+            RequestHeader::build("GET", b"//api/users", None).unwrap();
+        header.insert_header("Host", "Pingap.IO").unwrap();
+        let path = normalize_request(&mut header, false, false).unwrap();
+        assert_eq!(None, path);
+        assert_eq!("//api/users", header.uri.to_string());
+        assert_eq!(
+            "Pingap.IO",
+            header.headers.get("Host").unwrap().to_str().unwrap()
+        );
+
+        // the request of http/2 without host header
+        let mut header = RequestHeader::build(
+            "GET",
+            b"https://Pingap.IO:8443//api/./users?id=1",
+            None,
+        )
+        .unwrap();
+        let path = normalize_request(&mut header, true, true).unwrap();
+        assert_eq!("/api/users", path.unwrap());
+        assert_eq!(
+            "https://pingap.io:8443/api/users?id=1",
+            header.uri.to_string()
+        );
+        assert_eq!("pingap.io", util::get_host(&header).unwrap());
+
+        // only the authority is changed
+        let mut header =
+            RequestHeader::build("GET", b"https://Pingap.IO/api?id=1", None)
+                .unwrap();
+        let path = normalize_request(&mut header, true, true).unwrap();
+        assert_eq!(None, path);
+        assert_eq!("https://pingap.io/api?id=1", header.uri.to_string());
+    }
+}
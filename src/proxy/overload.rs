@@ -0,0 +1,167 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::http_extra::HttpResponse;
+use crate::util;
+use crate::webhook;
+use bytes::Bytes;
+use http::{header, HeaderValue, StatusCode};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const MONITOR_INTERVAL: Duration = Duration::from_millis(100);
+// the min interval of overload notification
+const NOTIFICATION_INTERVAL: u64 = 60;
+
+static EVENT_LOOP_DELAY: AtomicU64 = AtomicU64::new(0);
+static MONITOR_STARTED: AtomicBool = AtomicBool::new(false);
+static SHED_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static LAST_NOTIFIED_AT: AtomicU64 = AtomicU64::new(0);
+
+/// Starts the event loop delay monitor on current runtime,
+/// it's only started once.
+fn start_event_loop_monitor() {
+    if MONITOR_STARTED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    tokio::spawn(async {
+        loop {
+            let start = Instant::now();
+            tokio::time::sleep(MONITOR_INTERVAL).await;
+            let delay = start.elapsed().saturating_sub(MONITOR_INTERVAL);
+            EVENT_LOOP_DELAY.store(delay.as_millis() as u64, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Gets the latest delay(ms) of event loop.
+pub fn get_event_loop_delay() -> u64 {
+    EVENT_LOOP_DELAY.load(Ordering::Relaxed)
+}
+
+/// Gets the count of requests which are shed by overload protection.
+pub fn get_shed_requests() -> u64 {
+    SHED_REQUESTS.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct OverloadLimit {
+    // the max processing requests, 0 means no limit
+    pub max_processing: i32,
+    // the max delay(ms) of event loop, 0 means no limit
+    pub max_event_loop_delay: u64,
+    // only shed the requests of low priority locations
+    pub low_priority_only: bool,
+}
+
+impl OverloadLimit {
+    /// Returns the reason if the request should be shed.
+    pub fn check(&self, processing: i32, low_priority: bool) -> Option<String> {
+        if self.low_priority_only && !low_priority {
+            return None;
+        }
+        if self.max_processing > 0 && processing > self.max_processing {
+            return Some(format!(
+                "processing {processing} exceeds {}",
+                self.max_processing
+            ));
+        }
+        if self.max_event_loop_delay > 0 {
+            start_event_loop_monitor();
+            let delay = get_event_loop_delay();
+            if delay > self.max_event_loop_delay {
+                return Some(format!(
+                    "event loop delay {delay}ms exceeds {}ms",
+                    self.max_event_loop_delay
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// Records the shed request and sends the overload notification,
+/// the notification is sent at most once per minute.
+pub fn shed_request(server: &str, reason: &str) -> HttpResponse {
+    SHED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    let now = util::now().as_secs();
+    let last_notified_at = LAST_NOTIFIED_AT.load(Ordering::Relaxed);
+    if now.saturating_sub(last_notified_at) >= NOTIFICATION_INTERVAL
+        && LAST_NOTIFIED_AT
+            .compare_exchange(
+                last_notified_at,
+                now,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    {
+        warn!(server, reason, "server is overloaded");
+        webhook::send(webhook::SendNotificationParams {
+            category: webhook::NotificationCategory::Overload,
+            level: webhook::NotificationLevel::Warn,
+            msg: format!("server {server} is overloaded, {reason}"),
+        });
+    }
+    HttpResponse {
+        status: StatusCode::SERVICE_UNAVAILABLE,
+        body: Bytes::from_static(b"Server is overloaded"),
+        headers: Some(vec![(
+            header::RETRY_AFTER,
+            HeaderValue::from_static("1"),
+        )]),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_shed_requests, shed_request, OverloadLimit};
+    use http::StatusCode;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_overload_limit() {
+        let limit = OverloadLimit {
+            max_processing: 10,
+            ..Default::default()
+        };
+        assert_eq!(true, limit.check(10, false).is_none());
+        assert_eq!(
+            "processing 11 exceeds 10",
+            limit.check(11, false).unwrap_or_default()
+        );
+
+        let limit = OverloadLimit {
+            max_processing: 10,
+            low_priority_only: true,
+            ..Default::default()
+        };
+        assert_eq!(true, limit.check(11, false).is_none());
+        assert_eq!(true, limit.check(11, true).is_some());
+    }
+
+    #[test]
+    fn test_shed_request() {
+        let count = get_shed_requests();
+        let resp = shed_request("pingap", "processing 11 exceeds 10");
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, resp.status);
+        assert_eq!(
+            r#"Some([("retry-after", "1")])"#,
+            format!("{:?}", resp.headers)
+        );
+        assert_eq!(true, get_shed_requests() > count);
+    }
+}
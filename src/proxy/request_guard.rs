@@ -0,0 +1,254 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use ahash::AHashMap;
+use http::{header, Method, Version};
+use once_cell::sync::Lazy;
+use pingora::http::RequestHeader;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::warn;
+
+// the rejected count of requests, the key is `server:reason`
+static REJECTED_REQUESTS: Lazy<Mutex<AHashMap<String, u64>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+/// Gets the rejected count of requests by server and reason.
+pub fn get_rejected_requests() -> HashMap<String, u64> {
+    REJECTED_REQUESTS
+        .lock()
+        .map(|rejected| {
+            rejected
+                .iter()
+                .map(|(key, value)| (key.to_string(), *value))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RequestGuard {
+    // reject the protocol anomalies which may be used to smuggle request
+    pub strict: bool,
+    // the max count of request headers, 0 means no limit
+    pub max_header_count: usize,
+    // the max size of request headers, 0 means no limit
+    pub max_header_size: usize,
+}
+
+/// Checks whether the byte is valid token character of header name.
+#[inline]
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric()
+        || matches!(
+            b,
+            b'!' | b'#'
+                | b'$'
+                | b'%'
+                | b'&'
+                | b'\''
+                | b'*'
+                | b'+'
+                | b'-'
+                | b'.'
+                | b'^'
+                | b'_'
+                | b'`'
+                | b'|'
+                | b'~'
+        )
+}
+
+impl RequestGuard {
+    /// Returns the reason if the request should be rejected.
+    pub fn check(&self, header: &RequestHeader) -> Option<&'static str> {
+        let headers = &header.headers;
+        if self.max_header_count > 0 && headers.len() > self.max_header_count {
+            return Some("header_count");
+        }
+        if self.max_header_size > 0 {
+            // name: value\r\n
+            let size: usize = headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len() + 4)
+                .sum();
+            if size > self.max_header_size {
+                return Some("header_size");
+            }
+        }
+        if !self.strict {
+            return None;
+        }
+        for (name, value) in headers.iter() {
+            if !name.as_str().bytes().all(is_token) {
+                return Some("header_name");
+            }
+            if value
+                .as_bytes()
+                .iter()
+                .any(|&b| (b < 0x20 && b != b'\t') || b == 0x7f)
+            {
+                return Some("header_value");
+            }
+        }
+        // the request target of http/1 should be origin-form,
+        // the absolute-form is only for forward proxy
+        if header.version != Version::HTTP_2
+            && (header.uri.scheme().is_some()
+                || header.uri.authority().is_some()
+                || header.method == Method::CONNECT)
+        {
+            return Some("request_target");
+        }
+        let mut content_lengths =
+            headers.get_all(header::CONTENT_LENGTH).iter();
+        if let Some(first) = content_lengths.next() {
+            let valid = !first.is_empty()
+                && first.as_bytes().iter().all(|b| b.is_ascii_digit());
+            // multiple content-length or content-length with transfer-encoding
+            if !valid
+                || content_lengths.next().is_some()
+                || headers.contains_key(header::TRANSFER_ENCODING)
+            {
+                return Some("content_length");
+            }
+        }
+        let mut transfer_encodings =
+            headers.get_all(header::TRANSFER_ENCODING).iter();
+        if let Some(first) = transfer_encodings.next() {
+            // only a single `chunked` is accepted
+            if transfer_encodings.next().is_some()
+                || !first.as_bytes().eq_ignore_ascii_case(b"chunked")
+                || header.version == Version::HTTP_10
+            {
+                return Some("transfer_encoding");
+            }
+        }
+        None
+    }
+}
+
+/// Records the rejected request and creates the error of bad request,
+/// the connection should not be reused after the request is rejected.
+pub fn reject_request(server: &str, reason: &str) -> pingora::BError {
+    if let Ok(mut rejected) = REJECTED_REQUESTS.lock() {
+        *rejected.entry(format!("{server}:{reason}")).or_insert(0) += 1;
+    }
+    warn!(server, reason, "request is rejected by guard");
+    util::new_internal_error(400, format!("Invalid request, {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_rejected_requests, reject_request, RequestGuard};
+    use pingora::http::RequestHeader;
+    use pretty_assertions::assert_eq;
+
+    fn new_header(
+        path: &[u8],
+        headers: &[(&'static str, &str)],
+    ) -> RequestHeader {
+        let mut header = RequestHeader::build("POST", path, None).unwrap();
+        for (name, value) in headers {
+            header.append_header(*name, *value).unwrap();
+        }
+        header
+    }
+
+    #[test]
+    fn test_request_guard() {
+        let guard = RequestGuard {
+            strict: true,
+            max_header_count: 3,
+            max_header_size: 64,
+        };
+        assert_eq!(
+            None,
+            guard.check(&new_header(b"/", &[("Content-Length", "10")]))
+        );
+        assert_eq!(
+            None,
+            guard.check(&new_header(b"/", &[("Transfer-Encoding", "chunked")]))
+        );
+        assert_eq!(
+            Some("content_length"),
+            guard.check(&new_header(
+                b"/",
+                &[("Content-Length", "10"), ("Transfer-Encoding", "chunked")]
+            ))
+        );
+        assert_eq!(
+            Some("content_length"),
+            guard.check(&new_header(
+                b"/",
+                &[("Content-Length", "10"), ("Content-Length", "11")]
+            ))
+        );
+        assert_eq!(
+            Some("content_length"),
+            guard.check(&new_header(b"/", &[("Content-Length", "+10")]))
+        );
+        assert_eq!(
+            Some("transfer_encoding"),
+            guard.check(&new_header(
+                b"/",
+                &[("Transfer-Encoding", "gzip, chunked")]
+            ))
+        );
+        assert_eq!(
+            Some("request_target"),
+            guard.check(&new_header(b"http://pingap.io/", &[]))
+        );
+        assert_eq!(
+            Some("header_count"),
+            guard.check(&new_header(
+                b"/",
+                &[("A", "1"), ("B", "2"), ("C", "3"), ("D", "4")]
+            ))
+        );
+        assert_eq!(
+            Some("header_size"),
+            guard.check(&new_header(
+                b"/",
+                &[("Cookie", "a".repeat(64).as_str())]
+            ))
+        );
+
+        // only the limits are checked if strict is disabled
+        let guard = RequestGuard::default();
+        assert_eq!(
+            None,
+            guard.check(&new_header(
+                b"http://pingap.io/",
+                &[("Content-Length", "10"), ("Transfer-Encoding", "chunked")]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_reject_request() {
+        let err = reject_request("pingap", "content_length");
+        assert_eq!(
+            "HTTPStatus context: Invalid request, content_length cause:  InternalError",
+            err.to_string().trim()
+        );
+        assert_eq!(
+            true,
+            get_rejected_requests()
+                .get("pingap:content_length")
+                .is_some_and(|count| *count >= 1)
+        );
+    }
+}
@@ -0,0 +1,359 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use once_cell::sync::OnceCell;
+use pingora::proxy::Session;
+use snafu::Snafu;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+const DEFAULT_METRICS_MAX_SERIES: usize = 1000;
+// the label value of the series which exceed the limit,
+// and the path which doesn't match any template
+const OTHER_LABEL: &str = "_other";
+const EMPTY_LABEL: &str = "-";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid error {message}"))]
+    Invalid { message: String },
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The template of path, the segment starts with `:` matches any value,
+/// and the last segment `*` matches the rest of path.
+#[derive(Debug, Clone, PartialEq)]
+struct PathTemplate {
+    value: String,
+    segments: Vec<String>,
+}
+
+impl PathTemplate {
+    fn is_match(&self, path: &str) -> bool {
+        let mut segments = path.trim_start_matches('/').split('/');
+        for template in self.segments.iter() {
+            if template == "*" {
+                return true;
+            }
+            let Some(segment) = segments.next() else {
+                return false;
+            };
+            if template.starts_with(':') {
+                if segment.is_empty() {
+                    return false;
+                }
+            } else if template != segment {
+                return false;
+            }
+        }
+        segments.next().is_none()
+    }
+}
+
+fn parse_path_templates(values: &[String]) -> Result<Vec<PathTemplate>> {
+    let mut templates = vec![];
+    for value in values.iter() {
+        if !value.starts_with('/') {
+            return Err(Error::Invalid {
+                message: format!("path template({value}) should start with /"),
+            });
+        }
+        let segments: Vec<String> = value
+            .trim_start_matches('/')
+            .split('/')
+            .map(|item| item.to_string())
+            .collect();
+        if segments
+            .iter()
+            .take(segments.len() - 1)
+            .any(|item| item == "*")
+        {
+            return Err(Error::Invalid {
+                message: format!(
+                    "path template({value}) only supports * as the last segment"
+                ),
+            });
+        }
+        templates.push(PathTemplate {
+            value: value.to_string(),
+            segments,
+        });
+    }
+    Ok(templates)
+}
+
+/// Validates the path templates of metrics.
+pub fn validate_metrics_path_templates(values: &[String]) -> Result<()> {
+    parse_path_templates(values)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    server: String,
+    location: String,
+    upstream: String,
+    status_class: String,
+    path: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct SeriesValue {
+    requests: u64,
+    duration_ms: u64,
+}
+
+struct RequestMetrics {
+    // the path label is added only if the templates are set
+    templates: Vec<PathTemplate>,
+    max_series: usize,
+    series: Mutex<AHashMap<SeriesKey, SeriesValue>>,
+    // the count of requests which are recorded as the other series
+    overflow: AtomicU64,
+}
+
+/// The labels and values of request series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestSeries {
+    pub labels: Vec<(&'static str, String)>,
+    pub requests: u64,
+    pub duration_ms: u64,
+}
+
+static REQUEST_METRICS: OnceCell<RequestMetrics> = OnceCell::new();
+
+impl RequestMetrics {
+    fn get_path_label(&self, path: &str) -> Option<String> {
+        if self.templates.is_empty() {
+            return None;
+        }
+        let value = self
+            .templates
+            .iter()
+            .find(|item| item.is_match(path))
+            .map(|item| item.value.as_str())
+            .unwrap_or(OTHER_LABEL);
+        Some(value.to_string())
+    }
+    fn record(&self, key: SeriesKey, duration_ms: u64) {
+        let Ok(mut series) = self.series.lock() else {
+            return;
+        };
+        let mut key = key;
+        // the new series is merged into the other one if exceeds the limit,
+        // only the status class is kept
+        if !series.contains_key(&key) && series.len() >= self.max_series {
+            self.overflow.fetch_add(1, Ordering::Relaxed);
+            key = SeriesKey {
+                server: OTHER_LABEL.to_string(),
+                location: OTHER_LABEL.to_string(),
+                upstream: OTHER_LABEL.to_string(),
+                status_class: key.status_class,
+                path: key.path.map(|_| OTHER_LABEL.to_string()),
+            };
+        }
+        let value = series.entry(key).or_default();
+        value.requests += 1;
+        value.duration_ms += duration_ms;
+    }
+}
+
+/// Initializes the request metrics, the requests are labeled by server,
+/// location, upstream and status class, the path label is added if
+/// the path templates are set.
+pub fn init_request_metrics(
+    path_templates: &[String],
+    max_series: Option<usize>,
+) -> Result<()> {
+    let templates = parse_path_templates(path_templates)?;
+    let _ = REQUEST_METRICS.set(RequestMetrics {
+        templates,
+        max_series: max_series.unwrap_or(DEFAULT_METRICS_MAX_SERIES).max(1),
+        series: Mutex::new(AHashMap::new()),
+        overflow: AtomicU64::new(0),
+    });
+    Ok(())
+}
+
+fn get_status_class(ctx: &State) -> String {
+    ctx.status
+        .map(|status| format!("{}xx", status.as_u16() / 100))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Records the request to the series of its labels.
+pub fn record_request_metrics(server: &str, session: &Session, ctx: &State) {
+    let Some(metrics) = REQUEST_METRICS.get() else {
+        return;
+    };
+    let location = ctx.location.as_ref();
+    let upstream = ctx
+        .upstream_name
+        .as_deref()
+        .or(location.map(|item| item.upstream.as_str()))
+        .filter(|value| !value.is_empty())
+        .unwrap_or(EMPTY_LABEL);
+    let key = SeriesKey {
+        server: server.to_string(),
+        location: location
+            .map(|item| item.name.as_str())
+            .unwrap_or(EMPTY_LABEL)
+            .to_string(),
+        upstream: upstream.to_string(),
+        status_class: get_status_class(ctx),
+        path: metrics.get_path_label(session.req_header().uri.path()),
+    };
+    let duration_ms =
+        (util::now().as_millis() as u64).saturating_sub(ctx.created_at);
+    metrics.record(key, duration_ms);
+}
+
+/// Gets the series of request metrics, they are sorted by labels.
+pub fn get_request_metrics() -> Vec<RequestSeries> {
+    let Some(metrics) = REQUEST_METRICS.get() else {
+        return vec![];
+    };
+    let Ok(series) = metrics.series.lock() else {
+        return vec![];
+    };
+    let mut items: Vec<RequestSeries> = series
+        .iter()
+        .map(|(key, value)| {
+            let mut labels = vec![
+                ("server", key.server.clone()),
+                ("location", key.location.clone()),
+                ("upstream", key.upstream.clone()),
+                ("status_class", key.status_class.clone()),
+            ];
+            if let Some(path) = &key.path {
+                labels.push(("path", path.clone()));
+            }
+            RequestSeries {
+                labels,
+                requests: value.requests,
+                duration_ms: value.duration_ms,
+            }
+        })
+        .collect();
+    items.sort_by(|a, b| a.labels.cmp(&b.labels));
+    items
+}
+
+/// Gets the count of requests which exceed the limit of series.
+pub fn get_request_metrics_overflow() -> u64 {
+    REQUEST_METRICS
+        .get()
+        .map(|metrics| metrics.overflow.load(Ordering::Relaxed))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_path_templates, RequestMetrics, SeriesKey, SeriesValue};
+    use ahash::AHashMap;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_path_template() {
+        let templates = parse_path_templates(&[
+            "/users/:id".to_string(),
+            "/users/:id/orders/:order".to_string(),
+            "/static/*".to_string(),
+        ])
+        .unwrap();
+        let metrics = RequestMetrics {
+            templates,
+            max_series: 2,
+            series: Mutex::new(AHashMap::new()),
+            overflow: AtomicU64::new(0),
+        };
+        assert_eq!(
+            Some("/users/:id".to_string()),
+            metrics.get_path_label("/users/123")
+        );
+        assert_eq!(
+            Some("/users/:id/orders/:order".to_string()),
+            metrics.get_path_label("/users/123/orders/abc")
+        );
+        assert_eq!(
+            Some("/static/*".to_string()),
+            metrics.get_path_label("/static/js/app.3f2a.js")
+        );
+        assert_eq!(
+            Some("_other".to_string()),
+            metrics.get_path_label("/users/123/profile")
+        );
+        assert_eq!(
+            Some("_other".to_string()),
+            metrics.get_path_label("/users/")
+        );
+
+        assert_eq!(
+            "Invalid error path template(users) should start with /",
+            parse_path_templates(&["users".to_string()])
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "Invalid error path template(/*/users) only supports * as the last segment",
+            parse_path_templates(&["/*/users".to_string()])
+                .unwrap_err()
+                .to_string()
+        );
+
+        // cardinality limit
+        let new_key = |location: &str| SeriesKey {
+            server: "pingap".to_string(),
+            location: location.to_string(),
+            upstream: "charts".to_string(),
+            status_class: "2xx".to_string(),
+            path: Some("/users/:id".to_string()),
+        };
+        metrics.record(new_key("lo1"), 10);
+        metrics.record(new_key("lo2"), 10);
+        metrics.record(new_key("lo3"), 10);
+        metrics.record(new_key("lo4"), 10);
+        metrics.record(new_key("lo1"), 10);
+        assert_eq!(2, metrics.overflow.load(Ordering::Relaxed));
+        let series = metrics.series.lock().unwrap();
+        assert_eq!(3, series.len());
+        assert_eq!(
+            &SeriesValue {
+                requests: 2,
+                duration_ms: 20,
+            },
+            series.get(&new_key("lo1")).unwrap()
+        );
+        assert_eq!(
+            &SeriesValue {
+                requests: 2,
+                duration_ms: 20,
+            },
+            series
+                .get(&SeriesKey {
+                    server: "_other".to_string(),
+                    location: "_other".to_string(),
+                    upstream: "_other".to_string(),
+                    status_class: "2xx".to_string(),
+                    path: Some("_other".to_string()),
+                })
+                .unwrap()
+        );
+    }
+}
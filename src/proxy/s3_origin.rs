@@ -0,0 +1,232 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config::UpstreamConf;
+use crate::plugin::AwsSigV4;
+use crate::util;
+use chrono::{DateTime, Utc};
+use http::Method;
+use pingora::http::{RequestHeader, ResponseHeader};
+
+const S3_INDEX_FILE: &str = "index.html";
+// the request headers of client are not forwarded to the bucket
+const S3_REMOVED_REQUEST_HEADERS: [&str; 3] =
+    ["authorization", "cookie", "x-amz-security-token"];
+
+/// The s3 compatible origin of upstream, the request path is mapped to
+/// the key of bucket and the request is signed with aws signature v4.
+/// The conditional and range headers are forwarded as they are.
+#[derive(Debug)]
+pub struct S3Origin {
+    bucket: String,
+    // the prefix of object key
+    prefix: String,
+    // the host of request, e.g. bucket.s3.us-east-1.amazonaws.com
+    host: String,
+    // the bucket is the first segment of path
+    path_style: bool,
+    // none means the bucket is public
+    signer: Option<AwsSigV4>,
+}
+
+fn get_conf_or_env(value: &Option<String>, env: &str) -> String {
+    match value {
+        Some(value) if !value.is_empty() => value.to_string(),
+        _ => std::env::var(env).unwrap_or_default(),
+    }
+}
+
+impl S3Origin {
+    /// Creates the s3 origin if the bucket of upstream is set,
+    /// the credentials of environment are used if they are not set.
+    pub fn new(conf: &UpstreamConf) -> Option<Self> {
+        let bucket = conf.s3_bucket.clone().unwrap_or_default();
+        if bucket.is_empty() {
+            return None;
+        }
+        // the sni is used as host of virtual hosted style,
+        // otherwise it's the first address of upstream
+        let host = match &conf.sni {
+            Some(sni) if !sni.is_empty() => sni.to_string(),
+            _ => conf
+                .addrs
+                .first()
+                .and_then(|addr| addr.split_whitespace().next())
+                .unwrap_or_default()
+                .to_string(),
+        };
+        let access_key_id =
+            get_conf_or_env(&conf.s3_access_key_id, "AWS_ACCESS_KEY_ID");
+        let secret_access_key = get_conf_or_env(
+            &conf.s3_secret_access_key,
+            "AWS_SECRET_ACCESS_KEY",
+        );
+        let signer = if access_key_id.is_empty() || secret_access_key.is_empty()
+        {
+            None
+        } else {
+            Some(AwsSigV4::new(
+                &access_key_id,
+                &secret_access_key,
+                &get_conf_or_env(&conf.s3_session_token, "AWS_SESSION_TOKEN"),
+                &get_conf_or_env(&conf.s3_region, "AWS_REGION"),
+                "s3",
+            ))
+        };
+        let prefix = conf
+            .s3_prefix
+            .clone()
+            .unwrap_or_default()
+            .trim_matches('/')
+            .to_string();
+
+        Some(Self {
+            bucket,
+            prefix,
+            host,
+            path_style: conf.s3_path_style.unwrap_or_default(),
+            signer,
+        })
+    }
+    /// Gets the path of object, the query is removed to avoid
+    /// the sub resource requests of bucket(e.g. `?acl`).
+    fn get_object_path(&self, path: &str) -> String {
+        let mut key = path.trim_start_matches('/').to_string();
+        if key.is_empty() || key.ends_with('/') {
+            key.push_str(S3_INDEX_FILE);
+        }
+        if !self.prefix.is_empty() {
+            key = format!("{}/{key}", self.prefix);
+        }
+        if self.path_style {
+            format!("/{}/{key}", self.bucket)
+        } else {
+            format!("/{key}")
+        }
+    }
+    /// Converts the upstream request to the request of bucket object,
+    /// only `GET` and `HEAD` are allowed.
+    pub fn handle_request(
+        &self,
+        req: &mut RequestHeader,
+        now: DateTime<Utc>,
+    ) -> pingora::Result<()> {
+        if req.method != Method::GET && req.method != Method::HEAD {
+            return Err(util::new_internal_error(
+                405,
+                format!("Method {} is not allowed by s3 origin", req.method),
+            ));
+        }
+        let path = self.get_object_path(req.uri.path());
+        let uri = path.parse::<http::Uri>().map_err(|e| {
+            util::new_internal_error(400, format!("Invalid object key: {e}"))
+        })?;
+        req.set_uri(uri);
+        for name in S3_REMOVED_REQUEST_HEADERS {
+            let _ = req.remove_header(name);
+        }
+        if !self.host.is_empty() {
+            req.insert_header(http::header::HOST, &self.host)?;
+        }
+        if let Some(signer) = &self.signer {
+            signer.sign(req, now)?;
+        }
+        Ok(())
+    }
+    /// Removes the `x-amz-*` headers of bucket response.
+    pub fn handle_response(&self, resp: &mut ResponseHeader) {
+        let names: Vec<_> = resp
+            .headers
+            .keys()
+            .filter(|name| name.as_str().starts_with("x-amz-"))
+            .cloned()
+            .collect();
+        for name in names {
+            let _ = resp.remove_header(&name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::S3Origin;
+    use crate::config::UpstreamConf;
+    use chrono::DateTime;
+    use pingora::http::{RequestHeader, ResponseHeader};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_s3_origin() {
+        assert_eq!(true, S3Origin::new(&UpstreamConf::default()).is_none());
+
+        let origin = S3Origin::new(&UpstreamConf {
+            addrs: vec!["127.0.0.1:9000".to_string()],
+            s3_bucket: Some("assets".to_string()),
+            s3_prefix: Some("/static/".to_string()),
+            s3_path_style: Some(true),
+            s3_access_key_id: Some("AKIDEXAMPLE".to_string()),
+            s3_secret_access_key: Some("secret".to_string()),
+            s3_region: Some("us-east-1".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!("127.0.0.1:9000", origin.host);
+        assert_eq!(
+            "/assets/static/js/app.js",
+            origin.get_object_path("/js/app.js")
+        );
+        assert_eq!(
+            "/assets/static/docs/index.html",
+            origin.get_object_path("/docs/")
+        );
+
+        let mut req =
+            RequestHeader::build("GET", b"/js/app.js?acl", None).unwrap();
+        req.insert_header("Authorization", "Basic YWRtaW46MTIzMTIz")
+            .unwrap();
+        req.insert_header("Range", "bytes=0-99").unwrap();
+        let now = DateTime::from_timestamp(1440938160, 0).unwrap();
+        origin.handle_request(&mut req, now).unwrap();
+        assert_eq!("/assets/static/js/app.js", req.uri.to_string());
+        assert_eq!("127.0.0.1:9000", req.headers["host"]);
+        assert_eq!("bytes=0-99", req.headers["range"]);
+        assert_eq!(
+            true,
+            req.headers["authorization"]
+                .to_str()
+                .unwrap()
+                .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/s3/aws4_request")
+        );
+
+        let mut req = RequestHeader::build("PUT", b"/js/app.js", None).unwrap();
+        assert_eq!(
+            " HTTPStatus context: Method PUT is not allowed by s3 origin cause:  InternalError",
+            origin.handle_request(&mut req, now).err().unwrap().to_string()
+        );
+
+        let origin = S3Origin::new(&UpstreamConf {
+            addrs: vec!["assets.s3.amazonaws.com:443".to_string()],
+            sni: Some("assets.s3.amazonaws.com".to_string()),
+            s3_bucket: Some("assets".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!("/index.html", origin.get_object_path("/"));
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("x-amz-request-id", "123").unwrap();
+        resp.insert_header("ETag", "\"abc\"").unwrap();
+        origin.handle_response(&mut resp);
+        assert_eq!(1, resp.headers.len());
+    }
+}
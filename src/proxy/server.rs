@@ -12,35 +12,68 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::access_log::write_access_log;
+use super::always_online::{
+    get_always_online_key, get_always_online_response, new_always_online_record,
+};
+use super::capture::{finish_capture_record, new_capture_record};
+use super::clickhouse_log::export_clickhouse_log;
+use super::connection_log::record_connection;
 use super::dynamic_certificate::DynamicCertificate;
+use super::error_template::{
+    get_error_content_type, is_accept_json, render_error_template,
+    ErrorTemplates,
+};
+use super::error_tracking::capture_server_error;
+use super::latency_histogram::record_latency_histogram;
 use super::logger::Parser;
-use super::upstream::get_upstream;
+use super::metering::record_usage;
+use super::normalize::normalize_request;
+use super::overload::{shed_request, OverloadLimit};
+use super::request_guard::{reject_request, RequestGuard};
+use super::request_metrics::record_request_metrics;
+use super::slow_request::{
+    log_slow_request, unwatch_request, update_watched_upstream, watch_request,
+};
+use super::time_series::{record_time_series, RequestSample};
+use super::tls_fingerprint::get_tls_fingerprint;
+use super::traffic_replay::{finish_traffic_record, new_traffic_record};
+use super::upstream::{get_active_upstream, get_upstream, Upstream};
 use super::ServerConf;
 use crate::acme::get_certificate_info;
 use crate::acme::CertificateInfo;
 use crate::acme::{get_lets_encrypt_cert, handle_lets_encrypt};
+use crate::cache::{self, CacheRequest};
+use crate::cluster;
 use crate::config;
-use crate::config::PluginStep;
-use crate::http_extra::{HttpResponse, HTTP_HEADER_NAME_X_REQUEST_ID};
-use crate::plugin::get_plugins;
+use crate::config::{NotFoundAction, PluginStep};
+use crate::http_extra::{
+    HttpResponse, HTTP_HEADER_NAME_X_ACCEL_REDIRECT,
+    HTTP_HEADER_NAME_X_CACHE_LOCK, HTTP_HEADER_NAME_X_CACHE_LOOKUP,
+    HTTP_HEADER_NAME_X_CACHE_STATUS, HTTP_HEADER_NAME_X_REQUEST_ID,
+    HTTP_HEADER_NAME_X_SENDFILE,
+};
+use crate::plugin::{get_plugins, send_file};
 use crate::proxy::dynamic_certificate::TlsSettingParams;
 use crate::proxy::location::get_location;
+use crate::sink;
 use crate::state::CompressionStat;
-use crate::state::State;
+use crate::state::{set_profiling_tags, InternalRedirect, State};
 use crate::util;
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
-use http::StatusCode;
+use http::{HeaderValue, Method, StatusCode};
 use once_cell::sync::Lazy;
 use pingora::apps::HttpServerOptions;
 use pingora::cache::cache_control::CacheControl;
 use pingora::cache::cache_control::DirectiveValue;
 use pingora::cache::cache_control::InterpretCacheControl;
 use pingora::cache::filters::resp_cacheable;
+use pingora::cache::key::CacheHashKey;
 use pingora::cache::{
-    CacheKey, CacheMetaDefaults, NoCacheReason, RespCacheable,
+    CacheKey, CacheMeta, CacheMetaDefaults, NoCacheReason, RespCacheable,
 };
 use pingora::http::{RequestHeader, ResponseHeader};
 use pingora::listeners::TcpSocketOptions;
@@ -55,10 +88,11 @@ use pingora::services::listening::Service;
 use pingora::upstreams::peer::{HttpPeer, Peer};
 use snafu::Snafu;
 use std::collections::HashMap;
+use std::os::fd::{BorrowedFd, OwnedFd};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use tracing::{debug, error, info};
 
 #[derive(Debug, Snafu)]
@@ -98,6 +132,17 @@ pub fn try_init_server_locations(
     Ok(())
 }
 
+/// Gets the snapshot of server locations, it's used to roll back
+/// the reloading.
+pub(super) fn snapshot_server_locations() -> Arc<ServerLocations> {
+    LOCATION_MAP.load_full()
+}
+
+/// Restores the server locations from snapshot.
+pub(super) fn restore_server_locations(snapshot: Arc<ServerLocations>) {
+    LOCATION_MAP.store(snapshot);
+}
+
 #[inline]
 fn get_server_locations(name: &str) -> Option<Arc<Vec<String>>> {
     LOCATION_MAP.load().get(name).cloned()
@@ -110,20 +155,30 @@ pub struct Server {
     accepted: AtomicU64,
     processing: AtomicI32,
     log_parser: Option<Parser>,
-    error_template: String,
+    error_templates: ErrorTemplates,
     threads: Option<usize>,
     tls_cert: Option<Vec<u8>>,
     tls_key: Option<Vec<u8>>,
+    tls_files: Option<(String, String)>,
     tls_cipher_list: Option<String>,
     tls_ciphersuites: Option<String>,
     tls_min_version: Option<String>,
     tls_max_version: Option<String>,
     enbaled_h2: bool,
     lets_encrypt_enabled: bool,
+    // redirect all requests to the https port
+    https_redirect_port: Option<u16>,
     global_certificates: bool,
     certificate_file: PathBuf,
     tls_from_lets_encrypt: bool,
     tcp_socket_options: Option<TcpSocketOptions>,
+    slow_request_threshold: Option<u64>,
+    overload: Option<OverloadLimit>,
+    normalize_path: bool,
+    normalize_host: bool,
+    request_guard: Option<RequestGuard>,
+    // the handling of request which doesn't match any location
+    not_found: NotFoundAction,
 }
 
 pub struct ServerServices {
@@ -134,6 +189,20 @@ pub struct ServerServices {
 const META_DEFAULTS: CacheMetaDefaults =
     CacheMetaDefaults::new(|_| Some(1), 1, 1);
 
+// the non-standard status of nginx, the client closes the connection
+// before the response is done
+const CLIENT_CLOSED_REQUEST: u16 = 499;
+
+// the error to drop the upstream response of internal redirect, it isn't
+// the failure of proxy, and the redirect is served by fail_to_proxy
+const INTERNAL_REDIRECT: pingora::ErrorType =
+    pingora::ErrorType::Custom("InternalRedirect");
+
+#[inline]
+fn is_internal_redirect_error(e: &pingora::Error) -> bool {
+    e.etype() == &INTERNAL_REDIRECT
+}
+
 impl Server {
     /// Create a new server for http proxy.
     pub fn new(conf: &ServerConf) -> Result<Self> {
@@ -151,6 +220,34 @@ impl Server {
             } else {
                 None
             };
+        let overload = if conf.overload_max_processing.is_some()
+            || conf.overload_max_event_loop_delay.is_some()
+        {
+            Some(OverloadLimit {
+                max_processing: conf
+                    .overload_max_processing
+                    .unwrap_or_default(),
+                max_event_loop_delay: conf
+                    .overload_max_event_loop_delay
+                    .map(|value| value.as_millis() as u64)
+                    .unwrap_or_default(),
+                low_priority_only: conf.overload_low_priority_only,
+            })
+        } else {
+            None
+        };
+        let request_guard = if conf.strict_request
+            || conf.max_header_count.is_some()
+            || conf.max_header_size.is_some()
+        {
+            Some(RequestGuard {
+                strict: conf.strict_request,
+                max_header_count: conf.max_header_count.unwrap_or_default(),
+                max_header_size: conf.max_header_size.unwrap_or_default(),
+            })
+        } else {
+            None
+        };
         let s = Server {
             name: conf.name.clone(),
             admin: conf.admin,
@@ -158,27 +255,52 @@ impl Server {
             processing: AtomicI32::new(0),
             addr: conf.addr.clone(),
             log_parser: p,
-            error_template: conf.error_template.clone(),
+            error_templates: ErrorTemplates::new(
+                &conf.error_template,
+                conf.error_template_dir.as_deref(),
+            ),
             tls_key: conf.tls_key.clone(),
             tls_cert: conf.tls_cert.clone(),
+            tls_files: conf.tls_files.clone(),
             tls_cipher_list: conf.tls_cipher_list.clone(),
             tls_ciphersuites: conf.tls_ciphersuites.clone(),
             tls_min_version: conf.tls_min_version.clone(),
             tls_max_version: conf.tls_max_version.clone(),
             threads: conf.threads,
             lets_encrypt_enabled: false,
+            https_redirect_port: None,
             certificate_file: conf.get_certificate_file(),
             global_certificates: conf.global_certificates,
             enbaled_h2: conf.enbaled_h2,
             tcp_socket_options,
             tls_from_lets_encrypt: conf.lets_encrypt.is_some(),
+            slow_request_threshold: conf
+                .slow_request_threshold
+                .map(|value| value.as_millis() as u64),
+            overload,
+            normalize_path: conf.normalize_path,
+            normalize_host: conf.normalize_host,
+            request_guard,
+            not_found: conf.not_found.clone(),
         };
         Ok(s)
     }
+    /// Sets the profiling tags of current thread for the request.
+    #[inline]
+    fn set_request_profiling_tags(&self, ctx: &State) {
+        if let Some(location) = &ctx.location {
+            set_profiling_tags(&self.name, &location.name);
+        }
+    }
     /// Enable lets encrypt proxy plugin for `/.well-known/acme-challenge` handle.
     pub fn enable_lets_encrypt(&mut self) {
         self.lets_encrypt_enabled = true;
     }
+    /// Enable redirect to https, the requests except acme challenge
+    /// are redirected to the port with `301`.
+    pub fn enable_https_redirect(&mut self, port: u16) {
+        self.https_redirect_port = Some(port);
+    }
 
     /// Add TCP/TLS listening endpoint.
     pub fn run(
@@ -217,7 +339,7 @@ impl Server {
                     tls_cert_info = Some(info)
                 }
 
-                let d = DynamicCertificate::new(
+                let mut d = DynamicCertificate::new(
                     &cert,
                     &tls_key.unwrap_or_default(),
                 )
@@ -225,6 +347,9 @@ impl Server {
                     category: "tls".to_string(),
                     message: e.to_string(),
                 })?;
+                if let Some((cert_file, key_file)) = &self.tls_files {
+                    d = d.watch_files(cert_file, key_file);
+                }
                 dynamic_cert = Some(d);
             };
         }
@@ -313,6 +438,98 @@ impl Server {
         }
         Ok(())
     }
+    /// Gets the internal redirect from the `X-Sendfile` and `X-Accel-Redirect`
+    /// of upstream response, the request uri is changed to the path of
+    /// `X-Accel-Redirect` for matching the location.
+    fn get_internal_redirect(
+        &self,
+        session: &mut Session,
+        upstream_response: &ResponseHeader,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<InternalRedirect>> {
+        let Some(location) = ctx.location.clone() else {
+            return Ok(None);
+        };
+        let get_header_value = |name: &http::HeaderName| {
+            upstream_response
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        if let Some(value) = get_header_value(&HTTP_HEADER_NAME_X_SENDFILE) {
+            let Some(file) = location.get_sendfile_path(&value) else {
+                return Ok(None);
+            };
+            ctx.add_journal(|| format!("sendfile:{value}"));
+            return Ok(Some(InternalRedirect::File(file)));
+        }
+        if !location.is_internal_redirect() {
+            return Ok(None);
+        }
+        let Some(value) = get_header_value(&HTTP_HEADER_NAME_X_ACCEL_REDIRECT)
+        else {
+            return Ok(None);
+        };
+        let uri = value.parse::<http::Uri>().map_err(|e| {
+            util::new_internal_error(500, format!("{value} is invalid, {e}"))
+        })?;
+        session.req_header_mut().set_uri(uri);
+        let host = util::get_host(session.req_header())
+            .unwrap_or_default()
+            .to_string();
+        let path = session.req_header().uri.path().to_string();
+        // the internal only location is accessible for internal redirect
+        let target = get_server_locations(&self.name).and_then(|locations| {
+            locations
+                .iter()
+                .filter_map(|name| get_location(name))
+                .find(|item| item.matched(&host, &path))
+        });
+        let Some(target) = target else {
+            return Err(util::new_internal_error(
+                500,
+                format!("Location of internal redirect not found, path:{path}"),
+            ));
+        };
+        ctx.add_journal(|| format!("internal redirect:{}", target.name));
+        Ok(Some(InternalRedirect::Location(target)))
+    }
+    /// Serves the internal redirect, returns `true` if the response
+    /// has been sent to client.
+    async fn serve_internal_redirect(
+        &self,
+        session: &mut Session,
+        redirect: &InternalRedirect,
+        ctx: &mut State,
+    ) -> pingora::Result<bool> {
+        match redirect {
+            InternalRedirect::File(file) => {
+                // the symlink in sendfile root may point to the file
+                // outside, so the real path is checked before opening
+                let file = if let Some(location) = &ctx.location {
+                    location.get_sendfile_real_path(file).await
+                } else {
+                    None
+                };
+                let Some(file) = file else {
+                    let resp = HttpResponse::not_found("Not Found".into());
+                    ctx.status = Some(resp.status);
+                    resp.send(session).await?;
+                    return Ok(true);
+                };
+                send_file(session, ctx, &file).await?;
+                Ok(true)
+            },
+            // only the location served by plugins is supported,
+            // e.g. directory
+            InternalRedirect::Location(target) => {
+                target
+                    .handle_request_plugin(PluginStep::Request, session, ctx)
+                    .await
+            },
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -356,6 +573,258 @@ fn get_digest_detail(digest: &Digest) -> DigestDeailt {
     }
 }
 
+/// Formats the milliseconds as header value, e.g. `12ms`.
+#[inline]
+fn format_ms_header_value(ms: u64) -> HeaderValue {
+    let mut buf = BytesMut::with_capacity(24);
+    buf.extend(itoa::Buffer::new().format(ms).as_bytes());
+    buf.extend(b"ms");
+    // digits and ascii letters are always valid
+    HeaderValue::from_maybe_shared(buf.freeze())
+        .unwrap_or_else(|_| HeaderValue::from_static("0ms"))
+}
+
+/// Creates the `301` response which redirects to https,
+/// the port is omitted if it's the default port `443`.
+/// Creates the redirect response to the default host for the request
+/// without matched location, the path and query are kept.
+fn new_not_found_redirect_response(
+    req_header: &RequestHeader,
+    url: &str,
+) -> HttpResponse {
+    let uri = req_header
+        .uri
+        .path_and_query()
+        .map(|value| value.as_str())
+        .unwrap_or("/");
+    let location = format!("{url}{uri}");
+    let headers = HeaderValue::from_str(&location)
+        .map(|value| vec![(http::header::LOCATION, value)])
+        .ok();
+    HttpResponse {
+        status: StatusCode::FOUND,
+        headers,
+        ..Default::default()
+    }
+}
+
+fn new_https_redirect_response(
+    req_header: &RequestHeader,
+    port: u16,
+) -> HttpResponse {
+    let host = util::get_host(req_header).unwrap_or_default();
+    let host = if port == 443 {
+        host.to_string()
+    } else {
+        format!("{host}:{port}")
+    };
+    let uri = req_header
+        .uri
+        .path_and_query()
+        .map(|value| value.as_str())
+        .unwrap_or("/");
+    let location = format!("https://{host}{uri}");
+    let headers = HeaderValue::from_str(&location)
+        .map(|value| vec![(http::header::LOCATION, value)])
+        .ok();
+    HttpResponse {
+        status: StatusCode::MOVED_PERMANENTLY,
+        headers,
+        ..Default::default()
+    }
+}
+
+/// Gets the upstream of current tier, the active upstream of blue green
+/// deployment is resolved once, so the request isn't affected by switching.
+fn get_current_upstream(ctx: &mut State) -> Option<Arc<Upstream>> {
+    if let Some(name) = &ctx.upstream_name {
+        return get_upstream(name);
+    }
+    let up = ctx
+        .location
+        .as_ref()
+        .and_then(|location| location.get_upstream_name(ctx.upstream_tier))
+        .and_then(get_active_upstream)?;
+    ctx.upstream_name = Some(up.name.clone());
+    Some(up)
+}
+
+/// Gets the response when the upstream is down, the last successful response
+/// is served for GET request of always online location, otherwise
+/// the static fallback of location is served.
+fn get_upstream_down_response(
+    req_header: &RequestHeader,
+    ctx: &mut State,
+    code: u16,
+) -> Option<(ResponseHeader, Bytes)> {
+    let location = ctx.location.clone()?;
+    if location.always_online && req_header.method == Method::GET {
+        let key = get_always_online_key(&location.name, req_header);
+        if let Some(resp) = get_always_online_response(&key) {
+            ctx.add_journal(|| "always_online".to_string());
+            return Some(resp);
+        }
+    }
+    let content = location.upstream_down_fallback.as_ref()?;
+    let mut resp = ResponseHeader::build(code, Some(4)).ok()?;
+    let _ = resp.insert_header(
+        http::header::CONTENT_TYPE,
+        get_error_content_type(content),
+    );
+    let _ = resp
+        .insert_header(http::header::CONTENT_LENGTH, content.len().to_string());
+    let _ = resp.insert_header(http::header::CACHE_CONTROL, "no-store");
+    ctx.add_journal(|| "upstream_down_fallback".to_string());
+    Some((resp, Bytes::from(content.clone())))
+}
+
+/// Gets the request info of cache entry, it's used to prefetch the entry,
+/// the unix socket server is not supported.
+fn get_cache_request(session: &Session, ctx: &State) -> Option<CacheRequest> {
+    let addr = session.server_addr().and_then(|addr| addr.as_inet())?;
+    let req_header = session.req_header();
+    let scheme = if session
+        .digest()
+        .map(|digest| digest.ssl_digest.is_some())
+        .unwrap_or_default()
+    {
+        "https"
+    } else {
+        "http"
+    };
+    Some(CacheRequest {
+        scheme: scheme.to_string(),
+        host: util::get_host(req_header).unwrap_or_default().to_string(),
+        addr: addr.to_string(),
+        path: req_header.uri.to_string(),
+        headers: ctx.cache_key_headers.clone().unwrap_or_default(),
+    })
+}
+
+/// Gets the cacheable of response, the ttl of `X-Accel-Expires` or
+/// `Surrogate-Control` has higher priority than `Cache-Control`,
+/// and the 404 response is cacheable if `cache_not_found` of location is set.
+fn get_response_cacheable(resp: &ResponseHeader, ctx: &State) -> RespCacheable {
+    // the partial response is only cacheable if the range is part of cache key
+    if resp.status == StatusCode::PARTIAL_CONTENT && !ctx.cache_partial {
+        return RespCacheable::Uncacheable(NoCacheReason::Custom(
+            "partial content",
+        ));
+    }
+    let timestamp = util::now().as_secs();
+    if let Some(ttl) = cache::get_upstream_cache_ttl(&resp.headers, timestamp) {
+        if ttl == 0 {
+            return RespCacheable::Uncacheable(NoCacheReason::OriginNotCache);
+        }
+        let mut ttl = Duration::from_secs(ttl);
+        if let Some(d) = ctx.cache_max_ttl {
+            ttl = ttl.min(d);
+        }
+        let now = SystemTime::now();
+        return RespCacheable::Cacheable(CacheMeta::new(
+            now + ttl,
+            now,
+            0,
+            0,
+            resp.clone(),
+        ));
+    }
+    // the 404 response is cached by the ttl of location
+    if resp.status == StatusCode::NOT_FOUND {
+        if let Some(ttl) =
+            ctx.location.as_ref().and_then(|item| item.cache_not_found)
+        {
+            let cc = CacheControl::from_resp_headers(resp);
+            if cc.as_ref().is_some_and(|c| c.no_store() || c.private()) {
+                return RespCacheable::Uncacheable(
+                    NoCacheReason::OriginNotCache,
+                );
+            }
+            let now = SystemTime::now();
+            return RespCacheable::Cacheable(CacheMeta::new(
+                now + ttl,
+                now,
+                0,
+                0,
+                resp.clone(),
+            ));
+        }
+    }
+    let mut cc = CacheControl::from_resp_headers(resp);
+    if let Some(ref mut c) = &mut cc {
+        if c.no_cache() || c.no_store() || c.private() {
+            return RespCacheable::Uncacheable(NoCacheReason::OriginNotCache);
+        }
+        //  max-age=0
+        if let Ok(max_age) = c.max_age() {
+            if max_age.unwrap_or_default() == 0 {
+                return RespCacheable::Uncacheable(
+                    NoCacheReason::OriginNotCache,
+                );
+            }
+        }
+        // adjust cache ttl
+        if let Some(d) = ctx.cache_max_ttl {
+            if c.fresh_sec().unwrap_or_default() > d.as_secs() as u32 {
+                c.directives.insert(
+                    "s-maxage".to_string(),
+                    Some(DirectiveValue(
+                        itoa::Buffer::new()
+                            .format(d.as_secs())
+                            .as_bytes()
+                            .to_vec(),
+                    )),
+                );
+            }
+        }
+    }
+
+    resp_cacheable(cc.as_ref(), resp, false, &META_DEFAULTS)
+}
+
+/// Returns `true` if the client closes or resets the connection,
+/// the other downstream errors(e.g. timeout of reading body) aren't.
+fn is_client_disconnected(e: &pingora::Error) -> bool {
+    match e.etype() {
+        pingora::ErrorType::ConnectionClosed => true,
+        pingora::ErrorType::WriteError | pingora::ErrorType::ReadError => e
+            .root_cause()
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| {
+                matches!(
+                    err.kind(),
+                    std::io::ErrorKind::ConnectionReset
+                        | std::io::ErrorKind::ConnectionAborted
+                        | std::io::ErrorKind::BrokenPipe
+                        | std::io::ErrorKind::UnexpectedEof
+                )
+            }),
+        _ => false,
+    }
+}
+
+/// Holds the upstream connection of the aborted request for the grace
+/// period, so the upstream can complete the request instead of being
+/// cancelled. It's closed when the upstream closes it or the grace
+/// period is elapsed.
+fn hold_upstream_connection(fd: OwnedFd, grace: Duration) {
+    let stream = std::net::TcpStream::from(fd);
+    tokio::spawn(async move {
+        let result = stream
+            .set_nonblocking(true)
+            .and_then(|_| tokio::net::TcpStream::from_std(stream));
+        let Ok(mut stream) = result else {
+            return;
+        };
+        // the response of upstream is discarded
+        let _ = tokio::time::timeout(
+            grace,
+            tokio::io::copy(&mut stream, &mut tokio::io::sink()),
+        )
+        .await;
+    });
+}
+
 #[async_trait]
 impl ProxyHttp for Server {
     type CTX = State;
@@ -393,32 +862,100 @@ impl ProxyHttp for Server {
             }
             ctx.tls_cipher = digest_detail.tls_cipher;
             ctx.tls_version = digest_detail.tls_version;
+            if ctx.tls_version.is_some() {
+                ctx.tls_fingerprint = get_tls_fingerprint(session);
+            }
         };
         ctx.processing = self.processing.fetch_add(1, Ordering::Relaxed) + 1;
         ctx.accepted = self.accepted.fetch_add(1, Ordering::Relaxed) + 1;
         ctx.remote_addr = util::get_remote_addr(session);
+        ctx.capture = new_capture_record(session);
+        ctx.traffic_record = new_traffic_record(session);
+
+        if let Some(guard) = &self.request_guard {
+            if let Some(reason) = guard.check(session.req_header()) {
+                // the rest of the connection can't be trusted
+                session.set_keepalive(None);
+                return Err(reject_request(&self.name, reason));
+            }
+        }
+        // normalize the request before routing, the malformed target
+        // is rejected, it may be used to bypass the location or waf
+        if self.normalize_path || self.normalize_host {
+            match normalize_request(
+                session.req_header_mut(),
+                self.normalize_path,
+                self.normalize_host,
+            ) {
+                Ok(Some(path)) => {
+                    debug!(path, "request is normalized");
+                },
+                Ok(None) => {},
+                Err(message) => {
+                    return Err(util::new_internal_error(400, message));
+                },
+            }
+        }
 
-        // locations not found
-        let Some(locations) = get_server_locations(&self.name) else {
-            return Ok(());
-        };
         let header = session.req_header_mut();
         let host = util::get_host(header).unwrap_or_default();
         let path = header.uri.path();
-        for name in locations.iter() {
+        for name in get_server_locations(&self.name).unwrap_or_default().iter()
+        {
             let Some(location) = get_location(name) else {
                 continue;
             };
-            if location.matched(host, path) {
+            if location.matched(host, path)
+                && location.is_accessible(ctx.remote_addr.as_deref())
+            {
                 ctx.location = Some(location);
                 break;
             }
         }
+        // fall through to the catch-all location, the same access check
+        // is applied, so the internal only location isn't exposed by it
+        if ctx.location.is_none() {
+            if let NotFoundAction::Location(name) = &self.not_found {
+                match get_location(name) {
+                    Some(location)
+                        if location
+                            .is_accessible(ctx.remote_addr.as_deref()) =>
+                    {
+                        ctx.location = Some(location);
+                        ctx.add_journal(|| {
+                            format!("not_found:location:{name}")
+                        });
+                    },
+                    Some(_) => {
+                        ctx.add_journal(|| {
+                            format!("not_found:location:denied:{name}")
+                        });
+                    },
+                    None => {},
+                }
+            }
+        }
+        self.set_request_profiling_tags(ctx);
+        if self.slow_request_threshold.is_some() {
+            let name = ctx
+                .location
+                .as_ref()
+                .map(|location| location.name.as_str())
+                .unwrap_or_default();
+            ctx.slow_request_id =
+                Some(watch_request(name, session.req_header().uri.to_string()));
+        }
         if let Some(location) = &ctx.location {
             ctx.location_accepted =
                 location.accepted.fetch_add(1, Ordering::Relaxed) + 1;
             ctx.location_processing =
                 location.processing.fetch_add(1, Ordering::Relaxed) + 1;
+            if location.is_debug_journal(
+                session.req_header(),
+                ctx.remote_addr.as_deref(),
+            ) {
+                ctx.journal = Some(vec![format!("location:{}", location.name)]);
+            }
             let _ = location
                 .clone()
                 .handle_request_plugin(PluginStep::EarlyRequest, session, ctx)
@@ -434,6 +971,7 @@ impl ProxyHttp for Server {
     where
         Self::CTX: Send + Sync,
     {
+        self.set_request_profiling_tags(ctx);
         if self.admin {
             self.serve_admin(session, ctx).await?;
             return Ok(true);
@@ -445,27 +983,75 @@ impl ProxyHttp for Server {
                 return Ok(true);
             }
         }
+        if let Some(port) = self.https_redirect_port {
+            new_https_redirect_response(session.req_header(), port)
+                .send(session)
+                .await?;
+            return Ok(true);
+        }
+        if cluster::has_bans() {
+            // the same ip as banning is used, the X-Forwarded-For of
+            // untrusted client is ignored, so the ban can't be escaped
+            let ban_ip = cluster::get_ban_ip(session);
+            if cluster::is_banned(&ban_ip) {
+                ctx.add_journal(|| format!("cluster:banned:{ban_ip}"));
+                HttpResponse {
+                    status: StatusCode::FORBIDDEN,
+                    body: Bytes::from_static(b"Forbidden, ip is banned"),
+                    ..Default::default()
+                }
+                .send(session)
+                .await?;
+                return Ok(true);
+            }
+        }
 
         let header = session.req_header_mut();
-        let Some(location) = &ctx.location else {
+        let Some(location) = ctx.location.clone() else {
+            if let NotFoundAction::Redirect(url) = &self.not_found {
+                ctx.add_journal(|| "not_found:redirect".to_string());
+                new_not_found_redirect_response(header, url)
+                    .send(session)
+                    .await?;
+                return Ok(true);
+            }
             let host = util::get_host(header).unwrap_or_default();
-            HttpResponse::unknown_error(Bytes::from(format!(
-                "Location not found, host:{host} path:{}",
-                header.uri.path(),
-            )))
-            .send(session)
-            .await?;
-            return Ok(true);
+            // the error template of 404 is used
+            return Err(util::new_internal_error(
+                404,
+                format!(
+                    "Location not found, host:{host} path:{}",
+                    header.uri.path()
+                ),
+            ));
         };
 
+        if let Some(overload) = &self.overload {
+            if let Some(reason) =
+                overload.check(ctx.processing, location.low_priority)
+            {
+                ctx.add_journal(|| "overload:shed".to_string());
+                shed_request(&self.name, &reason).send(session).await?;
+                return Ok(true);
+            }
+        }
+
         debug!(name = location.name, "location is matched");
-        location.rewrite(header);
+        if location.rewrite(header, ctx) {
+            ctx.add_journal(|| format!("rewrite:{}", header.uri));
+        }
 
         // body limit
         location.client_body_size_limit(Some(header), ctx)?;
+        let body_size = util::get_content_length(header).unwrap_or_default();
+        // the body is kept by retry buffer, it will be sent again if retried
+        if location.is_request_body_buffered(body_size) {
+            session.as_downstream_mut().enable_retry_buffering();
+        } else if body_size > 0 && location.is_request_body_buffered(0) {
+            ctx.add_journal(|| format!("request_buffering:skip:{body_size}"));
+        }
 
         let done = location
-            .clone()
             .handle_request_plugin(PluginStep::Request, session, ctx)
             .await?;
 
@@ -484,14 +1070,55 @@ impl ProxyHttp for Server {
     where
         Self::CTX: Send + Sync,
     {
-        if let Some(location) = &ctx.location {
+        if let Some(location) = ctx.location.clone() {
             let done = location
-                .clone()
                 .handle_request_plugin(PluginStep::ProxyUpstream, session, ctx)
                 .await?;
             if done {
                 return Ok(false);
             }
+            // the concurrency of upstream is acquired once for the request,
+            // the reserved part is only available for high priority location
+            if ctx.upstream_concurrency_guard.is_none() {
+                if let Some(up) = get_current_upstream(ctx) {
+                    let Some(guard) = up.acquire_concurrency(location.priority)
+                    else {
+                        ctx.add_journal(|| {
+                            format!("overload:upstream:{}", up.name)
+                        });
+                        shed_request(
+                            &self.name,
+                            &format!(
+                                "upstream({}) concurrency is exceeded",
+                                up.name
+                            ),
+                        )
+                        .send(session)
+                        .await?;
+                        return Ok(false);
+                    };
+                    ctx.upstream_concurrency_guard = Some(guard);
+                }
+            }
+            // the fastcgi and wsgi upstreams are handled without http proxy
+            if let Some(up) = get_current_upstream(ctx).filter(|up| {
+                up.get_fastcgi().is_some() || up.get_wsgi().is_some()
+            }) {
+                let peer = up.new_http_peer(session, ctx).ok_or_else(|| {
+                    util::new_internal_error(
+                        503,
+                        format!("No available upstream for {}", up.name),
+                    )
+                })?;
+                if let Some(fastcgi) = up.get_fastcgi() {
+                    ctx.add_journal(|| format!("fastcgi:{}", up.name));
+                    fastcgi.proxy(session, ctx, &peer).await?;
+                } else if let Some(wsgi) = up.get_wsgi() {
+                    ctx.add_journal(|| format!("wsgi:{}", up.name));
+                    wsgi.proxy(session, ctx, &peer).await?;
+                }
+                return Ok(false);
+            }
         }
         Ok(true)
     }
@@ -501,36 +1128,146 @@ impl ProxyHttp for Server {
         session: &mut Session,
         ctx: &mut State,
     ) -> pingora::Result<Box<HttpPeer>> {
+        self.set_request_profiling_tags(ctx);
         let mut location_name = "unknown".to_string();
-        let peer = if let Some(location) = &ctx.location {
+        let mut peer = None;
+        if let Some(location) = ctx.location.clone() {
             location_name.clone_from(&location.name);
-            if let Some(up) = get_upstream(&location.upstream) {
-                ctx.upstream_connected = up.connected();
-                up.new_http_peer(session, ctx)
-            } else {
-                None
+            // try the upstreams of fallback chain in order,
+            // if the upstream has no available peer
+            while location.get_upstream_name(ctx.upstream_tier).is_some() {
+                if let Some(up) = get_current_upstream(ctx) {
+                    ctx.upstream_connected = up.connected();
+                    peer = if up.is_lazy_resolve() {
+                        up.new_lazy_http_peer(ctx).await
+                    } else {
+                        up.new_http_peer(session, ctx)
+                    };
+                    if peer.is_some() {
+                        ctx.add_journal(|| format!("upstream:{}", up.name));
+                        break;
+                    }
+                }
+                ctx.upstream_tier += 1;
+                ctx.upstream_name = None;
             }
-        } else {
-            None
         }
-        .ok_or_else(|| {
+        let mut peer = peer.ok_or_else(|| {
             util::new_internal_error(
                 503,
                 format!("No available upstream for {location_name}"),
             )
         })?;
+        if let Some(location) = &ctx.location {
+            location.set_peer_timeouts(&mut peer);
+        }
 
         ctx.upstream_connect_time =
             util::get_latency(&ctx.upstream_connect_time);
 
         Ok(Box::new(peer))
     }
+    fn fail_to_connect(
+        &self,
+        session: &mut Session,
+        peer: &HttpPeer,
+        ctx: &mut Self::CTX,
+        mut e: Box<pingora::Error>,
+    ) -> Box<pingora::Error> {
+        let addr = peer.address().to_string();
+        let up = get_current_upstream(ctx);
+        if let Some(up) = &up {
+            up.record_backend(&addr, None, true);
+        }
+        // happy eyeballs: fall back to the ipv4 backend of dual-stack
+        // upstream if the ipv6 backend fails to connect
+        let fallback = up.is_some_and(|up| {
+            up.should_fallback_ipv4(&addr, ctx.upstream_failed_addrs.as_deref())
+        });
+        if fallback {
+            ctx.add_journal(|| format!("ipv4_fallback:{addr}"));
+            ctx.upstream_failed_addrs
+                .get_or_insert_with(Vec::new)
+                .push(addr);
+            e.set_retry(true);
+            return e;
+        }
+        // the request body is not sent if it fails to connect,
+        // so the idempotent request can be retried on another backend
+        if let Some(location) = &ctx.location {
+            if location
+                .can_retry(&session.req_header().method, ctx.upstream_retries)
+            {
+                ctx.upstream_retries += 1;
+                // the buffered body is sent again by request body filter
+                ctx.payload_size = 0;
+                ctx.upstream_failed_addrs
+                    .get_or_insert_with(Vec::new)
+                    .push(peer.address().to_string());
+                ctx.add_journal(|| format!("retry:{}", peer.address()));
+                e.set_retry(true);
+                return e;
+            }
+        }
+        // retry the next tier upstream of fallback chain
+        if let Some(location) = &ctx.location {
+            if location.get_upstream_name(ctx.upstream_tier + 1).is_some() {
+                ctx.upstream_tier += 1;
+                ctx.upstream_name = None;
+                ctx.add_journal(|| format!("fallback:{}", peer.address()));
+                e.set_retry(true);
+            }
+        }
+        e
+    }
+    fn error_while_proxy(
+        &self,
+        peer: &HttpPeer,
+        session: &mut Session,
+        e: Box<pingora::Error>,
+        ctx: &mut Self::CTX,
+        client_reused: bool,
+    ) -> Box<pingora::Error> {
+        let mut e = e.more_context(format!("Peer: {peer}"));
+        // the upstream response is dropped for internal redirect,
+        // it shouldn't be retried
+        if is_internal_redirect_error(&e) {
+            return e;
+        }
+        // the request can't be retried if the body is truncated
+        let buffered = !session.as_downstream().retry_buffer_truncated();
+        e.retry.decide_reuse(client_reused && buffered);
+        if e.retry() || !buffered || session.response_written().is_some() {
+            return e;
+        }
+        // the body has been sent to upstream, it can be sent again only
+        // if it's kept by retry buffer or there is no body
+        if let Some(location) = &ctx.location {
+            let body_retryable = location
+                .is_request_body_buffered(ctx.payload_size)
+                || session.as_downstream_mut().is_body_empty();
+            if body_retryable
+                && location.can_retry(
+                    &session.req_header().method,
+                    ctx.upstream_retries,
+                )
+            {
+                ctx.upstream_retries += 1;
+                ctx.upstream_failed_addrs
+                    .get_or_insert_with(Vec::new)
+                    .push(peer.address().to_string());
+                ctx.add_journal(|| format!("retry:{}", peer.address()));
+                e.set_retry(true);
+            }
+        }
+        e
+    }
     async fn connected_to_upstream(
         &self,
-        _session: &mut Session,
+        session: &mut Session,
         reused: bool,
         peer: &HttpPeer,
-        _fd: std::os::unix::io::RawFd,
+        fd: std::os::unix::io::RawFd,
         digest: Option<&Digest>,
         ctx: &mut Self::CTX,
     ) -> pingora::Result<()>
@@ -555,7 +1292,38 @@ impl ProxyHttp for Server {
             }
         }
         ctx.upstream_reused = reused;
-        ctx.upstream_address = peer.address().to_string();
+        let previous_address = std::mem::replace(
+            &mut ctx.upstream_address,
+            peer.address().to_string(),
+        );
+        if let Some(id) = ctx.slow_request_id {
+            update_watched_upstream(id, &ctx.upstream_address);
+        }
+        if let Some(up) = get_current_upstream(ctx) {
+            // the request is retried on another connection
+            if !previous_address.is_empty() {
+                up.on_connection_released();
+            }
+            up.on_backend_finished(&previous_address);
+            let draining = up.on_backend_connected(&ctx.upstream_address);
+            ctx.upstream_connection_close =
+                up.on_connection_used(fd, reused) || draining;
+        }
+        // the connection is duplicated, so it isn't closed immediately
+        // when the non-idempotent request is aborted by client
+        ctx.upstream_grace_fd = ctx
+            .location
+            .as_ref()
+            .and_then(|location| {
+                location.get_client_abort_grace(&session.req_header().method)
+            })
+            .and_then(|_| {
+                // the fd is valid while the upstream session is alive
+                unsafe { BorrowedFd::borrow_raw(fd) }
+                    .try_clone_to_owned()
+                    .ok()
+            });
+        ctx.add_journal(|| format!("peer:{} reused:{reused}", peer.address()));
         ctx.upstream_connect_time =
             util::get_latency(&ctx.upstream_connect_time);
         ctx.upstream_processing_time =
@@ -574,6 +1342,42 @@ impl ProxyHttp for Server {
         if let Some(location) = &ctx.location {
             location.set_append_proxy_headers(session, ctx, upstream_response);
         }
+        // the request of s3 origin is mapped to the object of bucket
+        if let Some(up) = get_current_upstream(ctx) {
+            if let Some(s3_origin) = up.get_s3_origin() {
+                s3_origin.handle_request(
+                    upstream_response,
+                    chrono::DateTime::from_timestamp(
+                        util::now().as_secs() as i64,
+                        0,
+                    )
+                    .unwrap_or_default(),
+                )?;
+                ctx.add_journal(|| "s3:signed".to_string());
+            }
+        }
+        if ctx.upstream_connection_close {
+            let _ = upstream_response
+                .insert_header(http::header::CONNECTION, "close");
+        }
+        // fetch the full object for cache, the range of cache hit
+        // is served from the full object
+        if ctx.cache_range_fill {
+            let _ = upstream_response.remove_header(&http::header::RANGE);
+            let _ = upstream_response.remove_header(&http::header::IF_RANGE);
+        }
+        // the plugins(e.g. request sign) handle the final request header
+        if let Some(location) = &ctx.location {
+            location
+                .clone()
+                .handle_upstream_request_plugin(
+                    PluginStep::ProxyUpstream,
+                    session,
+                    ctx,
+                    upstream_response,
+                )
+                .await?;
+        }
         Ok(())
     }
     async fn request_body_filter(
@@ -588,6 +1392,12 @@ impl ProxyHttp for Server {
     {
         if let Some(buf) = body {
             ctx.payload_size += buf.len();
+            if let Some(capture) = ctx.capture.as_mut() {
+                capture.add_request_body(buf);
+            }
+            if let Some(record) = ctx.traffic_record.as_mut() {
+                record.add_request_body(buf);
+            }
             if let Some(location) = &ctx.location {
                 location.client_body_size_limit(None, ctx)?;
             }
@@ -608,44 +1418,56 @@ impl ProxyHttp for Server {
         Ok(key)
     }
 
-    fn response_cache_filter(
+    async fn cache_hit_filter(
         &self,
         _session: &Session,
+        _meta: &CacheMeta,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<bool> {
+        // force revalidation by the directive of request
+        if ctx.cache_refresh {
+            ctx.add_journal(|| "cache:refresh".to_string());
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn response_cache_filter(
+        &self,
+        session: &Session,
         resp: &ResponseHeader,
         ctx: &mut Self::CTX,
     ) -> pingora::Result<RespCacheable> {
-        let mut cc = CacheControl::from_resp_headers(resp);
-        if let Some(ref mut c) = &mut cc {
-            if c.no_cache() || c.no_store() || c.private() {
-                return Ok(RespCacheable::Uncacheable(
-                    NoCacheReason::OriginNotCache,
-                ));
-            }
-            //  max-age=0
-            if let Ok(max_age) = c.max_age() {
-                if max_age.unwrap_or_default() == 0 {
-                    return Ok(RespCacheable::Uncacheable(
-                        NoCacheReason::OriginNotCache,
-                    ));
-                }
-            }
-            // adjust cache ttl
-            if let Some(d) = ctx.cache_max_ttl {
-                if c.fresh_sec().unwrap_or_default() > d.as_secs() as u32 {
-                    c.directives.insert(
-                        "s-maxage".to_string(),
-                        Some(DirectiveValue(
-                            itoa::Buffer::new()
-                                .format(d.as_secs())
-                                .as_bytes()
-                                .to_vec(),
-                        )),
-                    );
-                }
+        let cacheable = get_response_cacheable(resp, ctx);
+        if let RespCacheable::Cacheable(meta) = &cacheable {
+            let hash = session.cache.cache_key().combined();
+            let fresh_until = meta
+                .fresh_until()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let tags = cache::get_upstream_cache_tags(&resp.headers);
+            if !tags.is_empty() {
+                // the stale response may be served after it's expired
+                let stale = meta
+                    .stale_while_revalidate_sec()
+                    .max(meta.stale_if_error_sec());
+                cache::add_cache_tags(&hash, &tags, fresh_until + stale as u64);
             }
+            // the index of cache entries for admin inspection
+            cache::add_cache_entry(
+                &hash,
+                &format!(
+                    "{}{}",
+                    ctx.cache_prefix.as_deref().unwrap_or_default(),
+                    session.req_header().uri
+                ),
+                fresh_until,
+                meta.variance().map(hex::encode),
+                get_cache_request(session, ctx),
+            );
         }
-
-        Ok(resp_cacheable(cc.as_ref(), resp, false, &META_DEFAULTS))
+        Ok(cacheable)
     }
 
     async fn response_filter(
@@ -657,24 +1479,74 @@ impl ProxyHttp for Server {
     where
         Self::CTX: Send + Sync,
     {
+        self.set_request_profiling_tags(ctx);
+        // the status of upstream response is kept for logging,
+        // the response may be replaced by internal redirect
+        if !ctx.upstream_address.is_empty() {
+            ctx.upstream_status = Some(upstream_response.status);
+        }
+        // retry the next tier upstream if the response status should fallback,
+        // the response of cache hit is not checked
+        if let Some(location) = &ctx.location {
+            let status = upstream_response.status.as_u16();
+            if !ctx.upstream_address.is_empty()
+                && location.is_fallback_status(ctx.upstream_tier, status)
+            {
+                ctx.upstream_tier += 1;
+                ctx.upstream_name = None;
+                ctx.status = None;
+                ctx.add_journal(|| format!("fallback:{status}"));
+                let mut e = util::new_internal_error(
+                    502,
+                    format!("Upstream response status {status}, fallback"),
+                );
+                e.set_retry(true);
+                return Err(e);
+            }
+        }
+        if let Some(redirect) =
+            self.get_internal_redirect(session, upstream_response, ctx)?
+        {
+            ctx.internal_redirect = Some(redirect);
+            // the upstream response can only be dropped by error,
+            // the internal redirect is served by fail_to_proxy
+            return Err(pingora::Error::explain(
+                INTERNAL_REDIRECT,
+                "upstream response is internal redirect",
+            ));
+        }
         if session.cache.enabled() {
+            // the cache control headers of upstream are only for pingap
+            cache::remove_upstream_cache_headers(upstream_response);
             // ignore insert header error
             let _ = upstream_response.insert_header(
-                "X-Cache-Status",
-                session.cache.phase().as_str(),
+                HTTP_HEADER_NAME_X_CACHE_STATUS.clone(),
+                HeaderValue::from_static(session.cache.phase().as_str()),
             );
             if let Some(d) = session.cache.lookup_duration() {
                 let ms = d.as_millis() as u64;
-                let _ = upstream_response
-                    .insert_header("X-Cache-Lookup", format!("{ms}ms"));
+                let _ = upstream_response.insert_header(
+                    HTTP_HEADER_NAME_X_CACHE_LOOKUP.clone(),
+                    format_ms_header_value(ms),
+                );
                 ctx.cache_lookup_time = Some(ms);
             }
             if let Some(d) = session.cache.lock_duration() {
                 let ms = d.as_millis() as u64;
-                let _ = upstream_response
-                    .insert_header("X-Cache-Lock", format!("{ms}ms"));
+                let _ = upstream_response.insert_header(
+                    HTTP_HEADER_NAME_X_CACHE_LOCK.clone(),
+                    format_ms_header_value(ms),
+                );
                 ctx.cache_lock_time = Some(ms);
             }
+            let phase = session.cache.phase().as_str();
+            ctx.add_journal(|| format!("cache:{phase}"));
+        } else if ctx.cache_bypass {
+            let _ = upstream_response.insert_header(
+                HTTP_HEADER_NAME_X_CACHE_STATUS.clone(),
+                HeaderValue::from_static("BYPASS"),
+            );
+            ctx.add_journal(|| "cache:BYPASS".to_string());
         }
 
         if let Some(location) = &ctx.location {
@@ -688,6 +1560,25 @@ impl ProxyHttp for Server {
                 )
                 .await?;
         }
+        // keep the successful response for always online
+        ctx.always_online = ctx
+            .location
+            .as_ref()
+            .filter(|location| location.always_online)
+            .and_then(|location| {
+                let req_header = session.req_header();
+                new_always_online_record(
+                    get_always_online_key(&location.name, req_header),
+                    req_header,
+                    upstream_response,
+                )
+            });
+        if let Some(journal) = ctx.get_journal() {
+            let _ = upstream_response.insert_header(
+                HTTP_HEADER_NAME_X_PINGAP_JOURNAL.clone(),
+                journal,
+            );
+        }
 
         Ok(())
     }
@@ -703,10 +1594,19 @@ impl ProxyHttp for Server {
             ctx.upstream_response_time =
                 util::get_latency(&ctx.upstream_response_time);
         }
+        // filter the headers of upstream before the headers of pingap added
+        if let Some(location) = &ctx.location {
+            location.filter_response_headers(upstream_response);
+        }
         if let Some(id) = &ctx.request_id {
             let _ = upstream_response
                 .insert_header(HTTP_HEADER_NAME_X_REQUEST_ID.clone(), id);
         }
+        if let Some(up) = get_current_upstream(ctx) {
+            if let Some(s3_origin) = up.get_s3_origin() {
+                s3_origin.handle_response(upstream_response);
+            }
+        }
         ctx.upstream_processing_time =
             util::get_latency(&ctx.upstream_processing_time);
     }
@@ -726,7 +1626,7 @@ impl ProxyHttp for Server {
 
     fn response_body_filter(
         &self,
-        _session: &mut Session,
+        session: &mut Session,
         body: &mut Option<Bytes>,
         end_of_stream: bool,
         ctx: &mut Self::CTX,
@@ -734,6 +1634,7 @@ impl ProxyHttp for Server {
     where
         Self::CTX: Send + Sync,
     {
+        self.set_request_profiling_tags(ctx);
         // set modify response body
         if let Some(modify) = &ctx.modify_response_body {
             if let Some(ref mut buf) = ctx.response_body {
@@ -742,7 +1643,7 @@ impl ProxyHttp for Server {
                     b.clear();
                 }
             } else {
-                let mut buf = BytesMut::new();
+                let mut buf = BytesMut::with_capacity(4096);
                 if let Some(b) = body {
                     buf.extend(&b[..]);
                     b.clear();
@@ -751,8 +1652,30 @@ impl ProxyHttp for Server {
             };
 
             if end_of_stream {
-                if let Some(ref buf) = ctx.response_body {
-                    *body = Some(modify.handle(Bytes::from(buf.to_owned())));
+                if let Some(buf) = ctx.response_body.take() {
+                    *body = Some(modify.handle(buf.freeze()));
+                }
+            }
+        }
+        if let Some(location) = &ctx.location {
+            location.clone().handle_response_body_plugin(
+                PluginStep::Response,
+                session,
+                ctx,
+                body,
+                end_of_stream,
+            )?;
+        }
+        if let (Some(capture), Some(buf)) = (ctx.capture.as_mut(), body) {
+            capture.add_response_body(buf);
+        }
+        if let Some(record) = ctx.always_online.as_mut() {
+            if let Some(buf) = body {
+                record.add_body(buf);
+            }
+            if end_of_stream {
+                if let Some(record) = ctx.always_online.take() {
+                    record.finish();
                 }
             }
         }
@@ -760,6 +1683,15 @@ impl ProxyHttp for Server {
         Ok(None)
     }
 
+    fn suppress_error_log(
+        &self,
+        _session: &Session,
+        _ctx: &Self::CTX,
+        error: &pingora::Error,
+    ) -> bool {
+        // the internal redirect isn't the failure of proxy
+        is_internal_redirect_error(error)
+    }
     async fn fail_to_proxy(
         &self,
         session: &mut Session,
@@ -769,46 +1701,146 @@ impl ProxyHttp for Server {
     where
         Self::CTX: Send + Sync,
     {
+        if is_internal_redirect_error(e) {
+            if let Some(redirect) = ctx.internal_redirect.clone() {
+                match self
+                    .serve_internal_redirect(session, &redirect, ctx)
+                    .await
+                {
+                    Ok(true) => {
+                        return ctx
+                            .status
+                            .map(|status| status.as_u16())
+                            .unwrap_or(200);
+                    },
+                    Ok(false) => {
+                        error!("internal redirect is not served by plugin");
+                    },
+                    Err(e) => {
+                        error!(
+                            error = e.to_string(),
+                            "serve internal redirect fail"
+                        );
+                    },
+                };
+            }
+            // the internal redirect fails, the response may be
+            // partially sent
+            ctx.status = Some(StatusCode::INTERNAL_SERVER_ERROR);
+            if session.response_written().is_none() {
+                let _ = HttpResponse::unknown_error(
+                    "Serve internal redirect fail".into(),
+                )
+                .send(session)
+                .await;
+            }
+            return 500;
+        }
         let server_session = session.as_mut();
 
         let code = match e.etype() {
             pingora::HTTPStatus(code) => *code,
             _ => match e.esource() {
                 pingora::ErrorSource::Upstream => 502,
+                // client aborts the request(connection is closed or
+                // reset), the in-flight upstream request is cancelled
+                pingora::ErrorSource::Downstream
+                    if is_client_disconnected(e) =>
+                {
+                    CLIENT_CLOSED_REQUEST
+                },
                 pingora::ErrorSource::Downstream => match e.etype() {
                     pingora::ErrorType::WriteError
                     | pingora::ErrorType::ReadError => 500,
-                    // client close the connection
-                    pingora::ErrorType::ConnectionClosed => 499,
                     _ => 400,
                 },
                 pingora::ErrorSource::Internal
                 | pingora::ErrorSource::Unset => 500,
             },
         };
+        // the connection of client is dead, no response is written
+        if code == CLIENT_CLOSED_REQUEST {
+            ctx.status = StatusCode::from_u16(code).ok();
+            ctx.add_journal(|| format!("client aborted: {e}"));
+            let mut grace = None;
+            if let Some(location) = &ctx.location {
+                location.client_aborted.fetch_add(1, Ordering::Relaxed);
+                grace = location.get_client_abort_grace(
+                    &server_session.req_header().method,
+                );
+            }
+            // the non-idempotent request is completed by upstream
+            // in the grace period
+            if let (Some(fd), Some(grace)) =
+                (ctx.upstream_grace_fd.take(), grace)
+            {
+                ctx.add_journal(|| format!("client_abort_grace:{grace:?}"));
+                hold_upstream_connection(fd, grace);
+            }
+            return code;
+        }
         let mut resp = match code {
             502 => error_resp::HTTP_502_RESPONSE.clone(),
             400 => error_resp::HTTP_400_RESPONSE.clone(),
             _ => error_resp::gen_error_response(code),
         };
 
-        let content = self
-            .error_template
-            .replace("{{version}}", util::get_pkg_version())
-            .replace("{{content}}", &e.to_string());
-        let buf = Bytes::from(content);
+        // the upstream is down, serve the last successful response
+        // or the static fallback of location
+        if (502..=504).contains(&code) {
+            if let Some((mut resp, buf)) = get_upstream_down_response(
+                server_session.req_header(),
+                ctx,
+                code,
+            ) {
+                ctx.status = Some(resp.status);
+                if let Some(journal) = ctx.get_journal() {
+                    let _ = resp.insert_header(
+                        HTTP_HEADER_NAME_X_PINGAP_JOURNAL.clone(),
+                        journal,
+                    );
+                }
+                server_session.set_keepalive(None);
+                let status = resp.status.as_u16();
+                server_session
+                    .write_response_header(Box::new(resp))
+                    .await
+                    .unwrap_or_else(|e| {
+                        error!(
+                            error = e.to_string(),
+                            "send upstream down response to downstream fail"
+                        );
+                    });
+                let _ = server_session.write_response_body(buf, true).await;
+                return status;
+            }
+        }
+
         ctx.status = Some(
             StatusCode::from_u16(code)
                 .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
         );
-        let content_type = if buf.starts_with(b"{") {
-            "application/json; charset=utf-8"
-        } else {
-            "text/html; charset=utf-8"
-        };
+        let req_header = server_session.req_header();
+        let template =
+            self.error_templates.get(code, is_accept_json(req_header));
+        let content = render_error_template(
+            template,
+            code,
+            &e.to_string(),
+            req_header,
+            ctx,
+        );
+        let content_type = get_error_content_type(&content);
+        let buf = Bytes::from(content);
         let _ = resp.insert_header(http::header::CONTENT_TYPE, content_type);
         let _ = resp
             .insert_header(http::header::CONTENT_LENGTH, buf.len().to_string());
+        if let Some(journal) = ctx.get_journal() {
+            let _ = resp.insert_header(
+                HTTP_HEADER_NAME_X_PINGAP_JOURNAL.clone(),
+                journal,
+            );
+        }
 
         // TODO: we shouldn't be closing downstream connections on internally generated errors
         // and possibly other upstream connect() errors (connection refused, timeout, etc)
@@ -834,7 +1866,7 @@ impl ProxyHttp for Server {
     async fn logging(
         &self,
         session: &mut Session,
-        _e: Option<&pingora::Error>,
+        e: Option<&pingora::Error>,
         ctx: &mut Self::CTX,
     ) where
         Self::CTX: Send + Sync,
@@ -862,8 +1894,88 @@ impl ProxyHttp for Server {
             }
         }
 
-        if let Some(p) = &self.log_parser {
-            info!("{}", p.format(session, ctx));
+        if let Some(id) = ctx.slow_request_id {
+            unwatch_request(id);
+        }
+        if let Some(threshold) = self.slow_request_threshold {
+            let latency = util::now().as_millis() as u64 - ctx.created_at;
+            if latency >= threshold {
+                log_slow_request(session, ctx, latency);
+            }
+        }
+
+        // the request aborted by client isn't the failure of upstream
+        let client_aborted = ctx
+            .status
+            .is_some_and(|status| status.as_u16() == CLIENT_CLOSED_REQUEST);
+        // the error of internal redirect is used to drop upstream response
+        let e = e.filter(|e| !is_internal_redirect_error(e));
+        let failed = (e.is_some() && !client_aborted)
+            || ctx.status.is_some_and(|status| status.as_u16() >= 500);
+        // the upstream address is set only if it is connected,
+        // the backend failed to connect is recorded in fail_to_connect
+        if let Some(up) = get_current_upstream(ctx) {
+            up.record_backend(
+                &ctx.upstream_address,
+                ctx.get_upstream_response_time(),
+                failed,
+            );
+            if !ctx.upstream_address.is_empty() {
+                up.on_connection_released();
+            }
+            up.on_backend_finished(&ctx.upstream_address);
+        }
+
+        capture_server_error(session, ctx);
+        finish_capture_record(session, ctx);
+        finish_traffic_record(ctx);
+        record_connection(&self.name, session, ctx);
+        record_request_metrics(&self.name, session, ctx);
+        record_latency_histogram(session, ctx);
+
+        let location = ctx.location.clone();
+        if let Some(location) = &location {
+            let cache_hit = session.cache.enabled().then(|| {
+                matches!(
+                    session.cache.phase().as_str(),
+                    "hit" | "stale" | "revalidated"
+                )
+            });
+            record_time_series(
+                &location.name,
+                &RequestSample {
+                    latency: util::now().as_millis() as u64 - ctx.created_at,
+                    failed,
+                    cache_hit,
+                },
+            );
+            record_usage(&location.name, session, ctx, failed);
+        }
+
+        // the access log of location overrides the server's one
+        if location
+            .as_ref()
+            .is_some_and(|location| location.access_log_disabled)
+        {
+            return;
+        }
+        export_clickhouse_log(session, ctx);
+        let Some(p) = location
+            .as_ref()
+            .and_then(|location| location.access_log.as_ref())
+            .or(self.log_parser.as_ref())
+        else {
+            return;
+        };
+        let line = p.format(session, ctx);
+        sink::send_access_log(&line);
+        if let Some(writer) = location
+            .as_ref()
+            .and_then(|location| location.access_log_writer.as_ref())
+        {
+            write_access_log(writer, line);
+        } else {
+            info!("{line}");
         }
     }
 }
@@ -872,7 +1984,11 @@ impl ProxyHttp for Server {
 mod tests {
     use super::Server;
     use crate::config::{LocationConf, PingapConf};
-    use crate::proxy::server::get_digest_detail;
+    use crate::proxy::server::{
+        format_ms_header_value, get_digest_detail, get_upstream_down_response,
+        is_client_disconnected, new_https_redirect_response,
+        new_not_found_redirect_response,
+    };
     use crate::proxy::{
         try_init_locations, try_init_server_locations, try_init_upstreams,
         Location, ServerConf,
@@ -886,8 +2002,90 @@ mod tests {
     use pretty_assertions::assert_eq;
     use std::sync::Arc;
     use std::time::{Duration, SystemTime};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio_test::io::Builder;
 
+    #[test]
+    fn test_is_client_disconnected() {
+        assert_eq!(
+            true,
+            is_client_disconnected(&pingora::Error::new(
+                pingora::ErrorType::ConnectionClosed
+            ))
+        );
+        assert_eq!(
+            true,
+            is_client_disconnected(&pingora::Error::because(
+                pingora::ErrorType::WriteError,
+                "write body",
+                std::io::Error::from(std::io::ErrorKind::BrokenPipe),
+            ))
+        );
+        assert_eq!(
+            true,
+            is_client_disconnected(&pingora::Error::because(
+                pingora::ErrorType::ReadError,
+                "read body",
+                std::io::Error::from(std::io::ErrorKind::ConnectionReset),
+            ))
+        );
+        // the timeout of reading body isn't aborted by client
+        assert_eq!(
+            false,
+            is_client_disconnected(&pingora::Error::because(
+                pingora::ErrorType::ReadError,
+                "read body",
+                std::io::Error::from(std::io::ErrorKind::TimedOut),
+            ))
+        );
+        assert_eq!(
+            false,
+            is_client_disconnected(&pingora::Error::new(
+                pingora::ErrorType::ReadTimedout
+            ))
+        );
+    }
+
+    #[test]
+    fn test_new_https_redirect_response() {
+        let mut req_header =
+            pingora::http::RequestHeader::build("GET", b"/api?id=1", None)
+                .unwrap();
+        req_header.insert_header("Host", "pingap.io:80").unwrap();
+        let resp = new_https_redirect_response(&req_header, 443);
+        assert_eq!(301, resp.status.as_u16());
+        assert_eq!(
+            r#"Some([("location", "https://pingap.io/api?id=1")])"#,
+            format!("{:?}", resp.headers)
+        );
+
+        let resp = new_https_redirect_response(&req_header, 8443);
+        assert_eq!(
+            r#"Some([("location", "https://pingap.io:8443/api?id=1")])"#,
+            format!("{:?}", resp.headers)
+        );
+    }
+
+    #[test]
+    fn test_new_not_found_redirect_response() {
+        let req_header =
+            pingora::http::RequestHeader::build("GET", b"/api?id=1", None)
+                .unwrap();
+        let resp =
+            new_not_found_redirect_response(&req_header, "https://pingap.io");
+        assert_eq!(302, resp.status.as_u16());
+        assert_eq!(
+            r#"Some([("location", "https://pingap.io/api?id=1")])"#,
+            format!("{:?}", resp.headers)
+        );
+    }
+
+    #[test]
+    fn test_format_ms_header_value() {
+        assert_eq!("0ms", format_ms_header_value(0).to_str().unwrap());
+        assert_eq!("1234ms", format_ms_header_value(1234).to_str().unwrap());
+    }
+
     #[test]
     fn test_get_digest_detail() {
         let digest = Digest {
@@ -975,6 +2173,66 @@ mod tests {
         assert_eq!(false, done);
     }
 
+    #[tokio::test]
+    async fn test_internal_redirect_sendfile() {
+        let server = new_server();
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("pingap.txt"), "Hello pingap").unwrap();
+
+        let (mut client, stream) = tokio::io::duplex(64 * 1024);
+        client
+            .write_all(b"GET /download HTTP/1.1\r\nHost: pingap.io\r\n\r\n")
+            .await
+            .unwrap();
+        let mut session = Session::new_h1(Box::new(stream));
+        session.read_request().await.unwrap();
+
+        let mut ctx = State {
+            location: Some(Arc::new(
+                Location::new(
+                    "lo",
+                    &LocationConf {
+                        upstream: Some("charts".to_string()),
+                        sendfile_root: Some(
+                            dir.path().to_string_lossy().to_string(),
+                        ),
+                        ..Default::default()
+                    },
+                )
+                .unwrap(),
+            )),
+            ..Default::default()
+        };
+        let mut upstream_response = ResponseHeader::build(200, None).unwrap();
+        upstream_response
+            .insert_header("X-Sendfile", "pingap.txt")
+            .unwrap();
+        upstream_response
+            .insert_header("X-Upstream", "charts")
+            .unwrap();
+        let err = server
+            .response_filter(&mut session, &mut upstream_response, &mut ctx)
+            .await
+            .unwrap_err();
+        assert_eq!(true, ctx.internal_redirect.is_some());
+        let status = server.fail_to_proxy(&mut session, &err, &mut ctx).await;
+        assert_eq!(200, status);
+        drop(session);
+
+        let mut buf = vec![];
+        client.read_to_end(&mut buf).await.unwrap();
+        let data = String::from_utf8_lossy(&buf).to_lowercase();
+        // only the file is sent, the upstream response is dropped
+        assert_eq!(1, data.matches("http/1.1 ").count());
+        assert_eq!(true, data.starts_with("http/1.1 200 ok\r\n"));
+        assert_eq!(true, data.contains("content-type: text/plain\r\n"));
+        assert_eq!(false, data.contains("x-upstream"));
+        assert_eq!(
+            true,
+            data.ends_with("\r\n\r\nc\r\nhello pingap\r\n0\r\n\r\n")
+        );
+    }
+
     #[tokio::test]
     async fn test_cache_key_callback() {
         let server = new_server();
@@ -1077,5 +2335,121 @@ mod tests {
             )
             .unwrap();
         assert_eq!(false, result.is_cacheable());
+
+        // x-accel-expires has higher priority than cache-control
+        let mut upstream_response =
+            ResponseHeader::build_no_case(200, None).unwrap();
+        upstream_response
+            .append_header("Cache-Control", "no-store")
+            .unwrap();
+        upstream_response
+            .append_header("X-Accel-Expires", "60")
+            .unwrap();
+        let result = server
+            .response_cache_filter(
+                &session,
+                &upstream_response,
+                &mut State::default(),
+            )
+            .unwrap();
+        assert_eq!(true, result.is_cacheable());
+
+        let mut upstream_response =
+            ResponseHeader::build_no_case(200, None).unwrap();
+        upstream_response
+            .append_header("Cache-Control", "max-age=100")
+            .unwrap();
+        upstream_response
+            .append_header("Surrogate-Control", "no-store")
+            .unwrap();
+        let result = server
+            .response_cache_filter(
+                &session,
+                &upstream_response,
+                &mut State::default(),
+            )
+            .unwrap();
+        assert_eq!(false, result.is_cacheable());
+
+        // 404 response is cached by the ttl of location
+        let upstream_response =
+            ResponseHeader::build_no_case(404, None).unwrap();
+        let location = Arc::new(
+            Location::new(
+                "lo",
+                &LocationConf {
+                    cache_not_found: Some(Duration::from_secs(30)),
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        );
+        let result = server
+            .response_cache_filter(
+                &session,
+                &upstream_response,
+                &mut State {
+                    location: Some(location.clone()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(true, result.is_cacheable());
+
+        let mut upstream_response =
+            ResponseHeader::build_no_case(404, None).unwrap();
+        upstream_response
+            .append_header("Cache-Control", "no-store")
+            .unwrap();
+        let result = server
+            .response_cache_filter(
+                &session,
+                &upstream_response,
+                &mut State {
+                    location: Some(location),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(false, result.is_cacheable());
+    }
+
+    #[test]
+    fn test_get_upstream_down_response() {
+        let req_header =
+            pingora::http::RequestHeader::build("GET", b"/api?id=1", None)
+                .unwrap();
+        let mut ctx = State::default();
+        assert_eq!(
+            true,
+            get_upstream_down_response(&req_header, &mut ctx, 502).is_none()
+        );
+
+        let location = Location::new(
+            "lo",
+            &LocationConf {
+                always_online: Some(true),
+                upstream_down_fallback: Some(
+                    r#"{"message":"offline"}"#.to_string(),
+                ),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut ctx = State {
+            location: Some(Arc::new(location)),
+            ..Default::default()
+        };
+        let (resp, body) =
+            get_upstream_down_response(&req_header, &mut ctx, 503).unwrap();
+        assert_eq!(503, resp.status.as_u16());
+        assert_eq!(
+            "application/json; charset=utf-8",
+            resp.headers.get("Content-Type").unwrap().to_str().unwrap()
+        );
+        assert_eq!(
+            r#"{"message":"offline"}"#,
+            std::str::from_utf8(&body).unwrap()
+        );
     }
 }
@@ -12,10 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::config::PingapConf;
+use crate::config::{NotFoundAction, PingapConf};
 use crate::util;
 use pingora::protocols::l4::ext::TcpKeepalive;
-use std::{fmt, path::PathBuf};
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
 
 static ERROR_TEMPLATE: &str = include_str!("../../error.html");
 
@@ -28,21 +28,48 @@ pub struct ServerConf {
     pub locations: Vec<String>,
     pub tls_cert: Option<Vec<u8>>,
     pub tls_key: Option<Vec<u8>>,
+    // the pem files of cert and key, they are watched for reload
+    pub tls_files: Option<(String, String)>,
     pub tls_cipher_list: Option<String>,
     pub tls_ciphersuites: Option<String>,
     pub tls_min_version: Option<String>,
     pub tls_max_version: Option<String>,
     pub threads: Option<usize>,
     pub error_template: String,
+    pub error_template_dir: Option<String>,
     pub lets_encrypt: Option<String>,
     pub certificate_file: Option<String>,
     pub tcp_keepalive: Option<TcpKeepalive>,
     pub tcp_fastopen: Option<usize>,
     pub global_certificates: bool,
     pub enbaled_h2: bool,
+    pub slow_request_threshold: Option<Duration>,
+    pub overload_max_processing: Option<i32>,
+    pub overload_max_event_loop_delay: Option<Duration>,
+    pub overload_low_priority_only: bool,
+    // run a companion http server to redirect to https
+    pub https_redirect: bool,
+    // normalize the path of request before routing
+    pub normalize_path: bool,
+    // lowercase the host of request before routing
+    pub normalize_host: bool,
+    // reject the protocol anomalies of request
+    pub strict_request: bool,
+    pub max_header_count: Option<usize>,
+    pub max_header_size: Option<usize>,
+    // the handling of request which doesn't match any location
+    pub not_found: NotFoundAction,
 }
 
 impl ServerConf {
+    /// Gets the port of the first listen address.
+    pub fn get_port(&self) -> Option<u16> {
+        self.addr
+            .split(',')
+            .next()
+            .and_then(|addr| addr.trim().rsplit_once(':'))
+            .and_then(|(_, port)| port.parse::<u16>().ok())
+    }
     pub fn get_certificate_file(&self) -> PathBuf {
         if let Some(file) = &self.certificate_file {
             util::resolve_path(file).into()
@@ -85,10 +112,18 @@ impl From<PingapConf> for Vec<ServerConf> {
         locations.sort_by_key(|b| std::cmp::Reverse(b.1.get_weight()));
         let mut servers = vec![];
         for (name, item) in conf.servers {
-            // load config validate base64
-            // so ignore error
+            // the certificate is validated when loading config,
+            // so the error is only logged here
             let tls_cert = util::convert_certificate_bytes(&item.tls_cert);
             let tls_key = util::convert_certificate_bytes(&item.tls_key);
+            let tls_files = match (&item.tls_cert, &item.tls_key) {
+                (Some(cert), Some(key))
+                    if util::is_pem_file(cert) && util::is_pem_file(key) =>
+                {
+                    Some((cert.clone(), key.clone()))
+                },
+                _ => None,
+            };
 
             let mut error_template =
                 conf.basic.error_template.clone().unwrap_or_default();
@@ -129,6 +164,7 @@ impl From<PingapConf> for Vec<ServerConf> {
                 admin: false,
                 tls_cert,
                 tls_key,
+                tls_files,
                 tls_cipher_list: item.tls_cipher_list.clone(),
                 tls_ciphersuites: item.tls_ciphersuites.clone(),
                 tls_min_version: item.tls_min_version.clone(),
@@ -146,6 +182,27 @@ impl From<PingapConf> for Vec<ServerConf> {
                 tcp_keepalive,
                 tcp_fastopen: item.tcp_fastopen,
                 error_template,
+                error_template_dir: conf.basic.error_template_dir.clone(),
+                slow_request_threshold: conf.basic.slow_request_threshold,
+                overload_max_processing: item.overload_max_processing,
+                overload_max_event_loop_delay: item
+                    .overload_max_event_loop_delay,
+                overload_low_priority_only: item
+                    .overload_low_priority_only
+                    .unwrap_or_default(),
+                https_redirect: item.https_redirect.unwrap_or_default(),
+                normalize_path: item.normalize_path.unwrap_or_default(),
+                normalize_host: item.normalize_host.unwrap_or_default(),
+                strict_request: item.strict_request.unwrap_or_default(),
+                max_header_count: item.max_header_count,
+                max_header_size: item
+                    .max_header_size
+                    .map(|item| item.as_u64() as usize),
+                not_found: item
+                    .not_found
+                    .as_deref()
+                    .and_then(|value| NotFoundAction::from_str(value).ok())
+                    .unwrap_or_default(),
             });
         }
 
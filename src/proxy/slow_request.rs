@@ -0,0 +1,274 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use async_trait::async_trait;
+use bytes::BytesMut;
+use once_cell::sync::{Lazy, OnceCell};
+use pingora::proxy::Session;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+struct InflightRequest {
+    started_at: u64,
+    location: String,
+    uri: String,
+    upstream_address: String,
+    reported: bool,
+}
+
+static INFLIGHT_REQUESTS: Lazy<Mutex<AHashMap<u64, InflightRequest>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+static REQUEST_SEQ: AtomicU64 = AtomicU64::new(0);
+static STUCK_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+static SLOW_LOG_SENDER: OnceCell<crossbeam_channel::Sender<String>> =
+    OnceCell::new();
+
+/// Initializes the dedicated slow request log file,
+/// the logs are written by a separate thread.
+pub fn init_slow_request_log(file: &str) -> std::io::Result<()> {
+    let file = util::resolve_path(file);
+    let mut f = OpenOptions::new().create(true).append(true).open(&file)?;
+    let (sender, receiver) = crossbeam_channel::bounded::<String>(1024);
+    if SLOW_LOG_SENDER.set(sender).is_err() {
+        return Ok(());
+    }
+    std::thread::spawn(move || {
+        for line in receiver.iter() {
+            if let Err(e) = writeln!(f, "{line}") {
+                error!(error = e.to_string(), "write slow request log fail");
+            }
+        }
+    });
+    info!(file, "init slow request log");
+    Ok(())
+}
+
+fn write_slow_log(line: String) {
+    if let Some(sender) = SLOW_LOG_SENDER.get() {
+        // drop the log if the channel is full
+        let _ = sender.try_send(line);
+    } else {
+        warn!(target: "slow_request", "{line}");
+    }
+}
+
+/// Watches the request until it's done, returns the id of watched request.
+pub fn watch_request(location: &str, uri: String) -> u64 {
+    let id = REQUEST_SEQ.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut requests) = INFLIGHT_REQUESTS.lock() {
+        requests.insert(
+            id,
+            InflightRequest {
+                started_at: util::now().as_millis() as u64,
+                location: location.to_string(),
+                uri,
+                upstream_address: "".to_string(),
+                reported: false,
+            },
+        );
+    }
+    id
+}
+
+/// Updates the upstream address of the watched request.
+pub fn update_watched_upstream(id: u64, upstream_address: &str) {
+    if let Ok(mut requests) = INFLIGHT_REQUESTS.lock() {
+        if let Some(request) = requests.get_mut(&id) {
+            request.upstream_address = upstream_address.to_string();
+        }
+    }
+}
+
+/// Removes the request from watchdog.
+pub fn unwatch_request(id: u64) {
+    if let Ok(mut requests) = INFLIGHT_REQUESTS.lock() {
+        requests.remove(&id);
+    }
+}
+
+/// Gets the count of stuck requests found by the latest check.
+pub fn get_stuck_requests() -> usize {
+    STUCK_REQUESTS.load(Ordering::Relaxed)
+}
+
+fn format_slow_log(
+    session: &Session,
+    ctx: &State,
+    latency: u64,
+) -> Option<String> {
+    let req_header = session.req_header();
+    let mut buf = BytesMut::with_capacity(256);
+    buf.extend(chrono::Local::now().to_rfc3339().as_bytes());
+    buf.extend(b" ");
+    buf.extend(req_header.method.as_str().as_bytes());
+    buf.extend(b" ");
+    buf.extend(req_header.uri.to_string().as_bytes());
+    buf.extend(b" status:");
+    if let Some(status) = ctx.status {
+        buf.extend(status.as_str().as_bytes());
+    } else {
+        buf.extend(b"0");
+    }
+    buf.extend(b" latency:");
+    buf = util::format_duration(buf, latency);
+    buf.extend(b" location:");
+    buf = ctx.append_value(buf, "location");
+    buf.extend(b" upstream:");
+    buf = ctx.append_value(buf, "upstream_addr");
+    for key in [
+        "upstream_connect_time",
+        "upstream_tcp_connect_time",
+        "upstream_tls_handshake_time",
+        "upstream_processing_time",
+        "upstream_response_time",
+        "cache_lookup_time",
+        "cache_lock_time",
+        "compression_time",
+    ] {
+        let value = ctx.append_value(BytesMut::new(), key);
+        if !value.is_empty() {
+            buf.extend(b" ");
+            buf.extend(key.as_bytes());
+            buf.extend(b":");
+            buf.extend(value);
+        }
+    }
+    std::string::String::from_utf8(buf.into()).ok()
+}
+
+/// Writes the slow request log with the timing of each phase.
+pub fn log_slow_request(session: &Session, ctx: &State, latency: u64) {
+    if let Some(line) = format_slow_log(session, ctx, latency) {
+        write_slow_log(line);
+    }
+}
+
+struct SlowRequestWatchdog {
+    threshold: Duration,
+}
+
+#[async_trait]
+impl ServiceTask for SlowRequestWatchdog {
+    async fn run(&self) -> Option<bool> {
+        let now = util::now().as_millis() as u64;
+        let threshold = self.threshold.as_millis() as u64;
+        let mut stuck_logs = vec![];
+        let mut count = 0;
+        if let Ok(mut requests) = INFLIGHT_REQUESTS.lock() {
+            for request in requests.values_mut() {
+                let elapsed = now.saturating_sub(request.started_at);
+                if elapsed < threshold {
+                    continue;
+                }
+                count += 1;
+                // only report once for each stuck request
+                if !request.reported {
+                    request.reported = true;
+                    stuck_logs.push(format!(
+                        "stuck request {} elapsed:{elapsed}ms location:{} upstream:{}",
+                        request.uri, request.location, request.upstream_address
+                    ));
+                }
+            }
+        }
+        STUCK_REQUESTS.store(count, Ordering::Relaxed);
+        for line in stuck_logs {
+            write_slow_log(line);
+        }
+        None
+    }
+    fn description(&self) -> String {
+        let threshold: humantime::Duration = self.threshold.into();
+        format!("Slow request watchdog, threshold:{threshold}")
+    }
+}
+
+/// Creates a watchdog task to find the requests which exceed the threshold.
+pub fn new_slow_request_watchdog_task(
+    threshold: Duration,
+) -> CommonServiceTask {
+    let interval =
+        threshold.clamp(Duration::from_secs(1), Duration::from_secs(10));
+    CommonServiceTask::new(
+        "Slow request watchdog",
+        interval,
+        SlowRequestWatchdog { threshold },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        format_slow_log, get_stuck_requests, unwatch_request,
+        update_watched_upstream, watch_request, SlowRequestWatchdog,
+        INFLIGHT_REQUESTS,
+    };
+    use crate::service::ServiceTask;
+    use crate::state::State;
+    use http::StatusCode;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn test_slow_request_watchdog() {
+        let id = watch_request("lo", "/users/me".to_string());
+        update_watched_upstream(id, "127.0.0.1:5000");
+        if let Ok(mut requests) = INFLIGHT_REQUESTS.lock() {
+            let request = requests.get_mut(&id).unwrap();
+            assert_eq!("127.0.0.1:5000", request.upstream_address);
+            request.started_at -= 60_000;
+        }
+        let watchdog = SlowRequestWatchdog {
+            threshold: Duration::from_secs(30),
+        };
+        watchdog.run().await;
+        assert_eq!(true, get_stuck_requests() >= 1);
+
+        unwatch_request(id);
+        assert_eq!(true, INFLIGHT_REQUESTS.lock().unwrap().get(&id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_format_slow_log() {
+        let input_header = "GET /vicanso/pingap?size=1 HTTP/1.1\r\n\r\n";
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+
+        let ctx = State {
+            status: Some(StatusCode::OK),
+            upstream_address: "127.0.0.1:5000".to_string(),
+            upstream_connect_time: Some(3),
+            upstream_processing_time: Some(1200),
+            ..Default::default()
+        };
+        let log = format_slow_log(&session, &ctx, 1500).unwrap();
+        assert_eq!(
+            true,
+            log.ends_with(
+                "GET /vicanso/pingap?size=1 status:200 latency:1.5s location: upstream:127.0.0.1:5000 upstream_connect_time:3ms upstream_processing_time:1.2s"
+            )
+        );
+    }
+}
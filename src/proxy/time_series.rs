@@ -0,0 +1,269 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use ahash::AHashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+// the history of the last hour is kept in memory
+pub const MAX_TIME_SERIES_MINUTES: usize = 60;
+// the upper bounds(ms) of latency histogram,
+// the latency greater than the last one is counted in the overflow bucket
+const LATENCY_BOUNDS: [u64; 11] =
+    [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Debug, Default, Clone)]
+struct MinuteBucket {
+    // the minute since unix epoch
+    minute: u64,
+    requests: u64,
+    errors: u64,
+    cache_lookups: u64,
+    cache_hits: u64,
+    max_latency: u64,
+    latencies: [u64; LATENCY_BOUNDS.len() + 1],
+}
+
+impl MinuteBucket {
+    /// Gets the approximate percentile of latency, it's the upper bound of
+    /// histogram bucket and not greater than the max latency.
+    fn get_percentile(&self, percentile: f64) -> u64 {
+        if self.requests == 0 {
+            return 0;
+        }
+        let target = (self.requests as f64 * percentile).ceil() as u64;
+        let mut count = 0;
+        for (index, value) in self.latencies.iter().enumerate() {
+            count += value;
+            if count >= target {
+                let bound =
+                    LATENCY_BOUNDS.get(index).cloned().unwrap_or(u64::MAX);
+                return bound.min(self.max_latency);
+            }
+        }
+        self.max_latency
+    }
+}
+
+/// The sampled request of location.
+#[derive(Debug, Default, Clone)]
+pub struct RequestSample {
+    // the latency(ms) of request
+    pub latency: u64,
+    // the request fails or the response status is 5xx
+    pub failed: bool,
+    // the cache is looked up, `Some(true)` means the cache is hit
+    pub cache_hit: Option<bool>,
+}
+
+/// The ring buffer of minute buckets, the index is `minute % capacity`.
+#[derive(Debug, Default)]
+struct TimeSeries {
+    buckets: Vec<MinuteBucket>,
+}
+
+impl TimeSeries {
+    fn record(&mut self, minute: u64, sample: &RequestSample) {
+        if self.buckets.is_empty() {
+            self.buckets =
+                vec![MinuteBucket::default(); MAX_TIME_SERIES_MINUTES];
+        }
+        let bucket =
+            &mut self.buckets[minute as usize % MAX_TIME_SERIES_MINUTES];
+        // the bucket of previous round is reset
+        if bucket.minute != minute {
+            *bucket = MinuteBucket {
+                minute,
+                ..Default::default()
+            };
+        }
+        bucket.requests += 1;
+        if sample.failed {
+            bucket.errors += 1;
+        }
+        if let Some(hit) = sample.cache_hit {
+            bucket.cache_lookups += 1;
+            if hit {
+                bucket.cache_hits += 1;
+            }
+        }
+        bucket.max_latency = bucket.max_latency.max(sample.latency);
+        let index = LATENCY_BOUNDS
+            .iter()
+            .position(|bound| sample.latency <= *bound)
+            .unwrap_or(LATENCY_BOUNDS.len());
+        bucket.latencies[index] += 1;
+    }
+    /// Gets the points of the last minutes, the minute without request
+    /// is filled with zero value.
+    fn get_points(&self, now: u64, minutes: usize) -> Vec<TimeSeriesPoint> {
+        let current = now / 60;
+        let minutes = minutes.clamp(1, MAX_TIME_SERIES_MINUTES) as u64;
+        let mut points = Vec::with_capacity(minutes as usize);
+        for minute in (current + 1).saturating_sub(minutes)..=current {
+            let mut point = TimeSeriesPoint {
+                timestamp: minute * 60,
+                ..Default::default()
+            };
+            let Some(bucket) = self
+                .buckets
+                .get(minute as usize % MAX_TIME_SERIES_MINUTES)
+                .filter(|bucket| {
+                    bucket.minute == minute && bucket.requests > 0
+                })
+            else {
+                points.push(point);
+                continue;
+            };
+            // the current minute is not finished
+            let seconds = if minute == current { now % 60 + 1 } else { 60 };
+            let requests = bucket.requests as f64;
+            point.requests = bucket.requests;
+            point.rps = requests / seconds as f64;
+            point.error_rate = bucket.errors as f64 / requests;
+            point.p50 = bucket.get_percentile(0.5);
+            point.p90 = bucket.get_percentile(0.9);
+            point.p99 = bucket.get_percentile(0.99);
+            if bucket.cache_lookups > 0 {
+                point.cache_hit_ratio = Some(
+                    bucket.cache_hits as f64 / bucket.cache_lookups as f64,
+                );
+            }
+            points.push(point);
+        }
+        points
+    }
+}
+
+/// The stats of location in one minute.
+#[derive(Serialize, Debug, Default, Clone, ToSchema)]
+pub struct TimeSeriesPoint {
+    // the start time(seconds) of minute
+    pub timestamp: u64,
+    pub requests: u64,
+    pub rps: f64,
+    pub error_rate: f64,
+    // the approximate percentiles(ms) of latency
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    // none if the cache is not used
+    pub cache_hit_ratio: Option<f64>,
+}
+
+static TIME_SERIES: Lazy<Mutex<AHashMap<String, TimeSeries>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+/// Records the request of location to the time series of current minute.
+pub fn record_time_series(location: &str, sample: &RequestSample) {
+    let minute = util::now().as_secs() / 60;
+    if let Ok(mut series) = TIME_SERIES.lock() {
+        if let Some(value) = series.get_mut(location) {
+            value.record(minute, sample);
+        } else {
+            let mut value = TimeSeries::default();
+            value.record(minute, sample);
+            series.insert(location.to_string(), value);
+        }
+    }
+}
+
+/// Gets the time series of the last minutes for locations,
+/// all locations are returned if the location is empty.
+pub fn get_time_series(
+    location: &str,
+    minutes: usize,
+) -> HashMap<String, Vec<TimeSeriesPoint>> {
+    let now = util::now().as_secs();
+    let Ok(series) = TIME_SERIES.lock() else {
+        return HashMap::new();
+    };
+    series
+        .iter()
+        .filter(|(name, _)| location.is_empty() || *name == location)
+        .map(|(name, value)| (name.to_string(), value.get_points(now, minutes)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_time_series, record_time_series, RequestSample, TimeSeries,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_time_series() {
+        let mut series = TimeSeries::default();
+        // 2024-01-01 00:00:00
+        let minute = 28401120;
+        for latency in 1..=100 {
+            series.record(
+                minute,
+                &RequestSample {
+                    latency,
+                    failed: latency > 95,
+                    cache_hit: Some(latency % 4 == 0),
+                },
+            );
+        }
+        series.record(
+            minute - 2,
+            &RequestSample {
+                latency: 30_000,
+                ..Default::default()
+            },
+        );
+
+        let points = series.get_points(minute * 60 + 59, 3);
+        assert_eq!(3, points.len());
+
+        assert_eq!((minute - 2) * 60, points[0].timestamp);
+        assert_eq!(1, points[0].requests);
+        assert_eq!(30_000, points[0].p99);
+        assert_eq!(None, points[0].cache_hit_ratio);
+
+        assert_eq!(0, points[1].requests);
+        assert_eq!(0.0, points[1].rps);
+
+        assert_eq!(100, points[2].requests);
+        assert_eq!("1.67", format!("{:.2}", points[2].rps));
+        assert_eq!(0.05, points[2].error_rate);
+        assert_eq!(50, points[2].p50);
+        assert_eq!(100, points[2].p90);
+        assert_eq!(100, points[2].p99);
+        assert_eq!(Some(0.25), points[2].cache_hit_ratio);
+
+        // the bucket of previous round is overwritten
+        series.record(minute + 60, &RequestSample::default());
+        let points = series.get_points((minute + 60) * 60, 1);
+        assert_eq!(1, points[0].requests);
+        assert_eq!(1.0, points[0].rps);
+        let points = series.get_points(minute * 60, 1);
+        assert_eq!(0, points[0].requests);
+    }
+
+    #[test]
+    fn test_record_time_series() {
+        record_time_series("time-series", &RequestSample::default());
+        let series = get_time_series("time-series", 5);
+        assert_eq!(1, series.len());
+        assert_eq!(5, series["time-series"].len());
+        assert_eq!(1, series["time-series"][4].requests);
+    }
+}
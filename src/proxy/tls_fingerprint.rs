@@ -0,0 +1,196 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use foreign_types::ForeignTypeRef;
+use once_cell::sync::Lazy;
+use pingora::protocols::Ssl as _;
+use pingora::proxy::Session;
+use pingora::tls::ex_data::Index;
+use pingora::tls::hash::{hash, MessageDigest};
+use pingora::tls::ssl::{
+    ClientHelloResponse, Ssl, SslContextBuilder, SslRef, SslVersion,
+};
+use tracing::error;
+
+const EXTENSION_SUPPORTED_GROUPS: u32 = 10;
+const EXTENSION_EC_POINT_FORMATS: u32 = 11;
+
+static JA3_INDEX: Lazy<Option<Index<Ssl, String>>> =
+    Lazy::new(|| match Ssl::new_ex_index() {
+        Ok(index) => Some(index),
+        Err(e) => {
+            error!(error = e.to_string(), "new ssl ex index fail");
+            None
+        },
+    });
+
+/// GREASE values(RFC 8701) are ignored by ja3.
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn to_u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|item| u16::from_be_bytes([item[0], item[1]]))
+        .filter(|item| !is_grease(*item))
+        .collect()
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Gets the ja3 string of client hello,
+/// `version,ciphers,extensions,groups,ec_point_formats`.
+fn get_ja3_string(
+    version: u16,
+    ciphers: &[u16],
+    extensions: &[u16],
+    groups: &[u16],
+    point_formats: &[u8],
+) -> String {
+    format!(
+        "{version},{},{},{},{}",
+        join(ciphers),
+        join(extensions),
+        join(groups),
+        join(point_formats)
+    )
+}
+
+fn get_legacy_version(ssl: &SslRef) -> u16 {
+    match ssl.client_hello_legacy_version() {
+        Some(SslVersion::SSL3) => 768,
+        Some(SslVersion::TLS1) => 769,
+        Some(SslVersion::TLS1_1) => 770,
+        Some(SslVersion::TLS1_3) => 772,
+        _ => 771,
+    }
+}
+
+fn get_client_hello_extensions(ssl: &SslRef) -> Vec<u16> {
+    let mut out: *mut libc::c_int = std::ptr::null_mut();
+    let mut len: libc::size_t = 0;
+    // the extensions are only available in client hello callback
+    let result = unsafe {
+        openssl_sys::SSL_client_hello_get1_extensions_present(
+            ssl.as_ptr(),
+            &mut out,
+            &mut len,
+        )
+    };
+    if result != 1 || out.is_null() {
+        return vec![];
+    }
+    let extensions = unsafe { std::slice::from_raw_parts(out, len) }
+        .iter()
+        .map(|item| *item as u16)
+        .filter(|item| !is_grease(*item))
+        .collect();
+    unsafe {
+        openssl_sys::OPENSSL_free(out as *mut libc::c_void);
+    }
+    extensions
+}
+
+fn get_client_hello_extension(ssl: &SslRef, extension: u32) -> &[u8] {
+    let mut out: *const libc::c_uchar = std::ptr::null();
+    let mut len: libc::size_t = 0;
+    let result = unsafe {
+        openssl_sys::SSL_client_hello_get0_ext(
+            ssl.as_ptr(),
+            extension,
+            &mut out,
+            &mut len,
+        )
+    };
+    if result != 1 || out.is_null() {
+        return &[];
+    }
+    unsafe { std::slice::from_raw_parts(out, len) }
+}
+
+fn get_client_hello_ja3(ssl: &SslRef) -> String {
+    let ciphers = to_u16_list(ssl.client_hello_ciphers().unwrap_or_default());
+    let extensions = get_client_hello_extensions(ssl);
+    // the first two bytes are the length of list
+    let groups = get_client_hello_extension(ssl, EXTENSION_SUPPORTED_GROUPS);
+    let groups = to_u16_list(groups.get(2..).unwrap_or_default());
+    // the first byte is the length of list
+    let point_formats =
+        get_client_hello_extension(ssl, EXTENSION_EC_POINT_FORMATS);
+    let point_formats = point_formats.get(1..).unwrap_or_default();
+
+    get_ja3_string(
+        get_legacy_version(ssl),
+        &ciphers,
+        &extensions,
+        &groups,
+        point_formats,
+    )
+}
+
+/// Sets the client hello callback to calculate the ja3 fingerprint,
+/// which is saved in the ex data of ssl.
+pub fn set_tls_fingerprint_callback(builder: &mut SslContextBuilder) {
+    builder.set_client_hello_callback(|ssl, _alert| {
+        if let Some(index) = *JA3_INDEX {
+            let ja3 = get_client_hello_ja3(ssl);
+            if let Ok(digest) = hash(MessageDigest::md5(), ja3.as_bytes()) {
+                ssl.set_ex_data(index, hex::encode(digest));
+            }
+        }
+        Ok(ClientHelloResponse::SUCCESS)
+    });
+}
+
+/// Gets the ja3 fingerprint of the downstream tls connection,
+/// it's only supported for http1.
+pub fn get_tls_fingerprint(session: &Session) -> Option<String> {
+    let index = (*JA3_INDEX)?;
+    let stream = session.as_downstream().stream()?;
+    stream.get_ssl()?.ex_data(index).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_ja3_string, is_grease, to_u16_list};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_ja3_string() {
+        assert_eq!(true, is_grease(0x0a0a));
+        assert_eq!(true, is_grease(0xfafa));
+        assert_eq!(false, is_grease(0x1301));
+
+        let ciphers = to_u16_list(&[0x0a, 0x0a, 0x13, 0x01, 0x13, 0x02]);
+        assert_eq!(vec![4865, 4866], ciphers);
+
+        assert_eq!(
+            "771,4865-4866,0-23-65281-10-11,29-23-24,0",
+            get_ja3_string(
+                771,
+                &ciphers,
+                &[0, 23, 65281, 10, 11],
+                &[29, 23, 24],
+                &[0]
+            )
+        );
+        assert_eq!("771,,,,", get_ja3_string(771, &[], &[], &[], &[]));
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::upstream::get_upstream;
+use crate::config::PingapConf;
+use ahash::AHashSet;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+const CATEGORY_SERVER: &str = "server";
+const CATEGORY_LOCATION: &str = "location";
+const CATEGORY_UPSTREAM: &str = "upstream";
+const CATEGORY_BACKEND: &str = "backend";
+
+/// The node of topology, the id is `category:name`.
+#[derive(Serialize, Debug, Clone, Default, ToSchema)]
+pub struct TopologyNode {
+    pub id: String,
+    // server, location, upstream or backend
+    pub category: String,
+    pub name: String,
+    // the health of backend, the upstream is healthy if any backend is healthy
+    pub healthy: Option<bool>,
+    // the location is not used by any server,
+    // or the upstream is not used by any location
+    pub orphaned: bool,
+}
+
+/// The edge between two nodes of topology.
+#[derive(Serialize, Debug, Clone, Default, ToSchema)]
+pub struct TopologyEdge {
+    pub source: String,
+    pub target: String,
+    // the fallback tier of upstream, 0 is the primary upstream
+    pub tier: Option<usize>,
+}
+
+/// The routing topology of servers, locations, upstreams and backends.
+#[derive(Serialize, Debug, Clone, Default, ToSchema)]
+pub struct Topology {
+    pub nodes: Vec<TopologyNode>,
+    pub edges: Vec<TopologyEdge>,
+}
+
+#[inline]
+fn get_node_id(category: &str, name: &str) -> String {
+    format!("{category}:{name}")
+}
+
+fn get_sorted_names<T>(values: &HashMap<String, T>) -> Vec<&String> {
+    let mut names: Vec<&String> = values.keys().collect();
+    names.sort();
+    names
+}
+
+/// Gets the routing topology of config, the health of backends
+/// is the current state of running upstreams.
+pub fn get_topology(conf: &PingapConf) -> Topology {
+    let mut topology = Topology::default();
+    let mut used_locations = AHashSet::new();
+    let mut used_upstreams = AHashSet::new();
+
+    for name in get_sorted_names(&conf.servers) {
+        let id = get_node_id(CATEGORY_SERVER, name);
+        for location in conf.servers[name].locations.iter().flatten() {
+            used_locations.insert(location.as_str());
+            topology.edges.push(TopologyEdge {
+                source: id.clone(),
+                target: get_node_id(CATEGORY_LOCATION, location),
+                tier: None,
+            });
+        }
+        topology.nodes.push(TopologyNode {
+            id,
+            category: CATEGORY_SERVER.to_string(),
+            name: name.to_string(),
+            ..Default::default()
+        });
+    }
+
+    for name in get_sorted_names(&conf.locations) {
+        let location = &conf.locations[name];
+        let id = get_node_id(CATEGORY_LOCATION, name);
+        // the primary upstream is tier 0, the fallbacks follow it
+        let upstreams =
+            std::iter::once(location.upstream.as_deref().unwrap_or_default())
+                .chain(
+                    location
+                        .fallback_upstreams
+                        .iter()
+                        .flatten()
+                        .map(|item| item.as_str()),
+                );
+        for (tier, upstream) in upstreams.enumerate() {
+            if upstream.is_empty() {
+                continue;
+            }
+            used_upstreams.insert(upstream);
+            topology.edges.push(TopologyEdge {
+                source: id.clone(),
+                target: get_node_id(CATEGORY_UPSTREAM, upstream),
+                tier: Some(tier),
+            });
+        }
+        topology.nodes.push(TopologyNode {
+            id,
+            category: CATEGORY_LOCATION.to_string(),
+            name: name.to_string(),
+            orphaned: !used_locations.contains(name.as_str()),
+            ..Default::default()
+        });
+    }
+
+    for name in get_sorted_names(&conf.upstreams) {
+        let id = get_node_id(CATEGORY_UPSTREAM, name);
+        let backends = get_upstream(name)
+            .map(|up| up.get_backends_health())
+            .unwrap_or_default();
+        let healthy = if backends.is_empty() {
+            None
+        } else {
+            Some(backends.iter().any(|(_, healthy)| *healthy))
+        };
+        for (addr, healthy) in backends {
+            let backend_name = format!("{name}/{addr}");
+            let backend_id = get_node_id(CATEGORY_BACKEND, &backend_name);
+            topology.edges.push(TopologyEdge {
+                source: id.clone(),
+                target: backend_id.clone(),
+                tier: None,
+            });
+            topology.nodes.push(TopologyNode {
+                id: backend_id,
+                category: CATEGORY_BACKEND.to_string(),
+                name: backend_name,
+                healthy: Some(healthy),
+                ..Default::default()
+            });
+        }
+        topology.nodes.push(TopologyNode {
+            id,
+            category: CATEGORY_UPSTREAM.to_string(),
+            name: name.to_string(),
+            healthy,
+            orphaned: !used_upstreams.contains(name.as_str()),
+        });
+    }
+
+    topology
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_topology;
+    use crate::config::{LocationConf, PingapConf, ServerConf, UpstreamConf};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_get_topology() {
+        let mut conf = PingapConf::default();
+        conf.servers.insert(
+            "test".to_string(),
+            ServerConf {
+                locations: Some(vec!["charts".to_string()]),
+                ..Default::default()
+            },
+        );
+        conf.locations.insert(
+            "charts".to_string(),
+            LocationConf {
+                upstream: Some("charts".to_string()),
+                fallback_upstreams: Some(vec!["backup".to_string()]),
+                ..Default::default()
+            },
+        );
+        conf.locations
+            .insert("unused".to_string(), LocationConf::default());
+        conf.upstreams
+            .insert("charts".to_string(), UpstreamConf::default());
+        conf.upstreams
+            .insert("backup".to_string(), UpstreamConf::default());
+        conf.upstreams
+            .insert("orphan".to_string(), UpstreamConf::default());
+
+        // the backends depend on the running upstreams, they are ignored
+        let topology = get_topology(&conf);
+        let nodes: Vec<String> = topology
+            .nodes
+            .iter()
+            .filter(|node| node.category != "backend")
+            .map(|node| format!("{}:{}", node.id, node.orphaned))
+            .collect();
+        assert_eq!(
+            r#"["server:test:false", "location:charts:false", "location:unused:true", "upstream:backup:false", "upstream:charts:false", "upstream:orphan:true"]"#,
+            format!("{nodes:?}")
+        );
+        let edges: Vec<String> = topology
+            .edges
+            .iter()
+            .filter(|edge| !edge.target.starts_with("backend:"))
+            .map(|edge| {
+                format!("{}->{}({:?})", edge.source, edge.target, edge.tier)
+            })
+            .collect();
+        assert_eq!(
+            r#"["server:test->location:charts(None)", "location:charts->upstream:charts(Some(0))", "location:charts->upstream:backup(Some(1))"]"#,
+            format!("{edges:?}")
+        );
+    }
+}
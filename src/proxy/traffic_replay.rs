@@ -0,0 +1,604 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::upstream::get_upstream;
+use crate::config::UpstreamConf;
+use crate::state::State;
+use crate::util;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::BytesMut;
+use once_cell::sync::{Lazy, OnceCell};
+use pingora::proxy::Session;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+// the default max size of recorded request body
+const DEFAULT_BODY_LIMIT: usize = 64 * 1024;
+const DEFAULT_REPLAY_RATE: u32 = 10;
+const DEFAULT_REPLAY_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REPLAY_MISMATCHES: usize = 100;
+// the credentials of client are not recorded
+const REDACTED_HEADERS: [&str; 3] =
+    ["authorization", "cookie", "proxy-authorization"];
+// the hop by hop headers are not replayed
+const SKIPPED_REPLAY_HEADERS: [&str; 4] = [
+    "connection",
+    "content-length",
+    "keep-alive",
+    "transfer-encoding",
+];
+
+/// The recorded request, it's written to file as a json line.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq)]
+pub struct TrafficRecord {
+    pub time: String,
+    pub method: String,
+    // the path and query of request
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    // the base64 of request body
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+    #[serde(default)]
+    pub body_truncated: bool,
+    // the response status of recorded request
+    #[serde(default)]
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub location: String,
+    #[serde(skip)]
+    body_limit: usize,
+    #[serde(skip)]
+    body_buf: BytesMut,
+}
+
+impl TrafficRecord {
+    fn new(session: &Session, body_limit: usize) -> Self {
+        let header = session.req_header();
+        let headers = header
+            .headers
+            .iter()
+            .filter(|(name, _)| !REDACTED_HEADERS.contains(&name.as_str()))
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).to_string(),
+                )
+            })
+            .collect();
+        TrafficRecord {
+            time: chrono::Local::now().to_rfc3339(),
+            method: header.method.to_string(),
+            uri: header
+                .uri
+                .path_and_query()
+                .map(|item| item.to_string())
+                .unwrap_or_else(|| "/".to_string()),
+            headers,
+            body_limit,
+            ..Default::default()
+        }
+    }
+    /// Appends the request body, the body over the limit is dropped
+    /// and the record is marked as truncated.
+    pub fn add_request_body(&mut self, data: &[u8]) {
+        let size = data.len().min(self.body_limit - self.body_buf.len());
+        if size < data.len() {
+            self.body_truncated = true;
+        }
+        self.body_buf.extend_from_slice(&data[..size]);
+    }
+}
+
+struct TrafficRecorder {
+    sender: crossbeam_channel::Sender<String>,
+    sample_rate: f64,
+    body_limit: usize,
+    count: AtomicU64,
+}
+
+impl TrafficRecorder {
+    /// Samples the requests by count, e.g. 0.1 means one of ten requests.
+    fn sampled(&self) -> bool {
+        if self.sample_rate >= 1.0 {
+            return true;
+        }
+        if self.sample_rate <= 0.0 {
+            return false;
+        }
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        (count as f64 * self.sample_rate).floor()
+            > ((count - 1) as f64 * self.sample_rate).floor()
+    }
+}
+
+static TRAFFIC_RECORDER: OnceCell<TrafficRecorder> = OnceCell::new();
+
+/// Initializes the traffic record file, the sampled requests are written
+/// by a separate thread as json lines.
+pub fn init_traffic_record(
+    file: &str,
+    sample_rate: f64,
+    body_limit: Option<usize>,
+) -> std::io::Result<()> {
+    let file = util::resolve_path(file);
+    let mut f = OpenOptions::new().create(true).append(true).open(&file)?;
+    let (sender, receiver) = crossbeam_channel::bounded::<String>(1024);
+    let recorder = TrafficRecorder {
+        sender,
+        sample_rate,
+        body_limit: body_limit.unwrap_or(DEFAULT_BODY_LIMIT),
+        count: AtomicU64::new(0),
+    };
+    if TRAFFIC_RECORDER.set(recorder).is_err() {
+        return Ok(());
+    }
+    std::thread::spawn(move || {
+        for line in receiver.iter() {
+            if let Err(e) = writeln!(f, "{line}") {
+                error!(error = e.to_string(), "write traffic record fail");
+            }
+        }
+    });
+    info!(file, sample_rate, "init traffic record");
+    Ok(())
+}
+
+/// Creates the traffic record if the request is sampled.
+pub fn new_traffic_record(session: &Session) -> Option<Box<TrafficRecord>> {
+    let recorder = TRAFFIC_RECORDER.get()?;
+    if !recorder.sampled() {
+        return None;
+    }
+    Some(Box::new(TrafficRecord::new(session, recorder.body_limit)))
+}
+
+/// Writes the traffic record with the response status to file,
+/// the record is dropped if the channel is full.
+pub fn finish_traffic_record(ctx: &mut State) {
+    let Some(mut record) = ctx.traffic_record.take() else {
+        return;
+    };
+    let Some(recorder) = TRAFFIC_RECORDER.get() else {
+        return;
+    };
+    if !record.body_buf.is_empty() {
+        record.body = STANDARD.encode(&record.body_buf);
+    }
+    record.status = ctx.status.map(|status| status.as_u16()).unwrap_or(0);
+    if let Some(location) = &ctx.location {
+        record.location.clone_from(&location.name);
+    }
+    match serde_json::to_string(&record) {
+        Ok(line) => {
+            let _ = recorder.sender.try_send(line);
+        },
+        Err(e) => {
+            error!(error = e.to_string(), "serialize traffic record fail");
+        },
+    }
+}
+
+/// The target of replay, the requests are sent to the base urls in turn.
+#[derive(Debug, Default, Clone)]
+pub struct ReplayTarget {
+    urls: Vec<String>,
+    // the domain of urls is resolved to the addresses(tls upstream)
+    resolve: Option<(String, Vec<SocketAddr>)>,
+}
+
+impl ReplayTarget {
+    /// Creates the target of the url, e.g. `http://127.0.0.1:3000`.
+    pub fn from_url(url: &str) -> Self {
+        Self {
+            urls: vec![url.trim_end_matches('/').to_string()],
+            resolve: None,
+        }
+    }
+    /// Creates the target of backends, the sni is used as the domain
+    /// of https url and it's resolved to the backends.
+    fn from_backends(addrs: &[String], sni: &str) -> std::io::Result<Self> {
+        if addrs.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "no available backend",
+            ));
+        }
+        if sni.is_empty() {
+            return Ok(Self {
+                urls: addrs
+                    .iter()
+                    .map(|addr| format!("http://{addr}"))
+                    .collect(),
+                resolve: None,
+            });
+        }
+        let mut socket_addrs = vec![];
+        for addr in addrs {
+            socket_addrs.extend(addr.to_socket_addrs()?);
+        }
+        // the port of resolved address is ignored, so it's set in the url
+        let port = socket_addrs.first().map(|addr| addr.port()).unwrap_or(443);
+        Ok(Self {
+            urls: vec![format!("https://{sni}:{port}")],
+            resolve: Some((sni.to_string(), socket_addrs)),
+        })
+    }
+    /// Creates the target of upstream config, all the addresses are used.
+    pub fn from_upstream_conf(conf: &UpstreamConf) -> std::io::Result<Self> {
+        let addrs: Vec<String> = conf
+            .addrs
+            .iter()
+            .filter_map(|addr| addr.split_whitespace().next())
+            .map(|addr| addr.to_string())
+            .collect();
+        Self::from_backends(&addrs, &conf.sni.clone().unwrap_or_default())
+    }
+    /// Creates the target of running upstream, only the healthy
+    /// backends are used.
+    fn from_upstream(name: &str) -> std::io::Result<Self> {
+        let up = get_upstream(name).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("upstream({name}) is not found"),
+            )
+        })?;
+        let addrs: Vec<String> = up
+            .get_backends_health()
+            .into_iter()
+            .filter(|(_, healthy)| *healthy)
+            .map(|(addr, _)| addr)
+            .collect();
+        Self::from_backends(&addrs, up.get_sni().unwrap_or_default())
+    }
+}
+
+/// The params of replay, the target is the url or the name of upstream.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, ToSchema)]
+pub struct ReplayParams {
+    // the file of traffic record
+    pub file: String,
+    pub target: String,
+    // the requests per second, default is 10
+    pub rate: Option<u32>,
+    // the max count of replayed requests, none means all
+    pub count: Option<usize>,
+    // the timeout of each request, default is 10s
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    #[schema(value_type = Option<String>)]
+    pub timeout: Option<Duration>,
+}
+
+/// The replayed request whose status is not the same as recorded.
+#[derive(Debug, Default, Clone, Serialize, ToSchema)]
+pub struct ReplayMismatch {
+    pub method: String,
+    pub uri: String,
+    pub expected: u16,
+    // 0 means the request is failed
+    pub actual: u16,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub error: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, ToSchema)]
+pub struct ReplayStatus {
+    pub replaying: bool,
+    pub params: Option<ReplayParams>,
+    pub total: usize,
+    // the status of response is the same as recorded
+    pub matched: usize,
+    pub mismatched: usize,
+    pub failed: usize,
+    // the request with truncated body is skipped
+    pub skipped: usize,
+    // the elapsed milliseconds of replay
+    pub elapsed: u64,
+    pub mismatches: Vec<ReplayMismatch>,
+}
+
+static REPLAYING: AtomicBool = AtomicBool::new(false);
+static REPLAY_STATUS: Lazy<Mutex<ReplayStatus>> =
+    Lazy::new(|| Mutex::new(ReplayStatus::default()));
+
+fn new_replay_request(
+    client: &reqwest::Client,
+    base_url: &str,
+    record: &TrafficRecord,
+) -> Result<reqwest::RequestBuilder, String> {
+    let method = reqwest::Method::from_bytes(record.method.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let mut req = client.request(method, format!("{base_url}{}", record.uri));
+    for (name, value) in record.headers.iter() {
+        if SKIPPED_REPLAY_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        req = req.header(name, value);
+    }
+    if !record.body.is_empty() {
+        let body = STANDARD.decode(&record.body).map_err(|e| e.to_string())?;
+        req = req.body(body);
+    }
+    Ok(req)
+}
+
+/// Replays the recorded requests against the target at the rate,
+/// the status of response is compared with the recorded status.
+/// The status is updated while replaying, and it's stopped if the
+/// replaying flag is cleared.
+async fn replay(params: ReplayParams, target: ReplayTarget) -> ReplayStatus {
+    let started_at = Instant::now();
+    let mut status = ReplayStatus {
+        replaying: true,
+        params: Some(params.clone()),
+        ..Default::default()
+    };
+    let update_status = |status: &ReplayStatus| {
+        if let Ok(mut current) = REPLAY_STATUS.lock() {
+            *current = status.clone();
+        }
+    };
+    let mut builder = reqwest::Client::builder()
+        .timeout(params.timeout.unwrap_or(DEFAULT_REPLAY_TIMEOUT))
+        .redirect(reqwest::redirect::Policy::none());
+    if let Some((domain, addrs)) = &target.resolve {
+        builder = builder.resolve_to_addrs(domain, addrs);
+    }
+    let reader = std::fs::File::open(util::resolve_path(&params.file))
+        .map(BufReader::new);
+    let (client, reader) = match (builder.build(), reader) {
+        (Ok(client), Ok(reader)) => (client, reader),
+        (Err(e), _) => {
+            error!(error = e.to_string(), "new replay client fail");
+            status.replaying = false;
+            return status;
+        },
+        (_, Err(e)) => {
+            error!(error = e.to_string(), "open traffic record fail");
+            status.replaying = false;
+            return status;
+        },
+    };
+    let rate = params.rate.unwrap_or(DEFAULT_REPLAY_RATE).max(1);
+    let mut interval = tokio::time::interval(Duration::from_secs(1) / rate);
+    let count = params.count.unwrap_or(usize::MAX);
+    for line in reader.lines() {
+        if status.total >= count || !REPLAYING.load(Ordering::Relaxed) {
+            break;
+        }
+        let Ok(line) = line else {
+            break;
+        };
+        let Ok(record) = serde_json::from_str::<TrafficRecord>(&line) else {
+            continue;
+        };
+        status.total += 1;
+        if record.body_truncated {
+            status.skipped += 1;
+            continue;
+        }
+        interval.tick().await;
+        let base_url = &target.urls[status.total % target.urls.len()];
+        let result = match new_replay_request(&client, base_url, &record) {
+            Ok(req) => req.send().await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        let mut mismatch = ReplayMismatch {
+            method: record.method.clone(),
+            uri: record.uri.clone(),
+            expected: record.status,
+            ..Default::default()
+        };
+        match result {
+            Ok(resp) if resp.status().as_u16() == record.status => {
+                status.matched += 1;
+            },
+            Ok(resp) => {
+                status.mismatched += 1;
+                mismatch.actual = resp.status().as_u16();
+            },
+            Err(e) => {
+                status.failed += 1;
+                mismatch.error = e;
+            },
+        };
+        if mismatch.actual != record.status
+            && status.mismatches.len() < MAX_REPLAY_MISMATCHES
+        {
+            status.mismatches.push(mismatch);
+        }
+        status.elapsed = started_at.elapsed().as_millis() as u64;
+        update_status(&status);
+    }
+    status.replaying = false;
+    status.elapsed = started_at.elapsed().as_millis() as u64;
+    info!(
+        total = status.total,
+        matched = status.matched,
+        mismatched = status.mismatched,
+        failed = status.failed,
+        "traffic replay is done"
+    );
+    status
+}
+
+/// Replays the traffic record and waits for the result, it's used by cli.
+pub async fn replay_traffic(
+    params: ReplayParams,
+    target: ReplayTarget,
+) -> ReplayStatus {
+    REPLAYING.store(true, Ordering::Relaxed);
+    let status = replay(params, target).await;
+    REPLAYING.store(false, Ordering::Relaxed);
+    status
+}
+
+/// Starts the replay in background, the target is the url or the name
+/// of upstream. Only one replay is running at the same time.
+pub fn start_replay(params: ReplayParams) -> std::io::Result<()> {
+    let target = if params.target.contains("://") {
+        ReplayTarget::from_url(&params.target)
+    } else {
+        ReplayTarget::from_upstream(&params.target)?
+    };
+    if REPLAYING.swap(true, Ordering::Relaxed) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            "replay is running",
+        ));
+    }
+    info!(
+        file = params.file,
+        target = params.target,
+        rate = params.rate,
+        "start traffic replay"
+    );
+    tokio::spawn(async move {
+        let status = replay(params, target).await;
+        REPLAYING.store(false, Ordering::Relaxed);
+        if let Ok(mut current) = REPLAY_STATUS.lock() {
+            *current = status;
+        }
+    });
+    Ok(())
+}
+
+/// Stops the running replay.
+pub fn stop_replay() {
+    REPLAYING.store(false, Ordering::Relaxed);
+}
+
+/// Gets the status of the running or the last replay.
+pub fn get_replay_status() -> ReplayStatus {
+    let mut status = REPLAY_STATUS
+        .lock()
+        .map(|status| status.clone())
+        .unwrap_or_default();
+    status.replaying = REPLAYING.load(Ordering::Relaxed);
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        new_replay_request, ReplayTarget, TrafficRecord, TrafficRecorder,
+    };
+    use crate::config::UpstreamConf;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::AtomicU64;
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn test_traffic_record() {
+        let headers = [
+            "Host: github.com",
+            "Content-Type: application/json",
+            "Cookie: uid=1",
+            "Authorization: Bearer abc",
+        ]
+        .join("\r\n");
+        let input_header = format!(
+            "POST /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n"
+        );
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+
+        let mut record = TrafficRecord::new(&session, 4);
+        assert_eq!("POST", record.method);
+        assert_eq!("/vicanso/pingap?size=1", record.uri);
+        assert_eq!(
+            vec![
+                ("host".to_string(), "github.com".to_string()),
+                ("content-type".to_string(), "application/json".to_string())
+            ],
+            record.headers
+        );
+        record.add_request_body(b"{\"a\"");
+        assert_eq!(false, record.body_truncated);
+        record.add_request_body(b":1}");
+        assert_eq!(true, record.body_truncated);
+        assert_eq!(b"{\"a\"", &record.body_buf[..]);
+
+        let record = serde_json::from_str::<TrafficRecord>(
+            r#"{"time":"2024-07-10T10:00:00+08:00","method":"POST","uri":"/users?id=1","headers":[["host","github.com"],["content-length","2"]],"body":"e30=","status":201}"#,
+        )
+        .unwrap();
+        assert_eq!(201, record.status);
+        let client = reqwest::Client::new();
+        let req = new_replay_request(&client, "http://127.0.0.1:3000", &record)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!("http://127.0.0.1:3000/users?id=1", req.url().to_string());
+        assert_eq!("github.com", req.headers()["host"]);
+        assert_eq!(b"{}", req.body().and_then(|body| body.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_traffic_sampled() {
+        let (sender, _) = crossbeam_channel::bounded::<String>(1);
+        let recorder = TrafficRecorder {
+            sender,
+            sample_rate: 0.25,
+            body_limit: 1024,
+            count: AtomicU64::new(0),
+        };
+        assert_eq!(25, (0..100).filter(|_| recorder.sampled()).count());
+    }
+
+    #[test]
+    fn test_replay_target() {
+        let target = ReplayTarget::from_url("http://127.0.0.1:3000/");
+        assert_eq!(vec!["http://127.0.0.1:3000".to_string()], target.urls);
+
+        let target = ReplayTarget::from_upstream_conf(&UpstreamConf {
+            addrs: vec![
+                "127.0.0.1:3000 10".to_string(),
+                "127.0.0.1:3001".to_string(),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            vec![
+                "http://127.0.0.1:3000".to_string(),
+                "http://127.0.0.1:3001".to_string()
+            ],
+            target.urls
+        );
+        assert_eq!(true, target.resolve.is_none());
+
+        let target = ReplayTarget::from_upstream_conf(&UpstreamConf {
+            addrs: vec!["127.0.0.1:8443".to_string()],
+            sni: Some("pingap.io".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(vec!["https://pingap.io:8443".to_string()], target.urls);
+        assert_eq!(
+            r#"Some(("pingap.io", [127.0.0.1:8443]))"#,
+            format!("{:?}", target.resolve)
+        );
+    }
+}
@@ -12,9 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::config::UpstreamConf;
+use super::fastcgi::FastCgi;
+use super::grpc_health_check::GrpcHealthCheck;
+use super::health_history::ObservedHealthCheck;
+use super::s3_origin::S3Origin;
+use super::wsgi::Wsgi;
+use crate::config::{UpstreamConf, MAX_LOCATION_PRIORITY};
 use crate::discovery::{
-    new_common_discover_backends, new_dns_discover_backends,
+    format_addrs, new_common_discover_backends, new_dns_discover_backends,
+    new_xds_discover_backends, AddrFamily, DnsFailurePolicy, LazyResolver,
 };
 use crate::service::{CommonServiceTask, ServiceTask};
 use crate::state::State;
@@ -27,20 +33,26 @@ use humantime::parse_duration;
 use once_cell::sync::Lazy;
 use pingora::http::RequestHeader;
 use pingora::lb::health_check::{HealthCheck, HttpHealthCheck, TcpHealthCheck};
-use pingora::lb::selection::{Consistent, RoundRobin};
-use pingora::lb::{Backends, LoadBalancer};
+use pingora::lb::selection::{
+    BackendIter, BackendSelection, Consistent, RoundRobin,
+};
+use pingora::lb::{Backend, Backends, LoadBalancer};
 use pingora::protocols::l4::ext::TcpKeepalive;
 use pingora::protocols::ALPN;
 use pingora::proxy::Session;
 use pingora::upstreams::peer::{HttpPeer, PeerOptions, Tracer, Tracing};
+use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 use std::collections::HashMap;
 use std::fmt;
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info};
 use url::Url;
+use utoipa::ToSchema;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -84,6 +96,42 @@ impl Tracing for UpstreamPeerTracer {
     }
 }
 
+/// The slow start state of backend, the warmup time is set when
+/// the backend is added or recovers from unhealthy.
+#[derive(Debug, Default)]
+struct BackendWarmup {
+    healthy: bool,
+    warmup_at: Option<Instant>,
+    count: u64,
+}
+
+impl BackendWarmup {
+    /// Get the weight factor of backend, it ramps up from 0.1 to 1.0
+    /// during the slow start window.
+    fn factor(&mut self, window: Duration) -> f64 {
+        let Some(warmup_at) = self.warmup_at else {
+            return 1.0;
+        };
+        let elapsed = warmup_at.elapsed();
+        if elapsed >= window {
+            self.warmup_at = None;
+            return 1.0;
+        }
+        (elapsed.as_secs_f64() / window.as_secs_f64()).max(0.1)
+    }
+    /// Accept the backend by its weight factor,
+    /// e.g. factor 0.3 means accept 3 of every 10 selections.
+    fn accept(&mut self, window: Duration) -> bool {
+        let factor = self.factor(window);
+        if factor >= 1.0 {
+            return true;
+        }
+        self.count += 1;
+        let count = self.count as f64;
+        (count * factor).floor() > ((count - 1.0) * factor).floor()
+    }
+}
+
 pub struct Upstream {
     pub name: String,
     hash: String,
@@ -103,6 +151,164 @@ pub struct Upstream {
     tcp_fast_open: Option<bool>,
     peer_tracer: Option<UpstreamPeerTracer>,
     tracer: Option<Tracer>,
+    keepalive_pool_size: Option<usize>,
+    max_requests_per_connection: Option<u32>,
+    max_h2_streams: Option<usize>,
+    // the request count of each connection, key is the fd of connection
+    connection_requests: Mutex<AHashMap<RawFd, u32>>,
+    // the connections which are proxying requests
+    active_connections: AtomicU32,
+    new_connections: AtomicU64,
+    reused_connections: AtomicU64,
+    closed_connections: AtomicU64,
+    slow_start: Option<Duration>,
+    // the warmup state of backends, key is the address of backend
+    backend_warmups: Mutex<AHashMap<String, BackendWarmup>>,
+    warming_backends: AtomicU32,
+    // prefer ipv6 backends, and fall back to ipv4 if it fails to connect
+    prefer_ipv6: bool,
+    // the request stats of backends, key is the address of backend
+    backend_stats: Mutex<AHashMap<String, BackendStats>>,
+    // select the backend by power of two choices with ewma latency
+    p2c_ewma: bool,
+    // resolve the hosts at request time
+    lazy_resolver: Option<LazyResolver>,
+    // the draining backends don't receive new requests,
+    // key is the address of backend
+    draining_backends: Mutex<AHashMap<String, BackendDrain>>,
+    draining_count: AtomicU32,
+    // the request is converted to the object request of s3 bucket
+    s3_origin: Option<S3Origin>,
+    // the request is sent to fastcgi backend instead of http proxy
+    fastcgi: Option<FastCgi>,
+    // the request is sent to python application server(uwsgi or scgi)
+    wsgi: Option<Wsgi>,
+    // the green upstream of blue green deployment
+    green_upstream: Option<String>,
+    // the min healthy backends of target upstream to switch
+    blue_green_min_healthy: usize,
+    // the max concurrent requests, 0 means unlimited
+    max_concurrency: u32,
+    // the concurrency reserved for high priority locations
+    reserved_concurrency: u32,
+    // the processing requests of upstream
+    processing: Arc<AtomicU32>,
+}
+
+/// The concurrency guard of upstream request,
+/// the processing count of upstream is decreased when it's dropped.
+pub struct UpstreamConcurrencyGuard(Arc<AtomicU32>);
+
+impl Drop for UpstreamConcurrencyGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The request stats of backend.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct BackendStats {
+    pub requests: u64,
+    pub errors: u64,
+    // the ewma of latency(ms)
+    pub latency_ewma: u64,
+    // the unix timestamp(seconds) of last used
+    pub last_used_at: u64,
+    // the processing requests of backend
+    pub processing: u32,
+}
+
+impl BackendStats {
+    /// Record the result of request, the weight of latency ewma
+    /// for the new value is 1/5.
+    fn record(&mut self, latency: Option<u64>, failed: bool) {
+        self.requests += 1;
+        if failed {
+            self.errors += 1;
+        }
+        if let Some(latency) = latency {
+            self.latency_ewma = if self.latency_ewma == 0 {
+                latency
+            } else {
+                (self.latency_ewma * 4 + latency) / 5
+            };
+        }
+        self.last_used_at = util::now().as_secs();
+    }
+}
+
+/// The draining state of backend, it doesn't receive new requests
+/// and its connections are closed after the processing requests are done.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct BackendDrain {
+    // the unix timestamp(seconds) when the drain starts
+    pub started_at: u64,
+    // the drain is set by the zero weight of config
+    pub from_config: bool,
+    // the processing requests of backend
+    pub processing: u32,
+    // all processing requests of backend are done
+    pub drained: bool,
+}
+
+/// The connection stats of upstream.
+#[derive(Serialize, Debug, Clone, Default, ToSchema)]
+pub struct UpstreamStats {
+    pub connected: Option<u32>,
+    pub new_connections: u64,
+    pub reused_connections: u64,
+    pub closed_connections: u64,
+    pub backends: HashMap<String, BackendStats>,
+    pub paused: Option<UpstreamPause>,
+    pub draining: HashMap<String, BackendDrain>,
+    // the active color of blue green upstream
+    pub color: Option<UpstreamColor>,
+    // the processing requests of upstream
+    pub processing: u32,
+}
+
+/// The color of blue green upstream, blue is the upstream itself
+/// and green is its green upstream.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamColor {
+    #[default]
+    Blue,
+    Green,
+}
+
+impl fmt::Display for UpstreamColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Blue => write!(f, "blue"),
+            Self::Green => write!(f, "green"),
+        }
+    }
+}
+
+/// The paused state of upstream, the value is the unix timestamp(seconds)
+/// when the pause expires.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct UpstreamPause {
+    pub discovery: Option<u64>,
+    pub health_check: Option<u64>,
+}
+
+impl UpstreamPause {
+    fn is_empty(&self) -> bool {
+        self.discovery.is_none() && self.health_check.is_none()
+    }
+    /// Clear the expired pause.
+    fn clear_expired(&mut self, now: u64) {
+        if self.discovery.is_some_and(|value| value <= now) {
+            self.discovery = None;
+        }
+        if self.health_check.is_some_and(|value| value <= now) {
+            self.health_check = None;
+        }
+    }
 }
 
 impl fmt::Display for Upstream {
@@ -137,6 +343,11 @@ pub struct HealthCheckConf {
     pub reuse_connection: bool,
     pub consecutive_success: usize,
     pub consecutive_failure: usize,
+    // the service name of grpc health check
+    pub service: String,
+    // the ip and port of health check, they override the address of backend
+    pub addr: Option<IpAddr>,
+    pub port: Option<u16>,
 }
 
 impl TryFrom<&str> for HealthCheckConf {
@@ -152,9 +363,16 @@ impl TryFrom<&str> for HealthCheckConf {
         let mut consecutive_failure = 2;
         let mut query_list = vec![];
         let mut reuse_connection = false;
+        let mut service = "".to_string();
+        let mut addr = None;
+        let mut port = None;
+        let grpc = value.scheme().starts_with("grpc");
         // HttpHealthCheck
         for (key, value) in value.query_pairs().into_iter() {
             match key.as_ref() {
+                "service" if grpc => {
+                    service = value.to_string();
+                },
                 "connection_timeout" => {
                     if let Ok(d) = parse_duration(value.as_ref()) {
                         connection_timeout = d;
@@ -183,6 +401,30 @@ impl TryFrom<&str> for HealthCheckConf {
                 "reuse" => {
                     reuse_connection = true;
                 },
+                // the address is `ip` or `ip:port`
+                "addr" => {
+                    if let Ok(socket_addr) = value.parse::<SocketAddr>() {
+                        addr = Some(socket_addr.ip());
+                        port = Some(socket_addr.port());
+                    } else {
+                        addr = Some(value.parse::<IpAddr>().map_err(|_| {
+                            Error::Invalid {
+                                message: format!(
+                                    "health check addr({value}) is invalid"
+                                ),
+                            }
+                        })?);
+                    }
+                },
+                "port" => {
+                    port = Some(value.parse::<u16>().map_err(|_| {
+                        Error::Invalid {
+                            message: format!(
+                                "health check port({value}) is invalid"
+                            ),
+                        }
+                    })?);
+                },
                 _ => {
                     if value.is_empty() {
                         query_list.push(key.to_string());
@@ -211,10 +453,49 @@ impl TryFrom<&str> for HealthCheckConf {
             check_frequency,
             consecutive_success,
             consecutive_failure,
+            service,
+            addr,
+            port,
         })
     }
 }
 
+/// The health check on the alternate address of backend, e.g. the backend
+/// exposes a dedicated health port.
+struct AddrOverrideHealthCheck {
+    check: Box<dyn HealthCheck + Send + Sync + 'static>,
+    addr: Option<IpAddr>,
+    port: Option<u16>,
+}
+
+impl AddrOverrideHealthCheck {
+    /// Gets the check target of backend, only the inet address is overridden.
+    fn get_target(&self, target: &Backend) -> Backend {
+        let mut backend = target.clone();
+        if let pingora::protocols::l4::socket::SocketAddr::Inet(addr) =
+            &mut backend.addr
+        {
+            if let Some(ip) = self.addr {
+                addr.set_ip(ip);
+            }
+            if let Some(port) = self.port {
+                addr.set_port(port);
+            }
+        }
+        backend
+    }
+}
+
+#[async_trait]
+impl HealthCheck for AddrOverrideHealthCheck {
+    fn health_threshold(&self, success: bool) -> usize {
+        self.check.health_threshold(success)
+    }
+    async fn check(&self, target: &Backend) -> pingora::Result<()> {
+        self.check.check(&self.get_target(target)).await
+    }
+}
+
 fn update_peer_options(
     conf: &HealthCheckConf,
     opt: PeerOptions,
@@ -265,11 +546,23 @@ fn new_http_health_check(conf: &HealthCheckConf) -> HttpHealthCheck {
     check
 }
 
+fn new_grpc_health_check(conf: &HealthCheckConf) -> GrpcHealthCheck {
+    let mut check =
+        GrpcHealthCheck::new(&conf.host, conf.schema == "grpcs", &conf.service);
+    check.peer_template.options =
+        update_peer_options(conf, check.peer_template.options.clone());
+    check.consecutive_success = conf.consecutive_success;
+    check.consecutive_failure = conf.consecutive_failure;
+
+    check
+}
+
 fn new_health_check(
     name: &str,
     health_check: &str,
 ) -> Result<(Box<dyn HealthCheck + Send + Sync + 'static>, Duration)> {
     let mut health_check_frequency = Duration::from_secs(10);
+    let mut addr_override = None;
     let hc: Box<dyn HealthCheck + Send + Sync + 'static> =
         if health_check.is_empty() {
             let mut check = TcpHealthCheck::new();
@@ -289,36 +582,90 @@ fn new_health_check(
                 health_check_conf = format!("{health_check_conf:?}"),
                 "new http health check"
             );
+            if health_check_conf.addr.is_some()
+                || health_check_conf.port.is_some()
+            {
+                addr_override =
+                    Some((health_check_conf.addr, health_check_conf.port));
+            }
             match health_check_conf.schema.as_str() {
                 "http" | "https" => {
                     Box::new(new_http_health_check(&health_check_conf))
                 },
+                "grpc" | "grpcs" => {
+                    Box::new(new_grpc_health_check(&health_check_conf))
+                },
                 _ => Box::new(new_tcp_health_check(&health_check_conf)),
             }
         };
-    Ok((hc, health_check_frequency))
+    // the health check is sent to the alternate address of backend
+    let hc: Box<dyn HealthCheck + Send + Sync + 'static> =
+        if let Some((addr, port)) = addr_override {
+            Box::new(AddrOverrideHealthCheck {
+                check: hc,
+                addr,
+                port,
+            })
+        } else {
+            hc
+        };
+    // the results are recorded for history and state change notification
+    Ok((
+        Box::new(ObservedHealthCheck::new(name, hc)),
+        health_check_frequency,
+    ))
 }
 
 const DNS_DISCOVERY: &str = "dns";
+const XDS_DISCOVERY: &str = "xds";
+// the endpoints of xds are pushed to memory,
+// so they can be updated frequently
+const XDS_UPDATE_FREQUENCY: Duration = Duration::from_secs(10);
+const ALGO_P2C_EWMA: &str = "p2c_ewma";
+const DNS_MIN_TTL: Duration = Duration::from_secs(5);
+const DNS_MAX_TTL: Duration = Duration::from_secs(3600);
+const RESOLVE_NEGATIVE_VALID: Duration = Duration::from_secs(5);
+// the default max concurrent streams of one h2 connection,
+// pingora uses 1 which makes the h2 connection can't be multiplexed
+const DEFAULT_MAX_H2_STREAMS: usize = 100;
+// the default min healthy backends to switch blue green upstream
+const BLUE_GREEN_MIN_HEALTHY: usize = 1;
 
 pub fn is_dns_discovery(value: &str) -> bool {
     value == DNS_DISCOVERY
 }
 
+pub fn is_xds_discovery(value: &str) -> bool {
+    value == XDS_DISCOVERY
+}
+
 fn new_backends(
+    name: &str,
     addrs: &[String],
     tls: bool,
-    ipv4_only: bool,
+    family: AddrFamily,
+    dns_ttl: Option<(Duration, Duration)>,
+    dns_failure_policy: DnsFailurePolicy,
     discovery: &str,
 ) -> Result<Backends> {
     if discovery == DNS_DISCOVERY {
-        new_dns_discover_backends(addrs, tls, ipv4_only).map_err(|e| {
-            Error::Invalid {
-                message: e.to_string(),
-            }
+        new_dns_discover_backends(
+            name,
+            addrs,
+            tls,
+            family,
+            dns_ttl,
+            dns_failure_policy,
+        )
+        .map_err(|e| Error::Invalid {
+            message: e.to_string(),
+        })
+    } else if discovery == XDS_DISCOVERY {
+        new_xds_discover_backends(addrs).map_err(|e| Error::Invalid {
+            message: e.to_string(),
         })
     } else {
-        new_common_discover_backends(addrs, tls, ipv4_only).map_err(|e| {
+        new_common_discover_backends(addrs, tls, family).map_err(|e| {
             Error::Invalid {
                 message: e.to_string(),
             }
@@ -326,6 +673,27 @@ fn new_backends(
     }
 }
 
+/// Gets the resolved addresses of zero weight, these backends are drained.
+/// Only the static addresses are supported, the domain is resolved once.
+fn get_zero_weight_addrs(addrs: &[String], tls: bool) -> Vec<String> {
+    format_addrs(addrs, tls)
+        .iter()
+        .filter(|(_, _, weight)| *weight == 0)
+        .flat_map(|(host, port, _)| {
+            util::join_host_port(host, port)
+                .to_socket_addrs()
+                .map(|items| {
+                    items.map(|item| item.to_string()).collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn is_ipv6_addr<T: AsRef<str>>(addr: T) -> bool {
+    addr.as_ref().starts_with('[')
+}
+
 fn get_hash_value(
     hash: &str,
     hash_key: &str,
@@ -371,10 +739,54 @@ impl Upstream {
         let mut hash = "".to_string();
         let sni = conf.sni.clone().unwrap_or_default();
         let tls = !sni.is_empty();
+        let family = AddrFamily::new(
+            conf.ipv4_only.unwrap_or_default(),
+            conf.ipv6_only.unwrap_or_default(),
+            conf.prefer_ipv6.unwrap_or_default(),
+        );
+        // refresh the backends of dns discovery after the ttl of dns record
+        let dns_ttl = if conf.dns_ttl_refresh.unwrap_or_default() {
+            Some((
+                conf.dns_min_ttl.unwrap_or(DNS_MIN_TTL),
+                conf.dns_max_ttl.unwrap_or(DNS_MAX_TTL),
+            ))
+        } else {
+            None
+        };
+        let dns_failure_policy = DnsFailurePolicy::new(
+            conf.dns_failure_policy.as_deref().unwrap_or_default(),
+            conf.dns_max_stale,
+            &conf.dns_fallback_addrs.clone().unwrap_or_default(),
+        )
+        .map_err(|e| Error::Invalid {
+            message: e.to_string(),
+        })?;
+        // the hosts of lazy resolve upstream are resolved at request time,
+        // so the backends of load balancer are empty
+        let lazy_resolver = if conf.lazy_resolve.unwrap_or_default() {
+            Some(LazyResolver::new(
+                &conf.addrs,
+                tls,
+                family,
+                conf.resolve_valid,
+                conf.resolve_negative_valid
+                    .unwrap_or(RESOLVE_NEGATIVE_VALID),
+            ))
+        } else {
+            None
+        };
+        let addrs = if lazy_resolver.is_some() {
+            vec![]
+        } else {
+            conf.addrs.clone()
+        };
         let backends = new_backends(
-            &conf.addrs,
+            name,
+            &addrs,
             tls,
-            conf.ipv4_only.unwrap_or_default(),
+            family,
+            dns_ttl,
+            dns_failure_policy,
             discovery.as_str(),
         )?;
 
@@ -382,6 +794,15 @@ impl Upstream {
             name,
             &conf.health_check.clone().unwrap_or_default(),
         )?;
+        let update_frequency = if discovery == XDS_DISCOVERY {
+            conf.update_frequency.or(Some(XDS_UPDATE_FREQUENCY))
+        } else if discovery == DNS_DISCOVERY && dns_ttl.is_some() {
+            // the dns discovery is checked with min ttl,
+            // and the backends are resolved again only if ttl is expired
+            conf.update_frequency.or(dns_ttl.map(|(min, _)| min))
+        } else {
+            conf.update_frequency
+        };
         let algo_method = conf.algo.clone().unwrap_or_default();
         let algo_params: Vec<&str> = algo_method.split(':').collect();
         let mut hash_key = "".to_string();
@@ -393,8 +814,8 @@ impl Upstream {
                 return;
             };
 
-            if discovery == DNS_DISCOVERY {
-                error!(error = err.to_string(), "dns discovery fail");
+            if discovery == DNS_DISCOVERY || discovery == XDS_DISCOVERY {
+                error!(error = err.to_string(), discovery, "discovery fail");
                 return;
             }
             // not dns discovery should panic
@@ -413,7 +834,7 @@ impl Upstream {
                 let result = lb.update().now_or_never();
                 check_result(result);
                 lb.set_health_check(hc);
-                lb.update_frequency = conf.update_frequency;
+                lb.update_frequency = update_frequency;
                 lb.health_check_frequency = Some(health_check_frequency);
                 SelectionLb::Consistent(Arc::new(lb))
             },
@@ -423,7 +844,7 @@ impl Upstream {
                 let result = lb.update().now_or_never();
                 check_result(result);
                 lb.set_health_check(hc);
-                lb.update_frequency = conf.update_frequency;
+                lb.update_frequency = update_frequency;
                 lb.health_check_frequency = Some(health_check_frequency);
                 SelectionLb::RoundRobin(Arc::new(lb))
             },
@@ -438,6 +859,10 @@ impl Upstream {
         } else {
             ALPN::H1
         };
+        // h2 connection is multiplexed by default if alpn includes h2
+        let max_h2_streams = conf.max_h2_streams.or_else(|| {
+            (!matches!(alpn, ALPN::H1)).then_some(DEFAULT_MAX_H2_STREAMS)
+        });
 
         let tcp_keepalive = if conf.tcp_idle.is_some()
             && conf.tcp_probe_count.is_some()
@@ -452,7 +877,10 @@ impl Upstream {
             None
         };
 
-        let peer_tracer = if conf.enable_tracer.unwrap_or_default() {
+        // the keepalive pool size is limited by the connected count of tracer
+        let peer_tracer = if conf.enable_tracer.unwrap_or_default()
+            || conf.keepalive_pool_size.is_some()
+        {
             Some(UpstreamPeerTracer::new())
         } else {
             None
@@ -460,6 +888,21 @@ impl Upstream {
         let tracer = peer_tracer
             .as_ref()
             .map(|peer_tracer| Tracer(Box::new(peer_tracer.to_owned())));
+        // the backends drained by admin api are kept after reloading
+        let mut draining_backends = get_backend_drains(name);
+        if discovery != DNS_DISCOVERY && discovery != XDS_DISCOVERY {
+            for addr in get_zero_weight_addrs(&addrs, tls) {
+                draining_backends.insert(
+                    addr,
+                    BackendDrain {
+                        started_at: util::now().as_secs(),
+                        from_config: true,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+        let draining_count = draining_backends.len() as u32;
         let up = Self {
             name: name.to_string(),
             tls,
@@ -479,6 +922,33 @@ impl Upstream {
             tcp_fast_open: conf.tcp_fast_open,
             peer_tracer,
             tracer,
+            keepalive_pool_size: conf.keepalive_pool_size,
+            max_requests_per_connection: conf.max_requests_per_connection,
+            max_h2_streams,
+            connection_requests: Mutex::new(AHashMap::new()),
+            active_connections: AtomicU32::new(0),
+            new_connections: AtomicU64::new(0),
+            reused_connections: AtomicU64::new(0),
+            closed_connections: AtomicU64::new(0),
+            slow_start: conf.slow_start,
+            backend_warmups: Mutex::new(AHashMap::new()),
+            warming_backends: AtomicU32::new(0),
+            prefer_ipv6: family == AddrFamily::PreferIpv6,
+            backend_stats: Mutex::new(AHashMap::new()),
+            p2c_ewma: algo_params[0] == ALGO_P2C_EWMA,
+            lazy_resolver,
+            draining_backends: Mutex::new(draining_backends),
+            draining_count: AtomicU32::new(draining_count),
+            s3_origin: S3Origin::new(conf),
+            fastcgi: FastCgi::new(conf),
+            wsgi: Wsgi::new(conf),
+            green_upstream: conf.green_upstream.clone(),
+            blue_green_min_healthy: conf
+                .blue_green_min_healthy
+                .unwrap_or(BLUE_GREEN_MIN_HEALTHY),
+            max_concurrency: conf.max_concurrency.unwrap_or_default(),
+            reserved_concurrency: conf.reserved_concurrency.unwrap_or_default(),
+            processing: Arc::new(AtomicU32::new(0)),
         };
         debug!(upstream = up.to_string(), "new upstream");
         Ok(up)
@@ -491,33 +961,502 @@ impl Upstream {
         session: &Session,
         ctx: &State,
     ) -> Option<HttpPeer> {
+        let excluded = ctx.upstream_failed_addrs.as_deref();
         let upstream = match &self.lb {
-            SelectionLb::RoundRobin(lb) => lb.select(b"", 256),
+            SelectionLb::RoundRobin(lb) if self.p2c_ewma => {
+                self.select_p2c_backend(lb, excluded)
+            },
+            SelectionLb::RoundRobin(lb) => {
+                self.select_backend(lb, b"", excluded)
+            },
             SelectionLb::Consistent(lb) => {
                 let value =
                     get_hash_value(&self.hash, &self.hash_key, session, ctx);
-                lb.select(value.as_bytes(), 256)
+                self.select_backend(lb, value.as_bytes(), excluded)
+            },
+        };
+        upstream.map(|upstream| self.new_peer(upstream))
+    }
+
+    /// Returns the sni of upstream if it's tls.
+    #[inline]
+    pub fn get_sni(&self) -> Option<&str> {
+        self.tls.then_some(self.sni.as_str())
+    }
+
+    /// Returns the s3 origin of upstream.
+    #[inline]
+    pub fn get_s3_origin(&self) -> Option<&S3Origin> {
+        self.s3_origin.as_ref()
+    }
+
+    /// Returns the fastcgi of upstream.
+    #[inline]
+    pub fn get_fastcgi(&self) -> Option<&FastCgi> {
+        self.fastcgi.as_ref()
+    }
+
+    /// Returns the wsgi(uwsgi or scgi) of upstream.
+    #[inline]
+    pub fn get_wsgi(&self) -> Option<&Wsgi> {
+        self.wsgi.as_ref()
+    }
+
+    /// Returns `true` if the hosts of upstream are resolved at request time.
+    #[inline]
+    pub fn is_lazy_resolve(&self) -> bool {
+        self.lazy_resolver.is_some()
+    }
+
+    /// Returns a new http peer of lazy resolve upstream, the host is resolved
+    /// by the cache of resolver, if it fails to resolve, it will return `None`.
+    pub async fn new_lazy_http_peer(&self, ctx: &State) -> Option<HttpPeer> {
+        let resolver = self.lazy_resolver.as_ref()?;
+        let upstream = resolver
+            .select(ctx.upstream_failed_addrs.as_deref())
+            .await?;
+        Some(self.new_peer(upstream))
+    }
+
+    fn new_peer(&self, upstream: Backend) -> HttpPeer {
+        let mut p = HttpPeer::new(upstream, self.tls, self.sni.clone());
+        p.options.connection_timeout = self.connection_timeout;
+        p.options.total_connection_timeout = self.total_connection_timeout;
+        p.options.read_timeout = self.read_timeout;
+        p.options.idle_timeout = self.idle_timeout;
+        p.options.write_timeout = self.write_timeout;
+        if let Some(verify_cert) = self.verify_cert {
+            p.options.verify_cert = verify_cert;
+        }
+        p.options.alpn = self.alpn.clone();
+        p.options.tcp_keepalive.clone_from(&self.tcp_keepalive);
+        p.options.tcp_recv_buf = self.tcp_recv_buf;
+        if let Some(tcp_fast_open) = self.tcp_fast_open {
+            p.options.tcp_fast_open = tcp_fast_open;
+        }
+        p.options.tracer.clone_from(&self.tracer);
+        if let Some(max_h2_streams) = self.max_h2_streams {
+            p.options.max_h2_streams = max_h2_streams;
+        }
+        p
+    }
+
+    /// Select the backend of load balancer, the warming backends are
+    /// accepted by their weight factor if slow start is enabled,
+    /// the excluded backends(failed to connect) and the draining backends
+    /// are skipped.
+    #[inline]
+    fn select_backend<S>(
+        &self,
+        lb: &LoadBalancer<S>,
+        key: &[u8],
+        excluded: Option<&[String]>,
+    ) -> Option<Backend>
+    where
+        S: BackendSelection + 'static,
+        S::Iter: BackendIter,
+    {
+        let window = self
+            .slow_start
+            .filter(|_| self.warming_backends.load(Ordering::Relaxed) > 0);
+        // ipv6 backends are preferred until one of them fails to connect
+        let ipv6_only = self.prefer_ipv6
+            && !excluded.is_some_and(|addrs| addrs.iter().any(is_ipv6_addr));
+        let draining = self.draining_count.load(Ordering::Relaxed) > 0;
+        if window.is_none() && excluded.is_none() && !ipv6_only && !draining {
+            return lb.select(key, 256);
+        }
+        let backend = lb.select_with(key, 256, |backend, healthy| {
+            if !healthy {
+                return false;
+            }
+            if ipv6_only && !is_ipv6_addr(&backend.addr.to_string()) {
+                return false;
+            }
+            if excluded
+                .is_some_and(|addrs| addrs.contains(&backend.addr.to_string()))
+            {
+                return false;
+            }
+            if draining && self.is_draining(&backend.addr.to_string()) {
+                return false;
+            }
+            let Some(window) = window else {
+                return true;
+            };
+            let Ok(mut warmups) = self.backend_warmups.lock() else {
+                return true;
+            };
+            warmups
+                .get_mut(&backend.addr.to_string())
+                .map(|warmup| warmup.accept(window))
+                .unwrap_or(true)
+        });
+        // all healthy backends are warming, excluded or draining
+        backend.or_else(|| lb.select(key, 256))
+    }
+
+    /// Select the backend by power of two choices, two random healthy
+    /// backends are picked and the one with lower ewma latency is used.
+    /// The backend without stats is preferred, so it can be measured.
+    fn select_p2c_backend(
+        &self,
+        lb: &LoadBalancer<RoundRobin>,
+        excluded: Option<&[String]>,
+    ) -> Option<Backend> {
+        let backends = lb.backends().get_backend();
+        let draining = self.draining_count.load(Ordering::Relaxed) > 0;
+        let candidates: Vec<&Backend> = backends
+            .iter()
+            .filter(|backend| {
+                let addr = backend.addr.to_string();
+                lb.backends().ready(backend)
+                    && !excluded.is_some_and(|addrs| addrs.contains(&addr))
+                    && !(draining && self.is_draining(&addr))
+            })
+            .collect();
+        let (first, second) = match candidates.len() {
+            0 => return self.select_backend(lb, b"", excluded),
+            1 => return Some(candidates[0].clone()),
+            count => {
+                let (a, b) = uuid::Uuid::new_v4().as_u64_pair();
+                let first = (a % count as u64) as usize;
+                // the second one is different from the first one
+                let second =
+                    (first + 1 + (b % (count - 1) as u64) as usize) % count;
+                (candidates[first], candidates[second])
             },
         };
-        upstream.map(|upstream| {
-            let mut p = HttpPeer::new(upstream, self.tls, self.sni.clone());
-            p.options.connection_timeout = self.connection_timeout;
-            p.options.total_connection_timeout = self.total_connection_timeout;
-            p.options.read_timeout = self.read_timeout;
-            p.options.idle_timeout = self.idle_timeout;
-            p.options.write_timeout = self.write_timeout;
-            if let Some(verify_cert) = self.verify_cert {
-                p.options.verify_cert = verify_cert;
+        let Ok(stats) = self.backend_stats.lock() else {
+            return Some(first.clone());
+        };
+        let get_latency = |backend: &Backend| {
+            stats
+                .get(&backend.addr.to_string())
+                .map(|item| item.latency_ewma)
+                .unwrap_or_default()
+        };
+        if get_latency(second) < get_latency(first) {
+            Some(second.clone())
+        } else {
+            Some(first.clone())
+        }
+    }
+
+    /// Whether it should fall back to the ipv4 backends after the ipv6
+    /// backend fails to connect, it only falls back once.
+    pub fn should_fallback_ipv4(
+        &self,
+        addr: &str,
+        failed_addrs: Option<&[String]>,
+    ) -> bool {
+        self.prefer_ipv6
+            && is_ipv6_addr(addr)
+            && !failed_addrs.is_some_and(|addrs| addrs.iter().any(is_ipv6_addr))
+    }
+
+    /// Refresh the warmup state of backends, the backend which is newly
+    /// added or recovers from unhealthy will start to warm up.
+    /// The backends of the first refresh are regarded as warmed.
+    fn refresh_slow_start<S>(&self, lb: &LoadBalancer<S>)
+    where
+        S: BackendSelection + 'static,
+        S::Iter: BackendIter,
+    {
+        let Some(window) = self.slow_start else {
+            return;
+        };
+        let Ok(mut warmups) = self.backend_warmups.lock() else {
+            return;
+        };
+        let first = warmups.is_empty();
+        let now = Instant::now();
+        let backends = lb.backends().get_backend();
+        let mut addrs = Vec::with_capacity(backends.len());
+        for backend in backends.iter() {
+            let addr = backend.addr.to_string();
+            let healthy = lb.backends().ready(backend);
+            if let Some(warmup) = warmups.get_mut(&addr) {
+                if healthy && !warmup.healthy {
+                    info!(name = self.name, addr, "backend starts to warm up");
+                    warmup.warmup_at = Some(now);
+                    warmup.count = 0;
+                }
+                warmup.healthy = healthy;
+            } else {
+                let warmup_at = if first {
+                    None
+                } else {
+                    info!(name = self.name, addr, "backend starts to warm up");
+                    Some(now)
+                };
+                warmups.insert(
+                    addr.clone(),
+                    BackendWarmup {
+                        healthy,
+                        warmup_at,
+                        count: 0,
+                    },
+                );
+            }
+            addrs.push(addr);
+        }
+        warmups.retain(|addr, _| addrs.contains(addr));
+        let warming = warmups
+            .values_mut()
+            .filter(|warmup| warmup.factor(window) < 1.0)
+            .count();
+        self.warming_backends
+            .store(warming as u32, Ordering::Relaxed);
+    }
+
+    /// Records the connection which is used to proxy the request,
+    /// returns `true` if the connection should be closed after the request,
+    /// because it exceeds the max requests or the idle connections of
+    /// keepalive pool are full. Only http/1.1 connection supports to be
+    /// closed by `Connection: close`.
+    #[inline]
+    pub fn on_connection_used(&self, fd: RawFd, reused: bool) -> bool {
+        let active =
+            self.active_connections.fetch_add(1, Ordering::Relaxed) + 1;
+        if reused {
+            self.reused_connections.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.new_connections.fetch_add(1, Ordering::Relaxed);
+        }
+        if !matches!(self.alpn, ALPN::H1) {
+            return false;
+        }
+        let mut should_close = false;
+        if let Some(max_requests) = self.max_requests_per_connection {
+            if let Ok(mut connection_requests) = self.connection_requests.lock()
+            {
+                let count = connection_requests.entry(fd).or_insert(0);
+                // the fd may be reused by a new connection
+                if !reused {
+                    *count = 0;
+                }
+                *count += 1;
+                if *count >= max_requests {
+                    connection_requests.remove(&fd);
+                    should_close = true;
+                }
             }
-            p.options.alpn = self.alpn.clone();
-            p.options.tcp_keepalive.clone_from(&self.tcp_keepalive);
-            p.options.tcp_recv_buf = self.tcp_recv_buf;
-            if let Some(tcp_fast_open) = self.tcp_fast_open {
-                p.options.tcp_fast_open = tcp_fast_open;
+        }
+        if let Some(pool_size) = self.keepalive_pool_size {
+            // the connection is returned to the pool after the request,
+            // so it's closed if the idle connections are full
+            let idle =
+                self.connected().unwrap_or_default().saturating_sub(active);
+            if idle as usize >= pool_size {
+                should_close = true;
             }
-            p.options.tracer.clone_from(&self.tracer);
-            p
-        })
+        }
+        if should_close {
+            self.closed_connections.fetch_add(1, Ordering::Relaxed);
+        }
+        should_close
+    }
+
+    /// Records the connection is released after the request.
+    #[inline]
+    pub fn on_connection_released(&self) {
+        let _ = self.active_connections.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |value| Some(value.saturating_sub(1)),
+        );
+    }
+
+    /// Returns `true` if the backend is draining.
+    #[inline]
+    fn is_draining(&self, addr: &str) -> bool {
+        self.draining_backends
+            .lock()
+            .map(|backends| backends.contains_key(addr))
+            .unwrap_or_default()
+    }
+
+    /// Sets the draining state of backend, `None` means the drain is removed.
+    fn set_draining(&self, addr: &str, drain: Option<BackendDrain>) {
+        let Ok(mut backends) = self.draining_backends.lock() else {
+            return;
+        };
+        if let Some(drain) = drain {
+            backends.insert(addr.to_string(), drain);
+        } else {
+            backends.remove(addr);
+        }
+        self.draining_count
+            .store(backends.len() as u32, Ordering::Relaxed);
+    }
+
+    /// Records the request which is connected to the backend,
+    /// returns `true` if the backend is draining, so the connection
+    /// should be closed after the request.
+    pub fn on_backend_connected(&self, addr: &str) -> bool {
+        if let Ok(mut stats) = self.backend_stats.lock() {
+            stats.entry(addr.to_string()).or_default().processing += 1;
+        }
+        self.draining_count.load(Ordering::Relaxed) > 0
+            && self.is_draining(addr)
+    }
+
+    /// Records the request of backend is finished.
+    pub fn on_backend_finished(&self, addr: &str) {
+        if addr.is_empty() {
+            return;
+        }
+        if let Ok(mut stats) = self.backend_stats.lock() {
+            if let Some(item) = stats.get_mut(addr) {
+                item.processing = item.processing.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Record the request result of backend, the failed request
+    /// includes connect failure and 5xx response.
+    pub fn record_backend(
+        &self,
+        addr: &str,
+        latency: Option<u64>,
+        failed: bool,
+    ) {
+        if addr.is_empty() {
+            return;
+        }
+        let Ok(mut stats) = self.backend_stats.lock() else {
+            return;
+        };
+        if let Some(item) = stats.get_mut(addr) {
+            item.record(latency, failed);
+            return;
+        }
+        let mut item = BackendStats::default();
+        item.record(latency, failed);
+        stats.insert(addr.to_string(), item);
+    }
+
+    /// Get the connection stats of upstream.
+    pub fn stats(&self) -> UpstreamStats {
+        let backends: HashMap<String, BackendStats> = self
+            .backend_stats
+            .lock()
+            .map(|stats| {
+                stats
+                    .iter()
+                    .map(|(addr, item)| (addr.to_string(), item.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let draining = self
+            .draining_backends
+            .lock()
+            .map(|drains| {
+                drains
+                    .iter()
+                    .map(|(addr, drain)| {
+                        let processing = backends
+                            .get(addr)
+                            .map(|item| item.processing)
+                            .unwrap_or_default();
+                        let drain = BackendDrain {
+                            processing,
+                            drained: processing == 0,
+                            ..drain.clone()
+                        };
+                        (addr.to_string(), drain)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        UpstreamStats {
+            connected: self.connected(),
+            new_connections: self.new_connections.load(Ordering::Relaxed),
+            reused_connections: self.reused_connections.load(Ordering::Relaxed),
+            closed_connections: self.closed_connections.load(Ordering::Relaxed),
+            backends,
+            paused: get_upstream_pause(&self.name),
+            draining,
+            color: self
+                .green_upstream
+                .as_ref()
+                .map(|_| get_upstream_color(&self.name)),
+            processing: self.processing.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Gets the concurrency limit of location priority, the reserved
+    /// concurrency is available in proportion to the priority,
+    /// the lowest priority can't use it and the highest can use all.
+    fn get_concurrency_limit(&self, priority: u8) -> u32 {
+        let max = MAX_LOCATION_PRIORITY as u32;
+        let priority = (priority as u32).min(max);
+        let reserved = self.reserved_concurrency * (max - priority) / max;
+        self.max_concurrency.saturating_sub(reserved)
+    }
+
+    /// Acquires the concurrency of upstream for the request of location,
+    /// returns `None` if the concurrency limit of its priority is reached.
+    pub fn acquire_concurrency(
+        &self,
+        priority: u8,
+    ) -> Option<UpstreamConcurrencyGuard> {
+        let limit = self.get_concurrency_limit(priority);
+        self.processing
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |value| {
+                if self.max_concurrency > 0 && value >= limit {
+                    None
+                } else {
+                    Some(value + 1)
+                }
+            })
+            .ok()?;
+        Some(UpstreamConcurrencyGuard(self.processing.clone()))
+    }
+
+    /// Returns the healthy backend count of upstream.
+    fn healthy_count(&self) -> usize {
+        self.get_backends_health()
+            .iter()
+            .filter(|(_, healthy)| *healthy)
+            .count()
+    }
+
+    /// Checks whether the blue green upstream can be switched to the target
+    /// upstream, the healthy backends of target should be enough.
+    fn check_switch_target(&self, target: &Upstream) -> Result<()> {
+        let healthy = target.healthy_count();
+        if healthy < self.blue_green_min_healthy {
+            return Err(Error::Invalid {
+                message: format!(
+                    "Healthy backends of upstream({}) are not enough, healthy: {healthy}, required: {}",
+                    target.name, self.blue_green_min_healthy
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Gets the backends of upstream with their health state.
+    pub fn get_backends_health(&self) -> Vec<(String, bool)> {
+        fn get_health<S>(lb: &LoadBalancer<S>) -> Vec<(String, bool)>
+        where
+            S: BackendSelection + 'static,
+            S::Iter: BackendIter,
+        {
+            lb.backends()
+                .get_backend()
+                .iter()
+                .map(|backend| {
+                    (backend.addr.to_string(), lb.backends().ready(backend))
+                })
+                .collect()
+        }
+        match &self.lb {
+            SelectionLb::RoundRobin(lb) => get_health(lb),
+            SelectionLb::Consistent(lb) => get_health(lb),
+        }
     }
 
     /// Get the connected count of upstream
@@ -552,6 +1491,218 @@ pub fn get_upstream(name: &str) -> Option<Arc<Upstream>> {
     UPSTREAM_MAP.load().get(name).cloned()
 }
 
+// the paused state is kept by upstream name, so it isn't reset by reloading
+static UPSTREAM_PAUSES: Lazy<Mutex<AHashMap<String, UpstreamPause>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+// the max duration of pause, it will be resumed automatically after expired
+const MAX_UPSTREAM_PAUSE_DURATION: Duration = Duration::from_secs(24 * 3600);
+
+/// Pause the discovery or health check of upstream for a duration,
+/// the duration is limited to 24 hours.
+pub fn pause_upstream(
+    name: &str,
+    discovery: bool,
+    health_check: bool,
+    duration: Duration,
+) -> Result<UpstreamPause> {
+    let expired_at = util::now().as_secs()
+        + duration.min(MAX_UPSTREAM_PAUSE_DURATION).as_secs();
+    let mut pauses = UPSTREAM_PAUSES.lock().map_err(|e| Error::Invalid {
+        message: e.to_string(),
+    })?;
+    let pause = pauses.entry(name.to_string()).or_default();
+    if discovery {
+        pause.discovery = Some(expired_at);
+    }
+    if health_check {
+        pause.health_check = Some(expired_at);
+    }
+    info!(name, discovery, health_check, expired_at, "pause upstream");
+    Ok(pause.clone())
+}
+
+/// Resume the paused discovery or health check of upstream.
+pub fn resume_upstream(
+    name: &str,
+    discovery: bool,
+    health_check: bool,
+) -> Result<()> {
+    let mut pauses = UPSTREAM_PAUSES.lock().map_err(|e| Error::Invalid {
+        message: e.to_string(),
+    })?;
+    if let Some(pause) = pauses.get_mut(name) {
+        if discovery {
+            pause.discovery = None;
+        }
+        if health_check {
+            pause.health_check = None;
+        }
+        if pause.is_empty() {
+            pauses.remove(name);
+        }
+    }
+    info!(name, discovery, health_check, "resume upstream");
+    Ok(())
+}
+
+/// Get the paused state of upstream, the expired pause is removed.
+pub fn get_upstream_pause(name: &str) -> Option<UpstreamPause> {
+    let mut pauses = UPSTREAM_PAUSES.lock().ok()?;
+    let pause = pauses.get_mut(name)?;
+    pause.clear_expired(util::now().as_secs());
+    if pause.is_empty() {
+        pauses.remove(name);
+        return None;
+    }
+    Some(pause.clone())
+}
+
+// the backends drained by admin api, key is the upstream name,
+// and the value is the drain start time of backends
+static BACKEND_DRAINS: Lazy<Mutex<AHashMap<String, AHashMap<String, u64>>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+fn get_backend_drains(name: &str) -> AHashMap<String, BackendDrain> {
+    let Ok(drains) = BACKEND_DRAINS.lock() else {
+        return AHashMap::new();
+    };
+    drains
+        .get(name)
+        .map(|backends| {
+            backends
+                .iter()
+                .map(|(addr, started_at)| {
+                    let drain = BackendDrain {
+                        started_at: *started_at,
+                        ..Default::default()
+                    };
+                    (addr.to_string(), drain)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Drains the backend of upstream, it's the same as setting the weight
+/// of backend to zero, the backend doesn't receive new requests.
+pub fn drain_backend(name: &str, addr: &str) -> Result<BackendDrain> {
+    let up = get_upstream(name).ok_or(Error::Invalid {
+        message: format!("Upstream({name}) is not found"),
+    })?;
+    if !up
+        .get_backends_health()
+        .iter()
+        .any(|(item, _)| item == addr)
+    {
+        return Err(Error::Invalid {
+            message: format!(
+                "Backend({addr}) of upstream({name}) is not found"
+            ),
+        });
+    }
+    let started_at = util::now().as_secs();
+    let mut drains = BACKEND_DRAINS.lock().map_err(|e| Error::Invalid {
+        message: e.to_string(),
+    })?;
+    drains
+        .entry(name.to_string())
+        .or_default()
+        .insert(addr.to_string(), started_at);
+    let drain = BackendDrain {
+        started_at,
+        ..Default::default()
+    };
+    up.set_draining(addr, Some(drain.clone()));
+    info!(name, addr, "drain backend");
+    Ok(drain)
+}
+
+/// Restores the drained backend of upstream, the backend of zero weight
+/// is restored until the config is reloaded.
+pub fn restore_backend(name: &str, addr: &str) -> Result<()> {
+    let mut drains = BACKEND_DRAINS.lock().map_err(|e| Error::Invalid {
+        message: e.to_string(),
+    })?;
+    if let Some(backends) = drains.get_mut(name) {
+        backends.remove(addr);
+        if backends.is_empty() {
+            drains.remove(name);
+        }
+    }
+    if let Some(up) = get_upstream(name) {
+        up.set_draining(addr, None);
+    }
+    info!(name, addr, "restore backend");
+    Ok(())
+}
+
+// the active color of blue green upstreams, key is the upstream name,
+// only the green color is kept and it isn't reset by reloading
+static UPSTREAM_COLORS: Lazy<Mutex<AHashMap<String, UpstreamColor>>> =
+    Lazy::new(|| Mutex::new(AHashMap::new()));
+
+/// Gets the active color of blue green upstream.
+fn get_upstream_color(name: &str) -> UpstreamColor {
+    UPSTREAM_COLORS
+        .lock()
+        .ok()
+        .and_then(|colors| colors.get(name).copied())
+        .unwrap_or_default()
+}
+
+/// Gets the upstream to handle request, it's the green upstream if the
+/// blue green upstream is switched to green.
+pub fn get_active_upstream(name: &str) -> Option<Arc<Upstream>> {
+    let up = get_upstream(name)?;
+    let Some(green) = &up.green_upstream else {
+        return Some(up);
+    };
+    if get_upstream_color(name) == UpstreamColor::Green {
+        if let Some(green) = get_upstream(green) {
+            return Some(green);
+        }
+    }
+    Some(up)
+}
+
+/// Switches the active color of blue green upstream, the switch is refused
+/// if the healthy backends of target upstream are not enough.
+pub fn switch_upstream(name: &str, color: UpstreamColor) -> Result<()> {
+    let up = get_upstream(name).ok_or(Error::Invalid {
+        message: format!("Upstream({name}) is not found"),
+    })?;
+    let Some(green) = &up.green_upstream else {
+        return Err(Error::Invalid {
+            message: format!("Upstream({name}) is not blue green upstream"),
+        });
+    };
+    let target = match color {
+        UpstreamColor::Blue => up.clone(),
+        UpstreamColor::Green => get_upstream(green).ok_or(Error::Invalid {
+            message: format!("Upstream({green}) is not found"),
+        })?,
+    };
+    up.check_switch_target(&target)?;
+    let mut colors = UPSTREAM_COLORS.lock().map_err(|e| Error::Invalid {
+        message: e.to_string(),
+    })?;
+    match color {
+        UpstreamColor::Blue => colors.remove(name),
+        UpstreamColor::Green => colors.insert(name.to_string(), color),
+    };
+    info!(name, color = color.to_string(), "switch upstream");
+    Ok(())
+}
+
+/// Get the connection stats of all upstreams.
+pub fn get_upstreams_stats() -> HashMap<String, UpstreamStats> {
+    UPSTREAM_MAP
+        .load()
+        .iter()
+        .map(|(name, up)| (name.to_string(), up.stats()))
+        .collect()
+}
+
 pub fn try_init_upstreams(confs: &HashMap<String, UpstreamConf>) -> Result<()> {
     let mut upstreams = AHashMap::new();
     for (name, conf) in confs.iter() {
@@ -563,6 +1714,16 @@ pub fn try_init_upstreams(confs: &HashMap<String, UpstreamConf>) -> Result<()> {
     Ok(())
 }
 
+/// Gets the snapshot of upstreams, it's used to roll back the reloading.
+pub(super) fn snapshot_upstreams() -> Arc<Upstreams> {
+    UPSTREAM_MAP.load_full()
+}
+
+/// Restores the upstreams from snapshot.
+pub(super) fn restore_upstreams(snapshot: Arc<Upstreams>) {
+    UPSTREAM_MAP.store(snapshot);
+}
+
 #[async_trait]
 impl ServiceTask for HealthCheckTask {
     async fn run(&self) -> Option<bool> {
@@ -596,10 +1757,15 @@ impl ServiceTask for HealthCheckTask {
                 .unwrap_or_default()
                 .as_secs();
 
+                let pause = get_upstream_pause(&name).unwrap_or_default();
+                if pause.discovery.is_some() {
+                    debug!(name, "update backends is paused");
+                }
                 // the firt time should match
-                if check_count == 0
-                    || (update_frequency > 0
-                        && check_frequency_matched(update_frequency))
+                if pause.discovery.is_none()
+                    && (check_count == 0
+                        || (update_frequency > 0
+                            && check_frequency_matched(update_frequency)))
                 {
                     debug!(name, "update backends is running",);
                     let result = if let Some(lb) = up.as_round_robind() {
@@ -639,7 +1805,18 @@ impl ServiceTask for HealthCheckTask {
                     .unwrap_or_default()
                     .as_secs();
 
-                if !check_frequency_matched(health_check_frequency) {
+                if pause.health_check.is_some() {
+                    debug!(name, "health check is paused");
+                }
+                if pause.health_check.is_some()
+                    || !check_frequency_matched(health_check_frequency)
+                {
+                    // the backends may be changed by discovery
+                    if let Some(lb) = up.as_round_robind() {
+                        up.refresh_slow_start(&lb);
+                    } else if let Some(lb) = up.as_consistent() {
+                        up.refresh_slow_start(&lb);
+                    }
                     return;
                 }
 
@@ -654,6 +1831,11 @@ impl ServiceTask for HealthCheckTask {
                         .await;
                 }
                 debug!(name, "health check is done",);
+                if let Some(lb) = up.as_round_robind() {
+                    up.refresh_slow_start(&lb);
+                } else if let Some(lb) = up.as_consistent() {
+                    up.refresh_slow_start(&lb);
+                }
             })
         });
         futures::future::join_all(jobs).await;
@@ -684,16 +1866,20 @@ pub fn new_upstream_health_check_task(interval: Duration) -> CommonServiceTask {
 #[cfg(test)]
 mod tests {
     use super::{
-        get_hash_value, new_backends, new_health_check, new_http_health_check,
-        new_tcp_health_check, HealthCheckConf, State, Upstream, UpstreamConf,
-        UpstreamPeerTracer,
+        get_hash_value, get_upstream_pause, new_backends, new_health_check,
+        new_http_health_check, new_tcp_health_check, pause_upstream,
+        resume_upstream, AddrOverrideHealthCheck, BackendDrain, BackendWarmup,
+        HealthCheckConf, State, Upstream, UpstreamColor, UpstreamConf,
+        UpstreamPause, UpstreamPeerTracer,
     };
+    use pingora::lb::health_check::HealthCheck;
+    use pingora::lb::Backend;
     use pingora::protocols::ALPN;
     use pingora::proxy::Session;
     use pingora::upstreams::peer::{Peer, Tracing};
     use pretty_assertions::assert_eq;
     use std::sync::atomic::Ordering;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     use tokio_test::io::Builder;
     #[test]
     fn test_health_check_conf() {
@@ -702,7 +1888,7 @@ mod tests {
                 .try_into()
                 .unwrap();
         assert_eq!(
-            r###"HealthCheckConf { schema: "tcp", host: "upstreamname", path: "", connection_timeout: 3s, read_timeout: 3s, check_frequency: 10s, reuse_connection: false, consecutive_success: 2, consecutive_failure: 1 }"###,
+            r###"HealthCheckConf { schema: "tcp", host: "upstreamname", path: "", connection_timeout: 3s, read_timeout: 3s, check_frequency: 10s, reuse_connection: false, consecutive_success: 2, consecutive_failure: 1, service: "", addr: None, port: None }"###,
             format!("{tcp_check:?}")
         );
         let tcp_check = new_tcp_health_check(&tcp_check);
@@ -715,7 +1901,7 @@ mod tests {
 
         let http_check: HealthCheckConf = "https://upstreamname/ping?connection_timeout=3s&read_timeout=1s&success=2&failure=1&check_frequency=10s&from=nginx&reuse".try_into().unwrap();
         assert_eq!(
-            r###"HealthCheckConf { schema: "https", host: "upstreamname", path: "/ping?from=nginx", connection_timeout: 3s, read_timeout: 1s, check_frequency: 10s, reuse_connection: true, consecutive_success: 2, consecutive_failure: 1 }"###,
+            r###"HealthCheckConf { schema: "https", host: "upstreamname", path: "/ping?from=nginx", connection_timeout: 3s, read_timeout: 1s, check_frequency: 10s, reuse_connection: true, consecutive_success: 2, consecutive_failure: 1, service: "", addr: None, port: None }"###,
             format!("{http_check:?}")
         );
         let http_check = new_http_health_check(&http_check);
@@ -730,6 +1916,59 @@ mod tests {
             Duration::from_secs(1),
             http_check.peer_template.options.read_timeout.unwrap()
         );
+
+        let grpc_check: HealthCheckConf =
+            "grpc://upstreamname?service=pingap.v1&read_timeout=1s&failure=3"
+                .try_into()
+                .unwrap();
+        assert_eq!(
+            r###"HealthCheckConf { schema: "grpc", host: "upstreamname", path: "", connection_timeout: 3s, read_timeout: 1s, check_frequency: 10s, reuse_connection: false, consecutive_success: 1, consecutive_failure: 3, service: "pingap.v1", addr: None, port: None }"###,
+            format!("{grpc_check:?}")
+        );
+        let grpc_check = new_grpc_health_check(&grpc_check);
+        assert_eq!(3, grpc_check.consecutive_failure);
+        assert_eq!(1, grpc_check.consecutive_success);
+        assert_eq!(
+            Duration::from_secs(1),
+            grpc_check.peer_template.options.read_timeout.unwrap()
+        );
+    }
+    #[test]
+    fn test_health_check_addr_override() {
+        let conf: HealthCheckConf =
+            "http://upstreamname/ping?port=9090".try_into().unwrap();
+        assert_eq!(None, conf.addr);
+        assert_eq!(Some(9090), conf.port);
+        let conf: HealthCheckConf =
+            "tcp://upstreamname?addr=127.0.0.1:8081".try_into().unwrap();
+        assert_eq!(Some("127.0.0.1".parse().unwrap()), conf.addr);
+        assert_eq!(Some(8081), conf.port);
+        assert_eq!(
+            "health check port(abc) is invalid",
+            HealthCheckConf::try_from("tcp://upstreamname?port=abc")
+                .unwrap_err()
+                .to_string()
+        );
+
+        let check = AddrOverrideHealthCheck {
+            check: Box::new(new_tcp_health_check(&conf)),
+            addr: None,
+            port: Some(9090),
+        };
+        let backend = Backend::new("192.168.1.1:8001").unwrap();
+        assert_eq!(
+            "192.168.1.1:9090",
+            check.get_target(&backend).addr.to_string()
+        );
+        assert_eq!(1, check.health_threshold(true));
+        assert_eq!(
+            true,
+            new_health_check(
+                "upstreamname",
+                "tcp://upstreamname?addr=127.0.0.1"
+            )
+            .is_ok()
+        );
     }
     #[test]
     fn test_new_health_check() {
@@ -739,20 +1978,26 @@ mod tests {
     #[test]
     fn test_new_backends() {
         let _ = new_backends(
+            "backends",
             &[
                 "192.168.1.1:8001 10".to_string(),
                 "192.168.1.2:8001".to_string(),
             ],
             false,
-            true,
+            AddrFamily::All,
+            None,
+            DnsFailurePolicy::Error,
             "",
         )
         .unwrap();
 
         let _ = new_backends(
+            "backends",
             &["192.168.1.1".to_string(), "192.168.1.2:8001".to_string()],
             true,
-            true,
+            AddrFamily::All,
+            None,
+            DnsFailurePolicy::Error,
             "",
         )
         .unwrap();
@@ -793,6 +2038,7 @@ mod tests {
         assert_eq!("cookie", up.hash);
         assert_eq!("user-id", up.hash_key);
         assert_eq!(ALPN::H2.to_string(), up.alpn.to_string());
+        assert_eq!(Some(100), up.max_h2_streams);
         assert_eq!("Some(5s)", format!("{:?}", up.connection_timeout));
         assert_eq!("Some(10s)", format!("{:?}", up.total_connection_timeout));
         assert_eq!("Some(3s)", format!("{:?}", up.read_timeout));
@@ -880,6 +2126,363 @@ mod tests {
         assert_eq!(true, up.as_round_robind().is_some());
     }
     #[test]
+    fn test_upstream_connection_limit() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec!["192.168.1.1:8001".to_string()],
+                max_requests_per_connection: Some(2),
+                keepalive_pool_size: Some(1),
+                max_h2_streams: Some(10),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, up.peer_tracer.is_some());
+        let tracer = up.peer_tracer.as_ref().unwrap();
+        tracer.on_connected();
+        assert_eq!(false, up.on_connection_used(10, false));
+        up.on_connection_released();
+        assert_eq!(true, up.on_connection_used(10, true));
+        up.on_connection_released();
+        // new connection with the same fd
+        assert_eq!(false, up.on_connection_used(10, false));
+
+        // the busy connections don't count for the idle pool
+        tracer.on_connected();
+        assert_eq!(false, up.on_connection_used(11, false));
+        tracer.on_connected();
+        assert_eq!(false, up.on_connection_used(12, false));
+
+        // one idle connection is in the pool
+        up.on_connection_released();
+        up.on_connection_released();
+        assert_eq!(true, up.on_connection_used(13, true));
+
+        let stats = up.stats();
+        assert_eq!(Some(3), stats.connected);
+        assert_eq!(4, stats.new_connections);
+        assert_eq!(2, stats.reused_connections);
+        assert_eq!(2, stats.closed_connections);
+    }
+    #[test]
+    fn test_backend_warmup() {
+        let window = Duration::from_secs(60);
+        let mut warmup = BackendWarmup {
+            healthy: true,
+            ..Default::default()
+        };
+        assert_eq!(1.0, warmup.factor(window));
+        assert_eq!(true, warmup.accept(window));
+
+        warmup.warmup_at = Some(Instant::now());
+        assert_eq!(0.1, warmup.factor(window));
+        let accepted = (0..100).filter(|_| warmup.accept(window)).count();
+        assert_eq!(10, accepted);
+
+        warmup.warmup_at = Instant::now().checked_sub(Duration::from_secs(30));
+        let factor = warmup.factor(window);
+        assert_eq!(true, (0.5..0.6).contains(&factor));
+
+        warmup.warmup_at = Instant::now().checked_sub(window);
+        assert_eq!(1.0, warmup.factor(window));
+        assert_eq!(true, warmup.warmup_at.is_none());
+    }
+    #[test]
+    fn test_upstream_slow_start() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec!["192.168.1.1:8001".to_string()],
+                slow_start: Some(Duration::from_secs(60)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let lb = up.as_round_robind().unwrap();
+        // the backends of first refresh are regarded as warmed
+        up.refresh_slow_start(&lb);
+        assert_eq!(0, up.warming_backends.load(Ordering::Relaxed));
+
+        // the backend recovers from unhealthy
+        if let Some(warmup) = up
+            .backend_warmups
+            .lock()
+            .unwrap()
+            .get_mut("192.168.1.1:8001")
+        {
+            warmup.healthy = false;
+        }
+        up.refresh_slow_start(&lb);
+        assert_eq!(1, up.warming_backends.load(Ordering::Relaxed));
+        // fallback to the warming backend if all backends are rejected
+        assert_eq!(true, up.select_backend(&lb, b"", None).is_some());
+    }
+    #[test]
+    fn test_upstream_select_excluded_backend() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec![
+                    "192.168.1.1:8001".to_string(),
+                    "192.168.1.2:8001".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let lb = up.as_round_robind().unwrap();
+        let excluded = vec!["192.168.1.1:8001".to_string()];
+        for _ in 0..5 {
+            let backend = up.select_backend(&lb, b"", Some(&excluded)).unwrap();
+            assert_eq!("192.168.1.2:8001", backend.addr.to_string());
+        }
+    }
+    #[test]
+    fn test_upstream_drain_backend() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec![
+                    "192.168.1.1:8001 0".to_string(),
+                    "192.168.1.2:8001".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let lb = up.as_round_robind().unwrap();
+        for _ in 0..5 {
+            let backend = up.select_backend(&lb, b"", None).unwrap();
+            assert_eq!("192.168.1.2:8001", backend.addr.to_string());
+        }
+
+        // the connection of draining backend should be closed
+        assert_eq!(true, up.on_backend_connected("192.168.1.1:8001"));
+        assert_eq!(false, up.on_backend_connected("192.168.1.2:8001"));
+        let drain = up.stats().draining.get("192.168.1.1:8001").cloned();
+        assert_eq!(true, drain.as_ref().is_some_and(|item| item.from_config));
+        assert_eq!(Some(1), drain.map(|item| item.processing));
+        up.on_backend_finished("192.168.1.1:8001");
+        let drain = up.stats().draining.get("192.168.1.1:8001").cloned();
+        assert_eq!(Some(true), drain.map(|item| item.drained));
+
+        up.set_draining("192.168.1.1:8001", None);
+        up.set_draining(
+            "192.168.1.2:8001",
+            Some(BackendDrain {
+                started_at: 1,
+                ..Default::default()
+            }),
+        );
+        for _ in 0..5 {
+            let backend = up.select_backend(&lb, b"", None).unwrap();
+            assert_eq!("192.168.1.1:8001", backend.addr.to_string());
+        }
+    }
+    #[test]
+    fn test_upstream_blue_green() {
+        let blue = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec!["192.168.1.1:8001".to_string()],
+                green_upstream: Some("charts-green".to_string()),
+                blue_green_min_healthy: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let green = Upstream::new(
+            "charts-green",
+            &UpstreamConf {
+                addrs: vec![
+                    "192.168.1.2:8001".to_string(),
+                    "192.168.1.3:8001".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(2, green.healthy_count());
+        assert_eq!(true, blue.check_switch_target(&green).is_ok());
+        assert_eq!(
+            "Healthy backends of upstream(charts) are not enough, healthy: 1, required: 2",
+            blue.check_switch_target(&blue).unwrap_err().to_string()
+        );
+        assert_eq!(Some(UpstreamColor::Blue), blue.stats().color);
+        assert_eq!(None, green.stats().color);
+        assert_eq!(
+            UpstreamColor::Green,
+            serde_json::from_str::<UpstreamColor>(r#""green""#).unwrap()
+        );
+    }
+    #[test]
+    fn test_upstream_concurrency() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec!["192.168.1.1:8001".to_string()],
+                max_concurrency: Some(10),
+                reserved_concurrency: Some(9),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(1, up.get_concurrency_limit(0));
+        assert_eq!(6, up.get_concurrency_limit(5));
+        assert_eq!(10, up.get_concurrency_limit(9));
+        assert_eq!(10, up.get_concurrency_limit(20));
+
+        let guard = up.acquire_concurrency(0);
+        assert_eq!(true, guard.is_some());
+        assert_eq!(true, up.acquire_concurrency(0).is_none());
+        let guards: Vec<_> =
+            (0..9).filter_map(|_| up.acquire_concurrency(9)).collect();
+        assert_eq!(9, guards.len());
+        assert_eq!(10, up.stats().processing);
+        assert_eq!(true, up.acquire_concurrency(9).is_none());
+
+        drop(guard);
+        drop(guards);
+        assert_eq!(0, up.stats().processing);
+
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec!["192.168.1.1:8001".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let guards: Vec<_> =
+            (0..100).filter_map(|_| up.acquire_concurrency(0)).collect();
+        assert_eq!(100, guards.len());
+    }
+    #[test]
+    fn test_upstream_prefer_ipv6() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec![
+                    "192.168.1.1:8001".to_string(),
+                    "[::1]:8001".to_string(),
+                ],
+                prefer_ipv6: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let lb = up.as_round_robind().unwrap();
+        for _ in 0..5 {
+            let backend = up.select_backend(&lb, b"", None).unwrap();
+            assert_eq!("[::1]:8001", backend.addr.to_string());
+        }
+        assert_eq!(true, up.should_fallback_ipv4("[::1]:8001", None));
+        assert_eq!(false, up.should_fallback_ipv4("192.168.1.1:8001", None));
+
+        let excluded = vec!["[::1]:8001".to_string()];
+        assert_eq!(
+            false,
+            up.should_fallback_ipv4("[::1]:8001", Some(&excluded))
+        );
+        let backend = up.select_backend(&lb, b"", Some(&excluded)).unwrap();
+        assert_eq!("192.168.1.1:8001", backend.addr.to_string());
+    }
+    #[test]
+    fn test_upstream_backend_stats() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec!["192.168.1.1:8001".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        up.record_backend("", Some(10), false);
+        up.record_backend("192.168.1.1:8001", Some(10), false);
+        up.record_backend("192.168.1.1:8001", Some(60), true);
+        up.record_backend("192.168.1.1:8001", None, true);
+        let stats = up.stats();
+        assert_eq!(1, stats.backends.len());
+        let item = stats.backends.get("192.168.1.1:8001").unwrap();
+        assert_eq!(3, item.requests);
+        assert_eq!(2, item.errors);
+        assert_eq!(20, item.latency_ewma);
+        assert_eq!(true, item.last_used_at > 0);
+    }
+    #[test]
+    fn test_upstream_p2c_ewma() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec![
+                    "192.168.1.1:8001".to_string(),
+                    "192.168.1.2:8001".to_string(),
+                ],
+                algo: Some("p2c_ewma".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, up.p2c_ewma);
+        let lb = up.as_round_robind().unwrap();
+        up.record_backend("192.168.1.1:8001", Some(100), false);
+        up.record_backend("192.168.1.2:8001", Some(10), false);
+        for _ in 0..5 {
+            let backend = up.select_p2c_backend(&lb, None).unwrap();
+            assert_eq!("192.168.1.2:8001", backend.addr.to_string());
+        }
+        let excluded = vec!["192.168.1.2:8001".to_string()];
+        let backend = up.select_p2c_backend(&lb, Some(&excluded)).unwrap();
+        assert_eq!("192.168.1.1:8001", backend.addr.to_string());
+    }
+    #[tokio::test]
+    async fn test_upstream_lazy_resolve() {
+        let up = Upstream::new(
+            "charts",
+            &UpstreamConf {
+                addrs: vec!["127.0.0.1:8001".to_string()],
+                lazy_resolve: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(true, up.is_lazy_resolve());
+        let lb = up.as_round_robind().unwrap();
+        assert_eq!(true, lb.backends().get_backend().is_empty());
+
+        let peer = up.new_lazy_http_peer(&State::default()).await.unwrap();
+        assert_eq!("127.0.0.1:8001", peer.address().to_string());
+
+        let peer = up
+            .new_lazy_http_peer(&State {
+                upstream_failed_addrs: Some(vec!["127.0.0.1:8001".to_string()]),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(true, peer.is_none());
+    }
+    #[test]
+    fn test_pause_upstream() {
+        let pause =
+            pause_upstream("pause-test", false, true, Duration::from_secs(60))
+                .unwrap();
+        assert_eq!(true, pause.discovery.is_none());
+        assert_eq!(true, pause.health_check.is_some());
+        assert_eq!(Some(pause), get_upstream_pause("pause-test"));
+
+        resume_upstream("pause-test", true, true).unwrap();
+        assert_eq!(None, get_upstream_pause("pause-test"));
+
+        let mut pause = UpstreamPause {
+            discovery: Some(10),
+            health_check: Some(20),
+        };
+        pause.clear_expired(15);
+        assert_eq!(None, pause.discovery);
+        assert_eq!(Some(20), pause.health_check);
+    }
+    #[test]
     fn test_upstream_peer_tracer() {
         let tracer = UpstreamPeerTracer::new();
         tracer.on_connected();
@@ -0,0 +1,346 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::cgi::{
+    connect, get_cgi_params, parse_cgi_headers, parse_params,
+    set_custom_params, set_param, with_timeout,
+};
+use crate::config::UpstreamConf;
+use crate::http_extra::HTTP_HEADER_TRANSFER_CHUNKED;
+use crate::state::State;
+use crate::util;
+use bytes::{BufMut, Bytes, BytesMut};
+use http::StatusCode;
+use pingora::proxy::Session;
+use pingora::upstreams::peer::HttpPeer;
+use std::str::FromStr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// the modifier of python wsgi request
+const UWSGI_MODIFIER_WSGI: u8 = 0;
+const UWSGI_MAX_DATA_SIZE: usize = 65535;
+const WSGI_READ_BUFFER_SIZE: usize = 16 * 1024;
+
+/// The protocol of python application server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WsgiProtocol {
+    Uwsgi,
+    Scgi,
+}
+
+impl FromStr for WsgiProtocol {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "uwsgi" => Ok(Self::Uwsgi),
+            "scgi" => Ok(Self::Scgi),
+            _ => Err(format!("{value} is not supported")),
+        }
+    }
+}
+
+impl std::fmt::Display for WsgiProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Uwsgi => write!(f, "uwsgi"),
+            Self::Scgi => write!(f, "scgi"),
+        }
+    }
+}
+
+/// Encodes the packet header of uwsgi, the vars are encoded as
+/// little endian length-prefixed key and value.
+fn encode_uwsgi(params: &[(String, String)]) -> pingora::Result<BytesMut> {
+    let mut vars = BytesMut::with_capacity(1024);
+    for (name, value) in params {
+        vars.put_u16_le(name.len() as u16);
+        vars.put_slice(name.as_bytes());
+        vars.put_u16_le(value.len() as u16);
+        vars.put_slice(value.as_bytes());
+    }
+    if vars.len() > UWSGI_MAX_DATA_SIZE {
+        return Err(util::new_internal_error(
+            431,
+            format!("uwsgi vars are too large, size: {}", vars.len()),
+        ));
+    }
+    let mut buf = BytesMut::with_capacity(vars.len() + 4);
+    buf.put_u8(UWSGI_MODIFIER_WSGI);
+    buf.put_u16_le(vars.len() as u16);
+    buf.put_u8(0);
+    buf.put_slice(&vars);
+    Ok(buf)
+}
+
+/// Encodes the headers of scgi as netstring, the `CONTENT_LENGTH` must be
+/// the first header and the `SCGI` header must be set.
+fn encode_scgi(params: &[(String, String)], content_length: usize) -> BytesMut {
+    let mut headers = BytesMut::with_capacity(1024);
+    let content_length = content_length.to_string();
+    for (name, value) in
+        [("CONTENT_LENGTH", content_length.as_str()), ("SCGI", "1")]
+            .into_iter()
+            .chain(
+                params
+                    .iter()
+                    .filter(|(name, _)| {
+                        name != "CONTENT_LENGTH" && name != "SCGI"
+                    })
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            )
+    {
+        headers.put_slice(name.as_bytes());
+        headers.put_u8(0);
+        headers.put_slice(value.as_bytes());
+        headers.put_u8(0);
+    }
+    let mut buf = BytesMut::with_capacity(headers.len() + 8);
+    buf.put_slice(format!("{}:", headers.len()).as_bytes());
+    buf.put_slice(&headers);
+    buf.put_u8(b',');
+    buf
+}
+
+/// The python application server(uwsgi or scgi) of upstream, the
+/// connection is closed by backend after the response is sent.
+pub struct Wsgi {
+    protocol: WsgiProtocol,
+    // the mount point of application, it's removed from path info
+    script_name: String,
+    // the custom params, the value supports variables
+    params: Vec<(String, String)>,
+}
+
+impl Wsgi {
+    /// Creates the wsgi upstream if the protocol is set.
+    pub fn new(conf: &UpstreamConf) -> Option<Self> {
+        let protocol =
+            WsgiProtocol::from_str(conf.wsgi_protocol.as_ref()?).ok()?;
+        Some(Self {
+            protocol,
+            script_name: conf
+                .wsgi_script_name
+                .clone()
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
+            params: parse_params(&conf.wsgi_params),
+        })
+    }
+    /// Gets the path info of request, the script name is removed.
+    fn get_path_info(&self, path: &str) -> String {
+        if self.script_name.is_empty() {
+            return path.to_string();
+        }
+        match path.strip_prefix(&self.script_name) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                rest.to_string()
+            },
+            _ => path.to_string(),
+        }
+    }
+    /// Gets the params of request, the custom params override the
+    /// default params with the same name.
+    fn get_params(
+        &self,
+        session: &Session,
+        ctx: &State,
+        content_length: usize,
+    ) -> Vec<(String, String)> {
+        let path = session.req_header().uri.path();
+        let mut params = get_cgi_params(session, ctx);
+        for (name, value) in [
+            ("SCRIPT_NAME", self.script_name.clone()),
+            ("PATH_INFO", self.get_path_info(path)),
+            ("CONTENT_LENGTH", content_length.to_string()),
+        ] {
+            set_param(&mut params, name, value);
+        }
+        if ctx.tls_version.is_some() && self.protocol == WsgiProtocol::Uwsgi {
+            set_param(&mut params, "UWSGI_SCHEME", "https".to_string());
+        }
+        set_custom_params(&mut params, &self.params, session, ctx);
+        params
+    }
+    /// Proxies the request to application server and sends the response to
+    /// client. The request body is streamed if its content length is known,
+    /// otherwise it's read completely to get the length.
+    pub async fn proxy(
+        &self,
+        session: &mut Session,
+        ctx: &mut State,
+        peer: &HttpPeer,
+    ) -> pingora::Result<()> {
+        let addr = peer.address().to_string();
+        let protocol = self.protocol;
+        let new_upstream_error = |e: std::io::Error| {
+            util::new_internal_error(502, format!("{protocol}({addr}): {e}"))
+        };
+        let content_length = session
+            .req_header()
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        // the body of chunked request is read before sending
+        let mut body = BytesMut::new();
+        if content_length.is_none() {
+            while let Some(data) = session.read_request_body().await? {
+                ctx.payload_size += data.len();
+                if let Some(location) = &ctx.location {
+                    location.client_body_size_limit(None, ctx)?;
+                }
+                body.extend_from_slice(&data);
+            }
+        }
+        let content_length = content_length.unwrap_or(body.len());
+
+        let mut stream = connect(&addr, peer.options.connection_timeout)
+            .await
+            .map_err(new_upstream_error)?;
+        ctx.upstream_address.clone_from(&addr);
+        ctx.upstream_connect_time =
+            util::get_latency(&ctx.upstream_connect_time);
+
+        let params = self.get_params(session, ctx, content_length);
+        let mut buf = match self.protocol {
+            WsgiProtocol::Uwsgi => encode_uwsgi(&params)?,
+            WsgiProtocol::Scgi => encode_scgi(&params, content_length),
+        };
+        buf.extend_from_slice(&body);
+        stream.write_all(&buf).await.map_err(new_upstream_error)?;
+        if body.is_empty() {
+            while let Some(data) = session.read_request_body().await? {
+                ctx.payload_size += data.len();
+                if let Some(location) = &ctx.location {
+                    location.client_body_size_limit(None, ctx)?;
+                }
+                stream.write_all(&data).await.map_err(new_upstream_error)?;
+            }
+        }
+
+        let read_timeout = peer.options.read_timeout;
+        let mut header_buf = BytesMut::new();
+        let mut header_sent = false;
+        loop {
+            let mut buf = BytesMut::with_capacity(WSGI_READ_BUFFER_SIZE);
+            let size = with_timeout(
+                read_timeout,
+                "read timeout",
+                stream.read_buf(&mut buf),
+            )
+            .await
+            .map_err(new_upstream_error)?;
+            // the response is end when the connection is closed
+            if size == 0 {
+                break;
+            }
+            if header_sent {
+                session
+                    .write_response_body(Some(buf.freeze()), false)
+                    .await?;
+                continue;
+            }
+            header_buf.extend_from_slice(&buf);
+            let Some(result) = parse_cgi_headers(&header_buf) else {
+                continue;
+            };
+            let (mut resp, size) = result?;
+            if !resp.headers.contains_key(http::header::CONTENT_LENGTH) {
+                let chunked = HTTP_HEADER_TRANSFER_CHUNKED.clone();
+                resp.insert_header(chunked.0, chunked.1)?;
+            }
+            ctx.status = Some(resp.status);
+            ctx.upstream_response_time =
+                util::get_latency(&ctx.upstream_response_time);
+            session.write_response_header(Box::new(resp), false).await?;
+            header_sent = true;
+            let body = header_buf.split_off(size);
+            if !body.is_empty() {
+                session
+                    .write_response_body(Some(body.freeze()), false)
+                    .await?;
+            }
+        }
+        if !header_sent {
+            return Err(util::new_internal_error(
+                StatusCode::BAD_GATEWAY.as_u16(),
+                format!("{protocol}({addr}): response header is invalid"),
+            ));
+        }
+        session
+            .write_response_body(Some(Bytes::new()), true)
+            .await?;
+        session.finish_body().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_scgi, encode_uwsgi, Wsgi, WsgiProtocol};
+    use crate::config::UpstreamConf;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_wsgi_encode() {
+        let params = vec![
+            ("REQUEST_METHOD".to_string(), "GET".to_string()),
+            ("CONTENT_LENGTH".to_string(), "5".to_string()),
+        ];
+        let buf = encode_uwsgi(&params).unwrap();
+        assert_eq!(b"\x00\x28\x00\x00", &buf[..4]);
+        assert_eq!(
+            b"\x0e\x00REQUEST_METHOD\x03\x00GET\x0e\x00CONTENT_LENGTH\x01\x005",
+            &buf[4..]
+        );
+
+        let buf = encode_scgi(&params, 5);
+        assert_eq!(
+            b"43:CONTENT_LENGTH\x005\x00SCGI\x001\x00REQUEST_METHOD\x00GET\x00,",
+            buf.as_ref()
+        );
+
+        let value = "a".repeat(65535);
+        assert_eq!(
+            true,
+            encode_uwsgi(&[("HTTP_X_DATA".to_string(), value)]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_wsgi_path_info() {
+        assert_eq!(true, Wsgi::new(&UpstreamConf::default()).is_none());
+        assert_eq!(WsgiProtocol::Scgi, WsgiProtocol::from_str("SCGI").unwrap());
+        assert_eq!(true, WsgiProtocol::from_str("ajp").is_err());
+
+        let wsgi = Wsgi::new(&UpstreamConf {
+            wsgi_protocol: Some("uwsgi".to_string()),
+            wsgi_script_name: Some("/app/".to_string()),
+            wsgi_params: Some(vec!["UWSGI_APPID blog".to_string()]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(WsgiProtocol::Uwsgi, wsgi.protocol);
+        assert_eq!("/app", wsgi.script_name);
+        assert_eq!(
+            vec![("UWSGI_APPID".to_string(), "blog".to_string())],
+            wsgi.params
+        );
+        assert_eq!("/users/1", wsgi.get_path_info("/app/users/1"));
+        assert_eq!("", wsgi.get_path_info("/app"));
+        assert_eq!("/application", wsgi.get_path_info("/application"));
+    }
+}
@@ -12,15 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::state;
 use async_trait::async_trait;
 use pingora::server::ShutdownWatch;
 use pingora::services::background::BackgroundService;
 use pyroscope::{
-    pyroscope::PyroscopeAgentRunning, PyroscopeAgent, PyroscopeError,
+    pyroscope::{PyroscopeAgentReady, PyroscopeAgentRunning},
+    PyroscopeAgent, PyroscopeError,
 };
 use pyroscope_pprofrs::{pprof_backend, PprofConfig};
 use snafu::{ResultExt, Snafu};
-use tracing::info;
+use std::time::Duration;
+use tracing::{error, info};
 use url::Url;
 
 #[derive(Debug, Snafu)]
@@ -48,10 +51,74 @@ pub fn new_agent_service(value: &str) -> AgentService {
 #[async_trait]
 impl BackgroundService for AgentService {
     async fn start(&self, mut shutdown: ShutdownWatch) {
-        let agent_running = start_pyroscope(&self.url).unwrap();
-        let _ = shutdown.changed().await;
-        let agent_ready = agent_running.stop().unwrap();
-        agent_ready.shutdown();
+        let agent_running = match start_pyroscope(&self.url) {
+            Ok(agent) => agent,
+            Err(e) => {
+                error!(error = e.to_string(), "start pyroscope fail");
+                return;
+            },
+        };
+        let (add_tag, remove_tag) = agent_running.tag_wrapper();
+        state::set_profiling_tagger(
+            Box::new(move |key, value| {
+                let _ = add_tag(key, value);
+            }),
+            Box::new(move |key, value| {
+                let _ = remove_tag(key, value);
+            }),
+        );
+        let mut agent = Agent::Running(agent_running);
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    break;
+                }
+                _ = interval.tick() => {
+                    // enable or disable by admin api
+                    agent = agent.toggle(state::is_profiling_enabled());
+                }
+            }
+        }
+        if let Agent::Running(agent_running) = agent {
+            if let Ok(agent_ready) = agent_running.stop() {
+                agent_ready.shutdown();
+            }
+        }
+    }
+}
+
+enum Agent {
+    Running(PyroscopeAgent<PyroscopeAgentRunning>),
+    Ready(PyroscopeAgent<PyroscopeAgentReady>),
+    Failed,
+}
+
+impl Agent {
+    fn toggle(self, enabled: bool) -> Self {
+        match (self, enabled) {
+            (Agent::Running(agent), false) => match agent.stop() {
+                Ok(agent) => {
+                    info!("pyroscope is disabled");
+                    Agent::Ready(agent)
+                },
+                Err(e) => {
+                    error!(error = e.to_string(), "stop pyroscope fail");
+                    Agent::Failed
+                },
+            },
+            (Agent::Ready(agent), true) => match agent.start() {
+                Ok(agent) => {
+                    info!("pyroscope is enabled");
+                    Agent::Running(agent)
+                },
+                Err(e) => {
+                    error!(error = e.to_string(), "start pyroscope fail");
+                    Agent::Failed
+                },
+            },
+            (agent, _) => agent,
+        }
     }
 }
 
@@ -83,6 +150,7 @@ fn start_pyroscope(
         connect_url = connect_url.replace(query, "");
     }
 
+    let hostname = state::get_hostname();
     let mut agent = PyroscopeAgent::builder(&connect_url, &application_name);
     if !user.is_empty() {
         agent = agent.basic_auth(user, password);
@@ -94,7 +162,7 @@ fn start_pyroscope(
                 .report_thread_id()
                 .report_thread_name(),
         ))
-        // .tags([("app", "Rust"), ("TagB", "ValueB")].to_vec())
+        .tags(vec![("hostname", hostname.as_str())])
         .build()
         .context(PyroscopeSnafu)?;
     info!("connect to pyroscope, app:{application_name}, url:{connect_url}");
@@ -0,0 +1,399 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{CommonServiceTask, ServiceTask};
+use crate::config::get_current_config;
+use crate::proxy::{get_time_series, TimeSeriesPoint};
+use crate::util;
+use crate::webhook;
+use ahash::AHashMap;
+use async_trait::async_trait;
+use chrono::{Local, TimeZone, Timelike};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+// the weight of new value for the baseline of all hours
+const ALPHA: f64 = 0.1;
+// the seasonal baseline of each hour only learns sixty values per day,
+// so the new value has a greater weight
+const SEASONAL_ALPHA: f64 = 0.2;
+// the baseline is used after learning enough values
+const WARMUP_SAMPLES: u64 = 30;
+// the surge is ignored if the rps is too small
+const MIN_SURGE_RPS: f64 = 1.0;
+// the drop to zero is detected only if the baseline rps is big enough
+const MIN_DROP_RPS: f64 = 0.5;
+// the error spike is detected only if the requests of minute are enough
+const MIN_ERROR_REQUESTS: u64 = 60;
+const MIN_ERROR_RATE: f64 = 0.05;
+// the same anomaly of location is alerted at most once every ten minutes
+const ALERT_INTERVAL: u64 = 10 * 60;
+
+pub const DEFAULT_ANOMALY_SENSITIVITY: f64 = 3.0;
+
+/// The exponentially weighted moving average and variance.
+#[derive(Debug, Default, Clone)]
+struct Ewma {
+    mean: f64,
+    variance: f64,
+    samples: u64,
+}
+
+impl Ewma {
+    fn update(&mut self, value: f64, alpha: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            let incr = alpha * diff;
+            self.mean += incr;
+            self.variance = (1.0 - alpha) * (self.variance + diff * incr);
+        }
+        self.samples += 1;
+    }
+    #[inline]
+    fn is_ready(&self) -> bool {
+        self.samples >= WARMUP_SAMPLES
+    }
+    /// Gets the standard deviation, it's not less than ten percent of mean
+    /// to avoid alerting on the small deviation of steady traffic.
+    #[inline]
+    fn std(&self, min: f64) -> f64 {
+        self.variance.sqrt().max(self.mean * 0.1).max(min)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+enum Anomaly {
+    TrafficSurge,
+    TrafficDrop,
+    ErrorSpike,
+}
+
+/// The learned baseline of location.
+#[derive(Debug)]
+struct Baseline {
+    rps: Ewma,
+    // the rps baseline of each hour in the day
+    hourly_rps: Vec<Ewma>,
+    error_rate: Ewma,
+    // the last alerted time of each anomaly
+    alerted_at: AHashMap<String, u64>,
+}
+
+impl Default for Baseline {
+    fn default() -> Self {
+        Self {
+            rps: Ewma::default(),
+            hourly_rps: vec![Ewma::default(); 24],
+            error_rate: Ewma::default(),
+            alerted_at: AHashMap::new(),
+        }
+    }
+}
+
+impl Baseline {
+    /// Gets the rps baseline, the seasonal one of the hour is preferred.
+    fn get_rps(&self, hour: usize) -> Option<&Ewma> {
+        if let Some(value) = self.hourly_rps.get(hour).filter(|v| v.is_ready())
+        {
+            return Some(value);
+        }
+        Some(&self.rps).filter(|v| v.is_ready())
+    }
+    /// Detects the anomalies of the minute point, the value deviated more than
+    /// `sensitivity` times of standard deviation from baseline is anomalous.
+    fn detect(
+        &self,
+        point: &TimeSeriesPoint,
+        hour: usize,
+        sensitivity: f64,
+    ) -> Vec<(Anomaly, f64)> {
+        let mut anomalies = vec![];
+        if let Some(rps) = self.get_rps(hour) {
+            let std = rps.std(0.01);
+            if point.rps >= MIN_SURGE_RPS
+                && point.rps > rps.mean * 2.0
+                && point.rps > rps.mean + sensitivity * std
+            {
+                anomalies.push((Anomaly::TrafficSurge, rps.mean));
+            }
+            if point.requests == 0
+                && rps.mean >= MIN_DROP_RPS
+                && rps.mean > sensitivity * std
+            {
+                anomalies.push((Anomaly::TrafficDrop, rps.mean));
+            }
+        }
+        let error_rate = &self.error_rate;
+        if error_rate.is_ready()
+            && point.requests >= MIN_ERROR_REQUESTS
+            && point.error_rate >= MIN_ERROR_RATE
+            && point.error_rate
+                > error_rate.mean + sensitivity * error_rate.std(0.01)
+        {
+            anomalies.push((Anomaly::ErrorSpike, error_rate.mean));
+        }
+        anomalies
+    }
+    fn update(&mut self, point: &TimeSeriesPoint, hour: usize) {
+        self.rps.update(point.rps, ALPHA);
+        if let Some(value) = self.hourly_rps.get_mut(hour) {
+            value.update(point.rps, SEASONAL_ALPHA);
+        }
+        // the error rate of minute without request is meaningless
+        if point.requests > 0 {
+            self.error_rate.update(point.error_rate, ALPHA);
+        }
+    }
+    /// Returns `true` if the anomaly should be alerted.
+    fn should_alert(&mut self, anomaly: Anomaly, now: u64) -> bool {
+        let key = anomaly.to_string();
+        if let Some(alerted_at) = self.alerted_at.get(&key) {
+            if now.saturating_sub(*alerted_at) < ALERT_INTERVAL {
+                return false;
+            }
+        }
+        self.alerted_at.insert(key, now);
+        true
+    }
+}
+
+struct AnomalyDetectionTask {
+    sensitivity: f64,
+    // the minute of last detection, it runs once per minute
+    last_minute: AtomicU64,
+    baselines: Mutex<AHashMap<String, Baseline>>,
+}
+
+impl AnomalyDetectionTask {
+    fn detect(&self, now: u64) {
+        let current = now / 60;
+        if self.last_minute.swap(current, Ordering::Relaxed) == current {
+            return;
+        }
+        let locations = get_current_config().locations.clone();
+        // the stats of last minute is completed
+        let series = get_time_series("", 2);
+        let Ok(mut baselines) = self.baselines.lock() else {
+            return;
+        };
+        // the baseline of removed location is cleared
+        baselines.retain(|name, _| locations.contains_key(name));
+        for name in locations.keys() {
+            let point = series
+                .get(name)
+                .and_then(|points| points.first().cloned())
+                .unwrap_or_else(|| TimeSeriesPoint {
+                    timestamp: (current - 1) * 60,
+                    ..Default::default()
+                });
+            let hour = Local
+                .timestamp_opt(point.timestamp as i64, 0)
+                .single()
+                .map(|value| value.hour() as usize)
+                .unwrap_or_default();
+            let baseline = baselines.entry(name.to_string()).or_default();
+            for (anomaly, expected) in
+                baseline.detect(&point, hour, self.sensitivity)
+            {
+                if !baseline.should_alert(anomaly, now) {
+                    continue;
+                }
+                let (level, msg) = match anomaly {
+                    Anomaly::ErrorSpike => (
+                        webhook::NotificationLevel::Error,
+                        format!(
+                            "error rate of location {name} is {:.2}%, baseline is {:.2}%",
+                            point.error_rate * 100.0,
+                            expected * 100.0
+                        ),
+                    ),
+                    Anomaly::TrafficDrop => (
+                        webhook::NotificationLevel::Error,
+                        format!(
+                            "traffic of location {name} drops to zero, baseline is {expected:.2} rps"
+                        ),
+                    ),
+                    Anomaly::TrafficSurge => (
+                        webhook::NotificationLevel::Warn,
+                        format!(
+                            "traffic of location {name} is {:.2} rps, baseline is {expected:.2} rps",
+                            point.rps
+                        ),
+                    ),
+                };
+                warn!(
+                    location = name,
+                    anomaly = anomaly.to_string(),
+                    msg,
+                    "anomaly is detected"
+                );
+                webhook::send(webhook::SendNotificationParams {
+                    category: webhook::NotificationCategory::Anomaly,
+                    level,
+                    msg: format!("{anomaly}: {msg}"),
+                });
+            }
+            baseline.update(&point, hour);
+        }
+    }
+}
+
+#[async_trait]
+impl ServiceTask for AnomalyDetectionTask {
+    async fn run(&self) -> Option<bool> {
+        self.detect(util::now().as_secs());
+        None
+    }
+    fn description(&self) -> String {
+        format!(
+            "Anomaly detection of locations, sensitivity: {}",
+            self.sensitivity
+        )
+    }
+}
+
+/// Creates the service of anomaly detection, it learns the baseline
+/// of locations every minute and alerts the significant deviations.
+pub fn new_anomaly_detection_service(sensitivity: f64) -> CommonServiceTask {
+    CommonServiceTask::new(
+        "AnomalyDetection",
+        Duration::from_secs(10),
+        AnomalyDetectionTask {
+            sensitivity,
+            // skip the current minute as it has not been completed
+            last_minute: AtomicU64::new(util::now().as_secs() / 60),
+            baselines: Mutex::new(AHashMap::new()),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Anomaly, Baseline, Ewma, ALERT_INTERVAL, ALPHA};
+    use crate::proxy::TimeSeriesPoint;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_ewma() {
+        let mut value = Ewma::default();
+        value.update(10.0, ALPHA);
+        assert_eq!(10.0, value.mean);
+        assert_eq!(false, value.is_ready());
+        for _ in 0..100 {
+            value.update(10.0, ALPHA);
+        }
+        assert_eq!(true, value.is_ready());
+        assert_eq!(10.0, value.mean);
+        // the standard deviation is not less than ten percent of mean
+        assert_eq!(1.0, value.std(0.01));
+
+        value.update(20.0, ALPHA);
+        assert_eq!(11.0, value.mean);
+        assert_eq!("3.000", format!("{:.3}", value.std(0.01)));
+    }
+
+    #[test]
+    fn test_detect_anomaly() {
+        let mut baseline = Baseline::default();
+        let point = TimeSeriesPoint {
+            requests: 600,
+            rps: 10.0,
+            error_rate: 0.01,
+            ..Default::default()
+        };
+        // not enough samples
+        assert_eq!(
+            true,
+            baseline
+                .detect(
+                    &TimeSeriesPoint {
+                        rps: 100.0,
+                        ..point.clone()
+                    },
+                    1,
+                    3.0
+                )
+                .is_empty()
+        );
+        for _ in 0..60 {
+            baseline.update(&point, 1);
+        }
+        assert_eq!(true, baseline.detect(&point, 1, 3.0).is_empty());
+        assert_eq!(
+            vec![(Anomaly::TrafficSurge, 10.0)],
+            baseline.detect(
+                &TimeSeriesPoint {
+                    rps: 100.0,
+                    ..point.clone()
+                },
+                1,
+                3.0
+            )
+        );
+        assert_eq!(
+            vec![(Anomaly::TrafficDrop, 10.0)],
+            baseline.detect(&TimeSeriesPoint::default(), 1, 3.0)
+        );
+        assert_eq!(
+            Anomaly::ErrorSpike,
+            baseline.detect(
+                &TimeSeriesPoint {
+                    error_rate: 0.5,
+                    ..point.clone()
+                },
+                1,
+                3.0
+            )[0]
+            .0
+        );
+        // the rps of other hour uses the baseline of all hours
+        assert_eq!(
+            vec![(Anomaly::TrafficSurge, 10.0)],
+            baseline.detect(
+                &TimeSeriesPoint {
+                    rps: 100.0,
+                    ..point.clone()
+                },
+                2,
+                3.0
+            )
+        );
+        assert_eq!(
+            true,
+            baseline
+                .detect(
+                    &TimeSeriesPoint {
+                        rps: 25.0,
+                        ..point.clone()
+                    },
+                    1,
+                    20.0
+                )
+                .is_empty()
+        );
+
+        assert_eq!(true, baseline.should_alert(Anomaly::TrafficSurge, 1000));
+        assert_eq!(false, baseline.should_alert(Anomaly::TrafficSurge, 1010));
+        assert_eq!(true, baseline.should_alert(Anomaly::TrafficDrop, 1010));
+        assert_eq!(
+            true,
+            baseline.should_alert(Anomaly::TrafficSurge, 1000 + ALERT_INTERVAL)
+        );
+    }
+}
@@ -14,10 +14,10 @@
 
 use crate::config::{
     get_config_path, get_current_config, load_config, set_current_config,
-    PingapConf, CATEGORY_LOCATION, CATEGORY_UPSTREAM,
 };
+use crate::discovery::merge_registry_config;
 use crate::service::{CommonServiceTask, ServiceTask};
-use crate::state::restart;
+use crate::state::{notify_ready, notify_reloading, restart};
 use crate::{proxy, webhook};
 use async_trait::async_trait;
 use std::sync::atomic::{AtomicU32, Ordering};
@@ -29,64 +29,25 @@ async fn hot_reload(
 ) -> Result<(bool, Vec<String>), Box<dyn std::error::Error>> {
     let conf = load_config(&get_config_path(), false).await?;
     conf.validate()?;
-    let mut current_conf: PingapConf = get_current_config().as_ref().clone();
+    let current_conf = get_current_config();
     let (_, original_diff_result) = current_conf.diff(&conf);
 
-    let mut should_reload_server_location = false;
-    for (name, server) in conf.servers.iter() {
-        if let Some(old) = current_conf.servers.get_mut(name) {
-            if server.locations != old.locations {
-                should_reload_server_location = true;
-                old.locations.clone_from(&server.locations);
-            }
-        }
-    }
-    let (updated_category_list, _) = current_conf.diff(&conf);
-    if !should_reload_server_location && updated_category_list.is_empty() {
+    let plan = proxy::ConfigApplyPlan::new(&current_conf, &conf);
+    if plan.is_empty() {
         return Ok((false, original_diff_result));
     }
 
-    let mut should_reload_upstream = false;
-    let mut should_reload_location = false;
-    let mut should_restart = false;
-    for category in updated_category_list {
-        match category.as_str() {
-            CATEGORY_LOCATION => should_reload_location = true,
-            CATEGORY_UPSTREAM => should_reload_upstream = true,
-            _ => should_restart = true,
-        };
-    }
-
-    if should_reload_upstream {
-        match proxy::try_init_upstreams(&conf.upstreams) {
-            Err(e) => {
-                error!(error = e.to_string(), "reload upstream fail");
-            },
-            Ok(()) => {
-                info!("reload upstream success");
-            },
-        };
-    }
-    if should_reload_location {
-        match proxy::try_init_locations(&conf.locations) {
-            Err(e) => {
-                error!(error = e.to_string(), "reload location fail");
-            },
-            Ok(()) => {
-                info!("reload location success");
-            },
-        };
-    }
-    if should_reload_server_location {
-        match proxy::try_init_server_locations(&conf.servers, &conf.locations) {
-            Err(e) => {
-                error!(error = e.to_string(), "reload server fail");
-            },
-            Ok(()) => {
-                info!("reload server location success");
-            },
-        };
+    // the locations and upstreams generated from registry are kept
+    let merged_conf = merge_registry_config(&conf);
+    notify_reloading();
+    let report = proxy::apply_config(&plan, &merged_conf);
+    notify_ready();
+    notify_config_apply(&report);
+    // the config isn't applied, it will be tried again in next check
+    if report.failed.is_some() {
+        return Ok((false, vec![]));
     }
+    let should_restart = !report.require_upgrade.is_empty();
 
     if hot_reload_only {
         // update current config only hot reload config updated
@@ -105,6 +66,50 @@ async fn hot_reload(
     Ok((false, vec![]))
 }
 
+/// Logs the report of applying config, the webhook notification
+/// is sent only if the result is changed.
+fn notify_config_apply(report: &proxy::ConfigApplyReport) {
+    let reloaded = report.reloaded.join(",");
+    let require_upgrade = report.require_upgrade.join(",");
+    let level = if let Some(failed) = &report.failed {
+        let error = report.error.clone().unwrap_or_default();
+        error!(
+            failed,
+            error,
+            rolled_back = report.rolled_back.join(","),
+            require_upgrade,
+            "apply config fail"
+        );
+        webhook::NotificationLevel::Error
+    } else {
+        info!(reloaded, require_upgrade, "apply config success");
+        webhook::NotificationLevel::Info
+    };
+    if !proxy::set_config_apply_report(report) {
+        return;
+    }
+    let msg = if let Some(failed) = &report.failed {
+        format!(
+            "apply config fail, failed: {failed}, error: {}, rolled back: {:?}",
+            report.error.clone().unwrap_or_default(),
+            report.rolled_back
+        )
+    } else {
+        format!(
+            "apply config success, reloaded: {:?}, require upgrade: {:?}",
+            report.reloaded, report.require_upgrade
+        )
+    };
+    webhook::send_with_details(
+        webhook::SendNotificationParams {
+            level,
+            category: webhook::NotificationCategory::DiffConfig,
+            msg,
+        },
+        serde_json::to_value(report).ok(),
+    );
+}
+
 struct AutoRestart {
     restart_unit: u32,
     only_hot_reload: bool,
@@ -0,0 +1,146 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cache::{
+    get_prefetch_cache_entries, get_prefetch_token, CacheRequest,
+    HTTP_HEADER_NAME_X_PINGAP_PREFETCH,
+};
+use crate::service::{CommonServiceTask, ServiceTask};
+use ahash::AHashSet;
+use async_trait::async_trait;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{debug, error};
+
+// the timeout of prefetch request
+const PREFETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct CachePrefetchParams {
+    // the min hit count of hot entry
+    pub min_hits: u64,
+    // prefetch the entry if its remaining ttl is less than this value
+    pub before: Duration,
+    // the max count of concurrent prefetch requests
+    pub concurrency: usize,
+}
+
+struct CachePrefetch {
+    min_hits: u64,
+    before: Duration,
+    semaphore: Arc<Semaphore>,
+    // the hashes of entries which are being prefetched
+    prefetching: Arc<Mutex<AHashSet<String>>>,
+}
+
+/// Sends the request to the server of pingap itself,
+/// the host is resolved to the local address of server.
+async fn prefetch(request: &CacheRequest) -> Result<u16, reqwest::Error> {
+    let addr: Option<SocketAddr> = request.addr.parse().ok();
+    let host = if request.host.is_empty() {
+        request.addr.clone()
+    } else {
+        request.host.clone()
+    };
+    let domain = host.split(':').next().unwrap_or_default();
+    let mut builder = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(PREFETCH_TIMEOUT);
+    if let Some(addr) = addr {
+        builder = builder.resolve(domain, addr);
+    }
+    let client = builder.build()?;
+    let port = addr.map(|addr| addr.port()).unwrap_or(80);
+    let url = format!("{}://{domain}:{port}{}", request.scheme, request.path);
+    let mut req = client.get(url).header("Host", host.as_str()).header(
+        HTTP_HEADER_NAME_X_PINGAP_PREFETCH.clone(),
+        get_prefetch_token(),
+    );
+    for item in request.headers.iter() {
+        if let Some((name, value)) = item.split_once(':') {
+            req = req.header(name, value);
+        }
+    }
+    let resp = req.send().await?;
+    let status = resp.status().as_u16();
+    // read the whole body, so the cache is stored
+    resp.bytes().await?;
+    Ok(status)
+}
+
+#[async_trait]
+impl ServiceTask for CachePrefetch {
+    async fn run(&self) -> Option<bool> {
+        let entries =
+            get_prefetch_cache_entries(self.min_hits, self.before.as_secs());
+        for entry in entries {
+            let Some(request) = entry.request else {
+                continue;
+            };
+            let Ok(permit) = self.semaphore.clone().try_acquire_owned() else {
+                debug!("prefetch concurrency is full");
+                break;
+            };
+            if let Ok(mut prefetching) = self.prefetching.lock() {
+                if !prefetching.insert(entry.hash.clone()) {
+                    continue;
+                }
+            }
+            let prefetching = self.prefetching.clone();
+            tokio::spawn(async move {
+                match prefetch(&request).await {
+                    Ok(status) => {
+                        debug!(key = entry.key, status, "prefetch cache")
+                    },
+                    Err(e) => error!(
+                        error = e.to_string(),
+                        key = entry.key,
+                        "prefetch cache fail"
+                    ),
+                }
+                if let Ok(mut prefetching) = prefetching.lock() {
+                    prefetching.remove(&entry.hash);
+                }
+                drop(permit);
+            });
+        }
+        None
+    }
+    fn description(&self) -> String {
+        format!(
+            "min hits: {}, before: {:?}, concurrency: {}",
+            self.min_hits,
+            self.before,
+            self.semaphore.available_permits()
+        )
+    }
+}
+
+/// Creates a service to refresh the hot cache entries which are
+/// close to expiry in background, smoothing the load of upstream.
+pub fn new_cache_prefetch_service(
+    params: CachePrefetchParams,
+) -> CommonServiceTask {
+    CommonServiceTask::new(
+        "Cache prefetch",
+        Duration::from_secs(1),
+        CachePrefetch {
+            min_hits: params.min_hits.max(1),
+            before: params.before,
+            semaphore: Arc::new(Semaphore::new(params.concurrency.max(1))),
+            prefetching: Arc::new(Mutex::new(AHashSet::new())),
+        },
+    )
+}
@@ -0,0 +1,84 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::state::{
+    find_activated_fd, get_activated_listeners, get_prebound_fd,
+};
+use async_trait::async_trait;
+use pingora::server::{ListenFds, ShutdownWatch};
+use pingora::services::Service;
+use std::os::fd::RawFd;
+use tracing::info;
+
+/// The service which uses the listeners bound before it starts, e.g. the
+/// listeners of systemd socket activation and the listeners bound before
+/// dropping privileges. The fds are added to the table of pingora, so the
+/// addresses are not bound again.
+pub struct ListenFdsService<S> {
+    service: S,
+    fds: Vec<(String, RawFd)>,
+}
+
+/// Gets the bound fds of the listen addresses.
+fn get_listen_fds(addrs: &str) -> Vec<(String, RawFd)> {
+    let listeners = get_activated_listeners();
+    addrs
+        .split(',')
+        .filter_map(|addr| {
+            get_prebound_fd(addr)
+                .or_else(|| find_activated_fd(listeners, addr))
+                .map(|fd| (addr.to_string(), fd))
+        })
+        .collect()
+}
+
+/// Wraps the service with the bound listeners of the addresses,
+/// the service is returned directly if no listener is matched.
+pub fn new_listen_fds_service<S: Service + 'static>(
+    service: S,
+    addrs: &str,
+) -> Box<dyn Service> {
+    let fds = get_listen_fds(addrs);
+    if fds.is_empty() {
+        return Box::new(service);
+    }
+    Box::new(ListenFdsService { service, fds })
+}
+
+#[async_trait]
+impl<S: Service> Service for ListenFdsService<S> {
+    async fn start_service(
+        &mut self,
+        fds: Option<ListenFds>,
+        shutdown: ShutdownWatch,
+    ) {
+        if let Some(fds) = &fds {
+            let mut table = fds.lock().await;
+            for (addr, fd) in self.fds.iter() {
+                // the fds of graceful upgrade are preferred
+                if table.get(addr).is_none() {
+                    info!(addr, fd, "use the bound listener");
+                    table.add(addr.clone(), *fd);
+                }
+            }
+        }
+        self.service.start_service(fds, shutdown).await
+    }
+    fn name(&self) -> &str {
+        self.service.name()
+    }
+    fn threads(&self) -> Option<usize> {
+        self.service.threads()
+    }
+}
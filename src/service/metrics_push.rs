@@ -0,0 +1,761 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{CommonServiceTask, ServiceTask};
+use crate::discovery::get_dns_resolve_stats;
+use crate::proxy::{
+    get_event_loop_delay, get_latency_histograms, get_locations_stats,
+    get_request_metrics, get_request_metrics_overflow, get_shed_requests,
+    get_stuck_requests, get_upstreams_stats, Exemplar, LatencyCategory,
+};
+use crate::sink::get_sink_dropped;
+use crate::state::{get_hostname, get_start_time};
+use crate::util;
+use async_trait::async_trait;
+use memory_stats::memory_stats;
+use snafu::Snafu;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::{debug, error};
+use url::Url;
+
+// the max size of udp packet for statsd
+const STATSD_MAX_PACKET_SIZE: usize = 1432;
+const REQUEST_LATENCY_METRIC: &str = "pingap_request_latency_ms";
+const UPSTREAM_LATENCY_METRIC: &str = "pingap_upstream_latency_ms";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid error {message}"))]
+    Invalid { message: String },
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum PushTarget {
+    // plain statsd, the labels are appended to the metric name
+    Statsd(String),
+    // statsd with the tags extension of datadog
+    DogStatsd(String),
+    // prometheus remote write url
+    RemoteWrite(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Metric {
+    name: String,
+    labels: Vec<(String, String)>,
+    value: f64,
+    // the exemplar of histogram bucket, only for remote write
+    exemplar: Option<Exemplar>,
+}
+
+impl Metric {
+    fn new(name: &str, value: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            labels: vec![],
+            value,
+            exemplar: None,
+        }
+    }
+    fn with_exemplar(mut self, exemplar: Option<Exemplar>) -> Self {
+        self.exemplar = exemplar;
+        self
+    }
+    fn with_label(mut self, name: &str, value: &str) -> Self {
+        self.labels.push((name.to_string(), value.to_string()));
+        self
+    }
+    fn with_labels(mut self, labels: &[(&str, String)]) -> Self {
+        for (name, value) in labels.iter() {
+            self.labels.push((name.to_string(), value.clone()));
+        }
+        self
+    }
+}
+
+/// Parses the push target, `statsd://host:port` and `dogstatsd://host:port`
+/// push metrics via udp, `http(s)://` pushes metrics via prometheus remote write.
+fn parse_push_target(value: &str) -> Result<PushTarget> {
+    let url = Url::parse(value).map_err(|e| Error::Invalid {
+        message: format!("{value} is invalid, {e}"),
+    })?;
+    let addr = || -> Result<String> {
+        let host = url.host_str().ok_or_else(|| Error::Invalid {
+            message: format!("host of {value} is required"),
+        })?;
+        Ok(util::join_host_port(
+            host.trim_start_matches('[').trim_end_matches(']'),
+            &url.port().unwrap_or(8125).to_string(),
+        ))
+    };
+    match url.scheme() {
+        "statsd" => Ok(PushTarget::Statsd(addr()?)),
+        "dogstatsd" => Ok(PushTarget::DogStatsd(addr()?)),
+        "http" | "https" => Ok(PushTarget::RemoteWrite(value.to_string())),
+        scheme => Err(Error::Invalid {
+            message: format!("metrics push scheme({scheme}) is not supported"),
+        }),
+    }
+}
+
+/// Parses the labels of instance, the format is `name=value`.
+fn parse_labels(values: &[String]) -> Result<Vec<(String, String)>> {
+    let mut labels = vec![];
+    for value in values.iter() {
+        let Some((name, value)) = value.split_once('=') else {
+            return Err(Error::Invalid {
+                message: format!("label({value}) is invalid"),
+            });
+        };
+        labels.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    Ok(labels)
+}
+
+/// Collects the internal metrics of pingap.
+fn collect_metrics() -> Vec<Metric> {
+    let mut metrics = vec![];
+    let physical_mem = memory_stats()
+        .map(|value| value.physical_mem)
+        .unwrap_or_default();
+    metrics.push(Metric::new(
+        "pingap_physical_memory_bytes",
+        physical_mem as f64,
+    ));
+    metrics.push(Metric::new(
+        "pingap_uptime_seconds",
+        util::now().as_secs().saturating_sub(get_start_time()) as f64,
+    ));
+    metrics.push(Metric::new(
+        "pingap_stuck_requests",
+        get_stuck_requests() as f64,
+    ));
+    metrics.push(Metric::new(
+        "pingap_shed_requests_total",
+        get_shed_requests() as f64,
+    ));
+    metrics.push(Metric::new(
+        "pingap_event_loop_delay_ms",
+        get_event_loop_delay() as f64,
+    ));
+    metrics.push(Metric::new(
+        "pingap_sink_dropped_total",
+        get_sink_dropped() as f64,
+    ));
+    metrics.push(Metric::new(
+        "pingap_requests_series_overflow_total",
+        get_request_metrics_overflow() as f64,
+    ));
+    for series in get_request_metrics() {
+        metrics.push(
+            Metric::new("pingap_requests_total", series.requests as f64)
+                .with_labels(&series.labels),
+        );
+        metrics.push(
+            Metric::new(
+                "pingap_request_duration_ms_sum",
+                series.duration_ms as f64,
+            )
+            .with_labels(&series.labels),
+        );
+    }
+    for histogram in get_latency_histograms() {
+        let name = match histogram.category {
+            LatencyCategory::Request => REQUEST_LATENCY_METRIC,
+            LatencyCategory::Upstream => UPSTREAM_LATENCY_METRIC,
+        };
+        for ((bound, count), exemplar) in
+            histogram.buckets.iter().zip(histogram.exemplars)
+        {
+            let le = if bound.is_infinite() {
+                "+Inf".to_string()
+            } else {
+                bound.to_string()
+            };
+            metrics.push(
+                Metric::new(&format!("{name}_bucket"), *count as f64)
+                    .with_label("location", &histogram.location)
+                    .with_label("le", &le)
+                    .with_exemplar(exemplar),
+            );
+        }
+        metrics.push(
+            Metric::new(&format!("{name}_sum"), histogram.sum)
+                .with_label("location", &histogram.location),
+        );
+        metrics.push(
+            Metric::new(&format!("{name}_count"), histogram.count as f64)
+                .with_label("location", &histogram.location),
+        );
+    }
+    for (name, (accepted, processing, client_aborted)) in get_locations_stats()
+    {
+        metrics.push(
+            Metric::new("pingap_location_accepted_total", accepted as f64)
+                .with_label("location", &name),
+        );
+        metrics.push(
+            Metric::new("pingap_location_processing", processing as f64)
+                .with_label("location", &name),
+        );
+        metrics.push(
+            Metric::new(
+                "pingap_location_client_aborted_total",
+                client_aborted as f64,
+            )
+            .with_label("location", &name),
+        );
+    }
+    for (name, stats) in get_dns_resolve_stats() {
+        for (metric, value) in [
+            ("pingap_dns_resolve_success_total", stats.success),
+            ("pingap_dns_resolve_failure_total", stats.failure),
+            ("pingap_dns_resolve_fallback_total", stats.fallback),
+            ("pingap_dns_cache_hits_total", stats.cache_hit),
+        ] {
+            metrics.push(
+                Metric::new(metric, value as f64).with_label("upstream", &name),
+            );
+        }
+    }
+    for (name, stats) in get_upstreams_stats() {
+        if let Some(connected) = stats.connected {
+            metrics.push(
+                Metric::new("pingap_upstream_connected", connected as f64)
+                    .with_label("upstream", &name),
+            );
+        }
+        metrics.push(
+            Metric::new(
+                "pingap_upstream_new_connections_total",
+                stats.new_connections as f64,
+            )
+            .with_label("upstream", &name),
+        );
+        metrics.push(
+            Metric::new(
+                "pingap_upstream_reused_connections_total",
+                stats.reused_connections as f64,
+            )
+            .with_label("upstream", &name),
+        );
+        for (addr, backend) in stats.backends.iter() {
+            metrics.push(
+                Metric::new(
+                    "pingap_backend_requests_total",
+                    backend.requests as f64,
+                )
+                .with_label("upstream", &name)
+                .with_label("backend", addr),
+            );
+            metrics.push(
+                Metric::new(
+                    "pingap_backend_errors_total",
+                    backend.errors as f64,
+                )
+                .with_label("upstream", &name)
+                .with_label("backend", addr),
+            );
+            metrics.push(
+                Metric::new(
+                    "pingap_backend_latency_ewma_ms",
+                    backend.latency_ewma as f64,
+                )
+                .with_label("upstream", &name)
+                .with_label("backend", addr),
+            );
+        }
+    }
+    metrics
+}
+
+/// Formats the metrics as statsd gauges, the lines are grouped
+/// into packets which are not larger than the max packet size.
+fn format_statsd(
+    metrics: &[Metric],
+    labels: &[(String, String)],
+    dog: bool,
+) -> Vec<String> {
+    let mut packets = vec![];
+    let mut packet = String::new();
+    for metric in metrics.iter() {
+        let all_labels = metric.labels.iter().chain(labels.iter());
+        let line = if dog {
+            let tags: Vec<String> = all_labels
+                .map(|(name, value)| format!("{name}:{value}"))
+                .collect();
+            if tags.is_empty() {
+                format!("{}:{}|g", metric.name, metric.value)
+            } else {
+                format!(
+                    "{}:{}|g|#{}",
+                    metric.name,
+                    metric.value,
+                    tags.join(",")
+                )
+            }
+        } else {
+            let mut name = metric.name.clone();
+            for (_, value) in all_labels {
+                name.push('.');
+                // the `.`, `:` and `|` are reserved by statsd
+                name.push_str(&value.replace(['.', ':', '|'], "_"));
+            }
+            format!("{name}:{}|g", metric.value)
+        };
+        if !packet.is_empty()
+            && packet.len() + line.len() + 1 > STATSD_MAX_PACKET_SIZE
+        {
+            packets.push(std::mem::take(&mut packet));
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(&line);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    packets
+}
+
+/// Formats the value of prometheus text exposition.
+fn format_prometheus_value(value: f64) -> String {
+    if value.is_infinite() {
+        if value.is_sign_positive() {
+            "+Inf".to_string()
+        } else {
+            "-Inf".to_string()
+        }
+    } else {
+        value.to_string()
+    }
+}
+
+/// Gets the histogram name of the `_bucket`, `_sum` and `_count` samples.
+fn get_histogram_family(name: &str) -> Option<&str> {
+    ["_bucket", "_sum", "_count"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .filter(|family| {
+            [REQUEST_LATENCY_METRIC, UPSTREAM_LATENCY_METRIC].contains(family)
+        })
+}
+
+/// Formats the metrics as prometheus text exposition, the samples
+/// of the same metric are grouped after its `# TYPE` line, and the
+/// `_bucket`, `_sum` and `_count` samples of histogram are grouped
+/// by the histogram name.
+fn format_prometheus(metrics: &[Metric]) -> String {
+    let mut families: Vec<(&str, Vec<&Metric>)> = vec![];
+    for metric in metrics.iter() {
+        let name = get_histogram_family(&metric.name).unwrap_or(&metric.name);
+        if let Some((_, samples)) =
+            families.iter_mut().find(|(family, _)| *family == name)
+        {
+            samples.push(metric);
+        } else {
+            families.push((name, vec![metric]));
+        }
+    }
+    let mut buf = String::new();
+    for (family, samples) in families.iter() {
+        let category = if get_histogram_family(&samples[0].name).is_some() {
+            "histogram"
+        // the sum of request duration only increases as the counter
+        } else if family.ends_with("_total") || family.ends_with("_sum") {
+            "counter"
+        } else {
+            "gauge"
+        };
+        buf.push_str(&format!("# TYPE {family} {category}\n"));
+        for metric in samples.iter() {
+            buf.push_str(&metric.name);
+            if !metric.labels.is_empty() {
+                let labels: Vec<String> = metric
+                    .labels
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = value
+                            .replace('\\', r"\\")
+                            .replace('"', r#"\""#)
+                            .replace('\n', r"\n");
+                        format!(r#"{name}="{value}""#)
+                    })
+                    .collect();
+                buf.push_str(&format!("{{{}}}", labels.join(",")));
+            }
+            buf.push_str(&format!(
+                " {}\n",
+                format_prometheus_value(metric.value)
+            ));
+        }
+    }
+    buf
+}
+
+/// Gets the internal metrics of pingap as prometheus text exposition,
+/// it's used by the scrape endpoint of stats plugin.
+pub fn get_prometheus_metrics() -> String {
+    format_prometheus(&collect_metrics())
+}
+
+fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn encode_bytes_field(buf: &mut Vec<u8>, field: u64, data: &[u8]) {
+    // wire type 2: length-delimited
+    encode_varint(buf, (field << 3) | 2);
+    encode_varint(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+/// Encodes the metrics as the protobuf `WriteRequest` of prometheus remote write.
+fn encode_write_request(
+    metrics: &[Metric],
+    labels: &[(String, String)],
+    timestamp: i64,
+) -> Vec<u8> {
+    let mut request = vec![];
+    for metric in metrics.iter() {
+        let mut all_labels = vec![("__name__", metric.name.as_str())];
+        for (name, value) in metric.labels.iter().chain(labels.iter()) {
+            all_labels.push((name.as_str(), value.as_str()));
+        }
+        // the labels should be sorted by name
+        all_labels.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut series = vec![];
+        for (name, value) in all_labels {
+            let mut label = vec![];
+            encode_bytes_field(&mut label, 1, name.as_bytes());
+            encode_bytes_field(&mut label, 2, value.as_bytes());
+            encode_bytes_field(&mut series, 1, &label);
+        }
+        let mut sample = vec![];
+        // wire type 1: 64-bit
+        sample.push((1 << 3) | 1);
+        sample.extend_from_slice(&metric.value.to_le_bytes());
+        // wire type 0: varint
+        sample.push(2 << 3);
+        encode_varint(&mut sample, timestamp as u64);
+        encode_bytes_field(&mut series, 2, &sample);
+
+        if let Some(exemplar) = &metric.exemplar {
+            let mut data = vec![];
+            let mut label = vec![];
+            encode_bytes_field(&mut label, 1, b"trace_id");
+            encode_bytes_field(&mut label, 2, exemplar.trace_id.as_bytes());
+            encode_bytes_field(&mut data, 1, &label);
+            data.push((2 << 3) | 1);
+            data.extend_from_slice(&exemplar.value.to_le_bytes());
+            data.push(3 << 3);
+            encode_varint(&mut data, exemplar.timestamp);
+            encode_bytes_field(&mut series, 3, &data);
+        }
+
+        encode_bytes_field(&mut request, 1, &series);
+    }
+    request
+}
+
+/// Encodes the data with the snappy block format, only the literal
+/// elements are used, it's valid for any snappy decoder.
+fn snappy_encode(data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(data.len() + data.len() / 60 + 8);
+    encode_varint(&mut buf, data.len() as u64);
+    for chunk in data.chunks(65536) {
+        let n = chunk.len() - 1;
+        if n < 60 {
+            buf.push((n as u8) << 2);
+        } else if n < 256 {
+            buf.push(60 << 2);
+            buf.push(n as u8);
+        } else {
+            buf.push(61 << 2);
+            buf.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        buf.extend_from_slice(chunk);
+    }
+    buf
+}
+
+struct MetricsPush {
+    target: PushTarget,
+    labels: Vec<(String, String)>,
+}
+
+impl MetricsPush {
+    async fn push_statsd(&self, addr: &str, dog: bool) -> Result<()> {
+        let packets = format_statsd(&collect_metrics(), &self.labels, dog);
+        let socket =
+            UdpSocket::bind("0.0.0.0:0")
+                .await
+                .map_err(|e| Error::Invalid {
+                    message: e.to_string(),
+                })?;
+        for packet in packets.iter() {
+            socket.send_to(packet.as_bytes(), addr).await.map_err(|e| {
+                Error::Invalid {
+                    message: e.to_string(),
+                }
+            })?;
+        }
+        Ok(())
+    }
+    async fn push_remote_write(&self, url: &str) -> Result<()> {
+        let timestamp = util::now().as_millis() as i64;
+        let data = snappy_encode(&encode_write_request(
+            &collect_metrics(),
+            &self.labels,
+            timestamp,
+        ));
+        let resp = reqwest::Client::new()
+            .post(url)
+            .header("Content-Type", "application/x-protobuf")
+            .header("Content-Encoding", "snappy")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(data)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        if resp.status().as_u16() >= 400 {
+            return Err(Error::Invalid {
+                message: format!("remote write status: {}", resp.status()),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ServiceTask for MetricsPush {
+    async fn run(&self) -> Option<bool> {
+        let result = match &self.target {
+            PushTarget::Statsd(addr) => self.push_statsd(addr, false).await,
+            PushTarget::DogStatsd(addr) => self.push_statsd(addr, true).await,
+            PushTarget::RemoteWrite(url) => self.push_remote_write(url).await,
+        };
+        match result {
+            Ok(()) => debug!("push metrics success"),
+            Err(e) => error!(error = e.to_string(), "push metrics fail"),
+        }
+        None
+    }
+    fn description(&self) -> String {
+        format!("{:?}", self.target)
+    }
+}
+
+/// Creates a service to push the internal metrics at the interval,
+/// the `instance` label is the hostname if it's not set.
+pub fn new_metrics_push_service(
+    url: &str,
+    interval: Duration,
+    labels: &[String],
+) -> Result<CommonServiceTask> {
+    let target = parse_push_target(url)?;
+    let mut labels = parse_labels(labels)?;
+    if !labels.iter().any(|(name, _)| name == "instance") {
+        labels.push(("instance".to_string(), get_hostname()));
+    }
+    Ok(CommonServiceTask::new(
+        "Metrics push",
+        interval.max(Duration::from_secs(1)),
+        MetricsPush { target, labels },
+    ))
+}
+
+/// Validates the url and labels of metrics push.
+pub fn validate_metrics_push(url: &str, labels: &[String]) -> Result<()> {
+    parse_push_target(url)?;
+    parse_labels(labels)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_write_request, format_prometheus, format_statsd, parse_labels,
+        parse_push_target, snappy_encode, Metric, PushTarget,
+    };
+    use crate::proxy::Exemplar;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_push_target() {
+        assert_eq!(
+            PushTarget::Statsd("127.0.0.1:8125".to_string()),
+            parse_push_target("statsd://127.0.0.1").unwrap()
+        );
+        assert_eq!(
+            PushTarget::DogStatsd("[::1]:9125".to_string()),
+            parse_push_target("dogstatsd://[::1]:9125").unwrap()
+        );
+        assert_eq!(
+            PushTarget::RemoteWrite(
+                "http://127.0.0.1:9090/api/v1/write".to_string()
+            ),
+            parse_push_target("http://127.0.0.1:9090/api/v1/write").unwrap()
+        );
+        assert_eq!(
+            "Invalid error metrics push scheme(tcp) is not supported",
+            parse_push_target("tcp://127.0.0.1:8125")
+                .unwrap_err()
+                .to_string()
+        );
+
+        assert_eq!(
+            vec![("env".to_string(), "prod".to_string())],
+            parse_labels(&["env = prod".to_string()]).unwrap()
+        );
+        assert_eq!(true, parse_labels(&["env".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_format_statsd() {
+        let metrics = vec![
+            Metric::new("pingap_uptime_seconds", 10.0),
+            Metric::new("pingap_location_accepted_total", 3.0)
+                .with_label("location", "lo"),
+        ];
+        let labels = vec![("instance".to_string(), "pingap.io".to_string())];
+        assert_eq!(
+            vec![
+                "pingap_uptime_seconds.pingap_io:10|g\npingap_location_accepted_total.lo.pingap_io:3|g"
+                    .to_string()
+            ],
+            format_statsd(&metrics, &labels, false)
+        );
+        assert_eq!(
+            vec![
+                "pingap_uptime_seconds:10|g|#instance:pingap.io\npingap_location_accepted_total:3|g|#location:lo,instance:pingap.io"
+                    .to_string()
+            ],
+            format_statsd(&metrics, &labels, true)
+        );
+
+        let metrics: Vec<Metric> = (0..100)
+            .map(|i| {
+                Metric::new("pingap_location_accepted_total", i as f64)
+                    .with_label("location", &format!("location{i}"))
+            })
+            .collect();
+        let packets = format_statsd(&metrics, &labels, true);
+        assert_eq!(true, packets.len() > 1);
+        assert_eq!(true, packets.iter().all(|item| item.len() <= 1432));
+        assert_eq!(
+            100,
+            packets
+                .iter()
+                .map(|item| item.lines().count())
+                .sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_format_prometheus() {
+        let labels = vec![
+            ("server", "test".to_string()),
+            ("location", "lo".to_string()),
+        ];
+        let metrics = vec![
+            Metric::new("pingap_uptime_seconds", 10.0),
+            Metric::new("pingap_requests_total", 3.0).with_labels(&labels),
+            Metric::new("pingap_request_duration_ms_sum", 30.0)
+                .with_labels(&labels),
+            Metric::new("pingap_requests_total", 1.0)
+                .with_label("location", r#"a"b\c"#),
+        ];
+        assert_eq!(
+            r#"# TYPE pingap_uptime_seconds gauge
+pingap_uptime_seconds 10
+# TYPE pingap_requests_total counter
+pingap_requests_total{server="test",location="lo"} 3
+pingap_requests_total{location="a\"b\\c"} 1
+# TYPE pingap_request_duration_ms_sum counter
+pingap_request_duration_ms_sum{server="test",location="lo"} 30
+"#,
+            format_prometheus(&metrics)
+        );
+
+        let metrics = vec![
+            Metric::new("pingap_request_latency_ms_bucket", 1.0)
+                .with_label("location", "lo")
+                .with_label("le", "5"),
+            Metric::new("pingap_request_latency_ms_bucket", 2.0)
+                .with_label("location", "lo")
+                .with_label("le", "+Inf"),
+            Metric::new("pingap_request_latency_ms_sum", 12.5)
+                .with_label("location", "lo"),
+            Metric::new("pingap_request_latency_ms_count", 2.0)
+                .with_label("location", "lo"),
+            Metric::new("pingap_upstream_latency_ms_count", 0.0)
+                .with_label("location", "lo"),
+        ];
+        assert_eq!(
+            r#"# TYPE pingap_request_latency_ms histogram
+pingap_request_latency_ms_bucket{location="lo",le="5"} 1
+pingap_request_latency_ms_bucket{location="lo",le="+Inf"} 2
+pingap_request_latency_ms_sum{location="lo"} 12.5
+pingap_request_latency_ms_count{location="lo"} 2
+# TYPE pingap_upstream_latency_ms histogram
+pingap_upstream_latency_ms_count{location="lo"} 0
+"#,
+            format_prometheus(&metrics)
+        );
+    }
+
+    #[test]
+    fn test_encode_write_request() {
+        let metrics = vec![Metric::new("up", 1.0)];
+        let buf = encode_write_request(&metrics, &[], 1);
+        assert_eq!(
+            "0a1d0a0e0a085f5f6e616d655f5f12027570120b09000000000000f03f1001",
+            hex::encode(buf)
+        );
+
+        let metrics =
+            vec![Metric::new("up", 1.0).with_exemplar(Some(Exemplar {
+                trace_id: "abc".to_string(),
+                value: 1.0,
+                timestamp: 1,
+            }))];
+        let buf = encode_write_request(&metrics, &[], 1);
+        assert_eq!(
+            "0a3b0a0e0a085f5f6e616d655f5f12027570120b09000000000000f03f10011a1c0a0f0a0874726163655f6964120361626311000000000000f03f1801",
+            hex::encode(buf)
+        );
+    }
+
+    #[test]
+    fn test_snappy_encode() {
+        assert_eq!("0308616263", hex::encode(snappy_encode(b"abc")));
+
+        let data = vec![b'a'; 100];
+        let buf = snappy_encode(&data);
+        assert_eq!("64f063", hex::encode(&buf[..3]));
+        assert_eq!(data, buf[3..].to_vec());
+    }
+}
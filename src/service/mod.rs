@@ -75,6 +75,20 @@ impl BackgroundService for CommonServiceTask {
     }
 }
 
+mod anomaly;
 mod auto_restart;
+mod cache_prefetch;
+mod listen_fds;
+mod metrics_push;
+mod schedule;
+mod systemd;
 
+pub use anomaly::{new_anomaly_detection_service, DEFAULT_ANOMALY_SENSITIVITY};
 pub use auto_restart::new_auto_restart_service;
+pub use cache_prefetch::{new_cache_prefetch_service, CachePrefetchParams};
+pub use listen_fds::new_listen_fds_service;
+pub use metrics_push::{
+    get_prometheus_metrics, new_metrics_push_service, validate_metrics_push,
+};
+pub use schedule::{new_schedule_service, validate_cron};
+pub use systemd::new_systemd_notify_service;
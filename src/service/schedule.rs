@@ -0,0 +1,301 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{CommonServiceTask, ServiceTask};
+use crate::config::{
+    get_config_path, get_current_config, load_config, save_config,
+};
+use async_trait::async_trait;
+use chrono::{Datelike, Local, Timelike};
+use snafu::Snafu;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tracing::{error, info};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid error {message}"))]
+    Invalid { message: String },
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The cron expression with five fields:
+/// minute, hour, day of month, month and day of week.
+#[derive(Debug, Clone, PartialEq)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // the day of month or day of week is `*`
+    any_day: bool,
+}
+
+/// Parses the field of cron, it supports `*`, `a-b`, `*/n`, `a-b/n`
+/// and the list of them, the value is returned as bit set.
+fn parse_cron_field(value: &str, min: u32, max: u32) -> Result<u64> {
+    let invalid = || Error::Invalid {
+        message: format!("cron field({value}) is invalid"),
+    };
+    let parse = |value: &str| -> Result<u32> {
+        let value = value.parse::<u32>().map_err(|_| invalid())?;
+        if value < min || value > max {
+            return Err(invalid());
+        }
+        Ok(value)
+    };
+    let mut bits = 0;
+    for item in value.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step = step.parse::<usize>().map_err(|_| invalid())?;
+                if step == 0 {
+                    return Err(invalid());
+                }
+                (range, step)
+            },
+            None => (item, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse(start)?, parse(end)?)
+        } else {
+            let start = parse(range)?;
+            // `a/n` means from a to max with step n
+            (start, if step > 1 { max } else { start })
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for v in (start..=end).step_by(step) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for Cron {
+    type Err = Error;
+    fn from_str(value: &str) -> Result<Self> {
+        let value = match value.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            value => value,
+        };
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(Error::Invalid {
+                message: format!("cron({value}) should have five fields"),
+            });
+        }
+        let mut weekdays = parse_cron_field(fields[4], 0, 7)?;
+        // both 0 and 7 are sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*" || fields[4] == "*",
+        })
+    }
+}
+
+impl Cron {
+    /// Returns `true` if the time matches the cron, as the standard cron,
+    /// if both day of month and day of week are restricted,
+    /// the time matches either of them.
+    fn is_match<T: Datelike + Timelike>(&self, value: &T) -> bool {
+        let contains = |bits: u64, v: u32| bits & (1 << v) != 0;
+        if !contains(self.minutes, value.minute())
+            || !contains(self.hours, value.hour())
+            || !contains(self.months, value.month())
+        {
+            return false;
+        }
+        let day = contains(self.days, value.day());
+        let weekday =
+            contains(self.weekdays, value.weekday().num_days_from_sunday());
+        if self.any_day {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+}
+
+/// Validates the cron expression of schedule.
+pub fn validate_cron(value: &str) -> Result<()> {
+    Cron::from_str(value)?;
+    Ok(())
+}
+
+/// Applies the schedules to the config of storage, the categories of
+/// changed config are saved, and they are reloaded by auto reload service.
+async fn run_schedules(names: &[String]) -> Result<()> {
+    let path = get_config_path();
+    let mut conf =
+        load_config(&path, false)
+            .await
+            .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+    let mut categories = vec![];
+    for name in names.iter() {
+        // the schedule may be removed from storage
+        let Some(schedule) = conf.schedules.get(name).cloned() else {
+            continue;
+        };
+        conf.apply_schedule(&schedule).map_err(|e| Error::Invalid {
+            message: format!("{e}(schedule:{name})"),
+        })?;
+        if !categories.contains(&schedule.category) {
+            categories.push(schedule.category);
+        }
+    }
+    conf.validate().map_err(|e| Error::Invalid {
+        message: e.to_string(),
+    })?;
+    for category in categories.iter() {
+        save_config(&path, &conf, category).await.map_err(|e| {
+            Error::Invalid {
+                message: e.to_string(),
+            }
+        })?;
+    }
+    Ok(())
+}
+
+struct ScheduleTask {
+    // the minute of last check, the schedule runs once per minute
+    last_minute: AtomicI64,
+}
+
+#[async_trait]
+impl ServiceTask for ScheduleTask {
+    async fn run(&self) -> Option<bool> {
+        let now = Local::now();
+        let minute = now.timestamp() / 60;
+        if self.last_minute.swap(minute, Ordering::Relaxed) == minute {
+            return None;
+        }
+        let mut names: Vec<String> = get_current_config()
+            .schedules
+            .iter()
+            .filter(|(_, schedule)| {
+                Cron::from_str(&schedule.cron)
+                    .map(|cron| cron.is_match(&now))
+                    .unwrap_or_default()
+            })
+            .map(|(name, _)| name.to_string())
+            .collect();
+        if names.is_empty() {
+            return None;
+        }
+        names.sort();
+        match run_schedules(&names).await {
+            Ok(()) => {
+                info!(schedules = names.join(","), "run schedules success");
+            },
+            Err(e) => {
+                error!(
+                    error = e.to_string(),
+                    schedules = names.join(","),
+                    "run schedules fail"
+                );
+            },
+        };
+        None
+    }
+    fn description(&self) -> String {
+        "Schedule config changes".to_string()
+    }
+}
+
+/// Creates the service of schedules, it checks the cron of schedules
+/// every ten seconds and runs the matched schedules once per minute.
+pub fn new_schedule_service() -> CommonServiceTask {
+    CommonServiceTask::new(
+        "Schedule",
+        Duration::from_secs(10),
+        ScheduleTask {
+            // skip the current minute as the schedule may trigger restart
+            last_minute: AtomicI64::new(Local::now().timestamp() / 60),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cron_field, Cron};
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_parse_cron_field() {
+        assert_eq!(0b1110, parse_cron_field("1-3", 0, 59).unwrap());
+        assert_eq!(0b1010101, parse_cron_field("*/2", 0, 6).unwrap());
+        assert_eq!(0b1000100, parse_cron_field("2/4", 0, 6).unwrap());
+        assert_eq!(0b100110, parse_cron_field("1,2,5", 0, 6).unwrap());
+
+        assert_eq!(true, parse_cron_field("60", 0, 59).is_err());
+        assert_eq!(true, parse_cron_field("3-1", 0, 59).is_err());
+        assert_eq!(true, parse_cron_field("*/0", 0, 59).is_err());
+        assert_eq!(true, parse_cron_field("a", 0, 59).is_err());
+    }
+
+    #[test]
+    fn test_cron_match() {
+        assert_eq!(true, Cron::from_str("0 2 * *").is_err());
+        assert_eq!(
+            Cron::from_str("0 0 * * *").unwrap(),
+            Cron::from_str("@daily").unwrap()
+        );
+
+        // 2024-07-01 is monday
+        let time = NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(2, 0, 0)
+            .unwrap();
+        assert_eq!(true, Cron::from_str("0 2 * * *").unwrap().is_match(&time));
+        assert_eq!(
+            false,
+            Cron::from_str("30 2 * * *").unwrap().is_match(&time)
+        );
+        assert_eq!(
+            true,
+            Cron::from_str("*/30 0-8 * * 1-5").unwrap().is_match(&time)
+        );
+        assert_eq!(
+            false,
+            Cron::from_str("0 2 * * 0,6").unwrap().is_match(&time)
+        );
+        // day of month or day of week
+        assert_eq!(true, Cron::from_str("0 2 15 * 1").unwrap().is_match(&time));
+        assert_eq!(
+            false,
+            Cron::from_str("0 2 15 * 7").unwrap().is_match(&time)
+        );
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::state::{
+    get_watchdog_interval, is_restarting, notify_ready, notify_stopping,
+    notify_watchdog,
+};
+use async_trait::async_trait;
+use pingora::server::ShutdownWatch;
+use pingora::services::background::BackgroundService;
+use tokio::time::interval;
+use tracing::info;
+
+struct SystemdNotifyService {}
+
+/// Creates the service which notifies systemd the status of pingap,
+/// `READY=1` is sent after the services start, the watchdog is pinged
+/// if `WatchdogSec` is set, and `STOPPING=1` is sent on shutdown.
+pub fn new_systemd_notify_service() -> impl BackgroundService {
+    SystemdNotifyService {}
+}
+
+#[async_trait]
+impl BackgroundService for SystemdNotifyService {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        notify_ready();
+        if let Some(watchdog_interval) = get_watchdog_interval() {
+            let period_human: humantime::Duration = watchdog_interval.into();
+            info!(
+                interval = period_human.to_string(),
+                "systemd watchdog is enabled"
+            );
+            let mut period = interval(watchdog_interval);
+            loop {
+                tokio::select! {
+                    _ = shutdown.changed() => {
+                        break;
+                    }
+                    _ = period.tick() => {
+                        notify_watchdog();
+                    }
+                }
+            }
+        } else {
+            let _ = shutdown.changed().await;
+        }
+        // the new process notifies ready with its pid when restarting
+        if !is_restarting() {
+            notify_stopping();
+        }
+    }
+}
@@ -0,0 +1,470 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::state::get_hostname;
+use crate::util;
+use async_trait::async_trait;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use snafu::Snafu;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tracing::{debug, error, info};
+use url::Url;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid error {message}"))]
+    Invalid { message: String },
+    #[snafu(display("Io error {source}"))]
+    Io { source: std::io::Error },
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+// the messages are dropped if the channel is full,
+// so the request processing is never blocked by the sink
+const SINK_CHANNEL_SIZE: usize = 10_240;
+const DEFAULT_BATCH_SIZE: usize = 100;
+// the default max payload of nats server is 1MB
+const MAX_PAYLOAD_SIZE: usize = 512 * 1024;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const DEFAULT_SUBJECT_PREFIX: &str = "pingap";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SinkCategory {
+    AccessLog,
+    SecurityEvent,
+}
+
+/// The security event of waf, acl, limit plugins and ip ban.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "category", rename_all = "snake_case")]
+pub enum SecurityEvent {
+    // the request is blocked by plugin
+    Block {
+        plugin: String,
+        action: String,
+        ip: String,
+        method: String,
+        path: String,
+    },
+    // the ip is banned until the expired time
+    Ban {
+        ip: String,
+        expired_at: u64,
+    },
+}
+
+#[derive(Serialize)]
+struct SecurityEventRecord<'a> {
+    timestamp: u64,
+    hostname: &'a str,
+    #[serde(flatten)]
+    event: &'a SecurityEvent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct NatsTarget {
+    addr: String,
+    access_log_subject: String,
+    security_event_subject: String,
+}
+
+struct Sink {
+    sender: Sender<(SinkCategory, String)>,
+    dropped: AtomicU64,
+}
+
+static SINK: OnceCell<Sink> = OnceCell::new();
+
+/// Parses the sink url, only `nats://host:port/prefix` is supported,
+/// the subjects are `prefix.access_log` and `prefix.security_event`.
+fn parse_sink_target(value: &str) -> Result<NatsTarget> {
+    let url = Url::parse(value).map_err(|e| Error::Invalid {
+        message: format!("{value} is invalid, {e}"),
+    })?;
+    if url.scheme() != "nats" {
+        return Err(Error::Invalid {
+            message: format!("sink scheme({}) is not supported", url.scheme()),
+        });
+    }
+    let host = url.host_str().ok_or_else(|| Error::Invalid {
+        message: format!("host of {value} is required"),
+    })?;
+    let prefix = url.path().trim_matches('/');
+    let prefix = if prefix.is_empty() {
+        DEFAULT_SUBJECT_PREFIX
+    } else {
+        prefix
+    };
+    if prefix.contains(['/', ' ']) {
+        return Err(Error::Invalid {
+            message: format!("subject prefix({prefix}) is invalid"),
+        });
+    }
+    Ok(NatsTarget {
+        addr: util::join_host_port(
+            host.trim_start_matches('[').trim_end_matches(']'),
+            &url.port().unwrap_or(4222).to_string(),
+        ),
+        access_log_subject: format!("{prefix}.access_log"),
+        security_event_subject: format!("{prefix}.security_event"),
+    })
+}
+
+/// Validates the url of sink.
+pub fn validate_sink(value: &str) -> Result<()> {
+    parse_sink_target(value)?;
+    Ok(())
+}
+
+#[inline]
+fn send(category: SinkCategory, message: String) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    if let Err(TrySendError::Full(_)) =
+        sink.sender.try_send((category, message))
+    {
+        sink.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns `true` if the sink is enabled.
+#[inline]
+pub fn is_sink_enabled() -> bool {
+    SINK.get().is_some()
+}
+
+/// Sends the access log to sink, it never blocks.
+#[inline]
+pub fn send_access_log(line: &str) {
+    if is_sink_enabled() {
+        send(SinkCategory::AccessLog, line.to_string());
+    }
+}
+
+/// Sends the security event to sink, it never blocks.
+pub fn send_security_event(event: SecurityEvent) {
+    if !is_sink_enabled() {
+        return;
+    }
+    let hostname = get_hostname();
+    let record = SecurityEventRecord {
+        timestamp: util::now().as_millis() as u64,
+        hostname: &hostname,
+        event: &event,
+    };
+    if let Ok(message) = serde_json::to_string(&record) {
+        send(SinkCategory::SecurityEvent, message);
+    }
+}
+
+/// Gets the count of dropped messages, it's dropped if the channel
+/// is full or the messages are failed to publish.
+pub fn get_sink_dropped() -> u64 {
+    SINK.get()
+        .map(|sink| sink.dropped.load(Ordering::Relaxed))
+        .unwrap_or_default()
+}
+
+/// Encodes the messages as the `PUB` commands of nats, the messages
+/// are joined by new line as a batch.
+fn encode_batches(
+    subject: &str,
+    messages: &[String],
+    batch_size: usize,
+) -> Vec<u8> {
+    let mut buf = vec![];
+    let mut payload = String::new();
+    let mut count = 0;
+    let flush = |buf: &mut Vec<u8>, payload: &mut String| {
+        buf.extend(format!("PUB {subject} {}\r\n", payload.len()).as_bytes());
+        buf.extend(payload.as_bytes());
+        buf.extend(b"\r\n");
+        payload.clear();
+    };
+    for message in messages.iter() {
+        if !payload.is_empty()
+            && (count >= batch_size
+                || payload.len() + message.len() + 1 > MAX_PAYLOAD_SIZE)
+        {
+            flush(&mut buf, &mut payload);
+            count = 0;
+        }
+        if !payload.is_empty() {
+            payload.push('\n');
+        }
+        payload.push_str(message);
+        count += 1;
+    }
+    if !payload.is_empty() {
+        flush(&mut buf, &mut payload);
+    }
+    buf
+}
+
+struct SinkTask {
+    target: NatsTarget,
+    batch_size: usize,
+    receiver: Receiver<(SinkCategory, String)>,
+    stream: Mutex<Option<BufReader<TcpStream>>>,
+}
+
+impl SinkTask {
+    async fn connect(&self) -> Result<BufReader<TcpStream>> {
+        let stream =
+            timeout(CONNECT_TIMEOUT, TcpStream::connect(&self.target.addr))
+                .await
+                .map_err(|e| Error::Invalid {
+                    message: e.to_string(),
+                })?
+                .map_err(|source| Error::Io { source })?;
+        let mut stream = BufReader::new(stream);
+        // the server sends the info after connected
+        let mut line = String::new();
+        timeout(CONNECT_TIMEOUT, stream.read_line(&mut line))
+            .await
+            .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?
+            .map_err(|source| Error::Io { source })?;
+        if !line.starts_with("INFO") {
+            return Err(Error::Invalid {
+                message: format!("unexpected nats message: {}", line.trim()),
+            });
+        }
+        let connect = format!(
+            r#"CONNECT {{"verbose":false,"pedantic":false,"name":"pingap-{}"}}"#,
+            get_hostname()
+        );
+        stream
+            .get_mut()
+            .write_all(format!("{connect}\r\n").as_bytes())
+            .await
+            .map_err(|source| Error::Io { source })?;
+        info!(addr = self.target.addr, "sink connects to nats");
+        Ok(stream)
+    }
+    /// Replies the ping of server, the connection is closed by server
+    /// if the ping is not replied.
+    async fn reply_ping(stream: &mut BufReader<TcpStream>) -> Result<()> {
+        let mut buf = [0; 1024];
+        let mut pings = 0;
+        loop {
+            match stream.get_ref().try_read(&mut buf) {
+                Ok(0) => {
+                    return Err(Error::Invalid {
+                        message: "nats connection is closed".to_string(),
+                    });
+                },
+                Ok(size) => {
+                    let data =
+                        std::str::from_utf8(&buf[..size]).unwrap_or_default();
+                    pings += data.matches("PING").count();
+                    if let Some(index) = data.find("-ERR") {
+                        error!(
+                            message = data[index..].trim(),
+                            "nats server error"
+                        );
+                    }
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    break;
+                },
+                Err(source) => return Err(Error::Io { source }),
+            }
+        }
+        for _ in 0..pings {
+            stream
+                .get_mut()
+                .write_all(b"PONG\r\n")
+                .await
+                .map_err(|source| Error::Io { source })?;
+        }
+        Ok(())
+    }
+    async fn publish(&self, data: &[u8]) -> Result<()> {
+        let mut guard = self.stream.lock().await;
+        // not to connect until there are messages to publish
+        if guard.is_none() && data.is_empty() {
+            return Ok(());
+        }
+        if guard.is_none() {
+            *guard = Some(self.connect().await?);
+        }
+        let Some(stream) = guard.as_mut() else {
+            return Ok(());
+        };
+        let mut result = Self::reply_ping(stream).await;
+        if result.is_ok() && !data.is_empty() {
+            result = stream
+                .get_mut()
+                .write_all(data)
+                .await
+                .map_err(|source| Error::Io { source });
+        }
+        // reconnect at the next time
+        if result.is_err() {
+            *guard = None;
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl ServiceTask for SinkTask {
+    async fn run(&self) -> Option<bool> {
+        let mut access_logs = vec![];
+        let mut security_events = vec![];
+        for (category, message) in
+            self.receiver.try_iter().take(SINK_CHANNEL_SIZE)
+        {
+            match category {
+                SinkCategory::AccessLog => access_logs.push(message),
+                SinkCategory::SecurityEvent => security_events.push(message),
+            }
+        }
+        let count = access_logs.len() + security_events.len();
+        let mut data = encode_batches(
+            &self.target.security_event_subject,
+            &security_events,
+            self.batch_size,
+        );
+        data.extend(encode_batches(
+            &self.target.access_log_subject,
+            &access_logs,
+            self.batch_size,
+        ));
+        match self.publish(&data).await {
+            Ok(()) => {
+                if count > 0 {
+                    debug!(count, "sink publishes messages success");
+                }
+            },
+            Err(e) => {
+                if let Some(sink) = SINK.get() {
+                    sink.dropped.fetch_add(count as u64, Ordering::Relaxed);
+                }
+                error!(error = e.to_string(), count, "sink publishes fail");
+            },
+        }
+        None
+    }
+    fn description(&self) -> String {
+        format!("{:?}", self.target)
+    }
+}
+
+/// Creates a service to publish the access logs and security events
+/// to nats, the messages are batched and published every second.
+pub fn new_sink_service(
+    url: &str,
+    batch_size: Option<usize>,
+) -> Result<CommonServiceTask> {
+    let target = parse_sink_target(url)?;
+    let (sender, receiver) = crossbeam_channel::bounded(SINK_CHANNEL_SIZE);
+    SINK.set(Sink {
+        sender,
+        dropped: AtomicU64::new(0),
+    })
+    .map_err(|_| Error::Invalid {
+        message: "sink is initialized".to_string(),
+    })?;
+    Ok(CommonServiceTask::new(
+        "Sink",
+        FLUSH_INTERVAL,
+        SinkTask {
+            target,
+            batch_size: batch_size
+                .filter(|value| *value > 0)
+                .unwrap_or(DEFAULT_BATCH_SIZE),
+            receiver,
+            stream: Mutex::new(None),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_batches, parse_sink_target, NatsTarget, SecurityEvent,
+        SecurityEventRecord,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_sink_target() {
+        assert_eq!(
+            NatsTarget {
+                addr: "127.0.0.1:4222".to_string(),
+                access_log_subject: "pingap.access_log".to_string(),
+                security_event_subject: "pingap.security_event".to_string(),
+            },
+            parse_sink_target("nats://127.0.0.1").unwrap()
+        );
+        assert_eq!(
+            "logs.prod.access_log",
+            parse_sink_target("nats://127.0.0.1:4223/logs.prod")
+                .unwrap()
+                .access_log_subject
+        );
+        assert_eq!(
+            "Invalid error sink scheme(kafka) is not supported",
+            parse_sink_target("kafka://127.0.0.1:9092")
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_encode_batches() {
+        let messages: Vec<String> = ["a", "bc", "def"]
+            .iter()
+            .map(|item| item.to_string())
+            .collect();
+        assert_eq!(
+            "PUB pingap.access_log 4\r\na\nbc\r\nPUB pingap.access_log 3\r\ndef\r\n",
+            std::string::String::from_utf8_lossy(&encode_batches(
+                "pingap.access_log",
+                &messages,
+                2
+            ))
+        );
+        assert_eq!(
+            true,
+            encode_batches("pingap.access_log", &[], 2).is_empty()
+        );
+
+        let record = SecurityEventRecord {
+            timestamp: 1_700_000_000_000,
+            hostname: "pingap",
+            event: &SecurityEvent::Ban {
+                ip: "1.1.1.1".to_string(),
+                expired_at: 1_700_000_600,
+            },
+        };
+        assert_eq!(
+            r#"{"timestamp":1700000000000,"hostname":"pingap","category":"ban","ip":"1.1.1.1","expired_at":1700000600}"#,
+            serde_json::to_string(&record).unwrap()
+        );
+    }
+}
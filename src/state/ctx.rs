@@ -12,17 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::plugin::CoalescingLeader;
+use crate::proxy::{
+    AlwaysOnlineRecord, CaptureRecord, TrafficRecord, UpstreamConcurrencyGuard,
+};
 use crate::util::format_duration;
 use crate::{proxy::Location, util};
+use ahash::AHashMap;
 use bytes::{Bytes, BytesMut};
 use http::StatusCode;
 use pingora_limits::inflight::Guard;
+use std::fmt::Write;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
 use std::{sync::Arc, time::Duration};
 
 pub trait ModifyResponseBody: Sync + Send {
     fn handle(&self, data: Bytes) -> Bytes;
 }
 
+/// The target of internal redirect, it's set by the `X-Sendfile`
+/// or `X-Accel-Redirect` of upstream response.
+#[derive(Clone)]
+pub enum InternalRedirect {
+    // the file of `X-Sendfile`
+    File(PathBuf),
+    // the location matched by the path of `X-Accel-Redirect`
+    Location(Arc<Location>),
+}
+
 pub struct CompressionStat {
     pub in_bytes: usize,
     pub out_bytes: usize,
@@ -50,6 +68,8 @@ pub struct State {
     pub tls_version: Option<String>,
     // client tls cipher
     pub tls_cipher: Option<String>,
+    // client tls fingerprint(ja3)
+    pub tls_fingerprint: Option<String>,
     // client tls handshake time
     pub tls_handshake_time: Option<u64>,
     // http status code
@@ -63,15 +83,43 @@ pub struct State {
     pub location: Option<Arc<Location>>,
     // the upstream address
     pub upstream_address: String,
+    // the status of upstream response, it's kept when the response
+    // is replaced, e.g. internal redirect
+    pub upstream_status: Option<StatusCode>,
+    // the tier of upstream fallback chain, 0 is the primary upstream
+    pub upstream_tier: usize,
+    // the upstream name of current tier, the active upstream of
+    // blue green deployment is resolved once for the request
+    pub upstream_name: Option<String>,
+    // the retries on other backends of upstream
+    pub upstream_retries: u8,
+    // the addresses of backends which are failed to connect,
+    // they are skipped when retrying
+    pub upstream_failed_addrs: Option<Vec<String>>,
     pub client_ip: Option<String>,
     pub remote_addr: Option<String>,
     pub guard: Option<Guard>,
+    // the concurrency of upstream is released when the request is done
+    pub upstream_concurrency_guard: Option<UpstreamConcurrencyGuard>,
     pub request_id: Option<String>,
     pub cache_prefix: Option<String>,
+    // the request headers(`name:value`) of cache key
+    pub cache_key_headers: Option<Vec<String>>,
     pub cache_lookup_time: Option<u64>,
     pub cache_lock_time: Option<u64>,
     pub cache_max_ttl: Option<Duration>,
+    // the cache is bypassed by the directive of request
+    pub cache_bypass: bool,
+    // the cached object is treated as expired by the directive of request
+    pub cache_refresh: bool,
+    // the range request is sent to upstream without range,
+    // so the full object is fetched and cached
+    pub cache_range_fill: bool,
+    // the partial response(206) of range request is cacheable
+    pub cache_partial: bool,
     pub upstream_reused: bool,
+    // close the upstream connection after the request
+    pub upstream_connection_close: bool,
     // upstream connect time
     // it may be a small value if it is a reused connection
     pub upstream_connect_time: Option<u64>,
@@ -91,6 +139,27 @@ pub struct State {
     pub compression_stat: Option<CompressionStat>,
     pub modify_response_body: Option<Box<dyn ModifyResponseBody>>,
     pub response_body: Option<BytesMut>,
+    // the debug journal of request, it's only recorded when debug is enabled
+    pub journal: Option<Vec<String>>,
+    // the id of request watched by slow request watchdog
+    pub slow_request_id: Option<u64>,
+    // the internal redirect of upstream response, it's served
+    // instead of the upstream response
+    pub internal_redirect: Option<InternalRedirect>,
+    // the duplicated fd of upstream connection, it keeps the connection
+    // open for the grace period after the client aborts
+    pub upstream_grace_fd: Option<OwnedFd>,
+    // the variables set by plugins, e.g. the identity of auth,
+    // they can be used by later plugins, header templates and access log
+    pub variables: Option<AHashMap<String, String>>,
+    // the record of request captured by admin, it's exported as har
+    pub capture: Option<Box<CaptureRecord>>,
+    // the sampled request recorded for traffic replay
+    pub traffic_record: Option<Box<TrafficRecord>>,
+    // the request is the leader of coalescing, its response is shared
+    pub coalescing: Option<Box<CoalescingLeader>>,
+    // the successful response is kept for always online
+    pub always_online: Option<Box<AlwaysOnlineRecord>>,
 }
 
 impl Default for State {
@@ -102,22 +171,35 @@ impl Default for State {
             location_accepted: 0,
             tls_version: None,
             tls_cipher: None,
+            tls_fingerprint: None,
             tls_handshake_time: None,
             status: None,
             connection_time: 0,
             connection_reused: false,
             created_at: util::now().as_millis() as u64,
             upstream_reused: false,
+            upstream_connection_close: false,
             location: None,
             upstream_address: "".to_string(),
+            upstream_status: None,
+            upstream_tier: 0,
+            upstream_name: None,
+            upstream_retries: 0,
+            upstream_failed_addrs: None,
             client_ip: None,
             remote_addr: None,
             guard: None,
+            upstream_concurrency_guard: None,
             request_id: None,
             cache_prefix: None,
+            cache_key_headers: None,
             cache_lookup_time: None,
             cache_lock_time: None,
             cache_max_ttl: None,
+            cache_bypass: false,
+            cache_refresh: false,
+            cache_range_fill: false,
+            cache_partial: false,
             upstream_connect_time: None,
             upstream_connected: None,
             upstream_tcp_connect_time: None,
@@ -128,13 +210,69 @@ impl Default for State {
             compression_stat: None,
             modify_response_body: None,
             response_body: None,
+            journal: None,
+            slow_request_id: None,
+            internal_redirect: None,
+            upstream_grace_fd: None,
+            variables: None,
+            capture: None,
+            traffic_record: None,
+            coalescing: None,
+            always_online: None,
         }
     }
 }
 
 const ONE_HOUR_MS: u64 = 60 * 60 * 1000;
 
+/// Formats the float with one decimal place,
+/// it's faster than `format!("{:.1}")`.
+#[inline]
+fn format_one_decimal(mut buf: BytesMut, value: f64) -> BytesMut {
+    if !value.is_finite() || value < 0.0 {
+        let _ = write!(buf, "{value:.1}");
+        return buf;
+    }
+    let value = (value * 10.0).round() as u64;
+    buf.extend(itoa::Buffer::new().format(value / 10).as_bytes());
+    buf.extend(b".");
+    buf.extend(itoa::Buffer::new().format(value % 10).as_bytes());
+    buf
+}
+
 impl State {
+    /// Adds an event to the debug journal,
+    /// the event is only generated when the journal is enabled.
+    #[inline]
+    pub fn add_journal<F>(&mut self, event: F)
+    where
+        F: FnOnce() -> String,
+    {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push(event());
+        }
+    }
+    /// Gets the debug journal joined by `; `.
+    #[inline]
+    pub fn get_journal(&self) -> Option<String> {
+        self.journal.as_ref().map(|journal| journal.join("; "))
+    }
+    /// Adds a variable to context, the value will be replaced
+    /// if the key exists.
+    #[inline]
+    pub fn add_variable(&mut self, key: &str, value: &str) {
+        self.variables
+            .get_or_insert_with(AHashMap::new)
+            .insert(key.to_string(), value.to_string());
+    }
+    /// Gets the variable from context.
+    #[inline]
+    pub fn get_variable(&self, key: &str) -> Option<&str> {
+        self.variables
+            .as_ref()
+            .and_then(|variables| variables.get(key))
+            .map(|value| value.as_str())
+    }
     #[inline]
     pub fn get_upstream_response_time(&self) -> Option<u64> {
         if let Some(value) = self.upstream_response_time {
@@ -173,6 +311,14 @@ impl State {
                 }
             },
             "upstream_addr" => buf.extend(self.upstream_address.as_bytes()),
+            "upstream_status" => {
+                if let Some(status) = self.upstream_status {
+                    buf.extend(status.as_str().as_bytes());
+                }
+            },
+            "upstream_tier" => buf.extend(
+                itoa::Buffer::new().format(self.upstream_tier).as_bytes(),
+            ),
             "processing" => buf
                 .extend(itoa::Buffer::new().format(self.processing).as_bytes()),
             "upstream_connect_time" => {
@@ -230,6 +376,11 @@ impl State {
                     buf.extend(value.as_bytes());
                 }
             },
+            "tls_fingerprint" => {
+                if let Some(value) = &self.tls_fingerprint {
+                    buf.extend(value.as_bytes());
+                }
+            },
             "tls_handshake_time" => {
                 if let Some(value) = self.tls_handshake_time {
                     buf = format_duration(buf, value);
@@ -243,7 +394,7 @@ impl State {
             },
             "compression_ratio" => {
                 if let Some(value) = &self.compression_stat {
-                    buf.extend(format!("{:.1}", value.ratio()).as_bytes());
+                    buf = format_one_decimal(buf, value.ratio());
                 }
             },
             "cache_lookup_time" => {
@@ -256,13 +407,25 @@ impl State {
                     buf = format_duration(buf, ms);
                 }
             },
+            "cache_directive" => {
+                if self.cache_bypass {
+                    buf.extend(b"BYPASS");
+                } else if self.cache_refresh {
+                    buf.extend(b"REFRESH");
+                }
+            },
             "service_time" => {
                 buf = format_duration(
                     buf,
                     util::now().as_millis() as u64 - self.created_at,
                 )
             },
-            _ => {},
+            _ => {
+                // the variables set by plugins
+                if let Some(value) = self.get_variable(key) {
+                    buf.extend(value.as_bytes());
+                }
+            },
         }
         buf
     }
@@ -270,16 +433,62 @@ impl State {
 
 #[cfg(test)]
 mod tests {
-    use super::State;
+    use super::{format_one_decimal, State};
     use crate::config::LocationConf;
     use crate::proxy::Location;
     use crate::state::CompressionStat;
     use crate::util;
     use bytes::BytesMut;
+    use http::StatusCode;
     use pretty_assertions::assert_eq;
     use std::sync::Arc;
     use std::time::Duration;
 
+    #[test]
+    fn test_variables() {
+        let mut ctx = State::default();
+        assert_eq!(true, ctx.get_variable("jwt_subject").is_none());
+        ctx.add_variable("jwt_subject", "pingap");
+        assert_eq!("pingap", ctx.get_variable("jwt_subject").unwrap());
+        assert_eq!(
+            b"pingap",
+            ctx.append_value(BytesMut::new(), "jwt_subject").as_ref()
+        );
+        assert_eq!(
+            true,
+            ctx.append_value(BytesMut::new(), "unknown").is_empty()
+        );
+    }
+
+    #[test]
+    fn test_journal() {
+        let mut ctx = State::default();
+        ctx.add_journal(|| "location:lo".to_string());
+        assert_eq!(true, ctx.get_journal().is_none());
+
+        ctx.journal = Some(vec![]);
+        ctx.add_journal(|| "location:lo".to_string());
+        ctx.add_journal(|| "upstream:charts".to_string());
+        assert_eq!(
+            "location:lo; upstream:charts",
+            ctx.get_journal().unwrap_or_default()
+        );
+    }
+
+    #[test]
+    fn test_format_one_decimal() {
+        assert_eq!(b"0.0", format_one_decimal(BytesMut::new(), 0.0).as_ref());
+        assert_eq!(b"1.3", format_one_decimal(BytesMut::new(), 1.26).as_ref());
+        assert_eq!(
+            b"12.0",
+            format_one_decimal(BytesMut::new(), 11.96).as_ref()
+        );
+        assert_eq!(
+            b"NaN",
+            format_one_decimal(BytesMut::new(), f64::NAN).as_ref()
+        );
+    }
+
     #[test]
     fn test_state() {
         let mut ctx = State {
@@ -305,6 +514,19 @@ mod tests {
             ctx.append_value(BytesMut::new(), "upstream_addr").as_ref()
         );
 
+        ctx.upstream_status = Some(StatusCode::NO_CONTENT);
+        assert_eq!(
+            b"204",
+            ctx.append_value(BytesMut::new(), "upstream_status")
+                .as_ref()
+        );
+
+        ctx.upstream_tier = 1;
+        assert_eq!(
+            b"1",
+            ctx.append_value(BytesMut::new(), "upstream_tier").as_ref()
+        );
+
         ctx.processing = 10;
         assert_eq!(
             b"10",
@@ -386,6 +608,14 @@ mod tests {
             ctx.append_value(BytesMut::new(), "tls_cipher").as_ref()
         );
 
+        ctx.tls_fingerprint =
+            Some("b32309a26951912be7dba376398abc3b".to_string());
+        assert_eq!(
+            b"b32309a26951912be7dba376398abc3b",
+            ctx.append_value(BytesMut::new(), "tls_fingerprint")
+                .as_ref()
+        );
+
         ctx.compression_stat = Some(CompressionStat {
             in_bytes: 1024,
             out_bytes: 500,
@@ -416,6 +646,13 @@ mod tests {
                 .as_ref()
         );
 
+        ctx.cache_bypass = true;
+        assert_eq!(
+            b"BYPASS",
+            ctx.append_value(BytesMut::new(), "cache_directive")
+                .as_ref()
+        );
+
         ctx.created_at = util::now().as_millis() as u64 - 1;
         assert_eq!(
             b"1ms",
@@ -14,5 +14,13 @@
 
 mod ctx;
 mod process;
+mod profiling;
+mod sandbox;
+mod systemd;
+mod upgrade;
 pub use ctx::*;
 pub use process::*;
+pub use profiling::*;
+pub use sandbox::*;
+pub use systemd::*;
+pub use upgrade::{upgrade_binary, UpgradeParams};
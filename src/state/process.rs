@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::notify_reloading;
 use crate::util;
 use crate::webhook;
 use once_cell::sync::Lazy;
@@ -68,6 +69,11 @@ static PROCESS_RESTAR_COUNT: Lazy<AtomicU8> = Lazy::new(|| AtomicU8::new(0));
 static PROCESS_RESTARTING: Lazy<AtomicBool> =
     Lazy::new(|| AtomicBool::new(false));
 
+/// Returns true if the process is restarting.
+pub fn is_restarting() -> bool {
+    PROCESS_RESTARTING.load(Ordering::Relaxed)
+}
+
 pub fn restart_now() -> io::Result<process::Output> {
     let restarting = PROCESS_RESTARTING.swap(true, Ordering::Relaxed);
     if restarting {
@@ -78,6 +84,8 @@ pub fn restart_now() -> io::Result<process::Output> {
         ));
     }
     info!("pingap will restart");
+    // the new process notifies systemd ready after it starts
+    notify_reloading();
     webhook::send(webhook::SendNotificationParams {
         level: webhook::NotificationLevel::Info,
         category: webhook::NotificationCategory::Restart,
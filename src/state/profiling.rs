@@ -0,0 +1,132 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use once_cell::sync::OnceCell;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The function to add or remove the tag of current thread.
+pub type ProfilingTagFn = Box<dyn Fn(String, String) + Send + Sync>;
+
+struct ProfilingTagger {
+    add: ProfilingTagFn,
+    remove: ProfilingTagFn,
+}
+
+static PROFILING_TAGGER: OnceCell<ProfilingTagger> = OnceCell::new();
+static PROFILING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // the (server, location) tags of current thread
+    static CURRENT_TAGS: RefCell<Option<(String, String)>> = const { RefCell::new(None) };
+}
+
+/// Sets the tagger of profiling agent,
+/// the profiling is available after it's set.
+pub fn set_profiling_tagger(add: ProfilingTagFn, remove: ProfilingTagFn) {
+    if PROFILING_TAGGER
+        .set(ProfilingTagger { add, remove })
+        .is_ok()
+    {
+        PROFILING_ENABLED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Returns `true` if the profiling agent is running.
+pub fn is_profiling_available() -> bool {
+    PROFILING_TAGGER.get().is_some()
+}
+
+/// Returns `true` if the profiling is enabled.
+pub fn is_profiling_enabled() -> bool {
+    PROFILING_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Enables or disables the profiling at runtime,
+/// returns `false` if the profiling agent is not running.
+pub fn set_profiling_enabled(enabled: bool) -> bool {
+    if !is_profiling_available() {
+        return false;
+    }
+    PROFILING_ENABLED.store(enabled, Ordering::Relaxed);
+    true
+}
+
+/// Sets the server and location tags of current thread,
+/// the cpu samples after it are attributed to the location.
+/// The tasks of different requests may run on the same thread,
+/// so it should be called when each phase of request starts.
+pub fn set_profiling_tags(server: &str, location: &str) {
+    if !is_profiling_enabled() {
+        return;
+    }
+    let Some(tagger) = PROFILING_TAGGER.get() else {
+        return;
+    };
+    CURRENT_TAGS.with(|current| {
+        let mut current = current.borrow_mut();
+        if let Some((current_server, current_location)) = current.as_ref() {
+            if current_server == server && current_location == location {
+                return;
+            }
+            (tagger.remove)("server".to_string(), current_server.clone());
+            (tagger.remove)("location".to_string(), current_location.clone());
+        }
+        (tagger.add)("server".to_string(), server.to_string());
+        (tagger.add)("location".to_string(), location.to_string());
+        *current = Some((server.to_string(), location.to_string()));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_profiling_available, is_profiling_enabled, set_profiling_enabled,
+        set_profiling_tagger, set_profiling_tags,
+    };
+    use pretty_assertions::assert_eq;
+    use std::sync::Mutex;
+
+    static TAGS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+    #[test]
+    fn test_profiling_tags() {
+        assert_eq!(false, is_profiling_available());
+        assert_eq!(false, set_profiling_enabled(true));
+
+        set_profiling_tagger(
+            Box::new(|key, value| {
+                TAGS.lock().unwrap().push(format!("+{key}:{value}"));
+            }),
+            Box::new(|key, value| {
+                TAGS.lock().unwrap().push(format!("-{key}:{value}"));
+            }),
+        );
+        assert_eq!(true, is_profiling_available());
+        assert_eq!(true, is_profiling_enabled());
+
+        set_profiling_tags("pingap", "lo");
+        // the same tags are not set again
+        set_profiling_tags("pingap", "lo");
+        set_profiling_tags("pingap", "charts");
+        assert_eq!(
+            r#"["+server:pingap", "+location:lo", "-server:pingap", "-location:lo", "+server:pingap", "+location:charts"]"#,
+            format!("{:?}", TAGS.lock().unwrap())
+        );
+
+        assert_eq!(true, set_profiling_enabled(false));
+        set_profiling_tags("pingap", "lo");
+        assert_eq!(6, TAGS.lock().unwrap().len());
+    }
+}
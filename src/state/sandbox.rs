@@ -0,0 +1,418 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{find_activated_fd, get_activated_listeners};
+use once_cell::sync::Lazy;
+use std::ffi::CString;
+use std::io;
+use std::net::TcpListener;
+use std::os::fd::{IntoRawFd, RawFd};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+pub const SANDBOX_LANDLOCK: &str = "landlock";
+
+// the system paths which are allowed to read and execute in sandbox,
+// e.g. the shared libraries, the dns config and the ca certificates
+const SANDBOX_SYSTEM_PATHS: [&str; 8] = [
+    "/etc", "/usr", "/lib", "/lib64", "/bin", "/proc", "/sys", "/dev",
+];
+
+static PREBOUND_LISTENERS: Lazy<Mutex<Vec<(String, RawFd)>>> =
+    Lazy::new(|| Mutex::new(vec![]));
+
+/// Returns true if the process runs as root and the user is set,
+/// the listeners are bound before the privileges are dropped.
+pub fn is_privilege_drop_enabled(user: Option<&str>) -> bool {
+    user.map_or(false, |value| !value.is_empty())
+        && unsafe { libc::geteuid() } == 0
+}
+
+/// Binds the listen addresses before dropping privileges, so the
+/// privileged ports(e.g. 80 and 443) can be used by the normal user.
+/// The addresses of systemd socket activation are skipped.
+pub fn prebind_listeners(addrs: &str) -> io::Result<()> {
+    let activated = get_activated_listeners();
+    let Ok(mut listeners) = PREBOUND_LISTENERS.lock() else {
+        return Ok(());
+    };
+    for addr in addrs.split(',') {
+        if listeners.iter().any(|(item, _)| item == addr)
+            || find_activated_fd(activated, addr).is_some()
+        {
+            continue;
+        }
+        let listener = TcpListener::bind(addr.trim()).map_err(|e| {
+            io::Error::new(e.kind(), format!("bind {addr} fail: {e}"))
+        })?;
+        listener.set_nonblocking(true)?;
+        info!(addr, "listener is bound before dropping privileges");
+        listeners.push((addr.to_string(), listener.into_raw_fd()));
+    }
+    Ok(())
+}
+
+/// Gets the fd of listen address which is bound before
+/// dropping privileges.
+pub fn get_prebound_fd(addr: &str) -> Option<RawFd> {
+    PREBOUND_LISTENERS
+        .lock()
+        .ok()?
+        .iter()
+        .find(|(item, _)| item == addr)
+        .map(|(_, fd)| *fd)
+}
+
+fn new_cstring(value: &str) -> io::Result<CString> {
+    CString::new(value)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Switches the process to the user and group, the primary group of
+/// user is used if the group is not set. It's applied to all threads.
+pub fn drop_privileges(user: &str, group: Option<&str>) -> io::Result<()> {
+    let name = new_cstring(user)?;
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if passwd.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("user({user}) is not found"),
+        ));
+    }
+    let (uid, mut gid) = unsafe { ((*passwd).pw_uid, (*passwd).pw_gid) };
+    if let Some(group) = group.filter(|value| !value.is_empty()) {
+        let name = new_cstring(group)?;
+        let value = unsafe { libc::getgrnam(name.as_ptr()) };
+        if value.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("group({group}) is not found"),
+            ));
+        }
+        gid = unsafe { (*value).gr_gid };
+    }
+    // the supplementary groups of root should be removed first
+    unsafe {
+        if libc::setgroups(1, &gid) != 0
+            || libc::setgid(gid) != 0
+            || libc::setuid(uid) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        // the root privileges can't be regained
+        if uid != 0 && libc::setuid(0) == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "privileges are not dropped",
+            ));
+        }
+    }
+    info!(user, uid, gid, "privileges are dropped");
+    Ok(())
+}
+
+/// Validates the category of sandbox.
+pub fn validate_sandbox(category: &str) -> io::Result<()> {
+    match category {
+        "" | SANDBOX_LANDLOCK => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("sandbox({category}) is not supported"),
+        )),
+    }
+}
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+// the access rights of abi v1, from execute to make symlink
+const ACCESS_FS_ABI_V1: u64 = (1 << 13) - 1;
+const ACCESS_FS_REFER: u64 = 1 << 13;
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+const ACCESS_FS_READ: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+// only these rights can be granted to the rule of file
+const ACCESS_FS_FILE: u64 = ACCESS_FS_EXECUTE
+    | ACCESS_FS_WRITE_FILE
+    | ACCESS_FS_READ_FILE
+    | ACCESS_FS_TRUNCATE;
+
+#[repr(C)]
+struct LandlockRulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct LandlockPathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// Gets the access rights which are handled by the abi version.
+fn get_handled_access(abi: libc::c_long) -> u64 {
+    let mut access = ACCESS_FS_ABI_V1;
+    if abi >= 2 {
+        access |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        access |= ACCESS_FS_TRUNCATE;
+    }
+    access
+}
+
+/// Gets the allowed access rights of the path.
+fn get_allowed_access(handled: u64, writable: bool, is_dir: bool) -> u64 {
+    let mut access = if writable {
+        handled
+    } else {
+        handled & ACCESS_FS_READ
+    };
+    if !is_dir {
+        access &= ACCESS_FS_FILE;
+    }
+    access
+}
+
+#[cfg(target_os = "linux")]
+fn apply_landlock(rules: &[(String, bool)]) -> io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<LandlockRulesetAttr>(),
+            0_usize,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "landlock is not supported by kernel",
+        ));
+    }
+    let handled = get_handled_access(abi);
+    let attr = LandlockRulesetAttr {
+        handled_access_fs: handled,
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const LandlockRulesetAttr,
+            std::mem::size_of::<LandlockRulesetAttr>(),
+            0_u32,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let ruleset = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+    for (path, writable) in rules.iter() {
+        // the path which doesn't exist is ignored
+        let Ok(metadata) = std::fs::metadata(path) else {
+            debug!(path, "sandbox path is not found");
+            continue;
+        };
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+            .open(path)?;
+        let attr = LandlockPathBeneathAttr {
+            allowed_access: get_allowed_access(
+                handled,
+                *writable,
+                metadata.is_dir(),
+            ),
+            parent_fd: file.as_raw_fd(),
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                LANDLOCK_RULE_PATH_BENEATH,
+                &attr as *const LandlockPathBeneathAttr,
+                0_u32,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+            || libc::syscall(
+                libc::SYS_landlock_restrict_self,
+                ruleset.as_raw_fd(),
+                0_u32,
+            ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_landlock(_rules: &[(String, bool)]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "landlock is only supported on linux",
+    ))
+}
+
+/// Gets the other threads of process, the format is `{name}({tid})`.
+#[cfg(target_os = "linux")]
+fn get_other_threads() -> Vec<String> {
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) }.to_string();
+    let Ok(entries) = std::fs::read_dir("/proc/self/task") else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let id = entry.file_name().to_string_lossy().to_string();
+            if id == tid {
+                return None;
+            }
+            let name = std::fs::read_to_string(entry.path().join("comm"))
+                .unwrap_or_default();
+            Some(format!("{}({id})", name.trim()))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_other_threads() -> Vec<String> {
+    vec![]
+}
+
+/// Restricts the filesystem access of process, the writable paths(e.g.
+/// config, cache and log directories) can be read and written, the
+/// readable paths(e.g. certificates and static files) and the system
+/// paths can only be read. The sandbox is applied to the current
+/// thread and the threads which are created after, so it should be
+/// applied before the services start, the threads which already exist
+/// are not restricted and they are logged.
+pub fn apply_sandbox(
+    category: &str,
+    writable_paths: &[String],
+    readable_paths: &[String],
+) -> io::Result<()> {
+    validate_sandbox(category)?;
+    if category.is_empty() {
+        return Ok(());
+    }
+    let mut rules: Vec<(String, bool)> = SANDBOX_SYSTEM_PATHS
+        .iter()
+        .map(|item| (item.to_string(), false))
+        .collect();
+    // the executable is used to restart
+    if let Ok(exec_path) = std::env::current_exe() {
+        rules.push((exec_path.to_string_lossy().to_string(), false));
+    }
+    rules.push(("/dev/null".to_string(), true));
+    for path in readable_paths.iter() {
+        rules.push((path.clone(), false));
+    }
+    for path in writable_paths.iter() {
+        rules.push((path.clone(), true));
+    }
+    apply_landlock(&rules)?;
+    info!(
+        category,
+        paths = writable_paths.join(","),
+        readable_paths = readable_paths.join(","),
+        "sandbox is applied"
+    );
+    // landlock only restricts the calling thread and its new threads
+    let threads = get_other_threads();
+    if !threads.is_empty() {
+        warn!(
+            category,
+            threads = threads.join(","),
+            "threads created before sandbox are not restricted"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        drop_privileges, get_allowed_access, get_handled_access,
+        get_other_threads, get_prebound_fd, prebind_listeners,
+        validate_sandbox,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_get_other_threads() {
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let handle = std::thread::Builder::new()
+            .name("sandbox-test".to_string())
+            .spawn(move || {
+                let _ = rx.recv();
+            })
+            .unwrap();
+        let threads = get_other_threads();
+        assert_eq!(
+            true,
+            threads.iter().any(|item| item.starts_with("sandbox-test("))
+        );
+        drop(tx);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_landlock_access() {
+        assert_eq!(0x1fff, get_handled_access(1));
+        assert_eq!(0x3fff, get_handled_access(2));
+        assert_eq!(0x7fff, get_handled_access(3));
+
+        let handled = get_handled_access(3);
+        assert_eq!(0x7fff, get_allowed_access(handled, true, true));
+        assert_eq!(0x4007, get_allowed_access(handled, true, false));
+        assert_eq!(0b1101, get_allowed_access(handled, false, true));
+        assert_eq!(0b101, get_allowed_access(handled, false, false));
+        assert_eq!(
+            0b111,
+            get_allowed_access(get_handled_access(1), true, false)
+        );
+    }
+
+    #[test]
+    fn test_privileges() {
+        assert_eq!(
+            "user(pingap-not-exists) is not found",
+            drop_privileges("pingap-not-exists", None)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(true, validate_sandbox("landlock").is_ok());
+        assert_eq!(
+            "sandbox(seccomp) is not supported",
+            validate_sandbox("seccomp").unwrap_err().to_string()
+        );
+
+        prebind_listeners("127.0.0.1:0").unwrap();
+        assert_eq!(true, get_prebound_fd("127.0.0.1:0").is_some());
+        assert_eq!(true, get_prebound_fd("127.0.0.1:1").is_none());
+    }
+}
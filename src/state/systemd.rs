@@ -0,0 +1,255 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use once_cell::sync::OnceCell;
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+// the first fd passed by systemd socket activation
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// The listener passed by systemd socket activation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivatedListener {
+    pub addr: SocketAddr,
+    pub fd: RawFd,
+}
+
+static ACTIVATED_LISTENERS: OnceCell<Vec<ActivatedListener>> = OnceCell::new();
+
+/// Parses the count of fds from `LISTEN_PID` and `LISTEN_FDS`,
+/// the fds are only for the process of `LISTEN_PID`.
+fn parse_listen_fds(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+) -> usize {
+    let Some(listen_pid) = listen_pid.and_then(|v| v.parse::<u32>().ok())
+    else {
+        return 0;
+    };
+    if listen_pid != pid {
+        return 0;
+    }
+    listen_fds
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or_default()
+}
+
+/// Gets the tcp listeners passed by systemd socket activation, the
+/// environment variables are removed after they are read, so the
+/// restarted process doesn't inherit them.
+pub fn get_activated_listeners() -> &'static [ActivatedListener] {
+    ACTIVATED_LISTENERS.get_or_init(|| {
+        let count = parse_listen_fds(
+            std::env::var("LISTEN_PID").ok().as_deref(),
+            std::env::var("LISTEN_FDS").ok().as_deref(),
+            std::process::id(),
+        );
+        for key in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            std::env::remove_var(key);
+        }
+        let mut listeners = vec![];
+        for fd in SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count as RawFd {
+            // the fd is owned by the listener temporarily to get its address
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            let result = listener
+                .set_nonblocking(true)
+                .and_then(|_| listener.local_addr());
+            let fd = listener.into_raw_fd();
+            match result {
+                Ok(addr) => {
+                    info!(fd, addr = addr.to_string(), "socket is activated");
+                    listeners.push(ActivatedListener { addr, fd });
+                },
+                Err(e) => {
+                    error!(
+                        error = e.to_string(),
+                        fd, "activated socket is not tcp listener"
+                    );
+                },
+            }
+        }
+        listeners
+    })
+}
+
+/// Whether the activated address matches the listen address, the
+/// unspecified addresses(e.g. `0.0.0.0` and `[::]`) of the same port
+/// are matched, because the systemd socket is dual stack by default.
+fn is_addr_matched(activated: &SocketAddr, addr: &SocketAddr) -> bool {
+    if activated.port() != addr.port() {
+        return false;
+    }
+    activated.ip() == addr.ip()
+        || (activated.ip().is_unspecified() && addr.ip().is_unspecified())
+}
+
+/// Finds the activated listener fd of the listen address.
+pub fn find_activated_fd(
+    listeners: &[ActivatedListener],
+    addr: &str,
+) -> Option<RawFd> {
+    let addr = addr.trim().to_socket_addrs().ok()?.next()?;
+    listeners
+        .iter()
+        .find(|item| is_addr_matched(&item.addr, &addr))
+        .map(|item| item.fd)
+}
+
+/// Sends the state to systemd by `NOTIFY_SOCKET`, it returns false
+/// if the process isn't started by systemd with notify type.
+pub fn sd_notify(state: &str) -> io::Result<bool> {
+    let Ok(path) = std::env::var("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    // the socket of upgrade command is removed after the upgrade is done
+    if !path.starts_with('@') && !std::path::Path::new(&path).exists() {
+        return Ok(false);
+    }
+    let socket = UnixDatagram::unbound()?;
+    // the socket of abstract namespace starts with `@`
+    if let Some(name) = path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr =
+                std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract socket is not supported",
+            ));
+        }
+    } else {
+        socket.send_to(state.as_bytes(), &path)?;
+    }
+    debug!(state, "sd notify success");
+    Ok(true)
+}
+
+fn notify(state: &str) {
+    if let Err(e) = sd_notify(state) {
+        error!(error = e.to_string(), state, "sd notify fail");
+    }
+}
+
+/// Notifies systemd that the service is ready, the main pid is sent
+/// because the process is changed after restart.
+pub fn notify_ready() {
+    notify(&format!("READY=1\nMAINPID={}", std::process::id()));
+}
+
+/// Gets the current time of monotonic clock.
+fn get_monotonic_usec() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000
+}
+
+/// Notifies systemd that the service is reloading, `READY=1` should be
+/// sent after the reloading is done.
+pub fn notify_reloading() {
+    notify(&format!(
+        "RELOADING=1\nMONOTONIC_USEC={}",
+        get_monotonic_usec()
+    ));
+}
+
+/// Notifies systemd that the service is stopping.
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+/// Sends the keep-alive ping of watchdog.
+pub fn notify_watchdog() {
+    notify("WATCHDOG=1");
+}
+
+/// Gets the interval of watchdog ping, it's half of `WATCHDOG_USEC`.
+pub fn get_watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC")
+        .ok()?
+        .parse::<u64>()
+        .ok()
+        .filter(|value| *value > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        find_activated_fd, parse_listen_fds, sd_notify, ActivatedListener,
+    };
+    use pretty_assertions::assert_eq;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_parse_listen_fds() {
+        assert_eq!(2, parse_listen_fds(Some("100"), Some("2"), 100));
+        assert_eq!(0, parse_listen_fds(Some("101"), Some("2"), 100));
+        assert_eq!(0, parse_listen_fds(None, Some("2"), 100));
+        assert_eq!(0, parse_listen_fds(Some("100"), None, 100));
+    }
+
+    #[test]
+    fn test_find_activated_fd() {
+        let listeners = vec![
+            ActivatedListener {
+                addr: "[::]:80".parse().unwrap(),
+                fd: 3,
+            },
+            ActivatedListener {
+                addr: "127.0.0.1:6188".parse().unwrap(),
+                fd: 4,
+            },
+        ];
+        assert_eq!(Some(3), find_activated_fd(&listeners, "0.0.0.0:80"));
+        assert_eq!(Some(4), find_activated_fd(&listeners, "127.0.0.1:6188"));
+        assert_eq!(None, find_activated_fd(&listeners, "127.0.0.1:80"));
+        assert_eq!(None, find_activated_fd(&listeners, "0.0.0.0:443"));
+    }
+
+    #[test]
+    fn test_sd_notify() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("notify.sock");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        std::env::set_var("NOTIFY_SOCKET", &path);
+        assert_eq!(true, sd_notify("READY=1").unwrap());
+        std::env::remove_var("NOTIFY_SOCKET");
+        let mut buf = [0; 32];
+        let size = socket.recv(&mut buf).unwrap();
+        assert_eq!(b"READY=1", &buf[..size]);
+        assert_eq!(false, sd_notify("READY=1").unwrap());
+    }
+}
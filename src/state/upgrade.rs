@@ -0,0 +1,354 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::sd_notify;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use snafu::Snafu;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+// the exit code of preflight when the listeners can't be bound
+const EXIT_CODE_LISTENER: i32 = 69;
+const UPGRADE_STEPS: usize = 6;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Io error {source}"))]
+    Io { source: std::io::Error },
+    #[snafu(display("Invalid error {message}"))]
+    Invalid { message: String },
+    #[snafu(display("Upgrade fail {message}, rollback {rollback}"))]
+    Rollback { message: String, rollback: String },
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The params of upgrading the running process to the new binary.
+#[derive(Debug, Clone, Default)]
+pub struct UpgradeParams {
+    // the new binary
+    pub binary: PathBuf,
+    // the binary which is started if the new one fails
+    pub rollback_binary: PathBuf,
+    pub conf: String,
+    // the args of new process, the config and upgrade flag are excluded
+    pub args: Vec<String>,
+    pub pid_file: String,
+    pub ready_timeout: Duration,
+}
+
+fn progress(step: usize, message: &str) {
+    println!("[{step}/{UPGRADE_STEPS}] {message}");
+}
+
+/// Parses the state of sd_notify, returns whether it's ready
+/// and the main pid.
+fn parse_notify_state(state: &str) -> (bool, Option<u32>) {
+    let mut ready = false;
+    let mut pid = None;
+    for line in state.lines() {
+        match line.split_once('=') {
+            Some(("READY", "1")) => ready = true,
+            Some(("MAINPID", value)) => pid = value.parse::<u32>().ok(),
+            _ => {},
+        };
+    }
+    (ready, pid)
+}
+
+fn read_pid(pid_file: &str) -> Option<u32> {
+    std::fs::read_to_string(pid_file)
+        .ok()?
+        .trim()
+        .parse::<u32>()
+        .ok()
+}
+
+fn is_process_alive(pid: u32) -> bool {
+    kill(Pid::from_raw(pid as i32), None).is_ok()
+}
+
+fn send_signal(pid: u32, signal: Signal) -> Result<()> {
+    kill(Pid::from_raw(pid as i32), signal).map_err(|e| Error::Invalid {
+        message: format!("send {signal} to {pid} fail: {e}"),
+    })
+}
+
+/// The socket which receives the readiness of new process,
+/// it's passed to the new process as `NOTIFY_SOCKET`.
+struct NotifySocket {
+    _dir: tempfile::TempDir,
+    path: PathBuf,
+    socket: UnixDatagram,
+}
+
+impl NotifySocket {
+    fn new() -> Result<Self> {
+        let dir = tempfile::tempdir().map_err(|e| Error::Io { source: e })?;
+        let path = dir.path().join("notify.sock");
+        let socket =
+            UnixDatagram::bind(&path).map_err(|e| Error::Io { source: e })?;
+        // the new process may switch to the normal user before it's ready
+        for (file, mode) in [(dir.path(), 0o755), (path.as_path(), 0o777)] {
+            std::fs::set_permissions(file, PermissionsExt::from_mode(mode))
+                .map_err(|e| Error::Io { source: e })?;
+        }
+        socket
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .map_err(|e| Error::Io { source: e })?;
+        Ok(Self {
+            _dir: dir,
+            path,
+            socket,
+        })
+    }
+    /// Waits for the ready state of process, the process of command exits
+    /// after daemonizing, so only the failure exit is treated as error.
+    fn wait_ready(&self, child: &mut Child, timeout: Duration) -> Result<u32> {
+        let started_at = Instant::now();
+        let mut buf = [0; 1024];
+        while started_at.elapsed() < timeout {
+            if let Ok(size) = self.socket.recv(&mut buf) {
+                let state = String::from_utf8_lossy(&buf[..size]);
+                let (ready, pid) = parse_notify_state(&state);
+                if ready {
+                    return Ok(pid.unwrap_or(child.id()));
+                }
+            }
+            if let Ok(Some(status)) = child.try_wait() {
+                if !status.success() {
+                    return Err(Error::Invalid {
+                        message: format!("process exits with {status}"),
+                    });
+                }
+            }
+        }
+        Err(Error::Invalid {
+            message: format!(
+                "process isn't ready in {}",
+                humantime::format_duration(timeout)
+            ),
+        })
+    }
+}
+
+/// Validates the config with the new binary, the output of preflight
+/// checks is returned if it fails.
+fn validate_config(binary: &Path, conf: &str) -> Result<()> {
+    let output = Command::new(binary)
+        .arg(format!("-c={conf}"))
+        .arg("-t")
+        .output()
+        .map_err(|e| Error::Io { source: e })?;
+    if !output.status.success() {
+        return Err(Error::Invalid {
+            message: format!(
+                "validate config fail({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stdout).trim()
+            ),
+        });
+    }
+    Ok(())
+}
+
+fn spawn_process(
+    binary: &Path,
+    params: &UpgradeParams,
+    notify_socket: &NotifySocket,
+    upgrade: bool,
+) -> Result<Child> {
+    let mut cmd = Command::new(binary);
+    cmd.arg(format!("-c={}", params.conf))
+        .args(&params.args)
+        .env("NOTIFY_SOCKET", &notify_socket.path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if upgrade {
+        cmd.arg("-u");
+    }
+    cmd.spawn().map_err(|e| Error::Io { source: e })
+}
+
+/// Gets the pid of new process, it's the pid of command before
+/// daemonizing, otherwise it's written to the pid file.
+fn get_new_pid(child: &mut Child, pid_file: &str, old_pid: u32) -> Option<u32> {
+    if let Ok(None) = child.try_wait() {
+        return Some(child.id());
+    }
+    read_pid(pid_file).filter(|pid| *pid != old_pid && is_process_alive(*pid))
+}
+
+/// Rolls back to the binary of params. If the new process is alive, the
+/// listeners are transferred back from it, otherwise the rollback process
+/// binds the listeners after the old process releases them.
+fn rollback(
+    params: &UpgradeParams,
+    notify_socket: &NotifySocket,
+    new_pid: Option<u32>,
+) -> Result<u32> {
+    if let Some(pid) = new_pid {
+        println!("rollback: transfer listeners back from process({pid})");
+        let mut child = spawn_process(
+            &params.rollback_binary,
+            params,
+            notify_socket,
+            true,
+        )?;
+        send_signal(pid, Signal::SIGQUIT)?;
+        return notify_socket.wait_ready(&mut child, params.ready_timeout);
+    }
+    let started_at = Instant::now();
+    loop {
+        println!("rollback: start process with the listeners bound again");
+        let mut child = spawn_process(
+            &params.rollback_binary,
+            params,
+            notify_socket,
+            false,
+        )?;
+        let result = notify_socket.wait_ready(&mut child, params.ready_timeout);
+        let listener_in_use = child
+            .try_wait()
+            .ok()
+            .flatten()
+            .and_then(|status| status.code())
+            == Some(EXIT_CODE_LISTENER);
+        // the old process is draining and holds the listeners,
+        // so it's tried again until timeout
+        if result.is_err()
+            && listener_in_use
+            && started_at.elapsed() < params.ready_timeout
+        {
+            std::thread::sleep(Duration::from_secs(1));
+            continue;
+        }
+        return result;
+    }
+}
+
+/// Upgrades the running process to the new binary: the config is validated
+/// by the new binary, the new process is spawned in upgrade mode, the
+/// listeners are transferred from the old process over the upgrade sock,
+/// and the old process drains after the new one is ready. It's rolled back
+/// if the new process isn't ready, the pid of running process is returned.
+pub fn upgrade_binary(params: &UpgradeParams) -> Result<u32> {
+    progress(
+        1,
+        &format!("validate config by {}", params.binary.display()),
+    );
+    validate_config(&params.binary, &params.conf)?;
+
+    let old_pid = read_pid(&params.pid_file)
+        .filter(|pid| is_process_alive(*pid))
+        .ok_or_else(|| Error::Invalid {
+            message: format!(
+                "running process is not found, pid file: {}",
+                params.pid_file
+            ),
+        })?;
+    progress(2, &format!("running process: {old_pid}"));
+
+    let notify_socket = NotifySocket::new()?;
+    let mut child =
+        spawn_process(&params.binary, params, &notify_socket, true)?;
+    progress(3, &format!("spawn new process: {}", child.id()));
+
+    send_signal(old_pid, Signal::SIGQUIT)?;
+    progress(4, "transfer listeners over upgrade sock");
+
+    let pid = match notify_socket.wait_ready(&mut child, params.ready_timeout) {
+        Ok(pid) => pid,
+        Err(e) => {
+            println!("new process fails: {e}");
+            let new_pid = get_new_pid(&mut child, &params.pid_file, old_pid);
+            return match rollback(params, &notify_socket, new_pid) {
+                Ok(pid) => Err(Error::Rollback {
+                    message: e.to_string(),
+                    rollback: format!("success, pid: {pid}"),
+                }),
+                Err(err) => Err(Error::Rollback {
+                    message: e.to_string(),
+                    rollback: format!("fail: {err}"),
+                }),
+            };
+        },
+    };
+    progress(5, &format!("new process is ready: {pid}"));
+    // the main pid of systemd is changed to the new process
+    let _ = sd_notify(&format!("READY=1\nMAINPID={pid}"));
+
+    let mut count = 0;
+    while is_process_alive(old_pid) {
+        if count % 10 == 0 {
+            println!("old process({old_pid}) is draining");
+        }
+        count += 1;
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    progress(6, &format!("old process({old_pid}) exits"));
+    Ok(pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_notify_state, read_pid, NotifySocket};
+    use pretty_assertions::assert_eq;
+    use std::os::unix::net::UnixDatagram;
+    use std::process::Command;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_notify_state() {
+        assert_eq!(
+            (true, Some(123)),
+            parse_notify_state("READY=1\nMAINPID=123")
+        );
+        assert_eq!((false, None), parse_notify_state("RELOADING=1"));
+        assert_eq!((true, None), parse_notify_state("READY=1"));
+    }
+
+    #[test]
+    fn test_notify_socket() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("pingap.pid");
+        std::fs::write(&file, "123\n").unwrap();
+        assert_eq!(Some(123), read_pid(&file.to_string_lossy()));
+
+        let notify_socket = NotifySocket::new().unwrap();
+        let mut child = Command::new("sleep").arg("1").spawn().unwrap();
+        UnixDatagram::unbound()
+            .unwrap()
+            .send_to(b"READY=1\nMAINPID=456", &notify_socket.path)
+            .unwrap();
+        assert_eq!(
+            456,
+            notify_socket
+                .wait_ready(&mut child, Duration::from_secs(1))
+                .unwrap()
+        );
+
+        let mut child = Command::new("false").spawn().unwrap();
+        assert_eq!(
+            true,
+            notify_socket
+                .wait_ready(&mut child, Duration::from_secs(1))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ipnet::IpNet;
+use std::net::{AddrParseError, IpAddr};
+use std::str::FromStr;
+
+/// Ip rules of ip address and cidr, e.g. `192.168.1.1` or `10.0.0.0/8`.
+#[derive(Debug, Clone, Default)]
+pub struct IpRules {
+    pub(crate) ip_net_list: Vec<IpNet>,
+    pub(crate) ip_list: Vec<String>,
+}
+
+impl IpRules {
+    pub fn new(values: &[String]) -> Self {
+        let mut ip_net_list = vec![];
+        let mut ip_list = vec![];
+        for item in values {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            if let Ok(value) = IpNet::from_str(item) {
+                ip_net_list.push(value);
+            } else {
+                ip_list.push(item.to_string());
+            }
+        }
+        Self {
+            ip_net_list,
+            ip_list,
+        }
+    }
+    /// Returns `true` if there is no rule.
+    pub fn is_empty(&self) -> bool {
+        self.ip_net_list.is_empty() && self.ip_list.is_empty()
+    }
+    /// Returns `true` if the ip is in the list or matches a cidr,
+    /// error is returned if the ip is invalid.
+    pub fn is_match(&self, ip: &str) -> Result<bool, AddrParseError> {
+        if self.ip_list.iter().any(|item| item == ip) {
+            return Ok(true);
+        }
+        let addr = ip.parse::<IpAddr>()?;
+        Ok(self.ip_net_list.iter().any(|item| item.contains(&addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IpRules;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_ip_rules() {
+        let rules = IpRules::new(&[
+            "192.168.1.1".to_string(),
+            "10.0.0.0/8".to_string(),
+            "".to_string(),
+        ]);
+        assert_eq!(false, rules.is_empty());
+        assert_eq!(true, rules.is_match("192.168.1.1").unwrap());
+        assert_eq!(true, rules.is_match("10.1.1.1").unwrap());
+        assert_eq!(false, rules.is_match("192.168.1.2").unwrap());
+        assert_eq!(true, rules.is_match("abc").is_err());
+
+        assert_eq!(true, IpRules::new(&[]).is_empty());
+    }
+}
@@ -22,6 +22,11 @@ use pingora::{http::RequestHeader, proxy::Session};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{path::Path, str::FromStr};
 use substring::Substring;
+use tracing::error;
+
+mod ip;
+
+pub use ip::IpRules;
 
 const NAME: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -93,6 +98,51 @@ pub fn get_client_ip(session: &Session) -> String {
     "".to_string()
 }
 
+/// Resolves the client ip which can't be forged, the `X-Forwarded-For` is
+/// only used if the remote addr is a trusted proxy, and it's resolved from
+/// right to left until the address isn't a trusted proxy.
+fn resolve_trusted_client_ip(
+    remote_addr: Option<&str>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &IpRules,
+) -> String {
+    let Some(mut ip) = remote_addr.map(|value| value.to_string()) else {
+        return "".to_string();
+    };
+    let is_trusted =
+        |value: &str| trusted_proxies.is_match(value).unwrap_or_default();
+    if !is_trusted(&ip) {
+        return ip;
+    }
+    for item in forwarded_for.unwrap_or_default().split(',').rev() {
+        let item = item.trim();
+        if item.parse::<std::net::IpAddr>().is_err() {
+            break;
+        }
+        ip = item.to_string();
+        if !is_trusted(item) {
+            break;
+        }
+    }
+    ip
+}
+
+/// Gets the client ip which can't be forged by the request headers,
+/// the `X-Forwarded-For` is only trusted if it's set by trusted proxies.
+pub fn get_trusted_client_ip(
+    session: &Session,
+    trusted_proxies: &IpRules,
+) -> String {
+    let forwarded_for = session
+        .get_header(HTTP_HEADER_X_FORWARDED_FOR.clone())
+        .and_then(|value| value.to_str().ok());
+    resolve_trusted_client_ip(
+        get_remote_addr(session).as_deref(),
+        forwarded_for,
+        trusted_proxies,
+    )
+}
+
 /// Gets string value from req header.
 pub fn get_req_header_value<'a>(
     req_header: &'a RequestHeader,
@@ -114,7 +164,7 @@ pub fn get_cookie_value<'a>(
     if let Some(cookie_value) = get_req_header_value(req_header, "Cookie") {
         for item in cookie_value.split(';') {
             if let Some((k, v)) = item.split_once('=') {
-                if k == cookie_name {
+                if k.trim() == cookie_name {
                     return Some(v.trim());
                 }
             }
@@ -183,6 +233,12 @@ pub fn is_pem(value: &str) -> bool {
     value.starts_with("-----")
 }
 
+/// Test whether or not the string is the path of pem file,
+/// the base64 of pem starts with `LS0t` and never contains `.` or `~`.
+pub fn is_pem_file(value: &str) -> bool {
+    value.starts_with('/') || value.starts_with('~') || value.contains('.')
+}
+
 // 2022-05-07: 1651852800
 // const SUPER_TIMESTAMP: u64 = 1651852800;
 static SUPER_TIMESTAMP: Lazy<SystemTime> = Lazy::new(|| {
@@ -233,6 +289,31 @@ pub fn get_host(header: &RequestHeader) -> Option<&str> {
     None
 }
 
+/// Split the address to host and port, the ipv6 address should be
+/// bracketed if it has port, e.g. `[::1]:8080`.
+pub fn split_host_port(addr: &str) -> (&str, Option<&str>) {
+    if let Some(value) = addr.strip_prefix('[') {
+        if let Some((host, rest)) = value.split_once(']') {
+            return (host, rest.strip_prefix(':').filter(|v| !v.is_empty()));
+        }
+    }
+    match addr.split_once(':') {
+        // more than one colon is an ipv6 address without port
+        Some((_, port)) if port.contains(':') => (addr, None),
+        Some((host, port)) => (host, Some(port)),
+        None => (addr, None),
+    }
+}
+
+/// Join the host and port, the ipv6 address will be bracketed.
+pub fn join_host_port(host: &str, port: &str) -> String {
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
 /// Get the content length from http request header.
 pub fn get_content_length(header: &RequestHeader) -> Option<usize> {
     if let Some(content_length) =
@@ -269,14 +350,26 @@ pub fn convert_tls_version(version: &Option<String>) -> Option<SslVersion> {
     None
 }
 
+/// Converts the certificate to pem bytes, the value can be pem,
+/// the path of pem file or the base64 of pem. The config has been
+/// validated, so the error is only logged and the empty bytes are returned.
 pub fn convert_certificate_bytes(value: &Option<String>) -> Option<Vec<u8>> {
     if let Some(value) = value {
         if is_pem(value) {
             return Some(value.as_bytes().to_vec());
-        } else {
-            let buf = STANDARD.decode(value).unwrap_or_default();
-            return Some(buf);
         }
+        let result = if is_pem_file(value) {
+            std::fs::read(resolve_path(value)).map_err(|e| e.to_string())
+        } else {
+            STANDARD.decode(value).map_err(|e| e.to_string())
+        };
+        return match result {
+            Ok(buf) => Some(buf),
+            Err(e) => {
+                error!(error = e, "convert certificate fail");
+                Some(vec![])
+            },
+        };
     }
     None
 }
@@ -343,9 +436,10 @@ pub fn format_duration(mut buf: BytesMut, ms: u64) -> BytesMut {
 #[cfg(test)]
 mod tests {
     use super::{
-        convert_tls_version, format_byte_size, format_duration, get_latency,
-        get_pkg_name, get_pkg_version, local_ip_list, remove_query_from_header,
-        resolve_path,
+        convert_certificate_bytes, convert_tls_version, format_byte_size,
+        format_duration, get_latency, get_pkg_name, get_pkg_version,
+        is_pem_file, join_host_port, local_ip_list, remove_query_from_header,
+        resolve_path, resolve_trusted_client_ip, split_host_port, IpRules,
     };
     use bytes::BytesMut;
     use pingora::{http::RequestHeader, tls::ssl::SslVersion};
@@ -364,6 +458,65 @@ mod tests {
         assert_eq!("/?name=pingap", req.uri.to_string());
     }
 
+    #[test]
+    fn test_resolve_trusted_client_ip() {
+        let trusted = IpRules::new(&["10.0.0.0/8".to_string()]);
+        // the forged header of untrusted client is ignored
+        assert_eq!(
+            "1.1.1.1",
+            resolve_trusted_client_ip(
+                Some("1.1.1.1"),
+                Some("2.2.2.2"),
+                &trusted
+            )
+        );
+        assert_eq!(
+            "1.1.1.1",
+            resolve_trusted_client_ip(
+                Some("1.1.1.1"),
+                Some("2.2.2.2"),
+                &IpRules::default()
+            )
+        );
+        // the address before the trusted proxies is the client
+        assert_eq!(
+            "3.3.3.3",
+            resolve_trusted_client_ip(
+                Some("10.0.0.1"),
+                Some("2.2.2.2, 3.3.3.3, 10.0.0.2"),
+                &trusted
+            )
+        );
+        assert_eq!(
+            "10.0.0.1",
+            resolve_trusted_client_ip(Some("10.0.0.1"), None, &trusted)
+        );
+        assert_eq!(
+            "10.0.0.2",
+            resolve_trusted_client_ip(
+                Some("10.0.0.1"),
+                Some("unknown, 10.0.0.2"),
+                &trusted
+            )
+        );
+        assert_eq!(
+            "",
+            resolve_trusted_client_ip(None, Some("2.2.2.2"), &trusted)
+        );
+    }
+
+    #[test]
+    fn test_split_join_host_port() {
+        assert_eq!(("127.0.0.1", Some("80")), split_host_port("127.0.0.1:80"));
+        assert_eq!(("pingap.io", None), split_host_port("pingap.io"));
+        assert_eq!(("::1", None), split_host_port("::1"));
+        assert_eq!(("::1", Some("8080")), split_host_port("[::1]:8080"));
+        assert_eq!(("::1", None), split_host_port("[::1]"));
+
+        assert_eq!("127.0.0.1:80", join_host_port("127.0.0.1", "80"));
+        assert_eq!("[::1]:8080", join_host_port("::1", "8080"));
+    }
+
     #[test]
     fn test_get_pkg_info() {
         assert_eq!("pingap", get_pkg_name());
@@ -399,6 +552,29 @@ mod tests {
             convert_tls_version(&Some("tlsv1.3".to_string())).unwrap()
         );
     }
+    #[test]
+    fn test_convert_certificate_bytes() {
+        assert_eq!(true, is_pem_file("/opt/pingap/cert.pem"));
+        assert_eq!(true, is_pem_file("~/certs/key"));
+        assert_eq!(true, is_pem_file("certs/pingap.crt"));
+        assert_eq!(false, is_pem_file("LS0tLS1CRUdJTg=="));
+
+        assert_eq!(None, convert_certificate_bytes(&None));
+        assert_eq!(
+            Some(b"-----BEGIN".to_vec()),
+            convert_certificate_bytes(&Some("LS0tLS1CRUdJTg==".to_string()))
+        );
+        // the invalid base64 is converted to empty bytes
+        assert_eq!(
+            Some(vec![]),
+            convert_certificate_bytes(&Some("ab".to_string()))
+        );
+        assert_eq!(
+            Some(vec![]),
+            convert_certificate_bytes(&Some("/not-exists.pem".to_string()))
+        );
+    }
+
     #[test]
     fn test_local_ip_list() {
         assert_eq!(false, local_ip_list().is_empty());
@@ -46,6 +46,10 @@ pub enum NotificationCategory {
     TlsValidity,
     ParseCertificateFail,
     ServiceDiscoverFail,
+    ServiceDiscoverChange,
+    Overload,
+    Honeypot,
+    Anomaly,
 }
 
 impl Display for NotificationLevel {
@@ -66,6 +70,15 @@ pub struct SendNotificationParams {
 }
 
 pub fn send(params: SendNotificationParams) {
+    send_with_details(params, None);
+}
+
+/// Sends the notification with the details, the details are added
+/// as `details` field of the normal webhook.
+pub fn send_with_details(
+    params: SendNotificationParams,
+    details: Option<Value>,
+) {
     info!(
         category = params.category.to_string(),
         message = params.msg,
@@ -166,6 +179,9 @@ pub fn send(params: SendNotificationParams) {
                             "message".to_string(),
                             Value::String(params.msg),
                         );
+                        if let Some(details) = details {
+                            data.insert("details".to_string(), details);
+                        }
                     },
                 }
 
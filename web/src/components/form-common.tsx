@@ -23,6 +23,8 @@ export enum FormItemCategory {
   HEADERS = "headers",
   PROXY_ADD_HEADERS = "proxyAddHeaders",
   PROXY_SET_HEADERS = "proxySetHeaders",
+  CGI_PARAMS = "cgiParams",
+  SCHEDULE_VALUES = "scheduleValues",
   WEBHOOK_TYPE = "webhookType",
   WEBHOOK_NOTIFICATIONS = "webhookNotifications",
   PLUGIN = "plugin",
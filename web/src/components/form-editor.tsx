@@ -304,6 +304,38 @@ export default function FormEditor({
         );
         break;
       }
+      case FormItemCategory.CGI_PARAMS: {
+        formItem = (
+          <FormTwoInputFields
+            id={item.id}
+            divide={" "}
+            values={item.defaultValue as string[]}
+            label={t("form.cgiParamName")}
+            valueLabel={t("form.cgiParamValue")}
+            onUpdate={(data) => {
+              updateValue(item.id, data);
+            }}
+            addLabel={item.label}
+          />
+        );
+        break;
+      }
+      case FormItemCategory.SCHEDULE_VALUES: {
+        formItem = (
+          <FormTwoInputFields
+            id={item.id}
+            divide={" = "}
+            values={item.defaultValue as string[]}
+            label={t("form.scheduleValueName")}
+            valueLabel={t("form.scheduleValueValue")}
+            onUpdate={(data) => {
+              updateValue(item.id, data);
+            }}
+            addLabel={item.label}
+          />
+        );
+        break;
+      }
       case FormItemCategory.PLUGIN: {
         const category = (data["category"] as string) || "";
         formItem = (
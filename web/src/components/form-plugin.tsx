@@ -30,11 +30,18 @@ export enum PluginCategory {
   REDIRECT = "redirect",
   PING = "ping",
   RESPONSE_HEADERS = "response_headers",
+  SECURITY_HEADERS = "security_headers",
+  BOT_DETECTION = "bot_detection",
+  REQUEST_SIGN = "request_sign",
   REFERER_RESTRICTION = "referer_restriction",
   CSRF = "csrf",
   CORS = "cors",
   OWASP_CRS_PLUGIN = "owasp_crs_plugin",
   WIREFILTER_PLUGIN = "wirefilter_plugin",
+  COALESCE = "coalesce",
+  HONEYPOT = "honeypot",
+  COOKIE_FILTER = "cookie_filter",
+  AB_TEST = "ab_test",
 }
 
 export function getPluginSteps(category: string) {
@@ -72,11 +79,18 @@ export function getPluginSteps(category: string) {
   pluginSupportSteps[PluginCategory.REDIRECT] = [0];
   pluginSupportSteps[PluginCategory.PING] = [0];
   pluginSupportSteps[PluginCategory.RESPONSE_HEADERS] = [2];
+  pluginSupportSteps[PluginCategory.SECURITY_HEADERS] = [2];
+  pluginSupportSteps[PluginCategory.BOT_DETECTION] = [0, 1];
+  pluginSupportSteps[PluginCategory.REQUEST_SIGN] = [1];
   pluginSupportSteps[PluginCategory.REFERER_RESTRICTION] = [0, 1];
   pluginSupportSteps[PluginCategory.CSRF] = [0];
   pluginSupportSteps[PluginCategory.CORS] = [0, 1];
   pluginSupportSteps[PluginCategory.OWASP_CRS_PLUGIN] = [0, 1];
   pluginSupportSteps[PluginCategory.WIREFILTER_PLUGIN] = [0, 1];
+  pluginSupportSteps[PluginCategory.COALESCE] = [0];
+  pluginSupportSteps[PluginCategory.HONEYPOT] = [0, 1];
+  pluginSupportSteps[PluginCategory.COOKIE_FILTER] = [1];
+  pluginSupportSteps[PluginCategory.AB_TEST] = [0, 1];
 
   const steps = pluginSupportSteps[category];
   if (steps) {
@@ -135,6 +149,34 @@ export function FormPluginField({
     },
   ];
 
+  // the block action of waf, acl and limit plugins
+  const newBlockActionFields = (id: string) => {
+    return [
+      {
+        category: "select" as const,
+        key: "block_action",
+        label: t("form.blockAction"),
+        id: `${id}-block-action`,
+        span: 4,
+        options: ["deny", "drop", "tarpit"],
+      },
+      {
+        category: "text" as const,
+        key: "tarpit_duration",
+        label: t("form.tarpitDuration"),
+        id: `${id}-tarpit-duration`,
+        span: 4,
+      },
+      {
+        category: "number" as const,
+        key: "tarpit_max",
+        label: t("form.tarpitMax"),
+        id: `${id}-tarpit-max`,
+        span: 4,
+      },
+    ];
+  };
+
   switch (category) {
     case PluginCategory.LIMIT: {
       fields.push(
@@ -152,7 +194,15 @@ export function FormPluginField({
           label: t("form.limitTag"),
           id: "limit-tag",
           span: 6,
-          options: ["cookie", "header", "query", "ip"],
+          options: [
+            "cookie",
+            "header",
+            "query",
+            "variable",
+            "method",
+            "path",
+            "ip",
+          ],
         },
         {
           category: "text",
@@ -175,6 +225,37 @@ export function FormPluginField({
           id: "limit-interval",
           span: 6,
         },
+        {
+          category: "textlist",
+          key: "keys",
+          label: t("form.limitKeys"),
+          addLabel: t("form.limitKeysAdd"),
+          id: "limit-keys",
+          span: 6,
+        },
+        {
+          category: "textlist",
+          key: "rates",
+          label: t("form.limitRates"),
+          addLabel: t("form.limitRatesAdd"),
+          id: "limit-rates",
+          span: 6,
+        },
+        {
+          category: "number",
+          key: "status",
+          label: t("form.limitStatus"),
+          id: "limit-status",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "message",
+          label: t("form.limitMessage"),
+          id: "limit-message",
+          span: 12,
+        },
+        ...newBlockActionFields("limit"),
       );
       break;
     }
@@ -237,6 +318,51 @@ export function FormPluginField({
           addLabel: t("form.adminAuthorizationAdd"),
           span: 12,
         },
+        {
+          category: "textlist",
+          key: "observer_tokens",
+          label: t("form.adminObserverTokens"),
+          id: "admin-observer-tokens",
+          addLabel: t("form.adminObserverTokensAdd"),
+          span: 12,
+        },
+        {
+          category: "checkbox",
+          key: "read_only",
+          label: t("form.adminReadOnly"),
+          id: "admin-read-only",
+          options: boolOptions,
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "session_ttl",
+          label: t("form.adminSessionTtl"),
+          id: "admin-session-ttl",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "session_idle_timeout",
+          label: t("form.adminSessionIdleTimeout"),
+          id: "admin-session-idle-timeout",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "session_rotate_interval",
+          label: t("form.adminSessionRotateInterval"),
+          id: "admin-session-rotate-interval",
+          span: 6,
+        },
+        {
+          category: "checkbox",
+          key: "session_ip_pinning",
+          label: t("form.adminSessionIpPinning"),
+          id: "admin-session-ip-pinning",
+          options: boolOptions,
+          span: 6,
+        },
       );
       break;
     }
@@ -378,6 +504,7 @@ export function FormPluginField({
           id: "ip-restriction-message",
           span: 12,
         },
+        ...newBlockActionFields("ip-restriction"),
       );
       break;
     }
@@ -417,6 +544,7 @@ export function FormPluginField({
           id: "referer-restriction-message",
           span: 12,
         },
+        ...newBlockActionFields("referer-restriction"),
       );
       break;
     }
@@ -601,6 +729,33 @@ export function FormPluginField({
       );
       break;
     }
+    case PluginCategory.COALESCE: {
+      fields.push(
+        {
+          category: "textlist",
+          key: "vary_headers",
+          label: t("form.coalesceVaryHeaders"),
+          addLabel: t("form.coalesceVaryHeadersAdd"),
+          id: "coalesce-vary-headers",
+          span: 12,
+        },
+        {
+          category: "text",
+          key: "timeout",
+          label: t("form.coalesceTimeout"),
+          id: "coalesce-timeout",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "max_body_size",
+          label: t("form.coalesceMaxBodySize"),
+          id: "coalesce-max-body-size",
+          span: 6,
+        },
+      );
+      break;
+    }
     case PluginCategory.MOCK: {
       fields.push(
         {
@@ -634,6 +789,57 @@ export function FormPluginField({
           id: "mock-data",
           span: 12,
         },
+        {
+          category: "textlist",
+          key: "routes",
+          label: t("form.mockRoutes"),
+          addLabel: t("form.mockRoutesAdd"),
+          id: "mock-routes",
+          span: 12,
+        },
+        {
+          category: "text",
+          key: "delay",
+          label: t("form.mockDelay"),
+          id: "mock-delay",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "jitter",
+          label: t("form.mockJitter"),
+          id: "mock-jitter",
+          span: 6,
+        },
+        {
+          category: "number",
+          key: "error_rate",
+          label: t("form.mockErrorRate"),
+          id: "mock-error-rate",
+          span: 6,
+        },
+        {
+          category: "number",
+          key: "error_status",
+          label: t("form.mockErrorStatus"),
+          id: "mock-error-status",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "error_data",
+          label: t("form.mockErrorData"),
+          id: "mock-error-data",
+          span: 6,
+        },
+        {
+          category: "checkbox",
+          key: "passthrough",
+          label: t("form.mockPassthrough"),
+          id: "mock-passthrough",
+          options: boolOptions,
+          span: 6,
+        },
       );
       break;
     }
@@ -706,6 +912,360 @@ export function FormPluginField({
       );
       break;
     }
+    case PluginCategory.BOT_DETECTION: {
+      fields.push(
+        {
+          category: "select",
+          key: "action",
+          label: t("form.botDetectionAction"),
+          id: "bot-detection-action",
+          span: 6,
+          options: ["tag", "limit", "block"],
+        },
+        {
+          category: "number",
+          key: "threshold",
+          label: t("form.botDetectionThreshold"),
+          id: "bot-detection-threshold",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "tag_header",
+          label: t("form.botDetectionTagHeader"),
+          id: "bot-detection-tag-header",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "message",
+          label: t("form.botDetectionMessage"),
+          id: "bot-detection-message",
+          span: 6,
+        },
+        {
+          category: "number",
+          key: "max",
+          label: t("form.botDetectionMax"),
+          id: "bot-detection-max",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "interval",
+          label: t("form.botDetectionInterval"),
+          id: "bot-detection-interval",
+          span: 6,
+        },
+        {
+          category: "textlist",
+          key: "user_agents",
+          label: t("form.botDetectionUserAgents"),
+          id: "bot-detection-user-agents",
+          span: 12,
+          divide: "",
+          addLabel: t("form.botDetectionUserAgentAdd"),
+        },
+        {
+          category: "textlist",
+          key: "blocked_fingerprints",
+          label: t("form.botDetectionBlockedFingerprints"),
+          id: "bot-detection-blocked-fingerprints",
+          span: 12,
+          divide: "",
+          addLabel: t("form.botDetectionFingerprintAdd"),
+        },
+        {
+          category: "textlist",
+          key: "allowed_fingerprints",
+          label: t("form.botDetectionAllowedFingerprints"),
+          id: "bot-detection-allowed-fingerprints",
+          span: 12,
+          divide: "",
+          addLabel: t("form.botDetectionFingerprintAdd"),
+        },
+        ...newBlockActionFields("bot-detection"),
+      );
+      break;
+    }
+    case PluginCategory.HONEYPOT: {
+      fields.push(
+        {
+          category: "textlist",
+          key: "paths",
+          label: t("form.honeypotPaths"),
+          addLabel: t("form.honeypotPathAdd"),
+          id: "honeypot-paths",
+          span: 12,
+        },
+        {
+          category: "text",
+          key: "ban_ttl",
+          label: t("form.honeypotBanTtl"),
+          id: "honeypot-ban-ttl",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "message",
+          label: t("form.honeypotMessage"),
+          id: "honeypot-message",
+          span: 6,
+        },
+        ...newBlockActionFields("honeypot"),
+      );
+      break;
+    }
+    case PluginCategory.COOKIE_FILTER: {
+      fields.push(
+        {
+          category: "textlist",
+          key: "drop_cookies",
+          label: t("form.cookieFilterDropCookies"),
+          addLabel: t("form.cookieFilterCookieAdd"),
+          id: "cookie-filter-drop-cookies",
+          span: 12,
+        },
+        {
+          category: "textlist",
+          key: "allow_cookies",
+          label: t("form.cookieFilterAllowCookies"),
+          addLabel: t("form.cookieFilterCookieAdd"),
+          id: "cookie-filter-allow-cookies",
+          span: 12,
+        },
+        {
+          category: "textlist",
+          key: "rename_cookies",
+          label: t("form.cookieFilterRenameCookies"),
+          addLabel: t("form.cookieFilterRenameCookieAdd"),
+          id: "cookie-filter-rename-cookies",
+          span: 12,
+        },
+        {
+          category: "text",
+          key: "scope_prefix",
+          label: t("form.cookieFilterScopePrefix"),
+          id: "cookie-filter-scope-prefix",
+          span: 6,
+        },
+      );
+      break;
+    }
+    case PluginCategory.AB_TEST: {
+      fields.push(
+        {
+          category: "text",
+          key: "experiment",
+          label: t("form.abTestExperiment"),
+          id: "ab-test-experiment",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "salt",
+          label: t("form.abTestSalt"),
+          id: "ab-test-salt",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "key",
+          label: t("form.abTestKey"),
+          id: "ab-test-key",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "header",
+          label: t("form.abTestHeader"),
+          id: "ab-test-header",
+          span: 6,
+        },
+        {
+          category: "textlist",
+          key: "buckets",
+          label: t("form.abTestBuckets"),
+          addLabel: t("form.abTestBucketAdd"),
+          id: "ab-test-buckets",
+          span: 12,
+        },
+      );
+      break;
+    }
+    case PluginCategory.REQUEST_SIGN: {
+      fields.push(
+        {
+          category: "select",
+          key: "algorithm",
+          label: t("form.requestSignAlgorithm"),
+          id: "request-sign-algorithm",
+          span: 6,
+          options: ["hmac", "aws_sigv4"],
+        },
+        {
+          category: "text",
+          key: "host",
+          label: t("form.requestSignHost"),
+          id: "request-sign-host",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "access_key_id",
+          label: "Access Key Id(aws_sigv4)",
+          id: "request-sign-access-key-id",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "secret_access_key",
+          label: "Secret Access Key(aws_sigv4)",
+          id: "request-sign-secret-access-key",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "session_token",
+          label: "Session Token(aws_sigv4)",
+          id: "request-sign-session-token",
+          span: 12,
+        },
+        {
+          category: "text",
+          key: "region",
+          label: "Region(aws_sigv4)",
+          id: "request-sign-region",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "service",
+          label: "Service(aws_sigv4)",
+          id: "request-sign-service",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "secret",
+          label: t("form.requestSignSecret"),
+          id: "request-sign-secret",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "key_id",
+          label: t("form.requestSignKeyId"),
+          id: "request-sign-key-id",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "header",
+          label: t("form.requestSignHeader"),
+          id: "request-sign-header",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "timestamp_header",
+          label: t("form.requestSignTimestampHeader"),
+          id: "request-sign-timestamp-header",
+          span: 6,
+        },
+        {
+          category: "textlist",
+          key: "signed_headers",
+          label: t("form.requestSignSignedHeaders"),
+          id: "request-sign-signed-headers",
+          span: 12,
+          divide: "",
+          addLabel: t("form.requestSignSignedHeadersAdd"),
+        },
+      );
+      break;
+    }
+    case PluginCategory.SECURITY_HEADERS: {
+      fields.push(
+        {
+          category: "select",
+          key: "preset",
+          label: t("form.securityHeadersPreset"),
+          id: "security-headers-preset",
+          span: 6,
+          options: ["basic", "strict"],
+        },
+        {
+          category: "checkbox",
+          key: "override",
+          label: t("form.securityHeadersOverride"),
+          id: "security-headers-override",
+          span: 6,
+          options: boolOptions,
+        },
+        {
+          category: "text",
+          key: "hsts",
+          label: "Strict-Transport-Security",
+          id: "security-headers-hsts",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "content_security_policy",
+          label: "Content-Security-Policy",
+          id: "security-headers-csp",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "x_frame_options",
+          label: "X-Frame-Options",
+          id: "security-headers-x-frame-options",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "x_content_type_options",
+          label: "X-Content-Type-Options",
+          id: "security-headers-x-content-type-options",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "referrer_policy",
+          label: "Referrer-Policy",
+          id: "security-headers-referrer-policy",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "permissions_policy",
+          label: "Permissions-Policy",
+          id: "security-headers-permissions-policy",
+          span: 6,
+        },
+        {
+          category: "textlist",
+          key: "disabled_headers",
+          label: t("form.securityHeadersDisabled"),
+          id: "security-headers-disabled-headers",
+          span: 12,
+          divide: "",
+          addLabel: t("form.securityHeadersDisabledAdd"),
+        },
+        {
+          category: "textlist",
+          key: "remove_headers",
+          label: t("form.responseHeadersRemoveHeaderName"),
+          id: "security-headers-remove-headers",
+          span: 12,
+          divide: "",
+          addLabel: t("form.responseHeadersRemove"),
+        },
+      );
+      break;
+    }
     case PluginCategory.CACHE: {
       fields.push(
         {
@@ -760,6 +1320,36 @@ export function FormPluginField({
           addLabel: t("form.cacheHeadersAdd"),
           span: 12,
         },
+        {
+          category: "text",
+          key: "bypass_header",
+          label: t("form.cacheBypassHeader"),
+          id: "cache-bypass-header",
+          span: 6,
+        },
+        {
+          category: "text",
+          key: "bypass_query",
+          label: t("form.cacheBypassQuery"),
+          id: "cache-bypass-query",
+          span: 6,
+        },
+        {
+          category: "select",
+          key: "range",
+          label: t("form.cacheRange"),
+          id: "cache-range",
+          span: 6,
+          options: ["fill", "partial"],
+        },
+        {
+          category: "textlist",
+          key: "bypass_ips",
+          label: t("form.cacheBypassIps"),
+          id: "cache-bypass-ips",
+          addLabel: t("form.cacheBypassIpsAdd"),
+          span: 12,
+        },
       );
       break;
     }
@@ -819,14 +1409,40 @@ export function FormPluginField({
       break;
     }
     default: {
-      fields.push({
-        category: "text",
-        key: "path",
-        label: t("form.statsPath"),
-        id: "stats-path",
-        span: 12,
-        required: true,
-      });
+      fields.push(
+        {
+          category: "text",
+          key: "path",
+          label: t("form.statsPath"),
+          id: "stats-path",
+          span: 12,
+          required: true,
+        },
+        {
+          category: "textlist",
+          key: "authorizations",
+          label: t("form.basicAuthList"),
+          id: "stats-authorizations",
+          addLabel: t("form.basicAuthAdd"),
+          span: 12,
+        },
+        {
+          category: "textlist",
+          key: "bearer_tokens",
+          label: t("form.statsBearerTokens"),
+          id: "stats-bearer-tokens",
+          addLabel: t("form.statsBearerTokenAdd"),
+          span: 12,
+        },
+        {
+          category: "textlist",
+          key: "ip_list",
+          label: t("form.ipList"),
+          id: "stats-ip-list",
+          addLabel: t("form.ipRestrictionAdd"),
+          span: 12,
+        },
+      );
       break;
     }
   }
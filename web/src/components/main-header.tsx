@@ -18,8 +18,9 @@ import DialogTitle from "@mui/material/DialogTitle";
 import CodeIcon from "@mui/icons-material/Code";
 import { useTranslation } from "react-i18next";
 import useBasicStore from "../states/basic";
+import useSessionStore from "../states/session";
 import request from "../helpers/request";
-import { goToTomlPrevew } from "../router";
+import { goToLogin, goToTomlPrevew } from "../router";
 import Logo from "../../assets/pingap.png";
 import Drawer from "@mui/material/Drawer";
 import MainNav from "./main-nav";
@@ -62,6 +63,11 @@ export default function MainHeader() {
     state.fetch,
     state.data,
   ]);
+  const [fetchSession, logout, sessionInfo] = useSessionStore((state) => [
+    state.fetch,
+    state.logout,
+    state.data,
+  ]);
   const [showSetting, setShowSetting] = React.useState(false);
   const [showRestartDialog, setShowRestartDialog] = React.useState(false);
   const [open, setOpen] = React.useState(false);
@@ -72,6 +78,8 @@ export default function MainHeader() {
 
   useAsync(async () => {
     try {
+      // 启用会话时获取csrf token
+      await fetchSession();
       await fetch();
     } catch (err) {
       console.error(err);
@@ -87,6 +95,16 @@ export default function MainHeader() {
       alert(err);
     }
   };
+  const confirmLogout = async () => {
+    try {
+      await logout();
+      setShowSetting(false);
+      goToLogin();
+    } catch (err) {
+      console.error(err);
+      alert(err);
+    }
+  };
   const box = (
     <React.Fragment>
       <Box
@@ -169,6 +187,18 @@ export default function MainHeader() {
               >
                 {t("header.restart")}
               </Button>
+              {sessionInfo.enabled && (
+                <Button
+                  style={{
+                    marginTop: "15px",
+                  }}
+                  fullWidth
+                  variant="outlined"
+                  onClick={confirmLogout}
+                >
+                  {t("header.logout")}
+                </Button>
+              )}
             </Box>
           </CardContent>
         </Card>
@@ -15,6 +15,7 @@ import AltRouteIcon from "@mui/icons-material/AltRoute";
 import ExtensionIcon from "@mui/icons-material/Extension";
 import Button from "@mui/material/Button";
 import SecurityIcon from "@mui/icons-material/Security";
+import ScheduleIcon from "@mui/icons-material/Schedule";
 import { useTranslation } from "react-i18next";
 import { useAsync } from "react-async-hook";
 import useConfigStore from "../states/config";
@@ -25,6 +26,7 @@ import {
   goToUpstreamInfo,
   goToPluginInfo,
   goToCertificateInfo,
+  goToScheduleInfo,
 } from "../router";
 import { formatError } from "../helpers/util";
 
@@ -35,6 +37,7 @@ enum NavCategory {
   UpstreamInfo,
   ProxyPluginInfo,
   CertificateInfo,
+  ScheduleInfo,
 }
 
 interface NavItem {
@@ -121,6 +124,15 @@ export default function MainNav({
         children: certificates,
         category: NavCategory.CertificateInfo,
       });
+
+      const schedules = Object.keys(config.schedules || {}).sort();
+      schedules.push(addTag);
+      items.push({
+        name: t("nav.schedule"),
+        icon: <ScheduleIcon />,
+        children: schedules,
+        category: NavCategory.ScheduleInfo,
+      });
       setNavItems(items);
     } catch (err) {
       setShowError({
@@ -210,6 +222,10 @@ export default function MainNav({
                   goToCertificateInfo(name);
                   break;
                 }
+                case NavCategory.ScheduleInfo: {
+                  goToScheduleInfo(name);
+                  break;
+                }
               }
             }}
           >
@@ -2,6 +2,14 @@ import axios, { AxiosRequestConfig, AxiosResponse } from "axios";
 import HTTPError from "./http-error";
 
 const requestedAt = "X-Requested-At";
+const csrfTokenHeader = "X-Pingap-Csrf-Token";
+// 会话的csrf token，修改类的请求需要设置
+let csrfToken = "";
+
+export function setCsrfToken(token: string) {
+  csrfToken = token;
+}
+
 const request = axios.create({
   // 默认超时为10秒
   timeout: 10 * 1000,
@@ -21,6 +29,9 @@ request.interceptors.request.use(
     config.url = `./api${config.url}`;
     if (config.headers) {
       config.headers[requestedAt] = `${Date.now()}`;
+      if (csrfToken && config.method != "get") {
+        config.headers[csrfTokenHeader] = csrfToken;
+      }
     }
     return config;
   },
@@ -79,6 +90,13 @@ request.interceptors.response.use(
       he.category = "timeout";
       he.message = "Request timeout";
     } else if (response) {
+      // 会话失效则跳转至登录页
+      if (
+        response.status === 401 &&
+        !response.config?.url?.endsWith("/login")
+      ) {
+        window.location.hash = "#/login";
+      }
       if (response.data && response.data.message) {
         he.message = response.data.message;
         he.category = response.data.category;
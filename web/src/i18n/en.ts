@@ -6,6 +6,7 @@ export default {
   "nav.upstream": "Upstream",
   "nav.plugin": "Plugin",
   "nav.certificate": "Certificate",
+  "nav.schedule": "Schedule",
   // header
   "header.title": "Informations",
   "header.startTime": "Start Time: ",
@@ -17,6 +18,12 @@ export default {
   "header.confirmTips": "Are you sure to restart pingap?",
   "header.confirm": "Restart",
   "header.cancel": "Cancel",
+  "header.logout": "Logout",
+  "login.title": "Login",
+  "login.account": "Account",
+  "login.password": "Password",
+  "login.submit": "Login",
+  "login.processing": "Logging in...",
   // basic info
   "basic.title": "Modify the basic configurations",
   "basic.description":
@@ -28,6 +35,7 @@ export default {
   "basic.workStealing": "Work Stealing",
   "basic.user": "User For Daemon",
   "basic.group": "Group For Daemon",
+  "basic.sandbox": "Sandbox(landlock)",
   "basic.gracePeriod": "Grace Period For Exit",
   "basic.gracefulShutdownTimeout": "Graceful Shutdown Timeout",
   "basic.logLevel": "Log Level",
@@ -37,12 +45,33 @@ export default {
   "basic.upstreamKeepalivePoolSize": "Upstream Keepalive Pool Size",
   "basic.cacheDirectory": "Program Cache Directory",
   "basic.cacheMaxSize": "Program Sharing Cache Max Size",
+  "basic.cacheSnapshot": "Cache Snapshot File",
+  "basic.cachePrefetchHits": "Min Hits Of Cache Prefetch",
+  "basic.cachePrefetchBefore": "Prefetch Before Cache Expired",
+  "basic.cachePrefetchConcurrency": "Concurrency Of Cache Prefetch",
   "basic.webhookType": "Webhook Type",
   "basic.webhookNotifications": "Webhook Notifications",
   "basic.webhook": "Webhook Http Url",
   "basic.sentry": "Sentry Connect Url",
+  "basic.sentrySampleRate": "Sentry Sample Rate Of 5xx Events",
+  "basic.sentryBurstThreshold": "Sentry 5xx Burst Threshold Per Minute",
   "basic.pyroscope": "Pyroscope Connect Url",
+  "basic.cluster": "Cluster Etcd Url",
+  "basic.metricsPush": "Metrics Push Url(statsd, dogstatsd or remote write)",
+  "basic.metricsPushInterval": "Metrics Push Interval",
+  "basic.metricsMaxSeries": "Max Series Of Request Metrics(default 1000)",
+  "basic.metering": "Usage Metering Export(file or http url)",
+  "basic.meteringInterval": "Usage Metering Window",
+  "basic.sink": "Access Log And Security Event Sink(nats)",
+  "basic.sinkBatchSize": "Sink Batch Size",
+  "basic.clickhouse": "ClickHouse Access Log Url",
+  "basic.clickhouseFlushInterval": "ClickHouse Flush Interval",
+  "basic.clickhouseSpool": "ClickHouse Spool Dir",
+  "basic.clickhouseBatchSize": "ClickHouse Batch Size",
+  "basic.anomalyDetection": "Anomaly Detection Of Location Traffic",
+  "basic.anomalySensitivity": "Anomaly Sensitivity(standard deviations, default 3)",
   "basic.errorTemplate": "Error Template",
+  "basic.errorTemplateDir": "Error Template Directory",
   // server info
   "server.title": "Modify server configuration",
   "server.description":
@@ -54,6 +83,13 @@ export default {
   "server.tlsCert": "Tls Cert Pem",
   "server.tlsKey": "Tls Key Pem",
   "server.globalCertificates": "Use The Global Certificates",
+  "server.httpsRedirect": "Redirect Http To Https",
+  "server.normalizePath": "Normalize Request Path",
+  "server.normalizeHost": "Lowercase Request Host",
+  "server.strictRequest": "Strict Request Validation",
+  "server.maxHeaderCount": "Max Header Count",
+  "server.maxHeaderSize": "Max Header Size",
+  "server.notFound": "Not Found Action(404, redirect:url or location:name)",
   "server.letsEncrypt": "Let's Encrypt Domain List",
   "server.certificateFile": "The File For Saving Certificate",
   "server.enabledH2": "Enable Http2",
@@ -62,9 +98,13 @@ export default {
   "server.tlsMinVersion": "Tls Min Proto Version",
   "server.tlsMaxVersion": "Tls Max proto Version",
   "server.tcpFastOpen": "The Backlog Size Of Tcp Fast Open",
+  "server.registry": "Service Registry(e.g. consul://127.0.0.1:8500?prefix=pingap)",
   "server.tcpIdle": "Tcp Keepalive Idle Duration",
   "server.tcpInterval": "Tcp Keepalive Interval Duration",
   "server.tcpProbeCount": "Tcp Keepalive Probe Max Number",
+  "server.overloadMaxProcessing": "Overload Max Processing Requests",
+  "server.overloadMaxEventLoopDelay": "Overload Max Event Loop Delay",
+  "server.overloadLowPriorityOnly": "Only Shed Low Priority Locations",
   "server.remark": "Remark",
   // location info
   "location.title": "Modify location configuration",
@@ -74,6 +114,7 @@ export default {
   "location.path": "Path",
   "location.upstream": "Select Upstream",
   "location.weight": "The Weight Of Location",
+  "location.priority": "Priority To Use Reserved Concurrency(0-9)",
   "location.rewrite": "Regexp Rewrite",
   "location.plugins": "Select Plugins For Location",
   "location.clientMaxBodySize": "Client Max Body Size",
@@ -87,6 +128,15 @@ export default {
   "upstream.addrs": "Upstream Addrs(ip:port)",
   "upstream.discovery": "Upstream Node Discover Type",
   "upstream.updateFrequency": "Discovery Update Frequency",
+  "upstream.dnsTtlRefresh": "Refresh By Dns Ttl",
+  "upstream.dnsMinTtl": "Dns Min Ttl",
+  "upstream.dnsMaxTtl": "Dns Max Ttl",
+  "upstream.dnsFailurePolicy": "Dns Failure Policy",
+  "upstream.dnsMaxStale": "Dns Max Stale",
+  "upstream.dnsFallbackAddrs": "Dns Fallback Addrs",
+  "upstream.lazyResolve": "Resolve At Request Time",
+  "upstream.resolveValid": "Resolve Cache Valid",
+  "upstream.resolveNegativeValid": "Resolve Failure Cache Valid",
   "upstream.algo": "Load Balancer Algorithm",
   "upstream.healthCheck": "Health Check",
   "upstream.connectionTimeout": "Connection Timeout",
@@ -95,9 +145,31 @@ export default {
   "upstream.writeTimeout": "Write Timeout",
   "upstream.idleTimeout": "Idle Timeout",
   "upstream.alpn": "Alpn",
+  "upstream.maxH2Streams": "Max H2 Streams",
+  "upstream.s3Bucket": "Bucket Of S3 Origin",
+  "upstream.s3Region": "Region Of S3 Origin",
+  "upstream.s3Prefix": "Key Prefix Of S3 Origin",
+  "upstream.s3PathStyle": "Path Style Of S3 Origin",
+  "upstream.s3AccessKeyId": "Access Key Id Of S3 Origin",
+  "upstream.s3SecretAccessKey": "Secret Access Key Of S3 Origin",
+  "upstream.s3SessionToken": "Session Token Of S3 Origin",
+  "upstream.fastcgi": "FastCGI Backend(e.g. php-fpm)",
+  "upstream.fastcgiRoot": "Document Root Of FastCGI",
+  "upstream.fastcgiIndex": "Index Script Of FastCGI",
+  "upstream.fastcgiKeepalive": "Keepalive Of FastCGI",
+  "upstream.fastcgiParams": "Params Of FastCGI(e.g. APP_ENV production)",
+  "upstream.wsgiProtocol": "Python Application Server Protocol",
+  "upstream.wsgiScriptName": "Script Name Of WSGI(e.g. /app)",
+  "upstream.wsgiParams": "Params Of WSGI(e.g. UWSGI_APPID blog)",
+  "upstream.greenUpstream": "Green Upstream Of Blue Green Deployment",
+  "upstream.blueGreenMinHealthy": "Min Healthy Backends To Switch Blue Green",
+  "upstream.maxConcurrency": "Max Concurrent Requests",
+  "upstream.reservedConcurrency": "Concurrency Reserved For High Priority",
   "upstream.sni": "Sni",
   "upstream.verifyCert": "Verify Cert",
   "upstream.ipv4Only": "Ipv4 Only",
+  "upstream.ipv6Only": "Ipv6 Only",
+  "upstream.preferIpv6": "Prefer Ipv6",
   "upstream.enableTracer": "Enable Tracer",
   "upstream.tcpFastOpen": "Enable tcp fast open",
   "upstream.tcpRecvBuf": "Tcp Recv Buffer Size",
@@ -115,10 +187,21 @@ export default {
   "certificate.certificateFile":
     "The File For Saving Let's Encrypt Certificate",
   "certificate.acme": "The Acme For Generating Certificate",
+  // schedule info
+  "schedule.title": "Scheduled config change",
+  "schedule.description":
+    "Change the config on schedule, it's applied by auto reload",
+  "schedule.cron": "Cron Expression(e.g. 0 2 * * *)",
+  "schedule.category": "Config Category",
+  "schedule.name": "Config Name",
+  "schedule.values": "Config Values(e.g. upstream = \"maintenance\")",
+  "schedule.remark": "Remark",
   // plugin info
   "plugin.title": "Modify plugin configuration",
   "plugin.description": "All plugin configuration of pingap",
   "plugin.step": "Plugin Exec Step",
+  "plugin.priority": "Plugin Priority",
+  "plugin.condition": "Plugin Condition, e.g. path ^= /api && method == GET|POST",
   "plugin.category": "Plugin Category",
   "plugin.config": "Plugin Config Data",
   "plugin.remark": "Remark",
@@ -145,6 +228,10 @@ export default {
   "from.addrs": "Add addr",
   "form.proxyHeaderName": "Header Name",
   "form.proxyHeaderValue": "Header Value",
+  "form.cgiParamName": "Param Name",
+  "form.cgiParamValue": "Param Value",
+  "form.scheduleValueName": "Field Name",
+  "form.scheduleValueValue": "Toml Value",
   "form.compressionGzipLevel": "Gzip Level",
   "form.compressionBrLevel": "Br Level",
   "form.compressionZstdLevel": "Zstd Level",
@@ -153,11 +240,27 @@ export default {
   "form.adminIpFailLimit": "Auth Fail Ip Limit Count",
   "form.adminAuthorization": "Base64 Value For Basic Auth(base64(user:pass))",
   "form.adminAuthorizationAdd": "Add More Authorization",
+  "form.adminObserverTokens": "Bearer Token Of Read Only Observer",
+  "form.adminObserverTokensAdd": "Add More Observer Token",
+  "form.adminReadOnly": "Read Only(all users are observer)",
+  "form.adminSessionTtl": "Session Ttl(enable login session, e.g. 1h)",
+  "form.adminSessionIdleTimeout": "Session Idle Timeout(default 15m)",
+  "form.adminSessionRotateInterval": "Session Rotate Interval(default 5m)",
+  "form.adminSessionIpPinning": "Pin Session To Login Ip",
   "form.limitCategory": "The Limit Category",
   "form.limitTag": "The Limit Tag",
   "form.limitKey": "The Limit Key",
   "form.limitMax": "The Limit Max Value",
   "form.limitInterval": "The Limit Interval For Rate",
+  "form.limitKeys": "The Composite Keys Of Limit, e.g. ip, path:1",
+  "form.limitKeysAdd": "Add Limit Key",
+  "form.limitRates": "The Rate Windows, e.g. 10/1s, 100/1m",
+  "form.limitRatesAdd": "Add Rate Window",
+  "form.limitStatus": "The Status Of Limited Response",
+  "form.limitMessage": "The Body Of Limited Response, supports max, value and retry_after templates",
+  "form.blockAction": "Block Action(deny, drop or tarpit)",
+  "form.tarpitDuration": "Tarpit Duration",
+  "form.tarpitMax": "Max Concurrent Tarpits",
   "form.allow": "Allow",
   "form.deny": "Deny",
   "form.dirPath": "The Directory For Static Serve",
@@ -204,6 +307,8 @@ export default {
   "form.redirectHttps": "Redirect Http To Https",
   "form.redirectPrefix": "The Prefix Add To Redirect Path",
   "form.statsPath": "The Path Of Stats",
+  "form.statsBearerTokens": "The Bearer Tokens Of Stats",
+  "form.statsBearerTokenAdd": "Add Bearer Token",
   "form.pingPath": "The Path Of Ping",
   "form.responseHeadersAddHeaderName": "Add Header Name",
   "form.responseHeadersAddHeaderValue": "Add Header Value",
@@ -219,10 +324,61 @@ export default {
   "form.mockHeaderName": "Mock Header Name Of Response",
   "form.mockHeaderValue": "Mock Header Value Of Response",
   "form.mockData": "Response Data",
+  "form.mockRoutes": "Response Of Path(path status data)",
+  "form.mockRoutesAdd": "Add Response Of Path",
+  "form.mockDelay": "Response Delay(e.g. 100ms)",
+  "form.mockJitter": "Random Jitter Of Delay(e.g. 50ms)",
+  "form.mockErrorRate": "Error Injection Rate(0-1)",
+  "form.mockErrorStatus": "Status Of Error Response",
+  "form.mockErrorData": "Data Of Error Response",
+  "form.mockPassthrough": "Only Inject Latency And Error(Proxy To Upstream)",
   "form.csrfTokenPath": "The Path For Get Csrf Token",
   "form.csrfName": "The Name Of Csrf Token",
   "form.csrfKey": "The Secret Key For Csrf",
   "form.csrfTtl": "The Ttl Of Csrf Token",
+  "form.coalesceVaryHeaders": "Vary Headers Of Coalescing Key",
+  "form.coalesceVaryHeadersAdd": "Add Vary Header",
+  "form.coalesceTimeout": "Max Wait Time Of Coalesced Requests",
+  "form.coalesceMaxBodySize": "Max Body Size Of Shared Response",
+  "form.botDetectionAction": "Action Of Bot Request",
+  "form.botDetectionThreshold": "Score Threshold(default 60)",
+  "form.botDetectionTagHeader": "Tag Header(default X-Bot-Score)",
+  "form.botDetectionMessage": "Forbidden Message",
+  "form.botDetectionMax": "Max Requests Of Bot",
+  "form.botDetectionInterval": "Limit Interval",
+  "form.botDetectionUserAgents": "Bot User Agent",
+  "form.botDetectionUserAgentAdd": "Add Bot User Agent",
+  "form.botDetectionBlockedFingerprints": "Blocked Tls Fingerprint(ja3)",
+  "form.botDetectionAllowedFingerprints": "Allowed Tls Fingerprint(ja3)",
+  "form.botDetectionFingerprintAdd": "Add Tls Fingerprint",
+  "form.honeypotPaths": "Decoy Paths, supports prefix match with *",
+  "form.honeypotPathAdd": "Add Decoy Path",
+  "form.honeypotBanTtl": "Ban Duration Of Client Ip(0s means not to ban)",
+  "form.honeypotMessage": "Decoy Response Message",
+  "form.cookieFilterDropCookies": "Dropped Cookies, supports prefix match with *",
+  "form.cookieFilterAllowCookies": "Allowed Cookies, only them are sent to upstream",
+  "form.cookieFilterCookieAdd": "Add Cookie Name",
+  "form.cookieFilterRenameCookies": "Renamed Cookies(name:upstream_name)",
+  "form.cookieFilterRenameCookieAdd": "Add Renamed Cookie",
+  "form.cookieFilterScopePrefix": "Scope Prefix Of Cookie",
+  "form.abTestExperiment": "Experiment Name",
+  "form.abTestSalt": "Salt Of Bucketing(default is experiment name)",
+  "form.abTestKey": "Key Of Client(ip, cookie:name or header:name)",
+  "form.abTestHeader": "Bucket Header For Upstream(default is X-Ab-Bucket)",
+  "form.abTestBuckets": "Buckets(name:weight or name:weight:upstream)",
+  "form.abTestBucketAdd": "Add Bucket",
+  "form.securityHeadersPreset": "Preset Of Security Headers",
+  "form.securityHeadersOverride": "Override Upstream Headers",
+  "form.securityHeadersDisabled": "Disabled Header(e.g. hsts)",
+  "form.securityHeadersDisabledAdd": "Add Disabled Header",
+  "form.requestSignAlgorithm": "Sign Algorithm",
+  "form.requestSignHost": "Host Of Upstream Request",
+  "form.requestSignSecret": "Secret Of Hmac",
+  "form.requestSignKeyId": "Key Id Of Hmac",
+  "form.requestSignHeader": "Header Of Signature(default X-Signature)",
+  "form.requestSignTimestampHeader": "Header Of Timestamp(default X-Timestamp)",
+  "form.requestSignSignedHeaders": "Signed Header",
+  "form.requestSignSignedHeadersAdd": "Add Signed Header",
   "form.cacheLock": "Lock Time Concurrent Lookups To The Same Asset",
   "form.cacheMaxFileSize":
     "The Max File Size To Cache(Bigger will be not cached)",
@@ -231,6 +387,11 @@ export default {
   "form.cacheHeadersAdd": "Add More Header For Cache key",
   "form.cacheEviction": "Enable Evicted From Storage",
   "form.cachePredictor": "Enable Predictor",
+  "form.cacheBypassHeader": "Secret Header To Bypass Cache(name:value)",
+  "form.cacheBypassQuery": "Query Name To Bypass Cache",
+  "form.cacheRange": "Range Request Cache Mode",
+  "form.cacheBypassIps": "Client Ips Allowed To Control Cache",
+  "form.cacheBypassIpsAdd": "Add More Ip",
   "form.cacheMaxTtl": "Max Ttl Of Cache",
   // cors
   "form.corsPath": "The Gegexp Path For Cors(optional)",
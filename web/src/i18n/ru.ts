@@ -6,6 +6,7 @@ export default {
   "nav.upstream": "Вверх по течению",
   "nav.plugin": "Плагин",
   "nav.certificate": "Сертификат",
+  "nav.schedule": "Расписание",
   // header
   "header.title": "Информация",
   "header.startTime": "Время начала: ",
@@ -17,6 +18,12 @@ export default {
   "header.confirmTips": "Вы уверены перезапустить pingap?",
   "header.confirm": "Перезапустить",
   "header.cancel": "Отмена",
+  "header.logout": "Выйти",
+  "login.title": "Вход",
+  "login.account": "Аккаунт",
+  "login.password": "Пароль",
+  "login.submit": "Войти",
+  "login.processing": "Вход...",
   // basic info
   "basic.title": "Изменить базовые конфигурации",
   "базовое.описание": "Базовая конфигурация pingap в основном включает в себя различные конфигурации, такие как журналы, плавный перезапуск, потоки и т. д.",
@@ -27,6 +34,7 @@ export default {
   "basic.workStealing": "Кража работы",
   "basic.user": "Пользователь для демона",
   "basic.group": "Группа для демона",
+  "basic.sandbox": "Песочница(landlock)",
 "basic.gracePeriod": "Льготный период для выхода",
   "basic.gracefulShutdownTimeout": "Тайм-аут плавного завершения работы",
   "basic.logLevel": "Уровень журнала",
@@ -36,12 +44,33 @@ export default {
   "basic.upstreamKeepalivePoolSize": "Размер восходящего пула поддержки активности",
   "basic.cacheDirectory": "Каталог кэша программы",
   "basic.cacheMaxSize": "Максимальный размер общего кэша программ",
+  "basic.cacheSnapshot": "Файл снимка кэша",
+  "basic.cachePrefetchHits": "Минимум попаданий для предвыборки кэша",
+  "basic.cachePrefetchBefore": "Предвыборка до истечения кэша",
+  "basic.cachePrefetchConcurrency": "Параллелизм предвыборки кэша",
 "basic.webhookType": "Тип вебхука",
   "basic.webhookNotifications": "Уведомления вебхука",
   "basic.webhook": "URL-адрес веб-перехватчика",
   "basic.sentry": "URL-адрес подключения Sentry",
+  "basic.sentrySampleRate": "Частота выборки событий 5xx в Sentry",
+  "basic.sentryBurstThreshold": "Порог всплеска 5xx в минуту для Sentry",
   "basic.pyrscope": "URL-адрес подключения пироскопа",
-  "basic.errorTemplate": "Шаблон ошибки",
+  "basic.cluster": "Адрес etcd кластера",
+  "basic.metricsPush": "URL-адрес отправки метрик (statsd, dogstatsd или remote write)",
+  "basic.metricsPushInterval": "Интервал отправки метрик",
+  "basic.metricsMaxSeries": "Максимальное число серий метрик запросов (по умолчанию 1000)",
+  "basic.metering": "Экспорт учета использования (файл или http url)",
+  "basic.meteringInterval": "Окно учета использования",
+  "basic.sink": "Отправка журналов доступа и событий безопасности (nats)",
+  "basic.sinkBatchSize": "Размер пакета отправки",
+  "basic.clickhouse": "URL записи журналов доступа в ClickHouse",
+  "basic.clickhouseFlushInterval": "Интервал записи в ClickHouse",
+  "basic.clickhouseSpool": "Каталог буфера ClickHouse",
+  "basic.clickhouseBatchSize": "Размер пакета ClickHouse",
+  "basic.anomalyDetection": "Обнаружение аномалий трафика location",
+  "basic.anomalySensitivity": "Чувствительность (число стандартных отклонений, по умолчанию 3)",
+"basic.errorTemplate": "Шаблон ошибки",
+"basic.errorTemplateDir": "Каталог шаблонов ошибок",
   // server info
  "server.title": "Изменить конфигурацию сервера",
   "server.description": "Конфигурация сервера, включая порты прослушивания, потоки и структуру формата журнала",
@@ -52,6 +81,13 @@ export default {
   "server.tlsCert": "Tls Cert Pem",
   "server.tlsKey": "Ключ Tls Pem",
   "server.globalCertificates": "Использовать глобальные сертификаты",
+  "server.httpsRedirect": "Перенаправлять http на https",
+  "server.normalizePath": "Нормализовать путь запроса",
+  "server.normalizeHost": "Приводить host запроса к нижнему регистру",
+  "server.strictRequest": "Строгая проверка запросов",
+  "server.maxHeaderCount": "Макс. количество заголовков",
+  "server.maxHeaderSize": "Макс. размер заголовков",
+  "server.notFound": "Действие при отсутствии location (404, redirect:url или location:name)",
   "server.letsEncrypt": "Let's Encrypt Domain List",
   "server.certificateFile": "Файл для сохранения сертификата",
   "server.enabledH2": "Включить Http2",
@@ -60,9 +96,13 @@ export default {
   "server.tlsMinVersion": "Минимальная версия Tls",
   "server.tlsMaxVersion": "Протоверсия Tls Max",
   "server.tcpFastOpen": "Размер очереди быстрого открытия TCP",
+  "server.registry": "Реестр сервисов(например consul://127.0.0.1:8500?prefix=pingap)",
   "server.tcpIdle": "Продолжительность простоя TCP Keepalive",
 "server.tcpInterval": "Продолжительность интервала поддержки активности TCP",
   "server.tcpProbeCount": "Максимальное количество тестов TCP Keepalive",
+  "server.overloadMaxProcessing": "Максимум обрабатываемых запросов при перегрузке",
+  "server.overloadMaxEventLoopDelay": "Максимальная задержка цикла событий при перегрузке",
+  "server.overloadLowPriorityOnly": "Отклонять только низкоприоритетные location",
   "server.remark": "Замечание",
   // location info
   "location.title": "Изменить конфигурацию местоположения",
@@ -71,6 +111,7 @@ export default {
   "location.path": "Путь",
   "location.upstream": "Выбрать восходящий поток",
   "location.weight": "Вес локации",
+  "location.priority": "Приоритет использования резерва(0-9)",
   "location.rewrite": "Перезапись регулярного выражения",
   "location.plugins": "Выберите плагины для местоположения",
   "location.clientMaxBodySize": "Максимальный размер тела клиента",
@@ -83,6 +124,15 @@ export default {
   "upstream.addrs": "Адреса восходящего потока(ip:port)",
   "upstream.discovery": "Тип обнаружения восходящего узла",
   "upstream.updateFrequency": "Частота обновления Discovery",
+  "upstream.dnsTtlRefresh": "Обновление по TTL DNS",
+  "upstream.dnsMinTtl": "Минимальный TTL DNS",
+  "upstream.dnsMaxTtl": "Максимальный TTL DNS",
+  "upstream.dnsFailurePolicy": "Политика при ошибке DNS",
+  "upstream.dnsMaxStale": "Максимальная устарелость DNS",
+  "upstream.dnsFallbackAddrs": "Резервные адреса DNS",
+  "upstream.lazyResolve": "Разрешение имени во время запроса",
+  "upstream.resolveValid": "Время кэширования разрешения",
+  "upstream.resolveNegativeValid": "Время кэширования ошибки разрешения",
   "upstream.algo": "Алгоритм балансировки нагрузки",
   "upstream.healthCheck": "Проверка работоспособности",
   "upstream.connectionTimeout": "Тайм-аут соединения",
@@ -91,9 +141,31 @@ export default {
   "upstream.writeTimeout": "Тайм-аут записи",
   "upstream.idleTimeout": "Тайм-аут простоя",
   "upstream.alpn": "Альпн",
+  "upstream.maxH2Streams": "Макс. потоков H2",
+  "upstream.s3Bucket": "Bucket источника S3",
+  "upstream.s3Region": "Регион источника S3",
+  "upstream.s3Prefix": "Префикс ключей источника S3",
+  "upstream.s3PathStyle": "Path style источника S3",
+  "upstream.s3AccessKeyId": "Access key id источника S3",
+  "upstream.s3SecretAccessKey": "Secret access key источника S3",
+  "upstream.s3SessionToken": "Session token источника S3",
+  "upstream.fastcgi": "FastCGI сервер(например php-fpm)",
+  "upstream.fastcgiRoot": "Корневой каталог FastCGI",
+  "upstream.fastcgiIndex": "Индексный скрипт FastCGI",
+  "upstream.fastcgiKeepalive": "Повторное использование соединений FastCGI",
+  "upstream.fastcgiParams": "Параметры FastCGI(например APP_ENV production)",
+  "upstream.wsgiProtocol": "Протокол сервера Python приложений",
+  "upstream.wsgiScriptName": "Путь монтирования WSGI(например /app)",
+  "upstream.wsgiParams": "Параметры WSGI(например UWSGI_APPID blog)",
+  "upstream.greenUpstream": "Зелёный upstream сине-зелёного развёртывания",
+  "upstream.blueGreenMinHealthy": "Мин. число здоровых бэкендов для переключения",
+  "upstream.maxConcurrency": "Макс. число одновременных запросов",
+  "upstream.reservedConcurrency": "Резерв для высокого приоритета",
   "upstream.sni": "Сни",
   "upstream.verifyCert": "Проверить сертификат",
   "upstream.ipv4Only": "Только Ipv4",
+  "upstream.ipv6Only": "Только Ipv6",
+  "upstream.preferIpv6": "Предпочитать Ipv6",
   "upstream.enableTracer": "Включить трассировку",
   "upstream.tcpFastOpen": "Включить быстрое открытие TCP",
   "upstream.tcpRecvBuf": "Размер буфера приема TCP",
@@ -110,10 +182,21 @@ export default {
 "certificate.domains": "Список доменов для Let's Encrypt",
   "certificate.certificateFile": "Файл для сохранения сертификата Let's Encrypt",
   "certificate.acme": "acme для создания сертификата",
+  // schedule info
+  "schedule.title": "Изменение конфигурации по расписанию",
+  "schedule.description":
+    "Изменение конфигурации по расписанию, применяется автоперезагрузкой",
+  "schedule.cron": "Выражение cron(например 0 2 * * *)",
+  "schedule.category": "Категория конфигурации",
+  "schedule.name": "Имя конфигурации",
+  "schedule.values": "Значения(например upstream = \"maintenance\")",
+  "schedule.remark": "Примечание",
   // plugin info
   "plugin.title": "Изменить конфигурацию плагина",
   "plugin.description": "Все настройки плагинов pingap",
   "plugin.step": "Шаг выполнения плагина",
+  "plugin.priority": "Приоритет плагина",
+  "plugin.condition": "Условие плагина, например: path ^= /api && method == GET|POST",
   "plugin.category": "Категория плагина",
   "plugin.config": "Данные конфигурации плагина",
   "plugin.remark": "Замечание",
@@ -139,6 +222,10 @@ export default {
   "from.addrs": "Добавить адрес",
   "form.proxyHeaderName": "Имя заголовка",
   "form.proxyHeaderValue": "Значение заголовка",
+  "form.cgiParamName": "Имя параметра",
+  "form.cgiParamValue": "Значение параметра",
+  "form.scheduleValueName": "Имя поля",
+  "form.scheduleValueValue": "Значение toml",
   "form.compressionGzipLevel": "Уровень Gzip",
   "form.compressionBrLevel": "Уровень Br",
   "form.compressionZstdLevel": "Уровень Zstd",
@@ -147,11 +234,27 @@ export default {
   "form.adminIpFailLimit": "Ограничение количества IP-адресов при неудачной аутентификации",
   "form.adminAuthorization": "Значение Base64 для базовой аутентификации(base64(user:pass))",
   "form.adminAuthorizationAdd": "Добавить дополнительную авторизацию",
+  "form.adminObserverTokens": "Bearer токен наблюдателя(только чтение)",
+  "form.adminObserverTokensAdd": "Добавить токен наблюдателя",
+  "form.adminReadOnly": "Только чтение(все пользователи наблюдатели)",
+  "form.adminSessionTtl": "Время жизни сессии(включает сессии входа, например 1h)",
+  "form.adminSessionIdleTimeout": "Таймаут бездействия сессии(по умолчанию 15m)",
+  "form.adminSessionRotateInterval": "Интервал смены идентификатора сессии(по умолчанию 5m)",
+  "form.adminSessionIpPinning": "Привязать сессию к IP входа",
   "form.limitCategory": "Категория лимита",
   "form.limitTag": "Тег лимита",
   "form.limitKey": "Ключ лимита",
   "form.limitMax": "Максимальное предельное значение",
 "form.limitInterval": "Предельный интервал для ставки",
+  "form.limitKeys": "Составные ключи лимита, например ip, path:1",
+  "form.limitKeysAdd": "Добавить ключ лимита",
+  "form.limitRates": "Окна ограничения, например 10/1s, 100/1m",
+  "form.limitRatesAdd": "Добавить окно",
+  "form.limitStatus": "Статус ответа при превышении лимита",
+  "form.limitMessage": "Тело ответа при превышении лимита, поддерживает шаблоны max, value и retry_after",
+  "form.blockAction": "Действие блокировки (deny, drop или tarpit)",
+  "form.tarpitDuration": "Длительность tarpit",
+  "form.tarpitMax": "Макс. одновременных tarpit",
   "form.allow": "Разрешить",
   "form.deny": "Отклонить",
   "form.dirPath": "Каталог для статического обслуживания",
@@ -197,6 +300,8 @@ export default {
   "form.redirectHttps": "Перенаправить Http на Https",
   "form.redirectPrefix": "Префикс добавляется к пути перенаправления",
 "form.statsPath": "Путь статистики",
+"form.statsBearerTokens": "Список Bearer токенов статистики",
+"form.statsBearerTokenAdd": "Добавить Bearer токен",
   "form.pingPath": "Путь пинга",
   "form.responseHeadersAddHeaderName": "Добавить имя заголовка",
   "form.responseHeadersAddHeaderValue": "Добавить значение заголовка",
@@ -212,10 +317,61 @@ export default {
   "form.mockHeaderName": "Имя ответа псевдозаголовка",
   "form.mockHeaderValue": "Значение ложного заголовка ответа",
   "form.mockData": "Данные ответа",
+  "form.mockRoutes": "Ответ для пути(путь статус данные)",
+  "form.mockRoutesAdd": "Добавить ответ для пути",
+  "form.mockDelay": "Задержка ответа(например 100ms)",
+  "form.mockJitter": "Случайный разброс задержки(например 50ms)",
+  "form.mockErrorRate": "Вероятность внедрения ошибки(0-1)",
+  "form.mockErrorStatus": "Статус ответа с ошибкой",
+  "form.mockErrorData": "Данные ответа с ошибкой",
+  "form.mockPassthrough": "Только задержка и ошибки(проксировать в upstream)",
   "form.csrfTokenPath": "Путь для получения токена Csrf",
   "form.csrfName": "Имя токена CSRF",
   "form.csrfKey": "Секретный ключ для Csrf",
 "form.csrfTtl": "Ttl токена Csrf",
+  "form.coalesceVaryHeaders": "Заголовки ключа объединения",
+  "form.coalesceVaryHeadersAdd": "Добавить заголовок",
+  "form.coalesceTimeout": "Максимальное время ожидания",
+  "form.coalesceMaxBodySize": "Максимальный размер общего ответа",
+"form.botDetectionAction": "Действие для запроса бота",
+"form.botDetectionThreshold": "Порог оценки(по умолчанию 60)",
+"form.botDetectionTagHeader": "Заголовок метки(по умолчанию X-Bot-Score)",
+"form.botDetectionMessage": "Сообщение о запрете",
+"form.botDetectionMax": "Максимум запросов бота",
+"form.botDetectionInterval": "Интервал ограничения",
+"form.botDetectionUserAgents": "User-Agent бота",
+"form.botDetectionUserAgentAdd": "Добавить User-Agent бота",
+"form.botDetectionBlockedFingerprints": "Запрещенный отпечаток TLS(ja3)",
+"form.botDetectionAllowedFingerprints": "Разрешенный отпечаток TLS(ja3)",
+"form.botDetectionFingerprintAdd": "Добавить отпечаток TLS",
+  "form.honeypotPaths": "Пути-приманки, поддерживается префикс с *",
+  "form.honeypotPathAdd": "Добавить путь-приманку",
+  "form.honeypotBanTtl": "Длительность бана IP клиента (0s — без бана)",
+  "form.honeypotMessage": "Сообщение ответа приманки",
+  "form.cookieFilterDropCookies": "Удаляемые cookie, поддерживается префикс с *",
+  "form.cookieFilterAllowCookies": "Разрешенные cookie, только они передаются в upstream",
+  "form.cookieFilterCookieAdd": "Добавить имя cookie",
+  "form.cookieFilterRenameCookies": "Переименование cookie (name:upstream_name)",
+  "form.cookieFilterRenameCookieAdd": "Добавить переименование cookie",
+  "form.cookieFilterScopePrefix": "Префикс области cookie",
+  "form.abTestExperiment": "Название эксперимента",
+  "form.abTestSalt": "Соль распределения (по умолчанию название эксперимента)",
+  "form.abTestKey": "Ключ клиента (ip, cookie:name или header:name)",
+  "form.abTestHeader": "Заголовок группы для upstream (по умолчанию X-Ab-Bucket)",
+  "form.abTestBuckets": "Группы (name:weight или name:weight:upstream)",
+  "form.abTestBucketAdd": "Добавить группу",
+"form.securityHeadersPreset": "Пресет заголовков безопасности",
+"form.securityHeadersOverride": "Перезаписывать заголовки upstream",
+"form.securityHeadersDisabled": "Отключенный заголовок(например hsts)",
+"form.securityHeadersDisabledAdd": "Добавить отключенный заголовок",
+"form.requestSignAlgorithm": "Алгоритм подписи",
+"form.requestSignHost": "Host запроса к upstream",
+"form.requestSignSecret": "Секрет Hmac",
+"form.requestSignKeyId": "Идентификатор ключа Hmac",
+"form.requestSignHeader": "Заголовок подписи(по умолчанию X-Signature)",
+"form.requestSignTimestampHeader": "Заголовок метки времени(по умолчанию X-Timestamp)",
+"form.requestSignSignedHeaders": "Подписываемый заголовок",
+"form.requestSignSignedHeadersAdd": "Добавить подписываемый заголовок",
   "form.cacheLock": "Блокировать время одновременного поиска одного и того же актива",
   "form.cacheMaxFileSize": "Максимальный размер файла для кэширования (больший размер не будет кэшироваться)",
   "form.cacheNamespace": "Пространство имен кэша",
@@ -223,6 +379,11 @@ export default {
   "form.cacheHeadersAdd": "Добавить дополнительный заголовок для ключа кэша",
   "form.cacheEviction": "Включить вытеснение из хранилища",
   "form.cachePredictor": "Включить предиктор",
+  "form.cacheBypassHeader": "Секретный заголовок для обхода кэша (name:value)",
+  "form.cacheBypassQuery": "Параметр запроса для обхода кэша",
+  "form.cacheRange": "Режим кэширования Range-запросов",
+  "form.cacheBypassIps": "IP клиентов, которым разрешено управлять кэшем",
+  "form.cacheBypassIpsAdd": "Добавить еще IP",
 "form.cacheMaxTtl": "Макс. срок жизни кэша",
   // cors
  "form.corsPath": "Путь Gegexp для Cors (необязательно)",
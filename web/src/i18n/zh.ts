@@ -6,6 +6,7 @@ export default {
   "nav.upstream": "上游服务配置",
   "nav.plugin": "插件配置",
   "nav.certificate": "证书配置",
+  "nav.schedule": "定时任务",
 
   // header
   "header.title": "应用信息",
@@ -18,6 +19,12 @@ export default {
   "header.confirmTips": "确认重启Pingap吗？",
   "header.confirm": "重启",
   "header.cancel": "取消",
+  "header.logout": "退出登录",
+  "login.title": "登录",
+  "login.account": "账号",
+  "login.password": "密码",
+  "login.submit": "登录",
+  "login.processing": "登录中...",
   // basic info
   "basic.title": "修改应用的基本信息配置",
   "basic.description": "Pingap的基本信息包括日志、优雅重启等待周期、线程等等",
@@ -28,6 +35,7 @@ export default {
   "basic.workStealing": "线程间工作是否可窃取",
   "basic.user": "后台模式切换用户",
   "basic.group": "后模式切换用户组",
+  "basic.sandbox": "文件访问沙箱(landlock)",
   "basic.gracePeriod": "重启等待周期",
   "basic.gracefulShutdownTimeout": "等待关闭时长",
   "basic.logLevel": "日志级别",
@@ -37,12 +45,33 @@ export default {
   "basic.upstreamKeepalivePoolSize": "上游节点连接池大小",
   "basic.cacheDirectory": "应用的缓存目录",
   "basic.cacheMaxSize": "应用共享的缓存空间大小限制",
+  "basic.cacheSnapshot": "缓存快照文件",
+  "basic.cachePrefetchHits": "缓存预取的最小命中次数",
+  "basic.cachePrefetchBefore": "缓存过期前预取的时长",
+  "basic.cachePrefetchConcurrency": "缓存预取的并发数",
   "basic.webhookType": "Webhook类型",
   "basic.webhookNotifications": "Webhook通知类型",
   "basic.webhook": "Webhook的请求地址",
   "basic.sentry": "Sentry地址",
+  "basic.sentrySampleRate": "Sentry 5xx事件的采样率",
+  "basic.sentryBurstThreshold": "Sentry 5xx突增的每分钟阈值",
   "basic.pyroscope": "Pyroscope地址",
+  "basic.cluster": "集群Etcd地址",
+  "basic.metricsPush": "指标推送地址(statsd、dogstatsd或remote write)",
+  "basic.metricsPushInterval": "指标推送间隔",
+  "basic.metricsMaxSeries": "请求指标的最大序列数(默认1000)",
+  "basic.metering": "用量计量导出地址(文件或http地址)",
+  "basic.meteringInterval": "用量计量时间窗口",
+  "basic.sink": "访问日志与安全事件推送地址(nats)",
+  "basic.sinkBatchSize": "推送批量大小",
+  "basic.clickhouse": "ClickHouse访问日志写入地址",
+  "basic.clickhouseFlushInterval": "ClickHouse写入间隔",
+  "basic.clickhouseSpool": "ClickHouse失败暂存目录",
+  "basic.clickhouseBatchSize": "ClickHouse批量大小",
+  "basic.anomalyDetection": "location流量异常检测",
+  "basic.anomalySensitivity": "异常检测的灵敏度(标准差的倍数，默认为3)",
   "basic.errorTemplate": "错误模板",
+  "basic.errorTemplateDir": "错误模板目录",
   // server info
   "server.title": "监听服务的相关配置",
   "server.description": "修改监听服务的各相关配置",
@@ -53,6 +82,13 @@ export default {
   "server.tlsCert": "Tls证书(Pem格式)",
   "server.tlsKey": "Tls密钥(Pem格式)",
   "server.globalCertificates": "使用应用的全局证书",
+  "server.httpsRedirect": "http重定向至https",
+  "server.normalizePath": "规范化请求路径",
+  "server.normalizeHost": "请求host转为小写",
+  "server.strictRequest": "严格校验请求",
+  "server.maxHeaderCount": "请求头最大数量",
+  "server.maxHeaderSize": "请求头最大长度",
+  "server.notFound": "未匹配location的处理(404、redirect:url或location:name)",
   "server.letsEncrypt": "使用let's encrypt的域名列表",
   "server.certificateFile": "保存tls证书的文件",
   "server.enabledH2": "是否启用http2",
@@ -61,9 +97,13 @@ export default {
   "server.tlsMinVersion": "Tls支持最低版本",
   "server.tlsMaxVersion": "Tls支持最高版本",
   "server.tcpFastOpen": "Tcp快速连接的backlog大小",
+  "server.registry": "服务注册中心(如consul://127.0.0.1:8500?prefix=pingap)",
   "server.tcpIdle": "Tcp保持连接的空闲时长",
   "server.tcpInterval": "Tcp保持连接探针的发送间隔",
   "server.tcpProbeCount": "Tcp保持连接探针发送的最大次数",
+  "server.overloadMaxProcessing": "过载保护的最大处理中请求数",
+  "server.overloadMaxEventLoopDelay": "过载保护的最大事件循环延时",
+  "server.overloadLowPriorityOnly": "过载时仅拒绝低优先级location的请求",
 
   "server.remark": "备注",
   // location info
@@ -74,6 +114,7 @@ export default {
   "location.path": "该Location匹配的路径",
   "location.upstream": "上游服务",
   "location.weight": "自定义权重",
+  "location.priority": "使用预留并发的优先级(0-9)",
   "location.rewrite": "路由重写规则",
   "location.plugins": "选择使用的插件",
   "location.clientMaxBodySize": "客户端内容长度最大限制",
@@ -86,6 +127,15 @@ export default {
   "upstream.addrs": "节点地址列表，格式为ip:port的形式",
   "upstream.discovery": "上流节点发现方式",
   "upstream.updateFrequency": "发现服务更新间隔",
+  "upstream.dnsTtlRefresh": "按DNS TTL刷新",
+  "upstream.dnsMinTtl": "DNS最小TTL",
+  "upstream.dnsMaxTtl": "DNS最大TTL",
+  "upstream.dnsFailurePolicy": "DNS解析失败策略",
+  "upstream.dnsMaxStale": "DNS节点最大过期时长",
+  "upstream.dnsFallbackAddrs": "DNS备用地址",
+  "upstream.lazyResolve": "请求时解析域名",
+  "upstream.resolveValid": "解析结果缓存时长",
+  "upstream.resolveNegativeValid": "解析失败缓存时长",
   "upstream.algo": "节点选择算法",
   "upstream.healthCheck": "健康检查配置",
   "upstream.connectionTimeout": "连接超时",
@@ -94,9 +144,31 @@ export default {
   "upstream.writeTimeout": "写超时",
   "upstream.idleTimeout": "空闲回收时长",
   "upstream.alpn": "Alpn",
+  "upstream.maxH2Streams": "H2最大并发流",
+  "upstream.s3Bucket": "S3源站的bucket",
+  "upstream.s3Region": "S3源站的区域",
+  "upstream.s3Prefix": "S3源站对象的前缀",
+  "upstream.s3PathStyle": "S3源站是否使用path style",
+  "upstream.s3AccessKeyId": "S3源站的access key id",
+  "upstream.s3SecretAccessKey": "S3源站的secret access key",
+  "upstream.s3SessionToken": "S3源站的session token",
+  "upstream.fastcgi": "FastCGI服务(如php-fpm)",
+  "upstream.fastcgiRoot": "FastCGI的根目录",
+  "upstream.fastcgiIndex": "FastCGI的默认脚本",
+  "upstream.fastcgiKeepalive": "FastCGI是否复用连接",
+  "upstream.fastcgiParams": "FastCGI的参数(如APP_ENV production)",
+  "upstream.wsgiProtocol": "Python应用服务的协议",
+  "upstream.wsgiScriptName": "WSGI的挂载路径(如/app)",
+  "upstream.wsgiParams": "WSGI的参数(如UWSGI_APPID blog)",
+  "upstream.greenUpstream": "蓝绿部署的绿色upstream",
+  "upstream.blueGreenMinHealthy": "蓝绿切换要求的最少健康节点数",
+  "upstream.maxConcurrency": "最大并发请求数",
+  "upstream.reservedConcurrency": "为高优先级预留的并发数",
   "upstream.sni": "Sni",
   "upstream.verifyCert": "是否校验证书",
   "upstream.ipv4Only": "是否仅Ipv4",
+  "upstream.ipv6Only": "是否仅Ipv6",
+  "upstream.preferIpv6": "是否优先Ipv6",
   "upstream.enableTracer": "启用连接跟踪",
   "upstream.tcpFastOpen": "启用Tcp快速连接",
   "upstream.tcpRecvBuf": "Tcp接收缓冲区大小",
@@ -113,11 +185,21 @@ export default {
   "certificate.certificateFile": "保存Let's Encrypt证书的文件",
   "certificate.domains": "请求Let's Encrypt申请证书时的域名列表",
   "certificate.acme": "选择使用acme生成证书的服务",
+  // schedule info
+  "schedule.title": "定时修改配置",
+  "schedule.description": "按计划修改配置，由自动重载应用",
+  "schedule.cron": "cron表达式(如0 2 * * *)",
+  "schedule.category": "配置类型",
+  "schedule.name": "配置名称",
+  "schedule.values": "修改的字段(如upstream = \"maintenance\")",
+  "schedule.remark": "备注",
 
   // plugin info
   "plugin.title": "各类插件的配置",
   "plugin.description": "添加配置各类插件，可便于后续服务使用",
   "plugin.step": "插件执行阶段",
+  "plugin.priority": "插件优先级(越大越先执行)",
+  "plugin.condition": "插件执行条件，如：path ^= /api && method == GET|POST",
   "plugin.category": "插件类型",
   "plugin.config": "插件配置数据",
   "plugin.remark": "备注",
@@ -144,6 +226,10 @@ export default {
   "form.addrs": "添加地址",
   "form.proxyHeaderName": "请求头名称",
   "form.proxyHeaderValue": "请求头值",
+  "form.cgiParamName": "参数名",
+  "form.cgiParamValue": "参数值",
+  "form.scheduleValueName": "字段名",
+  "form.scheduleValueValue": "toml格式的值",
   "form.compressionGzipLevel": "Gzip的压缩级别",
   "form.compressionBrLevel": "Br的压缩级别",
   "form.compressionZstdLevel": "Zstd的压缩级别",
@@ -152,11 +238,27 @@ export default {
   "form.adminIpFailLimit": "认证失败的IP限制次数",
   "form.adminAuthorization": "Basic auth，base64处理后的值，base64(user:pass)",
   "form.adminAuthorizationAdd": "添加更多的认证值",
+  "form.adminObserverTokens": "只读观察者的Bearer token",
+  "form.adminObserverTokensAdd": "添加更多的观察者token",
+  "form.adminReadOnly": "只读模式(所有用户均为观察者)",
+  "form.adminSessionTtl": "会话有效期(设置后启用登录会话，如1h)",
+  "form.adminSessionIdleTimeout": "会话空闲超时(默认15m)",
+  "form.adminSessionRotateInterval": "会话id轮换间隔(默认5m)",
+  "form.adminSessionIpPinning": "会话绑定登录IP",
 
   "form.limitCategory": "限流类型",
   "form.limitTag": "限流的标记",
   "form.limitKey": "限流的key",
   "form.limitInterval": "限流的计算间隔(用于rate类型)",
+  "form.limitKeys": "组合限流的key，如ip、path:1",
+  "form.limitKeysAdd": "添加限流key",
+  "form.limitRates": "多个限流窗口，如10/1s、100/1m",
+  "form.limitRatesAdd": "添加限流窗口",
+  "form.limitStatus": "超出限制时响应的状态码(默认为429)",
+  "form.limitMessage": "超出限制时响应的内容，支持max、value与retry_after模板",
+  "form.blockAction": "拦截的处理方式(deny、drop或tarpit)",
+  "form.tarpitDuration": "Tarpit响应时长",
+  "form.tarpitMax": "Tarpit最大并发数",
   "form.limitMax": "限流的最大值",
   "form.limitValue": "限流的相关限制",
   "form.allow": "允许",
@@ -205,6 +307,8 @@ export default {
   "form.redirectHttps": "重定向时至https",
   "form.redirectPrefix": "重定向时使用的前缀",
   "form.statsPath": "统计插件对应的路径",
+  "form.statsBearerTokens": "统计插件的Bearer认证token列表",
+  "form.statsBearerTokenAdd": "添加Bearer token",
   "form.pingPath": "Ping插件对应的路径",
   "form.responseHeadersAddHeaderName": "添加的响应头名称",
   "form.responseHeadersAddHeaderValue": "添加的响应头值",
@@ -220,10 +324,61 @@ export default {
   "form.mockHeaderName": "Mock响应头名称",
   "form.mockHeaderValue": "Mock响应头值",
   "form.mockData": "Mock响应的数据",
+  "form.mockRoutes": "指定路径的响应(路径 状态码 数据)",
+  "form.mockRoutesAdd": "添加指定路径的响应",
+  "form.mockDelay": "响应延时(如100ms)",
+  "form.mockJitter": "延时的随机抖动(如50ms)",
+  "form.mockErrorRate": "错误注入的概率(0-1)",
+  "form.mockErrorStatus": "错误响应的状态码",
+  "form.mockErrorData": "错误响应的数据",
+  "form.mockPassthrough": "仅注入延时与错误(转发至upstream)",
   "form.csrfTokenPath": "获取csrf令牌的路径",
   "form.csrfName": "csrf令牌的名称",
   "form.csrfKey": "生成csrf令牌的密钥",
   "form.csrfTtl": "csrf令牌的有效期",
+  "form.coalesceVaryHeaders": "合并请求的区分请求头",
+  "form.coalesceVaryHeadersAdd": "添加请求头",
+  "form.coalesceTimeout": "合并请求的最长等待时间",
+  "form.coalesceMaxBodySize": "共享响应的最大长度",
+  "form.botDetectionAction": "疑似爬虫请求的处理方式",
+  "form.botDetectionThreshold": "评分阈值(默认60)",
+  "form.botDetectionTagHeader": "标记的请求头(默认X-Bot-Score)",
+  "form.botDetectionMessage": "禁止访问的提示信息",
+  "form.botDetectionMax": "疑似爬虫的最大请求数",
+  "form.botDetectionInterval": "限制的时间间隔",
+  "form.botDetectionUserAgents": "爬虫的User-Agent",
+  "form.botDetectionUserAgentAdd": "添加爬虫的User-Agent",
+  "form.botDetectionBlockedFingerprints": "禁止的TLS指纹(ja3)",
+  "form.botDetectionAllowedFingerprints": "允许的TLS指纹(ja3)",
+  "form.botDetectionFingerprintAdd": "添加TLS指纹",
+  "form.honeypotPaths": "诱饵路径，支持*前缀匹配",
+  "form.honeypotPathAdd": "添加诱饵路径",
+  "form.honeypotBanTtl": "客户端IP的封禁时长(0s表示不封禁)",
+  "form.honeypotMessage": "诱饵响应的内容",
+  "form.cookieFilterDropCookies": "删除的Cookie，支持*前缀匹配",
+  "form.cookieFilterAllowCookies": "允许的Cookie，仅转发这些Cookie至upstream",
+  "form.cookieFilterCookieAdd": "添加Cookie名称",
+  "form.cookieFilterRenameCookies": "重命名的Cookie(name:upstream_name)",
+  "form.cookieFilterRenameCookieAdd": "添加重命名的Cookie",
+  "form.cookieFilterScopePrefix": "Cookie的作用域前缀",
+  "form.abTestExperiment": "实验名称",
+  "form.abTestSalt": "分桶的盐值(默认为实验名称)",
+  "form.abTestKey": "客户端标识(ip、cookie:name或header:name)",
+  "form.abTestHeader": "转发至upstream的分桶请求头(默认为X-Ab-Bucket)",
+  "form.abTestBuckets": "分桶列表(name:weight或name:weight:upstream)",
+  "form.abTestBucketAdd": "添加分桶",
+  "form.securityHeadersPreset": "安全响应头预设",
+  "form.securityHeadersOverride": "是否覆盖上游响应头",
+  "form.securityHeadersDisabled": "禁用的响应头(如hsts)",
+  "form.securityHeadersDisabledAdd": "添加禁用的响应头",
+  "form.requestSignAlgorithm": "签名算法",
+  "form.requestSignHost": "转发请求的Host",
+  "form.requestSignSecret": "hmac签名的密钥",
+  "form.requestSignKeyId": "hmac签名的密钥id",
+  "form.requestSignHeader": "签名的请求头(默认为X-Signature)",
+  "form.requestSignTimestampHeader": "时间戳的请求头(默认为X-Timestamp)",
+  "form.requestSignSignedHeaders": "参与签名的请求头",
+  "form.requestSignSignedHeadersAdd": "添加参与签名的请求头",
   "form.cacheLock": "相同请求时的等待时长",
   "form.cacheMaxFileSize": "缓存时的最大文件长度",
   "form.cacheNamespace": "缓存生成key的命名空间",
@@ -231,6 +386,11 @@ export default {
   "form.cacheHeadersAdd": "添加更多的请求头",
   "form.cacheEviction": "是否启用缓存清除方式",
   "form.cachePredictor": "是否启用缓存预测方式",
+  "form.cacheBypassHeader": "跳过缓存的密钥请求头(name:value)",
+  "form.cacheBypassQuery": "跳过缓存的query参数",
+  "form.cacheRange": "Range请求的缓存方式",
+  "form.cacheBypassIps": "允许控制缓存的客户端IP",
+  "form.cacheBypassIpsAdd": "添加更多的IP",
   "form.cacheMaxTtl": "缓存的最长有效期",
   // cors
   "form.corsPath": "设置支持cors的正则路径(可选)",
@@ -142,6 +142,34 @@ export default function BasicInfo() {
       span: 6,
       category: FormItemCategory.TEXT,
     },
+    {
+      id: "cache_snapshot",
+      label: t("basic.cacheSnapshot"),
+      defaultValue: basic.cache_snapshot,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "cache_prefetch_hits",
+      label: t("basic.cachePrefetchHits"),
+      defaultValue: basic.cache_prefetch_hits,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "cache_prefetch_before",
+      label: t("basic.cachePrefetchBefore"),
+      defaultValue: basic.cache_prefetch_before,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "cache_prefetch_concurrency",
+      label: t("basic.cachePrefetchConcurrency"),
+      defaultValue: basic.cache_prefetch_concurrency,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
     {
       id: "upgrade_sock",
       label: t("basic.upgradeSock"),
@@ -163,6 +191,13 @@ export default function BasicInfo() {
       span: 6,
       category: FormItemCategory.TEXT,
     },
+    {
+      id: "sandbox",
+      label: t("basic.sandbox"),
+      defaultValue: basic.sandbox,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
     {
       id: "webhook_type",
       label: t("basic.webhookType"),
@@ -185,6 +220,10 @@ export default function BasicInfo() {
         "restart_fail",
         "tls_validity",
         "service_discover_fail",
+        "service_discover_change",
+        "overload",
+        "honeypot",
+        "anomaly",
       ],
     },
     {
@@ -201,6 +240,20 @@ export default function BasicInfo() {
       span: 12,
       category: FormItemCategory.TEXT,
     },
+    {
+      id: "sentry_sample_rate",
+      label: t("basic.sentrySampleRate"),
+      defaultValue: basic.sentry_sample_rate,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "sentry_burst_threshold",
+      label: t("basic.sentryBurstThreshold"),
+      defaultValue: basic.sentry_burst_threshold,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
     {
       id: "pyroscope",
       label: t("basic.pyroscope"),
@@ -208,6 +261,128 @@ export default function BasicInfo() {
       span: 12,
       category: FormItemCategory.TEXT,
     },
+    {
+      id: "cluster",
+      label: t("basic.cluster"),
+      defaultValue: basic.cluster,
+      span: 12,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "metrics_push",
+      label: t("basic.metricsPush"),
+      defaultValue: basic.metrics_push,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "metrics_push_interval",
+      label: t("basic.metricsPushInterval"),
+      defaultValue: basic.metrics_push_interval,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "metrics_max_series",
+      label: t("basic.metricsMaxSeries"),
+      defaultValue: basic.metrics_max_series,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "metering",
+      label: t("basic.metering"),
+      defaultValue: basic.metering,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "metering_interval",
+      label: t("basic.meteringInterval"),
+      defaultValue: basic.metering_interval,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "sink",
+      label: t("basic.sink"),
+      defaultValue: basic.sink,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "sink_batch_size",
+      label: t("basic.sinkBatchSize"),
+      defaultValue: basic.sink_batch_size,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "clickhouse",
+      label: t("basic.clickhouse"),
+      defaultValue: basic.clickhouse,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "clickhouse_flush_interval",
+      label: t("basic.clickhouseFlushInterval"),
+      defaultValue: basic.clickhouse_flush_interval,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "clickhouse_spool",
+      label: t("basic.clickhouseSpool"),
+      defaultValue: basic.clickhouse_spool,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "clickhouse_batch_size",
+      label: t("basic.clickhouseBatchSize"),
+      defaultValue: basic.clickhouse_batch_size,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "anomaly_detection",
+      label: t("basic.anomalyDetection"),
+      defaultValue: basic.anomaly_detection,
+      span: 6,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 0,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "anomaly_sensitivity",
+      label: t("basic.anomalySensitivity"),
+      defaultValue: basic.anomaly_sensitivity,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "error_template_dir",
+      label: t("basic.errorTemplateDir"),
+      defaultValue: basic.error_template_dir,
+      span: 12,
+      category: FormItemCategory.TEXT,
+    },
     {
       id: "error_template",
       label: t("basic.errorTemplate"),
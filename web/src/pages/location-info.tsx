@@ -55,6 +55,11 @@ export default function LocationInfo() {
       option: 3,
       value: "pingap:ping",
     },
+    {
+      label: "pingap:securityHeaders",
+      option: 4,
+      value: "pingap:securityHeaders",
+    },
   ];
   if (config.plugins) {
     Object.keys(config.plugins).forEach((name) => {
@@ -118,6 +123,13 @@ export default function LocationInfo() {
       span: 6,
       category: FormItemCategory.NUMBER,
     },
+    {
+      id: "priority",
+      label: t("location.priority"),
+      defaultValue: location.priority,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
     {
       id: "client_max_body_size",
       label: t("location.clientMaxBodySize"),
@@ -0,0 +1,103 @@
+import * as React from "react";
+import { useTranslation } from "react-i18next";
+import Box from "@mui/material/Box";
+import Button from "@mui/material/Button";
+import Card from "@mui/material/Card";
+import CardContent from "@mui/material/CardContent";
+import TextField from "@mui/material/TextField";
+import Typography from "@mui/material/Typography";
+import Snackbar from "@mui/material/Snackbar";
+import { formatError } from "../helpers/util";
+import useSessionStore from "../states/session";
+
+export default function Login() {
+  const { t } = useTranslation();
+  const login = useSessionStore((state) => state.login);
+  const [account, setAccount] = React.useState("");
+  const [password, setPassword] = React.useState("");
+  const [processing, setProcessing] = React.useState(false);
+  const [showError, setShowError] = React.useState({
+    open: false,
+    message: "",
+  });
+
+  const handleSubmit = async () => {
+    if (processing) {
+      return;
+    }
+    setProcessing(true);
+    try {
+      await login(account, password);
+      // 重新加载页面以获取配置
+      window.location.hash = "#/";
+      window.location.reload();
+    } catch (err) {
+      setShowError({
+        open: true,
+        message: formatError(err),
+      });
+    } finally {
+      setProcessing(false);
+    }
+  };
+
+  return (
+    <Box display="flex" justifyContent="center" pt={8}>
+      <Card sx={{ width: 360 }}>
+        <CardContent>
+          <Typography gutterBottom variant="h5" component="div">
+            {t("login.title")}
+          </Typography>
+          <TextField
+            id="login-account"
+            label={t("login.account")}
+            margin="normal"
+            fullWidth
+            value={account}
+            onChange={(e) => {
+              setAccount(e.target.value.trim());
+            }}
+          />
+          <TextField
+            id="login-password"
+            label={t("login.password")}
+            type="password"
+            margin="normal"
+            fullWidth
+            value={password}
+            onChange={(e) => {
+              setPassword(e.target.value);
+            }}
+            onKeyDown={(e) => {
+              if (e.key === "Enter") {
+                handleSubmit();
+              }
+            }}
+          />
+          <Button
+            style={{
+              marginTop: "15px",
+            }}
+            fullWidth
+            variant="contained"
+            disabled={processing}
+            onClick={handleSubmit}
+          >
+            {processing ? t("login.processing") : t("login.submit")}
+          </Button>
+        </CardContent>
+      </Card>
+      <Snackbar
+        open={showError.open}
+        autoHideDuration={6000}
+        onClose={() => {
+          setShowError({
+            open: false,
+            message: "",
+          });
+        }}
+        message={showError.message}
+      />
+    </Box>
+  );
+}
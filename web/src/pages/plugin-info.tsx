@@ -40,9 +40,11 @@ export default function ProxyPluginInfo() {
     PluginCategory.MOCK,
     PluginCategory.REDIRECT,
     PluginCategory.CACHE,
+    PluginCategory.COALESCE,
 
     PluginCategory.REQUEST_ID,
     PluginCategory.COMPRESSION,
+    PluginCategory.REQUEST_SIGN,
 
     // auth
     PluginCategory.KEY_AUTH,
@@ -55,6 +57,10 @@ export default function ProxyPluginInfo() {
     PluginCategory.REFERER_RESTRICTION,
     PluginCategory.CSRF,
     PluginCategory.CORS,
+    PluginCategory.BOT_DETECTION,
+    PluginCategory.HONEYPOT,
+    PluginCategory.COOKIE_FILTER,
+    PluginCategory.AB_TEST,
 
     // WAF
     PluginCategory.OWASP_CRS_PLUGIN,
@@ -62,6 +68,7 @@ export default function ProxyPluginInfo() {
 
     // response
     PluginCategory.RESPONSE_HEADERS,
+    PluginCategory.SECURITY_HEADERS,
   ];
   const pluginOptions = plugins.map((item, index) => {
     return {
@@ -88,6 +95,20 @@ export default function ProxyPluginInfo() {
       category: FormItemCategory.PLUGIN_STEP,
       span: 6,
     },
+    {
+      id: "priority",
+      label: t("plugin.priority"),
+      defaultValue: proxyPlugin.priority as number,
+      category: FormItemCategory.NUMBER,
+      span: 6,
+    },
+    {
+      id: "condition",
+      label: t("plugin.condition"),
+      defaultValue: proxyPlugin.condition as string,
+      category: FormItemCategory.TEXT,
+      span: 12,
+    },
     {
       id: "value",
       label: t("plugin.config"),
@@ -0,0 +1,108 @@
+import useConfigStore from "../states/config";
+import { useParams } from "react-router-dom";
+import { useTranslation } from "react-i18next";
+
+import Loading from "../components/loading";
+import FormEditor from "../components/form-editor";
+import { goToScheduleInfo } from "../router";
+import { FormItem, FormItemCategory } from "../components/form-common";
+
+export default function ScheduleInfo() {
+  const { t } = useTranslation();
+
+  const [initialized, config, update, remove] = useConfigStore((state) => [
+    state.initialized,
+    state.data,
+    state.update,
+    state.remove,
+  ]);
+  const { name } = useParams();
+  if (!initialized) {
+    return <Loading />;
+  }
+  let created = false;
+  let scheduleName = name;
+  if (name == "*") {
+    created = true;
+    scheduleName = "";
+  }
+  const schedules = config.schedules || {};
+  const currentNames = Object.keys(schedules);
+  const schedule = schedules[scheduleName || ""] || {};
+
+  const categories = ["basic", "server", "location", "upstream", "plugin"];
+  const arr: FormItem[] = [
+    {
+      id: "cron",
+      label: t("schedule.cron"),
+      defaultValue: schedule.cron,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "name",
+      label: t("schedule.name"),
+      defaultValue: schedule.name,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "category",
+      label: t("schedule.category"),
+      defaultValue: schedule.category,
+      span: 12,
+      category: FormItemCategory.CHECKBOX,
+      options: categories.map((category, index) => {
+        return {
+          label: category,
+          option: index,
+          value: category,
+        };
+      }),
+    },
+    {
+      id: "values",
+      label: t("schedule.values"),
+      defaultValue: schedule.values,
+      span: 12,
+      category: FormItemCategory.SCHEDULE_VALUES,
+    },
+    {
+      id: "remark",
+      label: t("schedule.remark"),
+      defaultValue: schedule.remark,
+      span: 12,
+      category: FormItemCategory.TEXTAREA,
+    },
+  ];
+
+  const onUpsert = async (newName: string, data: Record<string, unknown>) => {
+    let scheduleName = name || "";
+    if (created) {
+      scheduleName = newName;
+    }
+    return update("schedule", scheduleName, data).then(() => {
+      if (created) {
+        goToScheduleInfo(scheduleName);
+      }
+    });
+  };
+  const onRemove = async () => {
+    return remove("schedule", name || "").then(() => {
+      goToScheduleInfo("*");
+    });
+  };
+  return (
+    <FormEditor
+      key={name}
+      title={t("schedule.title")}
+      description={t("schedule.description")}
+      items={arr}
+      onUpsert={onUpsert}
+      onRemove={onRemove}
+      created={created}
+      currentNames={currentNames}
+      hiddenIndex={0}
+    />
+  );
+}
@@ -167,6 +167,123 @@ export default function ServerInfo() {
         },
       ],
     },
+    {
+      id: "https_redirect",
+      label: t("server.httpsRedirect"),
+      defaultValue: server.https_redirect,
+      span: 6,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 2,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "normalize_path",
+      label: t("server.normalizePath"),
+      defaultValue: server.normalize_path,
+      span: 6,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 2,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "normalize_host",
+      label: t("server.normalizeHost"),
+      defaultValue: server.normalize_host,
+      span: 6,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 2,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "strict_request",
+      label: t("server.strictRequest"),
+      defaultValue: server.strict_request,
+      span: 6,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 2,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "max_header_count",
+      label: t("server.maxHeaderCount"),
+      defaultValue: server.max_header_count,
+      span: 3,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "max_header_size",
+      label: t("server.maxHeaderSize"),
+      defaultValue: server.max_header_size,
+      span: 3,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "not_found",
+      label: t("server.notFound"),
+      defaultValue: server.not_found,
+      span: 12,
+      category: FormItemCategory.TEXT,
+    },
     {
       id: "tls_cipher_list",
       label: t("server.tlsCipherList"),
@@ -225,6 +342,51 @@ export default function ServerInfo() {
       span: 6,
       category: FormItemCategory.NUMBER,
     },
+    {
+      id: "overload_max_processing",
+      label: t("server.overloadMaxProcessing"),
+      defaultValue: server.overload_max_processing,
+      span: 6,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "overload_max_event_loop_delay",
+      label: t("server.overloadMaxEventLoopDelay"),
+      defaultValue: server.overload_max_event_loop_delay,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "overload_low_priority_only",
+      label: t("server.overloadLowPriorityOnly"),
+      defaultValue: server.overload_low_priority_only,
+      span: 6,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 2,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "registry",
+      label: t("server.registry"),
+      defaultValue: server.registry,
+      span: 12,
+      category: FormItemCategory.TEXT,
+    },
     {
       id: "remark",
       label: t("server.remark"),
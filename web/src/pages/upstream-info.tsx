@@ -67,6 +67,125 @@ export default function UpstreamInfo() {
       span: 6,
       category: FormItemCategory.TEXT,
     },
+    {
+      id: "dns_ttl_refresh",
+      label: t("upstream.dnsTtlRefresh"),
+      defaultValue: upstream.dns_ttl_refresh,
+      span: 4,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 0,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "dns_min_ttl",
+      label: t("upstream.dnsMinTtl"),
+      defaultValue: upstream.dns_min_ttl,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "dns_max_ttl",
+      label: t("upstream.dnsMaxTtl"),
+      defaultValue: upstream.dns_max_ttl,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "dns_failure_policy",
+      label: t("upstream.dnsFailurePolicy"),
+      defaultValue: upstream.dns_failure_policy,
+      span: 6,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "keep",
+          option: 1,
+          value: "keep",
+        },
+        {
+          label: "fail",
+          option: 2,
+          value: "fail",
+        },
+        {
+          label: "static",
+          option: 3,
+          value: "static",
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "dns_max_stale",
+      label: t("upstream.dnsMaxStale"),
+      defaultValue: upstream.dns_max_stale,
+      span: 6,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "dns_fallback_addrs",
+      label: t("upstream.dnsFallbackAddrs"),
+      defaultValue: upstream.dns_fallback_addrs,
+      span: 12,
+      category: FormItemCategory.ADDRS,
+    },
+    {
+      id: "lazy_resolve",
+      label: t("upstream.lazyResolve"),
+      defaultValue: upstream.lazy_resolve,
+      span: 4,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 0,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "resolve_valid",
+      label: t("upstream.resolveValid"),
+      defaultValue: upstream.resolve_valid,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "resolve_negative_valid",
+      label: t("upstream.resolveNegativeValid"),
+      defaultValue: upstream.resolve_negative_valid,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
     {
       id: "algo",
       label: t("upstream.algo"),
@@ -145,6 +264,13 @@ export default function UpstreamInfo() {
         },
       ],
     },
+    {
+      id: "max_h2_streams",
+      label: t("upstream.maxH2Streams"),
+      defaultValue: upstream.max_h2_streams,
+      span: 4,
+      category: FormItemCategory.NUMBER,
+    },
     {
       id: "sni",
       label: t("upstream.sni"),
@@ -200,6 +326,54 @@ export default function UpstreamInfo() {
         },
       ],
     },
+    {
+      id: "ipv6_only",
+      label: t("upstream.ipv6Only"),
+      defaultValue: upstream.ipv6_only,
+      span: 4,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 0,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "prefer_ipv6",
+      label: t("upstream.preferIpv6"),
+      defaultValue: upstream.prefer_ipv6,
+      span: 4,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 0,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
     {
       id: "enable_tracer",
       label: t("upstream.enableTracer"),
@@ -276,6 +450,208 @@ export default function UpstreamInfo() {
       span: 6,
       category: FormItemCategory.NUMBER,
     },
+    {
+      id: "s3_bucket",
+      label: t("upstream.s3Bucket"),
+      defaultValue: upstream.s3_bucket,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "s3_region",
+      label: t("upstream.s3Region"),
+      defaultValue: upstream.s3_region,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "s3_prefix",
+      label: t("upstream.s3Prefix"),
+      defaultValue: upstream.s3_prefix,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "s3_path_style",
+      label: t("upstream.s3PathStyle"),
+      defaultValue: upstream.s3_path_style,
+      span: 4,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 0,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "s3_access_key_id",
+      label: t("upstream.s3AccessKeyId"),
+      defaultValue: upstream.s3_access_key_id,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "s3_secret_access_key",
+      label: t("upstream.s3SecretAccessKey"),
+      defaultValue: upstream.s3_secret_access_key,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "s3_session_token",
+      label: t("upstream.s3SessionToken"),
+      defaultValue: upstream.s3_session_token,
+      span: 12,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "fastcgi",
+      label: t("upstream.fastcgi"),
+      defaultValue: upstream.fastcgi,
+      span: 4,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 0,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "fastcgi_root",
+      label: t("upstream.fastcgiRoot"),
+      defaultValue: upstream.fastcgi_root,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "fastcgi_index",
+      label: t("upstream.fastcgiIndex"),
+      defaultValue: upstream.fastcgi_index,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "fastcgi_keepalive",
+      label: t("upstream.fastcgiKeepalive"),
+      defaultValue: upstream.fastcgi_keepalive,
+      span: 4,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "Yes",
+          option: 1,
+          value: true,
+        },
+        {
+          label: "No",
+          option: 0,
+          value: false,
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "fastcgi_params",
+      label: t("upstream.fastcgiParams"),
+      defaultValue: upstream.fastcgi_params,
+      span: 8,
+      category: FormItemCategory.CGI_PARAMS,
+    },
+    {
+      id: "wsgi_protocol",
+      label: t("upstream.wsgiProtocol"),
+      defaultValue: upstream.wsgi_protocol,
+      span: 4,
+      category: FormItemCategory.CHECKBOX,
+      options: [
+        {
+          label: "uwsgi",
+          option: 1,
+          value: "uwsgi",
+        },
+        {
+          label: "scgi",
+          option: 2,
+          value: "scgi",
+        },
+        {
+          label: "None",
+          option: -1,
+          value: null,
+        },
+      ],
+    },
+    {
+      id: "wsgi_script_name",
+      label: t("upstream.wsgiScriptName"),
+      defaultValue: upstream.wsgi_script_name,
+      span: 4,
+      category: FormItemCategory.TEXT,
+    },
+    {
+      id: "wsgi_params",
+      label: t("upstream.wsgiParams"),
+      defaultValue: upstream.wsgi_params,
+      span: 8,
+      category: FormItemCategory.CGI_PARAMS,
+    },
+    {
+      id: "green_upstream",
+      label: t("upstream.greenUpstream"),
+      defaultValue: upstream.green_upstream,
+      span: 4,
+      category: FormItemCategory.UPSTREAM,
+      options: currentNames.filter((item) => item !== upstreamName),
+    },
+    {
+      id: "blue_green_min_healthy",
+      label: t("upstream.blueGreenMinHealthy"),
+      defaultValue: upstream.blue_green_min_healthy,
+      span: 4,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "max_concurrency",
+      label: t("upstream.maxConcurrency"),
+      defaultValue: upstream.max_concurrency,
+      span: 4,
+      category: FormItemCategory.NUMBER,
+    },
+    {
+      id: "reserved_concurrency",
+      label: t("upstream.reservedConcurrency"),
+      defaultValue: upstream.reserved_concurrency,
+      span: 4,
+      category: FormItemCategory.NUMBER,
+    },
     {
       id: "remark",
       label: t("upstream.remark"),
@@ -7,6 +7,8 @@ import UpstreamInfo from "./pages/upstream-info";
 import PluginInfo from "./pages/plugin-info";
 import TomlPreview from "./pages/toml-preview";
 import CertificateInfo from "./pages/certificate-info";
+import ScheduleInfo from "./pages/schedule-info";
+import Login from "./pages/login";
 
 const PAHT_HOME = "/";
 const PATH_BASIC_INFO = "/basic-info";
@@ -15,7 +17,9 @@ const PATH_LOCATION_INFO = "/location-info/:name";
 const PATH_UPSTREAM_INFO = "/upstream-info/:name";
 const PATH_PLUGIN_INFO = "/plugin-info/:name";
 const PATH_CERTIFICATE_INFO = "/certificate-info/:name";
+const PATH_SCHEDULE_INFO = "/schedule-info/:name";
 const PATH_TOML_PREVIEW = "/toml-preivew";
+const PATH_LOGIN = "/login";
 
 const router = createHashRouter([
   {
@@ -46,14 +50,22 @@ const router = createHashRouter([
     path: PATH_CERTIFICATE_INFO,
     element: <CertificateInfo />,
   },
+  {
+    path: PATH_SCHEDULE_INFO,
+    element: <ScheduleInfo />,
+  },
   {
     path: PATH_TOML_PREVIEW,
     element: <TomlPreview />,
   },
+  {
+    path: PATH_LOGIN,
+    element: <Login />,
+  },
 ]);
 
 export function goToLogin() {
-  router.navigate("");
+  router.navigate(PATH_LOGIN);
 }
 
 export function goToHome() {
@@ -84,6 +96,10 @@ export function goToCertificateInfo(name: string) {
   router.navigate(PATH_CERTIFICATE_INFO.replace(":name", name));
 }
 
+export function goToScheduleInfo(name: string) {
+  router.navigate(PATH_SCHEDULE_INFO.replace(":name", name));
+}
+
 export function goToTomlPrevew() {
   router.navigate(PATH_TOML_PREVIEW);
 }
@@ -5,11 +5,22 @@ interface Upstream {
   addrs: string[];
   discovery?: string;
   update_frequency?: string;
+  dns_ttl_refresh?: boolean;
+  dns_min_ttl?: string;
+  dns_max_ttl?: string;
+  dns_failure_policy?: string;
+  dns_max_stale?: string;
+  dns_fallback_addrs?: string[];
+  lazy_resolve?: boolean;
+  resolve_valid?: string;
+  resolve_negative_valid?: string;
   algo?: string;
   sni?: string;
   alpn?: string;
   health_check?: string;
   ipv4_only?: boolean;
+  ipv6_only?: boolean;
+  prefer_ipv6?: boolean;
   enable_tracer?: boolean;
   connection_timeout?: string;
   total_connection_timeout?: string;
@@ -22,6 +33,30 @@ interface Upstream {
   tcp_probe_count?: number;
   tcp_recv_buf?: number;
   tcp_fast_open?: boolean;
+  keepalive_pool_size?: number;
+  max_requests_per_connection?: number;
+  max_h2_streams?: number;
+  slow_start?: string;
+  s3_bucket?: string;
+  s3_region?: string;
+  s3_prefix?: string;
+  s3_path_style?: boolean;
+  s3_access_key_id?: string;
+  s3_secret_access_key?: string;
+  s3_session_token?: string;
+  fastcgi?: boolean;
+  fastcgi_root?: string;
+  fastcgi_index?: string;
+  fastcgi_params?: string[];
+  fastcgi_keepalive?: boolean;
+  wsgi_protocol?: string;
+  wsgi_script_name?: string;
+  wsgi_params?: string[];
+  green_upstream?: string;
+  blue_green_min_healthy?: number;
+  max_concurrency?: number;
+  reserved_concurrency?: number;
+  namespace?: string;
   remark?: string;
 }
 
@@ -34,7 +69,34 @@ interface Location {
   proxy_add_headers?: string[];
   rewrite?: string;
   client_max_body_size?: string;
+  debug_journal?: boolean;
+  debug_journal_ips?: string[];
+  internal_only?: boolean;
+  internal_ips?: string[];
+  auth_bypass_paths?: string[];
+  internal_redirect?: boolean;
+  sendfile_root?: string;
+  low_priority?: boolean;
+  priority?: number;
+  fallback_upstreams?: string[];
+  fallback_statuses?: number[];
+  proxy_request_buffering?: boolean;
+  proxy_request_buffer_size?: string;
+  proxy_retries?: number;
+  client_abort_grace?: string;
+  proxy_connect_timeout?: string;
+  proxy_read_timeout?: string;
+  proxy_write_timeout?: string;
+  proxy_hide_headers?: string[];
+  proxy_allow_headers?: string[];
+  cache_not_found?: string;
+  always_online?: boolean;
+  upstream_down_fallback?: string;
+  access_log?: string;
+  access_log_file?: string;
   plugins?: string[];
+  plugin_overrides?: Record<string, Record<string, unknown>>;
+  namespace?: string;
   remark?: string;
 }
 
@@ -69,6 +131,13 @@ interface Server {
   certificate_file?: string;
   enabled_h2?: boolean;
   global_certificates?: boolean;
+  https_redirect?: boolean;
+  normalize_path?: boolean;
+  normalize_host?: boolean;
+  strict_request?: boolean;
+  max_header_count?: number;
+  max_header_size?: string;
+  not_found?: string;
   tls_cipher_list?: string;
   tls_ciphersuites?: string;
   tls_min_version?: string;
@@ -77,6 +146,16 @@ interface Server {
   tcp_interval?: string;
   tcp_probe_count?: number;
   tcp_fastopen?: number;
+  overload_max_processing?: number;
+  overload_max_event_loop_delay?: string;
+  overload_low_priority_only?: boolean;
+  location_plugins?: string[];
+  location_client_max_body_size?: string;
+  location_proxy_connect_timeout?: string;
+  location_proxy_read_timeout?: string;
+  location_proxy_write_timeout?: string;
+  registry?: string;
+  namespace?: string;
   remark?: string;
 }
 
@@ -90,26 +169,72 @@ interface Certificate {
   remark?: string;
 }
 
+interface Schedule {
+  cron?: string;
+  category?: string;
+  name?: string;
+  values?: string[];
+  remark?: string;
+}
+
 interface Basic {
   error_template?: string;
+  error_template_dir?: string;
   name?: string;
   pid_file?: string;
   upgrade_sock?: string;
   user?: string;
   group?: string;
+  sandbox?: string;
+  sandbox_paths?: string[];
   threads?: number;
   work_stealing?: boolean;
   grace_period?: string;
   graceful_shutdown_timeout?: string;
   upstream_keepalive_pool_size?: number;
+  critical_upstreams?: string[];
   log_buffered_lines?: number;
   log_format_json?: boolean;
   log_level?: string;
   auto_restart_check_interval?: string;
   cache_max_size?: number;
   cache_directory?: string;
+  cache_snapshot?: string;
+  cache_prefetch_hits?: number;
+  cache_prefetch_before?: string;
+  cache_prefetch_concurrency?: number;
+  slow_request_threshold?: string;
+  slow_request_log?: string;
+  connection_log?: string;
+  traffic_record?: string;
+  traffic_record_sample_rate?: number;
+  traffic_record_body_limit?: string;
+  metrics_push?: string;
+  metrics_push_interval?: string;
+  metrics_push_labels?: string[];
+  metrics_path_templates?: string[];
+  metrics_max_series?: number;
+  metrics_latency_buckets?: number[];
+  metrics_exemplars?: boolean;
+  metering?: string;
+  metering_interval?: string;
+  metering_consumers?: string[];
+  sink?: string;
+  sink_batch_size?: number;
+  clickhouse?: string;
+  clickhouse_columns?: string[];
+  clickhouse_batch_size?: number;
+  clickhouse_flush_interval?: string;
+  clickhouse_spool?: string;
+  anomaly_detection?: boolean;
+  anomaly_sensitivity?: number;
   sentry?: string;
+  sentry_sample_rate?: number;
+  sentry_burst_threshold?: number;
+  sentry_scrub_headers?: string[];
   pyroscope?: string;
+  cluster?: string;
+  trusted_proxies?: string[];
   webhook?: string;
   webhook_type?: string;
   webhook_notifications?: string[];
@@ -122,6 +247,8 @@ interface Config {
   servers?: Record<string, Server>;
   plugins?: Record<string, Record<string, unknown>>;
   certificates?: Record<string, Certificate>;
+  schedules?: Record<string, Schedule>;
+  snippets?: Record<string, unknown>;
 }
 
 interface ConfigState {
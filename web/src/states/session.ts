@@ -0,0 +1,55 @@
+import request, { setCsrfToken } from "../helpers/request";
+import { create } from "zustand";
+
+interface Session {
+  enabled: boolean;
+  csrf_token: string;
+  expired_at: number;
+}
+
+interface SessionState {
+  data: Session;
+  fetch: () => Promise<Session>;
+  login: (account: string, password: string) => Promise<Session>;
+  logout: () => Promise<void>;
+}
+
+const useSessionStore = create<SessionState>()((set) => ({
+  data: {
+    enabled: false,
+    csrf_token: "",
+    expired_at: 0,
+  },
+  fetch: async () => {
+    const { data } = await request.get<Session>("/session");
+    setCsrfToken(data.csrf_token);
+    set({
+      data,
+    });
+    return data;
+  },
+  login: async (account: string, password: string) => {
+    const { data } = await request.post<Session>("/login", {
+      account,
+      password,
+    });
+    setCsrfToken(data.csrf_token);
+    set({
+      data,
+    });
+    return data;
+  },
+  logout: async () => {
+    await request.post("/logout");
+    setCsrfToken("");
+    set({
+      data: {
+        enabled: true,
+        csrf_token: "",
+        expired_at: 0,
+      },
+    });
+  },
+}));
+
+export default useSessionStore;